@@ -22,6 +22,14 @@ impl AsRef<box_::PublicKey> for PublicKey {
     }
 }
 
+impl PublicKey {
+    /// Raw bytes of the public key
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &(self.0).0
+    }
+}
+
 impl Deref for PublicKey {
     type Target = box_::PublicKey;
 
@@ -52,6 +60,14 @@ impl AsRef<box_::SecretKey> for SecretKey {
     }
 }
 
+impl SecretKey {
+    /// Raw bytes of the secret key
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &(self.0).0
+    }
+}
+
 impl Deref for SecretKey {
     type Target = box_::SecretKey;
 
@@ -111,6 +127,12 @@ pub enum CryptoError {
     FailedToDecrypt,
 }
 
+/// Generate new random key pair
+pub fn random_keypair() -> (PublicKey, SecretKey) {
+    let (pk, sk) = box_::gen_keypair();
+    (PublicKey(pk), SecretKey(sk))
+}
+
 /// Create `PrecomputedKey` from public key and secret key
 ///
 /// # Arguments
@@ -165,6 +187,7 @@ pub fn decrypt(enc: &[u8], nonce: &Nonce, pck: &PrecomputedKey) -> Result<Vec<u8
 #[cfg(test)]
 mod tests {
     use failure::Error;
+    use crate::hash::HashType;
     use super::*;
 
     #[test]
@@ -173,6 +196,21 @@ mod tests {
         assert_eq!(NONCE_SIZE, nonce.0.len())
     }
 
+    #[test]
+    fn generate_random_keypair() -> Result<(), Error> {
+        let (pk, sk) = random_keypair();
+        assert_eq!(CRYPTO_KEY_SIZE, pk.as_bytes().len());
+        assert_eq!(CRYPTO_KEY_SIZE, sk.as_bytes().len());
+        assert!(precompute(&hex::encode(pk.as_bytes()), &hex::encode(sk.as_bytes())).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn public_key_to_peer_id() -> Result<(), Error> {
+        let pk = PublicKey::from_hex("8072b92ac74f031808b56c916f291b08201a6957c127f51bae66c1a754ad4209")?;
+        Ok(assert_eq!("idsyBpzU3VspRyD3GEDWkgUBRqQNti", HashType::CryptoboxPublicKeyHash.bytes_to_string(pk.as_bytes())))
+    }
+
     #[test]
     fn generate_precomputed_key() -> Result<(), Error> {
         let pk = "96678b88756dd6cfd6c129980247b70a6e44da77823c3672a2ec0eae870d8646";
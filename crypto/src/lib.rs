@@ -8,4 +8,6 @@ pub mod base58;
 pub mod nonce;
 pub mod crypto_box;
 #[macro_use]
-pub mod hash;
\ No newline at end of file
+pub mod hash;
+pub mod proof_of_work;
+pub mod signature;
@@ -0,0 +1,231 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Proof of work of the peer identity.
+//!
+//! Identity is valid, if the blake2b digest of the public key concatenated with the proof of work stamp,
+//! read as a little endian number, is lower than or equal to the target derived from the expected pow.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use failure::Fail;
+use num_bigint::BigUint;
+use num_traits::One;
+use rand::RngCore;
+
+use super::blake2b;
+
+/// Size of the proof of work stamp in bytes
+pub const POW_STAMP_SIZE: usize = 24;
+
+/// Number of mantissa bits used to express the fractional part of the expected pow
+const MANTISSA_BITS: usize = 48;
+/// Size of the target in bits
+const TARGET_BITS: usize = 256;
+/// Number of stamps tried by the worker thread, before the attempts counter is updated
+const ATTEMPTS_BATCH: u64 = 1_000;
+
+/// Possible errors for proof of work check
+#[derive(Debug, Fail, PartialEq)]
+pub enum PowError {
+    #[fail(display = "Expected proof of work must be between 0 and 256, but was {}", expected_pow)]
+    InvalidTarget {
+        expected_pow: f64
+    },
+    #[fail(display = "Proof of work stamp has invalid size: {}", size)]
+    InvalidStampSize {
+        size: usize
+    },
+    #[fail(display = "Proof of work is lower than expected pow {}", expected_pow)]
+    NotEnoughProofOfWork {
+        expected_pow: f64
+    },
+    #[fail(display = "Proof of work generation failed, all worker threads terminated")]
+    GenerationFailed,
+}
+
+/// Create target of the expected pow, this is the same as `Crypto_box.make_target` in Tezos
+pub fn make_target(expected_pow: f64) -> Result<BigUint, PowError> {
+    if !(0.0..=256.0).contains(&expected_pow) {
+        return Err(PowError::InvalidTarget { expected_pow });
+    }
+
+    let shift = expected_pow.trunc() as usize;
+    let frac = expected_pow.fract();
+    let mantissa = BigUint::from(if frac.abs() < f64::EPSILON {
+        (1u64 << MANTISSA_BITS) - 1
+    } else {
+        2f64.powf(MANTISSA_BITS as f64 - frac) as u64
+    });
+
+    if shift < TARGET_BITS - MANTISSA_BITS {
+        // mantissa is followed by ones
+        let ones_bits = TARGET_BITS - MANTISSA_BITS - shift;
+        Ok((mantissa << ones_bits) | ((BigUint::one() << ones_bits) - BigUint::one()))
+    } else {
+        Ok(mantissa >> (shift - (TARGET_BITS - MANTISSA_BITS)))
+    }
+}
+
+/// Check that the proof of work stamp of the public key satisfies the expected pow
+pub fn check_proof_of_work(public_key: &[u8], proof_of_work_stamp: &[u8], expected_pow: f64) -> Result<(), PowError> {
+    if proof_of_work_stamp.len() != POW_STAMP_SIZE {
+        return Err(PowError::InvalidStampSize { size: proof_of_work_stamp.len() });
+    }
+
+    let target = make_target(expected_pow)?;
+    if is_below_target(&[public_key, proof_of_work_stamp].concat(), &target) {
+        Ok(())
+    } else {
+        Err(PowError::NotEnoughProofOfWork { expected_pow })
+    }
+}
+
+/// Search for the proof of work stamp of the public key, which satisfies the expected pow.
+///
+/// Search runs in `threads` worker threads, each one starting from a random stamp.
+/// Total number of tried stamps is reported to the `progress` callback every `progress_interval`.
+pub fn generate_proof_of_work<F>(public_key: &[u8], expected_pow: f64, threads: usize, progress_interval: Duration, mut progress: F) -> Result<Vec<u8>, PowError>
+    where
+        F: FnMut(u64)
+{
+    let target = Arc::new(make_target(expected_pow)?);
+    let found = Arc::new(AtomicBool::new(false));
+    let attempts = Arc::new(AtomicU64::new(0));
+    let (result_tx, result_rx) = mpsc::channel();
+
+    let workers = (0..threads.max(1))
+        .map(|_| {
+            let mut data = public_key.to_vec();
+            let target = target.clone();
+            let found = found.clone();
+            let attempts = attempts.clone();
+            let result_tx = result_tx.clone();
+            thread::spawn(move || {
+                let stamp_offset = data.len();
+                data.resize(stamp_offset + POW_STAMP_SIZE, 0);
+                rand::thread_rng().fill_bytes(&mut data[stamp_offset..]);
+
+                while !found.load(Ordering::Acquire) {
+                    for _ in 0..ATTEMPTS_BATCH {
+                        if is_below_target(&data, &target) {
+                            found.store(true, Ordering::Release);
+                            let _ = result_tx.send(data[stamp_offset..].to_vec());
+                            return;
+                        }
+                        increment_stamp(&mut data[stamp_offset..]);
+                    }
+                    attempts.fetch_add(ATTEMPTS_BATCH, Ordering::Relaxed);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    // only workers hold the sender now, so the receiver is disconnected when all of them terminate
+    drop(result_tx);
+
+    let result = loop {
+        match result_rx.recv_timeout(progress_interval) {
+            Ok(stamp) => break Ok(stamp),
+            Err(RecvTimeoutError::Timeout) => progress(attempts.load(Ordering::Relaxed)),
+            Err(RecvTimeoutError::Disconnected) => break Err(PowError::GenerationFailed),
+        }
+    };
+
+    found.store(true, Ordering::Release);
+    workers.into_iter().for_each(|worker| { let _ = worker.join(); });
+    result
+}
+
+#[inline]
+fn is_below_target(data: &[u8], target: &BigUint) -> bool {
+    BigUint::from_bytes_le(&blake2b::digest_256(data)) <= *target
+}
+
+/// Increment stamp as a big endian number, overflow wraps around to zero
+fn increment_stamp(stamp: &mut [u8]) {
+    for byte in stamp.iter_mut().rev() {
+        let (value, overflow) = byte.overflowing_add(1);
+        *byte = value;
+        if !overflow {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(public_key: &str, proof_of_work_stamp: &str, expected_pow: f64) -> Result<(), PowError> {
+        check_proof_of_work(&hex::decode(public_key).unwrap(), &hex::decode(proof_of_work_stamp).unwrap(), expected_pow)
+    }
+
+    #[test]
+    fn test_make_target() -> Result<(), PowError> {
+        assert_eq!((BigUint::one() << 256) - BigUint::one(), make_target(0.0)?);
+        assert_eq!((BigUint::one() << 230) - BigUint::one(), make_target(26.0)?);
+        assert_eq!(BigUint::parse_bytes(b"b504f333f9deffffffffffffffffffffffffffffffffffffffffffffff", 16).unwrap(), make_target(24.5)?);
+        assert_eq!(BigUint::from(0u32), make_target(256.0)?);
+        assert_eq!(Err(PowError::InvalidTarget { expected_pow: -1.0 }), make_target(-1.0));
+        assert_eq!(Err(PowError::InvalidTarget { expected_pow: 256.5 }), make_target(256.5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_proof_of_work_tezedge_identity() {
+        let public_key = "8072b92ac74f031808b56c916f291b08201a6957c127f51bae66c1a754ad4209";
+        let stamp = "788a80b4326f0e04eef11ae8ce67c69e2db372ff57d3b23b";
+        assert!(check(public_key, stamp, 26.0).is_ok());
+        assert!(check(public_key, stamp, 29.0).is_ok());
+        assert!(check(public_key, stamp, 30.0).is_err());
+    }
+
+    #[test]
+    fn test_check_proof_of_work_ocaml_identity() {
+        let public_key = "5fd7ba1d15650abc9a510c37a8bee566b708fe6577f0d832b903e8f13d71c94a";
+        let stamp = "4b1354dcfc087e52c8fb510317b9464c297b8a55b79bfc95";
+        assert!(check(public_key, stamp, 0.0).is_ok());
+        assert!(check(public_key, stamp, 26.0).is_ok());
+        assert_eq!(Err(PowError::NotEnoughProofOfWork { expected_pow: 26.5 }), check(public_key, stamp, 26.5));
+        assert!(check(public_key, stamp, 256.0).is_err());
+    }
+
+    #[test]
+    fn test_check_proof_of_work_stamp_of_another_identity() {
+        let public_key = "5fd7ba1d15650abc9a510c37a8bee566b708fe6577f0d832b903e8f13d71c94a";
+        assert!(check(public_key, "788a80b4326f0e04eef11ae8ce67c69e2db372ff57d3b23b", 26.0).is_err());
+    }
+
+    #[test]
+    fn test_generate_proof_of_work() -> Result<(), PowError> {
+        let public_key = hex::decode("8072b92ac74f031808b56c916f291b08201a6957c127f51bae66c1a754ad4209").unwrap();
+        let stamp = generate_proof_of_work(&public_key, 8.0, 2, Duration::from_millis(10), |_| ())?;
+        assert_eq!(POW_STAMP_SIZE, stamp.len());
+        check_proof_of_work(&public_key, &stamp, 8.0)
+    }
+
+    #[test]
+    fn test_increment_stamp() {
+        let mut stamp = [0x00, 0xfe, 0xff];
+        increment_stamp(&mut stamp);
+        assert_eq!([0x00, 0xff, 0x00], stamp);
+        increment_stamp(&mut stamp);
+        assert_eq!([0x00, 0xff, 0x01], stamp);
+
+        let mut stamp = [0xff, 0xff];
+        increment_stamp(&mut stamp);
+        assert_eq!([0x00, 0x00], stamp);
+    }
+
+    #[test]
+    fn test_check_proof_of_work_invalid_stamp() {
+        assert_eq!(
+            Err(PowError::InvalidStampSize { size: 2 }),
+            check("5fd7ba1d15650abc9a510c37a8bee566b708fe6577f0d832b903e8f13d71c94a", "4b13", 0.0)
+        );
+    }
+}
@@ -0,0 +1,346 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Verification of the signatures used by Tezos: ed25519 (tz1), secp256k1 (tz2) and p256 (tz3).
+//!
+//! Signed data is prefixed by the watermark and hashed by blake2b, the resulting digest is signed by the key.
+
+use failure::Fail;
+use serde::{Deserialize, Serialize};
+use sodiumoxide::crypto::sign::ed25519;
+
+use crate::base58::{FromBase58Check, FromBase58CheckError, ToBase58Check};
+use crate::blake2b;
+use crate::hash::{ChainId, Hash, HashType};
+
+mod p256;
+
+mod prefix_bytes {
+    pub const ED25519_PUBLIC_KEY: [u8; 4] = [13, 15, 37, 217];
+    pub const SECP256K1_PUBLIC_KEY: [u8; 4] = [3, 254, 226, 86];
+    pub const P256_PUBLIC_KEY: [u8; 4] = [3, 178, 139, 127];
+    pub const ED25519_SIGNATURE: [u8; 5] = [9, 245, 205, 134, 18];
+    pub const SECP256K1_SIGNATURE: [u8; 5] = [13, 115, 101, 19, 63];
+    pub const P256_SIGNATURE: [u8; 4] = [54, 240, 44, 52];
+    pub const GENERIC_SIGNATURE: [u8; 3] = [4, 130, 43];
+}
+
+const ED25519_PUBLIC_KEY_SIZE: usize = 32;
+const ECDSA_PUBLIC_KEY_SIZE: usize = 33;
+const SIGNATURE_SIZE: usize = 64;
+
+/// Possible errors for signatures
+#[derive(Debug, Fail)]
+pub enum SignatureError {
+    #[fail(display = "Invalid public key: {}", reason)]
+    InvalidPublicKey {
+        reason: String
+    },
+    #[fail(display = "Invalid signature: {}", reason)]
+    InvalidSignature {
+        reason: String
+    },
+}
+
+impl From<FromBase58CheckError> for SignatureError {
+    fn from(error: FromBase58CheckError) -> Self {
+        SignatureError::InvalidPublicKey { reason: error.to_string() }
+    }
+}
+
+/// Watermark is prepended to the signed data, so the signature of one kind of data cannot be used for another kind
+#[derive(Clone, Debug, PartialEq)]
+pub enum Watermark {
+    /// "\x01" + chain id
+    Block(ChainId),
+    /// "\x02" + chain id
+    Endorsement(ChainId),
+    /// "\x03"
+    GenericOperation,
+}
+
+impl Watermark {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Watermark::Block(chain_id) => prefixed(0x01, chain_id),
+            Watermark::Endorsement(chain_id) => prefixed(0x02, chain_id),
+            Watermark::GenericOperation => vec![0x03],
+        }
+    }
+}
+
+fn prefixed(tag: u8, data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(1 + data.len());
+    bytes.push(tag);
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+/// Public key of the signer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PublicKey {
+    /// 32 bytes of the ed25519 public key, b58 prefix `edpk`
+    Ed25519(Vec<u8>),
+    /// 33 bytes of the compressed secp256k1 public key, b58 prefix `sppk`
+    Secp256k1(Vec<u8>),
+    /// 33 bytes of the compressed p256 public key, b58 prefix `p2pk`
+    P256(Vec<u8>),
+}
+
+impl PublicKey {
+    /// Parse public key from its b58check representation (`edpk...`, `sppk...`, `p2pk...`)
+    pub fn from_b58(public_key: &str) -> Result<Self, SignatureError> {
+        use prefix_bytes::*;
+
+        let bytes = public_key.from_base58check()?;
+        if bytes.starts_with(&ED25519_PUBLIC_KEY) {
+            Self::ed25519(&bytes[ED25519_PUBLIC_KEY.len()..])
+        } else if bytes.starts_with(&SECP256K1_PUBLIC_KEY) {
+            Self::ecdsa(&bytes[SECP256K1_PUBLIC_KEY.len()..]).map(PublicKey::Secp256k1)
+        } else if bytes.starts_with(&P256_PUBLIC_KEY) {
+            Self::ecdsa(&bytes[P256_PUBLIC_KEY.len()..]).map(PublicKey::P256)
+        } else {
+            Err(SignatureError::InvalidPublicKey { reason: format!("unknown prefix of the public key: {}", public_key) })
+        }
+    }
+
+    /// Parse public key from the bytes prefixed by the curve tag (0 - ed25519, 1 - secp256k1, 2 - p256),
+    /// which is the binary representation used by the protocol, e.g. in the context
+    pub fn from_tagged_bytes(bytes: &[u8]) -> Result<Self, SignatureError> {
+        match bytes.split_first() {
+            Some((0, public_key)) => Self::ed25519(public_key),
+            Some((1, public_key)) => Self::ecdsa(public_key).map(PublicKey::Secp256k1),
+            Some((2, public_key)) => Self::ecdsa(public_key).map(PublicKey::P256),
+            Some((tag, _)) => Err(SignatureError::InvalidPublicKey { reason: format!("unknown curve tag: {}", tag) }),
+            None => Err(SignatureError::InvalidPublicKey { reason: "empty public key".to_string() }),
+        }
+    }
+
+    fn ed25519(public_key: &[u8]) -> Result<Self, SignatureError> {
+        if public_key.len() == ED25519_PUBLIC_KEY_SIZE {
+            Ok(PublicKey::Ed25519(public_key.to_vec()))
+        } else {
+            Err(SignatureError::InvalidPublicKey { reason: format!("invalid size of the ed25519 public key: {}", public_key.len()) })
+        }
+    }
+
+    fn ecdsa(public_key: &[u8]) -> Result<Vec<u8>, SignatureError> {
+        if public_key.len() == ECDSA_PUBLIC_KEY_SIZE {
+            Ok(public_key.to_vec())
+        } else {
+            Err(SignatureError::InvalidPublicKey { reason: format!("invalid size of the public key: {}", public_key.len()) })
+        }
+    }
+
+    /// Convert public key to its b58check representation
+    pub fn to_b58(&self) -> String {
+        use prefix_bytes::*;
+
+        let (prefix, public_key): (&[u8], _) = match self {
+            PublicKey::Ed25519(public_key) => (&ED25519_PUBLIC_KEY, public_key),
+            PublicKey::Secp256k1(public_key) => (&SECP256K1_PUBLIC_KEY, public_key),
+            PublicKey::P256(public_key) => (&P256_PUBLIC_KEY, public_key),
+        };
+        let mut bytes = Vec::with_capacity(prefix.len() + public_key.len());
+        bytes.extend_from_slice(prefix);
+        bytes.extend_from_slice(public_key);
+        bytes.to_base58check()
+    }
+
+    /// Type of the public key hash (tz1, tz2 or tz3)
+    pub fn public_key_hash_type(&self) -> HashType {
+        match self {
+            PublicKey::Ed25519(_) => HashType::ContractTz1Hash,
+            PublicKey::Secp256k1(_) => HashType::ContractTz2Hash,
+            PublicKey::P256(_) => HashType::ContractTz3Hash,
+        }
+    }
+
+    /// Hash of the public key, its type is determined by [public_key_hash_type](PublicKey::public_key_hash_type)
+    pub fn public_key_hash(&self) -> Hash {
+        match self {
+            PublicKey::Ed25519(public_key)
+            | PublicKey::Secp256k1(public_key)
+            | PublicKey::P256(public_key) => blake2b::digest_160(public_key),
+        }
+    }
+
+    /// Verify signature of the `data` prefixed by the `watermark`.
+    ///
+    /// Returns `Ok(false)` if the signature does not match, `Err` if the signature cannot be verified at all.
+    pub fn verify(&self, watermark: Option<&Watermark>, data: &[u8], signature: &[u8]) -> Result<bool, SignatureError> {
+        let digest = match watermark {
+            Some(watermark) => {
+                let mut watermarked = watermark.to_bytes();
+                watermarked.extend_from_slice(data);
+                blake2b::digest_256(&watermarked)
+            }
+            None => blake2b::digest_256(data),
+        };
+        self.verify_digest(&digest, signature)
+    }
+
+    /// Verify signature of the blake2b digest of the signed data
+    pub fn verify_digest(&self, digest: &[u8], signature: &[u8]) -> Result<bool, SignatureError> {
+        if signature.len() != SIGNATURE_SIZE {
+            return Err(SignatureError::InvalidSignature { reason: format!("invalid size of the signature: {}", signature.len()) });
+        }
+
+        match self {
+            PublicKey::Ed25519(public_key) => {
+                let public_key = ed25519::PublicKey::from_slice(public_key)
+                    .ok_or_else(|| SignatureError::InvalidPublicKey { reason: "invalid ed25519 public key".to_string() })?;
+                let signature = ed25519::Signature::from_bytes(signature)
+                    .map_err(|e| SignatureError::InvalidSignature { reason: e.to_string() })?;
+                Ok(ed25519::verify_detached(&signature, digest, &public_key))
+            }
+            PublicKey::Secp256k1(public_key) => {
+                let public_key = secp256k1::PublicKey::parse_slice(public_key, None)
+                    .map_err(|e| SignatureError::InvalidPublicKey { reason: format!("{:?}", e) })?;
+                let message = secp256k1::Message::parse_slice(digest)
+                    .map_err(|e| SignatureError::InvalidSignature { reason: format!("{:?}", e) })?;
+                let signature = secp256k1::Signature::parse_slice(signature)
+                    .map_err(|e| SignatureError::InvalidSignature { reason: format!("{:?}", e) })?;
+                Ok(secp256k1::verify(&message, &signature, &public_key))
+            }
+            PublicKey::P256(public_key) => p256::verify_digest(public_key, digest, signature),
+        }
+    }
+}
+
+/// Parse signature from its b58check representation (`edsig...`, `spsig1...`, `p2sig...` or generic `sig...`)
+pub fn signature_from_b58(signature: &str) -> Result<Vec<u8>, SignatureError> {
+    use prefix_bytes::*;
+
+    let bytes = signature.from_base58check()
+        .map_err(|e| SignatureError::InvalidSignature { reason: e.to_string() })?;
+    let prefix_size = [&ED25519_SIGNATURE[..], &SECP256K1_SIGNATURE[..], &P256_SIGNATURE[..], &GENERIC_SIGNATURE[..]].iter()
+        .find(|prefix| bytes.starts_with(prefix) && bytes.len() == prefix.len() + SIGNATURE_SIZE)
+        .map(|prefix| prefix.len())
+        .ok_or_else(|| SignatureError::InvalidSignature { reason: format!("unknown prefix of the signature: {}", signature) })?;
+    Ok(bytes[prefix_size..].to_vec())
+}
+
+/// Convert signature to its generic b58check representation (`sig...`), which is used by the RPC
+pub fn signature_to_b58(signature: &[u8]) -> String {
+    [&prefix_bytes::GENERIC_SIGNATURE[..], signature].concat().to_base58check()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Operation bytes, which are signed in the test vectors.
+    ///
+    /// Signatures were produced by the reference implementations (libsodium, libsecp256k1 and python ecdsa),
+    /// not taken from a Tezos node, only the key and hash pairs are the well known sandbox accounts.
+    const DATA: &str = "10490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e000008c387fa065a181d45d47a9b78ddc77e92a881779ff2cbabbf9646eade4bf1405a08e00b725ed849eea46953b10b5cdebc518e6fd47e69b82d2ca18c4cf6d2f312dd08";
+    /// Mainnet chain id
+    const CHAIN_ID: &str = "NetXdQprcVkpaWU";
+
+    fn assert_signatures(public_key: &str, operation_signature: &str, block_signature: &str) -> Result<(), failure::Error> {
+        let public_key = PublicKey::from_b58(public_key)?;
+        let data = hex::decode(DATA)?;
+        let block = Watermark::Block(HashType::ChainId.string_to_bytes(CHAIN_ID)?);
+        let operation_signature = signature_from_b58(operation_signature)?;
+        let block_signature = signature_from_b58(block_signature)?;
+
+        assert!(public_key.verify(Some(&Watermark::GenericOperation), &data, &operation_signature)?);
+        assert!(public_key.verify(Some(&block), &data, &block_signature)?);
+
+        // signature is bound to the watermark
+        assert!(!public_key.verify(Some(&block), &data, &operation_signature)?);
+        assert!(!public_key.verify(Some(&Watermark::Endorsement(HashType::ChainId.string_to_bytes(CHAIN_ID)?)), &data, &block_signature)?);
+        assert!(!public_key.verify(None, &data, &operation_signature)?);
+
+        // signature is bound to the data
+        let mut modified = data.clone();
+        modified[0] ^= 1;
+        assert!(!public_key.verify(Some(&Watermark::GenericOperation), &modified, &operation_signature)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_public_key_hash() -> Result<(), failure::Error> {
+        // sandbox bootstrap accounts
+        let public_key = PublicKey::from_b58("edpkuBknW28nW72KG6RoHtYW7p12T6GKc7nAbwYX5m8Wd9sDVC9yav")?;
+        assert_eq!("tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx", public_key.public_key_hash_type().bytes_to_string(&public_key.public_key_hash()));
+        let public_key = PublicKey::from_b58("edpktzNbDAUjUk697W7gYg2CRuBQjyPxbEg8dLccYYwKSKvkPvjtV9")?;
+        assert_eq!("tz1gjaF81ZRRvdzjobyfVNsAeSC6PScjfQwN", public_key.public_key_hash_type().bytes_to_string(&public_key.public_key_hash()));
+        let public_key = PublicKey::from_b58("edpkuTXkJDGcFd5nh6VvMz8phXxU3Bi7h6hqgywNFi1vZTfQNnS1RV")?;
+        assert_eq!("tz1faswCTDciRzE4oJ9jn2Vm2dvjeyA9fUzU", public_key.public_key_hash_type().bytes_to_string(&public_key.public_key_hash()));
+
+        let public_key = PublicKey::from_b58("sppk7a5cv7qynbR4nABzLjgRyjt1gT8cVwsbESH7xbPbMZnWHX2eN5k")?;
+        assert_eq!("tz2EUM2Fh6w3PiMJ1iEqvWKyksT9Hjd8NQcs", public_key.public_key_hash_type().bytes_to_string(&public_key.public_key_hash()));
+        let public_key = PublicKey::from_b58("p2pk67HTySHGGdwtgp5sMYATzRs9dqmoCi9ZvMD6ki5mGp6CHC2CMax")?;
+        assert_eq!("tz3V9Ept7k2qpwUz5DJm7WEi2PWfdcrZN1zb", public_key.public_key_hash_type().bytes_to_string(&public_key.public_key_hash()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_public_key_b58_roundtrip() -> Result<(), failure::Error> {
+        for public_key in &["edpkuBknW28nW72KG6RoHtYW7p12T6GKc7nAbwYX5m8Wd9sDVC9yav", "sppk7a5cv7qynbR4nABzLjgRyjt1gT8cVwsbESH7xbPbMZnWHX2eN5k", "p2pk67HTySHGGdwtgp5sMYATzRs9dqmoCi9ZvMD6ki5mGp6CHC2CMax"] {
+            assert_eq!(*public_key, PublicKey::from_b58(public_key)?.to_b58());
+        }
+        assert!(PublicKey::from_b58("tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_public_key_from_tagged_bytes() -> Result<(), failure::Error> {
+        let public_key = PublicKey::from_tagged_bytes(&hex::decode("0003410eceaef47f2430969cf31bd58b291ee7ad7f61c0b18e1f6bc5dbf66f9b79")?)?;
+        assert_eq!("tz1PirboZKFVqkfE45hVLpkpXaZtLk3mqC17", public_key.public_key_hash_type().bytes_to_string(&public_key.public_key_hash()));
+
+        assert!(PublicKey::from_tagged_bytes(&hex::decode("0003410eceaef47f2430969cf31bd58b291ee7ad7f61c0b18e1f6bc5db")?).is_err());
+        assert!(PublicKey::from_tagged_bytes(&hex::decode("0403410eceaef47f2430969cf31bd58b291ee7ad7f61c0b18e1f6bc5dbf66f9b79")?).is_err());
+        assert!(PublicKey::from_tagged_bytes(&[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_ed25519() -> Result<(), failure::Error> {
+        // bootstrap1 account of the sandbox
+        assert_signatures(
+            "edpkuBknW28nW72KG6RoHtYW7p12T6GKc7nAbwYX5m8Wd9sDVC9yav",
+            "edsigtb844KhSCU3zRrj67emZahYa5NjwBEYTwNvGxjbYopdt4pMMA45KWCYL185Lgk1oDrkY813M7vrakCX2q35KMHoNj2LtrY",
+            "edsigtyJWPtE8Vqo19qXRkXyp5dQrzeo9LNWvVTN7eT7ynGXwybMh6xmJxvdujZBr7whP92nDGNye6Jr1j5kyZG8H1qSDhUkWmS",
+        )
+    }
+
+    #[test]
+    fn test_verify_secp256k1() -> Result<(), failure::Error> {
+        assert_signatures(
+            "sppk7a5cv7qynbR4nABzLjgRyjt1gT8cVwsbESH7xbPbMZnWHX2eN5k",
+            "spsig1SqEi9UAHPMRGtZtwnxPANBpS2hBvvMAC6M6Y8WUyiCv54qC9kopQg9rmcn8gDCcaDypSt7CPvU3aSZnGgbJC9xKn83Aq6",
+            "spsig1Hdc4E27gHYRVTRw1Jv9BPjnLZYJHsNv8CZKVxag59D3azwxRzKHRNSkLsYXTxXtE8XgTVDxkUnzeFDnjzZ9XnSTeWRd5g",
+        )
+    }
+
+    #[test]
+    fn test_verify_p256() -> Result<(), failure::Error> {
+        assert_signatures(
+            "p2pk67HTySHGGdwtgp5sMYATzRs9dqmoCi9ZvMD6ki5mGp6CHC2CMax",
+            "p2sigYP5J1WbnedVjkP8WMAEw4z7ipizniGQx9Y1mME6uvFaEiq3NUJcsafaio7xyzszeJJFsdJrVKgH63d4Puo6EhKKhRbevM",
+            "p2sigPLqSBiAfah6EKuRfGx9SNBKortYeirZydK4nLaofLjwuhmq8v9CYNzedZo1gb1YLnGerQ3LC1S5S3wKQuRzD8FgXf1Zsh",
+        )
+    }
+
+    #[test]
+    fn test_verify_invalid_signature() -> Result<(), failure::Error> {
+        let public_key = PublicKey::from_b58("edpkuBknW28nW72KG6RoHtYW7p12T6GKc7nAbwYX5m8Wd9sDVC9yav")?;
+        assert!(public_key.verify(Some(&Watermark::GenericOperation), &[1, 2, 3], &[0; 63]).is_err());
+        assert!(signature_from_b58("edpkuBknW28nW72KG6RoHtYW7p12T6GKc7nAbwYX5m8Wd9sDVC9yav").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_signature_b58_roundtrip() -> Result<(), failure::Error> {
+        let signature = signature_from_b58("edsigtb844KhSCU3zRrj67emZahYa5NjwBEYTwNvGxjbYopdt4pMMA45KWCYL185Lgk1oDrkY813M7vrakCX2q35KMHoNj2LtrY")?;
+        let generic = signature_to_b58(&signature);
+        assert!(generic.starts_with("sig"));
+        assert_eq!(signature, signature_from_b58(&generic)?);
+        Ok(())
+    }
+}
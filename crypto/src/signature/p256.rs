@@ -0,0 +1,229 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! ECDSA verification on the NIST P-256 curve (secp256r1), which is used by the tz3 accounts.
+//!
+//! Only verification of the public data is done here, so the arithmetic does not need to be constant time.
+//! Points are kept in the jacobian coordinates, so only one field inversion is needed per verification.
+
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+
+use super::SignatureError;
+
+const P: &[u8] = b"ffffffff00000001000000000000000000000000ffffffffffffffffffffffff";
+const B: &[u8] = b"5ac635d8aa3a93e7b3ebbd55769886bc651d06b0cc53b0f63bce3c3e27d2604b";
+const N: &[u8] = b"ffffffff00000000ffffffffffffffffbce6faada7179e84f3b9cac2fc632551";
+const GX: &[u8] = b"6b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c296";
+const GY: &[u8] = b"4fe342e2fe1a7f9b8ee7eb4a7c0f9e162bce33576b315ececbb6406837bf51f5";
+
+const COMPRESSED_PUBLIC_KEY_SIZE: usize = 33;
+const SCALAR_SIZE: usize = 32;
+
+/// Point in the jacobian coordinates (x = X / Z^2, y = Y / Z^3), `Z == 0` is the point at infinity
+#[derive(Clone)]
+struct Point {
+    x: BigUint,
+    y: BigUint,
+    z: BigUint,
+}
+
+impl Point {
+    fn infinity() -> Self {
+        Point { x: BigUint::one(), y: BigUint::one(), z: BigUint::zero() }
+    }
+
+    fn affine(x: BigUint, y: BigUint) -> Self {
+        Point { x, y, z: BigUint::one() }
+    }
+
+    fn is_infinity(&self) -> bool {
+        self.z.is_zero()
+    }
+}
+
+struct Curve {
+    p: BigUint,
+    n: BigUint,
+    b: BigUint,
+    g: Point,
+}
+
+impl Curve {
+    fn new() -> Self {
+        let hex = |value: &[u8]| BigUint::parse_bytes(value, 16).expect("invalid curve constant");
+        Curve {
+            p: hex(P),
+            n: hex(N),
+            b: hex(B),
+            g: Point::affine(hex(GX), hex(GY)),
+        }
+    }
+
+    fn add_mod(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        (a + b) % &self.p
+    }
+
+    fn sub_mod(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        (a + &self.p - b) % &self.p
+    }
+
+    fn mul_mod(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        (a * b) % &self.p
+    }
+
+    /// Decode the compressed SEC1 point (0x02 or 0x03 followed by x)
+    fn decompress(&self, bytes: &[u8]) -> Result<Point, SignatureError> {
+        if bytes.len() != COMPRESSED_PUBLIC_KEY_SIZE || (bytes[0] != 0x02 && bytes[0] != 0x03) {
+            return Err(SignatureError::InvalidPublicKey { reason: "p256 public key is not a compressed point".to_string() });
+        }
+        let x = BigUint::from_bytes_be(&bytes[1..]);
+        if x >= self.p {
+            return Err(SignatureError::InvalidPublicKey { reason: "p256 public key is out of the field".to_string() });
+        }
+
+        // y^2 = x^3 - 3x + b, p = 3 (mod 4), so the square root is rhs^((p + 1) / 4)
+        let x3 = self.mul_mod(&self.mul_mod(&x, &x), &x);
+        let rhs = self.add_mod(&self.sub_mod(&x3, &self.mul_mod(&BigUint::from(3u8), &x)), &self.b);
+        let y = rhs.modpow(&((&self.p + 1u8) >> 2), &self.p);
+        if self.mul_mod(&y, &y) != rhs {
+            return Err(SignatureError::InvalidPublicKey { reason: "p256 public key is not on the curve".to_string() });
+        }
+
+        let odd = (&y % 2u8).is_one();
+        let y = if odd == (bytes[0] == 0x03) { y } else { self.sub_mod(&BigUint::zero(), &y) };
+        Ok(Point::affine(x, y))
+    }
+
+    fn double(&self, point: &Point) -> Point {
+        if point.is_infinity() || point.y.is_zero() {
+            return Point::infinity();
+        }
+        // dbl-2001-b, a = -3
+        let delta = self.mul_mod(&point.z, &point.z);
+        let gamma = self.mul_mod(&point.y, &point.y);
+        let beta = self.mul_mod(&point.x, &gamma);
+        let alpha = self.mul_mod(
+            &BigUint::from(3u8),
+            &self.mul_mod(&self.sub_mod(&point.x, &delta), &self.add_mod(&point.x, &delta)),
+        );
+        let beta4 = self.mul_mod(&BigUint::from(4u8), &beta);
+        let x = self.sub_mod(&self.mul_mod(&alpha, &alpha), &self.add_mod(&beta4, &beta4));
+        let yz = self.add_mod(&point.y, &point.z);
+        let z = self.sub_mod(&self.sub_mod(&self.mul_mod(&yz, &yz), &gamma), &delta);
+        let gamma2 = self.mul_mod(&gamma, &gamma);
+        let y = self.sub_mod(&self.mul_mod(&alpha, &self.sub_mod(&beta4, &x)), &self.mul_mod(&BigUint::from(8u8), &gamma2));
+        Point { x, y, z }
+    }
+
+    fn add(&self, a: &Point, b: &Point) -> Point {
+        if a.is_infinity() {
+            return b.clone();
+        }
+        if b.is_infinity() {
+            return a.clone();
+        }
+        let z1z1 = self.mul_mod(&a.z, &a.z);
+        let z2z2 = self.mul_mod(&b.z, &b.z);
+        let u1 = self.mul_mod(&a.x, &z2z2);
+        let u2 = self.mul_mod(&b.x, &z1z1);
+        let s1 = self.mul_mod(&a.y, &self.mul_mod(&b.z, &z2z2));
+        let s2 = self.mul_mod(&b.y, &self.mul_mod(&a.z, &z1z1));
+        if u1 == u2 {
+            return if s1 == s2 { self.double(a) } else { Point::infinity() };
+        }
+
+        let h = self.sub_mod(&u2, &u1);
+        let r = self.sub_mod(&s2, &s1);
+        let hh = self.mul_mod(&h, &h);
+        let hhh = self.mul_mod(&hh, &h);
+        let u1hh = self.mul_mod(&u1, &hh);
+        let x = self.sub_mod(&self.sub_mod(&self.mul_mod(&r, &r), &hhh), &self.add_mod(&u1hh, &u1hh));
+        let y = self.sub_mod(&self.mul_mod(&r, &self.sub_mod(&u1hh, &x)), &self.mul_mod(&s1, &hhh));
+        let z = self.mul_mod(&h, &self.mul_mod(&a.z, &b.z));
+        Point { x, y, z }
+    }
+
+    /// Computes `k1 * a + k2 * b` at once (Shamir's trick)
+    fn mul_add(&self, k1: &BigUint, a: &Point, k2: &BigUint, b: &Point) -> Point {
+        let ab = self.add(a, b);
+        let bits = std::cmp::max(k1.bits(), k2.bits());
+        let mut result = Point::infinity();
+        for bit in (0..bits).rev() {
+            result = self.double(&result);
+            let mask = BigUint::one() << bit;
+            match ((k1 & &mask).is_zero(), (k2 & &mask).is_zero()) {
+                (false, false) => result = self.add(&result, &ab),
+                (false, true) => result = self.add(&result, a),
+                (true, false) => result = self.add(&result, b),
+                (true, true) => (),
+            }
+        }
+        result
+    }
+
+    /// Affine x coordinate of the point, which must not be the infinity
+    fn affine_x(&self, point: &Point) -> BigUint {
+        let z_inv = point.z.modpow(&(&self.p - 2u8), &self.p);
+        self.mul_mod(&point.x, &self.mul_mod(&z_inv, &z_inv))
+    }
+}
+
+/// Verify the ECDSA signature (r || s, 32 bytes each) of the 32 bytes `digest` by the compressed `public_key`
+pub(super) fn verify_digest(public_key: &[u8], digest: &[u8], signature: &[u8]) -> Result<bool, SignatureError> {
+    let curve = Curve::new();
+    let public_key = curve.decompress(public_key)?;
+    if digest.len() != SCALAR_SIZE {
+        return Err(SignatureError::InvalidSignature { reason: format!("invalid size of the digest: {}", digest.len()) });
+    }
+
+    let (r, s) = signature.split_at(SCALAR_SIZE);
+    let r = BigUint::from_bytes_be(r);
+    let s = BigUint::from_bytes_be(s);
+    if r.is_zero() || s.is_zero() || r >= curve.n || s >= curve.n {
+        return Err(SignatureError::InvalidSignature { reason: "p256 signature is out of range".to_string() });
+    }
+
+    // digest has the same bit size as the curve order, so it is not truncated
+    let e = BigUint::from_bytes_be(digest) % &curve.n;
+    let s_inv = s.modpow(&(&curve.n - 2u8), &curve.n);
+    let u1 = (e * &s_inv) % &curve.n;
+    let u2 = (&r * &s_inv) % &curve.n;
+
+    let point = curve.mul_add(&u1, &curve.g, &u2, &public_key);
+    if point.is_infinity() {
+        return Ok(false);
+    }
+    Ok(curve.affine_x(&point) % &curve.n == r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_double_equals_add() {
+        let curve = Curve::new();
+        let doubled = curve.double(&curve.g);
+        let tripled = curve.add(&doubled, &curve.g);
+        let sum = curve.mul_add(&BigUint::from(1u8), &curve.g, &BigUint::from(2u8), &curve.g);
+        assert_eq!(curve.affine_x(&tripled), curve.affine_x(&sum));
+        assert!(curve.mul_add(&(&curve.n - 1u8), &curve.g, &BigUint::one(), &curve.g).is_infinity());
+    }
+
+    #[test]
+    fn test_decompress_generator() -> Result<(), failure::Error> {
+        let curve = Curve::new();
+        let mut compressed = vec![0x02 + (&curve.g.y % 2u8).to_bytes_be()[0]];
+        compressed.extend_from_slice(&curve.g.x.to_bytes_be());
+        let point = curve.decompress(&compressed)?;
+        assert_eq!(curve.g.y, point.y);
+
+        compressed[0] ^= 1;
+        let negated = curve.decompress(&compressed)?;
+        assert_eq!(&curve.p - &curve.g.y, negated.y);
+
+        assert!(curve.decompress(&compressed[1..]).is_err());
+        Ok(())
+    }
+}
@@ -7,19 +7,28 @@ use std::fs;
 use std::io::{self, BufRead};
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use clap::{App, Arg};
+use clap::{App, Arg, SubCommand};
 
-use shell::peer_manager::Threshold;
+use crypto::hash::{BlockHash, HashType};
+use shell::peer_manager::{PeerScoring, Threshold};
+use storage::context::ContextBackend;
+use storage::pruning::HistoryMode;
 use tezos_api::environment;
 use tezos_api::environment::TezosEnvironment;
 
+/// Expected proof of work of the node identity and of the remote peers, if not configured otherwise
+const DEFAULT_EXPECTED_POW: &str = "26.0";
+
 #[derive(Debug, Clone)]
 pub struct P2p {
     pub listener_port: u16,
     pub bootstrap_lookup_addresses: Vec<String>,
     pub initial_peers: Vec<SocketAddr>,
     pub peer_threshold: Threshold,
+    pub peer_scoring: PeerScoring,
+    pub peer_expected_pow: f64,
 }
 
 #[derive(Debug, Clone)]
@@ -40,6 +49,9 @@ pub struct Logging {
 pub struct Storage {
     pub bootstrap_db_path: PathBuf,
     pub tezos_data_dir: PathBuf,
+    pub context_backend: ContextBackend,
+    pub history_mode: HistoryMode,
+    pub protocol_sources: Vec<PathBuf>,
 }
 
 #[derive(Debug, Clone)]
@@ -77,9 +89,71 @@ pub struct Environment {
     pub record: bool,
     pub tezos_network: TezosEnvironment,
     pub enable_testchain: bool,
+    /// Mempool is disabled by default, because the protocol runner cannot validate operations yet
+    pub enable_mempool: bool,
     pub protocol_runner: PathBuf,
     pub no_of_ffi_calls_threshold_for_gc: i32,
-    pub tokio_threads: usize
+    pub tokio_threads: usize,
+    /// Only report database migrations, which would run, and exit
+    pub dry_run: bool,
+
+    /// Snapshot command, node runs the command instead of syncing
+    pub snapshot: Option<SnapshotCommand>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SnapshotCommand {
+    Export {
+        block_hash: BlockHash,
+        file: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum IdentityCommand {
+    Generate {
+        file: PathBuf,
+        expected_pow: f64,
+        threads: usize,
+    },
+    Check {
+        file: PathBuf,
+        expected_pow: f64,
+    },
+}
+
+impl IdentityCommand {
+    /// Parses identity command from cli arguments, identity command does not need the rest of the node configuration
+    pub fn from_args() -> Option<Self> {
+        let args = tezos_app().get_matches();
+        args.subcommand_matches("identity")
+            .and_then(|identity_args| match identity_args.subcommand() {
+                ("generate", Some(generate_args)) => Some(IdentityCommand::Generate {
+                    file: generate_args.value_of("file")
+                        .unwrap()
+                        .parse::<PathBuf>()
+                        .expect("Provided value cannot be converted to path"),
+                    expected_pow: generate_args.value_of("expected-pow")
+                        .unwrap_or(DEFAULT_EXPECTED_POW)
+                        .parse::<f64>()
+                        .expect("Provided value cannot be converted to number"),
+                    threads: generate_args.value_of("threads")
+                        .map(|v| v.parse::<usize>().expect("Provided value cannot be converted to number"))
+                        .unwrap_or_else(num_cpus::get),
+                }),
+                ("check", Some(check_args)) => Some(IdentityCommand::Check {
+                    file: check_args.value_of("file")
+                        .unwrap()
+                        .parse::<PathBuf>()
+                        .expect("Provided value cannot be converted to path"),
+                    expected_pow: check_args.value_of("expected-pow")
+                        .unwrap_or(DEFAULT_EXPECTED_POW)
+                        .parse::<f64>()
+                        .expect("Provided value cannot be converted to number"),
+                }),
+                _ => None,
+            })
+    }
 }
 
 macro_rules! parse_validator_fn {
@@ -138,7 +212,8 @@ pub fn tezos_app() -> App<'static, 'static> {
             .long("identity-expected-pow")
             .takes_value(true)
             .value_name("NUM")
-            .help("Expected power of identity for node. It is used to generate new identity. Default: 26.0")
+            .help("Expected power of identity for node. It is used to generate new identity")
+            .default_value(DEFAULT_EXPECTED_POW)
             .validator(parse_validator_fn!(f64, "Value must be a valid f64 number for expected_pow")))
         .arg(Arg::with_name("bootstrap-db-path")
             .long("bootstrap-db-path")
@@ -146,6 +221,25 @@ pub fn tezos_app() -> App<'static, 'static> {
             .value_name("PATH")
             .help("Path to bootstrap database directory.
                        In case it starts with ./ or ../, it is relative path to the current dir, otherwise to the --tezos-data-dir"))
+        .arg(Arg::with_name("context-backend")
+            .long("context-backend")
+            .takes_value(true)
+            .value_name("BACKEND")
+            .possible_values(&["skip-list", "in-memory"])
+            .help("Storage backend for the context. In-memory context is not persisted and is intended for tests and benchmarks. Default: skip-list"))
+        .arg(Arg::with_name("history-mode")
+            .long("history-mode")
+            .takes_value(true)
+            .value_name("MODE")
+            .help("Full mode keeps data of all blocks, rolling:<cycles> mode prunes operations, json data and context actions of blocks older than <cycles> cycles. Default: full")
+            .validator(|v| v.parse::<HistoryMode>().map(|_| ())))
+        .arg(Arg::with_name("protocol-sources")
+            .long("protocol-sources")
+            .takes_value(true)
+            .value_name("PATH")
+            .help("Directories with sources of the protocols, which are served to the peers. Directories are delimited by a comma,
+                       each contains the TEZOS_PROTOCOL file, e.g. src/proto_006_PsCARTHA/lib_protocol of the Tezos repository.
+                       In case it starts with ./ or ../, it is relative path to the current dir, otherwise to the --tezos-data-dir"))
         .arg(Arg::with_name("bootstrap-lookup-address")
             .long("bootstrap-lookup-address")
             .takes_value(true)
@@ -195,6 +289,11 @@ pub fn tezos_app() -> App<'static, 'static> {
             .takes_value(true)
             .value_name("BOOL")
             .help("Flag for enable/disable test chain switching for block applying. Default: false"))
+        .arg(Arg::with_name("enable-mempool")
+            .long("enable-mempool")
+            .takes_value(true)
+            .value_name("BOOL")
+            .help("Flag for enable/disable mempool (collecting, validation and injection of operations). Default: false"))
         .arg(Arg::with_name("websocket-address")
             .long("websocket-address")
             .takes_value(true)
@@ -235,6 +334,29 @@ pub fn tezos_app() -> App<'static, 'static> {
                 .value_name("NUM")
                 .help("Maximal number of peers to connect to")
                 .validator(parse_validator_fn!(usize, "Value must be a valid number")))
+            .arg(Arg::with_name("peer-ban-threshold")
+                .long("peer-ban-threshold")
+                .takes_value(true)
+                .value_name("NUM")
+                .allow_hyphen_values(true)
+                .help("Peer is banned, when its score drops to this value. Peers are scored by their behaviour, e.g. invalid messages or timeouts lower the score")
+                .validator(parse_validator_fn!(i32, "Value must be a valid number")))
+            .arg(Arg::with_name("peer-ban-duration")
+                .long("peer-ban-duration")
+                .takes_value(true)
+                .value_name("SECS")
+                .help("How long (in seconds) is the IP address of the misbehaving peer banned")
+                .validator(parse_validator_fn!(u64, "Value must be a valid number")))
+            .arg(Arg::with_name("peer-expected-pow")
+                .long("peer-expected-pow")
+                .takes_value(true)
+                .value_name("NUM")
+                .help("Minimal proof of work of the remote peer identity, peers with insufficient proof of work are rejected")
+                .default_value(DEFAULT_EXPECTED_POW)
+                .validator(|v| match v.parse::<f64>() {
+                    Ok(pow) if (0.0..=256.0).contains(&pow) => Ok(()),
+                    _ => Err("Value must be a number between 0 and 256".to_string()),
+                }))
             .arg(Arg::with_name("protocol-runner")
                 .long("protocol-runner")
                 .takes_value(true)
@@ -257,7 +379,69 @@ pub fn tezos_app() -> App<'static, 'static> {
             .long("record")
             .takes_value(true)
             .value_name("BOOL")
-            .help("Flag for turn on/off record mode"));
+            .help("Flag for turn on/off record mode"))
+        .arg(Arg::with_name("dry-run")
+            .long("dry-run")
+            .help("Only report database migrations, which would run, and exit"))
+        .subcommand(SubCommand::with_name("snapshot")
+            .about("Export snapshot of the storage, node exits after the command is finished")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("export")
+                .about("Export the applied block with its recent history and the context at that block to the snapshot file")
+                .arg(Arg::with_name("block")
+                    .long("block")
+                    .takes_value(true)
+                    .value_name("BLOCK_HASH")
+                    .required(true)
+                    .help("Hash of the applied block, at which the snapshot is created")
+                    .validator(|v| HashType::BlockHash.string_to_bytes(&v).map(|_| ()).map_err(|e| format!("Invalid block hash '{}': {}", v, e))))
+                .arg(Arg::with_name("file")
+                    .long("file")
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .required(true)
+                    .help("Path of the created snapshot file"))))
+        .subcommand(SubCommand::with_name("identity")
+            .about("Generate/check identity file without running the node, node configuration is not required")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("generate")
+                .about("Generate new identity and store it to the identity file")
+                .arg(Arg::with_name("file")
+                    .long("file")
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .required(true)
+                    .help("Path of the generated json identity file")
+                    .validator(|v| if Path::new(&v).exists() { Err(format!("Identity file already exists at '{}'", v)) } else { Ok(()) }))
+                .arg(Arg::with_name("expected-pow")
+                    .long("expected-pow")
+                    .takes_value(true)
+                    .value_name("NUM")
+                    .help("Expected proof of work of the generated identity")
+                    .default_value(DEFAULT_EXPECTED_POW)
+                    .validator(parse_validator_fn!(f64, "Value must be a valid f64 number for expected_pow")))
+                .arg(Arg::with_name("threads")
+                    .long("threads")
+                    .takes_value(true)
+                    .value_name("NUM")
+                    .help("Number of threads searching for the proof of work stamp. Default: number of CPUs")
+                    .validator(parse_validator_fn!(usize, "Value must be a valid number"))))
+            .subcommand(SubCommand::with_name("check")
+                .about("Check that the identity file is valid and satisfies the expected proof of work")
+                .arg(Arg::with_name("file")
+                    .long("file")
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .required(true)
+                    .help("Path of the json identity file")
+                    .validator(|v| if Path::new(&v).exists() { Ok(()) } else { Err(format!("Identity file not found at '{}'", v)) }))
+                .arg(Arg::with_name("expected-pow")
+                    .long("expected-pow")
+                    .takes_value(true)
+                    .value_name("NUM")
+                    .help("Expected proof of work of the identity")
+                    .default_value(DEFAULT_EXPECTED_POW)
+                    .validator(parse_validator_fn!(f64, "Value must be a valid f64 number for expected_pow")))));
     app
 }
 
@@ -285,7 +469,8 @@ pub fn validate_required_args(args: &clap::ArgMatches) {
     validate_required_arg(args, "identity-expected-pow");
     validate_required_arg(args, "record");
 
-    // "bootstrap-lookup-address", "log-file" and "peers" are not required
+    // "bootstrap-lookup-address", "context-backend", "dry-run", "enable-mempool", "history-mode", "log-file", "peer-ban-threshold",
+    // "peer-ban-duration", "peer-expected-pow" and "peers" are not required
 }
 
 // Validates single required arg. If missing, exit whole process
@@ -431,6 +616,18 @@ impl Environment {
                         .parse::<usize>()
                         .expect("Provided value cannot be converted to number"),
                 ),
+                peer_scoring: PeerScoring {
+                    ban_threshold: args.value_of("peer-ban-threshold")
+                        .map(|v| v.parse::<i32>().expect("Provided value cannot be converted to number"))
+                        .unwrap_or_else(|| PeerScoring::default().ban_threshold),
+                    ban_duration: args.value_of("peer-ban-duration")
+                        .map(|v| Duration::from_secs(v.parse::<u64>().expect("Provided value cannot be converted to number")))
+                        .unwrap_or_else(|| PeerScoring::default().ban_duration),
+                },
+                peer_expected_pow: args.value_of("peer-expected-pow")
+                    .unwrap_or(DEFAULT_EXPECTED_POW)
+                    .parse::<f64>()
+                    .expect("Provided value cannot be converted to number"),
             },
             rpc: crate::configuration::Rpc {
                 listener_port: args
@@ -477,6 +674,20 @@ impl Environment {
                         .expect("Provided value cannot be converted to path");
                     get_final_path(&data_dir, db_path)
                 },
+                context_backend: args.value_of("context-backend")
+                    .unwrap_or("skip-list")
+                    .parse::<ContextBackend>()
+                    .expect("Was expecting 'skip-list' or 'in-memory'"),
+                history_mode: args.value_of("history-mode")
+                    .unwrap_or("full")
+                    .parse::<HistoryMode>()
+                    .expect("Was expecting 'full' or 'rolling:<cycles>'"),
+                protocol_sources: args.value_of("protocol-sources")
+                    .map(|dirs| dirs
+                        .split(',')
+                        .map(|dir| get_final_path(&data_dir, dir.parse::<PathBuf>().expect("Provided value cannot be converted to path")))
+                        .collect()
+                    ).unwrap_or_default(),
             },
             identity: crate::configuration::Identity {
                 identity_json_file_path: {
@@ -487,7 +698,7 @@ impl Environment {
                     get_final_path(&data_dir, identity_path)
                 },
                 expected_pow: args.value_of("identity-expected-pow")
-                    .unwrap_or("26.0")
+                    .unwrap_or(DEFAULT_EXPECTED_POW)
                     .parse::<f64>()
                     .expect("Provided value cannot be converted to number"),
             },
@@ -513,6 +724,23 @@ impl Environment {
                 .unwrap_or("false")
                 .parse::<bool>()
                 .expect("Provided value cannot be converted to bool"),
+            enable_mempool: args.value_of("enable-mempool")
+                .unwrap_or("false")
+                .parse::<bool>()
+                .expect("Provided value cannot be converted to bool"),
+            dry_run: args.is_present("dry-run"),
+            snapshot: args.subcommand_matches("snapshot")
+                .and_then(|snapshot_args| match snapshot_args.subcommand() {
+                    ("export", Some(export_args)) => Some(SnapshotCommand::Export {
+                        block_hash: HashType::BlockHash.string_to_bytes(export_args.value_of("block").unwrap())
+                            .expect("Provided value cannot be converted to block hash"),
+                        file: export_args.value_of("file")
+                            .unwrap()
+                            .parse::<PathBuf>()
+                            .expect("Provided value cannot be converted to path"),
+                    }),
+                    _ => None,
+                }),
         }
     }
 }
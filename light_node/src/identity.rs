@@ -4,22 +4,26 @@
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use failure::Fail;
+use hex::FromHex;
+use slog::{info, Logger};
 
+use crypto::crypto_box::{self, PublicKey, SecretKey};
+use crypto::hash::HashType;
+use crypto::proof_of_work::{self, PowError};
 use tezos_api::identity::Identity;
 
+/// How often is the progress of the proof of work search reported
+const POW_PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
 #[derive(Fail, Debug)]
 pub enum IdentityError {
     #[fail(display = "I/O error: {}", reason)]
     IoError {
         reason: io::Error
     },
-    #[fail(display = "Service error: {}, e: {}", message, error)]
-    ServiceError {
-        error: failure::Error,
-        message: &'static str,
-    },
     #[fail(display = "Identity serialization error: {}", reason)]
     SerializationError {
         reason: serde_json::Error
@@ -28,7 +32,14 @@ pub enum IdentityError {
     DeserializationError {
         reason: serde_json::Error
     },
-
+    #[fail(display = "Invalid identity: {}", reason)]
+    InvalidIdentity {
+        reason: String
+    },
+    #[fail(display = "Identity proof of work error: {}", error)]
+    ProofOfWorkError {
+        error: PowError
+    },
 }
 
 impl From<io::Error> for IdentityError {
@@ -37,6 +48,12 @@ impl From<io::Error> for IdentityError {
     }
 }
 
+impl From<PowError> for IdentityError {
+    fn from(error: PowError) -> Self {
+        IdentityError::ProofOfWorkError { error }
+    }
+}
+
 impl slog::Value for IdentityError {
     fn serialize(&self, _record: &slog::Record, key: slog::Key, serializer: &mut dyn slog::Serializer) -> slog::Result {
         serializer.emit_arguments(key, &format_args!("{}", self))
@@ -56,4 +73,129 @@ pub fn store_identity(path: &PathBuf, identity: &Identity) -> Result<(), Identit
     fs::write(&path, &identity_json)?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Generate new identity with the proof of work stamp satisfying the expected pow.
+///
+/// Proof of work is searched in `threads` threads, this can take a while for higher expected pow.
+pub fn generate_identity(expected_pow: f64, threads: usize, log: &Logger) -> Result<Identity, IdentityError> {
+    let (public_key, secret_key) = crypto_box::random_keypair();
+    let proof_of_work_stamp = proof_of_work::generate_proof_of_work(
+        public_key.as_bytes(),
+        expected_pow,
+        threads,
+        POW_PROGRESS_INTERVAL,
+        |attempts| info!(log, "Searching for proof of work stamp"; "attempts" => attempts, "expected_pow" => expected_pow),
+    )?;
+
+    Ok(Identity {
+        peer_id: HashType::CryptoboxPublicKeyHash.bytes_to_string(public_key.as_bytes()),
+        public_key: hex::encode(public_key.as_bytes()),
+        secret_key: hex::encode(secret_key.as_bytes()),
+        proof_of_work_stamp: hex::encode(proof_of_work_stamp),
+    })
+}
+
+/// Check that the identity is consistent and its proof of work stamp satisfies the expected pow
+pub fn check_identity(identity: &Identity, expected_pow: f64) -> Result<(), IdentityError> {
+    let public_key = decode_key::<PublicKey>(&identity.public_key, "public_key")?;
+    let secret_key = decode_key::<SecretKey>(&identity.secret_key, "secret_key")?;
+    if secret_key.public_key().as_ref() != public_key.as_bytes() {
+        return Err(IdentityError::InvalidIdentity { reason: "secret_key does not match the public_key".to_string() });
+    }
+    let proof_of_work_stamp = hex::decode(&identity.proof_of_work_stamp)
+        .map_err(|e| IdentityError::InvalidIdentity { reason: format!("proof_of_work_stamp is not a valid hex string: {}", e) })?;
+
+    let peer_id = HashType::CryptoboxPublicKeyHash.bytes_to_string(public_key.as_bytes());
+    if peer_id != identity.peer_id {
+        return Err(IdentityError::InvalidIdentity { reason: format!("peer_id {} does not match the public key, expected {}", identity.peer_id, peer_id) });
+    }
+
+    proof_of_work::check_proof_of_work(public_key.as_bytes(), &proof_of_work_stamp, expected_pow)
+        .map_err(IdentityError::from)
+}
+
+fn decode_key<K: FromHex>(key: &str, name: &str) -> Result<K, IdentityError> {
+    // key wrappers expect exactly 32 bytes
+    match hex::decode(key) {
+        Ok(bytes) if bytes.len() == 32 => K::from_hex(key)
+            .map_err(|_| IdentityError::InvalidIdentity { reason: format!("{} is not a valid hex string", name) }),
+        Ok(bytes) => Err(IdentityError::InvalidIdentity { reason: format!("{} has invalid size: {}", name, bytes.len()) }),
+        Err(e) => Err(IdentityError::InvalidIdentity { reason: format!("{} is not a valid hex string: {}", name, e) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use slog::Discard;
+
+    use super::*;
+
+    fn generate(expected_pow: f64) -> Identity {
+        generate_identity(expected_pow, 1, &Logger::root(Discard, slog::o!())).expect("Failed to generate identity")
+    }
+
+    #[test]
+    fn test_generate_identity() {
+        let identity = generate(4.0);
+        assert!(check_identity(&identity, 4.0).is_ok());
+        assert_eq!(HashType::CryptoboxPublicKeyHash.bytes_to_string(&hex::decode(&identity.public_key).unwrap()), identity.peer_id);
+    }
+
+    #[test]
+    fn test_check_identity_mismatched_keys() {
+        let identity = generate(0.0);
+        let other = generate(0.0);
+        let identity = Identity { secret_key: other.secret_key, ..identity };
+        match check_identity(&identity, 0.0) {
+            Err(IdentityError::InvalidIdentity { .. }) => (),
+            result => panic!("Mismatched secret key should be rejected, but result was: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_check_identity_mismatched_peer_id() {
+        let identity = generate(0.0);
+        let other = generate(0.0);
+        let identity = Identity { peer_id: other.peer_id, ..identity };
+        match check_identity(&identity, 0.0) {
+            Err(IdentityError::InvalidIdentity { .. }) => (),
+            result => panic!("Mismatched peer id should be rejected, but result was: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_check_identity_invalid_keys() {
+        let identity = generate(0.0);
+        let short_key = Identity { public_key: "abcd".to_string(), ..identity.clone() };
+        assert!(check_identity(&short_key, 0.0).is_err());
+        let not_hex = Identity { secret_key: "x".repeat(64), ..identity };
+        assert!(check_identity(&not_hex, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_check_identity_insufficient_pow() {
+        let identity = generate(0.0);
+        // zero stamp would have to hit 1 in 2^200 chance to satisfy the target
+        let identity = Identity { proof_of_work_stamp: hex::encode([0u8; proof_of_work::POW_STAMP_SIZE]), ..identity };
+        match check_identity(&identity, 200.0) {
+            Err(IdentityError::ProofOfWorkError { .. }) => (),
+            result => panic!("Insufficient proof of work should be rejected, but result was: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_store_and_load_identity() {
+        let path = std::env::temp_dir().join("__light_node_identity_test.json");
+        let identity = generate(0.0);
+        store_identity(&path, &identity).expect("Failed to store identity");
+        let loaded = load_identity(&path).expect("Failed to load identity");
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(identity.peer_id, loaded.peer_id);
+        assert_eq!(identity.public_key, loaded.public_key);
+        assert_eq!(identity.secret_key, loaded.secret_key);
+        assert_eq!(identity.proof_of_work_stamp, loaded.proof_of_work_stamp);
+        assert!(check_identity(&loaded, 0.0).is_ok());
+    }
+}
@@ -1,7 +1,9 @@
 // Copyright (c) SimpleStaking and Tezedge Contributors
 // SPDX-License-Identifier: MIT
 
-use std::sync::Arc;
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::{Arc, RwLock};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
@@ -14,31 +16,41 @@ use monitoring::{listener::{
     EventPayloadStorage,
     EventStorage, NetworkChannelListener,
 }, Monitor, WebsocketHandler};
+use crypto::hash::HashType;
 use networking::p2p::network_channel::NetworkChannel;
 use rpc::rpc_actor::RpcServer;
 use shell::chain_feeder::ChainFeeder;
 use shell::chain_manager::ChainManager;
 use shell::context_listener::ContextListener;
-use shell::peer_manager::PeerManager;
+use shell::history_pruner::HistoryPruner;
+use shell::mempool::{MempoolManager, MempoolState};
+use shell::mempool_prevalidator::MempoolPrevalidator;
+use shell::peer_manager::{PeerManager, PeersState};
 use shell::shell_channel::{ShellChannel, ShellChannelTopic, ShuttingDown};
-use storage::{block_storage, BlockMetaStorage, BlockStorage, context_action_storage, ContextActionStorage, OperationsMetaStorage, OperationsStorage, resolve_storage_init_chain_data, StorageError, StorageInitInfo, SystemStorage};
+use shell::validation::{BakingRightsResolver, ContextBakingRightsResolver};
+use storage::{block_storage, BlockMetaStorage, BlockStorage, context_action_storage, ContextActionStorage, operations_storage, OperationsMetaStorage, OperationsStorage, PeerBlacklistStorage, ProtocolStorage, resolve_storage_init_chain_data, StorageError, StorageInitInfo, SystemStorage};
+use storage::context::{ContextApiRef, ContextListIndex};
 use storage::p2p_message_storage::{P2PMessageSecondaryIndex, P2PMessageStorage};
 use storage::persistent::{CommitLogSchema, KeyValueSchema, open_cl, open_kv, PersistentStorage};
 use storage::persistent::sequence::Sequences;
+use storage::pruning::{HistoryMode, StoragePruner};
 use storage::skip_list::{DatabaseBackedSkipList, Lane, ListValue};
+use storage::migration;
+use storage::snapshot::{self, SnapshotError};
 use tezos_api::environment;
 use tezos_api::environment::TezosEnvironmentConfiguration;
 use tezos_api::ffi::TezosRuntimeConfiguration;
 use tezos_api::identity::Identity;
+use tezos_wrapper::pool::{ProtocolRunnerPool, ProtocolRunnerPoolConfiguration};
 use tezos_wrapper::service::{IpcCmdServer, IpcEvtServer, ProtocolEndpointConfiguration, ProtocolRunner, ProtocolRunnerEndpoint};
 
-use crate::configuration::LogFormat;
+use crate::configuration::{IdentityCommand, LogFormat, SnapshotCommand};
 use crate::identity::IdentityError;
 
 mod configuration;
 mod identity;
 
-const DATABASE_VERSION: i64 = 12;
+const DATABASE_VERSION: i64 = 15;
 
 macro_rules! shutdown_and_exit {
     ($err:expr, $sys:ident) => {{
@@ -100,6 +112,7 @@ fn block_on_actors(
     identity: Identity,
     actor_system: ActorSystem,
     persistent_storage: PersistentStorage,
+    protocol_endpoint_configuration: ProtocolEndpointConfiguration,
     protocol_commands: IpcCmdServer,
     protocol_events: IpcEvtServer,
     protocol_runner_run: Arc<AtomicBool>,
@@ -113,15 +126,42 @@ fn block_on_actors(
         .expect("Failed to create shell channel");
 
     // it's important to start ContextListener before ChainFeeder, because chain_feeder can trigger init_genesis which send ContextAction, and we need thouse action to process first
-    let _ = ContextListener::actor(&actor_system, &persistent_storage, protocol_events, log.clone())
+    let context = env.storage.context_backend.create(&persistent_storage);
+    let _ = ContextListener::actor(&actor_system, &persistent_storage, context.clone(), protocol_events, log.clone())
         .expect("Failed to create context event listener");
-    let _ = ChainFeeder::actor(&actor_system, shell_channel.clone(), &persistent_storage, &init_storage_data, &tezos_env, protocol_commands, log.clone())
+    // baking rights are resolved from the context, block headers are validated with them by both chain feeder and chain manager
+    let baking_rights: Arc<dyn BakingRightsResolver> = Arc::new(ContextBakingRightsResolver::new(&persistent_storage, context.clone()));
+    let _ = ChainFeeder::actor(&actor_system, shell_channel.clone(), &persistent_storage, context.clone(), &init_storage_data, &tezos_env, protocol_commands, Some(baking_rights.clone()), log.clone())
         .expect("Failed to create chain feeder");
     // if feeding is started, than run chain manager
-    let _ = ChainManager::actor(&actor_system, network_channel.clone(), shell_channel.clone(), &persistent_storage, &init_storage_data.chain_id)
+    let _ = ChainManager::actor(&actor_system, network_channel.clone(), shell_channel.clone(), &persistent_storage, &init_storage_data.chain_id, Some(baking_rights))
         .expect("Failed to create chain manager");
+    // protocol runner cannot validate operations yet, so the mempool has to be enabled explicitly
+    let mempool_state = if env.enable_mempool {
+        let mempool_state = Arc::new(RwLock::new(MempoolState::default()));
+        let _ = MempoolManager::actor(&actor_system, network_channel.clone(), shell_channel.clone(), &persistent_storage, &init_storage_data.chain_id, mempool_state.clone())
+            .expect("Failed to create mempool manager");
+        // operations are validated by a readonly protocol runner, so the validation does not block applying of blocks
+        let protocol_runner_pool = ProtocolRunnerPool::new(
+            ProtocolRunnerPoolConfiguration::new(1, Duration::from_secs(10), Duration::from_secs(10)),
+            protocol_endpoint_configuration,
+            log.clone(),
+        );
+        let _ = MempoolPrevalidator::actor(&actor_system, shell_channel.clone(), &persistent_storage, &init_storage_data.chain_id, Box::new(protocol_runner_pool), log.clone())
+            .expect("Failed to create mempool prevalidator");
+        Some(mempool_state)
+    } else {
+        info!(log, "Mempool is disabled");
+        None
+    };
+    if let HistoryMode::Rolling { cycles } = env.storage.history_mode {
+        info!(log, "Running in rolling history mode"; "cycles" => cycles);
+        let _ = HistoryPruner::actor(&actor_system, shell_channel.clone(), &persistent_storage, cycles, log.clone())
+            .expect("Failed to create history pruner");
+    }
 
     // and than open p2p and others
+    let peers_state = Arc::new(RwLock::new(PeersState::new(env.p2p.peer_scoring)));
     let _ = PeerManager::actor(
         &actor_system,
         network_channel.clone(),
@@ -133,13 +173,15 @@ fn block_on_actors(
         env.p2p.listener_port,
         identity,
         tezos_env.version.clone(),
-        persistent_storage.clone())
+        env.p2p.peer_expected_pow,
+        persistent_storage.clone(),
+        peers_state.clone())
         .expect("Failed to create peer manager");
     let websocket_handler = WebsocketHandler::actor(&actor_system, env.rpc.websocket_address, log.clone())
         .expect("Failed to start websocket actor");
     let _ = Monitor::actor(&actor_system, network_channel.clone(), websocket_handler, shell_channel.clone(), &persistent_storage)
         .expect("Failed to create monitor actor");
-    let _ = RpcServer::actor(&actor_system, shell_channel.clone(), ([0, 0, 0, 0], env.rpc.listener_port).into(), &tokio_runtime.handle(), &persistent_storage, &init_storage_data)
+    let _ = RpcServer::actor(&actor_system, shell_channel.clone(), ([0, 0, 0, 0], env.rpc.listener_port).into(), &tokio_runtime.handle(), &persistent_storage, context, mempool_state, peers_state, &init_storage_data)
         .expect("Failed to create RPC server");
     if env.record {
         info!(log, "Running in record mode");
@@ -224,46 +266,62 @@ fn check_database_compatibility(db: Arc<rocksdb::DB>, tezos_env: &TezosEnvironme
     Ok(db_version_ok && chain_id_ok)
 }
 
-fn ensure_identity(identity_cfg: &crate::configuration::Identity, protocol_runner_endpoint: &mut ProtocolRunnerEndpoint, log: Logger) -> Result<Identity, IdentityError> {
+fn ensure_identity(identity_cfg: &crate::configuration::Identity, log: Logger) -> Result<Identity, IdentityError> {
     if identity_cfg.identity_json_file_path.exists() {
         identity::load_identity(&identity_cfg.identity_json_file_path)
     } else {
         info!(log, "Generating new tezos identity. This will take a while"; "expected_pow" => identity_cfg.expected_pow);
 
-        // TODO: TE-74 will be replace with rust version without protocol_runner
-        // setup tezos ocaml runtime just for generate identity
-        let protocol_controller = match protocol_runner_endpoint.commands.accept() {
-            Ok(controller) => controller,
-            Err(e) => {
-                error!(log, "Failed to create protocol controller. Reason: {:?}", e);
-                return Err(IdentityError::ServiceError {
-                    error: e.into(),
-                    message: "Failed to create protocol controller",
-                });
-            },
-        };
-
-        match protocol_controller.generate_identity(identity_cfg.expected_pow) {
-            Ok(identity) => {
-                info!(log, "Identity successfully generated");
-                match identity::store_identity(&identity_cfg.identity_json_file_path, &identity) {
-                    Ok(()) => {
-                        drop(protocol_controller);
-                        info!(log, "Generated identity stored to file"; "file" => identity_cfg.identity_json_file_path.clone().into_os_string().into_string().unwrap());
-                        Ok(identity)
-                    }
-                    Err(e) => Err(e)
-                }
-            }
-            Err(e) => return Err(IdentityError::ServiceError {
-                error: e.into(),
-                message: "Failed to store generated identity",
-            })
+        let identity = identity::generate_identity(identity_cfg.expected_pow, num_cpus::get(), &log)?;
+        info!(log, "Identity successfully generated");
+        identity::store_identity(&identity_cfg.identity_json_file_path, &identity)?;
+        info!(log, "Generated identity stored to file"; "file" => identity_cfg.identity_json_file_path.clone().into_os_string().into_string().unwrap());
+        Ok(identity)
+    }
+}
+
+fn run_identity_command(identity_command: &IdentityCommand, log: &Logger) -> Result<(), IdentityError> {
+    match identity_command {
+        IdentityCommand::Generate { file, expected_pow, threads } => {
+            info!(log, "Generating new tezos identity"; "expected_pow" => expected_pow, "threads" => threads);
+            let identity = identity::generate_identity(*expected_pow, *threads, log)?;
+            identity::store_identity(file, &identity)?;
+            info!(log, "Generated identity stored to file"; "peer_id" => &identity.peer_id, "file" => file.to_string_lossy().to_string());
+        }
+        IdentityCommand::Check { file, expected_pow } => {
+            let identity = identity::load_identity(file)?;
+            identity::check_identity(&identity, *expected_pow)?;
+            info!(log, "Identity is valid"; "peer_id" => &identity.peer_id, "expected_pow" => expected_pow);
         }
     }
+    Ok(())
+}
+
+fn run_snapshot_command(snapshot_command: &SnapshotCommand, persistent_storage: &PersistentStorage, context: &ContextApiRef, log: Logger) -> Result<(), SnapshotError> {
+    match snapshot_command {
+        SnapshotCommand::Export { block_hash, file } => {
+            info!(log, "Exporting snapshot"; "block_hash" => HashType::BlockHash.bytes_to_string(block_hash), "file" => file.to_string_lossy().to_string());
+            let writer = BufWriter::new(File::create(file)?);
+            let header = snapshot::export_snapshot(persistent_storage, context, block_hash, writer)?;
+            info!(log, "Snapshot exported"; "level" => header.level, "block_count" => header.block_count);
+        }
+    }
+    Ok(())
 }
 
 fn main() {
+    // Identity command runs without the node configuration
+    if let Some(identity_command) = IdentityCommand::from_args() {
+        let log = Logger::root(create_terminal_logger!(LogFormat::Simple).fuse(), slog::o!());
+        let result = run_identity_command(&identity_command, &log);
+        if let Err(e) = &result {
+            error!(log, "Identity command failed"; "reason" => e);
+        }
+        // flush the async logger before exit
+        drop(log);
+        std::process::exit(if result.is_ok() { 0 } else { 1 });
+    }
+
     // Parses config + cli args
     let env = crate::configuration::Environment::from_args();
     let tezos_env = environment::TEZOS_ENV
@@ -275,51 +333,28 @@ fn main() {
 
     let actor_system = SystemBuilder::new().name("light-node").log(log.clone()).create().expect("Failed to create actor system");
 
-    // tezos protocol runner endpoint
-    let mut protocol_runner_endpoint = ProtocolRunnerEndpoint::new(ProtocolEndpointConfiguration::new(
-        TezosRuntimeConfiguration {
-            log_enabled: env.logging.ocaml_log_enabled,
-            no_of_ffi_calls_treshold_for_gc: env.no_of_ffi_calls_threshold_for_gc,
-        },
-        tezos_env.clone(),
-        env.enable_testchain,
-        &env.storage.tezos_data_dir,
-        &env.protocol_runner,
-    ));
-
-    // TODO: refactor and move to restarting protocol_runner thread, when generate_identity will be in rust
-    let mut protocol_runner_process = match protocol_runner_endpoint.runner.spawn() {
-        Ok(process) => process,
-        Err(e) => shutdown_and_exit!(error!(log, "Failed to spawn protocol runner process"; "reason" => e), actor_system),
-    };
-
-    // Loads tezos identity based on provided identity-file argument. In case it does not exist, it will try to automatically generate it
-    let tezos_identity = match ensure_identity(&env.identity, &mut protocol_runner_endpoint, log.clone()) {
-        Ok(identity) => {
-            info!(log, "Identity loaded from file"; "file" => env.identity.identity_json_file_path.clone().into_os_string().into_string().unwrap());
-            identity
-        }
-        Err(e) => shutdown_and_exit!(error!(log, "Failed to load identity"; "reason" => e, "file" => env.identity.identity_json_file_path.into_os_string().into_string().unwrap()), actor_system),
-    };
-
     let schemas = vec![
         block_storage::BlockPrimaryIndex::descriptor(),
         block_storage::BlockByLevelIndex::descriptor(),
         block_storage::BlockByContextHashIndex::descriptor(),
         BlockMetaStorage::descriptor(),
         OperationsStorage::descriptor(),
+        operations_storage::OperationsByHashIndex::descriptor(),
         OperationsMetaStorage::descriptor(),
         EventPayloadStorage::descriptor(),
         EventStorage::descriptor(),
         context_action_storage::ContextActionPrimaryIndex::descriptor(),
         context_action_storage::ContextActionByContractIndex::descriptor(),
         SystemStorage::descriptor(),
+        PeerBlacklistStorage::descriptor(),
         DatabaseBackedSkipList::descriptor(),
+        ContextListIndex::descriptor(),
         P2PMessageStorage::descriptor(),
         P2PMessageSecondaryIndex::descriptor(),
         Lane::descriptor(),
         ListValue::descriptor(),
         Sequences::descriptor(),
+        ProtocolStorage::descriptor(),
     ];
     let rocks_db = match open_kv(&env.storage.bootstrap_db_path, schemas) {
         Ok(db) => Arc::new(db),
@@ -327,12 +362,83 @@ fn main() {
     };
     debug!(log, "Loaded RocksDB database");
 
-    match check_database_compatibility(rocks_db.clone(), &tezos_env, log.clone()) {
+    let schemas = vec![
+        BlockStorage::descriptor(),
+        ContextActionStorage::descriptor()
+    ];
+
+    let commit_logs = match open_cl(&env.storage.bootstrap_db_path, schemas) {
+        Ok(commit_logs) => Arc::new(commit_logs),
+        Err(e) => shutdown_and_exit!(error!(log, "Failed to open commit logs"; "reason" => e), actor_system)
+    };
+    let persistent_storage = PersistentStorage::new(rocks_db.clone(), commit_logs);
+
+    // Migrate database from the older version, in dry-run mode just report the migrations and exit
+    match migration::migrations().migrate(&persistent_storage, DATABASE_VERSION, env.dry_run, &log) {
+        Ok(migrations) if env.dry_run => shutdown_and_exit!(info!(log, "Database migration dry-run finished"; "migrations" => migrations), actor_system),
+        Ok(0) => (),
+        Ok(migrations) => info!(log, "Database was migrated"; "migrations" => migrations, "db_version" => DATABASE_VERSION),
+        Err(e) => shutdown_and_exit!(error!(log, "Failed to migrate database"; "reason" => e), actor_system),
+    }
+
+    match check_database_compatibility(rocks_db, &tezos_env, log.clone()) {
         Ok(false) => shutdown_and_exit!(crit!(log, "Database incompatibility detected"), actor_system),
         Err(e) => shutdown_and_exit!(error!(log, "Failed to verify database compatibility"; "reason" => e), actor_system),
         _ => ()
     }
 
+    // Snapshot commands are run instead of the node
+    if let Some(snapshot_command) = &env.snapshot {
+        match run_snapshot_command(snapshot_command, &persistent_storage, &env.storage.context_backend.create(&persistent_storage), log.clone()) {
+            Ok(()) => shutdown_and_exit!(info!(log, "Snapshot command finished"), actor_system),
+            Err(e) => shutdown_and_exit!(error!(log, "Snapshot command failed"; "reason" => e), actor_system),
+        }
+    }
+
+    // Space of the pruned records is reclaimed from commit logs before anyone else uses the storage
+    if let HistoryMode::Rolling { .. } = env.storage.history_mode {
+        match StoragePruner::new(&persistent_storage).compact_commit_logs() {
+            Ok(true) => info!(log, "Commit logs were compacted"),
+            Ok(false) => (),
+            Err(e) => shutdown_and_exit!(error!(log, "Failed to compact commit logs"; "reason" => e), actor_system),
+        }
+    }
+
+    // Sources of the known protocols are served to the peers requesting them
+    let mut protocol_storage = ProtocolStorage::new(&persistent_storage);
+    for protocol_dir in &env.storage.protocol_sources {
+        match protocol_storage.put_sources(protocol_dir) {
+            Ok(protocol_hash) => info!(log, "Protocol sources stored"; "protocol_hash" => HashType::ProtocolHash.bytes_to_string(&protocol_hash), "dir" => protocol_dir.display().to_string()),
+            Err(e) => shutdown_and_exit!(error!(log, "Failed to store protocol sources"; "reason" => format!("{}", e), "dir" => protocol_dir.display().to_string()), actor_system),
+        }
+    }
+
+    // Loads tezos identity based on provided identity-file argument. In case it does not exist, it will try to automatically generate it
+    let tezos_identity = match ensure_identity(&env.identity, log.clone()) {
+        Ok(identity) => {
+            info!(log, "Identity loaded from file"; "file" => env.identity.identity_json_file_path.clone().into_os_string().into_string().unwrap());
+            identity
+        }
+        Err(e) => shutdown_and_exit!(error!(log, "Failed to load identity"; "reason" => e, "file" => env.identity.identity_json_file_path.into_os_string().into_string().unwrap()), actor_system),
+    };
+
+    // tezos protocol runner endpoint
+    let protocol_endpoint_configuration = ProtocolEndpointConfiguration::new(
+        TezosRuntimeConfiguration {
+            log_enabled: env.logging.ocaml_log_enabled,
+            no_of_ffi_calls_treshold_for_gc: env.no_of_ffi_calls_threshold_for_gc,
+        },
+        tezos_env.clone(),
+        env.enable_testchain,
+        &env.storage.tezos_data_dir,
+        &env.protocol_runner,
+    );
+    let protocol_runner_endpoint = ProtocolRunnerEndpoint::new(protocol_endpoint_configuration.clone());
+
+    let mut protocol_runner_process = match protocol_runner_endpoint.runner.spawn() {
+        Ok(process) => process,
+        Err(e) => shutdown_and_exit!(error!(log, "Failed to spawn protocol runner process"; "reason" => e), actor_system),
+    };
 
     let ProtocolRunnerEndpoint {
         runner: protocol_runner,
@@ -371,21 +477,8 @@ fn main() {
         });
     }
 
-    let schemas = vec![
-        BlockStorage::descriptor(),
-        ContextActionStorage::descriptor()
-    ];
-
-    {
-        let commit_logs = match open_cl(&env.storage.bootstrap_db_path, schemas) {
-            Ok(commit_logs) => Arc::new(commit_logs),
-            Err(e) => shutdown_and_exit!(error!(log, "Failed to open commit logs"; "reason" => e), actor_system)
-        };
-
-        let persistent_storage = PersistentStorage::new(rocks_db, commit_logs);
-        match resolve_storage_init_chain_data(&tezos_env, &env.storage.bootstrap_db_path, &env.storage.tezos_data_dir, log.clone()) {
-            Ok(init_data) => block_on_actors(&env, tezos_env, init_data, tezos_identity, actor_system, persistent_storage, protocol_commands, protocol_events, protocol_runner_run, log),
-            Err(e) => shutdown_and_exit!(error!(log, "Failed to resolve init storage chain data. Reason: {}", e), actor_system),
-        }
+    match resolve_storage_init_chain_data(&tezos_env, &env.storage.bootstrap_db_path, &env.storage.tezos_data_dir, log.clone()) {
+        Ok(init_data) => block_on_actors(&env, tezos_env, init_data, tezos_identity, actor_system, persistent_storage, protocol_endpoint_configuration, protocol_commands, protocol_events, protocol_runner_run, log),
+        Err(e) => shutdown_and_exit!(error!(log, "Failed to resolve init storage chain data. Reason: {}", e), actor_system),
     }
 }
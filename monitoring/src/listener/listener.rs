@@ -67,7 +67,7 @@ impl Actor for NetworkChannelListener {
             }
             NetworkChannelMsg::PeerBootstrapped(msg) => {
                 match msg {
-                    PeerBootstrapped::Success { peer, peer_id } => (EventType::PeerBootstrapped, peer.name().to_string(), peer_id.into_bytes()),
+                    PeerBootstrapped::Success { peer, peer_id, .. } => (EventType::PeerBootstrapped, peer.name().to_string(), peer_id.into_bytes()),
                     PeerBootstrapped::Failure { .. } => return,   // ignore message
                 }
 
@@ -75,6 +75,7 @@ impl Actor for NetworkChannelListener {
             NetworkChannelMsg::PeerMessageReceived(msg) => {
                 (EventType::PeerReceivedMessage, msg.peer.name().to_string(), msg.message.as_bytes().unwrap_or_default())
             }
+            NetworkChannelMsg::PeerBehaviourObserved(_) => return,   // ignore message
         };
 
         let id = self.event_index;
@@ -222,13 +222,14 @@ impl Receive<NetworkChannelMsg> for Monitor {
             }
             NetworkChannelMsg::PeerBootstrapped(msg) => {
                 match msg {
-                    PeerBootstrapped::Success { peer, peer_id } => if let Some(monitor) = self.peer_monitors.get_mut(peer.uri()) {
+                    PeerBootstrapped::Success { peer, peer_id, .. } => if let Some(monitor) = self.peer_monitors.get_mut(peer.uri()) {
                         monitor.public_key = Some(peer_id);
                     }
                     PeerBootstrapped::Failure { .. } => ()
                 }
             }
             NetworkChannelMsg::PeerMessageReceived(msg) => self.process_peer_message(msg, ctx.system.log()),
+            NetworkChannelMsg::PeerBehaviourObserved(_) => (),
         }
     }
 }
@@ -266,6 +267,9 @@ impl Receive<ShellChannelMsg> for Monitor {
                 self.chain_monitor.process_block_operations(msg.level as usize);
 
             },
+            ShellChannelMsg::HeadSwitched(_) => (),
+            ShellChannelMsg::MempoolOperationReceived(_) => (),
+            ShellChannelMsg::MempoolOperationValidated(_) => (),
             ShellChannelMsg::ShuttingDown(_) => ()
         }
     }
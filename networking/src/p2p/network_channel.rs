@@ -29,6 +29,9 @@ pub enum PeerBootstrapped {
     Success {
         peer: PeerRef,
         peer_id: String,
+        address: SocketAddr,
+        /// Port on which the peer accepts incoming connections, as announced in its connection message
+        listener_port: u16,
     },
     Failure {
         address: SocketAddr,
@@ -45,12 +48,39 @@ pub struct PeerMessageReceived {
     pub peer_address: SocketAddr
 }
 
+/// Behaviour of the peer, which affects its score
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PeerBehaviour {
+    /// Peer sent message, which could not be deserialized
+    InvalidMessage,
+    /// Peer did not respond on time
+    Timeout,
+    /// Peer refused our connection with NACK
+    Nack,
+    /// Peer failed at bootstrap process
+    BootstrapFailed,
+    /// Peer delivered requested block header
+    BlockDelivered,
+    /// Peer delivered requested operations
+    OperationsDelivered,
+    /// Peer sent block header, which failed the validation
+    InvalidBlockHeader,
+}
+
+/// Behaviour of the peer was observed
+#[derive(Clone, Debug)]
+pub struct PeerBehaviourObserved {
+    pub address: SocketAddr,
+    pub behaviour: PeerBehaviour,
+}
+
 /// Network channel event message.
 #[derive(Clone, Debug)]
 pub enum NetworkChannelMsg {
     PeerCreated(PeerCreated),
     PeerBootstrapped(PeerBootstrapped),
     PeerMessageReceived(PeerMessageReceived),
+    PeerBehaviourObserved(PeerBehaviourObserved),
 }
 
 impl From<PeerCreated> for NetworkChannelMsg {
@@ -71,6 +101,12 @@ impl From<PeerMessageReceived> for NetworkChannelMsg {
     }
 }
 
+impl From<PeerBehaviourObserved> for NetworkChannelMsg {
+    fn from(msg: PeerBehaviourObserved) -> Self {
+        NetworkChannelMsg::PeerBehaviourObserved(msg)
+    }
+}
+
 /// Represents various topics
 pub enum NetworkChannelTopic {
     /// Events generated from networking layer
@@ -17,6 +17,7 @@ use tokio::time::timeout;
 use crypto::crypto_box::precompute;
 use crypto::hash::HashType;
 use crypto::nonce::{self, Nonce, NoncePair};
+use crypto::proof_of_work::{check_proof_of_work, PowError};
 use storage::p2p_message_storage::P2PMessageStorage;
 use storage::StorageError;
 use tezos_encoding::binary_reader::BinaryReaderError;
@@ -24,7 +25,7 @@ use tezos_messages::p2p::binary_message::{BinaryChunk, BinaryChunkError, BinaryM
 use tezos_messages::p2p::encoding::ack::{NackInfo, NackMotive};
 use tezos_messages::p2p::encoding::prelude::*;
 
-use super::network_channel::{NetworkChannelRef, NetworkChannelTopic, PeerBootstrapped, PeerMessageReceived};
+use super::network_channel::{NetworkChannelRef, NetworkChannelTopic, PeerBehaviour, PeerBehaviourObserved, PeerBootstrapped, PeerMessageReceived};
 use super::stream::{EncryptedMessageReader, EncryptedMessageWriter, MessageStream, StreamError};
 
 const SUPPORTED_DISTRIBUTED_DB_VERSION: u16 = 0;
@@ -51,6 +52,10 @@ enum PeerError {
     NackWithMotiveReceived {
         nack_info: NackInfo
     },
+    #[fail(display = "Invalid proof of work of remote peer: {}", error)]
+    InvalidProofOfWork {
+        error: PowError
+    },
     #[fail(display = "Failed to create precomputed key")]
     FailedToPrecomputeKey,
     #[fail(display = "Network error: {}", message)]
@@ -152,6 +157,10 @@ impl SendMessage {
     pub fn new(msg: PeerMessageResponse) -> Self {
         SendMessage { message: Arc::new(msg) }
     }
+
+    pub fn message(&self) -> &PeerMessageResponse {
+        &self.message
+    }
 }
 
 #[derive(Clone)]
@@ -177,6 +186,8 @@ pub struct Local {
     proof_of_work_stamp: String,
     /// version of network protocol
     version: String,
+    /// minimal proof of work of the remote peer identity
+    expected_pow: f64,
 }
 
 pub type PeerRef = ActorRef<PeerMsg>;
@@ -207,6 +218,7 @@ impl Peer {
                  secret_key: &str,
                  proof_of_work_stamp: &str,
                  version: &str,
+                 expected_pow: f64,
                  tokio_executor: Handle,
                  socket_address: &SocketAddr,
                  p2p_msg_store: P2PMessageStorage) -> Result<PeerRef, CreateError>
@@ -217,6 +229,7 @@ impl Peer {
             public_key: public_key.into(),
             secret_key: secret_key.into(),
             version: version.into(),
+            expected_pow,
         };
         let props = Props::new_args(Peer::new, (network_channel, Arc::new(info), tokio_executor, *socket_address, p2p_msg_store));
         let actor_id = ACTOR_ID_GENERATOR.fetch_add(1, Ordering::SeqCst);
@@ -273,7 +286,7 @@ impl Receive<Bootstrap> for Peer {
             let peer_address = msg.address;
             debug!(system.log(), "Bootstrapping"; "ip" => &peer_address, "peer" => myself.name());
             match bootstrap(msg, info, system.log(), store.clone()).await {
-                Ok(BootstrapOutput(rx, tx, public_key)) => {
+                Ok(BootstrapOutput(rx, tx, public_key, listener_port)) => {
                     debug!(system.log(), "Bootstrap successful"; "ip" => &peer_address, "peer" => myself.name());
                     setup_net(&net, tx).await;
 
@@ -283,6 +296,8 @@ impl Receive<Bootstrap> for Peer {
                         msg: PeerBootstrapped::Success {
                             peer: myself.clone(),
                             peer_id: peer_id.clone(),
+                            address: peer_address,
+                            listener_port,
                         }.into(),
                         topic: NetworkChannelTopic::NetworkEvents.into(),
                     }, Some(myself.clone().into()));
@@ -296,6 +311,16 @@ impl Receive<Bootstrap> for Peer {
                 Err(err) => {
                     info!(system.log(), "Connection to peer failed"; "reason" => &err, "ip" => &peer_address, "peer" => myself.name());
 
+                    let behaviour = match &err {
+                        // self connection is not a fault of the peer
+                        PeerError::NackWithMotiveReceived { nack_info } if *nack_info.motive() == NackMotive::AlreadyConnected => None,
+                        PeerError::NackReceived | PeerError::NackWithMotiveReceived { .. } => Some(PeerBehaviour::Nack),
+                        _ => Some(PeerBehaviour::BootstrapFailed),
+                    };
+                    if let Some(behaviour) = behaviour {
+                        publish_behaviour(&network_channel, peer_address, behaviour, &myself);
+                    }
+
                     let potential_peers = match err {
                         PeerError::NackWithMotiveReceived { nack_info } => Some(nack_info.potential_peers_to_connect().clone()),
                         _ => None
@@ -351,7 +376,7 @@ impl Receive<SendMessage> for Peer {
 }
 
 /// Output values of the successful bootstrap process
-struct BootstrapOutput(EncryptedMessageReader, EncryptedMessageWriter, PublicKey);
+struct BootstrapOutput(EncryptedMessageReader, EncryptedMessageWriter, PublicKey, u16);
 
 async fn bootstrap(msg: Bootstrap, info: Arc<Local>, log: Logger, mut storage: P2PMessageStorage) -> Result<BootstrapOutput, PeerError> {
     let addr = msg.address;
@@ -419,6 +444,13 @@ async fn bootstrap(msg: Bootstrap, info: Arc<Local>, log: Logger, mut storage: P
         );
     }
 
+    if let Err(error) = check_proof_of_work(connection_message.public_key(), connection_message.proof_of_work_stamp(), info.expected_pow) {
+        // send nack
+        timeout(IO_TIMEOUT, msg_tx.write_message(&AckMessage::Nack(NackInfo::new(NackMotive::NoMotive, &[])))).await??;
+
+        return Err(PeerError::InvalidProofOfWork { error });
+    }
+
     let connecting_to_self = hex::encode(connection_message.public_key()) == info.public_key;
     if connecting_to_self {
         debug!(log, "Detected self connection");
@@ -445,7 +477,7 @@ async fn bootstrap(msg: Bootstrap, info: Arc<Local>, log: Logger, mut storage: P
     match ack_received {
         AckMessage::Ack => {
             debug!(log, "Received ACK");
-            Ok(BootstrapOutput(msg_rx, msg_tx, peer_public_key.clone()))
+            Ok(BootstrapOutput(msg_rx, msg_tx, peer_public_key.clone(), connection_message.port))
         }
         AckMessage::NackV0 => {
             debug!(log, "Received NACK");
@@ -493,13 +525,17 @@ async fn begin_process_incoming(mut rx: EncryptedMessageReader, net: Network, my
                     if let StreamError::DeserializationError { error: BinaryReaderError::UnsupportedTag { .. } } = e {
                         info!(log, "Messages with unsupported tags are ignored");
                     } else {
-                        warn!(log, "Failed to read peer message"; "reason" => e);
+                        warn!(log, "Failed to read peer message"; "reason" => &e);
+                        if let StreamError::DeserializationError { .. } | StreamError::MessageTooLarge { .. } = e {
+                            publish_behaviour(&event_channel, peer_address, PeerBehaviour::InvalidMessage, &myself);
+                        }
                         break;
                     }
                 }
             }
             Err(_) => {
                 warn!(log, "Peer message read timed out"; "secs" => READ_TIMEOUT_LONG.as_secs());
+                publish_behaviour(&event_channel, peer_address, PeerBehaviour::Timeout, &myself);
                 break;
             }
         }
@@ -517,3 +553,12 @@ async fn begin_process_incoming(mut rx: EncryptedMessageReader, net: Network, my
 
     info!(log, "Stopped to accept messages"; "ip" => format!("{:?}", &peer_address));
 }
+
+/// Notify others about observed behaviour of the peer
+fn publish_behaviour(event_channel: &NetworkChannelRef, address: SocketAddr, behaviour: PeerBehaviour, myself: &PeerRef) {
+    event_channel.tell(
+        Publish {
+            msg: PeerBehaviourObserved { address, behaviour }.into(),
+            topic: NetworkChannelTopic::NetworkEvents.into(),
+        }, Some(myself.clone().into()));
+}
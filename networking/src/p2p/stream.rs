@@ -18,8 +18,9 @@ use tokio::prelude::*;
 
 use crypto::crypto_box::{CryptoError, decrypt, encrypt, PrecomputedKey};
 use crypto::nonce::Nonce;
-use tezos_encoding::binary_reader::BinaryReaderError;
-use tezos_messages::p2p::binary_message::{BinaryChunk, BinaryChunkError, BinaryMessage, CONTENT_LENGTH_FIELD_BYTES};
+use tezos_encoding::binary_reader::{BinaryReaderError, StreamReader};
+use tezos_encoding::encoding::HasEncoding;
+use tezos_messages::p2p::binary_message::{BinaryChunk, BinaryChunkError, BinaryMessage, CONTENT_LENGTH_FIELD_BYTES, HasSizeLimit};
 
 use crate::p2p::peer::PeerId;
 
@@ -45,6 +46,12 @@ pub enum StreamError {
     DeserializationError {
         error: BinaryReaderError
     },
+    #[fail(display = "Message of {} bytes exceeds the limit of {} bytes, tag: {:?}", size, max_size, tag)]
+    MessageTooLarge {
+        size: usize,
+        max_size: usize,
+        tag: Option<u16>,
+    },
     #[fail(display = "Network error: {}, cause: {}", message, error)]
     NetworkError {
         message: &'static str,
@@ -72,7 +79,10 @@ impl From<BinaryChunkError> for StreamError {
 
 impl From<BinaryReaderError> for StreamError {
     fn from(error: BinaryReaderError) -> Self {
-        StreamError::DeserializationError { error }
+        match error {
+            BinaryReaderError::MessageTooLarge { size, max_size, tag } => StreamError::MessageTooLarge { size, max_size, tag },
+            error => StreamError::DeserializationError { error },
+        }
     }
 }
 
@@ -233,12 +243,14 @@ impl EncryptedMessageReader {
     }
 
     /// Consume content of inner message reader into specific message
+    ///
+    /// Decrypted chunks are fed into the [StreamReader], so the message is decoded only once all its bytes
+    /// are received and peer cannot make us buffer more than the size limit of the message.
     pub async fn read_message<M>(&mut self) -> Result<M, StreamError>
         where
-            M: BinaryMessage
+            M: BinaryMessage + HasEncoding + HasSizeLimit
     {
-        let mut input_remaining = 0;
-        let mut input_data = vec![];
+        let mut stream_reader = StreamReader::new(M::encoding(), M::size_limit());
 
         loop {
             // read
@@ -246,22 +258,10 @@ impl EncryptedMessageReader {
 
             // decrypt
             match decrypt(message_encrypted.content(), &self.nonce_fetch_increment(), &self.precomputed_key) {
-                Ok(mut message_decrypted) => {
+                Ok(message_decrypted) => {
                     trace!(self.log, "Message received"; "message" => FnValue(|_| hex::encode(&message_decrypted)));
-                    if input_remaining >= message_decrypted.len() {
-                        input_remaining -= message_decrypted.len();
-                    } else {
-                        input_remaining = 0;
-                    }
-
-                    input_data.append(&mut message_decrypted);
-
-                    if input_remaining == 0 {
-                        match M::from_bytes(input_data.clone()) {
-                            Ok(message) => break Ok(message),
-                            Err(BinaryReaderError::Underflow { bytes }) => input_remaining += bytes,
-                            Err(e) => break Err(e.into()),
-                        }
+                    if let Some(input_data) = stream_reader.feed(&message_decrypted)? {
+                        break Ok(M::from_bytes(input_data)?);
                     }
                 }
                 Err(error) => {
@@ -88,9 +88,9 @@ fn main() {
 
 mod tezos {
     use crypto::hash::{ChainId, ContextHash, ProtocolHash};
-    use tezos_api::ffi::{ApplyBlockError, ApplyBlockResult, CommitGenesisResult, GenesisChain, GetDataError, InitProtocolContextResult, ProtocolOverrides, TezosGenerateIdentityError, TezosRuntimeConfiguration, TezosRuntimeConfigurationError, TezosStorageInitError};
+    use tezos_api::ffi::{ApplyBlockError, ApplyBlockResult, CommitGenesisResult, GenesisChain, GetDataError, InitProtocolContextResult, ProtocolOverrides, TezosGenerateIdentityError, TezosRuntimeConfiguration, TezosRuntimeConfigurationError, TezosStorageInitError, ValidateOperationError, ValidateOperationResult};
     use tezos_api::identity::Identity;
-    use tezos_client::client::{apply_block, change_runtime_configuration, generate_identity, genesis_result_data, init_protocol_context};
+    use tezos_client::client::{apply_block, change_runtime_configuration, generate_identity, genesis_result_data, init_protocol_context, validate_operation};
     use tezos_messages::p2p::encoding::prelude::*;
     use tezos_wrapper::protocol::ProtocolApi;
 
@@ -101,6 +101,10 @@ mod tezos {
             apply_block(chain_id, block_header, predecessor_block_header, operations, max_operations_ttl)
         }
 
+        fn validate_operation(chain_id: &ChainId, block_header: &BlockHeader, operation: &Operation) -> Result<ValidateOperationResult, ValidateOperationError> {
+            validate_operation(chain_id, block_header, operation)
+        }
+
         fn change_runtime_configuration(settings: TezosRuntimeConfiguration) -> Result<(), TezosRuntimeConfigurationError> {
             change_runtime_configuration(settings)
         }
@@ -4,7 +4,12 @@
 use std::collections::HashMap;
 
 use serde::Serialize;
-use serde_json::Value;
+use serde_json::{Map, Value};
+
+use crypto::hash::HashType;
+use crypto::signature::signature_to_b58;
+use tezos_messages::p2p::binary_message::MessageHash;
+use tezos_messages::p2p::encoding::prelude::Operation;
 
 use crate::helpers::FullBlockInfo;
 
@@ -46,4 +51,64 @@ impl From<FullBlockInfo> for BlockInfo {
             metadata: val.metadata,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Size of the signature at the end of the operation data
+const SIGNATURE_SIZE: usize = 64;
+
+// { protocol: ProtocolHash,
+//   chain_id: ChainHash,
+//   hash: OperationHash,
+//   branch: BlockHash,
+//   contents: Vec<OperationContentsAndResult>,
+//   signature: Signature }
+#[derive(Serialize, Debug, Clone)]
+pub struct BlockOperation {
+    protocol: Value,
+    chain_id: UniString,
+    hash: UniString,
+    branch: UniString,
+    contents: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<UniString>,
+}
+
+impl BlockOperation {
+    /// Combine operation with its metadata (protocol and contents with results) produced by the protocol
+    pub fn new(operation: &Operation, metadata: &Map<String, Value>, chain_id: &str) -> Result<Self, failure::Error> {
+        let signature = match metadata.get("signature").and_then(Value::as_str) {
+            Some(signature) => Some(signature.to_string()),
+            None if operation.data().len() >= SIGNATURE_SIZE => Some(signature_to_b58(&operation.data()[operation.data().len() - SIGNATURE_SIZE..])),
+            None => None,
+        };
+
+        Ok(Self {
+            protocol: metadata.get("protocol").cloned().unwrap_or(Value::Null),
+            chain_id: chain_id.into(),
+            hash: HashType::OperationHash.bytes_to_string(&operation.message_hash()?).into(),
+            branch: HashType::BlockHash.bytes_to_string(operation.branch()).into(),
+            contents: metadata.get("contents").cloned().unwrap_or_else(|| Value::Array(vec![])),
+            signature: signature.map(UniString::from),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tezos_messages::p2p::binary_message::BinaryMessage;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_block_operation() -> Result<(), failure::Error> {
+        let operation = Operation::from_bytes(hex::decode("10490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e000008c387fa065a181d45d47a9b78ddc77e92a881779ff2cbabbf9646eade4bf1405a08e00b725ed849eea46953b10b5cdebc518e6fd47e69b82d2ca18c4cf6d2f312dd08")?)?;
+        let metadata: Map<String, Value> = serde_json::from_str(r#"{"protocol":"PsCARTHAGazKbHtnKfLzQg3kms52kSRpgnDY982a9oYsSXRLQEb","contents":[{"kind":"endorsement","level":574343,"metadata":{"balance_updates":[],"delegate":"tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx","slots":[1]}}]}"#)?;
+
+        assert_eq!(
+            r#"{"protocol":"PsCARTHAGazKbHtnKfLzQg3kms52kSRpgnDY982a9oYsSXRLQEb","chain_id":"NetXdQprcVkpaWU","hash":"onzNq7mGpf9bXYYVyWq1fyesTpdEZFQCTtD3aZDFSXHbhehhc3B","branch":"BKqTKfGwK3zHnVXX33X5PPHy1FDTnbkajj3eFtCXGFyfimQhT1H","contents":[{"kind":"endorsement","level":574343,"metadata":{"balance_updates":[],"delegate":"tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx","slots":[1]}}],"signature":"sigvhVipdz2GQs6kNM1BxCjfzkwe4KdySgkEvBaUQSPChz38AZAJzK6ta73tmge5XmzG1rCKfev6a7hD6f2rsur1rb3r1ofB"}"#,
+            serde_json::to_string(&BlockOperation::new(&operation, &metadata, "NetXdQprcVkpaWU")?)?
+        );
+
+        Ok(())
+    }
+}
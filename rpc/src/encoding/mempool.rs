@@ -0,0 +1,166 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crypto::hash::{HashType, OperationHash};
+use shell::mempool::{MempoolOperation, MempoolState};
+use tezos_api::ffi::{OperationClassification, ValidateOperationResult};
+use tezos_messages::p2p::encoding::prelude::*;
+
+// GET /chains/:chain_id/mempool/pending_operations
+
+/// Operations of the mempool grouped by the result of their validation
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PendingOperations {
+    pub applied: Vec<Value>,
+    pub refused: Vec<Value>,
+    pub branch_refused: Vec<Value>,
+    pub branch_delayed: Vec<Value>,
+    pub unprocessed: Vec<Value>,
+}
+
+impl From<&MempoolState> for PendingOperations {
+    fn from(state: &MempoolState) -> Self {
+        PendingOperations {
+            applied: state.applied().iter()
+                .map(|(operation_hash, mempool_operation)| validated_operation_json(operation_hash, mempool_operation.operation(), mempool_operation.result(), true))
+                .collect(),
+            refused: with_errors(state.refused()),
+            branch_refused: with_errors(state.branch_refused()),
+            branch_delayed: with_errors(state.branch_delayed()),
+            unprocessed: state.unprocessed().iter()
+                .map(|(operation_hash, operation)| {
+                    let hash = HashType::OperationHash.bytes_to_string(operation_hash);
+                    Value::Array(vec![Value::String(hash), unprocessed_operation_json(operation)])
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Not applied operations are encoded as `[hash, operation]` pairs
+fn with_errors(operations: &HashMap<OperationHash, MempoolOperation>) -> Vec<Value> {
+    operations.iter()
+        .map(|(operation_hash, mempool_operation)| {
+            let hash = HashType::OperationHash.bytes_to_string(operation_hash);
+            Value::Array(vec![Value::String(hash), validated_operation_json(operation_hash, mempool_operation.operation(), mempool_operation.result(), false)])
+        })
+        .collect()
+}
+
+// GET /chains/:chain_id/mempool/monitor_operations
+
+/// Classifications of the operations, which should be streamed to the client
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitoredOperations {
+    pub applied: bool,
+    pub refused: bool,
+    pub branch_refused: bool,
+    pub branch_delayed: bool,
+}
+
+impl MonitoredOperations {
+    /// Returns `true` if operations with provided classification should be streamed
+    pub fn accepts(&self, classification: &OperationClassification) -> bool {
+        match classification {
+            OperationClassification::Applied => self.applied,
+            OperationClassification::Refused => self.refused,
+            OperationClassification::BranchRefused => self.branch_refused,
+            OperationClassification::BranchDelayed => self.branch_delayed,
+        }
+    }
+}
+
+impl Default for MonitoredOperations {
+    fn default() -> Self {
+        MonitoredOperations {
+            applied: true,
+            refused: false,
+            branch_refused: false,
+            branch_delayed: false,
+        }
+    }
+}
+
+/// Converts validated operation to the JSON object used by mempool RPCs.
+///
+/// Object contains operation hash (only if `with_hash` is set), branch, decoded protocol data
+/// and the error reported by protocol for not applied operations.
+pub fn validated_operation_json(operation_hash: &OperationHash, operation: &Operation, result: &ValidateOperationResult, with_hash: bool) -> Value {
+    let mut json = Map::new();
+    if with_hash {
+        json.insert("hash".to_string(), Value::String(HashType::OperationHash.bytes_to_string(operation_hash)));
+    }
+    json.insert("branch".to_string(), Value::String(HashType::BlockHash.bytes_to_string(operation.branch())));
+
+    match serde_json::from_str::<Value>(&result.protocol_data_json) {
+        Ok(Value::Object(protocol_data)) => json.extend(protocol_data),
+        // protocol was not able to decode operation, so we return just raw data
+        _ => {
+            json.insert("data".to_string(), Value::String(hex::encode(operation.data())));
+        }
+    }
+
+    if !result.error_json.is_empty() {
+        let error = serde_json::from_str::<Value>(&result.error_json)
+            .unwrap_or_else(|_| Value::String(result.error_json.clone()));
+        json.insert("error".to_string(), error);
+    }
+
+    Value::Object(json)
+}
+
+/// Converts operation, which was not validated yet, to the JSON object used by mempool RPCs
+pub fn unprocessed_operation_json(operation: &Operation) -> Value {
+    let mut json = Map::new();
+    json.insert("branch".to_string(), Value::String(HashType::BlockHash.bytes_to_string(operation.branch())));
+    json.insert("data".to_string(), Value::String(hex::encode(operation.data())));
+    Value::Object(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use tezos_messages::p2p::binary_message::BinaryMessage;
+
+    use super::*;
+
+    fn operation() -> Operation {
+        Operation::from_bytes(hex::decode("10490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e000008c387fa065a181d45d47a9b78ddc77e92a881779ff2cbabbf9646eade4bf1405a08e00b725ed849eea46953b10b5cdebc518e6fd47e69b82d2ca18c4cf6d2f312dd08").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn encoded_applied_operation() {
+        let operation_hash = vec![1; HashType::OperationHash.size()];
+        let result = ValidateOperationResult {
+            classification: OperationClassification::Applied,
+            protocol_data_json: "{\"contents\":[],\"signature\":\"sig\"}".to_string(),
+            error_json: String::new(),
+        };
+
+        let json = validated_operation_json(&operation_hash, &operation(), &result, true);
+        assert_eq!(json["hash"], Value::String(HashType::OperationHash.bytes_to_string(&operation_hash)));
+        assert_eq!(json["branch"], Value::String(HashType::BlockHash.bytes_to_string(operation().branch())));
+        assert_eq!(json["signature"], Value::String("sig".to_string()));
+        assert!(json.get("data").is_none());
+        assert!(json.get("error").is_none());
+    }
+
+    #[test]
+    fn encoded_refused_operation() {
+        let operation_hash = vec![1; HashType::OperationHash.size()];
+        let result = ValidateOperationResult {
+            classification: OperationClassification::Refused,
+            protocol_data_json: String::new(),
+            error_json: "[{\"kind\":\"permanent\"}]".to_string(),
+        };
+
+        let json = validated_operation_json(&operation_hash, &operation(), &result, false);
+        assert!(json.get("hash").is_none());
+        assert_eq!(json["data"], Value::String(hex::encode(operation().data())));
+        assert_eq!(json["error"][0]["kind"], Value::String("permanent".to_string()));
+    }
+}
@@ -1,6 +1,8 @@
 pub mod base_types;
 pub mod monitor;
 pub mod chain;
+pub mod mempool;
+pub mod network;
 
 #[cfg(test)]
 pub mod test_helpers {
@@ -0,0 +1,96 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use serde::Serialize;
+
+use shell::peer_manager::PeersState;
+
+// GET /network/peers
+
+/// Connected peer, encoded together with its id as `[peer_id, info]` pair
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PeerInfo {
+    pub state: &'static str,
+    pub score: i32,
+    pub reachable_at: String,
+}
+
+impl PeerInfo {
+    pub fn list(state: &PeersState) -> Vec<(String, PeerInfo)> {
+        state.connected().iter()
+            .map(|(address, peer_id)| (peer_id.clone(), PeerInfo {
+                state: "running",
+                score: state.score(&address.ip()),
+                reachable_at: address.to_string(),
+            }))
+            .collect()
+    }
+}
+
+// GET /network/points
+
+/// Known point (connected or banned address), encoded as `[address, info]` pair
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PointInfo {
+    pub state: &'static str,
+    pub score: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p2p_peer_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub banned_until: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ban_reason: Option<String>,
+}
+
+impl PointInfo {
+    pub fn list(state: &PeersState) -> Vec<(String, PointInfo)> {
+        let connected = state.connected().iter()
+            .map(|(address, peer_id)| (address.to_string(), PointInfo {
+                state: "running",
+                score: state.score(&address.ip()),
+                p2p_peer_id: Some(peer_id.clone()),
+                banned_until: None,
+                ban_reason: None,
+            }));
+        let banned = state.banned().iter()
+            .map(|(address, ban)| (address.to_string(), PointInfo {
+                state: "banned",
+                score: state.score(address),
+                p2p_peer_id: None,
+                banned_until: Some(ban.banned_until()),
+                ban_reason: Some(ban.reason().clone()),
+            }));
+
+        connected.chain(banned).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use networking::p2p::network_channel::PeerBehaviour;
+    use shell::peer_manager::PeerScoring;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_peers_and_points() -> Result<(), serde_json::Error> {
+        let mut state = PeersState::new(PeerScoring::default());
+        let address: SocketAddr = "192.168.1.10:9732".parse().unwrap();
+        state.peer_connected(address, "idtQk6ZbCGJNCj5t2UhF3cdsbRvRXi".to_string());
+        state.update_score(address.ip(), PeerBehaviour::BlockDelivered);
+        state.ban("192.168.1.11".parse().unwrap(), 1_000, "Invalid messages".to_string());
+
+        assert_eq!(
+            r#"[["idtQk6ZbCGJNCj5t2UhF3cdsbRvRXi",{"state":"running","score":1,"reachable_at":"192.168.1.10:9732"}]]"#,
+            serde_json::to_string(&PeerInfo::list(&state))?
+        );
+        assert_eq!(
+            r#"[["192.168.1.10:9732",{"state":"running","score":1,"p2p_peer_id":"idtQk6ZbCGJNCj5t2UhF3cdsbRvRXi"}],["192.168.1.11",{"state":"banned","score":0,"banned_until":2800,"ban_reason":"Invalid messages"}]]"#,
+            serde_json::to_string(&PointInfo::list(&state))?
+        );
+
+        Ok(())
+    }
+}
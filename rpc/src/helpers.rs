@@ -8,28 +8,20 @@ use failure::bail;
 use serde::Serialize;
 use serde_json::Value;
 
-use crypto::hash::{BlockHash, HashType, ProtocolHash};
+use crypto::hash::{BlockHash, HashType};
+use shell::protocol::{ContextProtocolParam, get_context_index_by_level};
 use shell::shell_channel::BlockApplied;
-use storage::{BlockMetaStorage, BlockStorage, BlockStorageReader};
+use storage::{BlockMetaStorage, BlockStorage, BlockStorageReader, SystemStorage};
+use storage::context::ContextIndex;
 use storage::persistent::PersistentStorage;
+use storage::pruning::ensure_not_pruned;
 use storage::skip_list::Bucket;
 use tezos_messages::p2p::encoding::prelude::*;
 use tezos_messages::ts_to_rfc3339;
 
-use crate::ContextList;
+use crate::ContextApiRef;
 use crate::rpc_actor::RpcCollectedStateRef;
 
-#[macro_export]
-macro_rules! merge_slices {
-    ( $($x:expr),* ) => {{
-        let mut res = vec![];
-        $(
-            res.extend_from_slice($x);
-        )*
-        res
-    }}
-}
-
 /// Object containing information to recreate the full block information
 #[derive(Serialize, Debug, Clone)]
 pub struct FullBlockInfo {
@@ -290,26 +282,6 @@ pub(crate) fn get_block_hash_by_block_id(block_id: &str, persistent_storage: &Pe
     Ok(block_hash)
 }
 
-/// Return block timestamp in epoch time format by block level
-/// 
-/// # Arguments
-/// 
-/// * `level` - Level of block.
-/// * `state` - Current RPC state (head).
-pub(crate) fn get_block_timestamp_by_level(level: i32, persistent_storage: &PersistentStorage) -> Result<i64, failure::Error> {
-    let block_storage = BlockStorage::new(persistent_storage);
-    match block_storage.get_by_block_level(level)? {
-        Some(current_head) => Ok(current_head.header.timestamp()),
-        None => bail!("Block not found in db by level {}", level)
-    }
-}
-
-pub(crate) struct ContextProtocolParam {
-    pub protocol_hash: ProtocolHash,
-    pub constants_data: Vec<u8>,
-    pub level: usize,
-}
-
 /// Get protocol and context constants as bytes from context list for desired block or level
 ///
 /// # Arguments
@@ -322,33 +294,36 @@ pub(crate) struct ContextProtocolParam {
 pub(crate) fn get_context_protocol_params(
     block_id: &str,
     opt_level: Option<i64>,
-    list: ContextList,
+    list: ContextApiRef,
     persistent_storage: &PersistentStorage,
     state: &RpcCollectedStateRef) -> Result<ContextProtocolParam, failure::Error> {
 
     // first check if level is already known
-    let level: usize = if let Some(l) = opt_level {
-        l.try_into()?
+    let block_storage = BlockStorage::new(persistent_storage);
+    let block = if let Some(l) = opt_level {
+        block_storage.get_by_block_level(l.try_into()?)?
     } else {
-        // get level level by block_id
-        if let Some(l) = get_level_by_block_id(block_id, persistent_storage, state)? {
-            l
-        } else {
-            bail!("Level not found for block_id {}", block_id)
-        }
+        block_storage.get(&get_block_hash_by_block_id(block_id, persistent_storage, state)?)?
+    };
+    let block = match block {
+        Some(block) => block,
+        None => bail!("Block not found for block_id {}", block_id)
     };
+    let level: usize = block.header.level().try_into()?;
+    let context_hash = block.header.context().clone();
 
     let protocol_hash: Vec<u8>;
     let constants: Vec<u8>;
     {
+        let context_index = ContextIndex::new(Some(context_hash.clone()));
         let reader = list.read().unwrap();
-        if let Some(Bucket::Exists(data)) = reader.get_key(level, &"protocol".to_string())? {
+        if let Some(Bucket::Exists(data)) = reader.get_key(&context_index, &vec!["protocol".to_string()])? {
             protocol_hash = data;
         } else {
             panic!(format!("Protocol not found in context for block: {}, level: {}", block_id, level));
         }
 
-        if let Some(Bucket::Exists(data)) = reader.get_key(level, &"data/v1/constants".to_string())? {
+        if let Some(Bucket::Exists(data)) = reader.get_key(&context_index, &vec!["data".to_string(), "v1".to_string(), "constants".to_string()])? {
             constants = data;
         } else {
             panic!("Protocol constants not found in context for block: {}, level: {}, protocol_hash: {}", block_id, level, HashType::ProtocolHash.bytes_to_string(&protocol_hash));
@@ -358,14 +333,24 @@ pub(crate) fn get_context_protocol_params(
     Ok(ContextProtocolParam {
         protocol_hash,
         constants_data: constants,
-        level: level.try_into()?,
+        level,
+        context_hash,
     })
 }
 
-pub(crate) fn get_context(level: &str, list: ContextList) -> Result<Option<HashMap<String, Bucket<Vec<u8>>>>, failure::Error> {
-    let level = level.parse()?;
+pub(crate) fn get_context(level: &str, context: ContextApiRef, persistent_storage: &PersistentStorage) -> Result<Option<HashMap<String, Bucket<Vec<u8>>>>, failure::Error> {
+    let context_index = get_context_index_by_level(level.parse()?, persistent_storage)?;
     {
-        let storage = list.read().expect("poisoned storage lock");
-        storage.get(level).map_err(|e| e.into())
+        let context = context.read().expect("poisoned storage lock");
+        context.get_context(&context_index).map_err(|e| e.into())
     }
-}
\ No newline at end of file
+}
+
+/// Returns error, if data of the block were already pruned in the rolling history mode
+pub(crate) fn ensure_block_not_pruned(block_hash: &BlockHash, persistent_storage: &PersistentStorage) -> Result<(), failure::Error> {
+    let block_meta_storage = BlockMetaStorage::new(persistent_storage);
+    if let Some(meta) = block_meta_storage.get(block_hash)? {
+        ensure_not_pruned(&SystemStorage::new(persistent_storage.kv()), meta.level())?;
+    }
+    Ok(())
+}
@@ -5,7 +5,9 @@ use hyper::{Body, Response, StatusCode};
 use slog::{Logger, warn};
 
 use crypto::hash::HashType;
-pub use storage::persistent::{ContextList, ContextMap};
+pub use storage::context::ContextApiRef;
+pub use storage::persistent::ContextMap;
+use storage::StorageError;
 
 use crate::rpc_actor::RpcCollectedStateRef;
 
@@ -28,14 +30,28 @@ pub(crate) fn make_json_response<T: serde::Serialize>(content: &T) -> ServiceRes
         .body(Body::from(serde_json::to_string(content)?))?)
 }
 
+/// Function to generate JSON response with internal server error status, e.g. when operation was not accepted
+pub(crate) fn make_json_error_response<T: serde::Serialize>(content: &T) -> ServiceResult {
+    Ok(Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .header(hyper::header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(Body::from(serde_json::to_string(content)?))?)
+}
+
+/// Function to generate streamed JSON response, body is sent in chunks through the channel sender
+pub(crate) fn make_json_stream_response(body: Body) -> ServiceResult {
+    Ok(Response::builder()
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .header(hyper::header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(body)?)
+}
+
 /// Returns result as a JSON response.
 pub(crate) fn result_to_json_response<T: serde::Serialize>(res: Result<T, failure::Error>, log: &Logger) -> ServiceResult {
     match res {
         Ok(t) => make_json_response(&t),
-        Err(err) => {
-            warn!(log, "Failed to execute RPC function"; "reason" => format!("{:?}", err));
-            empty()
-        }
+        Err(err) => error_to_response(err, log)
     }
 }
 
@@ -46,7 +62,15 @@ pub(crate) fn result_option_to_json_response<T: serde::Serialize>(res: Result<Op
             Some(t) => make_json_response(&t),
             None => not_found()
         }
-        Err(err) => {
+        Err(err) => error_to_response(err, log)
+    }
+}
+
+/// Returns response for the failed RPC function, data of pruned blocks are reported as an error
+fn error_to_response(err: failure::Error, log: &Logger) -> ServiceResult {
+    match err.downcast_ref::<StorageError>() {
+        Some(StorageError::PrunedError { level, pruned_level }) => pruned(*level, *pruned_level),
+        _ => {
             warn!(log, "Failed to execute RPC function"; "reason" => format!("{:?}", err));
             empty()
         }
@@ -76,4 +100,27 @@ pub(crate) fn not_found() -> ServiceResult {
     Ok(Response::builder()
         .status(StatusCode::from_u16(404)?)
         .body(Body::from("not found"))?)
-}
\ No newline at end of file
+}
+
+/// Generate 400 response, e.g. when url parameter cannot be parsed
+pub(crate) fn bad_request(reason: String) -> ServiceResult {
+    Ok(Response::builder()
+        .status(StatusCode::from_u16(400)?)
+        .body(Body::from(reason))?)
+}
+
+/// Generate 404 response for the block, whose data were pruned
+pub(crate) fn pruned(level: i32, pruned_level: i32) -> ServiceResult {
+    let error = serde_json::json!([{
+        "kind": "permanent",
+        "id": "pruned_block",
+        "level": level,
+        "pruned_level": pruned_level,
+        "msg": format!("Data of the block at level {} were pruned, only blocks above level {} are available", level, pruned_level),
+    }]);
+    Ok(Response::builder()
+        .status(StatusCode::from_u16(404)?)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .header(hyper::header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(Body::from(serde_json::to_string(&error)?))?)
+}
@@ -4,15 +4,20 @@
 use std::net::SocketAddr;
 use std::sync::{Arc, RwLock};
 
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
 use getset::Getters;
 use riker::actors::*;
 use slog::{Logger, warn};
 use tokio::runtime::Handle;
 
 use crypto::hash::ChainId;
-use shell::shell_channel::{BlockApplied, ShellChannelMsg, ShellChannelRef, ShellChannelTopic};
+use shell::mempool::MempoolStateRef;
+use shell::peer_manager::PeersStateRef;
+use shell::shell_channel::{BlockApplied, MempoolOperationValidated, ShellChannelMsg, ShellChannelRef, ShellChannelTopic};
+use storage::context::ContextApiRef;
 use storage::persistent::PersistentStorage;
 use storage::StorageInitInfo;
+use tezos_messages::base::fitness_comparator::fitness_increases;
 
 use crate::server::{RpcServiceEnvironment, spawn_server};
 
@@ -29,6 +34,26 @@ pub struct RpcCollectedState {
     current_head: Option<BlockApplied>,
     #[get = "pub(crate)"]
     chain_id: ChainId,
+    /// Streaming RPC requests waiting for new heads
+    head_listeners: Vec<UnboundedSender<BlockApplied>>,
+    /// Streaming RPC requests waiting for validated mempool operations
+    mempool_operation_listeners: Vec<UnboundedSender<MempoolOperationValidated>>,
+}
+
+impl RpcCollectedState {
+    /// Register new listener, which will receive all new heads from now on
+    pub(crate) fn subscribe_heads(&mut self) -> UnboundedReceiver<BlockApplied> {
+        let (tx, rx) = unbounded();
+        self.head_listeners.push(tx);
+        rx
+    }
+
+    /// Register new listener, which will receive all mempool operations validated from now on
+    pub(crate) fn subscribe_mempool_operations(&mut self) -> UnboundedReceiver<MempoolOperationValidated> {
+        let (tx, rx) = unbounded();
+        self.mempool_operation_listeners.push(tx);
+        rx
+    }
 }
 
 /// Actor responsible for managing HTTP REST API and server, and to share parts of inner actor
@@ -52,21 +77,26 @@ impl RpcServer {
         rpc_listen_address: SocketAddr,
         tokio_executor: &Handle,
         persistent_storage: &PersistentStorage,
+        context: ContextApiRef,
+        mempool_state: Option<MempoolStateRef>,
+        peers_state: PeersStateRef,
         init_storage_data: &StorageInitInfo) -> Result<RpcServerRef, CreateError> {
 
         // TODO: refactor - call load_current_head in pre_start
         let shared_state = Arc::new(RwLock::new(RpcCollectedState {
             current_head: load_current_head(persistent_storage, sys.log()),
             chain_id: init_storage_data.chain_id.clone(),
+            head_listeners: Vec::new(),
+            mempool_operation_listeners: Vec::new(),
         }));
         let actor_ref = sys.actor_of(
-            Props::new_args(Self::new, (shell_channel, shared_state.clone())),
+            Props::new_args(Self::new, (shell_channel.clone(), shared_state.clone())),
             Self::name(),
         )?;
 
         // spawn RPC JSON server
         {
-            let env = RpcServiceEnvironment::new(sys.clone(), actor_ref.clone(), persistent_storage, &init_storage_data.genesis_block_header_hash, shared_state, sys.log());
+            let env = RpcServiceEnvironment::new(sys.clone(), actor_ref.clone(), shell_channel, persistent_storage, context, mempool_state, peers_state, &init_storage_data.genesis_block_header_hash, shared_state, sys.log());
             let inner_log = sys.log();
 
             tokio_executor.spawn(async move {
@@ -101,22 +131,29 @@ impl Receive<ShellChannelMsg> for RpcServer {
     fn receive(&mut self, _ctx: &Context<Self::Msg>, msg: ShellChannelMsg, _sender: Sender) {
         match msg {
             ShellChannelMsg::BlockApplied(block) => {
-                let current_head_ref = &mut *self.state.write().unwrap();
-                match &mut current_head_ref.current_head {
-                    Some(current_head) => {
-                        if current_head.header().header.level() < block.header().header.level() {
-                            *current_head = block;
-                        }
-                    }
-                    None => current_head_ref.current_head = Some(block)
+                let state = &mut *self.state.write().unwrap();
+                // applied block can be also a part of the side branch, so we move current head only if fitness increases
+                let is_new_head = match &state.current_head {
+                    Some(current_head) => fitness_increases(current_head.header().header.fitness(), block.header().header.fitness()),
+                    None => true
+                };
+                if is_new_head {
+                    // forward new head to all streaming requests, closed requests are removed
+                    state.head_listeners.retain(|listener| listener.unbounded_send(block.clone()).is_ok());
+                    state.current_head = Some(block);
                 }
             }
+            ShellChannelMsg::MempoolOperationValidated(operation) => {
+                // forward operation to all streaming requests, closed requests are removed
+                let state = &mut *self.state.write().unwrap();
+                state.mempool_operation_listeners.retain(|listener| listener.unbounded_send(operation.clone()).is_ok());
+            }
             _ => (/* Not yet implemented, do nothing */),
         }
     }
 }
 
-/// Load local head (block with highest level) from dedicated storage
+/// Load local head (applied block with the highest fitness) from dedicated storage
 fn load_current_head(persistent_storage: &PersistentStorage, log: Logger) -> Option<BlockApplied> {
     use storage::{BlockStorage, BlockStorageReader, BlockMetaStorage, BlockMetaStorageReader, StorageError};
 
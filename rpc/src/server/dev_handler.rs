@@ -4,7 +4,7 @@
 use hyper::{Body, Request};
 use slog::warn;
 
-use crate::{empty, make_json_response, result_to_json_response, ServiceResult, unwrap_block_hash};
+use crate::{empty, make_json_response, result_option_to_json_response, result_to_json_response, ServiceResult, unwrap_block_hash};
 use crate::server::{HasSingleValue, Params, Query, RpcServiceEnvironment, service, service_stats};
 
 pub async fn dev_blocks(_: Request<Body>, _: Params, query: Query, env: RpcServiceEnvironment) -> ServiceResult {
@@ -33,7 +33,7 @@ pub async fn dev_contract_actions(_: Request<Body>, params: Params, query: Query
 pub async fn dev_context(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
     // TODO: Add parameter checks
     let context_level = params.get_str("id").unwrap();
-    result_to_json_response(service::get_context(context_level, env.persistent_storage().context_storage()), env.log())
+    result_to_json_response(service::get_context(context_level, env.context().clone(), env.persistent_storage()), env.log())
 }
 
 #[allow(dead_code)]
@@ -70,3 +70,12 @@ pub async fn  p2p_host_messages(_: Request<Body>, params: Params, _: Query, env:
 
     result_to_json_response(service::retrieve_host_p2p_messages(start, end, host, env.persistent_storage()), env.log())
 }
+
+pub async fn describe_encodings(_: Request<Body>, _: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    result_to_json_response(service::get_described_encodings(), env.log())
+}
+
+pub async fn describe_encoding(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    let name = params.get_str("name").unwrap();
+    result_option_to_json_response(service::describe_encoding(name), env.log())
+}
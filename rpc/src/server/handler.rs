@@ -2,24 +2,33 @@
 // SPDX-License-Identifier: MIT
 
 use chrono::prelude::*;
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::StreamExt;
 use hyper::{Body, Request};
-use slog::warn;
+use serde::Serialize;
+use slog::{debug, Logger, warn};
 
-use crypto::hash::HashType;
+use crypto::hash::{chain_id_to_b58_string, HashType};
 use shell::shell_channel::BlockApplied;
 use tezos_messages::ts_to_rfc3339;
 
 use crate::{
+    bad_request,
     empty,
     encoding::{
         base_types::*,
+        mempool::{MonitoredOperations, validated_operation_json},
         monitor::BootstrapInfo
     },
+    helpers::BlockHeaderInfo,
+    make_json_error_response,
     make_json_response,
+    make_json_stream_response,
     result_option_to_json_response,
     result_to_json_response,
     ServiceResult,
-    services
+    services,
+    services::protocol::{ContractInfo, DelegateInfo},
 };
 use crate::server::{HasSingleValue, HResult, Params, Query, RpcServiceEnvironment};
 use crate::server::service;
@@ -31,19 +40,20 @@ fn timestamp() -> TimeStamp {
 }
 
 pub async fn bootstrapped(_: Request<Body>, _: Params, _: Query, env: RpcServiceEnvironment) -> HResult {
-    let state_read = env.state().read().unwrap();
-
-    let bootstrap_info = match state_read.current_head().as_ref() {
-        Some(current_head) => {
-            let current_head: BlockApplied = current_head.clone();
-            let block = HashType::BlockHash.bytes_to_string(&current_head.header().hash);
-            let timestamp = ts_to_rfc3339(current_head.header().header.timestamp());
-            BootstrapInfo::new(block.into(), TimeStamp::Rfc(timestamp))
+    let (current_head, heads) = {
+        let mut state = env.state().write().unwrap();
+        match state.current_head().clone() {
+            Some(current_head) => (current_head, state.subscribe_heads()),
+            // node does not have any head yet, so there is nothing to monitor
+            None => return make_json_response(&BootstrapInfo::new(String::new().into(), TimeStamp::Integral(0))),
         }
-        None => BootstrapInfo::new(String::new().into(), TimeStamp::Integral(0))
     };
 
-    make_json_response(&bootstrap_info)
+    make_json_stream_response(stream_heads(Some(current_head), heads, vec![], env.log().clone(), |head| {
+        let block = HashType::BlockHash.bytes_to_string(&head.header().hash);
+        let timestamp = ts_to_rfc3339(head.header().header.timestamp());
+        BootstrapInfo::new(block.into(), TimeStamp::Rfc(timestamp))
+    }))
 }
 
 pub async fn commit_hash(_: Request<Body>, _: Params, _: Query, _: RpcServiceEnvironment) -> HResult {
@@ -63,17 +73,19 @@ pub async fn valid_blocks(_: Request<Body>, _: Params, _: Query, _: RpcServiceEn
     empty()
 }
 
-pub async fn head_chain(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+pub async fn head_chain(_: Request<Body>, params: Params, query: Query, env: RpcServiceEnvironment) -> ServiceResult {
     let chain_id = params.get_str("chain_id").unwrap();
 
     if chain_id == "main" {
-        let current_head = service::get_full_current_head(env.state());
-        if let Ok(Some(_current_head)) = current_head {
-            // TODO: implement
-            empty()
-        } else {
-            empty()
-        }
+        let next_protocols = query.get("next_protocol").cloned().unwrap_or_default();
+        let (chain_id, current_head, heads) = {
+            let mut state = env.state().write().unwrap();
+            // genesis block does not contain protocol data, so it cannot be streamed as a head
+            let current_head = state.current_head().clone().filter(|head| head.header().header.level() > 0);
+            (chain_id_to_b58_string(state.chain_id()), current_head, state.subscribe_heads())
+        };
+
+        make_json_stream_response(stream_heads(current_head, heads, next_protocols, env.log().clone(), move |head| BlockHeaderInfo::new(head, &chain_id)))
     } else {
         empty()
     }
@@ -110,27 +122,69 @@ pub async fn chains_block_id_header(_: Request<Body>, params: Params, _: Query,
     }
 }
 
+pub async fn chains_block_id_operations(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    let block_id = params.get_str("block_id").unwrap();
+
+    result_option_to_json_response(service::get_block_operations(block_id, env.persistent_storage(), env.state()), env.log())
+}
+
+pub async fn chains_block_id_operations_list(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    let block_id = params.get_str("block_id").unwrap();
+    let list_offset: usize = match params.get_str("list_offset").unwrap().parse() {
+        Ok(list_offset) => list_offset,
+        Err(e) => return bad_request(format!("Invalid list_offset: {}", e)),
+    };
+
+    let operations_list = service::get_block_operations(block_id, env.persistent_storage(), env.state())
+        .map(|operations| operations.and_then(|operations| operations.into_iter().nth(list_offset)));
+    result_option_to_json_response(operations_list, env.log())
+}
+
+pub async fn chains_block_id_operations_list_operation(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    let block_id = params.get_str("block_id").unwrap();
+    let list_offset: usize = match params.get_str("list_offset").unwrap().parse() {
+        Ok(list_offset) => list_offset,
+        Err(e) => return bad_request(format!("Invalid list_offset: {}", e)),
+    };
+    let operation_offset: usize = match params.get_str("operation_offset").unwrap().parse() {
+        Ok(operation_offset) => operation_offset,
+        Err(e) => return bad_request(format!("Invalid operation_offset: {}", e)),
+    };
+
+    let operation = service::get_block_operations(block_id, env.persistent_storage(), env.state())
+        .map(|operations| operations
+            .and_then(|operations| operations.into_iter().nth(list_offset))
+            .and_then(|operations| operations.into_iter().nth(operation_offset)));
+    result_option_to_json_response(operation, env.log())
+}
+
+pub async fn chains_block_id_operation_hashes(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    let block_id = params.get_str("block_id").unwrap();
+
+    result_option_to_json_response(service::get_block_operation_hashes(block_id, env.persistent_storage(), env.state()), env.log())
+}
+
 pub async fn context_constants(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
     let block_id = params.get_str("block_id").unwrap();
 
-    result_to_json_response(service::get_context_constants_just_for_rpc(block_id, None, env.persistent_storage().context_storage(), env.persistent_storage(), env.state()), env.log())
+    result_to_json_response(service::get_context_constants_just_for_rpc(block_id, None, env.context().clone(), env.persistent_storage(), env.state()), env.log())
 }
 
 pub async fn context_cycle(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
     let block_id = params.get_str("block_id").unwrap();
 
-    result_to_json_response(service::get_cycle_from_context(block_id, env.persistent_storage().context_storage()), env.log())
+    result_to_json_response(service::get_cycle_from_context(block_id, env.context().clone(), env.persistent_storage()), env.log())
 }
 
 pub async fn rolls_owner_current(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
     let block_id = params.get_str("block_id").unwrap();
-    result_to_json_response(service::get_rolls_owner_current_from_context(block_id, env.persistent_storage().context_storage()), env.log())
+    result_to_json_response(service::get_rolls_owner_current_from_context(block_id, env.context().clone(), env.persistent_storage()), env.log())
 }
 
 pub async fn cycle(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
     let block_id = params.get_str("block_id").unwrap();
     let cycle_id = params.get_str("cycle_id").unwrap();
-    result_to_json_response(service::get_cycle_from_context_as_json(block_id, cycle_id, env.persistent_storage().context_storage()), env.log())
+    result_to_json_response(service::get_cycle_from_context_as_json(block_id, cycle_id, env.context().clone(), env.persistent_storage()), env.log())
 }
 
 pub async fn baking_rights(_: Request<Body>, params: Params, query: Query, env: RpcServiceEnvironment) -> ServiceResult {
@@ -143,7 +197,7 @@ pub async fn baking_rights(_: Request<Body>, params: Params, query: Query, env:
     let has_all = query.contains_key("all");
 
     // list -> context, persistent, state odizolovat
-    match services::protocol::check_and_get_baking_rights(chain_id, block_id, level, delegate, cycle, max_priority, has_all, env.persistent_storage().context_storage(), env.persistent_storage(), env.state()) {
+    match services::protocol::check_and_get_baking_rights(chain_id, block_id, level, delegate, cycle, max_priority, has_all, env.context().clone(), env.persistent_storage(), env.state()) {
         Ok(Some(rights)) => result_to_json_response(Ok(Some(rights)), env.log()),
         Err(e) => { //pass error to response parser
             let res: Result<Option<String>, failure::Error> = Err(e);
@@ -166,7 +220,7 @@ pub async fn endorsing_rights(_: Request<Body>, params: Params, query: Query, en
     let has_all = query.contains_key("all");
 
     // get RPC response and unpack it from RpcResponseData enum
-    match services::protocol::check_and_get_endorsing_rights(chain_id, block_id, level, delegate, cycle, has_all, env.persistent_storage().context_storage(), env.persistent_storage(), env.state()) {
+    match services::protocol::check_and_get_endorsing_rights(chain_id, block_id, level, delegate, cycle, has_all, env.context().clone(), env.persistent_storage(), env.state()) {
         Ok(Some(rights)) => result_to_json_response(Ok(Some(rights)), env.log()),
         Err(e) => { //pass error to response parser
             let res: Result<Option<String>, failure::Error> = Err(e);
@@ -184,5 +238,257 @@ pub async fn votes_listings(_: Request<Body>, params: Params, _: Query, env: Rpc
     let chain_id = params.get_str("chain_id").unwrap();
     let block_id = params.get_str("block_id").unwrap();
 
-    result_to_json_response(services::protocol::get_votes_listings(chain_id, block_id, env.persistent_storage(), env.persistent_storage().context_storage(), env.state()), env.log())
+    result_to_json_response(services::protocol::get_votes_listings(chain_id, block_id, env.persistent_storage(), env.context().clone(), env.state()), env.log())
+}
+
+pub async fn context_contracts(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    let block_id = params.get_str("block_id").unwrap();
+
+    result_option_to_json_response(services::protocol::get_contracts(block_id, env.context().clone(), env.persistent_storage(), env.state()), env.log())
+}
+
+pub async fn context_contract(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    contract_field(&params, &env, Some)
+}
+
+pub async fn context_contract_balance(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    contract_field(&params, &env, |contract| Some(contract.balance().clone()))
+}
+
+pub async fn context_contract_delegate(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    contract_field(&params, &env, |contract| contract.delegate().clone())
+}
+
+pub async fn context_contract_counter(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    contract_field(&params, &env, |contract| contract.counter().clone())
+}
+
+pub async fn context_contract_manager_key(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    // public key which is not revealed yet is reported as null
+    contract_field(&params, &env, |contract| Some(contract.manager_key().clone()))
+}
+
+pub async fn context_contract_script(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    contract_field(&params, &env, |contract| contract.script().clone())
+}
+
+pub async fn context_contract_storage(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    contract_field(&params, &env, |contract| contract.script().as_ref().map(|script| script.storage().clone()))
+}
+
+pub async fn context_delegates(_: Request<Body>, params: Params, query: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    let block_id = params.get_str("block_id").unwrap();
+    let active = query_flag(&query, "active", false);
+    let inactive = query_flag(&query, "inactive", false);
+
+    result_option_to_json_response(services::protocol::get_delegates(block_id, active, inactive, env.context().clone(), env.persistent_storage(), env.state()), env.log())
+}
+
+pub async fn context_delegate(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    delegate_field(&params, &env, |delegate| delegate)
+}
+
+pub async fn context_delegate_balance(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    delegate_field(&params, &env, |delegate| delegate.balance().clone())
+}
+
+pub async fn context_delegate_frozen_balance(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    delegate_field(&params, &env, |delegate| delegate.frozen_balance().clone())
+}
+
+pub async fn context_delegate_frozen_balance_by_cycle(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    delegate_field(&params, &env, |delegate| delegate.frozen_balance_by_cycle().clone())
+}
+
+pub async fn context_delegate_staking_balance(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    delegate_field(&params, &env, |delegate| delegate.staking_balance().clone())
+}
+
+pub async fn context_delegate_delegated_contracts(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    delegate_field(&params, &env, |delegate| delegate.delegated_contracts().clone())
+}
+
+pub async fn context_delegate_delegated_balance(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    delegate_field(&params, &env, |delegate| delegate.delegated_balance().clone())
+}
+
+pub async fn context_delegate_deactivated(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    delegate_field(&params, &env, |delegate| *delegate.deactivated())
+}
+
+pub async fn context_delegate_grace_period(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    delegate_field(&params, &env, |delegate| *delegate.grace_period())
+}
+
+pub async fn inject_operation(req: Request<Body>, _: Params, query: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let operation_data: String = serde_json::from_slice(&body)?;
+    let is_async = query.contains_key("async");
+    let mempool_state = match env.mempool_state() {
+        Some(mempool_state) => mempool_state.clone(),
+        None => return make_json_error_response(&format!("{}", service::InjectionError::MempoolDisabled)),
+    };
+
+    let operation_hash = match service::inject_operation(&operation_data, env.shell_channel(), &mempool_state) {
+        Ok(operation_hash) => operation_hash,
+        Err(e) => {
+            warn!(env.log(), "Failed to inject operation"; "reason" => format!("{}", e));
+            return make_json_error_response(&format!("{}", e));
+        }
+    };
+
+    if is_async {
+        return make_json_response(&HashType::OperationHash.bytes_to_string(&operation_hash));
+    }
+
+    match service::wait_for_operation_validation(operation_hash, mempool_state).await {
+        Ok(operation_hash) => make_json_response(&operation_hash),
+        Err(e) => {
+            warn!(env.log(), "Injected operation was not accepted"; "reason" => format!("{}", e));
+            match e.downcast::<service::InjectionError>() {
+                Ok(service::InjectionError::OperationNotApplied { error_json, .. }) => {
+                    // pass errors reported by protocol to the client
+                    let error = serde_json::from_str::<serde_json::Value>(&error_json)
+                        .unwrap_or_else(|_| serde_json::Value::String(error_json.clone()));
+                    make_json_error_response(&error)
+                }
+                Ok(e) => make_json_error_response(&format!("{}", e)),
+                Err(e) => make_json_error_response(&format!("{}", e)),
+            }
+        }
+    }
+}
+
+pub async fn mempool_pending_operations(_: Request<Body>, params: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    let chain_id = params.get_str("chain_id").unwrap();
+
+    if chain_id == "main" {
+        result_to_json_response(service::get_pending_operations(env.mempool_state()), env.log())
+    } else {
+        empty()
+    }
+}
+
+pub async fn network_peers(_: Request<Body>, _: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    result_to_json_response(service::get_network_peers(env.peers_state()), env.log())
+}
+
+pub async fn network_points(_: Request<Body>, _: Params, _: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    result_to_json_response(service::get_network_points(env.peers_state()), env.log())
+}
+
+pub async fn mempool_monitor_operations(_: Request<Body>, params: Params, query: Query, env: RpcServiceEnvironment) -> ServiceResult {
+    let chain_id = params.get_str("chain_id").unwrap();
+
+    if chain_id == "main" {
+        let defaults = MonitoredOperations::default();
+        let monitored = MonitoredOperations {
+            applied: query_flag(&query, "applied", defaults.applied),
+            refused: query_flag(&query, "refused", defaults.refused),
+            branch_refused: query_flag(&query, "branch_refused", defaults.branch_refused),
+            branch_delayed: query_flag(&query, "branch_delayed", defaults.branch_delayed),
+        };
+
+        // subscribe before reading the mempool, so no validated operation is missed
+        let mut validated_operations = env.state().write().unwrap().subscribe_mempool_operations();
+        let applied: Vec<serde_json::Value> = match env.mempool_state() {
+            Some(mempool_state) if monitored.applied => mempool_state.read().unwrap().applied().iter()
+                .map(|(operation_hash, operation)| validated_operation_json(operation_hash, operation.operation(), operation.result(), true))
+                .collect(),
+            _ => vec![],
+        };
+
+        let (mut sender, body) = Body::channel();
+        let log = env.log().clone();
+        tokio::spawn(async move {
+            if !applied.is_empty() {
+                if sender.send_data(json_chunk(&applied).unwrap_or_default().into()).await.is_err() {
+                    return;
+                }
+            }
+
+            while let Some(operation) = validated_operations.next().await {
+                if monitored.accepts(&operation.result().classification) {
+                    let chunk = vec![validated_operation_json(operation.operation_hash(), operation.operation(), operation.result(), true)];
+                    if sender.send_data(json_chunk(&chunk).unwrap_or_default().into()).await.is_err() {
+                        debug!(log, "Mempool operations monitor was closed by client");
+                        break;
+                    }
+                }
+            }
+        });
+
+        make_json_stream_response(body)
+    } else {
+        empty()
+    }
+}
+
+/// Stream current head and all following heads as JSON objects until client disconnects.
+///
+/// If `next_protocols` is not empty, only heads with one of the provided next protocols are streamed.
+fn stream_heads<F, T>(current_head: Option<BlockApplied>, heads: UnboundedReceiver<BlockApplied>, next_protocols: Vec<String>, log: Logger, to_json: F) -> Body
+    where
+        F: Fn(&BlockApplied) -> T + Send + 'static,
+        T: Serialize
+{
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        let mut heads = futures::stream::iter(current_head).chain(heads);
+        while let Some(head) = heads.next().await {
+            if !next_protocols.is_empty() && !next_protocol(&head).map_or(false, |protocol| next_protocols.contains(&protocol)) {
+                continue;
+            }
+
+            let chunk = match json_chunk(&to_json(&head)) {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    warn!(log, "Failed to serialize head"; "reason" => format!("{}", e));
+                    continue;
+                }
+            };
+            if sender.send_data(chunk.into()).await.is_err() {
+                debug!(log, "Heads monitor was closed by client");
+                break;
+            }
+        }
+    });
+    body
+}
+
+/// Streamed JSON objects are delimited by a new line, so clients can split them
+fn json_chunk<T: Serialize>(value: &T) -> Result<String, serde_json::Error> {
+    serde_json::to_string(value).map(|mut chunk| {
+        chunk.push('\n');
+        chunk
+    })
+}
+
+/// Next protocol of the block taken from the block metadata
+fn next_protocol(block: &BlockApplied) -> Option<String> {
+    let metadata: serde_json::Value = serde_json::from_str(block.json_data().block_header_proto_metadata_json()).ok()?;
+    metadata.get("next_protocol").and_then(|protocol| protocol.as_str()).map(|protocol| protocol.to_string())
+}
+
+/// Flag is set if it is present in query without a value or with any value except `false`
+fn query_flag(query: &Query, key: &str, default: bool) -> bool {
+    query.get_str(key).map(|value| value != "false").unwrap_or(default)
+}
+
+/// Respond with the part of the contract selected by the `field`, missing contract or missing part is reported as not found
+fn contract_field<T: Serialize>(params: &Params, env: &RpcServiceEnvironment, field: impl FnOnce(ContractInfo) -> Option<T>) -> ServiceResult {
+    let block_id = params.get_str("block_id").unwrap();
+    let contract_id = params.get_str("contract_id").unwrap();
+
+    let contract = services::protocol::get_contract(block_id, contract_id, env.context().clone(), env.persistent_storage(), env.state());
+    result_option_to_json_response(contract.map(|contract| contract.and_then(field)), env.log())
+}
+
+/// Respond with the part of the delegate selected by the `field`, public key hash which is not a delegate is reported as not found
+fn delegate_field<T: Serialize>(params: &Params, env: &RpcServiceEnvironment, field: impl FnOnce(DelegateInfo) -> T) -> ServiceResult {
+    let block_id = params.get_str("block_id").unwrap();
+    let pkh = params.get_str("pkh").unwrap();
+
+    let delegate = services::protocol::get_delegate(block_id, pkh, env.context().clone(), env.persistent_storage(), env.state());
+    result_option_to_json_response(delegate.map(|delegate| delegate.map(field)), env.log())
 }
@@ -14,6 +14,10 @@ use riker::actors::ActorSystem;
 use slog::Logger;
 
 use crypto::hash::{BlockHash, HashType};
+use shell::mempool::MempoolStateRef;
+use shell::peer_manager::PeersStateRef;
+use shell::shell_channel::ShellChannelRef;
+use storage::context::ContextApiRef;
 use storage::persistent::PersistentStorage;
 
 use crate::empty;
@@ -33,8 +37,17 @@ pub struct RpcServiceEnvironment {
     #[get = "pub(crate)"]
     actor: RpcServerRef,
     #[get = "pub(crate)"]
+    shell_channel: ShellChannelRef,
+    #[get = "pub(crate)"]
     persistent_storage: PersistentStorage,
     #[get = "pub(crate)"]
+    context: ContextApiRef,
+    /// Mempool is not available, when it is disabled
+    #[get = "pub(crate)"]
+    mempool_state: Option<MempoolStateRef>,
+    #[get = "pub(crate)"]
+    peers_state: PeersStateRef,
+    #[get = "pub(crate)"]
     genesis_hash: String,
     #[get = "pub(crate)"]
     state: RpcCollectedStateRef,
@@ -43,8 +56,8 @@ pub struct RpcServiceEnvironment {
 }
 
 impl RpcServiceEnvironment {
-    pub fn new(sys: ActorSystem, actor: RpcServerRef, persistent_storage: &PersistentStorage, genesis_hash: &BlockHash, state: RpcCollectedStateRef, log: Logger) -> Self {
-        Self { sys, actor, persistent_storage: persistent_storage.clone(), genesis_hash: HashType::BlockHash.bytes_to_string(genesis_hash), state, log }
+    pub fn new(sys: ActorSystem, actor: RpcServerRef, shell_channel: ShellChannelRef, persistent_storage: &PersistentStorage, context: ContextApiRef, mempool_state: Option<MempoolStateRef>, peers_state: PeersStateRef, genesis_hash: &BlockHash, state: RpcCollectedStateRef, log: Logger) -> Self {
+        Self { sys, actor, shell_channel, persistent_storage: persistent_storage.clone(), context, mempool_state, peers_state, genesis_hash: HashType::BlockHash.bytes_to_string(genesis_hash), state, log }
     }
 }
 
@@ -22,6 +22,10 @@ pub(crate) fn create_routes() -> PathTree<Handler> {
     routes.handle("/monitor/heads/:chain_id", handler::head_chain);
     routes.handle("/chains/:chain_id/blocks/:block_id", handler::chains_block_id);
     routes.handle("/chains/:chain_id/blocks/:block_id/header", handler::chains_block_id_header);
+    routes.handle("/chains/:chain_id/blocks/:block_id/operations", handler::chains_block_id_operations);
+    routes.handle("/chains/:chain_id/blocks/:block_id/operations/:list_offset", handler::chains_block_id_operations_list);
+    routes.handle("/chains/:chain_id/blocks/:block_id/operations/:list_offset/:operation_offset", handler::chains_block_id_operations_list_operation);
+    routes.handle("/chains/:chain_id/blocks/:block_id/operation_hashes", handler::chains_block_id_operation_hashes);
     routes.handle("/chains/:chain_id/blocks/:block_id/context/constants", handler::context_constants);
     routes.handle("/chains/:chain_id/blocks/:block_id/context/raw/bytes/cycle", handler::context_cycle);
     routes.handle("/chains/:chain_id/blocks/:block_id/context/raw/bytes/rolls/owner/current", handler::rolls_owner_current);
@@ -29,6 +33,29 @@ pub(crate) fn create_routes() -> PathTree<Handler> {
     routes.handle("/chains/:chain_id/blocks/:block_id/helpers/baking_rights", handler::baking_rights);
     routes.handle("/chains/:chain_id/blocks/:block_id/helpers/endorsing_rights", handler::endorsing_rights);
     routes.handle("/chains/:chain_id/blocks/:block_id/votes/listings", handler::votes_listings);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/contracts", handler::context_contracts);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/contracts/:contract_id", handler::context_contract);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/contracts/:contract_id/balance", handler::context_contract_balance);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/contracts/:contract_id/delegate", handler::context_contract_delegate);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/contracts/:contract_id/counter", handler::context_contract_counter);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/contracts/:contract_id/manager_key", handler::context_contract_manager_key);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/contracts/:contract_id/script", handler::context_contract_script);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/contracts/:contract_id/storage", handler::context_contract_storage);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/delegates", handler::context_delegates);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/delegates/:pkh", handler::context_delegate);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/delegates/:pkh/balance", handler::context_delegate_balance);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/delegates/:pkh/frozen_balance", handler::context_delegate_frozen_balance);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/delegates/:pkh/frozen_balance_by_cycle", handler::context_delegate_frozen_balance_by_cycle);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/delegates/:pkh/staking_balance", handler::context_delegate_staking_balance);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/delegates/:pkh/delegated_contracts", handler::context_delegate_delegated_contracts);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/delegates/:pkh/delegated_balance", handler::context_delegate_delegated_balance);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/delegates/:pkh/deactivated", handler::context_delegate_deactivated);
+    routes.handle("/chains/:chain_id/blocks/:block_id/context/delegates/:pkh/grace_period", handler::context_delegate_grace_period);
+    routes.handle("/chains/:chain_id/mempool/pending_operations", handler::mempool_pending_operations);
+    routes.handle("/chains/:chain_id/mempool/monitor_operations", handler::mempool_monitor_operations);
+    routes.handle("/injection/operation", handler::inject_operation);
+    routes.handle("/network/peers", handler::network_peers);
+    routes.handle("/network/points", handler::network_points);
 
     // Tezedge dev and support rpc
     routes.handle("/dev/chains/main/blocks", dev_handler::dev_blocks);
@@ -38,6 +65,8 @@ pub(crate) fn create_routes() -> PathTree<Handler> {
     routes.handle("/stats/memory", dev_handler::dev_stats_memory);
     routes.handle("/p2p/:offset/:count", dev_handler::p2p_messages);
     routes.handle("/p2p/:offset/:count/:host", dev_handler::p2p_host_messages);
+    routes.handle("/describe", dev_handler::describe_encodings);
+    routes.handle("/describe/:name", dev_handler::describe_encoding);
     //routes.handle("/stats/storage", dev_handler::dev_stats_storage);
 
     routes
@@ -2,27 +2,65 @@
 // SPDX-License-Identifier: MIT
 
 use std::collections::{HashMap};
+use std::time::Duration;
 
-use failure::bail;
+use failure::{bail, format_err, Fail};
+use riker::actors::*;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-use crypto::hash::{chain_id_to_b58_string};
-use shell::shell_channel::BlockApplied;
+use crypto::hash::{chain_id_to_b58_string, HashType, OperationHash};
+use shell::mempool::{self, MempoolStateRef};
+use shell::peer_manager::PeersStateRef;
+use shell::protocol::get_context_index_by_level;
+use shell::shell_channel::{BlockApplied, ShellChannelRef};
 use shell::stats::memory::{Memory, MemoryData, MemoryStatsResult};
-use storage::{BlockHeaderWithHash, BlockStorage, BlockStorageReader, ContextActionRecordValue, ContextActionStorage};
+use storage::{BlockHeaderWithHash, BlockStorage, BlockStorageReader, ContextActionRecordValue, ContextActionStorage, OperationsStorage, OperationsStorageReader, SystemStorage};
 use storage::block_storage::BlockJsonData;
 use storage::p2p_message_storage::P2PMessageStorage;
 use storage::p2p_message_storage::rpc_message::P2PRpcMessage;
 use storage::persistent::PersistentStorage;
+use storage::pruning::ensure_not_pruned;
 use storage::skip_list::Bucket;
+use tezos_api::ffi::OperationClassification;
 use tezos_context::channel::ContextAction;
+use tezos_encoding::describe::{describe_binary, describe_json};
+use tezos_messages::p2p::binary_message::{BinaryMessage, MessageHash};
+use tezos_messages::p2p::encoding::prelude::OperationMessage;
 use tezos_messages::protocol::RpcJsonMap;
+use tezos_messages::schema;
 
-use crate::ContextList;
-use crate::helpers::{BlockHeaderInfo, FullBlockInfo, get_block_hash_by_block_id, get_context_protocol_params, PagedResult};
+use crate::ContextApiRef;
+use crate::encoding::chain::BlockOperation;
+use crate::encoding::mempool::PendingOperations;
+use crate::encoding::network::{PeerInfo, PointInfo};
+use crate::helpers::{BlockHeaderInfo, ensure_block_not_pruned, FullBlockInfo, get_block_hash_by_block_id, get_context_protocol_params, PagedResult};
 use crate::rpc_actor::RpcCollectedStateRef;
 use storage::context_action_storage::contract_id_to_contract_address_for_index;
 
+/// How long to wait for the validation of injected operation
+const INJECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Possible reasons why injected operation was not accepted
+#[derive(Debug, Fail)]
+pub enum InjectionError {
+    #[fail(display = "Mempool is disabled, operations cannot be injected")]
+    MempoolDisabled,
+    #[fail(display = "Operation {} was not applied by protocol", operation_hash)]
+    OperationNotApplied {
+        operation_hash: String,
+        error_json: String,
+    },
+    #[fail(display = "Operation {} was not validated in time", operation_hash)]
+    ValidationTimeout {
+        operation_hash: String,
+    },
+    #[fail(display = "Validation of operation {} was canceled", operation_hash)]
+    ValidationCanceled {
+        operation_hash: String,
+    },
+}
+
 // Serialize, Deserialize,
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Cycle
@@ -58,6 +96,7 @@ pub(crate) fn get_blocks(every_nth_level: Option<i32>, block_id: &str, limit: us
 pub(crate) fn get_block_actions(block_id: &str, persistent_storage: &PersistentStorage, state: &RpcCollectedStateRef) -> Result<Vec<ContextAction>, failure::Error> {
     let context_action_storage = ContextActionStorage::new(persistent_storage);
     let block_hash = get_block_hash_by_block_id(block_id, persistent_storage, state)?;
+    ensure_block_not_pruned(&block_hash, persistent_storage)?;
     get_block_actions_by_hash(&context_action_storage, &block_hash)
 }
 
@@ -107,10 +146,12 @@ pub(crate) fn get_full_block(block_id: &str, persistent_storage: &PersistentStor
     // TODO: rework block_id to accept types String and integer for block levels
     match block_id.parse() {
         Ok(val) => {
+            ensure_not_pruned(&SystemStorage::new(persistent_storage.kv()), val)?;
             block = block_storage.get_by_block_level_with_json_data(val)?.map(|(header, json_data)| map_header_and_json_to_full_block_info(header, json_data, &state));
         }
         Err(_e) => {
             let block_hash = get_block_hash_by_block_id(block_id, persistent_storage, state)?;
+            ensure_block_not_pruned(&block_hash, persistent_storage)?;
             block = block_storage.get_with_json_data(&block_hash)?.map(|(header, json_data)| map_header_and_json_to_full_block_info(header, json_data, &state));
         }
     }
@@ -121,11 +162,66 @@ pub(crate) fn get_full_block(block_id: &str, persistent_storage: &PersistentStor
 pub(crate) fn get_block_header(block_id: &str, persistent_storage: &PersistentStorage, state: &RpcCollectedStateRef) -> Result<Option<BlockHeaderInfo>, failure::Error> {
     let block_storage = BlockStorage::new(persistent_storage);
     let block_hash = get_block_hash_by_block_id(block_id, persistent_storage, state)?;
+    ensure_block_not_pruned(&block_hash, persistent_storage)?;
     let block = block_storage.get_with_json_data(&block_hash)?.map(|(header, json_data)| map_header_and_json_to_block_header_info(header, json_data, state));
 
     Ok(block)
 }
 
+/// Get operations of the block grouped by validation passes, operations are combined with their metadata
+pub(crate) fn get_block_operations(block_id: &str, persistent_storage: &PersistentStorage, state: &RpcCollectedStateRef) -> Result<Option<Vec<Vec<BlockOperation>>>, failure::Error> {
+    let block_hash = get_block_hash_by_block_id(block_id, persistent_storage, state)?;
+    ensure_block_not_pruned(&block_hash, persistent_storage)?;
+    let (header, json_data) = match BlockStorage::new(persistent_storage).get_with_json_data(&block_hash)? {
+        Some(block) => block,
+        None => return Ok(None),
+    };
+    let metadata: Vec<Vec<Map<String, Value>>> = serde_json::from_str(json_data.operations_proto_metadata_json())?;
+    let chain_id = chain_id_to_b58_string(state.read().unwrap().chain_id());
+
+    // every validation pass has its own list, even if there are no operations in it
+    let mut operations = vec![Vec::new(); header.header.validation_pass() as usize];
+    for message in OperationsStorage::new(persistent_storage).get_operations(&block_hash)? {
+        let validation_pass = message.operations_for_block().validation_pass() as usize;
+        let validation_pass_operations = operations.get_mut(validation_pass)
+            .ok_or_else(|| format_err!("Invalid validation pass {} of operations of block {}", validation_pass, block_id))?;
+        *validation_pass_operations = message.operations().iter()
+            .enumerate()
+            .map(|(operation_offset, operation)| {
+                let operation_metadata = metadata.get(validation_pass)
+                    .and_then(|operations_metadata| operations_metadata.get(operation_offset))
+                    .ok_or_else(|| format_err!("Missing metadata of operation {}/{} of block {}", validation_pass, operation_offset, block_id))?;
+                BlockOperation::new(operation, operation_metadata, &chain_id)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+    }
+
+    Ok(Some(operations))
+}
+
+/// Get hashes of the block operations grouped by validation passes
+pub(crate) fn get_block_operation_hashes(block_id: &str, persistent_storage: &PersistentStorage, state: &RpcCollectedStateRef) -> Result<Option<Vec<Vec<String>>>, failure::Error> {
+    let block_hash = get_block_hash_by_block_id(block_id, persistent_storage, state)?;
+    ensure_block_not_pruned(&block_hash, persistent_storage)?;
+    let header = match BlockStorage::new(persistent_storage).get(&block_hash)? {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+
+    // every validation pass has its own list, even if there are no operations in it
+    let mut operation_hashes = vec![Vec::new(); header.header.validation_pass() as usize];
+    for message in OperationsStorage::new(persistent_storage).get_operations(&block_hash)? {
+        let validation_pass = message.operations_for_block().validation_pass() as usize;
+        let validation_pass_hashes = operation_hashes.get_mut(validation_pass)
+            .ok_or_else(|| format_err!("Invalid validation pass {} of operations of block {}", validation_pass, block_id))?;
+        *validation_pass_hashes = message.operations().iter()
+            .map(|operation| operation.message_hash().map(|hash| HashType::OperationHash.bytes_to_string(&hash)))
+            .collect::<Result<Vec<_>, _>>()?;
+    }
+
+    Ok(Some(operation_hashes))
+}
+
 /// Get protocol context constants from context list
 /// (just for RPC render use-case, do not use in processing or algorithms)
 ///
@@ -139,7 +235,7 @@ pub(crate) fn get_block_header(block_id: &str, persistent_storage: &PersistentSt
 pub(crate) fn get_context_constants_just_for_rpc(
     block_id: &str,
     opt_level: Option<i64>,
-    list: ContextList,
+    list: ContextApiRef,
     persistent_storage: &PersistentStorage,
     state: &RpcCollectedStateRef) -> Result<Option<RpcJsonMap>, failure::Error> {
     let context_proto_params = get_context_protocol_params(
@@ -153,12 +249,12 @@ pub(crate) fn get_context_constants_just_for_rpc(
     Ok(tezos_messages::protocol::get_constants_for_rpc(&context_proto_params.constants_data, context_proto_params.protocol_hash)?)
 }
 
-pub(crate) fn get_cycle_from_context(level: &str, list: ContextList) -> Result<Option<HashMap<String, Cycle>>, failure::Error> {
-    let ctxt_level: usize = level.parse().unwrap();
+pub(crate) fn get_cycle_from_context(level: &str, list: ContextApiRef, persistent_storage: &PersistentStorage) -> Result<Option<HashMap<String, Cycle>>, failure::Error> {
+    let context_index = get_context_index_by_level(level.parse()?, persistent_storage)?;
 
     let context_data = {
         let reader = list.read().expect("mutex poisoning");
-        if let Ok(Some(c)) = reader.get(ctxt_level) {
+        if let Ok(Some(c)) = reader.get_context(&context_index) {
             c
         } else {
             bail!("Context data not found")
@@ -243,12 +339,11 @@ pub(crate) fn get_cycle_from_context(level: &str, list: ContextList) -> Result<O
     Ok(Some(cycles))
 }
 
-pub(crate) fn get_cycle_from_context_as_json(level: &str, cycle_id: &str, list: ContextList) -> Result<Option<CycleJson>, failure::Error> {
-    let level: usize = level.parse()?;
-
+pub(crate) fn get_cycle_from_context_as_json(level: &str, cycle_id: &str, list: ContextApiRef, persistent_storage: &PersistentStorage) -> Result<Option<CycleJson>, failure::Error> {
+    let context_index = get_context_index_by_level(level.parse()?, persistent_storage)?;
     let list = list.read().expect("mutex poisoning");
-    let random_seed = list.get_key(level, &format!("data/cycle/{}/random_seed", &cycle_id));
-    let roll_snapshot = list.get_key(level, &format!("data/cycle/{}/roll_snapshot", &cycle_id));
+    let random_seed = list.get_key(&context_index, &vec!["data".to_string(), "cycle".to_string(), cycle_id.to_string(), "random_seed".to_string()]);
+    let roll_snapshot = list.get_key(&context_index, &vec!["data".to_string(), "cycle".to_string(), cycle_id.to_string(), "roll_snapshot".to_string()]);
     match (random_seed, roll_snapshot) {
         (Ok(Some(random_seed)), Ok(Some(roll_snapshot))) => {
             let cycle_json = CycleJson {
@@ -261,13 +356,12 @@ pub(crate) fn get_cycle_from_context_as_json(level: &str, cycle_id: &str, list:
     }
 }
 
-pub(crate) fn get_rolls_owner_current_from_context(level: &str, list: ContextList) -> Result<Option<HashMap<String, HashMap<String, HashMap<String, String>>>>, failure::Error> {
-    let ctxt_level: usize = level.parse().unwrap();
-    // println!("level: {:?}", ctxt_level);
+pub(crate) fn get_rolls_owner_current_from_context(level: &str, list: ContextApiRef, persistent_storage: &PersistentStorage) -> Result<Option<HashMap<String, HashMap<String, HashMap<String, String>>>>, failure::Error> {
+    let context_index = get_context_index_by_level(level.parse()?, persistent_storage)?;
 
     let context_data = {
         let reader = list.read().expect("mutex poisoning");
-        if let Ok(Some(c)) = reader.get(ctxt_level) {
+        if let Ok(Some(c)) = reader.get_context(&context_index) {
             c
         } else {
             bail!("Context data not found")
@@ -358,8 +452,82 @@ pub(crate) fn get_stats_memory() -> MemoryStatsResult<MemoryData> {
     memory.get_memory_stats()
 }
 
-pub(crate) fn get_context(level: &str, list: ContextList) -> Result<Option<HashMap<String, Bucket<Vec<u8>>>>, failure::Error> {
-    crate::helpers::get_context(level, list)
+pub(crate) fn get_context(level: &str, list: ContextApiRef, persistent_storage: &PersistentStorage) -> Result<Option<HashMap<String, Bucket<Vec<u8>>>>, failure::Error> {
+    crate::helpers::get_context(level, list, persistent_storage)
+}
+
+/// Binary layout and JSON schema of a described encoding
+#[derive(Serialize, Debug)]
+pub(crate) struct EncodingDescription {
+    name: String,
+    json: Value,
+    binary: String,
+}
+
+/// Get names of all encodings which can be described
+pub(crate) fn get_described_encodings() -> Result<Vec<&'static str>, failure::Error> {
+    Ok(schema::described_encodings().into_iter().map(|(name, _)| name).collect())
+}
+
+/// Describe encoding registered under the `name`
+pub(crate) fn describe_encoding(name: &str) -> Result<Option<EncodingDescription>, failure::Error> {
+    Ok(schema::find_encoding(name).map(|encoding| EncodingDescription {
+        name: name.to_string(),
+        json: describe_json(&encoding),
+        binary: describe_binary(&encoding),
+    }))
+}
+
+/// Get operations of the mempool grouped by the result of their validation, disabled mempool has no operations
+pub(crate) fn get_pending_operations(mempool_state: &Option<MempoolStateRef>) -> Result<PendingOperations, failure::Error> {
+    match mempool_state {
+        Some(mempool_state) => Ok(PendingOperations::from(&*mempool_state.read().unwrap())),
+        None => Ok(PendingOperations::default()),
+    }
+}
+
+/// Get connected peers with their scores
+pub(crate) fn get_network_peers(peers_state: &PeersStateRef) -> Result<Vec<(String, PeerInfo)>, failure::Error> {
+    let state = peers_state.read().unwrap();
+    Ok(PeerInfo::list(&*state))
+}
+
+/// Get connected and banned addresses
+pub(crate) fn get_network_points(peers_state: &PeersStateRef) -> Result<Vec<(String, PointInfo)>, failure::Error> {
+    let state = peers_state.read().unwrap();
+    Ok(PointInfo::list(&*state))
+}
+
+/// Inject operation to the mempool.
+///
+/// Operation is validated by the mempool and propagated to the peers if it is applied.
+/// Returns hash of the operation.
+pub(crate) fn inject_operation(operation_data: &str, shell_channel: &ShellChannelRef, mempool_state: &MempoolStateRef) -> Result<OperationHash, failure::Error> {
+    let operation = OperationMessage::from_bytes(hex::decode(operation_data)?)?.operation().clone();
+    mempool::inject_operation(operation, shell_channel, mempool_state)
+}
+
+/// Wait until injected operation is validated by the mempool.
+///
+/// Returns error if operation was not applied or it was not validated in time.
+pub(crate) async fn wait_for_operation_validation(operation_hash: OperationHash, mempool_state: MempoolStateRef) -> Result<String, failure::Error> {
+    let operation_hash_b58 = HashType::OperationHash.bytes_to_string(&operation_hash);
+    let validation = mempool_state.write().unwrap().wait_for_validation(&operation_hash);
+
+    match tokio::time::timeout(INJECTION_TIMEOUT, validation).await {
+        Ok(Ok(Some(result))) => if result.classification == OperationClassification::Applied {
+            Ok(operation_hash_b58)
+        } else {
+            Err(InjectionError::OperationNotApplied {
+                operation_hash: operation_hash_b58,
+                error_json: result.error_json,
+            }.into())
+        },
+        // operation was already included in a block
+        Ok(Ok(None)) => Ok(operation_hash_b58),
+        Ok(Err(_)) => Err(InjectionError::ValidationCanceled { operation_hash: operation_hash_b58 }.into()),
+        Err(_) => Err(InjectionError::ValidationTimeout { operation_hash: operation_hash_b58 }.into()),
+    }
 }
 
 #[inline]
@@ -0,0 +1,76 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use shell::protocol::ContextProtocolParam;
+use shell::protocol::context_helpers::{context_key, contract_address_to_contract_id, contract_context_path, get_context_value, get_context_values_by_prefix, get_manager_key, pkh_from_tagged_bytes, tez_from_bytes, z_from_bytes};
+use storage::context::ContextApiRef;
+use tezos_encoding::micheline::Micheline;
+
+use crate::services::protocol::{ContractInfo, ContractScript};
+
+/// Return ids of all contracts stored in the context.
+///
+/// # Arguments
+///
+/// * `context_proto_params` - Protocol hash, constants, level and context hash of the requested block.
+/// * `list` - Context list handler.
+pub(crate) fn get_contracts(context_proto_params: ContextProtocolParam, list: ContextApiRef) -> Result<Option<Vec<String>>, failure::Error> {
+    let contracts_index = context_key(&[], &["data", "contracts", "index"]);
+    let context = get_context_values_by_prefix(&context_proto_params.context_hash, &contracts_index, &list)?;
+
+    // contracts are listed in the order of their context keys
+    let mut keys: Vec<&String> = context.keys().collect();
+    keys.sort();
+
+    // every contract has its balance, the contract address is the 10th component of the key (e.g. data/contracts/index/ad/af/43/23/f9/3e/000003cb7d7842406496fc07288635562bfd17e176c4/balance)
+    let contracts = keys.into_iter()
+        .map(|key| key.split('/').collect::<Vec<&str>>())
+        .filter(|key| key.len() == 11 && key[10] == "balance")
+        .map(|key| contract_address_to_contract_id(&hex::decode(key[9])?))
+        .collect::<Result<Vec<String>, failure::Error>>()?;
+
+    Ok(Some(contracts))
+}
+
+/// Return contract read from the context, None if the contract does not exist.
+///
+/// # Arguments
+///
+/// * `context_proto_params` - Protocol hash, constants, level and context hash of the requested block.
+/// * `contract_id` - Contract id (tz... or KT1...).
+/// * `list` - Context list handler.
+pub(crate) fn get_contract(context_proto_params: ContextProtocolParam, contract_id: &str, list: ContextApiRef) -> Result<Option<ContractInfo>, failure::Error> {
+    let context_hash = &context_proto_params.context_hash;
+    let contract_path = contract_context_path(contract_id)?;
+
+    // every existing contract has its balance
+    let balance = match get_context_value(context_hash, &context_key(&contract_path, &["balance"]), &list)? {
+        Some(balance) => tez_from_bytes(&balance)?,
+        None => return Ok(None),
+    };
+
+    let delegate = get_context_value(context_hash, &context_key(&contract_path, &["delegate"]), &list)?
+        .map(|delegate| pkh_from_tagged_bytes(&delegate).map(|delegate| delegate.to_string()))
+        .transpose()?;
+
+    // only implicit contracts have counter
+    let counter = get_context_value(context_hash, &context_key(&contract_path, &["counter"]), &list)?
+        .map(|counter| z_from_bytes(&counter))
+        .transpose()?;
+
+    // only originated contracts have script
+    let code = get_context_value(context_hash, &context_key(&contract_path, &["data", "code"]), &list)?;
+    let storage = get_context_value(context_hash, &context_key(&contract_path, &["data", "storage"]), &list)?;
+    let script = match (code, storage) {
+        (Some(code), Some(storage)) => Some(ContractScript::new(
+            Micheline::from_lazy_expr_bytes(&code)?,
+            Micheline::from_lazy_expr_bytes(&storage)?,
+        )),
+        _ => None,
+    };
+
+    let manager_key = get_manager_key(context_hash, contract_id, &list)?
+        .map(|manager_key| manager_key.to_b58());
+
+    Ok(Some(ContractInfo::new(balance.to_string(), delegate, script, counter, manager_key)))
+}
@@ -0,0 +1,141 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::collections::BTreeMap;
+
+use crypto::hash::ContextHash;
+use shell::protocol::ContextProtocolParam;
+use shell::protocol::context_helpers::{context_key, contract_address_to_contract_id, contract_context_path, delegate_context_path, get_context_value, get_context_values_by_prefix, tez_from_bytes};
+use storage::context::ContextApiRef;
+use storage::num_from_slice;
+use tezos_messages::base::signature_public_key_hash::SignaturePublicKeyHash;
+
+use crate::services::protocol::{DelegateInfo, FrozenBalanceByCycle};
+
+/// Return public key hashes of all registered delegates matching the activity filter.
+///
+/// # Arguments
+///
+/// * `context_proto_params` - Protocol hash, constants, level and context hash of the requested block.
+/// * `active` - Return only active delegates.
+/// * `inactive` - Return only deactivated delegates.
+/// * `list` - Context list handler.
+pub(crate) fn get_delegates(context_proto_params: ContextProtocolParam, active: bool, inactive: bool, list: ContextApiRef) -> Result<Option<Vec<String>>, failure::Error> {
+    let context_hash = &context_proto_params.context_hash;
+    let context = get_context_values_by_prefix(context_hash, &context_key(&[], &["data", "delegates"]), &list)?;
+
+    // delegates are listed in the order of their context keys
+    let mut keys: Vec<&String> = context.keys().collect();
+    keys.sort();
+
+    let mut delegates = Vec::new();
+    for key in keys {
+        // get the address an the curve tag from the key (e.g. data/delegates/ed25519/2c/ca/28/ab/01/9ae2d8c26f4ce4924cad67a2dc6618)
+        let key: Vec<&str> = key.split('/').collect();
+        if key.len() != 9 {
+            continue;
+        }
+        let delegate = SignaturePublicKeyHash::from_hex_hash_and_curve(&key[3..9].join(""), key[2])?.to_string();
+
+        let matches_filter = match (active, inactive) {
+            (true, false) => !is_deactivated(context_hash, &delegate, &list)?,
+            (false, true) => is_deactivated(context_hash, &delegate, &list)?,
+            _ => true,
+        };
+        if matches_filter {
+            delegates.push(delegate);
+        }
+    }
+
+    Ok(Some(delegates))
+}
+
+/// Return delegate read from the context, None if the public key hash is not registered as delegate.
+///
+/// # Arguments
+///
+/// * `context_proto_params` - Protocol hash, constants, level and context hash of the requested block.
+/// * `tokens_per_roll` - Tokens per roll from the protocol constants, in mutez.
+/// * `pkh` - Public key hash of the delegate (tz...).
+/// * `list` - Context list handler.
+pub(crate) fn get_delegate(context_proto_params: ContextProtocolParam, tokens_per_roll: i64, pkh: &str, list: ContextApiRef) -> Result<Option<DelegateInfo>, failure::Error> {
+    let context_hash = &context_proto_params.context_hash;
+    if get_context_value(context_hash, &delegate_context_path(pkh)?, &list)?.is_none() {
+        return Ok(None);
+    }
+
+    let contract_path = contract_context_path(pkh)?;
+
+    let spendable_balance = get_tez(context_hash, &context_key(&contract_path, &["balance"]), &list)?;
+
+    // frozen balances are stored by cycle (e.g. .../frozen_balance/12/deposits)
+    let mut frozen_by_cycle: BTreeMap<i32, (i64, i64, i64)> = BTreeMap::new();
+    for (key, value) in get_context_values_by_prefix(context_hash, &context_key(&contract_path, &["frozen_balance"]), &list)? {
+        let key: Vec<&str> = key.split('/').skip(contract_path.len() + 1).collect();
+        if key.len() != 2 {
+            continue;
+        }
+        let amount = tez_from_bytes(&value)?;
+        let frozen = frozen_by_cycle.entry(key[0].parse()?).or_default();
+        match key[1] {
+            "deposits" => frozen.0 = amount,
+            "fees" => frozen.1 = amount,
+            "rewards" => frozen.2 = amount,
+            _ => (),
+        }
+    }
+    let (frozen_deposits, frozen_fees, frozen_rewards) = frozen_by_cycle.values()
+        .fold((0, 0, 0), |(deposits, fees, rewards), frozen| (deposits + frozen.0, fees + frozen.1, rewards + frozen.2));
+    let frozen_balance = frozen_deposits + frozen_fees + frozen_rewards;
+
+    // staking balance is the value of the rolls owned by the delegate and the change, which does not make up a whole roll
+    let mut rolls: i64 = 0;
+    for pk in get_context_values_by_prefix(context_hash, &context_key(&[], &["data", "rolls", "owner", "current"]), &list)?.values() {
+        if SignaturePublicKeyHash::from_tagged_bytes(pk.clone())?.to_string() == pkh {
+            rolls += 1;
+        }
+    }
+    let change = get_tez(context_hash, &context_key(&contract_path, &["change"]), &list)?;
+    let staking_balance = tokens_per_roll * rolls + change;
+
+    // the contract address is the last component of the key (e.g. .../delegated/ad/af/43/23/f9/3e/000003cb7d7842406496fc07288635562bfd17e176c4)
+    let delegated = get_context_values_by_prefix(context_hash, &context_key(&contract_path, &["delegated"]), &list)?;
+    let mut delegated_keys: Vec<&String> = delegated.keys().collect();
+    delegated_keys.sort();
+    let delegated_contracts = delegated_keys.into_iter()
+        .filter_map(|key| key.split('/').last())
+        .map(|address| contract_address_to_contract_id(&hex::decode(address)?))
+        .collect::<Result<Vec<String>, failure::Error>>()?;
+
+    let grace_period = get_context_value(context_hash, &context_key(&contract_path, &["delegate_desactivation"]), &list)?
+        .map(|cycle| num_from_slice!(cycle, 0, i32))
+        .unwrap_or(0);
+
+    Ok(Some(DelegateInfo::new(
+        (spendable_balance + frozen_balance).to_string(),
+        frozen_balance.to_string(),
+        frozen_by_cycle.into_iter()
+            .map(|(cycle, (deposit, fees, rewards))| FrozenBalanceByCycle::new(cycle, deposit.to_string(), fees.to_string(), rewards.to_string()))
+            .collect(),
+        staking_balance.to_string(),
+        delegated_contracts,
+        (staking_balance - spendable_balance - frozen_deposits - frozen_fees).to_string(),
+        is_deactivated(context_hash, pkh, &list)?,
+        grace_period,
+    )))
+}
+
+/// Delegate is deactivated when it was not baking or endorsing for preserved_cycles
+#[inline]
+fn is_deactivated(context_hash: &ContextHash, pkh: &str, list: &ContextApiRef) -> Result<bool, failure::Error> {
+    let contract_path = contract_context_path(pkh)?;
+    Ok(get_context_value(context_hash, &context_key(&contract_path, &["inactive_delegate"]), list)?.is_some())
+}
+
+/// Get tez amount stored in the context, missing amount is zero
+#[inline]
+fn get_tez(context_hash: &ContextHash, key: &[String], list: &ContextApiRef) -> Result<i64, failure::Error> {
+    get_context_value(context_hash, key, list)?
+        .map(|amount| tez_from_bytes(&amount))
+        .unwrap_or(Ok(0))
+}
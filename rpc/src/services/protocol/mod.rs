@@ -10,18 +10,21 @@
 //!     - if service has the same implementation for various protocol, can be place directly here
 //!     - if in new version of protocol is changed behavior, we have to splitted it here aslo by protocol_hash
 
-use std::convert::TryInto;
 
-use failure::bail;
+use failure::{bail, Fail};
 use getset::Getters;
 use itertools::Itertools;
 use serde::Serialize;
 
 use crypto::hash::HashType;
 use storage::num_from_slice;
-use storage::persistent::{ContextList, ContextMap, PersistentStorage};
+use storage::{BlockStorage, BlockStorageReader};
+use storage::context::{ContextApiRef, ContextIndex};
+use storage::persistent::{ContextMap, PersistentStorage};
 use storage::skip_list::Bucket;
+use tezos_encoding::micheline::Micheline;
 use tezos_messages::base::signature_public_key_hash::SignaturePublicKeyHash;
+use tezos_messages::p2p::binary_message::BinaryMessage;
 use tezos_messages::protocol::{
     proto_001 as proto_001_constants,
     proto_002 as proto_002_constants,
@@ -33,12 +36,29 @@ use tezos_messages::protocol::{
     RpcJsonMap,
 };
 
-use crate::helpers::{get_context, get_context_protocol_params, get_level_by_block_id};
+use crate::helpers::{get_block_hash_by_block_id, get_context_protocol_params};
 use crate::rpc_actor::RpcCollectedStateRef;
 
+mod contract_service;
+mod delegate_service;
 mod proto_005_2;
 mod proto_006;
 
+/// Possible reasons why protocol rpc service can not be served
+#[derive(Debug, Fail)]
+pub enum UnsupportedProtocolError {
+    #[fail(display = "{} is not yet implemented for protocol: {}", service, protocol)]
+    NotYetImplemented {
+        service: String,
+        protocol: String,
+    },
+    #[fail(display = "Missing {} implementation for protocol: {}, protocol is not yet supported!", service, protocol)]
+    UnsupportedProtocol {
+        service: String,
+        protocol: String,
+    },
+}
+
 /// Return generated baking rights.
 ///
 /// # Arguments
@@ -63,7 +83,7 @@ pub(crate) fn check_and_get_baking_rights(
     cycle: Option<&str>,
     max_priority: Option<&str>,
     has_all: bool,
-    list: ContextList,
+    list: ContextApiRef,
     persistent_storage: &PersistentStorage,
     state: &RpcCollectedStateRef) -> Result<Option<Vec<RpcJsonMap>>, failure::Error> {
 
@@ -77,13 +97,14 @@ pub(crate) fn check_and_get_baking_rights(
     )?;
 
     // split impl by protocol
+    const SERVICE: &str = "Baking rights";
     let hash: &str = &HashType::ProtocolHash.bytes_to_string(&context_proto_params.protocol_hash);
     match hash {
         proto_001_constants::PROTOCOL_HASH
         | proto_002_constants::PROTOCOL_HASH
         | proto_003_constants::PROTOCOL_HASH
         | proto_004_constants::PROTOCOL_HASH
-        | proto_005_constants::PROTOCOL_HASH => panic!("not yet implemented!"),
+        | proto_005_constants::PROTOCOL_HASH => Err(UnsupportedProtocolError::NotYetImplemented { service: SERVICE.to_string(), protocol: hash.to_string() }.into()),
         proto_005_2_constants::PROTOCOL_HASH => {
             proto_005_2::rights_service::check_and_get_baking_rights(
                 context_proto_params,
@@ -110,7 +131,7 @@ pub(crate) fn check_and_get_baking_rights(
                 persistent_storage,
             )
         }
-        _ => panic!("Missing baking rights implemetation for protocol: {}, protocol is not yet supported!", hash)
+        _ => Err(UnsupportedProtocolError::UnsupportedProtocol { service: SERVICE.to_string(), protocol: hash.to_string() }.into()),
     }
 }
 
@@ -136,7 +157,7 @@ pub(crate) fn check_and_get_endorsing_rights(
     delegate: Option<&str>,
     cycle: Option<&str>,
     has_all: bool,
-    list: ContextList,
+    list: ContextApiRef,
     persistent_storage: &PersistentStorage,
     state: &RpcCollectedStateRef) -> Result<Option<Vec<RpcJsonMap>>, failure::Error> {
 
@@ -150,13 +171,14 @@ pub(crate) fn check_and_get_endorsing_rights(
     )?;
 
     // split impl by protocol
+    const SERVICE: &str = "Endorsing rights";
     let hash: &str = &HashType::ProtocolHash.bytes_to_string(&context_proto_params.protocol_hash);
     match hash {
         proto_001_constants::PROTOCOL_HASH
         | proto_002_constants::PROTOCOL_HASH
         | proto_003_constants::PROTOCOL_HASH
         | proto_004_constants::PROTOCOL_HASH
-        | proto_005_constants::PROTOCOL_HASH => panic!("not yet implemented!"),
+        | proto_005_constants::PROTOCOL_HASH => Err(UnsupportedProtocolError::NotYetImplemented { service: SERVICE.to_string(), protocol: hash.to_string() }.into()),
         proto_005_2_constants::PROTOCOL_HASH => {
             proto_005_2::rights_service::check_and_get_endorsing_rights(
                 context_proto_params,
@@ -181,21 +203,192 @@ pub(crate) fn check_and_get_endorsing_rights(
                 persistent_storage,
             )
         }
-        _ => panic!("Missing endorsing rights implemetation for protocol: {}, protocol is not yet supported!", hash)
+        _ => Err(UnsupportedProtocolError::UnsupportedProtocol { service: SERVICE.to_string(), protocol: hash.to_string() }.into()),
+    }
+}
+
+/// Return ids of all contracts stored in the context.
+///
+/// # Arguments
+///
+/// * `block_id` - Url path parameter 'block_id', it contains string "head", block level or block hash.
+/// * `list` - Context list handler.
+/// * `persistent_storage` - Persistent storage handler.
+/// * `state` - Current RPC collected state (head).
+pub(crate) fn get_contracts(
+    block_id: &str,
+    list: ContextApiRef,
+    persistent_storage: &PersistentStorage,
+    state: &RpcCollectedStateRef) -> Result<Option<Vec<String>>, failure::Error> {
+
+    // get protocol and constants
+    let context_proto_params = get_context_protocol_params(
+        block_id,
+        None,
+        list.clone(),
+        persistent_storage,
+        state,
+    )?;
+
+    // split impl by protocol
+    const SERVICE: &str = "Contracts";
+    let hash: &str = &HashType::ProtocolHash.bytes_to_string(&context_proto_params.protocol_hash);
+    match hash {
+        proto_001_constants::PROTOCOL_HASH
+        | proto_002_constants::PROTOCOL_HASH
+        | proto_003_constants::PROTOCOL_HASH
+        | proto_004_constants::PROTOCOL_HASH
+        | proto_005_constants::PROTOCOL_HASH => Err(UnsupportedProtocolError::NotYetImplemented { service: SERVICE.to_string(), protocol: hash.to_string() }.into()),
+        proto_005_2_constants::PROTOCOL_HASH
+        | proto_006_constants::PROTOCOL_HASH => contract_service::get_contracts(context_proto_params, list),
+        _ => Err(UnsupportedProtocolError::UnsupportedProtocol { service: SERVICE.to_string(), protocol: hash.to_string() }.into()),
+    }
+}
+
+/// Return contract (balance, delegate, counter, manager key and script) read from the context.
+///
+/// # Arguments
+///
+/// * `block_id` - Url path parameter 'block_id', it contains string "head", block level or block hash.
+/// * `contract_id` - Url path parameter 'contract_id', contract id (tz... or KT1...).
+/// * `list` - Context list handler.
+/// * `persistent_storage` - Persistent storage handler.
+/// * `state` - Current RPC collected state (head).
+pub(crate) fn get_contract(
+    block_id: &str,
+    contract_id: &str,
+    list: ContextApiRef,
+    persistent_storage: &PersistentStorage,
+    state: &RpcCollectedStateRef) -> Result<Option<ContractInfo>, failure::Error> {
+
+    // get protocol and constants
+    let context_proto_params = get_context_protocol_params(
+        block_id,
+        None,
+        list.clone(),
+        persistent_storage,
+        state,
+    )?;
+
+    // split impl by protocol
+    const SERVICE: &str = "Contract";
+    let hash: &str = &HashType::ProtocolHash.bytes_to_string(&context_proto_params.protocol_hash);
+    match hash {
+        proto_001_constants::PROTOCOL_HASH
+        | proto_002_constants::PROTOCOL_HASH
+        | proto_003_constants::PROTOCOL_HASH
+        | proto_004_constants::PROTOCOL_HASH
+        | proto_005_constants::PROTOCOL_HASH => Err(UnsupportedProtocolError::NotYetImplemented { service: SERVICE.to_string(), protocol: hash.to_string() }.into()),
+        proto_005_2_constants::PROTOCOL_HASH
+        | proto_006_constants::PROTOCOL_HASH => contract_service::get_contract(context_proto_params, contract_id, list),
+        _ => Err(UnsupportedProtocolError::UnsupportedProtocol { service: SERVICE.to_string(), protocol: hash.to_string() }.into()),
+    }
+}
+
+/// Return public key hashes of registered delegates.
+///
+/// # Arguments
+///
+/// * `block_id` - Url path parameter 'block_id', it contains string "head", block level or block hash.
+/// * `active` - Url query parameter 'active'.
+/// * `inactive` - Url query parameter 'inactive'.
+/// * `list` - Context list handler.
+/// * `persistent_storage` - Persistent storage handler.
+/// * `state` - Current RPC collected state (head).
+///
+/// All delegates are returned if both or none of the `active` and `inactive` filters are set.
+pub(crate) fn get_delegates(
+    block_id: &str,
+    active: bool,
+    inactive: bool,
+    list: ContextApiRef,
+    persistent_storage: &PersistentStorage,
+    state: &RpcCollectedStateRef) -> Result<Option<Vec<String>>, failure::Error> {
+
+    // get protocol and constants
+    let context_proto_params = get_context_protocol_params(
+        block_id,
+        None,
+        list.clone(),
+        persistent_storage,
+        state,
+    )?;
+
+    // split impl by protocol
+    const SERVICE: &str = "Delegates";
+    let hash: &str = &HashType::ProtocolHash.bytes_to_string(&context_proto_params.protocol_hash);
+    match hash {
+        proto_001_constants::PROTOCOL_HASH
+        | proto_002_constants::PROTOCOL_HASH
+        | proto_003_constants::PROTOCOL_HASH
+        | proto_004_constants::PROTOCOL_HASH
+        | proto_005_constants::PROTOCOL_HASH => Err(UnsupportedProtocolError::NotYetImplemented { service: SERVICE.to_string(), protocol: hash.to_string() }.into()),
+        proto_005_2_constants::PROTOCOL_HASH
+        | proto_006_constants::PROTOCOL_HASH => delegate_service::get_delegates(context_proto_params, active, inactive, list),
+        _ => Err(UnsupportedProtocolError::UnsupportedProtocol { service: SERVICE.to_string(), protocol: hash.to_string() }.into()),
     }
 }
 
-pub(crate) fn get_votes_listings(_chain_id: &str, block_id: &str, persistent_storage: &PersistentStorage, context_list: ContextList, state: &RpcCollectedStateRef) -> Result<Option<Vec<VoteListings>>, failure::Error> {
+/// Return delegate (balances, delegated contracts and activity) read from the context.
+///
+/// # Arguments
+///
+/// * `block_id` - Url path parameter 'block_id', it contains string "head", block level or block hash.
+/// * `pkh` - Url path parameter 'pkh', public key hash of the delegate (tz...).
+/// * `list` - Context list handler.
+/// * `persistent_storage` - Persistent storage handler.
+/// * `state` - Current RPC collected state (head).
+pub(crate) fn get_delegate(
+    block_id: &str,
+    pkh: &str,
+    list: ContextApiRef,
+    persistent_storage: &PersistentStorage,
+    state: &RpcCollectedStateRef) -> Result<Option<DelegateInfo>, failure::Error> {
+
+    // get protocol and constants
+    let context_proto_params = get_context_protocol_params(
+        block_id,
+        None,
+        list.clone(),
+        persistent_storage,
+        state,
+    )?;
+
+    // split impl by protocol
+    const SERVICE: &str = "Delegate";
+    let hash: &str = &HashType::ProtocolHash.bytes_to_string(&context_proto_params.protocol_hash);
+    match hash {
+        proto_001_constants::PROTOCOL_HASH
+        | proto_002_constants::PROTOCOL_HASH
+        | proto_003_constants::PROTOCOL_HASH
+        | proto_004_constants::PROTOCOL_HASH
+        | proto_005_constants::PROTOCOL_HASH => Err(UnsupportedProtocolError::NotYetImplemented { service: SERVICE.to_string(), protocol: hash.to_string() }.into()),
+        proto_005_2_constants::PROTOCOL_HASH => {
+            let constants = proto_005_2_constants::constants::ParametricConstants::from_bytes(context_proto_params.constants_data.clone())?;
+            let tokens_per_roll: i64 = constants.tokens_per_roll().0.to_string().parse()?;
+            delegate_service::get_delegate(context_proto_params, tokens_per_roll, pkh, list)
+        }
+        proto_006_constants::PROTOCOL_HASH => {
+            let constants = proto_006_constants::constants::ParametricConstants::from_bytes(context_proto_params.constants_data.clone())?;
+            let tokens_per_roll: i64 = constants.tokens_per_roll().0.to_string().parse()?;
+            delegate_service::get_delegate(context_proto_params, tokens_per_roll, pkh, list)
+        }
+        _ => Err(UnsupportedProtocolError::UnsupportedProtocol { service: SERVICE.to_string(), protocol: hash.to_string() }.into()),
+    }
+}
+
+pub(crate) fn get_votes_listings(_chain_id: &str, block_id: &str, persistent_storage: &PersistentStorage, context_list: ContextApiRef, state: &RpcCollectedStateRef) -> Result<Option<Vec<VoteListings>>, failure::Error> {
     let mut listings = Vec::<VoteListings>::new();
 
-    // get block level first
-    let block_level: i64 = match get_level_by_block_id(block_id, persistent_storage, state)? {
-        Some(val) => val.try_into()?,
-        None => bail!("Block level not found")
+    // get context hash of the block first
+    let block_hash = get_block_hash_by_block_id(block_id, persistent_storage, state)?;
+    let context_hash = match BlockStorage::new(persistent_storage).get(&block_hash)? {
+        Some(block) => block.header.context().clone(),
+        None => bail!("Block not found")
     };
 
     // get the whole context
-    let ctxt = get_context(&block_level.to_string(), context_list)?;
+    let ctxt = context_list.read().expect("poisoned storage lock").get_context(&ContextIndex::new(Some(context_hash)))?;
 
     // filter out the listings data
     let listings_data: ContextMap = ctxt.unwrap().into_iter()
@@ -240,4 +433,145 @@ impl VoteListings {
             rolls,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Contract stored in the context, serialized the same way as contract info by the ocaml node
+#[derive(Serialize, Debug, Clone, Getters)]
+pub struct ContractInfo {
+    /// Spendable balance in mutez
+    #[get = "pub(crate)"]
+    balance: String,
+
+    /// Public key hash of the delegate (tz...)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[get = "pub(crate)"]
+    delegate: Option<String>,
+
+    /// Code and storage of the originated contract
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[get = "pub(crate)"]
+    script: Option<ContractScript>,
+
+    /// Operation counter of the implicit contract
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[get = "pub(crate)"]
+    counter: Option<String>,
+
+    /// Revealed public key of the manager, it is served by its own rpc
+    #[serde(skip)]
+    #[get = "pub(crate)"]
+    manager_key: Option<String>,
+}
+
+impl ContractInfo {
+    /// Simple constructor to construct ContractInfo
+    pub fn new(balance: String, delegate: Option<String>, script: Option<ContractScript>, counter: Option<String>, manager_key: Option<String>) -> Self {
+        Self {
+            balance,
+            delegate,
+            script,
+            counter,
+            manager_key,
+        }
+    }
+}
+
+/// Michelson script of the originated contract
+#[derive(Serialize, Debug, Clone, Getters)]
+pub struct ContractScript {
+    #[get = "pub(crate)"]
+    code: Micheline,
+
+    #[get = "pub(crate)"]
+    storage: Micheline,
+}
+
+impl ContractScript {
+    /// Simple constructor to construct ContractScript
+    pub fn new(code: Micheline, storage: Micheline) -> Self {
+        Self {
+            code,
+            storage,
+        }
+    }
+}
+
+/// Delegate stored in the context, serialized the same way as delegate info by the ocaml node
+#[derive(Serialize, Debug, Clone, Getters)]
+pub struct DelegateInfo {
+    /// Full balance (spendable and frozen) in mutez
+    #[get = "pub(crate)"]
+    balance: String,
+
+    /// Sum of all frozen deposits, fees and rewards
+    #[get = "pub(crate)"]
+    frozen_balance: String,
+
+    #[get = "pub(crate)"]
+    frozen_balance_by_cycle: Vec<FrozenBalanceByCycle>,
+
+    /// Rolls owned by the delegate and the change
+    #[get = "pub(crate)"]
+    staking_balance: String,
+
+    /// Contracts delegated to the delegate
+    #[get = "pub(crate)"]
+    delegated_contracts: Vec<String>,
+
+    /// Staking balance without the own balance (frozen rewards excluded) of the delegate
+    #[get = "pub(crate)"]
+    delegated_balance: String,
+
+    #[get = "pub(crate)"]
+    deactivated: bool,
+
+    /// Cycle in which the delegate will be deactivated if it stays inactive
+    #[get = "pub(crate)"]
+    grace_period: i32,
+}
+
+impl DelegateInfo {
+    /// Simple constructor to construct DelegateInfo
+    pub fn new(
+        balance: String,
+        frozen_balance: String,
+        frozen_balance_by_cycle: Vec<FrozenBalanceByCycle>,
+        staking_balance: String,
+        delegated_contracts: Vec<String>,
+        delegated_balance: String,
+        deactivated: bool,
+        grace_period: i32,
+    ) -> Self {
+        Self {
+            balance,
+            frozen_balance,
+            frozen_balance_by_cycle,
+            staking_balance,
+            delegated_contracts,
+            delegated_balance,
+            deactivated,
+            grace_period,
+        }
+    }
+}
+
+/// Frozen deposit, fees and rewards of the delegate in the cycle
+#[derive(Serialize, Debug, Clone)]
+pub struct FrozenBalanceByCycle {
+    cycle: i32,
+    deposit: String,
+    fees: String,
+    rewards: String,
+}
+
+impl FrozenBalanceByCycle {
+    /// Simple constructor to construct FrozenBalanceByCycle
+    pub fn new(cycle: i32, deposit: String, fees: String, rewards: String) -> Self {
+        Self {
+            cycle,
+            deposit,
+            fees,
+            rewards,
+        }
+    }
+}
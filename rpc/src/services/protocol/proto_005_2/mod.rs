@@ -1,5 +1,4 @@
 // Copyright (c) SimpleStaking and Tezedge Contributors
 // SPDX-License-Identifier: MIT
 
-pub(crate) mod rights_service;
-mod helpers;
\ No newline at end of file
+pub(crate) mod rights_service;
\ No newline at end of file
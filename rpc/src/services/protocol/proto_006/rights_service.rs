@@ -7,15 +7,16 @@ use std::convert::TryInto;
 use failure::format_err;
 use itertools::Itertools;
 
-use storage::persistent::{ContextList, PersistentStorage};
+use shell::protocol::ContextProtocolParam;
+use shell::protocol::proto_006::draw_baker;
+use shell::protocol::proto_006::helpers::{EndorserSlots, get_prng_number, init_prng, RightsConstants, RightsContextData, RightsParams};
+use storage::context::ContextApiRef;
+use storage::context_action_storage::contract_id_to_contract_address_for_index;
+use storage::persistent::PersistentStorage;
 use tezos_messages::base::signature_public_key_hash::SignaturePublicKeyHash;
 use tezos_messages::protocol::{RpcJsonMap, ToRpcJsonMap};
 use tezos_messages::protocol::proto_006::rights::{BakingRights, EndorsingRight};
 
-use crate::helpers::ContextProtocolParam;
-use crate::services::protocol::proto_006::helpers::{EndorserSlots, get_prng_number, init_prng, RightsConstants, RightsContextData, RightsParams};
-use storage::context_action_storage::contract_id_to_contract_address_for_index;
-
 /// Return generated baking rights.
 ///
 /// # Arguments
@@ -40,17 +41,18 @@ pub(crate) fn check_and_get_baking_rights(
     cycle: Option<&str>,
     max_priority: Option<&str>,
     has_all: bool,
-    list: ContextList,
+    list: ContextApiRef,
     persistent_storage: &PersistentStorage) -> Result<Option<Vec<RpcJsonMap>>, failure::Error> {
 
     // get block level first
     let block_level: i64 = context_proto_params.level.try_into()?;
+    let context_hash = context_proto_params.context_hash.clone();
 
     let constants: RightsConstants = RightsConstants::parse_rights_constants(context_proto_params)?;
 
     let params: RightsParams = RightsParams::parse_rights_parameters(chain_id, level, delegate, cycle, max_priority, has_all, block_level, &constants, persistent_storage, true)?;
 
-    let context_data: RightsContextData = RightsContextData::prepare_context_data_for_rights(params.clone(), constants.clone(), list)?;
+    let context_data: RightsContextData = RightsContextData::prepare_context_data_for_rights(params.clone(), constants.clone(), &context_hash, list, persistent_storage)?;
 
     get_baking_rights(&context_data, &params, &constants)
 }
@@ -131,8 +133,6 @@ pub(crate) fn get_baking_rights(context_data: &RightsContextData, parameters: &R
 /// Baking priorities are are assigned to Roles, the default behavior is to include only the top priority for the delegate
 #[inline]
 fn baking_rights_assign_rolls(parameters: &RightsParams, constants: &RightsConstants, context_data: &RightsContextData, level: i32, estimated_head_timestamp: i64, is_cycle: bool, baking_rights: &mut Vec<BakingRights>) -> Result<(), failure::Error> {
-    const BAKING_USE_STRING: &[u8] = b"level baking:";
-
     // hashset is defined to keep track of the delegates with priorities allready assigned
     let mut assigned = HashSet::new();
 
@@ -141,26 +141,11 @@ fn baking_rights_assign_rolls(parameters: &RightsParams, constants: &RightsConst
     let max_priority = *parameters.max_priority();
     let has_all = parameters.has_all();
     let block_level = *parameters.block_level();
-    let last_roll = *context_data.last_roll();
-    let rolls_map = context_data.rolls();
     let display_level: i32 = (*parameters.display_level()).try_into()?;
 
     for priority in 0..max_priority + 1 {
         // draw the rolls for the requested parameters
-        let delegate_to_assign;
-        // TODO: priority can overflow in the ocaml code, do a priority % i32::max_value()
-        let mut state = init_prng(&context_data, &constants, BAKING_USE_STRING, level.try_into()?, priority.try_into()?)?;
-
-        loop {
-            let (random_num, sequence) = get_prng_number(state, last_roll)?;
-
-            if let Some(d) = rolls_map.get(&random_num) {
-                delegate_to_assign = d;
-                break;
-            } else {
-                state = sequence;
-            }
-        }
+        let delegate_to_assign = draw_baker(context_data, constants, level, priority)?;
 
         // if the delegate was assgined and the the has_all flag is not set skip this priority
         if assigned.contains(&delegate_to_assign) && !has_all {
@@ -216,17 +201,18 @@ pub(crate) fn check_and_get_endorsing_rights(
     delegate: Option<&str>,
     cycle: Option<&str>,
     has_all: bool,
-    list: ContextList,
+    list: ContextApiRef,
     persistent_storage: &PersistentStorage) -> Result<Option<Vec<RpcJsonMap>>, failure::Error> {
 
     // get block level from block_id and from now get all nessesary data by block level
     let block_level: i64 = context_proto_params.level.try_into()?;
+    let context_hash = context_proto_params.context_hash.clone();
 
     let constants: RightsConstants = RightsConstants::parse_rights_constants(context_proto_params)?;
 
     let params: RightsParams = RightsParams::parse_rights_parameters(chain_id, level, delegate, cycle, None, has_all, block_level, &constants, persistent_storage, false)?;
 
-    let context_data: RightsContextData = RightsContextData::prepare_context_data_for_rights(params.clone(), constants.clone(), list)?;
+    let context_data: RightsContextData = RightsContextData::prepare_context_data_for_rights(params.clone(), constants.clone(), &context_hash, list, persistent_storage)?;
 
     get_endorsing_rights(&context_data, &params, &constants)
 }
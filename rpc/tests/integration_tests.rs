@@ -55,10 +55,18 @@ async fn integration_tests_rpc(from_block: i64, to_block: i64) {
 
         // --------------------------- Tests for each block_id ---------------------------
         test_rpc_compare_json(&format!("{}/{}", "chains/main/blocks", level)).await;
+        test_rpc_compare_json(&format!("{}/{}/{}", "chains/main/blocks", level, "operations")).await;
+        test_rpc_compare_json(&format!("{}/{}/{}", "chains/main/blocks", level, "operations/0")).await;
+        test_rpc_compare_json(&format!("{}/{}/{}", "chains/main/blocks", level, "operation_hashes")).await;
         test_rpc_compare_json(&format!("{}/{}/{}", "chains/main/blocks", level, "context/constants")).await;
         test_rpc_compare_json(&format!("{}/{}/{}", "chains/main/blocks", level, "helpers/endorsing_rights")).await;
         test_rpc_compare_json(&format!("{}/{}/{}", "chains/main/blocks", level, "helpers/baking_rights")).await;
         test_rpc_compare_json(&format!("{}/{}/{}", "chains/main/blocks", level, "votes/listings")).await;
+        test_rpc_compare_json(&format!("{}/{}/{}", "chains/main/blocks", level, "context/delegates")).await;
+        if let Some(baker) = block_json["metadata"]["baker"].as_str() {
+            test_rpc_compare_json(&format!("{}/{}/{}/{}", "chains/main/blocks", level, "context/delegates", baker)).await;
+            test_rpc_compare_json(&format!("{}/{}/{}/{}", "chains/main/blocks", level, "context/contracts", baker)).await;
+        }
         // --------------------------------- End of tests --------------------------------
 
         // we need some constants for
@@ -13,22 +13,29 @@ use std::time::Duration;
 
 use failure::{Error, Fail};
 use riker::actors::*;
-use slog::{debug, Logger, warn, trace};
+use slog::{debug, error, info, Logger, trace, warn};
 
-use crypto::hash::{BlockHash, HashType};
-use storage::{BlockMetaStorage, BlockMetaStorageReader, BlockStorage, BlockStorageReader, initialize_storage_with_genesis_block, OperationsMetaStorage, OperationsStorage, OperationsStorageReader, StorageError, StorageInitInfo, store_applied_block_result, store_commit_genesis_result};
+use crypto::hash::{BlockHash, ChainId, HashType};
+use storage::{BlockHeaderWithHash, BlockMetaStorage, BlockMetaStorageReader, BlockStorage, BlockStorageReader, initialize_storage_with_genesis_block, OperationsMetaStorage, OperationsStorage, OperationsStorageReader, StorageError, StorageInitInfo, store_applied_block_result, store_commit_genesis_result};
+use storage::block_meta_storage::Meta;
+use storage::context::ContextApiRef;
+use storage::merkle_hash::CommitInfo;
 use storage::persistent::PersistentStorage;
 use tezos_api::environment::TezosEnvironmentConfiguration;
+use tezos_messages::base::fitness_comparator::fitness_increases;
 use tezos_wrapper::service::{IpcCmdServer, ProtocolController, ProtocolServiceError};
 
-use crate::shell_channel::{BlockApplied, ShellChannelMsg, ShellChannelRef, ShellChannelTopic};
+use crate::shell_channel::{BlockApplied, HeadSwitched, ShellChannelMsg, ShellChannelRef, ShellChannelTopic};
 use crate::subscription::subscribe_to_shell_events;
+use crate::validation::{BakingRightsResolver, HeaderValidationError, HeaderValidator};
 
 /// This command triggers feeding of completed blocks to the tezos protocol
 #[derive(Clone, Debug)]
 pub struct FeedChainToProtocol;
 
 type SharedJoinHandle = Arc<Mutex<Option<JoinHandle<Result<(), Error>>>>>;
+/// Recently received blocks, which can possibly start or extend a branch
+type BranchCandidates = Arc<Mutex<Vec<BlockHash>>>;
 
 /// Feeds blocks and operations to the tezos protocol (ocaml code).
 #[actor(FeedChainToProtocol, ShellChannelMsg)]
@@ -39,6 +46,8 @@ pub struct ChainFeeder {
     block_applier_run: Arc<AtomicBool>,
     /// Block applier thread
     block_applier_thread: SharedJoinHandle,
+    /// Blocks which should be checked by block applier thread
+    branch_candidates: BranchCandidates,
 }
 
 /// Reference to [chain feeder](ChainFeeder) actor
@@ -53,19 +62,28 @@ impl ChainFeeder {
     /// This actor spawns a new thread in which it will periodically monitor [`persistent_storage`](PersistentStorage).
     /// Purpose of the monitoring thread is to detect whether it is possible to apply blocks received by the p2p layer.
     /// If the block can be applied, it is sent via IPC to the `protocol_runner`, where it is then applied by calling a tezos ffi.
+    ///
+    /// Context hash of every applied block is verified against the hash of the [`context`](ContextApiRef) committed by the context listener.
+    /// Block header is validated against its applied predecessor before the block is applied,
+    /// minimal delays and signatures are verified only, when `baking_rights` are provided.
     pub fn actor(
         sys: &impl ActorRefFactory,
         shell_channel: ShellChannelRef,
         persistent_storage: &PersistentStorage,
+        context: ContextApiRef,
         init_storage_data: &StorageInitInfo,
         tezos_env: &TezosEnvironmentConfiguration,
         ipc_server: IpcCmdServer,
+        baking_rights: Option<Arc<dyn BakingRightsResolver>>,
         log: Logger) -> Result<ChainFeederRef, CreateError> {
         let apply_block_run = Arc::new(AtomicBool::new(true));
+        let branch_candidates = Arc::new(Mutex::new(Vec::new()));
         let block_applier_thread = {
             let apply_block_run = apply_block_run.clone();
+            let branch_candidates = branch_candidates.clone();
             let shell_channel = shell_channel.clone();
             let persistent_storage = persistent_storage.clone();
+            let context = context.clone();
             let init_storage_data = init_storage_data.clone();
             let tezos_env = tezos_env.clone();
 
@@ -74,12 +92,13 @@ impl ChainFeeder {
                 let mut block_meta_storage = BlockMetaStorage::new(&persistent_storage);
                 let operations_storage = OperationsStorage::new(&persistent_storage);
                 let mut operations_meta_storage = OperationsMetaStorage::new(&persistent_storage);
+                let header_validator = HeaderValidator::with_supported_protocols(&persistent_storage, &init_storage_data.chain_id, baking_rights);
                 let mut ipc_server = ipc_server;
 
                 while apply_block_run.load(Ordering::Acquire) {
                     match ipc_server.accept() {
                         Ok(protocol_controller) =>
-                            match feed_chain_to_protocol(&tezos_env, &init_storage_data, &apply_block_run, &shell_channel, &mut block_storage, &mut block_meta_storage, &operations_storage, &mut operations_meta_storage, protocol_controller, &log) {
+                            match feed_chain_to_protocol(&tezos_env, &init_storage_data, &apply_block_run, &shell_channel, &context, &mut block_storage, &mut block_meta_storage, &operations_storage, &mut operations_meta_storage, &header_validator, &branch_candidates, protocol_controller, &log) {
                                 Ok(()) => debug!(log, "Feed chain to protocol finished"),
                                 Err(err) => {
                                     if apply_block_run.load(Ordering::Acquire) {
@@ -96,7 +115,7 @@ impl ChainFeeder {
         };
 
         let myself = sys.actor_of(
-            Props::new_args(ChainFeeder::new, (shell_channel, apply_block_run, Arc::new(Mutex::new(Some(block_applier_thread))))),
+            Props::new_args(ChainFeeder::new, (shell_channel, apply_block_run, Arc::new(Mutex::new(Some(block_applier_thread))), branch_candidates)),
             ChainFeeder::name())?;
 
         Ok(myself)
@@ -108,16 +127,19 @@ impl ChainFeeder {
         "chain-feeder"
     }
 
-    fn new((shell_channel, block_applier_run, block_applier_thread): (ShellChannelRef, Arc<AtomicBool>, SharedJoinHandle)) -> Self {
+    fn new((shell_channel, block_applier_run, block_applier_thread, branch_candidates): (ShellChannelRef, Arc<AtomicBool>, SharedJoinHandle, BranchCandidates)) -> Self {
         ChainFeeder {
             shell_channel,
             block_applier_run,
             block_applier_thread,
+            branch_candidates,
         }
     }
 
     fn process_shell_channel_message(&mut self, _ctx: &Context<ChainFeederMsg>, msg: ShellChannelMsg) -> Result<(), Error> {
         match msg {
+            ShellChannelMsg::BlockReceived(message) => self.schedule_branch_candidate(message.hash),
+            ShellChannelMsg::AllBlockOperationsReceived(message) => self.schedule_branch_candidate(message.hash),
             ShellChannelMsg::ShuttingDown(_) => {
                 self.block_applier_run.store(false, Ordering::Release);
             }
@@ -126,6 +148,15 @@ impl ChainFeeder {
 
         Ok(())
     }
+
+    /// Let the block applier thread know, that the block might be ready to be applied.
+    /// Such block can be a part of a new branch, which does not extend the current head.
+    fn schedule_branch_candidate(&mut self, block_hash: BlockHash) {
+        self.branch_candidates.lock().unwrap().push(block_hash);
+        if let Some(join_handle) = self.block_applier_thread.lock().unwrap().as_ref() {
+            join_handle.thread().unpark();
+        }
+    }
 }
 
 impl Actor for ChainFeeder {
@@ -192,6 +223,10 @@ pub enum FeedChainError {
     ProtocolServiceError {
         error: ProtocolServiceError
     },
+    #[fail(display = "Context hash verification failed, blocks cannot be applied on top of the unverified context! context_hash: {}", context_hash)]
+    ContextVerificationError {
+        context_hash: String
+    },
 }
 
 impl From<StorageError> for FeedChainError {
@@ -211,10 +246,13 @@ fn feed_chain_to_protocol(
     init_storage_data: &StorageInitInfo,
     apply_block_run: &AtomicBool,
     shell_channel: &ShellChannelRef,
+    context: &ContextApiRef,
     block_storage: &mut BlockStorage,
     block_meta_storage: &mut BlockMetaStorage,
     operations_storage: &OperationsStorage,
     operations_meta_storage: &mut OperationsMetaStorage,
+    header_validator: &HeaderValidator,
+    branch_candidates: &BranchCandidates,
     protocol_controller: ProtocolController,
     log: &Logger,
 ) -> Result<(), FeedChainError> {
@@ -235,8 +273,11 @@ fn feed_chain_to_protocol(
     )?;
 
     // now resolve where to start apply next blocks (at least genesis should be there)
-    let mut current_head_hash: BlockHash = match &block_meta_storage.load_current_head()? {
-        Some(block) => block.clone(),
+    let mut current_head: BlockHeaderWithHash = match &block_meta_storage.load_current_head()? {
+        Some(block_hash) => match block_storage.get(block_hash)? {
+            Some(block) => block,
+            None => return Err(FeedChainError::UnknownCurrentHeadError),
+        },
         None => {
             // this should not happen here, we applied at least genesis before
             return Err(FeedChainError::UnknownCurrentHeadError);
@@ -245,99 +286,104 @@ fn feed_chain_to_protocol(
 
     // now we can start applying block
     while apply_block_run.load(Ordering::Acquire) {
-        match block_meta_storage.get(&current_head_hash)? {
-            Some(mut current_head_meta) => {
-                if current_head_meta.is_applied() {
-                    // Current head is already applied, so we should move to successor
-                    // or in case no successor is available do nothing.
-                    match current_head_meta.successor() {
-                        Some(successor_hash) => {
-                            current_head_hash = successor_hash.clone();
-                            continue;
-                        }
-                        None => ( /* successor is not yet available, we do nothing for now */ )
-                    }
-                } else {
-                    // Current head is not applied, so we should apply it now.
-                    // But first let's fetch current head data from block storage..
-                    match block_storage.get(&current_head_hash)? {
-                        Some(current_head) => {
-                            // Good, we have block data available, let's' look is we have all operations
-                            // available. If yes we will apply them. If not, we will do nothing.
-                            if operations_meta_storage.is_complete(&current_head.hash)? {
-                                debug!(log, "Applying block"; "block_header_hash" => block_hash_encoding.bytes_to_string(&current_head.hash));
-                                let operations = operations_storage.get_operations(&current_head_hash)?
-                                    .drain(..)
-                                    .map(Some)
-                                    .collect();
-
-                                // apply block and it's operations
-                                let (predecessor, predecessor_additional_data) = match block_storage.get_with_additional_data(&current_head.header.predecessor())? {
-                                    None => {
-                                        warn!(log, "No data was found in database for the predecessor, so we try to apply it first"; "predecessor_block_header_hash" => block_hash_encoding.bytes_to_string(&current_head.header.predecessor()));
-                                        current_head_hash = current_head.header.predecessor().clone();
-                                        continue;
-                                    }
-                                    Some(predecesor_data) => predecesor_data
-                                };
-
-                                let apply_block_result = protocol_controller.apply_block(
-                                    &chain_id,
-                                    &current_head.header,
-                                    &predecessor.header,
-                                    &operations,
-                                    predecessor_additional_data.max_operations_ttl().clone(),
-                                )?;
-                                debug!(
-                                    log,
-                                    "Block was applied";
-                                    "block_header_hash" => block_hash_encoding.bytes_to_string(&current_head.hash),
-                                    "context_hash" => HashType::ContextHash.bytes_to_string(&apply_block_result.context_hash),
-                                    "validation_result_message" => &apply_block_result.validation_result_message
-                                );
-
-
-
-                                // store result
-                                let (block_json_data, _) = store_applied_block_result(
-                                    block_storage,
-                                    block_meta_storage,
-                                    &current_head.hash,
-                                    apply_block_result,
-                                    &mut current_head_meta,
-                                )?;
-
-                                // notify listeners
-                                if apply_block_run.load(Ordering::Acquire) {
-                                    // notify others that the block successfully applied
-                                    shell_channel.tell(
-                                        Publish {
-                                            msg: BlockApplied::new(current_head, block_json_data).into(),
-                                            topic: ShellChannelTopic::ShellEvents.into(),
-                                        }, None);
-                                }
+        // We walk the block tree starting from the current head and from recently received blocks.
+        // Every applied block is a possible fork point, so all its successors are visited, not only the first one.
+        let mut blocks_to_visit = vec![current_head.hash.clone()];
+        blocks_to_visit.extend(branch_candidates.lock().unwrap().drain(..));
+
+        while let Some(block_hash) = blocks_to_visit.pop() {
+            if !apply_block_run.load(Ordering::Acquire) {
+                break;
+            }
 
-                                // Current head is already applied, so we should move to successor
-                                // or in case no successor is available do nothing.
-                                match current_head_meta.successor() {
-                                    Some(successor_hash) => {
-                                        current_head_hash = successor_hash.clone();
-                                        continue;
+            let mut block_meta = match block_meta_storage.get(&block_hash)? {
+                Some(block_meta) => block_meta,
+                None => {
+                    trace!(log, "No meta info record was found in database for the block"; "block_header_hash" => block_hash_encoding.bytes_to_string(&block_hash));
+                    continue;
+                }
+            };
+
+            if !block_meta.is_applied() {
+                // Block is not applied, so we should try to apply it now.
+                let applied_block = apply_block(
+                    &chain_id,
+                    &block_hash,
+                    &mut block_meta,
+                    apply_block_run,
+                    shell_channel,
+                    context,
+                    block_storage,
+                    block_meta_storage,
+                    operations_storage,
+                    operations_meta_storage,
+                    header_validator,
+                    &protocol_controller,
+                    log,
+                )?;
+
+                match applied_block {
+                    Some(applied_block) => {
+                        // block with a better fitness becomes a new current head
+                        if fitness_increases(current_head.header.fitness(), applied_block.header.fitness()) {
+                            let previous_head = std::mem::replace(&mut current_head, applied_block);
+                            block_meta_storage.store_current_head(&current_head.hash)?;
+                            // blocks of the main chain are returned by their level
+                            block_storage.index_by_level(&current_head.hash)?;
+
+                            if current_head.header.predecessor() != &previous_head.hash {
+                                // new head is not a successor of the previous one, so it is on another branch
+                                match resolve_head_switch(block_meta_storage, &previous_head.hash, &current_head)? {
+                                    Some(head_switched) => {
+                                        for block_hash in head_switched.rolled_back_blocks() {
+                                            block_storage.unindex_by_level(block_hash)?;
+                                        }
+                                        for block_hash in head_switched.applied_blocks() {
+                                            block_storage.index_by_level(block_hash)?;
+                                        }
+                                        info!(
+                                            log,
+                                            "Current head switched to another branch";
+                                            "previous_head" => block_hash_encoding.bytes_to_string(head_switched.previous_head()),
+                                            "new_head" => block_hash_encoding.bytes_to_string(&head_switched.new_head().hash),
+                                            "common_ancestor" => block_hash_encoding.bytes_to_string(head_switched.common_ancestor()),
+                                            "rolled_back_blocks" => head_switched.rolled_back_blocks().len(),
+                                            "applied_blocks" => head_switched.applied_blocks().len(),
+                                        );
+                                        if apply_block_run.load(Ordering::Acquire) {
+                                            shell_channel.tell(
+                                                Publish {
+                                                    msg: head_switched.into(),
+                                                    topic: ShellChannelTopic::ShellEvents.into(),
+                                                }, None);
+                                        }
                                     }
-                                    None => ( /* successor is not yet available, we do nothing for now */ )
+                                    None => warn!(log, "Failed to find common ancestor of the previous and the new head";
+                                                  "previous_head" => block_hash_encoding.bytes_to_string(&previous_head.hash),
+                                                  "new_head" => block_hash_encoding.bytes_to_string(&current_head.hash))
                                 }
-                            } else {
-                                // we don't have all operations available, do nothing
                             }
                         }
-                        None => ( /* it's possible that data was not yet written do the storage, so don't panic! */ )
+                    }
+                    None => {
+                        // block cannot be applied yet (missing data, operations or predecessor), we do nothing for now
+                        continue;
+                    }
+                }
+            }
+
+            // Block is applied, so we should continue with its successors (there can be more of them in case of a fork)
+            // or in case no successor is available do nothing.
+            for successor_hash in block_meta.successors() {
+                if let Some(successor_meta) = block_meta_storage.get(successor_hash)? {
+                    if !successor_meta.is_applied() {
+                        blocks_to_visit.push(successor_hash.clone());
                     }
                 }
             }
-            None => warn!(log, "No meta info record was found in database for the current head"; "block_header_hash" => block_hash_encoding.bytes_to_string(&current_head_hash))
         }
 
-        // This should be hit only in case that the current branch is applied
+        // This should be hit only in case that all known branches are applied
         // and no successor was available to continue the apply cycle. In that case
         // this thread will be stopped and will wait until it's waked again.
         thread::park();
@@ -346,6 +392,160 @@ fn feed_chain_to_protocol(
     Ok(())
 }
 
+/// Applies block with the tezos protocol and stores the result.
+///
+/// Block is applied only if its data, all its operations and its applied predecessor are available
+/// and its header is valid, otherwise `None` is returned.
+fn apply_block(
+    chain_id: &ChainId,
+    block_hash: &BlockHash,
+    block_meta: &mut Meta,
+    apply_block_run: &AtomicBool,
+    shell_channel: &ShellChannelRef,
+    context: &ContextApiRef,
+    block_storage: &mut BlockStorage,
+    block_meta_storage: &mut BlockMetaStorage,
+    operations_storage: &OperationsStorage,
+    operations_meta_storage: &OperationsMetaStorage,
+    header_validator: &HeaderValidator,
+    protocol_controller: &ProtocolController,
+    log: &Logger,
+) -> Result<Option<BlockHeaderWithHash>, FeedChainError> {
+    let block_hash_encoding = HashType::BlockHash;
+
+    // let's fetch block data from block storage..
+    let block = match block_storage.get(block_hash)? {
+        Some(block) => block,
+        None => return Ok(None) /* it's possible that data was not yet written do the storage, so don't panic! */
+    };
+
+    // .. and look is we have all operations available. If not, we will do nothing.
+    if !operations_meta_storage.is_complete(&block.hash)? {
+        return Ok(None);
+    }
+
+    // predecessor must be already applied, because protocol applies block on top of the predecessor context
+    match block_meta_storage.get(block.header.predecessor())? {
+        Some(predecessor_meta) if predecessor_meta.is_applied() => (),
+        _ => return Ok(None)
+    }
+    let (predecessor, predecessor_additional_data) = match block_storage.get_with_additional_data(block.header.predecessor())? {
+        Some(predecessor_data) => predecessor_data,
+        None => {
+            warn!(log, "No data was found in database for the applied predecessor"; "predecessor_block_header_hash" => block_hash_encoding.bytes_to_string(block.header.predecessor()));
+            return Ok(None);
+        }
+    };
+
+    // header could be received before its predecessor was applied, so protocol specific checks are done now
+    match header_validator.validate_with_predecessor(&block, &predecessor) {
+        Ok(()) => (),
+        Err(HeaderValidationError::StorageError { error }) => return Err(error.into()),
+        Err(error) => {
+            warn!(log, "Block header is not valid, block is not applied"; "reason" => error, "block_header_hash" => block_hash_encoding.bytes_to_string(&block.hash));
+            return Ok(None);
+        }
+    }
+
+    // once the context does not match the context hash calculated by the protocol, nothing can be applied on top of it
+    if let Some(context_hash) = context.read().expect("lock poisoning").verification_failure() {
+        return Err(FeedChainError::ContextVerificationError { context_hash: HashType::ContextHash.bytes_to_string(&context_hash) });
+    }
+
+    debug!(log, "Applying block"; "block_header_hash" => block_hash_encoding.bytes_to_string(&block.hash));
+    let operations = operations_storage.get_operations(block_hash)?
+        .drain(..)
+        .map(Some)
+        .collect();
+
+    // apply block and it's operations
+    let apply_block_result = protocol_controller.apply_block(
+        chain_id,
+        &block.header,
+        &predecessor.header,
+        &operations,
+        predecessor_additional_data.max_operations_ttl(),
+    )?;
+    debug!(
+        log,
+        "Block was applied";
+        "block_header_hash" => block_hash_encoding.bytes_to_string(&block.hash),
+        "context_hash" => HashType::ContextHash.bytes_to_string(&apply_block_result.context_hash),
+        "validation_result_message" => &apply_block_result.validation_result_message
+    );
+
+    // commit of the block is verified, when both the context hash and the committed context are available
+    let context_hash = apply_block_result.context_hash.clone();
+    let commit_info = CommitInfo::new(block.header.timestamp(), apply_block_result.validation_result_message.clone());
+
+    // store result
+    let (block_json_data, _) = store_applied_block_result(
+        block_storage,
+        block_meta_storage,
+        &block.hash,
+        apply_block_result,
+        block_meta,
+    )?;
+
+    if let Err(e) = context.write().expect("lock poisoning").verify_commit(&context_hash, commit_info) {
+        error!(log, "Context hash verification failed"; "block_header_hash" => block_hash_encoding.bytes_to_string(&block.hash), "reason" => format!("{}", e));
+        return Err(FeedChainError::ContextVerificationError { context_hash: HashType::ContextHash.bytes_to_string(&context_hash) });
+    }
+
+    // notify listeners
+    if apply_block_run.load(Ordering::Acquire) {
+        // notify others that the block successfully applied
+        shell_channel.tell(
+            Publish {
+                msg: BlockApplied::new(block.clone(), block_json_data).into(),
+                topic: ShellChannelTopic::ShellEvents.into(),
+            }, None);
+    }
+
+    Ok(Some(block))
+}
+
+/// Walks back from both heads until their common ancestor is found.
+///
+/// Returns `None` if heads do not have a common ancestor in the storage.
+fn resolve_head_switch(block_meta_storage: &BlockMetaStorage, previous_head: &BlockHash, new_head: &BlockHeaderWithHash) -> Result<Option<HeadSwitched>, StorageError> {
+    let mut rolled_back_blocks = vec![];
+    let mut applied_blocks = vec![];
+
+    let mut previous_branch = match block_meta_storage.get(previous_head)? {
+        Some(meta) => (previous_head.clone(), meta),
+        None => return Ok(None)
+    };
+    let mut new_branch = match block_meta_storage.get(&new_head.hash)? {
+        Some(meta) => (new_head.hash.clone(), meta),
+        None => return Ok(None)
+    };
+
+    while previous_branch.0 != new_branch.0 {
+        let (branch, visited_blocks) = if previous_branch.1.level() >= new_branch.1.level() {
+            (&mut previous_branch, &mut rolled_back_blocks)
+        } else {
+            (&mut new_branch, &mut applied_blocks)
+        };
+
+        let predecessor = match branch.1.predecessor() {
+            // genesis is predecessor of itself, so we cannot go any further
+            Some(predecessor) if predecessor != &branch.0 => predecessor.clone(),
+            _ => return Ok(None)
+        };
+        let predecessor_meta = match block_meta_storage.get(&predecessor)? {
+            Some(meta) => meta,
+            None => return Ok(None)
+        };
+        visited_blocks.push(std::mem::replace(branch, (predecessor, predecessor_meta)).0);
+    }
+
+    rolled_back_blocks.reverse();
+    applied_blocks.reverse();
+
+    Ok(Some(HeadSwitched::new(previous_head.clone(), new_head.clone(), previous_branch.0, rolled_back_blocks, applied_blocks)))
+}
+
 /// This initializes ocaml runtime and protocol context,
 /// if we start with new databazes without genesis,
 /// it ensures correct initialization of storage with genesis and his data.
@@ -411,4 +611,103 @@ pub(crate) fn initialize_protocol_context(
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crypto::hash::chain_id_from_block_hash;
+    use storage::tests_common::TmpStorage;
+    use tezos_messages::p2p::encoding::prelude::*;
+
+    use super::*;
+
+    fn block(predecessor: &BlockHash, level: i32, marker: u8) -> BlockHeaderWithHash {
+        BlockHeaderWithHash::new(
+            BlockHeaderBuilder::default()
+                .level(level)
+                .proto(1)
+                .predecessor(predecessor.clone())
+                .timestamp(i64::from(level))
+                .validation_pass(4)
+                .operations_hash(vec![0; HashType::OperationListListHash.size()])
+                .fitness(vec![vec![0]])
+                .context(vec![0; HashType::ContextHash.size()])
+                .protocol_data(vec![marker])
+                .build().unwrap()
+        ).unwrap()
+    }
+
+    fn store_branch(block_meta_storage: &mut BlockMetaStorage, chain_id: &ChainId, root: &BlockHash, root_level: i32, length: i32, marker: u8) -> Result<Vec<BlockHeaderWithHash>, Error> {
+        let mut branch: Vec<BlockHeaderWithHash> = vec![];
+        for level in root_level + 1..=root_level + length {
+            let predecessor = branch.last().map(|block| block.hash.clone()).unwrap_or_else(|| root.clone());
+            let block = block(&predecessor, level, marker);
+            block_meta_storage.put_block_header(&block, chain_id)?;
+            branch.push(block);
+        }
+        Ok(branch)
+    }
+
+    #[test]
+    fn test_resolve_head_switch_to_fork() -> Result<(), Error> {
+        let tmp_storage = TmpStorage::create("__feeder_resolve_head_switch_to_fork")?;
+        let mut block_meta_storage = BlockMetaStorage::new(tmp_storage.storage());
+        let genesis_hash = vec![0; HashType::BlockHash.size()];
+        let chain_id = chain_id_from_block_hash(&genesis_hash);
+        block_meta_storage.put(&genesis_hash, &Meta::genesis_meta(&genesis_hash, &chain_id, true))?;
+
+        // genesis <- 1 <- 2 <- a3 <- a4
+        //                  \<- b3 <- b4 <- b5
+        let main = store_branch(&mut block_meta_storage, &chain_id, &genesis_hash, 0, 2, 0)?;
+        let fork_a = store_branch(&mut block_meta_storage, &chain_id, &main[1].hash, 2, 2, 1)?;
+        let fork_b = store_branch(&mut block_meta_storage, &chain_id, &main[1].hash, 2, 3, 2)?;
+
+        let head_switched = resolve_head_switch(&block_meta_storage, &fork_a[1].hash, &fork_b[2])?.expect("common ancestor should be found");
+        assert_eq!(&fork_a[1].hash, head_switched.previous_head());
+        assert_eq!(&fork_b[2].hash, &head_switched.new_head().hash);
+        assert_eq!(&main[1].hash, head_switched.common_ancestor());
+        assert_eq!(&vec![fork_a[0].hash.clone(), fork_a[1].hash.clone()], head_switched.rolled_back_blocks());
+        assert_eq!(&vec![fork_b[0].hash.clone(), fork_b[1].hash.clone(), fork_b[2].hash.clone()], head_switched.applied_blocks());
+
+        // switch back to the shorter branch from the longer one
+        let head_switched = resolve_head_switch(&block_meta_storage, &fork_b[2].hash, &fork_a[1])?.expect("common ancestor should be found");
+        assert_eq!(&main[1].hash, head_switched.common_ancestor());
+        assert_eq!(3, head_switched.rolled_back_blocks().len());
+        assert_eq!(2, head_switched.applied_blocks().len());
+
+        // switch to the genesis branch
+        let head_switched = resolve_head_switch(&block_meta_storage, &fork_a[1].hash, &main[0])?.expect("common ancestor should be found");
+        assert_eq!(&main[0].hash, head_switched.common_ancestor());
+        assert_eq!(3, head_switched.rolled_back_blocks().len());
+        assert!(head_switched.applied_blocks().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_head_switch_without_common_ancestor() -> Result<(), Error> {
+        let tmp_storage = TmpStorage::create("__feeder_resolve_head_switch_without_common_ancestor")?;
+        let mut block_meta_storage = BlockMetaStorage::new(tmp_storage.storage());
+        let genesis_hash = vec![0; HashType::BlockHash.size()];
+        let other_genesis_hash = vec![1; HashType::BlockHash.size()];
+        let chain_id = chain_id_from_block_hash(&genesis_hash);
+        block_meta_storage.put(&genesis_hash, &Meta::genesis_meta(&genesis_hash, &chain_id, true))?;
+        block_meta_storage.put(&other_genesis_hash, &Meta::genesis_meta(&other_genesis_hash, &chain_id, true))?;
+
+        // branches starting in different genesis blocks
+        let branch = store_branch(&mut block_meta_storage, &chain_id, &genesis_hash, 0, 2, 0)?;
+        let other_branch = store_branch(&mut block_meta_storage, &chain_id, &other_genesis_hash, 0, 3, 1)?;
+        assert!(resolve_head_switch(&block_meta_storage, &branch[1].hash, &other_branch[2])?.is_none());
+
+        // predecessor of the new head is not stored
+        let unknown_hash = vec![2; HashType::BlockHash.size()];
+        let orphan = block(&unknown_hash, 5, 2);
+        block_meta_storage.put_block_header(&orphan, &chain_id)?;
+        assert!(resolve_head_switch(&block_meta_storage, &branch[1].hash, &orphan)?.is_none());
+
+        // previous head is not stored
+        assert!(resolve_head_switch(&block_meta_storage, &unknown_hash, &branch[1])?.is_none());
+
+        Ok(())
+    }
+}
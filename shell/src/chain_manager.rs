@@ -5,7 +5,9 @@
 //! - tries to download most recent header from the other peers
 //! - also supplies downloaded data to other peers
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use failure::Error;
@@ -13,13 +15,14 @@ use itertools::Itertools;
 use riker::actors::*;
 use slog::{debug, info, trace, warn};
 
-use crypto::hash::{BlockHash, ChainId, HashType};
-use networking::p2p::network_channel::{NetworkChannelMsg, NetworkChannelRef, PeerBootstrapped};
+use crypto::hash::{BlockHash, ChainId, HashType, ProtocolHash};
+use networking::p2p::network_channel::{NetworkChannelMsg, NetworkChannelRef, NetworkChannelTopic, PeerBehaviour, PeerBehaviourObserved, PeerBootstrapped};
 use networking::p2p::peer::{PeerRef, SendMessage};
-use storage::{BlockHeaderWithHash, BlockMetaStorage, BlockStorage, BlockStorageReader, OperationsStorage, OperationsStorageReader, StorageError};
+use storage::{BlockHeaderWithHash, BlockMetaStorage, BlockStorage, BlockStorageReader, OperationKey, OperationsStorage, OperationsStorageReader, ProtocolStorage, StorageError};
 use storage::block_meta_storage::BlockMetaStorageReader;
 use storage::p2p_message_storage::P2PMessageStorage;
 use storage::persistent::PersistentStorage;
+use tezos_messages::base::fitness_comparator::{Fitness, fitness_increases};
 use tezos_messages::p2p::binary_message::MessageHash;
 use tezos_messages::p2p::encoding::prelude::*;
 
@@ -27,6 +30,7 @@ use crate::shell_channel::{AllBlockOperationsReceived, BlockReceived, ShellChann
 use crate::state::block_state::{BlockState, MissingBlock};
 use crate::state::operations_state::{MissingOperations, OperationsState};
 use crate::subscription::*;
+use crate::validation::{BakingRightsResolver, HeaderValidationError, HeaderValidator};
 
 /// Limit to how many blocks to request in a batch
 const BLOCK_HEADERS_BATCH_SIZE: usize = 10;
@@ -74,6 +78,13 @@ struct CurrentHead {
 }
 
 impl CurrentHead {
+    fn need_update_local(&self, new_local_fitness: &Fitness) -> bool {
+        match &self.local {
+            None => true,
+            Some(current_local_head) => fitness_increases(&current_local_head.fitness, new_local_fitness)
+        }
+    }
+
     fn need_update_remote_level(&self, new_remote_level: i32) -> bool {
         match &self.remote {
             None => true,
@@ -103,6 +114,18 @@ struct Head {
     hash: BlockHash,
     /// Level of the head.
     level: i32,
+    /// Fitness of the head.
+    fitness: Fitness,
+}
+
+impl From<&BlockHeaderWithHash> for Head {
+    fn from(block: &BlockHeaderWithHash) -> Self {
+        Head {
+            hash: block.hash.clone(),
+            level: block.header.level(),
+            fitness: block.header.fitness().clone(),
+        }
+    }
 }
 
 impl Head {
@@ -142,12 +165,18 @@ pub struct ChainManager {
     block_meta_storage: Box<dyn BlockMetaStorageReader>,
     /// Operations storage
     operations_storage: Box<dyn OperationsStorageReader>,
+    /// Protocol storage
+    protocol_storage: ProtocolStorage,
+    /// Protocols requested from peers, only requested protocols are stored when received
+    requested_protocols: HashSet<ProtocolHash>,
     /// Msg storage
     p2p_message_storage: P2PMessageStorage,
     /// Holds state of the block chain
     block_state: BlockState,
     /// Holds state of the operations
     operations_state: OperationsState,
+    /// Pre-validation of the received block headers
+    header_validator: HeaderValidator,
     /// Current head information
     current_head: CurrentHead,
     /// Internal stats
@@ -162,7 +191,9 @@ pub type ChainManagerRef = ActorRef<ChainManagerMsg>;
 impl ChainManager {
 
     /// Create new actor instance.
-    pub fn actor(sys: &impl ActorRefFactory, network_channel: NetworkChannelRef, shell_channel: ShellChannelRef, persistent_storage: &PersistentStorage, chain_id: &ChainId) -> Result<ChainManagerRef, CreateError> {
+    ///
+    /// Minimal delays and signatures of the block headers received from peers are verified only, when `baking_rights` are provided.
+    pub fn actor(sys: &impl ActorRefFactory, network_channel: NetworkChannelRef, shell_channel: ShellChannelRef, persistent_storage: &PersistentStorage, chain_id: &ChainId, baking_rights: Option<Arc<dyn BakingRightsResolver>>) -> Result<ChainManagerRef, CreateError> {
         sys.actor_of(
             Props::new_args(
                 ChainManager::new,
@@ -170,7 +201,8 @@ impl ChainManager {
                         network_channel,
                         shell_channel,
                         persistent_storage.clone(),
-                        chain_id.clone()
+                        chain_id.clone(),
+                        baking_rights
                 )
             ),
             ChainManager::name())
@@ -182,16 +214,19 @@ impl ChainManager {
         "chain-manager"
     }
 
-    fn new((network_channel, shell_channel, persistent_storage, chain_id): (NetworkChannelRef, ShellChannelRef, PersistentStorage, ChainId)) -> Self {
+    fn new((network_channel, shell_channel, persistent_storage, chain_id, baking_rights): (NetworkChannelRef, ShellChannelRef, PersistentStorage, ChainId, Option<Arc<dyn BakingRightsResolver>>)) -> Self {
         ChainManager {
             network_channel,
             shell_channel,
             block_storage: Box::new(BlockStorage::new(&persistent_storage)),
             block_meta_storage: Box::new(BlockMetaStorage::new(&persistent_storage)),
             operations_storage: Box::new(OperationsStorage::new(&persistent_storage)),
+            protocol_storage: ProtocolStorage::new(&persistent_storage),
+            requested_protocols: HashSet::new(),
             p2p_message_storage: P2PMessageStorage::new(&persistent_storage),
             block_state: BlockState::new(&persistent_storage, &chain_id),
             operations_state: OperationsState::new(&persistent_storage, &chain_id),
+            header_validator: HeaderValidator::with_supported_protocols(&persistent_storage, &chain_id, baking_rights),
             peers: HashMap::new(),
             current_head: CurrentHead {
                 local: None,
@@ -291,20 +326,24 @@ impl ChainManager {
             peers,
             block_state,
             operations_state,
+            header_validator,
+            network_channel,
             shell_channel,
             block_storage,
             operations_storage,
+            protocol_storage,
+            requested_protocols,
             stats,
             p2p_message_storage,
             ..
         } = self;
 
         match msg {
-            NetworkChannelMsg::PeerBootstrapped(PeerBootstrapped::Success { peer, .. }) => {
+            NetworkChannelMsg::PeerBootstrapped(PeerBootstrapped::Success { peer, address, .. }) => {
                 let log = ctx.system.log().new(slog::o!("peer" => peer.name().to_string()));
 
                 debug!(log, "Requesting current branch");
-                let peer = PeerState::new(peer);
+                let peer = PeerState::new(peer, address);
                 // store peer
                 let actor_uri = peer.peer_ref.uri().clone();
                 self.peers.insert(actor_uri.clone(), peer);
@@ -315,6 +354,7 @@ impl ChainManager {
             NetworkChannelMsg::PeerMessageReceived(received) => {
                 let log = ctx.system.log().new(slog::o!("peer" => received.peer.name().to_string()));
                 let _ = p2p_message_storage.store_peer_message(received.message.messages(), true, received.peer_address).unwrap();
+                let mut deactivated = false;
 
                 match peers.get_mut(received.peer.uri()) {
                     Some(peer) => {
@@ -340,6 +380,7 @@ impl ChainManager {
                                         self.current_head.remote = Some(Head {
                                             hash: message.current_branch().current_head().message_hash()?,
                                             level: message.current_branch().current_head().level(),
+                                            fitness: message.current_branch().current_head().fitness().clone(),
                                         });
                                     }
 
@@ -381,9 +422,25 @@ impl ChainManager {
                                             trace!(log, "Received block header");
                                             peer.block_response_last = Instant::now();
 
+                                            match header_validator.validate(&block_header_with_hash) {
+                                                Ok(()) => (),
+                                                Err(HeaderValidationError::StorageError { error }) => return Err(error.into()),
+                                                Err(error @ HeaderValidationError::BakingRightsNotResolved { .. }) => {
+                                                    // not a fault of the peer, header is validated again before it is applied
+                                                    warn!(log, "Failed to validate block header"; "reason" => error, "block_header_hash" => BLOCK_HASH_ENCODING.bytes_to_string(&block_header_with_hash.hash));
+                                                }
+                                                Err(error) => {
+                                                    warn!(log, "Received invalid block header"; "reason" => error, "block_header_hash" => BLOCK_HASH_ENCODING.bytes_to_string(&block_header_with_hash.hash));
+                                                    tell_peer_behaviour(network_channel, peer, PeerBehaviour::InvalidBlockHeader);
+                                                    ctx.system.stop(received.peer.clone());
+                                                    break;
+                                                }
+                                            }
+                                            tell_peer_behaviour(network_channel, peer, PeerBehaviour::BlockDelivered);
+
                                             let is_new_block =
-                                                block_state.process_block_header(&block_header_with_hash)
-                                                    .and(operations_state.process_block_header(&block_header_with_hash))?;
+                                                block_state.process_block_header(&block_header_with_hash)?
+                                                    && operations_state.process_block_header(&block_header_with_hash)?;
 
                                             if is_new_block {
                                                 // update stats
@@ -422,7 +479,7 @@ impl ChainManager {
                                     if block_state.get_chain_id() == message.chain_id() {
                                         if let Some(current_head_local) = &self.current_head.local {
                                             if let Some(current_head) = block_storage.get(&current_head_local.hash)? {
-                                                let msg = CurrentHeadMessage::new(block_state.get_chain_id().clone(), (*current_head.header).clone());
+                                                let msg = CurrentHeadMessage::new(block_state.get_chain_id().clone(), (*current_head.header).clone(), Mempool::default());
                                                 tell_peer(msg.into(), peer);
                                             }
                                         }
@@ -435,6 +492,7 @@ impl ChainManager {
                                             let operation_was_expected = missing_operations.validation_passes.remove(&operations.operations_for_block().validation_pass());
                                             if operation_was_expected {
                                                 peer.operations_response_last = Instant::now();
+                                                tell_peer_behaviour(network_channel, peer, PeerBehaviour::OperationsDelivered);
                                                 trace!(log, "Received operations validation pass"; "validation_pass" => operations.operations_for_block().validation_pass(), "block_header_hash" => BLOCK_HASH_ENCODING.bytes_to_string(&block_hash));
 
                                                 if operations_state.process_block_operations(&operations)? {
@@ -481,15 +539,57 @@ impl ChainManager {
                                         }
                                     }
                                 }
+                                PeerMessage::GetOperationHashesForBlocks(message) => {
+                                    for get_op_hashes in message.get_operation_hashes_for_blocks() {
+                                        if get_op_hashes.validation_pass() < 0 {
+                                            continue;
+                                        }
+
+                                        let key = OperationKey::new(get_op_hashes.hash(), get_op_hashes.validation_pass() as u8);
+                                        if let Some(op) = operations_storage.get(&key)? {
+                                            let operation_hashes = op.operations().iter()
+                                                .map(|operation| operation.message_hash())
+                                                .collect::<Result<Vec<_>, _>>()?;
+                                            let msg = OperationHashesForBlocksMessage::new(get_op_hashes.clone(), op.operation_hashes_path().clone(), operation_hashes);
+                                            tell_peer(msg.into(), peer);
+                                        }
+                                    }
+                                }
+                                PeerMessage::GetProtocols(message) => {
+                                    for protocol_hash in message.get_protocols() {
+                                        if let Some(protocol) = protocol_storage.get(protocol_hash)? {
+                                            tell_peer(ProtocolMessage::new(protocol).into(), peer);
+                                        }
+                                    }
+                                }
+                                PeerMessage::Protocol(message) => {
+                                    let protocol_hash = message.protocol().message_hash()?;
+                                    if requested_protocols.remove(&protocol_hash) {
+                                        protocol_storage.put(message.protocol())?;
+                                        debug!(log, "Received protocol"; "protocol_hash" => HashType::ProtocolHash.bytes_to_string(&protocol_hash));
+                                    } else {
+                                        debug!(log, "Ignoring protocol, which was not requested"; "protocol_hash" => HashType::ProtocolHash.bytes_to_string(&protocol_hash));
+                                    }
+                                }
                                 PeerMessage::Bootstrap => {
                                     // on bootstrap reset peer state
                                 }
+                                PeerMessage::Deactivate(message) => {
+                                    if block_state.get_chain_id() == message.deactivate() {
+                                        info!(log, "Peer deactivated our chain, chain will not be synced with the peer");
+                                        deactivated = true;
+                                    }
+                                }
                                 ignored_message => trace!(log, "Ignored message"; "message" => format!("{:?}", ignored_message))
                             }
                         }
                     }
                     None => debug!(log, "Received message from non-existing peer")
                 }
+
+                if deactivated {
+                    self.remove_peer(received.peer.uri());
+                }
             }
             _ => (),
         }
@@ -497,15 +597,40 @@ impl ChainManager {
         Ok(())
     }
 
+    /// Stop syncing the chain with the peer, queued blocks and operations are returned to the missing queues
+    fn remove_peer(&mut self, uri: &ActorUri) {
+        if let Some(mut peer) = self.peers.remove(uri) {
+            peer.queued_block_headers
+                .drain()
+                .for_each(|(_, missing_block)| {
+                    self.block_state.push_missing_block(missing_block).expect("Failed to re-schedule block hash");
+                });
+
+            self.operations_state.push_missing_operations(peer.queued_operations.drain().map(|(_, op)| op))
+                .expect("Failed to return to queue")
+        }
+    }
+
     fn process_shell_channel_message(&mut self, ctx: &Context<ChainManagerMsg>, msg: ShellChannelMsg) -> Result<(), Error> {
         match msg {
             ShellChannelMsg::BlockApplied(message) => {
-                self.current_head.local = Some(Head {
-                    hash: message.header().hash.clone(),
-                    level: message.header().header.level(),
-                });
+                // applied block can be also a part of the side branch, so we move local head only if fitness increases
+                if self.current_head.need_update_local(message.header().header.fitness()) {
+                    self.current_head.local = Some(message.header().into());
+                }
                 self.stats.applied_block_level = Some(message.header().header.level());
                 self.stats.applied_block_last = Some(Instant::now());
+
+                if let Some(protocol_hash) = next_protocol(message.json_data().block_header_proto_metadata_json()) {
+                    self.request_protocol(protocol_hash)?;
+                }
+            }
+            ShellChannelMsg::HeadSwitched(message) => {
+                info!(ctx.system.log(), "Local head switched to another branch";
+                    "previous_head" => BLOCK_HASH_ENCODING.bytes_to_string(message.previous_head()),
+                    "new_head" => BLOCK_HASH_ENCODING.bytes_to_string(&message.new_head().hash),
+                    "new_head_level" => message.new_head().header.level());
+                self.current_head.local = Some(message.new_head().into());
             }
             ShellChannelMsg::ShuttingDown(_) => {
                 self.shutting_down = true;
@@ -517,6 +642,18 @@ impl ChainManager {
         Ok(())
     }
 
+    /// Ask peers for sources of the protocol, which we do not have yet, so we can serve it to other peers
+    fn request_protocol(&mut self, protocol_hash: ProtocolHash) -> Result<(), Error> {
+        if self.peers.is_empty() || self.requested_protocols.contains(&protocol_hash) || self.protocol_storage.contains(&protocol_hash)? {
+            return Ok(());
+        }
+
+        self.peers.values_mut()
+            .for_each(|peer| tell_peer(GetProtocolsMessage::new(vec![protocol_hash.clone()]).into(), peer));
+        self.requested_protocols.insert(protocol_hash);
+        Ok(())
+    }
+
     fn hydrate_state(&mut self, ctx: &Context<ChainManagerMsg>) {
         info!(ctx.system.log(), "Hydrating block state");
         self.block_state.hydrate().expect("Failed to hydrate block state");
@@ -530,10 +667,7 @@ impl ChainManager {
                 self.block_storage
                     .get(&hash)
                     .expect(&format!("Failed to read head: {}", BLOCK_HASH_ENCODING.bytes_to_string(&hash)))
-                    .map(|block| Head {
-                        hash: block.hash.clone(),
-                        level: block.header.level(),
-                    })
+                    .map(|block| Head::from(&block))
             }
             None => None
         };
@@ -603,16 +737,7 @@ impl Receive<SystemEvent> for ChainManager {
 
     fn receive(&mut self, _ctx: &Context<Self::Msg>, msg: SystemEvent, _sender: Option<BasicActorRef>) {
         if let SystemEvent::ActorTerminated(evt) = msg {
-            if let Some(mut peer) = self.peers.remove(evt.actor.uri()) {
-                peer.queued_block_headers
-                    .drain()
-                    .for_each(|(_, missing_block)| {
-                        self.block_state.push_missing_block(missing_block).expect("Failed to re-schedule block hash");
-                    });
-
-                self.operations_state.push_missing_operations(peer.queued_operations.drain().map(|(_, op)| op))
-                    .expect("Failed to return to queue")
-            }
+            self.remove_peer(evt.actor.uri());
         }
     }
 }
@@ -687,6 +812,7 @@ impl Receive<DisconnectStalledPeers> for ChainManager {
                 };
 
                 if should_disconnect {
+                    tell_peer_behaviour(&self.network_channel, state, PeerBehaviour::Timeout);
                     ctx.system.stop(state.peer_ref.clone());
                 }
             });
@@ -744,6 +870,8 @@ impl Receive<AskPeersAboutCurrentBranch> for ChainManager {
 struct PeerState {
     /// Reference to peer actor
     peer_ref: PeerRef,
+    /// Peer address
+    address: SocketAddr,
     /// Queued blocks
     queued_block_headers: HashMap<BlockHash, MissingBlock>,
     /// Queued operations
@@ -764,9 +892,10 @@ struct PeerState {
 }
 
 impl PeerState {
-    fn new(peer_ref: PeerRef) -> Self {
+    fn new(peer_ref: PeerRef, address: SocketAddr) -> Self {
         PeerState {
             peer_ref,
+            address,
             queued_block_headers: HashMap::new(),
             queued_operations: HashMap::new(),
             current_head_level: None,
@@ -797,6 +926,39 @@ impl PeerState {
     }
 }
 
+/// Extract hash of the protocol of the next block from the block metadata json
+fn next_protocol(block_header_proto_metadata_json: &str) -> Option<ProtocolHash> {
+    let metadata: serde_json::Value = serde_json::from_str(block_header_proto_metadata_json).ok()?;
+    let next_protocol = metadata.get("next_protocol")?.as_str()?;
+    HashType::ProtocolHash.string_to_bytes(next_protocol).ok()
+}
+
 fn tell_peer(msg: PeerMessageResponse, peer: &mut PeerState) {
     peer.peer_ref.tell(SendMessage::new(msg), None);
-}
\ No newline at end of file
+}
+
+/// Notify peer manager about the behaviour of the peer, so the peer can be scored
+fn tell_peer_behaviour(network_channel: &NetworkChannelRef, peer: &PeerState, behaviour: PeerBehaviour) {
+    network_channel.tell(
+        Publish {
+            msg: PeerBehaviourObserved { address: peer.address, behaviour }.into(),
+            topic: NetworkChannelTopic::NetworkEvents.into(),
+        }, None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_protocol() {
+        let metadata = r#"{"protocol":"PsBabyM1eUXZseaJdmXFApDSBqj8YBfwELoxZHHW77EMcAbbwAS","next_protocol":"PsCARTHAGazKbHtnKfLzQg3kms52kSRpgnDY982a9oYsSXRLQEb"}"#;
+        assert_eq!(
+            Some(HashType::ProtocolHash.string_to_bytes("PsCARTHAGazKbHtnKfLzQg3kms52kSRpgnDY982a9oYsSXRLQEb").unwrap()),
+            next_protocol(metadata)
+        );
+        assert_eq!(None, next_protocol(r#"{"protocol":"PsBabyM1eUXZseaJdmXFApDSBqj8YBfwELoxZHHW77EMcAbbwAS"}"#));
+        assert_eq!(None, next_protocol(r#"{"next_protocol":"invalid"}"#));
+        assert_eq!(None, next_protocol(""));
+    }
+}
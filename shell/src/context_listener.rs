@@ -10,10 +10,10 @@ use std::thread::JoinHandle;
 
 use failure::Error;
 use riker::actors::*;
-use slog::{crit, debug, Logger, warn};
+use slog::{crit, debug, error, Logger, warn};
 
-use storage::{BlockStorage, ContextActionStorage};
-use storage::context::{ContextApi, ContextDiff, TezedgeContext};
+use storage::ContextActionStorage;
+use storage::context::{ContextApiRef, ContextDiff};
 use storage::persistent::PersistentStorage;
 use tezos_context::channel::ContextAction;
 use tezos_wrapper::service::IpcEvtServer;
@@ -37,23 +37,22 @@ impl ContextListener {
     /// Create new actor instance.
     ///
     /// This actor spawns a new thread in which it listens for incoming events from the `protocol_runner`.
-    /// Events are received from IPC channel provided by [`event_server`](IpcEvtServer).
-    pub fn actor(sys: &impl ActorRefFactory, persistent_storage: &PersistentStorage, mut event_server: IpcEvtServer, log: Logger) -> Result<ContextListenerRef, CreateError> {
-        let context_storage = persistent_storage.context_storage();
+    /// Events are received from IPC channel provided by [`event_server`](IpcEvtServer)
+    /// and applied to the [`context`](ContextApiRef) selected at startup.
+    pub fn actor(sys: &impl ActorRefFactory, persistent_storage: &PersistentStorage, context: ContextApiRef, mut event_server: IpcEvtServer, log: Logger) -> Result<ContextListenerRef, CreateError> {
         let listener_run = Arc::new(AtomicBool::new(true));
         let block_applier_thread = {
             let listener_run = listener_run.clone();
             let persistent_storage = persistent_storage.clone();
 
             thread::spawn(move || {
-                let mut context: Box<dyn ContextApi> = Box::new(TezedgeContext::new(BlockStorage::new(&persistent_storage), context_storage));
                 let mut context_action_storage = ContextActionStorage::new(&persistent_storage);
                 while listener_run.load(Ordering::Acquire) {
                     match listen_protocol_events(
                         &listener_run,
                         &mut event_server,
                         &mut context_action_storage,
-                        &mut context,
+                        &context,
                         &log,
                     ) {
                         Ok(()) => debug!(log, "Context listener finished"),
@@ -110,7 +109,7 @@ fn listen_protocol_events(
     apply_block_run: &AtomicBool,
     event_server: &mut IpcEvtServer,
     context_action_storage: &mut ContextActionStorage,
-    context: &mut Box<dyn ContextApi>,
+    context: &ContextApiRef,
     log: &Logger,
 ) -> Result<(), Error> {
     debug!(log, "Waiting for connection from protocol runner");
@@ -119,7 +118,7 @@ fn listen_protocol_events(
 
     let mut event_count = 0;
 
-    let mut context_diff: ContextDiff = context.init_from_start();
+    let mut context_diff: ContextDiff = context.read().expect("lock poisoning").init_from_start();
 
     while apply_block_run.load(Ordering::Acquire) {
         match rx.receive() {
@@ -147,27 +146,32 @@ fn listen_protocol_events(
                     }
                     ContextAction::Copy { block_hash: Some(block_hash), to_key: key, from_key, context_hash, ignored, .. } => {
                         if !ignored {
-                            context.copy_to_diff(context_hash, from_key, key, &mut context_diff)?;
+                            context.read().expect("lock poisoning").copy_to_diff(context_hash, from_key, key, &mut context_diff)?;
                         }
                         context_action_storage.put_action(&block_hash.clone(), msg)?;
                     }
                     | ContextAction::Delete { block_hash: Some(block_hash), key, context_hash, ignored, .. } => {
                         if !ignored {
-                            context.delete_to_diff(context_hash, key, &mut context_diff)?;
+                            context.read().expect("lock poisoning").delete_to_diff(context_hash, key, &mut context_diff)?;
                         }
                         context_action_storage.put_action(&block_hash.clone(), msg)?;
                     }
                     | ContextAction::RemoveRecursively { block_hash: Some(block_hash), key, context_hash, ignored, .. } => {
                         if !ignored {
-                            context.remove_recursively_to_diff(context_hash, key, &mut context_diff)?;
+                            context.read().expect("lock poisoning").remove_recursively_to_diff(context_hash, key, &mut context_diff)?;
                         }
                         context_action_storage.put_action(&block_hash.clone(), msg)?;
                     }
                     ContextAction::Commit { parent_context_hash, new_context_hash, block_hash: Some(block_hash), .. } => {
-                        context.commit(block_hash, parent_context_hash, new_context_hash, &context_diff)?;
+                        match context.write().expect("lock poisoning").commit(block_hash, parent_context_hash, new_context_hash, &context_diff) {
+                            Ok(()) => (),
+                            // diff is already committed, so we can continue with the next block
+                            Err(e) if e.is_verification_error() => error!(log, "Context hash verification failed"; "block_hash" => HashType::BlockHash.bytes_to_string(block_hash), "reason" => format!("{}", e)),
+                            Err(e) => return Err(e.into()),
+                        }
                     }
                     ContextAction::Checkout { context_hash, .. } => {
-                        context_diff = context.checkout(context_hash)?;
+                        context_diff = context.read().expect("lock poisoning").checkout(context_hash)?;
                         event_count = 0;
                     }
                     ContextAction::Mem { block_hash: Some(block_hash), .. }
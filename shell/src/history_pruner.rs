@@ -0,0 +1,208 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Prunes data of old blocks in the rolling history mode.
+//!
+//! Only the data of the current cycle and of the configured number of preceding cycles are kept.
+//! Cycle boundaries are resolved from the metadata of applied blocks.
+
+use std::cmp;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::thread;
+use std::thread::JoinHandle;
+
+use failure::Error;
+use riker::actors::*;
+use serde_json::Value;
+use slog::{info, Logger, warn};
+
+use storage::persistent::PersistentStorage;
+use storage::pruning::StoragePruner;
+use storage::StorageError;
+
+use crate::shell_channel::{ShellChannelMsg, ShellChannelRef};
+use crate::subscription::subscribe_to_shell_events;
+
+type SharedJoinHandle = Arc<Mutex<Option<JoinHandle<Result<(), Error>>>>>;
+
+/// Deletes operations, json data and context actions of blocks older than configured number of cycles
+/// together with branches abandoned before them.
+#[actor(ShellChannelMsg)]
+pub struct HistoryPruner {
+    /// All events from shell will be published to this channel
+    shell_channel: ShellChannelRef,
+    /// Number of cycles preceding the current cycle, which are kept
+    cycles: u32,
+    /// Blocks bellow this level should be pruned
+    prune_below_level: Arc<AtomicI32>,
+    /// Thread where blocks are pruned will run until this is set to `false`
+    pruner_run: Arc<AtomicBool>,
+    /// Pruner thread
+    pruner_thread: SharedJoinHandle,
+}
+
+/// Reference to [history pruner](HistoryPruner) actor
+pub type HistoryPrunerRef = ActorRef<HistoryPrunerMsg>;
+
+impl HistoryPruner {
+    /// Create new actor instance.
+    ///
+    /// Pruning itself can take a long time, so it is done in a separate thread.
+    pub fn actor(sys: &impl ActorRefFactory, shell_channel: ShellChannelRef, persistent_storage: &PersistentStorage, cycles: u32, log: Logger) -> Result<HistoryPrunerRef, CreateError> {
+        let prune_below_level = Arc::new(AtomicI32::new(0));
+        let pruner_run = Arc::new(AtomicBool::new(true));
+        let pruner_thread = {
+            let prune_below_level = prune_below_level.clone();
+            let pruner_run = pruner_run.clone();
+            let persistent_storage = persistent_storage.clone();
+
+            thread::spawn(move || {
+                let mut pruner = StoragePruner::new(&persistent_storage);
+
+                while pruner_run.load(Ordering::Acquire) {
+                    match prune(&mut pruner, &prune_below_level, &pruner_run) {
+                        Ok(0) => (),
+                        Ok(pruned_blocks) => info!(log, "Data of old blocks were pruned"; "pruned_blocks" => pruned_blocks, "prune_below_level" => prune_below_level.load(Ordering::Acquire)),
+                        Err(e) => warn!(log, "Failed to prune data of old blocks"; "reason" => e),
+                    }
+
+                    // wait until the next cycle is reached
+                    thread::park();
+                }
+
+                Ok(())
+            })
+        };
+
+        sys.actor_of(
+            Props::new_args(HistoryPruner::new, (shell_channel, cycles, prune_below_level, pruner_run, Arc::new(Mutex::new(Some(pruner_thread))))),
+            HistoryPruner::name())
+    }
+
+    /// The `HistoryPruner` is intended to serve as a singleton actor so that's why
+    /// we won't support multiple names per instance.
+    fn name() -> &'static str {
+        "history-pruner"
+    }
+
+    fn new((shell_channel, cycles, prune_below_level, pruner_run, pruner_thread): (ShellChannelRef, u32, Arc<AtomicI32>, Arc<AtomicBool>, SharedJoinHandle)) -> Self {
+        HistoryPruner { shell_channel, cycles, prune_below_level, pruner_run, pruner_thread }
+    }
+
+    fn process_shell_channel_message(&mut self, msg: ShellChannelMsg) {
+        match msg {
+            ShellChannelMsg::BlockApplied(block) => {
+                if let Some(prune_below_level) = resolve_prune_below_level(block.json_data().block_header_proto_metadata_json(), self.cycles) {
+                    if prune_below_level > self.prune_below_level.load(Ordering::Acquire) {
+                        self.prune_below_level.store(prune_below_level, Ordering::Release);
+                        if let Some(join_handle) = self.pruner_thread.lock().unwrap().as_ref() {
+                            join_handle.thread().unpark();
+                        }
+                    }
+                }
+            }
+            ShellChannelMsg::ShuttingDown(_) => {
+                self.pruner_run.store(false, Ordering::Release);
+            }
+            _ => ()
+        }
+    }
+}
+
+impl Actor for HistoryPruner {
+    type Msg = HistoryPrunerMsg;
+
+    fn pre_start(&mut self, ctx: &Context<Self::Msg>) {
+        subscribe_to_shell_events(&self.shell_channel, ctx.myself());
+    }
+
+    fn post_stop(&mut self) {
+        self.pruner_run.store(false, Ordering::Release);
+
+        let join_handle = self.pruner_thread.lock().unwrap()
+            .take().expect("Thread join handle is missing");
+        join_handle.thread().unpark();
+        let _ = join_handle.join().expect("Failed to join pruner thread");
+    }
+
+    fn recv(&mut self, ctx: &Context<Self::Msg>, msg: Self::Msg, sender: Sender) {
+        self.receive(ctx, msg, sender);
+    }
+}
+
+impl Receive<ShellChannelMsg> for HistoryPruner {
+    type Msg = HistoryPrunerMsg;
+
+    fn receive(&mut self, _ctx: &Context<Self::Msg>, msg: ShellChannelMsg, _sender: Sender) {
+        self.process_shell_channel_message(msg)
+    }
+}
+
+/// Prune all levels bellow `prune_below_level`, which were not pruned yet, and returns number of pruned levels.
+///
+/// Levels, where the main chain can still be replaced by a fork, are never pruned.
+fn prune(pruner: &mut StoragePruner, prune_below_level: &AtomicI32, pruner_run: &AtomicBool) -> Result<usize, StorageError> {
+    let prune_below_level = cmp::min(prune_below_level.load(Ordering::Acquire), pruner.last_allowed_fork_level()?);
+    let mut level = pruner.pruned_level()?.map(|pruned_level| pruned_level + 1).unwrap_or(0);
+    let mut pruned_levels = 0;
+
+    while level < prune_below_level && pruner_run.load(Ordering::Acquire) {
+        pruner.prune_level(level)?;
+        pruned_levels += 1;
+        level += 1;
+    }
+
+    if pruned_levels > 0 {
+        pruner.compact()?;
+    }
+
+    Ok(pruned_levels)
+}
+
+/// Resolve the first level, which should be kept, from the block metadata.
+///
+/// Metadata contains the `level` object with the position of the block in the current cycle
+/// (`cycle`, `cycle_position`) and in the whole chain (`level_position`), from which the number
+/// of blocks per cycle is calculated.
+fn resolve_prune_below_level(block_metadata_json: &str, cycles: u32) -> Option<i32> {
+    let metadata: Value = serde_json::from_str(block_metadata_json).ok()?;
+    let level = &metadata["level"];
+    let block_level = level["level"].as_i64()?;
+    let level_position = level["level_position"].as_i64()?;
+    let cycle = level["cycle"].as_i64()?;
+    let cycle_position = level["cycle_position"].as_i64()?;
+
+    if cycle < i64::from(cycles) || cycle == 0 {
+        return None;
+    }
+
+    let blocks_per_cycle = (level_position - cycle_position) / cycle;
+    let current_cycle_first_level = block_level - cycle_position;
+    Some((current_cycle_first_level - i64::from(cycles) * blocks_per_cycle) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(level: i64, cycle: i64, cycle_position: i64) -> String {
+        format!(r#"{{"protocol":"PsCARTHAGazKbHtnKfLzQg3kms52kSRpgnDY982a9oYsSXRLQEb","level":{{"level":{},"level_position":{},"cycle":{},"cycle_position":{},"voting_period":0,"voting_period_position":0,"expected_commitment":false}}}}"#,
+                level, level - 1, cycle, cycle_position)
+    }
+
+    #[test]
+    fn test_resolve_prune_below_level() {
+        // block 10 of the cycle 5, cycle has 2048 blocks
+        assert_eq!(Some(2 * 2048 + 1), resolve_prune_below_level(&metadata(5 * 2048 + 11, 5, 10), 3));
+        assert_eq!(Some(4 * 2048 + 1), resolve_prune_below_level(&metadata(5 * 2048 + 11, 5, 10), 1));
+    }
+
+    #[test]
+    fn test_resolve_prune_below_level_too_early() {
+        assert_eq!(None, resolve_prune_below_level(&metadata(2 * 2048 + 11, 2, 10), 3));
+        assert_eq!(None, resolve_prune_below_level(&metadata(11, 0, 10), 1));
+        assert_eq!(None, resolve_prune_below_level("", 1));
+        assert_eq!(None, resolve_prune_below_level("{}", 1));
+    }
+}
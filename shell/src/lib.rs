@@ -11,7 +11,12 @@ pub mod shell_channel;
 pub mod chain_feeder;
 pub mod context_listener;
 pub mod chain_manager;
+pub mod mempool;
+pub mod mempool_prevalidator;
 pub mod peer_manager;
+pub mod history_pruner;
+pub mod protocol;
+pub mod validation;
 
 pub(crate) mod subscription {
     use riker::actors::*;
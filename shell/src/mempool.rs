@@ -0,0 +1,415 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Manages mempool of the node.
+//! - collects operations advertised by peers in their current head messages
+//! - sends operations to the protocol for prevalidation and keeps them sorted by the validation result
+//! - advertises known valid operations to peers
+//! - removes operations which were included in applied blocks
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use failure::Error;
+use riker::actors::*;
+use slog::{debug, trace, warn};
+
+use crypto::hash::{BlockHash, ChainId, HashType, OperationHash};
+use networking::p2p::network_channel::{NetworkChannelMsg, NetworkChannelRef, PeerBootstrapped};
+use networking::p2p::peer::{PeerRef, SendMessage};
+use storage::{BlockHeaderWithHash, BlockMetaStorage, BlockMetaStorageReader, BlockStorage, BlockStorageReader, OperationsStorage, OperationsStorageReader};
+use storage::persistent::PersistentStorage;
+use tezos_api::ffi::OperationClassification;
+use tezos_messages::base::fitness_comparator::fitness_increases;
+use tezos_messages::p2p::binary_message::MessageHash;
+use tezos_messages::p2p::encoding::prelude::*;
+
+pub use crate::state::mempool_state::{MempoolOperation, MempoolState};
+
+use crate::shell_channel::{MempoolOperationReceived, ShellChannelMsg, ShellChannelRef, ShellChannelTopic};
+use crate::subscription::*;
+
+/// How often to advertise known valid operations to peers
+const ADVERTISE_MEMPOOL_INTERVAL: Duration = Duration::from_secs(5);
+/// After this time requested operation can be requested again
+const OPERATION_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Mempool of the peer is processed only if its current head is not too far ahead of our current head,
+/// otherwise we are most likely still bootstrapping and operations cannot be validated anyway.
+const MAX_PEER_HEAD_LEVEL_AHEAD: i32 = 1;
+const OPERATION_HASH_ENCODING: HashType = HashType::OperationHash;
+
+/// Thread safe reference to a shared mempool state
+pub type MempoolStateRef = Arc<RwLock<MempoolState>>;
+
+/// Message commands [`MempoolManager`] to advertise its known valid operations to peers.
+#[derive(Clone, Debug)]
+pub struct AdvertiseMempool;
+
+/// Add operation, which was not received from a peer (e.g. it was injected by RPC), to the mempool and send it for validation.
+///
+/// Operation is propagated to the peers by the [`MempoolManager`], if it is applied. Returns hash of the operation.
+pub fn inject_operation(operation: Operation, shell_channel: &ShellChannelRef, mempool_state: &MempoolStateRef) -> Result<OperationHash, Error> {
+    let operation_hash: OperationHash = operation.message_hash()?;
+    if mempool_state.write().unwrap().add_unprocessed(operation_hash.clone(), operation.clone()) {
+        shell_channel.tell(
+            Publish {
+                msg: MempoolOperationReceived {
+                    operation_hash: operation_hash.clone(),
+                    operation,
+                }.into(),
+                topic: ShellChannelTopic::ShellEvents.into(),
+            }, None);
+    }
+    Ok(operation_hash)
+}
+
+/// Purpose of this actor is to collect, validate and propagate mempool operations.
+#[actor(AdvertiseMempool, NetworkChannelMsg, ShellChannelMsg, SystemEvent)]
+pub struct MempoolManager {
+    /// All events generated by the network layer will end up in this channel
+    network_channel: NetworkChannelRef,
+    /// All events from shell will be published to this channel
+    shell_channel: ShellChannelRef,
+    /// Connected peers
+    peers: HashMap<ActorUri, PeerRef>,
+    /// Block storage
+    block_storage: Box<dyn BlockStorageReader>,
+    /// Block meta storage
+    block_meta_storage: Box<dyn BlockMetaStorageReader>,
+    /// Operations storage
+    operations_storage: Box<dyn OperationsStorageReader>,
+    /// Chain, for which the mempool is maintained
+    chain_id: ChainId,
+    /// Local current head, operations are validated on top of it
+    current_head: Option<BlockHeaderWithHash>,
+    /// Mempool operations
+    state: MempoolStateRef,
+    /// Indicates that known valid operations changed since they were advertised last time
+    mempool_changed: bool,
+    /// Indicates that system is shutting down
+    shutting_down: bool,
+}
+
+/// Reference to [mempool manager](MempoolManager) actor.
+pub type MempoolManagerRef = ActorRef<MempoolManagerMsg>;
+
+impl MempoolManager {
+    /// Create new actor instance.
+    pub fn actor(sys: &impl ActorRefFactory, network_channel: NetworkChannelRef, shell_channel: ShellChannelRef, persistent_storage: &PersistentStorage, chain_id: &ChainId, mempool_state: MempoolStateRef) -> Result<MempoolManagerRef, CreateError> {
+        sys.actor_of(
+            Props::new_args(
+                MempoolManager::new,
+                (
+                    network_channel,
+                    shell_channel,
+                    persistent_storage.clone(),
+                    chain_id.clone(),
+                    mempool_state,
+                )
+            ),
+            MempoolManager::name())
+    }
+
+    /// The `MempoolManager` is intended to serve as a singleton actor so that's why
+    /// we won't support multiple names per instance.
+    fn name() -> &'static str {
+        "mempool-manager"
+    }
+
+    fn new((network_channel, shell_channel, persistent_storage, chain_id, state): (NetworkChannelRef, ShellChannelRef, PersistentStorage, ChainId, MempoolStateRef)) -> Self {
+        MempoolManager {
+            network_channel,
+            shell_channel,
+            peers: HashMap::new(),
+            block_storage: Box::new(BlockStorage::new(&persistent_storage)),
+            block_meta_storage: Box::new(BlockMetaStorage::new(&persistent_storage)),
+            operations_storage: Box::new(OperationsStorage::new(&persistent_storage)),
+            chain_id,
+            current_head: None,
+            state,
+            mempool_changed: false,
+            shutting_down: false,
+        }
+    }
+
+    fn process_network_channel_message(&mut self, ctx: &Context<MempoolManagerMsg>, msg: NetworkChannelMsg) -> Result<(), Error> {
+        match msg {
+            NetworkChannelMsg::PeerBootstrapped(PeerBootstrapped::Success { peer, .. }) => {
+                self.peers.insert(peer.uri().clone(), peer);
+            }
+            NetworkChannelMsg::PeerMessageReceived(received) => {
+                let log = ctx.system.log().new(slog::o!("peer" => received.peer.name().to_string()));
+
+                for message in received.message.messages() {
+                    match message {
+                        PeerMessage::CurrentHead(message) => {
+                            if message.chain_id() != &self.chain_id || !self.accepts_mempool_of(message.current_block_header()) {
+                                continue;
+                            }
+
+                            let mempool = message.current_mempool();
+                            let requested_operations = {
+                                let state = &mut *self.state.write().unwrap();
+                                mempool.known_valid().iter()
+                                    .chain(mempool.pending().iter())
+                                    .filter(|operation_hash| state.register_requested((*operation_hash).clone()))
+                                    .cloned()
+                                    .collect::<Vec<_>>()
+                            };
+
+                            if !requested_operations.is_empty() {
+                                trace!(log, "Requesting mempool operations"; "count" => requested_operations.len());
+                                received.peer.tell(SendMessage::new(GetOperationsMessage::new(requested_operations).into()), None);
+                            }
+                        }
+                        PeerMessage::Operation(message) => {
+                            let operation = message.operation();
+                            let operation_hash: OperationHash = operation.message_hash()?;
+                            if self.state.write().unwrap().process_received(operation_hash.clone(), operation.clone()) {
+                                trace!(log, "Received mempool operation"; "operation_hash" => OPERATION_HASH_ENCODING.bytes_to_string(&operation_hash));
+                                self.schedule_validation(ctx, operation_hash, operation.clone());
+                            }
+                        }
+                        PeerMessage::GetOperations(message) => {
+                            let state = self.state.read().unwrap();
+                            for operation_hash in message.get_operations() {
+                                // operations already included in blocks are not in the mempool anymore
+                                let operation = match state.find_operation(operation_hash) {
+                                    Some(operation) => Some(operation.clone()),
+                                    None => self.operations_storage.find_operation(operation_hash)?,
+                                };
+                                if let Some(operation) = operation {
+                                    let msg: OperationMessage = operation.into();
+                                    received.peer.tell(SendMessage::new(msg.into()), None);
+                                }
+                            }
+                        }
+                        _ => ()
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn process_shell_channel_message(&mut self, ctx: &Context<MempoolManagerMsg>, msg: ShellChannelMsg) -> Result<(), Error> {
+        match msg {
+            ShellChannelMsg::MempoolOperationValidated(message) => {
+                let accepted = self.state.write().unwrap().process_result(message.operation_hash(), message.result());
+                if accepted && message.result().classification == OperationClassification::Applied {
+                    // new valid operations (e.g. injected ones) are propagated immediately
+                    self.mempool_changed = true;
+                    self.advertise_mempool();
+                }
+            }
+            ShellChannelMsg::BlockApplied(message) => {
+                let is_new_head = match &self.current_head {
+                    Some(current_head) => fitness_increases(current_head.header.fitness(), message.header().header.fitness()),
+                    None => true,
+                };
+                // operations are validated again only when the new head extends the current one, head switch is handled separately
+                let extends_current_head = match &self.current_head {
+                    Some(current_head) => message.header().header.predecessor() == &current_head.hash,
+                    None => true,
+                };
+                if is_new_head {
+                    self.current_head = Some(message.header().clone());
+                    self.remove_block_operations(&message.header().hash)?;
+                    if extends_current_head {
+                        self.reclassify_operations(ctx, false);
+                    }
+                }
+            }
+            ShellChannelMsg::HeadSwitched(message) => {
+                self.current_head = Some(message.new_head().clone());
+
+                // operations from rolled back blocks are not included in the chain anymore, so they are returned to the mempool
+                for block_hash in message.rolled_back_blocks() {
+                    for operation in self.block_operations(block_hash)? {
+                        let operation_hash = operation.message_hash()?;
+                        if self.state.write().unwrap().add_unprocessed(operation_hash.clone(), operation.clone()) {
+                            self.schedule_validation(ctx, operation_hash, operation);
+                        }
+                    }
+                }
+                for block_hash in message.applied_blocks() {
+                    self.remove_block_operations(block_hash)?;
+                }
+                self.reclassify_operations(ctx, true);
+            }
+            ShellChannelMsg::ShuttingDown(_) => {
+                self.shutting_down = true;
+            }
+            _ => ()
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if mempool advertised together with the `current_head` of the peer should be processed
+    fn accepts_mempool_of(&self, peer_current_head: &BlockHeader) -> bool {
+        match &self.current_head {
+            Some(current_head) => peer_current_head.level() - current_head.header.level() <= MAX_PEER_HEAD_LEVEL_AHEAD,
+            None => false,
+        }
+    }
+
+    /// Send operation to the protocol for validation
+    fn schedule_validation(&self, ctx: &Context<MempoolManagerMsg>, operation_hash: OperationHash, operation: Operation) {
+        self.shell_channel.tell(
+            Publish {
+                msg: MempoolOperationReceived {
+                    operation_hash,
+                    operation,
+                }.into(),
+                topic: ShellChannelTopic::ShellEvents.into(),
+            }, Some(ctx.myself().into()));
+    }
+
+    /// Validate mempool operations again, because current head was changed
+    fn reclassify_operations(&mut self, ctx: &Context<MempoolManagerMsg>, branch_switched: bool) {
+        let operations = self.state.write().unwrap().reclassify(branch_switched);
+        self.mempool_changed = true;
+
+        for (operation_hash, operation) in operations {
+            self.schedule_validation(ctx, operation_hash, operation);
+        }
+    }
+
+    /// Remove operations included in the block from the mempool
+    fn remove_block_operations(&mut self, block_hash: &BlockHash) -> Result<(), Error> {
+        let operation_hashes = self.block_operations(block_hash)?.iter()
+            .map(|operation| operation.message_hash())
+            .collect::<Result<Vec<_>, _>>()?;
+        self.state.write().unwrap().remove_operations(&operation_hashes);
+        Ok(())
+    }
+
+    fn block_operations(&self, block_hash: &BlockHash) -> Result<Vec<Operation>, Error> {
+        Ok(
+            self.operations_storage.get_operations(block_hash)?
+                .drain(..)
+                .flat_map(|operations_for_block| operations_for_block.operations().clone())
+                .collect()
+        )
+    }
+
+    /// Advertise known valid operations to peers, if they changed since the last advertisement
+    fn advertise_mempool(&mut self) {
+        // changes are kept until there is some peer to advertise them to
+        if !self.mempool_changed || self.peers.is_empty() {
+            return;
+        }
+
+        if let Some(current_head) = &self.current_head {
+            let known_valid = self.state.read().unwrap().known_valid();
+            let msg = CurrentHeadMessage::new(self.chain_id.clone(), (*current_head.header).clone(), Mempool::new(known_valid, vec![]));
+            let msg = SendMessage::new(msg.into());
+            self.peers.values()
+                .for_each(|peer| peer.tell(msg.clone(), None));
+            self.mempool_changed = false;
+        }
+    }
+
+    fn load_current_head(&mut self, ctx: &Context<MempoolManagerMsg>) {
+        self.current_head = match self.block_meta_storage.load_current_head() {
+            Ok(Some(block_hash)) => match self.block_storage.get(&block_hash) {
+                Ok(block) => block,
+                Err(e) => {
+                    warn!(ctx.system.log(), "Failed to read current head"; "reason" => e);
+                    None
+                }
+            },
+            Ok(None) => None,
+            Err(e) => {
+                warn!(ctx.system.log(), "Failed to load current head"; "reason" => e);
+                None
+            }
+        };
+    }
+}
+
+impl Actor for MempoolManager {
+    type Msg = MempoolManagerMsg;
+
+    fn pre_start(&mut self, ctx: &Context<Self::Msg>) {
+        subscribe_to_actor_terminated(ctx.system.sys_events(), ctx.myself());
+        subscribe_to_network_events(&self.network_channel, ctx.myself());
+        subscribe_to_shell_events(&self.shell_channel, ctx.myself());
+
+        self.load_current_head(ctx);
+
+        ctx.schedule::<Self::Msg, _>(
+            ADVERTISE_MEMPOOL_INTERVAL,
+            ADVERTISE_MEMPOOL_INTERVAL,
+            ctx.myself(),
+            None,
+            AdvertiseMempool.into());
+    }
+
+    fn sys_recv(&mut self, ctx: &Context<Self::Msg>, msg: SystemMsg, sender: Option<BasicActorRef>) {
+        if let SystemMsg::Event(evt) = msg {
+            self.receive(ctx, evt, sender);
+        }
+    }
+
+    fn recv(&mut self, ctx: &Context<Self::Msg>, msg: Self::Msg, sender: Sender) {
+        self.receive(ctx, msg, sender);
+    }
+}
+
+impl Receive<SystemEvent> for MempoolManager {
+    type Msg = MempoolManagerMsg;
+
+    fn receive(&mut self, _ctx: &Context<Self::Msg>, msg: SystemEvent, _sender: Option<BasicActorRef>) {
+        if let SystemEvent::ActorTerminated(evt) = msg {
+            self.peers.remove(evt.actor.uri());
+        }
+    }
+}
+
+impl Receive<AdvertiseMempool> for MempoolManager {
+    type Msg = MempoolManagerMsg;
+
+    fn receive(&mut self, ctx: &Context<Self::Msg>, _msg: AdvertiseMempool, _sender: Sender) {
+        if self.shutting_down {
+            return;
+        }
+
+        let expired_requests = self.state.write().unwrap().remove_expired_requests(OPERATION_REQUEST_TIMEOUT);
+        if expired_requests > 0 {
+            debug!(ctx.system.log(), "Requested mempool operations were not received in time"; "count" => expired_requests);
+        }
+
+        self.advertise_mempool();
+    }
+}
+
+impl Receive<NetworkChannelMsg> for MempoolManager {
+    type Msg = MempoolManagerMsg;
+
+    fn receive(&mut self, ctx: &Context<Self::Msg>, msg: NetworkChannelMsg, _sender: Sender) {
+        if self.shutting_down {
+            return;
+        }
+
+        match self.process_network_channel_message(ctx, msg) {
+            Ok(_) => (),
+            Err(e) => warn!(ctx.system.log(), "Failed to process network channel message"; "reason" => format!("{:?}", e)),
+        }
+    }
+}
+
+impl Receive<ShellChannelMsg> for MempoolManager {
+    type Msg = MempoolManagerMsg;
+
+    fn receive(&mut self, ctx: &Context<Self::Msg>, msg: ShellChannelMsg, _sender: Sender) {
+        match self.process_shell_channel_message(ctx, msg) {
+            Ok(_) => (),
+            Err(e) => warn!(ctx.system.log(), "Failed to process shell channel message"; "reason" => format!("{:?}", e)),
+        }
+    }
+}
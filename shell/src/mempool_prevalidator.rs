@@ -0,0 +1,417 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Validates operations received to the mempool by the protocol.
+//!
+//! Validation runs in its own thread with its own protocol runner (see [`OperationValidator`]),
+//! so it never delays applying of blocks by the [chain feeder](crate::chain_feeder::ChainFeeder).
+//! Operations are validated on top of the current head and results are published to the shell channel.
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use failure::{Error, Fail};
+use riker::actors::*;
+use slog::{debug, trace, warn, Logger};
+
+use crypto::hash::{ChainId, HashType};
+use storage::{BlockMetaStorage, BlockMetaStorageReader, BlockStorage, BlockStorageReader, StorageError};
+use storage::persistent::PersistentStorage;
+use tezos_api::ffi::{OperationClassification, ValidateOperationError, ValidateOperationResult};
+use tezos_messages::p2p::encoding::prelude::*;
+use tezos_wrapper::pool::{ProtocolRunnerPool, ProtocolRunnerPoolError};
+use tezos_wrapper::service::{ProtocolError, ProtocolServiceError};
+
+use crate::shell_channel::{MempoolOperationReceived, MempoolOperationValidated, ShellChannelMsg, ShellChannelRef, ShellChannelTopic};
+use crate::subscription::subscribe_to_shell_events;
+
+/// How long to wait before operations, which could not be validated, are validated again
+const VALIDATION_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+type SharedJoinHandle = Arc<Mutex<Option<JoinHandle<Result<(), Error>>>>>;
+/// Operations received to the mempool, which are waiting for validation
+type PendingOperations = Arc<Mutex<Vec<MempoolOperationReceived>>>;
+
+/// Possible errors for the operation validation
+#[derive(Debug, Fail)]
+pub enum PrevalidationError {
+    #[fail(display = "Protocol runner is not available: {}", reason)]
+    ProtocolRunnerNotAvailable {
+        reason: ProtocolRunnerPoolError
+    },
+    #[fail(display = "Protocol service error: {}", reason)]
+    ProtocolServiceError {
+        reason: ProtocolServiceError
+    },
+}
+
+impl From<ProtocolRunnerPoolError> for PrevalidationError {
+    fn from(reason: ProtocolRunnerPoolError) -> Self {
+        PrevalidationError::ProtocolRunnerNotAvailable { reason }
+    }
+}
+
+impl From<ProtocolServiceError> for PrevalidationError {
+    fn from(reason: ProtocolServiceError) -> Self {
+        PrevalidationError::ProtocolServiceError { reason }
+    }
+}
+
+/// Validates mempool operations by the protocol.
+pub trait OperationValidator: Send {
+    /// Validate operation on top of the block
+    fn validate_operation(&self, chain_id: &ChainId, block_header: &BlockHeader, operation: &Operation) -> Result<ValidateOperationResult, PrevalidationError>;
+}
+
+/// Operations are validated by readonly protocol runners, the writer runner is used only for applying of blocks.
+impl OperationValidator for ProtocolRunnerPool {
+    fn validate_operation(&self, chain_id: &ChainId, block_header: &BlockHeader, operation: &Operation) -> Result<ValidateOperationResult, PrevalidationError> {
+        let protocol_controller = self.readonly()?;
+        Ok(protocol_controller.validate_operation(chain_id, block_header, operation)?)
+    }
+}
+
+/// Validates mempool operations in a separate thread.
+#[actor(ShellChannelMsg)]
+pub struct MempoolPrevalidator {
+    /// All events from shell will be published to this channel
+    shell_channel: ShellChannelRef,
+    /// Validation thread will run until this is set to `false`
+    validator_run: Arc<AtomicBool>,
+    /// Validation thread
+    validator_thread: SharedJoinHandle,
+    /// Operations which should be validated by the validation thread
+    pending_operations: PendingOperations,
+}
+
+/// Reference to [mempool prevalidator](MempoolPrevalidator) actor
+pub type MempoolPrevalidatorRef = ActorRef<MempoolPrevalidatorMsg>;
+
+impl MempoolPrevalidator {
+    /// Create new actor instance.
+    ///
+    /// Operations are validated by the `validator` on top of the current head read from the [`persistent_storage`](PersistentStorage).
+    /// Operations, which cannot be validated now (e.g. protocol runner is not available or it does not support the validation),
+    /// are kept and validated again later, they are never dropped without the validation result.
+    pub fn actor(
+        sys: &impl ActorRefFactory,
+        shell_channel: ShellChannelRef,
+        persistent_storage: &PersistentStorage,
+        chain_id: &ChainId,
+        validator: Box<dyn OperationValidator>,
+        log: Logger) -> Result<MempoolPrevalidatorRef, CreateError> {
+        let validator_run = Arc::new(AtomicBool::new(true));
+        let pending_operations = Arc::new(Mutex::new(Vec::new()));
+        let validator_thread = {
+            let validator_run = validator_run.clone();
+            let pending_operations = pending_operations.clone();
+            let shell_channel = shell_channel.clone();
+            let persistent_storage = persistent_storage.clone();
+            let chain_id = chain_id.clone();
+
+            thread::spawn(move || {
+                let block_storage = BlockStorage::new(&persistent_storage);
+                let block_meta_storage = BlockMetaStorage::new(&persistent_storage);
+
+                while validator_run.load(Ordering::Acquire) {
+                    let validated_all = match validate_pending_operations(&chain_id, &validator_run, &pending_operations, validator.as_ref(), &block_storage, &block_meta_storage, &shell_channel, &log) {
+                        Ok(validated_all) => validated_all,
+                        Err(err) => {
+                            warn!(log, "Failed to validate mempool operations"; "reason" => format!("{:?}", err));
+                            false
+                        }
+                    };
+
+                    // wait for new operations, or try again later, when some operations could not be validated
+                    if validated_all {
+                        thread::park();
+                    } else {
+                        thread::park_timeout(VALIDATION_RETRY_INTERVAL);
+                    }
+                }
+
+                Ok(())
+            })
+        };
+
+        sys.actor_of(
+            Props::new_args(MempoolPrevalidator::new, (shell_channel, validator_run, Arc::new(Mutex::new(Some(validator_thread))), pending_operations)),
+            MempoolPrevalidator::name())
+    }
+
+    /// The `MempoolPrevalidator` is intended to serve as a singleton actor so that's why
+    /// we won't support multiple names per instance.
+    fn name() -> &'static str {
+        "mempool-prevalidator"
+    }
+
+    fn new((shell_channel, validator_run, validator_thread, pending_operations): (ShellChannelRef, Arc<AtomicBool>, SharedJoinHandle, PendingOperations)) -> Self {
+        MempoolPrevalidator {
+            shell_channel,
+            validator_run,
+            validator_thread,
+            pending_operations,
+        }
+    }
+
+    fn process_shell_channel_message(&mut self, _ctx: &Context<MempoolPrevalidatorMsg>, msg: ShellChannelMsg) -> Result<(), Error> {
+        match msg {
+            ShellChannelMsg::MempoolOperationReceived(message) => {
+                self.pending_operations.lock().unwrap().push(message);
+                self.wake_up_validator_thread();
+            }
+            ShellChannelMsg::ShuttingDown(_) => {
+                self.validator_run.store(false, Ordering::Release);
+                self.wake_up_validator_thread();
+            }
+            _ => ()
+        }
+
+        Ok(())
+    }
+
+    fn wake_up_validator_thread(&self) {
+        if let Some(join_handle) = self.validator_thread.lock().unwrap().as_ref() {
+            join_handle.thread().unpark();
+        }
+    }
+}
+
+impl Actor for MempoolPrevalidator {
+    type Msg = MempoolPrevalidatorMsg;
+
+    fn pre_start(&mut self, ctx: &Context<Self::Msg>) {
+        subscribe_to_shell_events(&self.shell_channel, ctx.myself());
+    }
+
+    fn post_stop(&mut self) {
+        // Set the flag, and let the thread wake up. There is no race condition here, if `unpark`
+        // happens first, `park` will return immediately. Hence there is no risk of a deadlock.
+        self.validator_run.store(false, Ordering::Release);
+
+        let join_handle = self.validator_thread.lock().unwrap()
+            .take().expect("Thread join handle is missing");
+        join_handle.thread().unpark();
+        let _ = join_handle.join().expect("Failed to join mempool validator thread");
+    }
+
+    fn recv(&mut self, ctx: &Context<Self::Msg>, msg: Self::Msg, sender: Sender) {
+        self.receive(ctx, msg, sender);
+    }
+}
+
+impl Receive<ShellChannelMsg> for MempoolPrevalidator {
+    type Msg = MempoolPrevalidatorMsg;
+
+    fn receive(&mut self, ctx: &Context<Self::Msg>, msg: ShellChannelMsg, _sender: Sender) {
+        match self.process_shell_channel_message(ctx, msg) {
+            Ok(_) => (),
+            Err(e) => warn!(ctx.system.log(), "Failed to process shell channel message"; "reason" => format!("{:?}", e)),
+        }
+    }
+}
+
+/// Validates pending operations on top of the current head and publishes results.
+///
+/// Operations which were not validated are returned back to the pending operations.
+/// Returns `false` if some operations have to be validated again later.
+fn validate_pending_operations(
+    chain_id: &ChainId,
+    validator_run: &AtomicBool,
+    pending_operations: &PendingOperations,
+    validator: &dyn OperationValidator,
+    block_storage: &BlockStorage,
+    block_meta_storage: &BlockMetaStorage,
+    shell_channel: &ShellChannelRef,
+    log: &Logger,
+) -> Result<bool, StorageError> {
+    let operations: Vec<MempoolOperationReceived> = pending_operations.lock().unwrap().drain(..).collect();
+    if operations.is_empty() {
+        return Ok(true);
+    }
+
+    let current_head = match block_meta_storage.load_current_head()? {
+        Some(block_hash) => block_storage.get(&block_hash)?,
+        None => None,
+    };
+    let current_head = match current_head {
+        Some(current_head) => current_head,
+        None => {
+            debug!(log, "Mempool operations cannot be validated without the current head");
+            return_pending_operations(pending_operations, operations);
+            return Ok(false);
+        }
+    };
+
+    let mut operations = operations.into_iter();
+    while let Some(operation) = operations.next() {
+        if !validator_run.load(Ordering::Acquire) {
+            return_pending_operations(pending_operations, std::iter::once(operation).chain(operations).collect());
+            return Ok(false);
+        }
+
+        let result = match validator.validate_operation(chain_id, &current_head.header, &operation.operation) {
+            Ok(result) => result,
+            Err(PrevalidationError::ProtocolServiceError { reason: ProtocolServiceError::ProtocolError { reason: ProtocolError::ValidateOperationError { reason: ValidateOperationError::OperationValidationNotSupported { message } } } }) => {
+                // operations are not refused, because it is not their fault, they are just not validated yet
+                warn!(log, "Mempool operations cannot be validated by the protocol runner"; "reason" => message);
+                return_pending_operations(pending_operations, std::iter::once(operation).chain(operations).collect());
+                return Ok(false);
+            }
+            Err(PrevalidationError::ProtocolServiceError { reason: ProtocolServiceError::ProtocolError { reason: ProtocolError::ValidateOperationError { reason } } }) => {
+                // operation which cannot be handled by the protocol is refused
+                ValidateOperationResult {
+                    classification: OperationClassification::Refused,
+                    protocol_data_json: String::new(),
+                    error_json: serde_json::Value::String(reason.to_string()).to_string(),
+                }
+            }
+            Err(e) => {
+                warn!(log, "Mempool operation validation failed, it will be validated again later"; "reason" => format!("{}", e));
+                return_pending_operations(pending_operations, std::iter::once(operation).chain(operations).collect());
+                return Ok(false);
+            }
+        };
+        trace!(log, "Mempool operation was validated";
+            "operation_hash" => HashType::OperationHash.bytes_to_string(&operation.operation_hash),
+            "classification" => format!("{:?}", result.classification));
+
+        let MempoolOperationReceived { operation_hash, operation } = operation;
+        shell_channel.tell(
+            Publish {
+                msg: MempoolOperationValidated::new(operation_hash, operation, result).into(),
+                topic: ShellChannelTopic::ShellEvents.into(),
+            }, None);
+    }
+
+    Ok(true)
+}
+
+/// Return operations back in front of the pending operations, so the order of the operations is kept
+fn return_pending_operations(pending_operations: &PendingOperations, mut operations: Vec<MempoolOperationReceived>) {
+    let mut pending_operations = pending_operations.lock().unwrap();
+    operations.extend(pending_operations.drain(..));
+    *pending_operations = operations;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use slog::Discard;
+
+    use storage::BlockHeaderWithHash;
+    use storage::tests_common::TmpStorage;
+    use tezos_messages::p2p::binary_message::{BinaryMessage, MessageHash};
+
+    use crate::shell_channel::ShellChannel;
+
+    use super::*;
+
+    /// Validator, which applies every operation, if the validation is supported
+    struct FakeValidator {
+        supported: AtomicBool,
+        validated: AtomicUsize,
+    }
+
+    impl OperationValidator for FakeValidator {
+        fn validate_operation(&self, _: &ChainId, _: &BlockHeader, _: &Operation) -> Result<ValidateOperationResult, PrevalidationError> {
+            if !self.supported.load(Ordering::Acquire) {
+                let reason = ValidateOperationError::OperationValidationNotSupported { message: "function 'validate_operation' is not registered".to_string() };
+                return Err(ProtocolServiceError::ProtocolError { reason: ProtocolError::ValidateOperationError { reason } }.into());
+            }
+            self.validated.fetch_add(1, Ordering::AcqRel);
+            Ok(ValidateOperationResult {
+                classification: OperationClassification::Applied,
+                protocol_data_json: String::new(),
+                error_json: String::new(),
+            })
+        }
+    }
+
+    fn received(marker: u8) -> Result<MempoolOperationReceived, Error> {
+        let mut bytes = vec![0; HashType::BlockHash.size()];
+        bytes.push(marker);
+        let operation = Operation::from_bytes(bytes)?;
+        Ok(MempoolOperationReceived { operation_hash: operation.message_hash()?, operation })
+    }
+
+    fn pending_markers(pending_operations: &PendingOperations) -> Vec<u8> {
+        pending_operations.lock().unwrap().iter()
+            .map(|received| received.operation.data()[0])
+            .collect()
+    }
+
+    #[test]
+    fn test_operations_are_kept_until_validated() -> Result<(), Error> {
+        let tmp_storage = TmpStorage::create("__mempool_prevalidator_keeps_operations")?;
+        let log = Logger::root(Discard, slog::o!());
+        let actor_system = SystemBuilder::new().name("mempool_prevalidator_keeps_operations").log(log.clone()).create().expect("Failed to create actor system");
+        let shell_channel = ShellChannel::actor(&actor_system).expect("Failed to create shell channel");
+        let mut block_storage = BlockStorage::new(tmp_storage.storage());
+        let mut block_meta_storage = BlockMetaStorage::new(tmp_storage.storage());
+        let chain_id = vec![1, 2, 3, 4];
+
+        let validator = FakeValidator { supported: AtomicBool::new(false), validated: AtomicUsize::new(0) };
+        let validator_run = AtomicBool::new(true);
+        let pending_operations: PendingOperations = Arc::new(Mutex::new(vec![received(1)?, received(2)?]));
+        let validate = |block_storage: &BlockStorage, block_meta_storage: &BlockMetaStorage| validate_pending_operations(&chain_id, &validator_run, &pending_operations, &validator, block_storage, block_meta_storage, &shell_channel, &log);
+
+        // operations cannot be validated without the current head
+        assert!(!validate(&block_storage, &block_meta_storage)?);
+        assert_eq!(vec![1, 2], pending_markers(&pending_operations));
+
+        let head = BlockHeaderWithHash::new(
+            BlockHeaderBuilder::default()
+                .level(1)
+                .proto(1)
+                .predecessor(vec![0; HashType::BlockHash.size()])
+                .timestamp(1)
+                .validation_pass(4)
+                .operations_hash(vec![0; HashType::OperationListListHash.size()])
+                .fitness(vec![vec![0]])
+                .context(vec![0; HashType::ContextHash.size()])
+                .protocol_data(vec![])
+                .build().unwrap()
+        )?;
+        block_storage.put_block_header(&head)?;
+        block_meta_storage.store_current_head(&head.hash)?;
+
+        // drained operations are returned in front of the operations received meanwhile, when the validation is not supported
+        assert!(!validate(&block_storage, &block_meta_storage)?);
+        pending_operations.lock().unwrap().push(received(3)?);
+        assert!(!validate(&block_storage, &block_meta_storage)?);
+        assert_eq!(vec![1, 2, 3], pending_markers(&pending_operations));
+
+        // all operations are validated, once the validation is supported
+        validator.supported.store(true, Ordering::Release);
+        assert!(validate(&block_storage, &block_meta_storage)?);
+        assert!(pending_markers(&pending_operations).is_empty());
+        assert_eq!(3, validator.validated.load(Ordering::Acquire));
+
+        actor_system.shutdown();
+        Ok(())
+    }
+
+    #[test]
+    fn test_operations_are_kept_when_stopped() -> Result<(), Error> {
+        let tmp_storage = TmpStorage::create("__mempool_prevalidator_stopped")?;
+        let log = Logger::root(Discard, slog::o!());
+        let actor_system = SystemBuilder::new().name("mempool_prevalidator_stopped").log(log.clone()).create().expect("Failed to create actor system");
+        let shell_channel = ShellChannel::actor(&actor_system).expect("Failed to create shell channel");
+        let block_storage = BlockStorage::new(tmp_storage.storage());
+        let block_meta_storage = BlockMetaStorage::new(tmp_storage.storage());
+
+        let validator = FakeValidator { supported: AtomicBool::new(true), validated: AtomicUsize::new(0) };
+        let pending_operations: PendingOperations = Arc::new(Mutex::new(vec![received(1)?]));
+        assert!(!validate_pending_operations(&vec![1, 2, 3, 4], &AtomicBool::new(false), &pending_operations, &validator, &block_storage, &block_meta_storage, &shell_channel, &log)?);
+        assert_eq!(vec![1], pending_markers(&pending_operations));
+        assert_eq!(0, validator.validated.load(Ordering::Acquire));
+
+        actor_system.shutdown();
+        Ok(())
+    }
+}
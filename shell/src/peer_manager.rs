@@ -7,9 +7,9 @@ use std::cmp;
 use std::collections::{HashMap, HashSet};
 use std::iter::FromIterator;
 use std::net::{IpAddr, SocketAddr};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use dns_lookup::LookupError;
 use futures::lock::Mutex;
@@ -20,32 +20,47 @@ use tokio::net::{TcpListener, TcpStream};
 use tokio::runtime::Handle;
 use tokio::time::timeout;
 
-use networking::p2p::network_channel::{NetworkChannelMsg, NetworkChannelRef, NetworkChannelTopic, PeerBootstrapped, PeerCreated};
-use networking::p2p::peer::{Bootstrap, Peer, PeerRef, SendMessage};
+use networking::p2p::network_channel::{NetworkChannelMsg, NetworkChannelRef, NetworkChannelTopic, PeerBehaviourObserved, PeerBootstrapped, PeerCreated};
+use crypto::hash::HashType;
+use networking::p2p::peer::{Bootstrap, Peer, PeerId, PeerRef, SendMessage};
 use storage::p2p_message_storage::P2PMessageStorage;
+use storage::PeerBlacklistStorage;
 use storage::persistent::PersistentStorage;
 use tezos_api::identity::Identity;
 use tezos_messages::p2p::encoding::prelude::*;
 
+pub use crate::state::peers_state::{PeerScoring, PeersState};
+
 use crate::shell_channel::{ShellChannelMsg, ShellChannelRef};
 use crate::subscription::*;
 
 /// Timeout for outgoing connections
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(8);
-/// Whitelist all IP addresses after 30 minutes
-const WHITELIST_INTERVAL: Duration = Duration::from_secs(1_800);
+/// How often to remove expired bans
+const REMOVE_EXPIRED_BANS_INTERVAL: Duration = Duration::from_secs(60);
 /// How often to do DNS peer discovery
 const DISCOVERY_INTERVAL: Duration = Duration::from_secs(60);
 /// Limit how often we allow to trigger check of a peer count
 const CHECK_PEER_COUNT_LIMIT: Duration = Duration::from_secs(5);
+/// How often to propose a swap of peers to a random connected peer
+const PROPOSE_SWAP_INTERVAL: Duration = Duration::from_secs(120);
+/// Minimal time between two accepted swaps
+const SWAP_LINGER: Duration = Duration::from_secs(30);
+
+/// Thread safe reference to a shared state of peers
+pub type PeersStateRef = Arc<RwLock<PeersState>>;
 
 /// Check peer threshold
 #[derive(Clone, Debug)]
 pub struct CheckPeerCount;
 
-/// Whitelist all IP address.
+/// Remove bans of IP addresses, which already expired.
 #[derive(Clone, Debug)]
-pub struct WhitelistAllIpAddresses;
+pub struct RemoveExpiredBans;
+
+/// Propose a swap of peers to a random connected peer.
+#[derive(Clone, Debug)]
+pub struct ProposeSwap;
 
 /// Accept incoming peer connection.
 #[derive(Clone, Debug)]
@@ -86,7 +101,14 @@ impl Threshold {
 /// It monitors number of connected peers. If the number of connected peers is too low it tries to
 /// connect to more peers. If the number of connected peers is too high, then randomly selected peers
 /// are disconnected.
-#[actor(CheckPeerCount, WhitelistAllIpAddresses, AcceptPeer, ConnectToPeer, NetworkChannelMsg, ShellChannelMsg, SystemEvent, DeadLetter)]
+///
+/// Peers are scored by their behaviour and IP addresses of misbehaving peers are banned
+/// for a configured duration. Bans are persisted, so they survive restarts of the node.
+///
+/// Peer set is rotated by the Tezos swap protocol. Periodically one connected peer receives `SwapRequest`
+/// with a point of another connected peer. Peer, which accepts the swap, answers with `SwapAck` with one of its own
+/// peers. Both sides then connect to the received point and drop the peer they offered, once the new connection is bootstrapped.
+#[actor(CheckPeerCount, RemoveExpiredBans, ProposeSwap, AcceptPeer, ConnectToPeer, NetworkChannelMsg, ShellChannelMsg, SystemEvent, DeadLetter)]
 pub struct PeerManager {
     /// All events generated by the network layer will end up in this channel
     network_channel: NetworkChannelRef,
@@ -110,15 +132,23 @@ pub struct PeerManager {
     identity: Identity,
     /// Protocol version
     protocol_version: String,
+    /// Minimal proof of work of the remote peer identity
+    peer_expected_pow: f64,
     /// Message receiver boolean indicating whether
     /// more connections should be accepted from network
     rx_run: Arc<AtomicBool>,
-    /// set of blacklisted IP addresses
-    ip_blacklist: HashSet<IpAddr>,
+    /// Scores of peers and banned IP addresses
+    peers_state: PeersStateRef,
+    /// Persisted bans of IP addresses
+    peer_blacklist_storage: PeerBlacklistStorage,
     /// Last time we did DNS peer discovery
     discovery_last: Option<Instant>,
     /// Last time we checked peer count
     check_peer_count_last: Option<Instant>,
+    /// Last time we accepted a swap
+    swap_last: Option<Instant>,
+    /// Swaps waiting for the connection to the new point, peer is dropped when the new point is bootstrapped
+    pending_swaps: HashMap<SocketAddr, ActorUri>,
     /// Indicates that system is shutting down
     shutting_down: bool,
     /// Storage
@@ -139,7 +169,9 @@ impl PeerManager {
                  listener_port: u16,
                  identity: Identity,
                  protocol_version: String,
+                 peer_expected_pow: f64,
                  ps: PersistentStorage,
+                 peers_state: PeersStateRef,
     ) -> Result<PeerManagerRef, CreateError> {
         sys.actor_of(
             Props::new_args(PeerManager::new, (
@@ -152,7 +184,9 @@ impl PeerManager {
                 listener_port,
                 identity,
                 protocol_version,
-                ps)),
+                peer_expected_pow,
+                ps,
+                peers_state)),
             PeerManager::name())
     }
 
@@ -162,8 +196,8 @@ impl PeerManager {
         "peer-manager"
     }
 
-    fn new((network_channel, shell_channel, tokio_executor, bootstrap_addresses, initial_peers, threshold, listener_port, identity, protocol_version, ps):
-           (NetworkChannelRef, ShellChannelRef, Handle, Vec<String>, HashSet<SocketAddr>, Threshold, u16, Identity, String, PersistentStorage)) -> Self {
+    fn new((network_channel, shell_channel, tokio_executor, bootstrap_addresses, initial_peers, threshold, listener_port, identity, protocol_version, peer_expected_pow, ps, peers_state):
+           (NetworkChannelRef, ShellChannelRef, Handle, Vec<String>, HashSet<SocketAddr>, Threshold, u16, Identity, String, f64, PersistentStorage, PeersStateRef)) -> Self {
         PeerManager {
             network_channel,
             shell_channel,
@@ -174,12 +208,16 @@ impl PeerManager {
             listener_port,
             identity,
             protocol_version,
+            peer_expected_pow,
             rx_run: Arc::new(AtomicBool::new(true)),
             potential_peers: HashSet::new(),
             peers: HashMap::new(),
-            ip_blacklist: HashSet::new(),
+            peers_state,
+            peer_blacklist_storage: PeerBlacklistStorage::new(&ps),
             discovery_last: None,
             check_peer_count_last: None,
+            swap_last: None,
+            pending_swaps: HashMap::new(),
             shutting_down: false,
             p2p_msg_storage: P2PMessageStorage::new(&ps),
         }
@@ -220,12 +258,13 @@ impl PeerManager {
             &self.identity.secret_key,
             &self.identity.proof_of_work_stamp,
             &self.protocol_version,
+            self.peer_expected_pow,
             self.tokio_executor.clone(),
             socket_address,
             self.p2p_msg_storage.clone(),
         ).unwrap();
 
-        self.peers.insert(peer.uri().clone(), PeerState { peer_ref: peer.clone(), address: socket_address.clone() });
+        self.peers.insert(peer.uri().clone(), PeerState::new(peer.clone(), *socket_address));
 
         self.network_channel.tell(
             Publish {
@@ -241,7 +280,172 @@ impl PeerManager {
 
     /// Check if given ip address is blacklisted to connect to
     fn is_blacklisted(&self, ip_address: &IpAddr) -> bool {
-        self.ip_blacklist.contains(ip_address)
+        self.peers_state.read().unwrap().is_banned(ip_address, unix_now())
+    }
+
+    /// Load persisted bans, expired bans are removed from the storage
+    fn restore_bans(&mut self, log: &Logger) -> Result<(), failure::Error> {
+        let now = unix_now();
+        let mut peers_state = self.peers_state.write().unwrap();
+        for (address, ban) in self.peer_blacklist_storage.get_all()? {
+            if ban.is_active(now) {
+                peers_state.restore_ban(address, ban);
+            } else {
+                self.peer_blacklist_storage.delete(&address)?;
+            }
+        }
+        info!(log, "Restored banned IP addresses"; "count" => peers_state.banned().len());
+
+        Ok(())
+    }
+
+    /// Update score of the peer and ban its IP address if the score is too low
+    fn process_peer_behaviour(&mut self, ctx: &Context<PeerManagerMsg>, msg: PeerBehaviourObserved) -> Result<(), failure::Error> {
+        let ip_address = msg.address.ip();
+        let should_ban = self.peers_state.write().unwrap().update_score(ip_address, msg.behaviour);
+        if should_ban {
+            let reason = format!("Score dropped too low, last behaviour: {:?}", msg.behaviour);
+            let ban = self.peers_state.write().unwrap().ban(ip_address, unix_now(), reason);
+            warn!(ctx.system.log(), "Banning IP address"; "ip" => format!("{}", ip_address), "reason" => ban.reason(), "banned_until" => ban.banned_until());
+            self.peer_blacklist_storage.put(&ip_address, &ban)?;
+
+            // disconnect all peers from the banned address
+            self.potential_peers.retain(|address| address.ip() != ip_address);
+            self.peers.values()
+                .filter(|peer_state| peer_state.address.ip() == ip_address)
+                .for_each(|peer_state| ctx.system.stop(peer_state.peer_ref.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Forget peer, which was stopped
+    fn remove_peer(&mut self, uri: &ActorUri) -> bool {
+        match self.peers.remove(uri) {
+            Some(peer_state) => {
+                self.peers_state.write().unwrap().peer_disconnected(&peer_state.address);
+                // swap cannot be finished without the new connection or the dropped peer
+                self.pending_swaps.retain(|address, peer_to_drop| *address != peer_state.address && *peer_to_drop != *uri);
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Check if we are connected (or connecting) to the point or to the peer
+    fn is_connected(&self, point: &SocketAddr, peer_id: &str) -> bool {
+        self.peers.values()
+            .any(|peer_state| peer_state.address == *point
+                || peer_state.listener_address().as_ref() == Some(point)
+                || peer_state.peer_id.as_ref().map(|id| id == peer_id).unwrap_or(false))
+    }
+
+    /// Pick random bootstrapped peer, which is not the `excluded` peer, returns its listener point
+    fn random_bootstrapped_peer(&self, excluded: &ActorUri) -> Option<(ActorUri, SocketAddr, PeerId)> {
+        let candidates = self.peers.iter()
+            .filter(|(uri, _)| *uri != excluded)
+            .filter_map(|(uri, peer_state)| match (peer_state.listener_address(), peer_state.peer_id.as_ref()) {
+                (Some(point), Some(peer_id)) => Some((uri.clone(), point, peer_id.clone())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        candidates.choose(&mut rand::thread_rng()).cloned()
+    }
+
+    /// Connect to the new point, peer is dropped when the new point is bootstrapped
+    fn swap(&mut self, ctx: &Context<PeerManagerMsg>, new_point: SocketAddr, peer_to_drop: ActorUri) {
+        let dropped_address = self.peers.get(&peer_to_drop).map(|peer_state| peer_state.address.to_string()).unwrap_or_default();
+        info!(ctx.system.log(), "Swapping peer"; "new_point" => new_point, "peer_to_drop" => dropped_address);
+        self.start_swap(new_point, peer_to_drop);
+        ctx.myself().tell(ConnectToPeer { address: new_point }, None);
+    }
+
+    /// Remember the pending swap, so the dropped peer can be stopped once the new point is bootstrapped
+    fn start_swap(&mut self, new_point: SocketAddr, peer_to_drop: ActorUri) {
+        self.swap_last = Some(Instant::now());
+        self.pending_swaps.insert(new_point, peer_to_drop);
+    }
+
+    /// Validate point and peer id of the received swap message
+    fn parse_swap_message(&self, message: &SwapMessage) -> Option<(SocketAddr, PeerId)> {
+        let point = message.point().parse::<SocketAddr>().ok()?;
+        let peer_id = HashType::CryptoboxPublicKeyHash.bytes_to_string(message.peer_id());
+        if self.is_blacklisted(&point.ip()) || self.is_connected(&point, &peer_id) {
+            None
+        } else {
+            Some((point, peer_id))
+        }
+    }
+
+    /// Peer proposed us its peer, we offer one of our peers in exchange
+    fn process_swap_request(&mut self, ctx: &Context<PeerManagerMsg>, source: &PeerRef, message: &SwapMessage) {
+        match self.accept_swap_request(source.uri(), message) {
+            Ok((swap_ack, new_point, proposed_uri)) => {
+                source.tell(SendMessage::new(PeerMessage::SwapAck(swap_ack).into()), None);
+                self.swap(ctx, new_point, proposed_uri);
+            }
+            Err(reason) => debug!(ctx.system.log(), "Ignoring swap request"; "reason" => reason, "peer" => source.name(), "point" => message.point()),
+        }
+    }
+
+    /// Resolve swap request of the `source` peer, returns ack for the `source` peer, new point to connect to and the peer we offered
+    fn accept_swap_request(&self, source: &ActorUri, message: &SwapMessage) -> Result<(SwapMessage, SocketAddr, ActorUri), &'static str> {
+        if self.swap_last.filter(|swap_last| swap_last.elapsed() < SWAP_LINGER).is_some() {
+            return Err("last swap was too recent");
+        }
+        let (new_point, _) = self.parse_swap_message(message).ok_or("point is already connected or banned")?;
+        let (proposed_uri, proposed_point, proposed_peer_id) = self.random_bootstrapped_peer(source).ok_or("there is no peer to offer")?;
+        let proposed_peer_id = HashType::CryptoboxPublicKeyHash.string_to_bytes(&proposed_peer_id).map_err(|_| "invalid peer id of the offered peer")?;
+
+        Ok((SwapMessage::new(&proposed_point, proposed_peer_id), new_point, proposed_uri))
+    }
+
+    /// Peer accepted our swap request, we drop the peer we proposed
+    fn process_swap_ack(&mut self, ctx: &Context<PeerManagerMsg>, source: &PeerRef, message: &SwapMessage) {
+        match self.accept_swap_ack(source.uri(), message) {
+            Ok((new_point, proposed_uri)) => self.swap(ctx, new_point, proposed_uri),
+            Err(reason) => debug!(ctx.system.log(), "Ignoring swap ack"; "reason" => reason, "peer" => source.name(), "point" => message.point()),
+        }
+    }
+
+    /// Resolve swap ack of the `source` peer, returns new point to connect to and the peer we proposed in the swap request
+    fn accept_swap_ack(&mut self, source: &ActorUri, message: &SwapMessage) -> Result<(SocketAddr, ActorUri), &'static str> {
+        let proposed_peer_id = self.peers.get_mut(source)
+            .and_then(|peer_state| peer_state.last_sent_swap_request.take())
+            .ok_or("swap ack was not expected")?;
+        let proposed_uri = self.peers.iter()
+            .find(|(_, peer_state)| peer_state.peer_id.as_ref() == Some(&proposed_peer_id))
+            .map(|(uri, _)| uri.clone())
+            .ok_or("proposed peer is gone")?;
+        let (new_point, _) = self.parse_swap_message(message).ok_or("point is already connected or banned")?;
+
+        Ok((new_point, proposed_uri))
+    }
+
+    /// Pick a bootstrapped peer and another peer, which we propose to it, returns recipient and the swap request
+    fn propose_swap(&mut self) -> Option<(PeerRef, SwapMessage)> {
+        let recipient_uri = self.peers.iter()
+            .filter(|(_, peer_state)| peer_state.peer_id.is_some())
+            .map(|(uri, _)| uri.clone())
+            .collect::<Vec<_>>()
+            .choose(&mut rand::thread_rng())
+            .cloned()?;
+        let (_, proposed_point, proposed_peer_id) = self.random_bootstrapped_peer(&recipient_uri)?;
+        let proposed_peer_id_bytes = HashType::CryptoboxPublicKeyHash.string_to_bytes(&proposed_peer_id).ok()?;
+
+        let recipient = self.peers.get_mut(&recipient_uri)?;
+        recipient.last_sent_swap_request = Some(proposed_peer_id);
+        Some((recipient.peer_ref.clone(), SwapMessage::new(&proposed_point, proposed_peer_id_bytes)))
+    }
+
+    /// New point of the swap was bootstrapped, we can drop the peer we offered
+    fn finish_swap(&mut self, ctx: &Context<PeerManagerMsg>, new_point: &SocketAddr) {
+        if let Some(peer_to_drop) = self.pending_swaps.remove(new_point) {
+            if let Some(peer_state) = self.peers.get(&peer_to_drop) {
+                info!(ctx.system.log(), "Swap finished, dropping peer"; "new_point" => new_point, "dropped_peer" => peer_state.address);
+                ctx.system.stop(peer_state.peer_ref.clone());
+            }
+        }
     }
 
     fn process_shell_channel_message(&mut self, ctx: &Context<PeerManagerMsg>, msg: ShellChannelMsg) -> Result<(), failure::Error> {
@@ -292,11 +496,21 @@ impl Actor for PeerManager {
             None,
             CheckPeerCount.into());
         ctx.schedule::<Self::Msg, _>(
-            WHITELIST_INTERVAL,
-            WHITELIST_INTERVAL,
+            PROPOSE_SWAP_INTERVAL,
+            PROPOSE_SWAP_INTERVAL,
+            ctx.myself(),
+            None,
+            ProposeSwap.into());
+        ctx.schedule::<Self::Msg, _>(
+            REMOVE_EXPIRED_BANS_INTERVAL,
+            REMOVE_EXPIRED_BANS_INTERVAL,
             ctx.myself(),
             None,
-            WhitelistAllIpAddresses.into());
+            RemoveExpiredBans.into());
+
+        if let Err(e) = self.restore_bans(&ctx.system.log()) {
+            warn!(ctx.system.log(), "Failed to restore banned IP addresses"; "reason" => format!("{:?}", e));
+        }
 
 
         let listener_port = self.listener_port;
@@ -328,7 +542,7 @@ impl Receive<DeadLetter> for PeerManager {
     type Msg = PeerManagerMsg;
 
     fn receive(&mut self, _ctx: &Context<Self::Msg>, msg: DeadLetter, _sender: Option<BasicActorRef>) {
-        self.peers.remove(msg.recipient.uri());
+        self.remove_peer(msg.recipient.uri());
     }
 }
 
@@ -349,7 +563,7 @@ impl Receive<SystemEvent> for PeerManager {
 
     fn receive(&mut self, ctx: &Context<Self::Msg>, msg: SystemEvent, _sender: Option<BasicActorRef>) {
         if let SystemEvent::ActorTerminated(evt) = msg {
-            if let Some(_) = self.peers.remove(evt.actor.uri()) {
+            if self.remove_peer(evt.actor.uri()) {
                 self.trigger_check_peer_count(ctx);
             }
         }
@@ -417,27 +631,45 @@ impl Receive<NetworkChannelMsg> for PeerManager {
                             let addresses = self.peers.values()
                                 .into_iter()
                                 .filter(|peer_state| peer_state.peer_ref != received.peer)
-                                .map(|peer_state| peer_state.address)
+                                .filter_map(PeerState::listener_address)
                                 .collect::<Vec<_>>();
                             let msg = AdvertiseMessage::new(&addresses);
                             received.peer.tell(SendMessage::new(PeerMessage::Advertise(msg).into()), None);
                         }
+                        PeerMessage::SwapRequest(message) => {
+                            info!(ctx.system.log(), "Received swap request"; "peer" => received.peer.name(), "point" => message.point());
+                            self.process_swap_request(ctx, &received.peer, message);
+                        }
+                        PeerMessage::SwapAck(message) => {
+                            info!(ctx.system.log(), "Received swap ack"; "peer" => received.peer.name(), "point" => message.point());
+                            self.process_swap_ack(ctx, &received.peer, message);
+                        }
                         _ => {}
                     });
                 self.trigger_check_peer_count(ctx);
             }
+            NetworkChannelMsg::PeerBootstrapped(PeerBootstrapped::Success { peer, peer_id, address, listener_port }) => {
+                if let Some(peer_state) = self.peers.get_mut(peer.uri()) {
+                    peer_state.peer_id = Some(peer_id.clone());
+                    peer_state.listener_port = Some(listener_port);
+                }
+                self.peers_state.write().unwrap().peer_connected(address, peer_id);
+                self.finish_swap(ctx, &address);
+            }
             NetworkChannelMsg::PeerBootstrapped(PeerBootstrapped::Failure { address, potential_peers_to_connect }) => {
-                // received message that bootstrap process failed for the peer
-                match potential_peers_to_connect {
-                    Some(peers) => {
-                        info!(ctx.system.log(), "Received list of potential peers in the NACK message"; "ip" => format!("{}", address.ip()), "peers" => format!("{:?}", &peers));
-                        self.process_potential_peers(&peers);
-                        self.trigger_check_peer_count(ctx);
-                    }
-                    None => {
-                        info!(ctx.system.log(), "Blacklisting IP because peer failed at bootstrap process"; "ip" => format!("{}", address.ip()));
-                        self.ip_blacklist.insert(address.ip());
-                    }
+                if self.pending_swaps.remove(&address).is_some() {
+                    info!(ctx.system.log(), "Swap failed, new point was not bootstrapped"; "new_point" => address);
+                }
+                // received message that bootstrap process failed for the peer, peer is scored by the `PeerBehaviourObserved` event
+                if let Some(peers) = potential_peers_to_connect {
+                    info!(ctx.system.log(), "Received list of potential peers in the NACK message"; "ip" => format!("{}", address.ip()), "peers" => format!("{:?}", &peers));
+                    self.process_potential_peers(&peers);
+                    self.trigger_check_peer_count(ctx);
+                }
+            }
+            NetworkChannelMsg::PeerBehaviourObserved(msg) => {
+                if let Err(e) = self.process_peer_behaviour(ctx, msg) {
+                    warn!(ctx.system.log(), "Failed to process peer behaviour"; "reason" => format!("{:?}", e));
                 }
             }
             _ => ()
@@ -445,12 +677,33 @@ impl Receive<NetworkChannelMsg> for PeerManager {
     }
 }
 
-impl Receive<WhitelistAllIpAddresses> for PeerManager {
+impl Receive<RemoveExpiredBans> for PeerManager {
+    type Msg = PeerManagerMsg;
+
+    fn receive(&mut self, ctx: &Context<Self::Msg>, _msg: RemoveExpiredBans, _sender: Sender) {
+        let unbanned = self.peers_state.write().unwrap().remove_expired_bans(unix_now());
+        for address in unbanned {
+            info!(ctx.system.log(), "Ban of IP address expired"; "ip" => format!("{}", address));
+            if let Err(e) = self.peer_blacklist_storage.delete(&address) {
+                warn!(ctx.system.log(), "Failed to remove expired ban"; "ip" => format!("{}", address), "reason" => e);
+            }
+        }
+    }
+}
+
+impl Receive<ProposeSwap> for PeerManager {
     type Msg = PeerManagerMsg;
 
-    fn receive(&mut self, ctx: &Context<Self::Msg>, _msg: WhitelistAllIpAddresses, _sender: Sender) {
-        info!(ctx.system.log(), "Whitelisting all IP addresses");
-        self.ip_blacklist.clear();
+    fn receive(&mut self, ctx: &Context<Self::Msg>, _msg: ProposeSwap, _sender: Sender) {
+        if self.shutting_down {
+            return;
+        }
+
+        // pick recipient of the swap request and the peer, which we propose to the recipient
+        if let Some((recipient, swap_request)) = self.propose_swap() {
+            debug!(ctx.system.log(), "Proposing swap"; "peer" => recipient.name(), "proposed_point" => swap_request.point());
+            recipient.tell(SendMessage::new(PeerMessage::SwapRequest(swap_request).into()), None);
+        }
     }
 }
 
@@ -516,6 +769,11 @@ async fn begin_listen_incoming(listener_port: u16, peer_manager: PeerManagerRef,
     }
 }
 
+/// Current unix timestamp in seconds
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
 /// Do DNS lookup for collection of names and create collection of socket addresses
 fn dns_lookup_peers(bootstrap_addresses: &[String], log: &Logger) -> HashSet<SocketAddr> {
     let mut resolved_peers = HashSet::new();
@@ -550,5 +808,199 @@ struct PeerState {
     /// Reference to peer actor
     peer_ref: PeerRef,
     /// Peer IP address
-    address: SocketAddr
+    address: SocketAddr,
+    /// Peer id, known after the peer is bootstrapped
+    peer_id: Option<PeerId>,
+    /// Port on which the peer accepts incoming connections, known after the peer is bootstrapped
+    listener_port: Option<u16>,
+    /// Id of the peer, which we proposed to this peer in the last swap request
+    last_sent_swap_request: Option<PeerId>,
+}
+
+impl PeerState {
+    fn new(peer_ref: PeerRef, address: SocketAddr) -> Self {
+        PeerState {
+            peer_ref,
+            address,
+            peer_id: None,
+            listener_port: None,
+            last_sent_swap_request: None,
+        }
+    }
+
+    /// Point, on which the peer accepts incoming connections. For incoming connections
+    /// the `address` port is an ephemeral port of the remote side, so it cannot be shared with other peers.
+    fn listener_address(&self) -> Option<SocketAddr> {
+        self.listener_port.map(|listener_port| SocketAddr::new(self.address.ip(), listener_port))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use slog::Discard;
+
+    use networking::p2p::network_channel::NetworkChannel;
+    use networking::p2p::peer::PeerMsg;
+    use storage::tests_common::TmpStorage;
+
+    use crate::shell_channel::ShellChannel;
+
+    use super::*;
+
+    /// Peer actor, which ignores all messages
+    struct DummyPeer;
+
+    impl DummyPeer {
+        fn new() -> Self {
+            DummyPeer
+        }
+    }
+
+    impl Actor for DummyPeer {
+        type Msg = PeerMsg;
+
+        fn recv(&mut self, _: &Context<Self::Msg>, _: Self::Msg, _: Sender) {}
+    }
+
+    struct TestContext {
+        actor_system: ActorSystem,
+        _runtime: tokio::runtime::Runtime,
+        _tmp_storage: TmpStorage,
+        peer_manager: PeerManager,
+    }
+
+    impl TestContext {
+        fn create(name: &str) -> Self {
+            let tmp_storage = TmpStorage::create(format!("__shell_peer_manager_{}", name)).expect("Failed to create storage");
+            let runtime = tokio::runtime::Builder::new().basic_scheduler().enable_all().build().expect("Failed to create tokio runtime");
+            let actor_system = SystemBuilder::new().name(name).log(Logger::root(Discard, slog::o!())).create().expect("Failed to create actor system");
+            let network_channel = NetworkChannel::actor(&actor_system).expect("Failed to create network channel");
+            let shell_channel = ShellChannel::actor(&actor_system).expect("Failed to create shell channel");
+            let identity = Identity {
+                peer_id: peer_id(0),
+                public_key: String::new(),
+                secret_key: String::new(),
+                proof_of_work_stamp: String::new(),
+            };
+
+            let peer_manager = PeerManager::new((
+                network_channel,
+                shell_channel,
+                runtime.handle().clone(),
+                vec![],
+                HashSet::new(),
+                Threshold::new(1, 10),
+                9732,
+                identity,
+                "TEZOS_ALPHANET_CARTHAGE_2019-11-28T13:02:13Z".to_string(),
+                0.0,
+                tmp_storage.storage().clone(),
+                Arc::new(RwLock::new(PeersState::new(PeerScoring::default())))
+            ));
+
+            TestContext { actor_system, _runtime: runtime, _tmp_storage: tmp_storage, peer_manager }
+        }
+
+        /// Register bootstrapped peer, `address` is the address of the connection and `listener_port` is announced by the peer
+        fn add_peer(&mut self, index: u8, address: &str, listener_port: u16) -> ActorUri {
+            let peer_ref: PeerRef = self.actor_system.actor_of(Props::new(DummyPeer::new), &format!("dummy-peer-{}", index)).expect("Failed to create peer");
+            let mut peer_state = PeerState::new(peer_ref.clone(), address.parse().unwrap());
+            peer_state.peer_id = Some(peer_id(index));
+            peer_state.listener_port = Some(listener_port);
+            self.peer_manager.peers.insert(peer_ref.uri().clone(), peer_state);
+            peer_ref.uri().clone()
+        }
+    }
+
+    fn peer_id(index: u8) -> PeerId {
+        HashType::CryptoboxPublicKeyHash.bytes_to_string(&[index; 16])
+    }
+
+    fn swap_message(point: &str, index: u8) -> SwapMessage {
+        SwapMessage::new(&point.parse().unwrap(), HashType::CryptoboxPublicKeyHash.string_to_bytes(&peer_id(index)).unwrap())
+    }
+
+    #[test]
+    fn test_swap_request_offers_listener_point() {
+        let mut context = TestContext::create("swap_request_offers_listener_point");
+        // incoming connection from an ephemeral port
+        let offered = context.add_peer(1, "10.0.0.1:50123", 9733);
+        let source = context.add_peer(2, "10.0.0.2:9732", 9732);
+
+        let (swap_ack, new_point, peer_to_drop) = context.peer_manager.accept_swap_request(&source, &swap_message("10.0.0.3:9732", 3)).expect("Swap request should be accepted");
+        assert_eq!("10.0.0.1:9733", swap_ack.point());
+        assert_eq!(&HashType::CryptoboxPublicKeyHash.string_to_bytes(&peer_id(1)).unwrap(), swap_ack.peer_id());
+        assert_eq!("10.0.0.3:9732".parse::<SocketAddr>().unwrap(), new_point);
+        assert_eq!(offered, peer_to_drop);
+
+        // the next swap is not accepted too early
+        context.peer_manager.start_swap(new_point, peer_to_drop.clone());
+        assert_eq!(Some(&peer_to_drop), context.peer_manager.pending_swaps.get(&new_point));
+        assert!(context.peer_manager.accept_swap_request(&source, &swap_message("10.0.0.4:9732", 4)).is_err());
+
+        context.actor_system.shutdown();
+    }
+
+    #[test]
+    fn test_swap_request_rejects_connected_point() {
+        let mut context = TestContext::create("swap_request_rejects_connected_point");
+        context.add_peer(1, "10.0.0.1:50123", 9733);
+        let source = context.add_peer(2, "10.0.0.2:9732", 9732);
+
+        // listener point of the connected peer
+        assert!(context.peer_manager.accept_swap_request(&source, &swap_message("10.0.0.1:9733", 3)).is_err());
+        // known peer id
+        assert!(context.peer_manager.accept_swap_request(&source, &swap_message("10.0.0.3:9732", 1)).is_err());
+        // unknown point and peer
+        assert!(context.peer_manager.accept_swap_request(&source, &swap_message("10.0.0.3:9732", 3)).is_ok());
+
+        context.actor_system.shutdown();
+    }
+
+    #[test]
+    fn test_swap_request_without_peer_to_offer() {
+        let mut context = TestContext::create("swap_request_without_peer_to_offer");
+        let source = context.add_peer(1, "10.0.0.1:9732", 9732);
+
+        assert!(context.peer_manager.accept_swap_request(&source, &swap_message("10.0.0.3:9732", 3)).is_err());
+
+        context.actor_system.shutdown();
+    }
+
+    #[test]
+    fn test_propose_swap_and_accept_ack() {
+        let mut context = TestContext::create("propose_swap_and_accept_ack");
+        let first = context.add_peer(1, "10.0.0.1:50123", 9733);
+        let second = context.add_peer(2, "10.0.0.2:50124", 9734);
+
+        let (recipient, swap_request) = context.peer_manager.propose_swap().expect("Swap should be proposed");
+        let (recipient_uri, proposed_uri, proposed_point) = if *recipient.uri() == first {
+            (first, second, "10.0.0.2:9734")
+        } else {
+            (second, first, "10.0.0.1:9733")
+        };
+        assert_eq!(proposed_point, swap_request.point());
+
+        // ack from the other peer is not expected
+        assert!(context.peer_manager.accept_swap_ack(&proposed_uri, &swap_message("10.0.0.3:9732", 3)).is_err());
+
+        let (new_point, peer_to_drop) = context.peer_manager.accept_swap_ack(&recipient_uri, &swap_message("10.0.0.3:9732", 3)).expect("Swap ack should be accepted");
+        assert_eq!("10.0.0.3:9732".parse::<SocketAddr>().unwrap(), new_point);
+        assert_eq!(proposed_uri, peer_to_drop);
+
+        // ack is accepted only once
+        assert!(context.peer_manager.accept_swap_ack(&recipient_uri, &swap_message("10.0.0.4:9732", 4)).is_err());
+
+        context.actor_system.shutdown();
+    }
+
+    #[test]
+    fn test_propose_swap_requires_two_bootstrapped_peers() {
+        let mut context = TestContext::create("propose_swap_requires_two_bootstrapped_peers");
+        context.add_peer(1, "10.0.0.1:9732", 9732);
+
+        assert!(context.peer_manager.propose_swap().is_none());
+
+        context.actor_system.shutdown();
+    }
 }
@@ -0,0 +1,256 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Helpers to read contracts and delegates from the context, the layout of the context is the same for the protocols 005 and 006.
+
+use std::collections::HashMap;
+
+use failure::bail;
+
+use crypto::blake2b;
+use crypto::hash::{ContextHash, HashType};
+use crypto::signature::PublicKey;
+use storage::context::{ContextApiRef, ContextIndex};
+use storage::context_action_storage::contract_id_to_contract_address_for_index;
+use storage::skip_list::Bucket;
+use tezos_messages::base::signature_public_key_hash::SignaturePublicKeyHash;
+use tezos_messages::protocol::proto_005_2::operation::CONTRACT_ADDRESS_SIZE;
+
+/// Get value stored in the context list under the key, deleted keys are reported as missing
+///
+/// # Arguments
+///
+/// * `context_hash` - context hash of the block to select from context list
+/// * `key` - context key split into the path components
+/// * `list` - context list handler
+#[inline]
+pub fn get_context_value(context_hash: &ContextHash, key: &[String], list: &ContextApiRef) -> Result<Option<Vec<u8>>, failure::Error> {
+    let reader = list.read().unwrap();
+    match reader.get_key(&ContextIndex::new(Some(context_hash.clone())), &key.to_vec())? {
+        Some(Bucket::Exists(value)) => Ok(Some(value)),
+        _ => Ok(None),
+    }
+}
+
+/// Get all existing keys (and their values) starting with the prefix from the context list
+///
+/// # Arguments
+///
+/// * `context_hash` - context hash of the block to select from context list
+/// * `prefix` - context key prefix split into the path components
+/// * `list` - context list handler
+pub fn get_context_values_by_prefix(context_hash: &ContextHash, prefix: &[String], list: &ContextApiRef) -> Result<HashMap<String, Vec<u8>>, failure::Error> {
+    let reader = list.read().unwrap();
+    let context = reader.get_by_key_prefix(&ContextIndex::new(Some(context_hash.clone())), &prefix.to_vec())?
+        .unwrap_or_default();
+
+    Ok(context.into_iter()
+        .filter_map(|(key, value)| match value {
+            Bucket::Exists(value) => Some((key, value)),
+            Bucket::Deleted => None,
+        })
+        .collect())
+}
+
+/// Append path components to the context key
+#[inline]
+pub fn context_key(path: &[String], suffix: &[&str]) -> Vec<String> {
+    path.iter()
+        .cloned()
+        .chain(suffix.iter().map(|component| component.to_string()))
+        .collect()
+}
+
+/// Context path of the contract storage (e.g. data/contracts/index/ad/af/43/23/f9/3e/000003cb7d7842406496fc07288635562bfd17e176c4)
+///
+/// Contracts are indexed by the first 6 bytes of the blake2b hash of the binary contract id.
+///
+/// # Arguments
+///
+/// * `contract_id` - contract id (tz... or KT1...)
+pub fn contract_context_path(contract_id: &str) -> Result<Vec<String>, failure::Error> {
+    let contract_address = contract_id_to_contract_address_for_index(contract_id)?;
+    let index_hash = blake2b::digest_256(&contract_address);
+
+    let mut path = vec!["data".to_string(), "contracts".to_string(), "index".to_string()];
+    path.extend(index_hash[0..6].iter().map(|byte| hex::encode(&[*byte])));
+    path.push(hex::encode(&contract_address));
+    Ok(path)
+}
+
+/// Return revealed public key of the contract manager, None if the key was not revealed yet.
+///
+/// # Arguments
+///
+/// * `context_hash` - Context hash of the block, whose context is read.
+/// * `contract_id` - Contract id (tz... or KT1...).
+/// * `list` - Context list handler.
+pub fn get_manager_key(context_hash: &ContextHash, contract_id: &str, list: &ContextApiRef) -> Result<Option<PublicKey>, failure::Error> {
+    let contract_path = contract_context_path(contract_id)?;
+
+    // manager is stored as public key hash (tag 0) until the public key is revealed (tag 1)
+    match get_context_value(context_hash, &context_key(&contract_path, &["manager"]), list)? {
+        Some(ref manager) if manager.first() == Some(&1) => Ok(Some(PublicKey::from_tagged_bytes(&manager[1..])?)),
+        _ => Ok(None),
+    }
+}
+
+/// Context path of the registered delegate (e.g. data/delegates/ed25519/2c/ca/28/ab/01/9ae2d8c26f4ce4924cad67a2dc6618)
+///
+/// # Arguments
+///
+/// * `pkh` - public key hash of the delegate (tz...)
+pub fn delegate_context_path(pkh: &str) -> Result<Vec<String>, failure::Error> {
+    let (curve, hash) = match SignaturePublicKeyHash::from_b58_hash(pkh)? {
+        SignaturePublicKeyHash::Ed25519(hash) => ("ed25519", hash),
+        SignaturePublicKeyHash::Secp256k1(hash) => ("secp256k1", hash),
+        SignaturePublicKeyHash::P256(hash) => ("p256", hash),
+    };
+    let hash = hex::encode(hash);
+
+    let mut path = vec!["data".to_string(), "delegates".to_string(), curve.to_string()];
+    path.extend((0..5).map(|idx| hash[idx * 2..idx * 2 + 2].to_string()));
+    path.push(hash[10..].to_string());
+    Ok(path)
+}
+
+/// Convert binary contract id used in the context keys to contract id (tz... or KT1...)
+///
+/// Implicit contracts are tagged by 0 followed by tagged public key hash,
+/// originated contracts are tagged by 1 followed by the contract hash and one byte of padding.
+pub fn contract_address_to_contract_id(contract_address: &[u8]) -> Result<String, failure::Error> {
+    if contract_address.len() != CONTRACT_ADDRESS_SIZE {
+        bail!("Invalid contract address: {}", hex::encode(contract_address));
+    }
+    match contract_address[0] {
+        0 => Ok(pkh_from_tagged_bytes(&contract_address[1..])?.to_string()),
+        1 => Ok(HashType::ContractKt1Hash.bytes_to_string(&contract_address[1..CONTRACT_ADDRESS_SIZE - 1])),
+        tag => bail!("Invalid contract address tag: {}", tag),
+    }
+}
+
+/// Decode public key hash stored in the context as curve tag followed by the 20 bytes of the hash
+pub fn pkh_from_tagged_bytes(bytes: &[u8]) -> Result<SignaturePublicKeyHash, failure::Error> {
+    if bytes.len() != 21 {
+        bail!("Invalid public key hash: {}", hex::encode(bytes));
+    }
+    let curve = match bytes[0] {
+        0 => "ed25519",
+        1 => "secp256k1",
+        2 => "p256",
+        tag => bail!("Invalid curve tag: {}", tag),
+    };
+    Ok(SignaturePublicKeyHash::from_hex_hash_and_curve(&hex::encode(&bytes[1..]), curve)?)
+}
+
+/// Decode tez amount stored in the context, protocol keeps amounts in mutez as int64 encoded as variable length natural number
+pub fn tez_from_bytes(bytes: &[u8]) -> Result<i64, failure::Error> {
+    let mut amount: i64 = 0;
+    for (idx, byte) in bytes.iter().enumerate() {
+        let shift = 7 * idx;
+        if shift >= 63 {
+            bail!("Tez amount overflow: {}", hex::encode(bytes));
+        }
+        amount |= i64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(amount);
+        }
+    }
+    bail!("Incomplete tez amount: {}", hex::encode(bytes))
+}
+
+/// Decode integer stored in the context as variable length zarith number (e.g. contract counter) and return its decimal representation
+///
+/// First byte carries the sign and 6 bits of the value, every following byte carries 7 bits of the value.
+pub fn z_from_bytes(bytes: &[u8]) -> Result<String, failure::Error> {
+    let first = match bytes.first() {
+        Some(first) => first,
+        None => bail!("Incomplete integer: {}", hex::encode(bytes)),
+    };
+    let negative = first & 0x40 != 0;
+    let mut value: i64 = i64::from(first & 0x3f);
+    if first & 0x80 != 0 {
+        for (idx, byte) in bytes.iter().enumerate().skip(1) {
+            let shift = 6 + 7 * (idx - 1);
+            if shift >= 63 {
+                bail!("Integer overflow: {}", hex::encode(bytes));
+            }
+            value |= i64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(if negative { -value } else { value }.to_string());
+            }
+        }
+        bail!("Incomplete integer: {}", hex::encode(bytes))
+    }
+    Ok(if negative { -value } else { value }.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_key() {
+        let path = vec!["data".to_string(), "contracts".to_string()];
+        assert_eq!(vec!["data", "contracts", "index", "balance"], context_key(&path, &["index", "balance"]));
+        assert_eq!(vec!["data", "delegates"], context_key(&[], &["data", "delegates"]));
+    }
+
+    #[test]
+    fn test_contract_context_path() -> Result<(), failure::Error> {
+        assert_eq!(
+            "data/contracts/index/ad/af/43/23/f9/3e/000003cb7d7842406496fc07288635562bfd17e176c4",
+            contract_context_path("tz1Kz6VSEPNnKPiNvhyio6E1otbSdDhVD9qB")?.join("/"),
+        );
+        assert!(contract_context_path("invalid").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_delegate_context_path() -> Result<(), failure::Error> {
+        assert_eq!(
+            "data/delegates/ed25519/2c/ca/28/ab/01/9ae2d8c26f4ce4924cad67a2dc6618",
+            delegate_context_path("tz1PirboZKFVqkfE45hVLpkpXaZtLk3mqC17")?.join("/"),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_contract_address_to_contract_id() -> Result<(), failure::Error> {
+        assert_eq!("tz1Kz6VSEPNnKPiNvhyio6E1otbSdDhVD9qB", contract_address_to_contract_id(&hex::decode("000003cb7d7842406496fc07288635562bfd17e176c4")?)?);
+        assert_eq!("KT1CX2LRUzpD1SAyYhRKPMpKKy1DYtCdYCpq", contract_address_to_contract_id(&hex::decode("012b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b00")?)?);
+        assert!(contract_address_to_contract_id(&hex::decode("000003cb7d7842406496fc07288635562bfd17e176")?).is_err());
+        assert!(contract_address_to_contract_id(&hex::decode("020003cb7d7842406496fc07288635562bfd17e176c4")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pkh_from_tagged_bytes() -> Result<(), failure::Error> {
+        assert_eq!("tz1PirboZKFVqkfE45hVLpkpXaZtLk3mqC17", pkh_from_tagged_bytes(&hex::decode("002cca28ab019ae2d8c26f4ce4924cad67a2dc6618")?)?.to_string());
+        assert!(pkh_from_tagged_bytes(&hex::decode("032cca28ab019ae2d8c26f4ce4924cad67a2dc6618")?).is_err());
+        assert!(pkh_from_tagged_bytes(&[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tez_from_bytes() -> Result<(), failure::Error> {
+        assert_eq!(0, tez_from_bytes(&[0x00])?);
+        assert_eq!(127, tez_from_bytes(&[0x7f])?);
+        assert_eq!(128, tez_from_bytes(&[0x80, 0x01])?);
+        assert_eq!(1_000_000, tez_from_bytes(&hex::decode("c0843d")?)?);
+        assert!(tez_from_bytes(&[0x80]).is_err());
+        assert!(tez_from_bytes(&[0xff; 10]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_z_from_bytes() -> Result<(), failure::Error> {
+        assert_eq!("0", z_from_bytes(&[0x00])?);
+        assert_eq!("-42", z_from_bytes(&[0x6a])?);
+        assert_eq!("64", z_from_bytes(&[0x80, 0x01])?);
+        assert_eq!("-8256", z_from_bytes(&[0xc0, 0x81, 0x01])?);
+        assert!(z_from_bytes(&[]).is_err());
+        assert!(z_from_bytes(&[0x80]).is_err());
+        Ok(())
+    }
+}
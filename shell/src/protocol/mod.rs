@@ -0,0 +1,65 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Protocol data stored in the context, which are needed by the shell as well as by the RPCs,
+//! e.g. baking rights used to verify the signatures of the block headers.
+
+use std::convert::TryInto;
+
+use failure::bail;
+
+use crypto::hash::{ContextHash, ProtocolHash};
+use storage::{BlockStorage, BlockStorageReader};
+use storage::context::ContextIndex;
+use storage::persistent::PersistentStorage;
+
+pub mod context_helpers;
+pub mod proto_005_2;
+pub mod proto_006;
+
+#[macro_export]
+macro_rules! merge_slices {
+    ( $($x:expr),* ) => {{
+        let mut res = vec![];
+        $(
+            res.extend_from_slice($x);
+        )*
+        res
+    }}
+}
+
+/// Protocol and its constants stored in the context of the block
+pub struct ContextProtocolParam {
+    pub protocol_hash: ProtocolHash,
+    pub constants_data: Vec<u8>,
+    pub level: usize,
+    pub context_hash: ContextHash,
+}
+
+/// Return block timestamp in epoch time format by block level
+/// 
+/// # Arguments
+/// 
+/// * `level` - Level of block.
+/// * `state` - Current RPC state (head).
+pub fn get_block_timestamp_by_level(level: i32, persistent_storage: &PersistentStorage) -> Result<i64, failure::Error> {
+    let block_storage = BlockStorage::new(persistent_storage);
+    match block_storage.get_by_block_level(level)? {
+        Some(current_head) => Ok(current_head.header.timestamp()),
+        None => bail!("Block not found in db by level {}", level)
+    }
+}
+
+/// Return context index of the block at the level of the current branch
+///
+/// # Arguments
+///
+/// * `level` - Level of block.
+/// * `persistent_storage` - Persistent storage handler.
+pub fn get_context_index_by_level(level: usize, persistent_storage: &PersistentStorage) -> Result<ContextIndex, failure::Error> {
+    let block_storage = BlockStorage::new(persistent_storage);
+    match block_storage.get_by_block_level(level.try_into()?)? {
+        Some(block) => Ok(ContextIndex::new(Some(block.header.context().clone()))),
+        None => bail!("Block not found in db by level {}", level)
+    }
+}
@@ -9,30 +9,32 @@ use failure::{bail, Fail, format_err};
 use getset::Getters;
 
 use crypto::blake2b;
+use crypto::hash::ContextHash;
+use storage::context::{ContextApiRef, ContextIndex};
 use storage::num_from_slice;
-use storage::persistent::{ContextList, ContextMap, PersistentStorage};
+use storage::persistent::{ContextMap, PersistentStorage};
 use storage::skip_list::Bucket;
 use tezos_messages::base::signature_public_key_hash::SignaturePublicKeyHash;
 use tezos_messages::p2p::binary_message::BinaryMessage;
 
-use crate::helpers::{ContextProtocolParam, get_block_timestamp_by_level};
+use crate::protocol::{ContextProtocolParam, get_block_timestamp_by_level, get_context_index_by_level};
 
 use crate::merge_slices;
 
 /// Context constants used in baking and endorsing rights
 #[derive(Debug, Clone, Getters)]
 pub struct RightsConstants {
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     blocks_per_cycle: i32,
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     preserved_cycles: u8,
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     nonce_length: u8,
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     time_between_blocks: Vec<i64>,
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     blocks_per_roll_snapshot: i32,
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     endorsers_per_block: u16,
 }
 
@@ -66,7 +68,7 @@ impl RightsConstants {
     /// * `persistent_storage` - Persistent storage handler.
     /// * `state` - Current RPC collected state (head).
     #[inline]
-    pub(crate) fn parse_rights_constants(context_proto_param: ContextProtocolParam) -> Result<Self, failure::Error> {
+    pub fn parse_rights_constants(context_proto_param: ContextProtocolParam) -> Result<Self, failure::Error> {
         let dynamic = tezos_messages::protocol::proto_005_2::constants::ParametricConstants::from_bytes(context_proto_param.constants_data)?;
         let fixed = tezos_messages::protocol::proto_005_2::constants::FIXED;
 
@@ -85,15 +87,15 @@ impl RightsConstants {
 #[derive(Debug, Clone, Getters)]
 pub struct RightsContextData {
     /// Random seed for Tezos PRNG
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     random_seed: Vec<u8>,
 
     /// Number of last roll so Tezos PRNG will not overflow
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     last_roll: i32,
 
     /// List of rolls mapped to rollers contract id
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     rolls: HashMap<i32, String>,
 }
 
@@ -113,10 +115,12 @@ impl RightsContextData {
     ///
     /// * `parameters` - Parameters created by [RightsParams](RightsParams::parse_rights_parameters).
     /// * `constants` - Context constants used in baking and endorsing rights.
+    /// * `context_hash` - Context hash of the block at the block_level.
     /// * `list` - Context list handler.
+    /// * `persistent_storage` - Persistent storage handler.
     ///
     /// Return RightsContextData.
-    pub(crate) fn prepare_context_data_for_rights(parameters: RightsParams, constants: RightsConstants, list: ContextList) -> Result<Self, failure::Error> {
+    pub fn prepare_context_data_for_rights(parameters: RightsParams, constants: RightsConstants, context_hash: &ContextHash, list: ContextApiRef, persistent_storage: &PersistentStorage) -> Result<Self, failure::Error> {
         // prepare constants that are used
         let blocks_per_cycle = *constants.blocks_per_cycle();
         let preserved_cycles = *constants.preserved_cycles();
@@ -134,7 +138,8 @@ impl RightsContextData {
         };
 
         // get context list of block_id level as ContextMap
-        let current_context = Self::get_context_as_hashmap(block_level.try_into()?, list.clone())?;
+        let current_context_index = ContextIndex::new(Some(context_hash.clone()));
+        let current_context = Self::get_context_as_hashmap(&current_context_index, list.clone())?;
 
         // get index of roll snapshot
         let roll_snapshot: i16 = {
@@ -167,14 +172,20 @@ impl RightsContextData {
 
         // prepare context list from which rollers are selected
         // first prepare snapshot_level which is used access context list where are stored rollers for requested_cycle
-        let snapshot_level = if requested_cycle < (preserved_cycles as i64) + 2 {
-            block_level
+        // snapshots are older than the block_id, so they are read from the current branch by level
+        let roll_context_index = if requested_cycle < (preserved_cycles as i64) + 2 {
+            current_context_index
         } else {
             let cycle_of_rolls = requested_cycle - (preserved_cycles as i64) - 2;
             // to calculate order of snapshot add 1 to snapshot index (roll_snapshot)
-            (cycle_of_rolls * (blocks_per_cycle as i64)) + (((roll_snapshot + 1) as i64) * (blocks_per_roll_snapshot as i64)) - 1
+            let snapshot_level = (cycle_of_rolls * (blocks_per_cycle as i64)) + (((roll_snapshot + 1) as i64) * (blocks_per_roll_snapshot as i64)) - 1;
+            if snapshot_level == block_level {
+                current_context_index
+            } else {
+                get_context_index_by_level(snapshot_level.try_into()?, persistent_storage)?
+            }
         };
-        let roll_context = Self::get_context_as_hashmap(snapshot_level.try_into()?, list.clone())?;
+        let roll_context = Self::get_context_as_hashmap(&roll_context_index, list.clone())?;
 
         // get list of rolls from context list
         let context_rolls = if let Some(rolls) = Self::get_context_rolls(roll_context)? {
@@ -225,15 +236,15 @@ impl RightsContextData {
     ///
     /// # Arguments
     ///
-    /// * `level` - level to select from context list
+    /// * `context_index` - index of the context to select from context list
     /// * `list` - context list handler
     ///
-    /// Return context list for given level as HashMap
-    fn get_context_as_hashmap(level: usize, list: ContextList) -> Result<ContextMap, failure::Error> {
+    /// Return context list for given index as HashMap
+    fn get_context_as_hashmap(context_index: &ContextIndex, list: ContextApiRef) -> Result<ContextMap, failure::Error> {
         // get the whole context
         let context = {
             let reader = list.read().unwrap();
-            if let Ok(Some(ctx)) = reader.get(level) {
+            if let Ok(Some(ctx)) = reader.get_context(context_index) {
                 ctx
             } else {
                 bail!("Context not found")
@@ -247,43 +258,43 @@ impl RightsContextData {
 #[derive(Debug, Clone, Getters)]
 pub struct RightsParams {
     /// Id of a chain. Url path parameter 'chain_id'.
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     chain_id: String,
 
     /// Level (height) of block. Parsed from url path parameter 'block_id'.
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     block_level: i64,
 
     /// Header timestamp of block. Parsed from url path parameter 'block_id'.
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     block_timestamp: i64,
 
     /// Contract id to filter output by delegate. Url query parameter 'delegate'.
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     requested_delegate: Option<String>,
 
     /// Cycle for whitch all rights will be listed. Url query parameter 'cycle'.
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     requested_cycle: Option<i64>,
 
     /// Level (height) of block for whitch all rights will be listed. Url query parameter 'level'.
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     requested_level: i64,
 
     /// Level to be displayed in output.
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     display_level: i64,
 
     /// Level for estimated_time computation. Endorsing rights only.
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     timestamp_level: i64,
 
     /// Max priority to which baking rights are listed. Url query parameter 'max_priority'.
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     max_priority: i64,
 
     /// Indicate that baking rights for maximum priority should be listed. Url query parameter 'all'.
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     has_all: bool,
 }
 
@@ -331,7 +342,7 @@ impl RightsParams {
     /// * `is_baking_rights` - flag to identify if are parsed baking or endorsing rights
     ///
     /// Return RightsParams
-    pub(crate) fn parse_rights_parameters(
+    pub fn parse_rights_parameters(
         param_chain_id: &str,
         param_level: Option<&str>,
         param_delegate: Option<&str>,
@@ -448,11 +459,11 @@ impl RightsParams {
 #[derive(Debug, Clone, Getters)]
 pub struct EndorserSlots {
     /// endorser contract id in form:tz1.../tz2.../KT1...
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     contract_id: String,
 
     /// Orderer vector of endorsement slots
-    #[get = "pub(crate)"]
+    #[get = "pub"]
     slots: Vec<u16>,
 }
 
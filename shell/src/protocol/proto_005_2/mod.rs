@@ -0,0 +1,74 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Baking rights of the protocol 005_2 drawn from the rolls stored in the context.
+
+use std::convert::TryInto;
+
+use storage::context::ContextApiRef;
+use storage::persistent::PersistentStorage;
+
+use crate::protocol::ContextProtocolParam;
+use crate::protocol::proto_005_2::helpers::{get_prng_number, init_prng, RightsConstants, RightsContextData, RightsParams};
+
+pub mod helpers;
+
+/// Draw the delegate, who has the right to bake the block at the level with the priority, using Tezos PRNG
+///
+/// # Arguments
+///
+/// * `context_data` - Data from context list used in baking and endorsing rights generation filled in [RightsContextData](RightsContextData::prepare_context_data_for_rights).
+/// * `constants` - Context constants used in baking and endorsing rights [RightsConstants](RightsConstants::parse_rights_constants).
+/// * `level` - Level to feed Tezos PRNG.
+/// * `priority` - Baking priority to feed Tezos PRNG.
+#[inline]
+pub fn draw_baker<'a>(context_data: &'a RightsContextData, constants: &RightsConstants, level: i32, priority: i64) -> Result<&'a String, failure::Error> {
+    const BAKING_USE_STRING: &[u8] = b"level baking:";
+
+    let last_roll = *context_data.last_roll();
+    let rolls_map = context_data.rolls();
+
+    // TODO: priority can overflow in the ocaml code, do a priority % i32::max_value()
+    let mut state = init_prng(context_data, constants, BAKING_USE_STRING, level, priority.try_into()?)?;
+    loop {
+        let (random_num, sequence) = get_prng_number(state, last_roll)?;
+
+        if let Some(delegate) = rolls_map.get(&random_num) {
+            return Ok(delegate);
+        } else {
+            state = sequence;
+        }
+    }
+}
+
+/// Return public key hash of the delegate, who has the right to bake the block at the level with the priority.
+///
+/// # Arguments
+///
+/// * `context_proto_params` - Protocol hash, constants and level of the predecessor of the baked block.
+/// * `chain_id` - Chain id of the baked block.
+/// * `block_timestamp` - Timestamp of the predecessor of the baked block.
+/// * `level` - Level of the baked block.
+/// * `priority` - Baking priority of the baked block.
+/// * `list` - Context list handler.
+/// * `persistent_storage` - Persistent storage handler.
+pub fn get_baker(
+    context_proto_params: ContextProtocolParam,
+    chain_id: &str,
+    block_timestamp: i64,
+    level: i32,
+    priority: u16,
+    list: ContextApiRef,
+    persistent_storage: &PersistentStorage) -> Result<String, failure::Error> {
+
+    let block_level: i64 = context_proto_params.level.try_into()?;
+    let context_hash = context_proto_params.context_hash.clone();
+
+    let constants: RightsConstants = RightsConstants::parse_rights_constants(context_proto_params)?;
+
+    let params = RightsParams::new(chain_id.to_string(), block_level, block_timestamp, None, None, level.into(), level.into(), level.into(), priority.into(), false);
+
+    let context_data: RightsContextData = RightsContextData::prepare_context_data_for_rights(params, constants.clone(), &context_hash, list, persistent_storage)?;
+
+    draw_baker(&context_data, &constants, level, priority.into()).map(String::clone)
+}
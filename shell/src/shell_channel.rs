@@ -6,9 +6,11 @@
 use getset::Getters;
 use riker::actors::*;
 
-use crypto::hash::BlockHash;
+use crypto::hash::{BlockHash, OperationHash};
 use storage::block_storage::BlockJsonData;
 use storage::BlockHeaderWithHash;
+use tezos_api::ffi::ValidateOperationResult;
+use tezos_messages::p2p::encoding::prelude::Operation;
 
 /// Message informing actors about successful block application by protocol
 #[derive(Clone, Debug, Getters)]
@@ -25,6 +27,31 @@ impl BlockApplied {
     }
 }
 
+/// Message informing actors that current head was switched to a block from a different branch.
+///
+/// Blocks in `rolled_back_blocks` are no longer part of the main chain, blocks in
+/// `applied_blocks` are now part of the main chain. Both lists are ordered from the
+/// common ancestor (exclusive) towards the respective head (inclusive).
+#[derive(Clone, Debug, Getters)]
+pub struct HeadSwitched {
+    #[get = "pub"]
+    previous_head: BlockHash,
+    #[get = "pub"]
+    new_head: BlockHeaderWithHash,
+    #[get = "pub"]
+    common_ancestor: BlockHash,
+    #[get = "pub"]
+    rolled_back_blocks: Vec<BlockHash>,
+    #[get = "pub"]
+    applied_blocks: Vec<BlockHash>,
+}
+
+impl HeadSwitched {
+    pub fn new(previous_head: BlockHash, new_head: BlockHeaderWithHash, common_ancestor: BlockHash, rolled_back_blocks: Vec<BlockHash>, applied_blocks: Vec<BlockHash>) -> Self {
+        Self { previous_head, new_head, common_ancestor, rolled_back_blocks, applied_blocks }
+    }
+}
+
 /// Notify actors that system is about to shut down
 #[derive(Clone, Debug)]
 pub struct ShuttingDown;
@@ -43,12 +70,39 @@ pub struct AllBlockOperationsReceived {
     pub level: i32,
 }
 
+/// Message informing actors about receiving new operation to the mempool, operation should be validated by protocol
+#[derive(Clone, Debug)]
+pub struct MempoolOperationReceived {
+    pub operation_hash: OperationHash,
+    pub operation: Operation,
+}
+
+/// Message informing actors about result of the mempool operation validation
+#[derive(Clone, Debug, Getters)]
+pub struct MempoolOperationValidated {
+    #[get = "pub"]
+    operation_hash: OperationHash,
+    #[get = "pub"]
+    operation: Operation,
+    #[get = "pub"]
+    result: ValidateOperationResult,
+}
+
+impl MempoolOperationValidated {
+    pub fn new(operation_hash: OperationHash, operation: Operation, result: ValidateOperationResult) -> Self {
+        Self { operation_hash, operation, result }
+    }
+}
+
 /// Shell channel event message.
 #[derive(Clone, Debug)]
 pub enum ShellChannelMsg {
     BlockApplied(BlockApplied),
+    HeadSwitched(HeadSwitched),
     BlockReceived(BlockReceived),
     AllBlockOperationsReceived(AllBlockOperationsReceived),
+    MempoolOperationReceived(MempoolOperationReceived),
+    MempoolOperationValidated(MempoolOperationValidated),
     ShuttingDown(ShuttingDown),
 }
 
@@ -58,6 +112,12 @@ impl From<BlockApplied> for ShellChannelMsg {
     }
 }
 
+impl From<HeadSwitched> for ShellChannelMsg {
+    fn from(msg: HeadSwitched) -> Self {
+        ShellChannelMsg::HeadSwitched(msg)
+    }
+}
+
 impl From<BlockReceived> for ShellChannelMsg {
     fn from(msg: BlockReceived) -> Self {
         ShellChannelMsg::BlockReceived(msg)
@@ -70,6 +130,18 @@ impl From<AllBlockOperationsReceived> for ShellChannelMsg {
     }
 }
 
+impl From<MempoolOperationReceived> for ShellChannelMsg {
+    fn from(msg: MempoolOperationReceived) -> Self {
+        ShellChannelMsg::MempoolOperationReceived(msg)
+    }
+}
+
+impl From<MempoolOperationValidated> for ShellChannelMsg {
+    fn from(msg: MempoolOperationValidated) -> Self {
+        ShellChannelMsg::MempoolOperationValidated(msg)
+    }
+}
+
 impl From<ShuttingDown> for ShellChannelMsg {
     fn from(msg: ShuttingDown) -> Self {
         ShellChannelMsg::ShuttingDown(msg)
@@ -7,7 +7,7 @@ use std::cmp::Ordering;
 use rand::Rng;
 
 use crypto::hash::{BlockHash, ChainId};
-use storage::{BlockHeaderWithHash, BlockMetaStorage, BlockStorage, BlockStorageReader, IteratorMode, StorageError};
+use storage::{BlockHeaderWithHash, BlockMetaStorage, BlockStorage, BlockStorageReader, IteratorMode, StorageError, SystemStorage};
 use storage::persistent::PersistentStorage;
 
 use crate::collections::{BlockData, UniqueBlockData};
@@ -18,6 +18,8 @@ pub struct BlockState {
     block_storage: BlockStorage,
     ///persistent block metadata storage
     block_meta_storage: BlockMetaStorage,
+    /// persistent system storage, holds the pruned level
+    system_storage: SystemStorage,
     /// Current missing blocks.
     /// This represents a set of missing block we will try to retrieve in the future.
     /// Before we try to fetch missing block it is removed from this queue.
@@ -33,12 +35,21 @@ impl BlockState {
         BlockState {
             block_storage: BlockStorage::new(persistent_storage),
             block_meta_storage: BlockMetaStorage::new(persistent_storage),
+            system_storage: SystemStorage::new(persistent_storage.kv()),
             missing_blocks: UniqueBlockData::new(),
             chain_id: chain_id.clone(),
         }
     }
 
-    pub fn process_block_header(&mut self, block_header: &BlockHeaderWithHash) -> Result<(), StorageError> {
+    /// Store block header and schedule its predecessor, if it is not known yet.
+    ///
+    /// Returns `false` if the block was ignored, because it is not known and its level was already pruned,
+    /// so it belongs to an abandoned branch.
+    pub fn process_block_header(&mut self, block_header: &BlockHeaderWithHash) -> Result<bool, StorageError> {
+        if self.is_pruned_level(block_header.header.level())? && !self.block_storage.contains(&block_header.hash)? {
+            return Ok(false);
+        }
+
         // check if we already have seen predecessor
         self.push_missing_block(MissingBlock {
             block_hash: block_header.header.predecessor().clone(),
@@ -50,7 +61,7 @@ impl BlockState {
         // update meta
         self.block_meta_storage.put_block_header(block_header, &self.chain_id)?;
 
-        Ok(())
+        Ok(true)
     }
 
     #[inline]
@@ -68,12 +79,21 @@ impl BlockState {
 
     #[inline]
     pub fn push_missing_block(&mut self, missing_block: MissingBlock) -> Result<(), StorageError> {
+        // blocks with unknown level are scheduled anyway and ignored once their header is received
+        if missing_block.level > 0 && self.is_pruned_level(missing_block.level)? {
+            return Ok(());
+        }
         if !self.block_storage.contains(&missing_block.block_hash)? {
             self.missing_blocks.push(missing_block);
         }
         Ok(())
     }
 
+    #[inline]
+    fn is_pruned_level(&self, level: i32) -> Result<bool, StorageError> {
+        Ok(self.system_storage.get_pruned_level()?.filter(|pruned_level| level <= *pruned_level).is_some())
+    }
+
     #[inline]
     pub fn has_missing_blocks(&self) -> bool {
         !self.missing_blocks.is_empty()
@@ -0,0 +1,299 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use futures::channel::oneshot;
+use getset::Getters;
+
+use crypto::hash::OperationHash;
+use tezos_api::ffi::{OperationClassification, ValidateOperationResult};
+use tezos_messages::p2p::encoding::prelude::*;
+
+/// Operation in the mempool together with the result of its validation
+#[derive(Clone, Debug, Getters)]
+pub struct MempoolOperation {
+    #[get = "pub"]
+    operation: Operation,
+    #[get = "pub"]
+    result: ValidateOperationResult,
+}
+
+/// Receives the validation result of the operation, `None` means that operation left the mempool without
+/// being validated again, because it was included in a block.
+pub type ValidationReceiver = oneshot::Receiver<Option<ValidateOperationResult>>;
+
+/// Holds the state of the mempool.
+///
+/// Every known operation is either requested from a peer, waiting for validation (unprocessed)
+/// or classified by the protocol as applied, refused, branch refused or branch delayed.
+#[derive(Default, Getters)]
+pub struct MempoolState {
+    /// Operations which were requested from peers, but were not received yet
+    requested: HashMap<OperationHash, Instant>,
+    /// Operations received from peers, which are waiting for validation
+    #[get = "pub"]
+    unprocessed: HashMap<OperationHash, Operation>,
+    /// Operations successfully applied on top of the current head
+    #[get = "pub"]
+    applied: HashMap<OperationHash, MempoolOperation>,
+    /// Operations which will never be valid
+    #[get = "pub"]
+    refused: HashMap<OperationHash, MempoolOperation>,
+    /// Operations which are not valid on the current branch
+    #[get = "pub"]
+    branch_refused: HashMap<OperationHash, MempoolOperation>,
+    /// Operations which are not valid yet on the current branch
+    #[get = "pub"]
+    branch_delayed: HashMap<OperationHash, MempoolOperation>,
+    /// Listeners waiting for the validation result of the unprocessed operations
+    validation_waiters: HashMap<OperationHash, Vec<oneshot::Sender<Option<ValidateOperationResult>>>>,
+}
+
+impl MempoolState {
+    /// Returns `true` if operation is already known to the mempool
+    pub fn is_known(&self, operation_hash: &OperationHash) -> bool {
+        self.requested.contains_key(operation_hash)
+            || self.unprocessed.contains_key(operation_hash)
+            || self.applied.contains_key(operation_hash)
+            || self.refused.contains_key(operation_hash)
+            || self.branch_refused.contains_key(operation_hash)
+            || self.branch_delayed.contains_key(operation_hash)
+    }
+
+    /// Register operation which will be requested from a peer.
+    ///
+    /// Returns `true` if operation was not yet known to the mempool.
+    pub fn register_requested(&mut self, operation_hash: OperationHash) -> bool {
+        if self.is_known(&operation_hash) {
+            false
+        } else {
+            self.requested.insert(operation_hash, Instant::now());
+            true
+        }
+    }
+
+    /// Process operation received from a peer, only requested operations are accepted.
+    ///
+    /// Returns `true` if operation should be validated.
+    pub fn process_received(&mut self, operation_hash: OperationHash, operation: Operation) -> bool {
+        if self.requested.remove(&operation_hash).is_some() {
+            self.unprocessed.insert(operation_hash, operation);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Add operation which was not received from a peer (e.g. it was injected).
+    ///
+    /// Returns `true` if operation should be validated.
+    pub fn add_unprocessed(&mut self, operation_hash: OperationHash, operation: Operation) -> bool {
+        if self.is_known(&operation_hash) && !self.requested.contains_key(&operation_hash) {
+            false
+        } else {
+            self.requested.remove(&operation_hash);
+            self.unprocessed.insert(operation_hash, operation);
+            true
+        }
+    }
+
+    /// Classify unprocessed operation by the validation result.
+    ///
+    /// Returns `false` if operation was not waiting for validation, e.g. it was included in a block meanwhile.
+    pub fn process_result(&mut self, operation_hash: &OperationHash, result: &ValidateOperationResult) -> bool {
+        match self.unprocessed.remove(operation_hash) {
+            Some(operation) => {
+                let classified = match result.classification {
+                    OperationClassification::Applied => &mut self.applied,
+                    OperationClassification::Refused => &mut self.refused,
+                    OperationClassification::BranchRefused => &mut self.branch_refused,
+                    OperationClassification::BranchDelayed => &mut self.branch_delayed,
+                };
+                classified.insert(operation_hash.clone(), MempoolOperation { operation, result: result.clone() });
+                self.notify_waiters(operation_hash, Some(result));
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Remove operations, which were included in the applied block
+    pub fn remove_operations(&mut self, operation_hashes: &[OperationHash]) {
+        for operation_hash in operation_hashes {
+            self.requested.remove(operation_hash);
+            self.unprocessed.remove(operation_hash);
+            self.applied.remove(operation_hash);
+            self.refused.remove(operation_hash);
+            self.branch_refused.remove(operation_hash);
+            self.branch_delayed.remove(operation_hash);
+            self.notify_waiters(operation_hash, None);
+        }
+    }
+
+    /// Move operations, which should be validated again on top of the new head, back to the unprocessed operations.
+    ///
+    /// Applied and branch delayed operations are always validated again, branch refused operations
+    /// are validated again only if the head was switched to another branch.
+    ///
+    /// Returns operations which should be validated.
+    pub fn reclassify(&mut self, branch_switched: bool) -> Vec<(OperationHash, Operation)> {
+        let mut operations: Vec<(OperationHash, Operation)> = self.applied.drain()
+            .chain(self.branch_delayed.drain())
+            .map(|(operation_hash, mempool_operation)| (operation_hash, mempool_operation.operation))
+            .collect();
+        if branch_switched {
+            operations.extend(
+                self.branch_refused.drain()
+                    .map(|(operation_hash, mempool_operation)| (operation_hash, mempool_operation.operation))
+            );
+        }
+
+        for (operation_hash, operation) in &operations {
+            self.unprocessed.insert(operation_hash.clone(), operation.clone());
+        }
+        operations
+    }
+
+    /// Forget requested operations, which were not received in time, so they can be requested again
+    pub fn remove_expired_requests(&mut self, timeout: Duration) -> usize {
+        let count = self.requested.len();
+        self.requested.retain(|_, requested| requested.elapsed() < timeout);
+        count - self.requested.len()
+    }
+
+    /// Find operation known to the mempool
+    pub fn find_operation(&self, operation_hash: &OperationHash) -> Option<&Operation> {
+        self.unprocessed.get(operation_hash)
+            .or_else(|| self.applied.get(operation_hash).map(|o| &o.operation))
+            .or_else(|| self.branch_delayed.get(operation_hash).map(|o| &o.operation))
+            .or_else(|| self.branch_refused.get(operation_hash).map(|o| &o.operation))
+            .or_else(|| self.refused.get(operation_hash).map(|o| &o.operation))
+    }
+
+    /// Register listener for the validation result of the operation.
+    ///
+    /// If the operation was already classified (or it is not in the mempool anymore), the result is available immediately.
+    pub fn wait_for_validation(&mut self, operation_hash: &OperationHash) -> ValidationReceiver {
+        let (sender, receiver) = oneshot::channel();
+        if self.unprocessed.contains_key(operation_hash) {
+            self.validation_waiters.entry(operation_hash.clone())
+                .or_insert_with(Vec::new)
+                .push(sender);
+        } else {
+            let result = self.applied.get(operation_hash)
+                .or_else(|| self.branch_delayed.get(operation_hash))
+                .or_else(|| self.branch_refused.get(operation_hash))
+                .or_else(|| self.refused.get(operation_hash))
+                .map(|o| o.result.clone());
+            // receiver is still owned here, so send cannot fail
+            let _ = sender.send(result);
+        }
+        receiver
+    }
+
+    fn notify_waiters(&mut self, operation_hash: &OperationHash, result: Option<&ValidateOperationResult>) {
+        if let Some(waiters) = self.validation_waiters.remove(operation_hash) {
+            for waiter in waiters {
+                // waiter could already give up waiting
+                let _ = waiter.send(result.cloned());
+            }
+        }
+    }
+
+    /// Hashes of operations which are known to be valid on top of the current head
+    pub fn known_valid(&self) -> Vec<OperationHash> {
+        self.applied.keys().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tezos_messages::p2p::binary_message::BinaryMessage;
+
+    use super::*;
+
+    fn operation() -> Operation {
+        Operation::from_bytes(hex::decode("10490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e000008c387fa065a181d45d47a9b78ddc77e92a881779ff2cbabbf9646eade4bf1405a08e00b725ed849eea46953b10b5cdebc518e6fd47e69b82d2ca18c4cf6d2f312dd08").unwrap()).unwrap()
+    }
+
+    fn result(classification: OperationClassification) -> ValidateOperationResult {
+        ValidateOperationResult {
+            classification,
+            protocol_data_json: String::new(),
+            error_json: String::new(),
+        }
+    }
+
+    #[test]
+    fn mempool_state_classifies_requested_operations() {
+        let mut state = MempoolState::default();
+        let applied_hash = vec![1; 32];
+        let delayed_hash = vec![2; 32];
+        let unknown_hash = vec![3; 32];
+
+        assert!(state.register_requested(applied_hash.clone()));
+        assert!(state.register_requested(delayed_hash.clone()));
+        assert!(!state.register_requested(applied_hash.clone()));
+
+        // only requested operations are accepted
+        assert!(state.process_received(applied_hash.clone(), operation()));
+        assert!(state.process_received(delayed_hash.clone(), operation()));
+        assert!(!state.process_received(unknown_hash.clone(), operation()));
+        assert_eq!(2, state.unprocessed().len());
+
+        assert!(state.process_result(&applied_hash, &result(OperationClassification::Applied)));
+        assert!(state.process_result(&delayed_hash, &result(OperationClassification::BranchDelayed)));
+        assert!(!state.process_result(&unknown_hash, &result(OperationClassification::Applied)));
+        assert!(state.unprocessed().is_empty());
+        assert_eq!(vec![applied_hash.clone()], state.known_valid());
+        assert!(state.branch_delayed().contains_key(&delayed_hash));
+        assert!(state.find_operation(&delayed_hash).is_some());
+
+        // new head, so applied and delayed operations should be validated again
+        let reclassified = state.reclassify(false);
+        assert_eq!(2, reclassified.len());
+        assert!(state.known_valid().is_empty());
+        assert_eq!(2, state.unprocessed().len());
+
+        // operation was included in a block, so it is not part of the mempool anymore
+        state.remove_operations(&[applied_hash.clone()]);
+        assert!(!state.is_known(&applied_hash));
+        assert!(!state.process_result(&applied_hash, &result(OperationClassification::Applied)));
+        assert!(state.is_known(&delayed_hash));
+    }
+
+    #[test]
+    fn mempool_state_notifies_validation_waiters() {
+        let mut state = MempoolState::default();
+        let applied_hash = vec![1; 32];
+        let included_hash = vec![2; 32];
+        assert!(state.add_unprocessed(applied_hash.clone(), operation()));
+        assert!(state.add_unprocessed(included_hash.clone(), operation()));
+
+        let mut applied_waiter = state.wait_for_validation(&applied_hash);
+        let mut included_waiter = state.wait_for_validation(&included_hash);
+        assert_eq!(None, applied_waiter.try_recv().unwrap());
+
+        assert!(state.process_result(&applied_hash, &result(OperationClassification::Applied)));
+        state.remove_operations(&[included_hash.clone()]);
+
+        assert_eq!(Some(Some(result(OperationClassification::Applied))), applied_waiter.try_recv().unwrap());
+        assert_eq!(Some(None), included_waiter.try_recv().unwrap());
+
+        // operation is already classified, so result is available immediately
+        let mut late_waiter = state.wait_for_validation(&applied_hash);
+        assert_eq!(Some(Some(result(OperationClassification::Applied))), late_waiter.try_recv().unwrap());
+    }
+
+    #[test]
+    fn mempool_state_removes_expired_requests() {
+        let mut state = MempoolState::default();
+        assert!(state.register_requested(vec![1; 32]));
+
+        assert_eq!(0, state.remove_expired_requests(Duration::from_secs(60)));
+        assert_eq!(1, state.remove_expired_requests(Duration::from_secs(0)));
+        assert!(!state.is_known(&vec![1; 32]));
+    }
+}
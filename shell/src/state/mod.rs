@@ -2,4 +2,6 @@
 // SPDX-License-Identifier: MIT
 
 pub mod block_state;
+pub mod mempool_state;
 pub mod operations_state;
+pub mod peers_state;
@@ -0,0 +1,166 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::cmp;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use getset::Getters;
+
+use networking::p2p::network_channel::PeerBehaviour;
+use networking::p2p::peer::PeerId;
+use storage::PeerBan;
+
+/// Score of the peer cannot get higher than this, so a long connected peer can still be banned
+const MAX_SCORE: i32 = 100;
+
+/// Configuration of the peer scoring
+#[derive(Copy, Clone, Debug)]
+pub struct PeerScoring {
+    /// Peer is banned when its score drops to this value
+    pub ban_threshold: i32,
+    /// How long is the peer banned
+    pub ban_duration: Duration,
+}
+
+impl PeerScoring {
+    /// Score change for the observed behaviour of the peer
+    pub fn score_change(behaviour: PeerBehaviour) -> i32 {
+        match behaviour {
+            PeerBehaviour::InvalidMessage => -50,
+            PeerBehaviour::Timeout => -20,
+            PeerBehaviour::Nack => -10,
+            PeerBehaviour::BootstrapFailed => -100,
+            PeerBehaviour::BlockDelivered => 1,
+            PeerBehaviour::OperationsDelivered => 1,
+            PeerBehaviour::InvalidBlockHeader => -100,
+        }
+    }
+}
+
+impl Default for PeerScoring {
+    fn default() -> Self {
+        PeerScoring {
+            ban_threshold: -100,
+            ban_duration: Duration::from_secs(1_800),
+        }
+    }
+}
+
+/// Holds scores of the peers, connected peers and banned addresses.
+///
+/// Scores and bans are tracked per IP address, because the peer can easily change its port.
+#[derive(Getters)]
+pub struct PeersState {
+    scoring: PeerScoring,
+    /// Scores of the known IP addresses
+    scores: HashMap<IpAddr, i32>,
+    /// Connected and bootstrapped peers
+    #[get = "pub"]
+    connected: HashMap<SocketAddr, PeerId>,
+    /// Banned IP addresses
+    #[get = "pub"]
+    banned: HashMap<IpAddr, PeerBan>,
+}
+
+impl PeersState {
+    pub fn new(scoring: PeerScoring) -> Self {
+        PeersState {
+            scoring,
+            scores: HashMap::new(),
+            connected: HashMap::new(),
+            banned: HashMap::new(),
+        }
+    }
+
+    /// Current score of the IP address, unknown addresses have zero score
+    pub fn score(&self, address: &IpAddr) -> i32 {
+        self.scores.get(address).cloned().unwrap_or(0)
+    }
+
+    /// Update score of the IP address according to the peer behaviour.
+    ///
+    /// Returns `true` if the score dropped to the ban threshold and the address should be banned.
+    pub fn update_score(&mut self, address: IpAddr, behaviour: PeerBehaviour) -> bool {
+        let score = self.scores.entry(address).or_insert(0);
+        *score = cmp::min(*score + PeerScoring::score_change(behaviour), MAX_SCORE);
+        *score <= self.scoring.ban_threshold
+    }
+
+    /// Ban IP address for the configured duration, starting at the unix timestamp `now` (in seconds)
+    pub fn ban(&mut self, address: IpAddr, now: u64, reason: String) -> PeerBan {
+        let ban = PeerBan::new(now + self.scoring.ban_duration.as_secs(), reason);
+        self.scores.remove(&address);
+        self.banned.insert(address, ban.clone());
+        ban
+    }
+
+    /// Restore previously stored ban
+    pub fn restore_ban(&mut self, address: IpAddr, ban: PeerBan) {
+        self.banned.insert(address, ban);
+    }
+
+    /// Check if the IP address is banned at the unix timestamp `now` (in seconds)
+    pub fn is_banned(&self, address: &IpAddr, now: u64) -> bool {
+        self.banned.get(address)
+            .map(|ban| ban.is_active(now))
+            .unwrap_or(false)
+    }
+
+    /// Remove bans, which expired before the unix timestamp `now` (in seconds), and return unbanned addresses
+    pub fn remove_expired_bans(&mut self, now: u64) -> Vec<IpAddr> {
+        let expired = self.banned.iter()
+            .filter(|(_, ban)| !ban.is_active(now))
+            .map(|(address, _)| *address)
+            .collect::<Vec<_>>();
+        expired.iter().for_each(|address| { self.banned.remove(address); });
+        expired
+    }
+
+    pub fn peer_connected(&mut self, address: SocketAddr, peer_id: PeerId) {
+        self.connected.insert(address, peer_id);
+    }
+
+    pub fn peer_disconnected(&mut self, address: &SocketAddr) {
+        self.connected.remove(address);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_and_ban() {
+        let mut state = PeersState::new(PeerScoring::default());
+        let address: IpAddr = "192.168.1.10".parse().unwrap();
+
+        assert!(!state.update_score(address, PeerBehaviour::BlockDelivered));
+        assert_eq!(1, state.score(&address));
+        assert!(!state.update_score(address, PeerBehaviour::InvalidMessage));
+        assert!(!state.update_score(address, PeerBehaviour::Timeout));
+        assert!(state.update_score(address, PeerBehaviour::InvalidMessage));
+
+        let ban = state.ban(address, 1_000, "Invalid messages".to_string());
+        assert_eq!(2_800, ban.banned_until());
+        assert_eq!(0, state.score(&address));
+        assert!(state.is_banned(&address, 2_799));
+        assert!(!state.is_banned(&address, 2_800));
+
+        assert!(state.remove_expired_bans(2_799).is_empty());
+        assert_eq!(vec![address], state.remove_expired_bans(2_800));
+        assert!(state.banned().is_empty());
+    }
+
+    #[test]
+    fn test_score_is_capped() {
+        let mut state = PeersState::new(PeerScoring::default());
+        let address: IpAddr = "192.168.1.10".parse().unwrap();
+
+        (0..1_000).for_each(|_| { state.update_score(address, PeerBehaviour::OperationsDelivered); });
+        assert_eq!(MAX_SCORE, state.score(&address));
+        assert!(!state.update_score(address, PeerBehaviour::BootstrapFailed));
+        assert!(state.update_score(address, PeerBehaviour::BootstrapFailed));
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Resolution of the baking rights, which are used to verify minimal delays and signatures of the block headers.
+//!
+//! Baker is drawn from the baking rights computed from the context of the applied predecessor,
+//! its public key is the revealed manager key of the baker's implicit contract.
+
+use std::convert::TryInto;
+
+use failure::{bail, format_err};
+
+use crypto::hash::{ChainId, HashType};
+use crypto::signature::PublicKey;
+use storage::{BlockHeaderWithHash, BlockMetaStorage, BlockStorage, BlockStorageReader};
+use storage::context::{ContextApiRef, ContextIndex};
+use storage::persistent::PersistentStorage;
+use storage::skip_list::Bucket;
+use tezos_messages::p2p::binary_message::BinaryMessage;
+use tezos_messages::protocol::{
+    proto_005_2 as proto_005_2_constants,
+    proto_006 as proto_006_constants,
+};
+
+use crate::protocol::{ContextProtocolParam, proto_005_2, proto_006};
+use crate::protocol::context_helpers::get_manager_key;
+
+use super::{BakingRight, BakingRightsResolver, HeaderValidationError};
+
+/// Resolves baking rights from the rolls, constants and manager keys stored in the context.
+///
+/// Baking rights are resolved only for the protocols 005_2 and 006, the predecessor of the block has to be applied.
+pub struct ContextBakingRightsResolver {
+    persistent_storage: PersistentStorage,
+    block_storage: BlockStorage,
+    block_meta_storage: BlockMetaStorage,
+    list: ContextApiRef,
+}
+
+impl ContextBakingRightsResolver {
+    pub fn new(persistent_storage: &PersistentStorage, list: ContextApiRef) -> Self {
+        ContextBakingRightsResolver {
+            persistent_storage: persistent_storage.clone(),
+            block_storage: BlockStorage::new(persistent_storage),
+            block_meta_storage: BlockMetaStorage::new(persistent_storage),
+            list,
+        }
+    }
+
+    /// Return protocol and constants stored in the context of the applied predecessor together with its timestamp
+    fn predecessor_context_params(&self, header: &BlockHeaderWithHash) -> Result<(ContextProtocolParam, i64), failure::Error> {
+        let predecessor_hash = header.header.predecessor();
+        match self.block_meta_storage.get(predecessor_hash)? {
+            Some(meta) if meta.is_applied() => (),
+            _ => bail!("Predecessor {} is not applied", HashType::BlockHash.bytes_to_string(predecessor_hash)),
+        }
+        let predecessor = self.block_storage.get(predecessor_hash)?
+            .ok_or_else(|| format_err!("Applied predecessor {} was not found", HashType::BlockHash.bytes_to_string(predecessor_hash)))?;
+
+        let level: usize = predecessor.header.level().try_into()?;
+        let context_hash = predecessor.header.context().clone();
+        let context_index = ContextIndex::new(Some(context_hash.clone()));
+        let reader = self.list.read().unwrap();
+        let protocol_hash = match reader.get_key(&context_index, &vec!["protocol".to_string()])? {
+            Some(Bucket::Exists(protocol_hash)) => protocol_hash,
+            _ => bail!("Protocol was not found in the context {}", HashType::ContextHash.bytes_to_string(&context_hash)),
+        };
+        let constants_data = match reader.get_key(&context_index, &vec!["data".to_string(), "v1".to_string(), "constants".to_string()])? {
+            Some(Bucket::Exists(constants_data)) => constants_data,
+            _ => bail!("Protocol constants were not found in the context {}", HashType::ContextHash.bytes_to_string(&context_hash)),
+        };
+
+        Ok((ContextProtocolParam { protocol_hash, constants_data, level, context_hash }, predecessor.header.timestamp()))
+    }
+
+    fn baking_right(&self, chain_id: &ChainId, header: &BlockHeaderWithHash, priority: u16) -> Result<Option<BakingRight>, failure::Error> {
+        let (context_proto_params, predecessor_timestamp) = self.predecessor_context_params(header)?;
+        let context_hash = context_proto_params.context_hash.clone();
+        let chain_id = HashType::ChainId.bytes_to_string(chain_id);
+
+        // split impl by protocol
+        let hash: &str = &HashType::ProtocolHash.bytes_to_string(&context_proto_params.protocol_hash);
+        let (baker, time_between_blocks) = match hash {
+            proto_005_2_constants::PROTOCOL_HASH => {
+                let constants = proto_005_2_constants::constants::ParametricConstants::from_bytes(context_proto_params.constants_data.clone())?;
+                let baker = proto_005_2::get_baker(context_proto_params, &chain_id, predecessor_timestamp, header.header.level(), priority, self.list.clone(), &self.persistent_storage)?;
+                (baker, constants.time_between_blocks().clone())
+            }
+            proto_006_constants::PROTOCOL_HASH => {
+                let constants = proto_006_constants::constants::ParametricConstants::from_bytes(context_proto_params.constants_data.clone())?;
+                let baker = proto_006::get_baker(context_proto_params, &chain_id, predecessor_timestamp, header.header.level(), priority, self.list.clone(), &self.persistent_storage)?;
+                (baker, constants.time_between_blocks().clone())
+            }
+            _ => return Ok(None),
+        };
+
+        match get_manager_key(&context_hash, &baker, &self.list)? {
+            Some(baker_key) if public_key_hash(&baker_key) == baker => Ok(Some(BakingRight { baker_key, time_between_blocks })),
+            Some(baker_key) => Err(format_err!("Manager key {} does not belong to the baker {}", baker_key.to_b58(), baker)),
+            None => Err(format_err!("Public key of the baker {} is not revealed", baker)),
+        }
+    }
+}
+
+impl BakingRightsResolver for ContextBakingRightsResolver {
+    fn resolve(&self, chain_id: &ChainId, header: &BlockHeaderWithHash, priority: u16) -> Result<Option<BakingRight>, HeaderValidationError> {
+        self.baking_right(chain_id, header, priority)
+            .map_err(|e| HeaderValidationError::BakingRightsNotResolved { reason: format!("{}", e) })
+    }
+}
+
+/// Public key hash (tz...) of the public key
+fn public_key_hash(public_key: &PublicKey) -> String {
+    public_key.public_key_hash_type().bytes_to_string(&public_key.public_key_hash())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_public_key_hash() -> Result<(), failure::Error> {
+        let public_key = PublicKey::from_b58("edpkuBknW28nW72KG6RoHtYW7p12T6GKc7nAbwYX5m8Wd9sDVC9yav")?;
+        assert_eq!("tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx", public_key_hash(&public_key));
+        Ok(())
+    }
+}
@@ -0,0 +1,295 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Validation of the protocol data of the block header used by the Emmy family of protocols (001 - 006).
+//!
+//! Protocol data consists of the baking priority, proof of work nonce, optional seed nonce hash
+//! and the signature of the baker. Signature is checked over the header without the signature,
+//! prefixed by the block watermark and the chain id. Block cannot be baked sooner than the minimal
+//! delay after its predecessor, which depends on the baking priority.
+
+use std::sync::Arc;
+
+use crypto::hash::ChainId;
+use crypto::signature::{PublicKey, Watermark};
+use storage::BlockHeaderWithHash;
+use tezos_messages::p2p::binary_message::BinaryMessage;
+
+use super::{HeaderValidationError, ProtocolHeaderValidator};
+
+const PRIORITY_SIZE: usize = 2;
+const PROOF_OF_WORK_NONCE_SIZE: usize = 8;
+const SEED_NONCE_HASH_SIZE: usize = 32;
+const SIGNATURE_SIZE: usize = 64;
+/// Delay between blocks used by the protocol, when the `time_between_blocks` constant is empty
+const DEFAULT_TIME_BETWEEN_BLOCKS: i64 = 60;
+
+/// Right to bake the block with the given priority
+#[derive(Clone, Debug)]
+pub struct BakingRight {
+    /// Public key of the baker, who had the right to bake the block
+    pub baker_key: PublicKey,
+    /// Protocol constant `time_between_blocks`, the minimal delays between blocks by the priority
+    pub time_between_blocks: Vec<i64>,
+}
+
+/// Resolves the baking right of the block from the context of its applied predecessor.
+pub trait BakingRightsResolver: Send + Sync {
+    /// Returns `None`, when the protocol of the block is not supported by the resolver.
+    fn resolve(&self, chain_id: &ChainId, header: &BlockHeaderWithHash, priority: u16) -> Result<Option<BakingRight>, HeaderValidationError>;
+}
+
+/// Protocol data of the block header
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmmyProtocolData {
+    pub priority: u16,
+    pub proof_of_work_nonce: Vec<u8>,
+    pub seed_nonce_hash: Option<Vec<u8>>,
+    pub signature: Vec<u8>,
+}
+
+impl EmmyProtocolData {
+    pub fn parse(protocol_data: &[u8]) -> Result<Self, HeaderValidationError> {
+        let invalid = |reason: &str| HeaderValidationError::InvalidProtocolData { reason: reason.to_string() };
+
+        let seed_nonce_hash_tag = PRIORITY_SIZE + PROOF_OF_WORK_NONCE_SIZE;
+        if protocol_data.len() <= seed_nonce_hash_tag {
+            return Err(invalid("protocol data is too short"));
+        }
+        let priority = u16::from_be_bytes([protocol_data[0], protocol_data[1]]);
+        let proof_of_work_nonce = protocol_data[PRIORITY_SIZE..seed_nonce_hash_tag].to_vec();
+
+        let (seed_nonce_hash, signature_start) = match protocol_data[seed_nonce_hash_tag] {
+            0x00 => (None, seed_nonce_hash_tag + 1),
+            0xff => {
+                let end = seed_nonce_hash_tag + 1 + SEED_NONCE_HASH_SIZE;
+                if protocol_data.len() < end {
+                    return Err(invalid("seed nonce hash is too short"));
+                }
+                (Some(protocol_data[seed_nonce_hash_tag + 1..end].to_vec()), end)
+            }
+            _ => return Err(invalid("invalid seed nonce hash tag")),
+        };
+
+        if protocol_data.len() - signature_start != SIGNATURE_SIZE {
+            return Err(invalid("invalid signature size"));
+        }
+
+        Ok(EmmyProtocolData {
+            priority,
+            proof_of_work_nonce,
+            seed_nonce_hash,
+            signature: protocol_data[signature_start..].to_vec(),
+        })
+    }
+}
+
+/// Validator of the block headers of the Emmy family of protocols.
+///
+/// Minimal delay and signature are verified only if the baking rights resolver is provided and it supports the protocol.
+#[derive(Clone)]
+pub struct EmmyHeaderValidator {
+    baking_rights: Option<Arc<dyn BakingRightsResolver>>,
+}
+
+impl EmmyHeaderValidator {
+    pub fn new(baking_rights: Option<Arc<dyn BakingRightsResolver>>) -> Self {
+        EmmyHeaderValidator { baking_rights }
+    }
+}
+
+impl ProtocolHeaderValidator for EmmyHeaderValidator {
+    fn validate(&self, chain_id: &ChainId, header: &BlockHeaderWithHash, predecessor: &BlockHeaderWithHash) -> Result<(), HeaderValidationError> {
+        let protocol_data = EmmyProtocolData::parse(header.header.protocol_data())?;
+
+        let baking_right = match &self.baking_rights {
+            Some(baking_rights) => baking_rights.resolve(chain_id, header, protocol_data.priority)?,
+            None => None,
+        };
+        if let Some(baking_right) = baking_right {
+            let minimal_timestamp = predecessor.header.timestamp() + minimal_block_delay(&baking_right.time_between_blocks, protocol_data.priority);
+            if header.header.timestamp() < minimal_timestamp {
+                return Err(HeaderValidationError::TimestampTooEarly { timestamp: header.header.timestamp(), minimal_timestamp });
+            }
+
+            let signed_bytes = signed_bytes(header)?;
+            let valid = baking_right.baker_key.verify(Some(&Watermark::Block(chain_id.clone())), &signed_bytes, &protocol_data.signature)
+                .map_err(|_| HeaderValidationError::InvalidSignature)?;
+            if !valid {
+                return Err(HeaderValidationError::InvalidSignature);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Minimal delay in seconds between the predecessor and the block baked with the priority (`Baking.minimal_time` of the protocol).
+///
+/// Delays are summed for the priorities from the first one, the last delay is repeated for all the higher priorities.
+pub fn minimal_block_delay(time_between_blocks: &[i64], priority: u16) -> i64 {
+    let default_time_between_blocks = [DEFAULT_TIME_BETWEEN_BLOCKS];
+    let time_between_blocks = if time_between_blocks.is_empty() { &default_time_between_blocks[..] } else { time_between_blocks };
+
+    let steps = i64::from(priority) + 1;
+    let last = time_between_blocks.len() - 1;
+    if steps <= last as i64 {
+        time_between_blocks[..steps as usize].iter().sum()
+    } else {
+        time_between_blocks[..last].iter().sum::<i64>() + (steps - last as i64) * time_between_blocks[last]
+    }
+}
+
+/// Header without the signature, which is signed by the baker
+fn signed_bytes(header: &BlockHeaderWithHash) -> Result<Vec<u8>, HeaderValidationError> {
+    let mut header_bytes = header.header.as_bytes()
+        .map_err(|e| HeaderValidationError::InvalidProtocolData { reason: format!("Failed to serialize header: {}", e) })?;
+    header_bytes.truncate(header_bytes.len() - SIGNATURE_SIZE);
+    Ok(header_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use sodiumoxide::crypto::sign::ed25519;
+
+    use crypto::blake2b;
+    use crypto::hash::HashType;
+    use tezos_messages::p2p::encoding::prelude::*;
+
+    use super::*;
+
+    fn header_with_protocol_data(protocol_data: Vec<u8>) -> BlockHeaderWithHash {
+        let header = BlockHeaderBuilder::default()
+            .level(2)
+            .proto(1)
+            .predecessor(vec![1; 32])
+            .timestamp(1_590_000_000)
+            .validation_pass(4)
+            .operations_hash(vec![2; 32])
+            .fitness(vec![vec![0, 1], vec![0, 0, 0, 0, 0, 0, 0, 1]])
+            .context(vec![3; 32])
+            .protocol_data(protocol_data)
+            .build()
+            .unwrap();
+        BlockHeaderWithHash::new(header).unwrap()
+    }
+
+    fn protocol_data(seed_nonce_hash: Option<Vec<u8>>, signature: Vec<u8>) -> Vec<u8> {
+        let mut protocol_data = vec![0, 3];
+        protocol_data.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        match seed_nonce_hash {
+            Some(seed_nonce_hash) => {
+                protocol_data.push(0xff);
+                protocol_data.extend(seed_nonce_hash);
+            }
+            None => protocol_data.push(0x00),
+        }
+        protocol_data.extend(signature);
+        protocol_data
+    }
+
+    fn predecessor(timestamp: i64) -> BlockHeaderWithHash {
+        let header = BlockHeaderBuilder::default()
+            .level(1)
+            .proto(1)
+            .predecessor(vec![4; 32])
+            .timestamp(timestamp)
+            .validation_pass(4)
+            .operations_hash(vec![2; 32])
+            .fitness(vec![vec![0, 1], vec![0, 0, 0, 0, 0, 0, 0, 0]])
+            .context(vec![3; 32])
+            .protocol_data(vec![])
+            .build()
+            .unwrap();
+        BlockHeaderWithHash::new(header).unwrap()
+    }
+
+    struct FixedBaker(PublicKey);
+
+    impl BakingRightsResolver for FixedBaker {
+        fn resolve(&self, _: &ChainId, _: &BlockHeaderWithHash, _: u16) -> Result<Option<BakingRight>, HeaderValidationError> {
+            Ok(Some(BakingRight { baker_key: self.0.clone(), time_between_blocks: vec![60, 40] }))
+        }
+    }
+
+    #[test]
+    fn test_parse_protocol_data() -> Result<(), failure::Error> {
+        let parsed = EmmyProtocolData::parse(&protocol_data(None, vec![9; 64]))?;
+        assert_eq!(3, parsed.priority);
+        assert_eq!(vec![1, 2, 3, 4, 5, 6, 7, 8], parsed.proof_of_work_nonce);
+        assert_eq!(None, parsed.seed_nonce_hash);
+        assert_eq!(vec![9; 64], parsed.signature);
+
+        let parsed = EmmyProtocolData::parse(&protocol_data(Some(vec![5; 32]), vec![9; 64]))?;
+        assert_eq!(Some(vec![5; 32]), parsed.seed_nonce_hash);
+        assert_eq!(vec![9; 64], parsed.signature);
+
+        assert!(EmmyProtocolData::parse(&[]).is_err());
+        assert!(EmmyProtocolData::parse(&protocol_data(None, vec![9; 63])).is_err());
+        assert!(EmmyProtocolData::parse(&protocol_data(Some(vec![5; 32]), vec![9; 65])).is_err());
+        let mut invalid_tag = protocol_data(None, vec![9; 64]);
+        invalid_tag[10] = 0x01;
+        assert!(EmmyProtocolData::parse(&invalid_tag).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_signature() -> Result<(), failure::Error> {
+        let chain_id = HashType::ChainId.string_to_bytes("NetXgtSLGNJvNye")?;
+        let (public_key, secret_key) = ed25519::gen_keypair();
+
+        // sign header with placeholder signature, which is cut off before signing
+        let unsigned = header_with_protocol_data(protocol_data(None, vec![0; 64]));
+        let mut watermarked = Watermark::Block(chain_id.clone()).to_bytes();
+        watermarked.extend(signed_bytes(&unsigned)?);
+        let signature = ed25519::sign_detached(&blake2b::digest_256(&watermarked), &secret_key);
+        let signed = header_with_protocol_data(protocol_data(None, signature.to_bytes().to_vec()));
+
+        // block with priority 3 can be baked 180 seconds after the predecessor
+        let predecessor = predecessor(signed.header.timestamp() - 180);
+        let validator = EmmyHeaderValidator::new(Some(Arc::new(FixedBaker(PublicKey::Ed25519(public_key.as_ref().to_vec())))));
+        validator.validate(&chain_id, &signed, &predecessor)?;
+
+        // header signed by another baker
+        let (other_public_key, _) = ed25519::gen_keypair();
+        let validator = EmmyHeaderValidator::new(Some(Arc::new(FixedBaker(PublicKey::Ed25519(other_public_key.as_ref().to_vec())))));
+        match validator.validate(&chain_id, &signed, &predecessor) {
+            Err(HeaderValidationError::InvalidSignature) => (),
+            result => panic!("expected invalid signature, but was: {:?}", result),
+        }
+
+        // signature is not checked, when baker is not known
+        EmmyHeaderValidator::new(None).validate(&chain_id, &signed, &predecessor)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_minimal_delay() -> Result<(), failure::Error> {
+        let chain_id = HashType::ChainId.string_to_bytes("NetXgtSLGNJvNye")?;
+        let (public_key, _) = ed25519::gen_keypair();
+        let validator = EmmyHeaderValidator::new(Some(Arc::new(FixedBaker(PublicKey::Ed25519(public_key.as_ref().to_vec())))));
+
+        // block with priority 3 was baked one second too early, so the signature is not checked at all
+        let header = header_with_protocol_data(protocol_data(None, vec![0; 64]));
+        match validator.validate(&chain_id, &header, &predecessor(header.header.timestamp() - 179)) {
+            Err(HeaderValidationError::TimestampTooEarly { timestamp, minimal_timestamp }) => assert_eq!(timestamp + 1, minimal_timestamp),
+            result => panic!("expected timestamp too early, but was: {:?}", result),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_minimal_block_delay() {
+        assert_eq!(60, minimal_block_delay(&[60, 40], 0));
+        assert_eq!(100, minimal_block_delay(&[60, 40], 1));
+        assert_eq!(180, minimal_block_delay(&[60, 40], 3));
+        assert_eq!(30, minimal_block_delay(&[30], 0));
+        assert_eq!(90, minimal_block_delay(&[30], 2));
+        assert_eq!(60 + 40 + 20, minimal_block_delay(&[60, 40, 20], 2));
+        assert_eq!(60 + 40 + 20 + 20, minimal_block_delay(&[60, 40, 20], 3));
+        assert_eq!(120, minimal_block_delay(&[], 1));
+    }
+}
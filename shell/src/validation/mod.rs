@@ -0,0 +1,255 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Pre-validation of the block headers received from peers, before they are stored.
+//! - timestamp of the header cannot be too far in the future
+//! - level, timestamp and fitness have to increase compared to the predecessor, if the predecessor is already known
+//! - protocol specific part of the header is checked by the validator registered for the protocol,
+//!   which was activated by the predecessor, if the predecessor is already applied
+//!
+//! Headers received before their predecessor was applied are validated again, before they are applied
+//! (see [`HeaderValidator::validate_with_predecessor`]).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use failure::Fail;
+
+use crypto::hash::{BlockHash, ChainId, HashType, ProtocolHash};
+use storage::{BlockHeaderWithHash, BlockStorage, BlockStorageReader, StorageError};
+use storage::persistent::PersistentStorage;
+use tezos_messages::base::fitness_comparator::fitness_increases;
+use tezos_messages::p2p::encoding::prelude::*;
+use tezos_messages::protocol::{proto_001, proto_002, proto_003, proto_004, proto_005, proto_005_2, proto_006};
+
+pub use self::baking_rights::ContextBakingRightsResolver;
+pub use self::emmy::{BakingRight, BakingRightsResolver, EmmyHeaderValidator, EmmyProtocolData, minimal_block_delay};
+
+mod baking_rights;
+mod emmy;
+
+/// Timestamp of the block header can be at most this many seconds ahead of the local time
+pub const MAX_CLOCK_DRIFT: i64 = 15;
+
+/// Possible errors for the block header validation
+#[derive(Debug, Fail)]
+pub enum HeaderValidationError {
+    #[fail(display = "Block timestamp {} is in the future, local time: {}", timestamp, now)]
+    TimestampInFuture {
+        timestamp: i64,
+        now: i64,
+    },
+    #[fail(display = "Block timestamp {} does not follow the predecessor's timestamp {}", timestamp, predecessor_timestamp)]
+    TimestampNotIncreased {
+        timestamp: i64,
+        predecessor_timestamp: i64,
+    },
+    #[fail(display = "Block timestamp {} is sooner than the minimal timestamp {} for its priority", timestamp, minimal_timestamp)]
+    TimestampTooEarly {
+        timestamp: i64,
+        minimal_timestamp: i64,
+    },
+    #[fail(display = "Block level {} does not follow the predecessor's level {}", level, predecessor_level)]
+    InvalidLevel {
+        level: i32,
+        predecessor_level: i32,
+    },
+    #[fail(display = "Block fitness does not increase compared to the predecessor")]
+    FitnessNotIncreased,
+    #[fail(display = "Invalid protocol data: {}", reason)]
+    InvalidProtocolData {
+        reason: String
+    },
+    #[fail(display = "Block is not signed by the baker")]
+    InvalidSignature,
+    #[fail(display = "Baking rights cannot be resolved: {}", reason)]
+    BakingRightsNotResolved {
+        reason: String
+    },
+    #[fail(display = "Storage error: {}", error)]
+    StorageError {
+        error: StorageError
+    },
+}
+
+impl From<StorageError> for HeaderValidationError {
+    fn from(error: StorageError) -> Self {
+        HeaderValidationError::StorageError { error }
+    }
+}
+
+impl slog::Value for HeaderValidationError {
+    fn serialize(&self, _record: &slog::Record, key: slog::Key, serializer: &mut dyn slog::Serializer) -> slog::Result {
+        serializer.emit_arguments(key, &format_args!("{}", self))
+    }
+}
+
+/// Validates the protocol specific part of the block header.
+pub trait ProtocolHeaderValidator: Send + Sync {
+    /// Validate header on top of its applied predecessor
+    fn validate(&self, chain_id: &ChainId, header: &BlockHeaderWithHash, predecessor: &BlockHeaderWithHash) -> Result<(), HeaderValidationError>;
+}
+
+/// Pre-validation stage of the block headers received from peers.
+pub struct HeaderValidator {
+    /// Chain, which the headers belong to
+    chain_id: ChainId,
+    /// Block storage
+    block_storage: Box<dyn BlockStorageReader>,
+    /// Validators of the protocol specific part of the header
+    validators: HashMap<ProtocolHash, Box<dyn ProtocolHeaderValidator>>,
+}
+
+impl HeaderValidator {
+    /// Create header validator without any protocol specific validators.
+    pub fn new(persistent_storage: &PersistentStorage, chain_id: &ChainId) -> Self {
+        HeaderValidator {
+            chain_id: chain_id.clone(),
+            block_storage: Box::new(BlockStorage::new(persistent_storage)),
+            validators: HashMap::new(),
+        }
+    }
+
+    /// Create header validator with validators of all supported protocols registered.
+    ///
+    /// Minimal delays and signatures are verified only, when `baking_rights` are provided.
+    pub fn with_supported_protocols(persistent_storage: &PersistentStorage, chain_id: &ChainId, baking_rights: Option<Arc<dyn BakingRightsResolver>>) -> Self {
+        let mut validator = Self::new(persistent_storage, chain_id);
+        let emmy = EmmyHeaderValidator::new(baking_rights);
+        for protocol in &[proto_001::PROTOCOL_HASH, proto_002::PROTOCOL_HASH, proto_003::PROTOCOL_HASH, proto_004::PROTOCOL_HASH, proto_005::PROTOCOL_HASH, proto_005_2::PROTOCOL_HASH, proto_006::PROTOCOL_HASH] {
+            let protocol_hash = HashType::ProtocolHash.string_to_bytes(protocol).expect("Invalid protocol hash");
+            validator.register(protocol_hash, Box::new(emmy.clone()));
+        }
+        validator
+    }
+
+    /// Register validator of the protocol specific part of the header
+    pub fn register(&mut self, protocol_hash: ProtocolHash, validator: Box<dyn ProtocolHeaderValidator>) {
+        self.validators.insert(protocol_hash, validator);
+    }
+
+    /// Validate block header received from a peer.
+    ///
+    /// Header is validated against its predecessor only if the predecessor is already known,
+    /// otherwise it is validated before it is applied.
+    pub fn validate(&self, header: &BlockHeaderWithHash) -> Result<(), HeaderValidationError> {
+        validate_timestamp(&header.header, now())?;
+
+        match self.block_storage.get(header.header.predecessor())? {
+            Some(predecessor) => self.validate_with_predecessor(header, &predecessor),
+            None => Ok(()),
+        }
+    }
+
+    /// Validate block header against its predecessor.
+    ///
+    /// Protocol specific part of the header is validated only if the predecessor is applied,
+    /// so every block has to be validated by this function again, before it is applied.
+    pub fn validate_with_predecessor(&self, header: &BlockHeaderWithHash, predecessor: &BlockHeaderWithHash) -> Result<(), HeaderValidationError> {
+        validate_against_predecessor(&header.header, &predecessor.header)?;
+
+        if let Some(protocol_hash) = self.protocol_of_successor(&predecessor.hash)? {
+            if let Some(validator) = self.validators.get(&protocol_hash) {
+                validator.validate(&self.chain_id, header, predecessor)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Protocol activated by the applied block, which is the protocol of its successors
+    fn protocol_of_successor(&self, block_hash: &BlockHash) -> Result<Option<ProtocolHash>, HeaderValidationError> {
+        let json_data = match self.block_storage.get_with_json_data(block_hash)? {
+            Some((_, json_data)) => json_data,
+            None => return Ok(None),
+        };
+        let metadata: serde_json::Value = serde_json::from_str(json_data.block_header_proto_metadata_json())
+            .map_err(|e| HeaderValidationError::InvalidProtocolData { reason: format!("Invalid predecessor metadata: {}", e) })?;
+
+        Ok(metadata.get("next_protocol")
+            .and_then(|protocol| protocol.as_str())
+            .and_then(|protocol| HashType::ProtocolHash.string_to_bytes(protocol).ok()))
+    }
+}
+
+/// Timestamp of the header cannot be too far in the future
+fn validate_timestamp(header: &BlockHeader, now: i64) -> Result<(), HeaderValidationError> {
+    if header.timestamp() > now + MAX_CLOCK_DRIFT {
+        Err(HeaderValidationError::TimestampInFuture { timestamp: header.timestamp(), now })
+    } else {
+        Ok(())
+    }
+}
+
+/// Header has to follow its predecessor
+fn validate_against_predecessor(header: &BlockHeader, predecessor: &BlockHeader) -> Result<(), HeaderValidationError> {
+    if header.level() != predecessor.level() + 1 {
+        return Err(HeaderValidationError::InvalidLevel { level: header.level(), predecessor_level: predecessor.level() });
+    }
+    if header.timestamp() <= predecessor.timestamp() {
+        return Err(HeaderValidationError::TimestampNotIncreased { timestamp: header.timestamp(), predecessor_timestamp: predecessor.timestamp() });
+    }
+    if !fitness_increases(predecessor.fitness(), header.fitness()) {
+        return Err(HeaderValidationError::FitnessNotIncreased);
+    }
+    Ok(())
+}
+
+/// Local time as a unix timestamp
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(level: i32, timestamp: i64, fitness: Vec<Vec<u8>>) -> BlockHeader {
+        BlockHeaderBuilder::default()
+            .level(level)
+            .proto(1)
+            .predecessor(vec![0; 32])
+            .timestamp(timestamp)
+            .validation_pass(4)
+            .operations_hash(vec![0; 32])
+            .fitness(fitness)
+            .context(vec![0; 32])
+            .protocol_data(vec![])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_validate_timestamp() {
+        let now = 1_590_000_000;
+        assert!(validate_timestamp(&header(2, now, vec![]), now).is_ok());
+        assert!(validate_timestamp(&header(2, now + MAX_CLOCK_DRIFT, vec![]), now).is_ok());
+        match validate_timestamp(&header(2, now + MAX_CLOCK_DRIFT + 1, vec![]), now) {
+            Err(HeaderValidationError::TimestampInFuture { .. }) => (),
+            result => panic!("expected timestamp in future, but was: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_validate_against_predecessor() {
+        let predecessor = header(10, 1_590_000_000, vec![vec![0, 1], vec![0, 0, 0, 0, 0, 0, 0, 5]]);
+
+        assert!(validate_against_predecessor(&header(11, 1_590_000_060, vec![vec![0, 1], vec![0, 0, 0, 0, 0, 0, 0, 6]]), &predecessor).is_ok());
+        match validate_against_predecessor(&header(12, 1_590_000_060, vec![vec![0, 1], vec![0, 0, 0, 0, 0, 0, 0, 6]]), &predecessor) {
+            Err(HeaderValidationError::InvalidLevel { level: 12, predecessor_level: 10 }) => (),
+            result => panic!("expected invalid level, but was: {:?}", result),
+        }
+        match validate_against_predecessor(&header(11, 1_590_000_000, vec![vec![0, 1], vec![0, 0, 0, 0, 0, 0, 0, 6]]), &predecessor) {
+            Err(HeaderValidationError::TimestampNotIncreased { .. }) => (),
+            result => panic!("expected timestamp not increased, but was: {:?}", result),
+        }
+        match validate_against_predecessor(&header(11, 1_590_000_060, vec![vec![0, 1], vec![0, 0, 0, 0, 0, 0, 0, 5]]), &predecessor) {
+            Err(HeaderValidationError::FitnessNotIncreased) => (),
+            result => panic!("expected fitness not increased, but was: {:?}", result),
+        }
+    }
+}
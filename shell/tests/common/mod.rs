@@ -0,0 +1,110 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Local mock peer, which collects all the messages sent to it, and helpers to talk to the shell actors through it.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver, Sender as MpscSender};
+use std::time::{Duration, Instant};
+
+use failure::Error;
+use riker::actors::*;
+use slog::{Discard, Logger};
+
+use crypto::hash::{ChainId, HashType};
+use networking::p2p::network_channel::{NetworkChannelMsg, NetworkChannelRef, NetworkChannelTopic, PeerMessageReceived};
+use networking::p2p::peer::{PeerMsg, PeerRef};
+use tezos_messages::p2p::binary_message::BinaryMessage;
+use tezos_messages::p2p::encoding::prelude::*;
+
+pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+pub const PUBLISH_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Mock of the remote peer, which forwards all messages sent to the peer to the test.
+pub struct MockPeer {
+    responses: Arc<Mutex<MpscSender<PeerMessage>>>,
+}
+
+impl MockPeer {
+    pub fn actor(sys: &impl ActorRefFactory) -> Result<(PeerRef, Receiver<PeerMessage>), CreateError> {
+        let (tx, rx) = channel();
+        let peer = sys.actor_of(Props::new_args(MockPeer::new, Arc::new(Mutex::new(tx))), "mock-peer")?;
+        Ok((peer, rx))
+    }
+
+    fn new(responses: Arc<Mutex<MpscSender<PeerMessage>>>) -> Self {
+        MockPeer { responses }
+    }
+}
+
+impl Actor for MockPeer {
+    type Msg = PeerMsg;
+
+    fn recv(&mut self, _: &Context<Self::Msg>, msg: Self::Msg, _: Option<BasicActorRef>) {
+        if let PeerMsg::SendMessage(msg) = msg {
+            let responses = self.responses.lock().unwrap();
+            for message in msg.message().messages() {
+                let _ = responses.send(message.clone());
+            }
+        }
+    }
+}
+
+/// Wait for the first message sent to the mock peer, which matches the predicate
+pub fn wait_for_response<P: Fn(&PeerMessage) -> bool>(responses: &Receiver<PeerMessage>, predicate: P) -> Option<PeerMessage> {
+    wait_for_response_within(responses, RESPONSE_TIMEOUT, predicate)
+}
+
+pub fn wait_for_response_within<P: Fn(&PeerMessage) -> bool>(responses: &Receiver<PeerMessage>, timeout: Duration, predicate: P) -> Option<PeerMessage> {
+    let started = Instant::now();
+    while let Some(remaining) = timeout.checked_sub(started.elapsed()) {
+        match responses.recv_timeout(remaining) {
+            Ok(message) if predicate(&message) => return Some(message),
+            Ok(_) => continue,
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+pub fn received(peer: &PeerRef, peer_address: SocketAddr, message: PeerMessage) -> PeerMessageReceived {
+    PeerMessageReceived {
+        peer: peer.clone(),
+        message: Arc::new(message.into()),
+        peer_address,
+    }
+}
+
+pub fn publish(network_channel: &NetworkChannelRef, msg: NetworkChannelMsg) {
+    network_channel.tell(
+        Publish {
+            msg,
+            topic: NetworkChannelTopic::NetworkEvents.into(),
+        }, None);
+}
+
+/// Actors subscribe to the network channel asynchronously after start, so the message is published
+/// repeatedly until the first response matching the predicate is received
+pub fn publish_until_response<P: Fn(&PeerMessage) -> bool>(network_channel: &NetworkChannelRef, msg: NetworkChannelMsg, responses: &Receiver<PeerMessage>, predicate: P) -> Option<PeerMessage> {
+    let started = Instant::now();
+    while started.elapsed() < RESPONSE_TIMEOUT {
+        publish(network_channel, msg.clone());
+        if let Some(response) = wait_for_response_within(responses, PUBLISH_RETRY_INTERVAL, &predicate) {
+            return Some(response);
+        }
+    }
+    None
+}
+
+pub fn chain_id() -> Result<ChainId, Error> {
+    Ok(HashType::ChainId.string_to_bytes("NetXgtSLGNJvNye")?)
+}
+
+pub fn operation() -> Result<Operation, Error> {
+    Ok(Operation::from_bytes(hex::decode("10490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e000008c387fa065a181d45d47a9b78ddc77e92a881779ff2cbabbf9646eade4bf1405a08e00b725ed849eea46953b10b5cdebc518e6fd47e69b82d2ca18c4cf6d2f312dd08")?)?)
+}
+
+pub fn logger() -> Logger {
+    Logger::root(Discard, slog::o!())
+}
@@ -16,8 +16,9 @@ use slog::{Drain, Level, Logger, warn};
 
 use crypto::hash::{BlockHash, ChainId, ContextHash, HashType};
 use shell::context_listener::ContextListener;
-use storage::{BlockHeaderWithHash, BlockMetaStorage, BlockStorage, initialize_storage_with_genesis_block, OperationsMetaStorage, resolve_storage_init_chain_data, store_commit_genesis_result};
-use storage::context::{ContextApi, ContextIndex, TezedgeContext};
+use storage::{BlockHeaderWithHash, BlockMetaStorage, BlockStorage, BlockStorageReader, initialize_storage_with_genesis_block, OperationsMetaStorage, resolve_storage_init_chain_data, store_commit_genesis_result};
+use storage::context::{ContextApiRef, ContextBackend, ContextIndex};
+use storage::merkle_hash::CommitInfo;
 use storage::persistent::ContextList;
 use storage::skip_list::Bucket;
 use storage::tests_common::TmpStorage;
@@ -71,13 +72,15 @@ fn test_apply_block_and_check_context() -> Result<(), failure::Error> {
 
     // run context_listener actor
     let actor_system = SystemBuilder::new().name("test_apply_block_and_check_context").log(log.clone()).create().expect("Failed to create actor system");
-    let _ = ContextListener::actor(&actor_system, &persistent_storage, event_server, log.clone()).expect("Failed to create context event listener");
+    let context = ContextBackend::SkipList.create(&persistent_storage);
+    let _ = ContextListener::actor(&actor_system, &persistent_storage, context.clone(), event_server, log.clone()).expect("Failed to create context event listener");
 
     // run apply blocks
     let _ = apply_blocks_like_chain_feeder(
         &mut block_storage,
         &mut block_meta_storage,
         &mut operations_meta_storage,
+        &context,
         tezos_env,
         storage_db_path,
         context_db_path,
@@ -92,28 +95,32 @@ fn test_apply_block_and_check_context() -> Result<(), failure::Error> {
     assert!(event_thread.join().is_ok());
     let _ = actor_system.shutdown();
 
+    // context hashes calculated by the OCaml protocol match the committed context
+    assert!(context.read().expect("lock poisoning").verification_failure().is_none());
+
     // check context 0/1/2
-    let context = TezedgeContext::new(
-        BlockStorage::new(&persistent_storage),
-        persistent_storage.context_storage(),
-    );
+    let context = context.read().expect("lock poisoning");
+    let context_index = |level: i32| -> Result<ContextIndex, failure::Error> {
+        let block = block_storage.get_by_block_level(level)?.expect("block not found by level");
+        Ok(ContextIndex::new(Some(block.header.context().clone())))
+    };
 
     // check level 0
-    if let Some(Bucket::Exists(data)) = context.get_key(&ContextIndex::new(Some(0), None), &vec!["protocol".to_string()])? {
+    if let Some(Bucket::Exists(data)) = context.get_key(&context_index(0)?, &vec!["protocol".to_string()])? {
         assert_eq!("PtYuensgYBb3G3x1hLLbCmcav8ue8Kyd2khADcL5LsT5R1hcXex", HashType::ProtocolHash.bytes_to_string(&data));
     } else {
         panic!(format!("Protocol not found in context for level: {}", 0));
     }
 
     // check level 1
-    if let Some(Bucket::Exists(data)) = context.get_key(&ContextIndex::new(Some(1), None), &vec!["protocol".to_string()])? {
+    if let Some(Bucket::Exists(data)) = context.get_key(&context_index(1)?, &vec!["protocol".to_string()])? {
         assert_eq!("PsBabyM1eUXZseaJdmXFApDSBqj8YBfwELoxZHHW77EMcAbbwAS", HashType::ProtocolHash.bytes_to_string(&data));
     } else {
         panic!(format!("Protocol not found in context for level: {}", 1));
     }
 
     // check level 2
-    if let Some(Bucket::Exists(data)) = context.get_key(&ContextIndex::new(Some(2), None), &vec!["protocol".to_string()])? {
+    if let Some(Bucket::Exists(data)) = context.get_key(&context_index(2)?, &vec!["protocol".to_string()])? {
         assert_eq!("PsBabyM1eUXZseaJdmXFApDSBqj8YBfwELoxZHHW77EMcAbbwAS", HashType::ProtocolHash.bytes_to_string(&data));
     } else {
         panic!(format!("Protocol not found in context for level: {}", 2));
@@ -181,6 +188,7 @@ fn apply_blocks_like_chain_feeder(
     block_storage: &mut BlockStorage,
     block_meta_storage: &mut BlockMetaStorage,
     operations_meta_storage: &mut OperationsMetaStorage,
+    context: &ContextApiRef,
     tezos_env: &TezosEnvironmentConfiguration,
     storage_db_path: &str,
     context_db_path: &str,
@@ -255,7 +263,12 @@ fn apply_blocks_like_chain_feeder(
             }
         };
         assert!(result.is_ok());
-        last_result = Some(result.unwrap());
+        let result = result.unwrap();
+
+        // verify context hash like chain feeder
+        let commit_info = CommitInfo::new(block.header.timestamp(), result.validation_result_message.clone());
+        context.write().expect("lock poisoning").verify_commit(&result.context_hash, commit_info)?;
+        last_result = Some(result);
     }
 
     Ok(assert!(last_result.is_some()))
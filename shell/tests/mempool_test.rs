@@ -0,0 +1,125 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Checks, that operations injected to the mempool are validated and advertised to the peers.
+//! Protocol is replaced by a fake validator, which applies every operation.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Instant;
+
+use failure::Error;
+use riker::actors::*;
+
+use crypto::hash::{ChainId, HashType};
+use networking::p2p::network_channel::{NetworkChannel, PeerBootstrapped};
+use shell::mempool::{inject_operation, MempoolManager, MempoolState};
+use shell::mempool_prevalidator::{MempoolPrevalidator, OperationValidator, PrevalidationError};
+use shell::shell_channel::{MempoolOperationReceived, ShellChannel, ShellChannelTopic};
+use storage::{BlockHeaderWithHash, BlockMetaStorage, BlockStorage};
+use storage::tests_common::TmpStorage;
+use tezos_api::ffi::{OperationClassification, ValidateOperationResult};
+use tezos_messages::p2p::binary_message::BinaryMessage;
+use tezos_messages::p2p::encoding::prelude::*;
+
+use crate::common::{chain_id, logger, MockPeer, operation, publish, publish_until_response, PUBLISH_RETRY_INTERVAL, received, RESPONSE_TIMEOUT, wait_for_response};
+
+mod common;
+
+#[test]
+fn test_injected_operation_is_validated_and_advertised() -> Result<(), Error> {
+    let tmp_storage = TmpStorage::create("__shell_mempool_inject")?;
+    let persistent_storage = tmp_storage.storage();
+    let chain_id = chain_id()?;
+
+    // operations are validated and advertised on top of the current head
+    let head = BlockHeaderWithHash::new(
+        BlockHeaderBuilder::default()
+            .level(1)
+            .proto(1)
+            .predecessor(vec![0; HashType::BlockHash.size()])
+            .timestamp(1)
+            .validation_pass(4)
+            .operations_hash(vec![0; HashType::OperationListListHash.size()])
+            .fitness(vec![vec![0]])
+            .context(vec![0; HashType::ContextHash.size()])
+            .protocol_data(vec![])
+            .build().unwrap()
+    )?;
+    BlockStorage::new(persistent_storage).put_block_header(&head)?;
+    BlockMetaStorage::new(persistent_storage).store_current_head(&head.hash)?;
+
+    // run mempool
+    let actor_system = SystemBuilder::new().name("mempool_inject").log(logger()).create().expect("Failed to create actor system");
+    let network_channel = NetworkChannel::actor(&actor_system).expect("Failed to create network channel");
+    let shell_channel = ShellChannel::actor(&actor_system).expect("Failed to create shell channel");
+    let mempool_state = Arc::new(RwLock::new(MempoolState::default()));
+    let _ = MempoolManager::actor(&actor_system, network_channel.clone(), shell_channel.clone(), persistent_storage, &chain_id, mempool_state.clone()).expect("Failed to create mempool manager");
+    let _ = MempoolPrevalidator::actor(&actor_system, shell_channel.clone(), persistent_storage, &chain_id, Box::new(ApplyingValidator), logger()).expect("Failed to create mempool prevalidator");
+
+    // inject operation
+    let operation = operation()?;
+    let operation_hash = inject_operation(operation.clone(), &shell_channel, &mempool_state)?;
+    assert!(mempool_state.read().unwrap().is_known(&operation_hash));
+
+    // injected operation is served to the peers, which also means, that mempool manager is subscribed to the network channel
+    let (peer, responses) = MockPeer::actor(&actor_system).expect("Failed to create mock peer");
+    let peer_address: SocketAddr = "127.0.0.1:9732".parse()?;
+    let request = received(&peer, peer_address, GetOperationsMessage::new(vec![operation_hash.clone()]).into());
+    match publish_until_response(&network_channel, request.into(), &responses, |message| if let PeerMessage::Operation(_) = message { true } else { false }) {
+        Some(PeerMessage::Operation(message)) => assert_eq!(operation.as_bytes()?, message.operation().as_bytes()?),
+        message => panic!("expected operation message, but was: {:?}", message),
+    }
+    publish(&network_channel, PeerBootstrapped::Success { peer: peer.clone(), peer_id: "idsyBpzU3VspRyD3GEDWkgUBRqQNti".to_string(), address: peer_address, listener_port: peer_address.port() }.into());
+
+    // wait for the validation result the same way as the synchronous injection RPC does,
+    // prevalidator subscribes to the shell channel asynchronously, so the operation is sent for validation repeatedly
+    let mut validation = mempool_state.write().unwrap().wait_for_validation(&operation_hash);
+    let started = Instant::now();
+    let result = loop {
+        if let Some(result) = validation.try_recv()? {
+            break result;
+        }
+        assert!(started.elapsed() < RESPONSE_TIMEOUT, "injected operation was not validated in time");
+        shell_channel.tell(
+            Publish {
+                msg: MempoolOperationReceived {
+                    operation_hash: operation_hash.clone(),
+                    operation: operation.clone(),
+                }.into(),
+                topic: ShellChannelTopic::ShellEvents.into(),
+            }, None);
+        thread::sleep(PUBLISH_RETRY_INTERVAL);
+    };
+    match result {
+        Some(result) => assert_eq!(OperationClassification::Applied, result.classification),
+        None => panic!("injected operation was not validated"),
+    }
+    assert!(mempool_state.read().unwrap().applied().contains_key(&operation_hash));
+
+    // applied operation is advertised to the peer together with the current head
+    match wait_for_response(&responses, |message| if let PeerMessage::CurrentHead(message) = message { message.current_mempool().known_valid().contains(&operation_hash) } else { false }) {
+        Some(PeerMessage::CurrentHead(message)) => {
+            assert_eq!(&chain_id, message.chain_id());
+            assert_eq!(head.header.as_ref(), message.current_block_header());
+        }
+        message => panic!("expected current head message, but was: {:?}", message),
+    }
+
+    actor_system.shutdown();
+    Ok(())
+}
+
+/// Validator, which applies every operation
+struct ApplyingValidator;
+
+impl OperationValidator for ApplyingValidator {
+    fn validate_operation(&self, _: &ChainId, _: &BlockHeader, _: &Operation) -> Result<ValidateOperationResult, PrevalidationError> {
+        Ok(ValidateOperationResult {
+            classification: OperationClassification::Applied,
+            protocol_data_json: String::new(),
+            error_json: String::new(),
+        })
+    }
+}
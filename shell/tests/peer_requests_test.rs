@@ -0,0 +1,147 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Checks, that requests of the peers are answered from the storage.
+//! Requests are sent by a local mock peer, which collects all the messages sent to it.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+
+use failure::Error;
+use riker::actors::*;
+
+use crypto::hash::HashType;
+use networking::p2p::network_channel::{NetworkChannel, PeerBootstrapped};
+use shell::chain_manager::ChainManager;
+use shell::mempool::{MempoolManager, MempoolState};
+use shell::shell_channel::ShellChannel;
+use storage::{OperationsStorage, ProtocolStorage};
+use storage::tests_common::TmpStorage;
+use tezos_messages::p2p::binary_message::{BinaryMessage, MessageHash};
+use tezos_messages::p2p::encoding::prelude::*;
+
+use crate::common::{chain_id, logger, MockPeer, operation, publish, publish_until_response, received, wait_for_response};
+
+mod common;
+
+#[test]
+fn test_chain_manager_serves_protocols_and_operation_hashes() -> Result<(), Error> {
+    let tmp_storage = TmpStorage::create("__shell_peer_requests_chain_manager")?;
+    let persistent_storage = tmp_storage.storage();
+    let chain_id = chain_id()?;
+
+    // prepare data to be served
+    let block_hash = HashType::BlockHash.string_to_bytes("BKyQ9EofHrgaZKENioHyP4FZNsTmiSEcVmcghgzCC9cGhE7oCET")?;
+    let operation = operation()?;
+    OperationsStorage::new(persistent_storage).put_operations(&OperationsForBlocksMessage::new(OperationsForBlock::new(block_hash.clone(), 3), Path::Op, vec![operation.clone()]))?;
+    let protocol = Protocol::new(0, vec![Component::new("Main".to_string(), None, "let x = 1".to_string())]);
+    let protocol_hash = ProtocolStorage::new(persistent_storage).put(&protocol)?;
+
+    // run chain manager
+    let actor_system = SystemBuilder::new().name("peer_requests_chain_manager").log(logger()).create().expect("Failed to create actor system");
+    let network_channel = NetworkChannel::actor(&actor_system).expect("Failed to create network channel");
+    let shell_channel = ShellChannel::actor(&actor_system).expect("Failed to create shell channel");
+    let _ = ChainManager::actor(&actor_system, network_channel.clone(), shell_channel, persistent_storage, &chain_id, None).expect("Failed to create chain manager");
+
+    let (peer, responses) = MockPeer::actor(&actor_system).expect("Failed to create mock peer");
+    let peer_address: SocketAddr = "127.0.0.1:9732".parse()?;
+    let bootstrapped = PeerBootstrapped::Success { peer: peer.clone(), peer_id: "idsyBpzU3VspRyD3GEDWkgUBRqQNti".to_string(), address: peer_address, listener_port: peer_address.port() };
+    // bootstrapped peer is asked for the current branch
+    assert!(publish_until_response(&network_channel, bootstrapped.into(), &responses, |message| if let PeerMessage::GetCurrentBranch(_) = message { true } else { false }).is_some());
+
+    // request protocol
+    publish(&network_channel, received(&peer, peer_address, GetProtocolsMessage::new(vec![protocol_hash.clone(), vec![0; 32]]).into()).into());
+    match wait_for_response(&responses, |message| if let PeerMessage::Protocol(_) = message { true } else { false }) {
+        Some(PeerMessage::Protocol(message)) => assert_eq!(protocol_hash, message.protocol().message_hash()?),
+        message => panic!("expected protocol message, but was: {:?}", message),
+    }
+
+    // request operation hashes
+    publish(&network_channel, received(&peer, peer_address, GetOperationHashesForBlocksMessage::new(vec![OperationHashesForBlock::new(block_hash.clone(), 3)]).into()).into());
+    match wait_for_response(&responses, |message| if let PeerMessage::OperationHashesForBlock(_) = message { true } else { false }) {
+        Some(PeerMessage::OperationHashesForBlock(message)) => {
+            assert_eq!(&block_hash, message.operation_hashes_for_block().hash());
+            assert_eq!(3, message.operation_hashes_for_block().validation_pass());
+            assert_eq!(&vec![operation.message_hash()?], message.operation_hashes());
+        }
+        message => panic!("expected operation hashes message, but was: {:?}", message),
+    }
+
+    actor_system.shutdown();
+    Ok(())
+}
+
+#[test]
+fn test_chain_manager_stops_serving_deactivated_peer() -> Result<(), Error> {
+    let tmp_storage = TmpStorage::create("__shell_peer_requests_deactivate")?;
+    let persistent_storage = tmp_storage.storage();
+    let chain_id = chain_id()?;
+
+    let mut protocol_storage = ProtocolStorage::new(persistent_storage);
+    let first_protocol_hash = protocol_storage.put(&Protocol::new(0, vec![Component::new("Main".to_string(), None, "let x = 1".to_string())]))?;
+    let second_protocol_hash = protocol_storage.put(&Protocol::new(0, vec![Component::new("Main".to_string(), None, "let x = 2".to_string())]))?;
+
+    // run chain manager
+    let actor_system = SystemBuilder::new().name("peer_requests_deactivate").log(logger()).create().expect("Failed to create actor system");
+    let network_channel = NetworkChannel::actor(&actor_system).expect("Failed to create network channel");
+    let shell_channel = ShellChannel::actor(&actor_system).expect("Failed to create shell channel");
+    let _ = ChainManager::actor(&actor_system, network_channel.clone(), shell_channel, persistent_storage, &chain_id, None).expect("Failed to create chain manager");
+
+    let (peer, responses) = MockPeer::actor(&actor_system).expect("Failed to create mock peer");
+    let peer_address: SocketAddr = "127.0.0.1:9732".parse()?;
+    let bootstrapped = PeerBootstrapped::Success { peer: peer.clone(), peer_id: "idsyBpzU3VspRyD3GEDWkgUBRqQNti".to_string(), address: peer_address, listener_port: peer_address.port() };
+    assert!(publish_until_response(&network_channel, bootstrapped.clone().into(), &responses, |message| if let PeerMessage::GetCurrentBranch(_) = message { true } else { false }).is_some());
+
+    // deactivation of other chain is ignored
+    publish(&network_channel, received(&peer, peer_address, PeerMessage::Deactivate(DeactivateMessage::new(HashType::ChainId.string_to_bytes("NetXdQprcVkpaWU")?))).into());
+    publish(&network_channel, received(&peer, peer_address, GetProtocolsMessage::new(vec![first_protocol_hash.clone()]).into()).into());
+    match wait_for_response(&responses, |message| if let PeerMessage::Protocol(_) = message { true } else { false }) {
+        Some(PeerMessage::Protocol(message)) => assert_eq!(first_protocol_hash, message.protocol().message_hash()?),
+        message => panic!("expected protocol message, but was: {:?}", message),
+    }
+
+    // deactivated peer is not served, messages are processed in order, so only the request sent after the next bootstrap is answered
+    publish(&network_channel, received(&peer, peer_address, PeerMessage::Deactivate(DeactivateMessage::new(chain_id.clone()))).into());
+    publish(&network_channel, received(&peer, peer_address, GetProtocolsMessage::new(vec![first_protocol_hash]).into()).into());
+    publish(&network_channel, bootstrapped.into());
+    publish(&network_channel, received(&peer, peer_address, GetProtocolsMessage::new(vec![second_protocol_hash.clone()]).into()).into());
+    match wait_for_response(&responses, |message| if let PeerMessage::Protocol(_) = message { true } else { false }) {
+        Some(PeerMessage::Protocol(message)) => assert_eq!(second_protocol_hash, message.protocol().message_hash()?),
+        message => panic!("expected protocol message, but was: {:?}", message),
+    }
+
+    actor_system.shutdown();
+    Ok(())
+}
+
+#[test]
+fn test_mempool_serves_stored_operations() -> Result<(), Error> {
+    let tmp_storage = TmpStorage::create("__shell_peer_requests_mempool")?;
+    let persistent_storage = tmp_storage.storage();
+    let chain_id = chain_id()?;
+
+    // operation is already included in a block, so it is not in the mempool
+    let block_hash = HashType::BlockHash.string_to_bytes("BKyQ9EofHrgaZKENioHyP4FZNsTmiSEcVmcghgzCC9cGhE7oCET")?;
+    let operation = operation()?;
+    let operation_hash = operation.message_hash()?;
+    OperationsStorage::new(persistent_storage).put_operations(&OperationsForBlocksMessage::new(OperationsForBlock::new(block_hash, 3), Path::Op, vec![operation.clone()]))?;
+
+    // run mempool manager
+    let actor_system = SystemBuilder::new().name("peer_requests_mempool").log(logger()).create().expect("Failed to create actor system");
+    let network_channel = NetworkChannel::actor(&actor_system).expect("Failed to create network channel");
+    let shell_channel = ShellChannel::actor(&actor_system).expect("Failed to create shell channel");
+    let _ = MempoolManager::actor(&actor_system, network_channel.clone(), shell_channel, persistent_storage, &chain_id, Arc::new(RwLock::new(MempoolState::default()))).expect("Failed to create mempool manager");
+
+    let (peer, responses) = MockPeer::actor(&actor_system).expect("Failed to create mock peer");
+    let peer_address: SocketAddr = "127.0.0.1:9732".parse()?;
+
+    // request operation
+    let request = received(&peer, peer_address, GetOperationsMessage::new(vec![operation_hash, vec![0; 32]]).into());
+    match publish_until_response(&network_channel, request.into(), &responses, |message| if let PeerMessage::Operation(_) = message { true } else { false }) {
+        Some(PeerMessage::Operation(message)) => assert_eq!(operation.as_bytes()?, message.operation().as_bytes()?),
+        message => panic!("expected operation message, but was: {:?}", message),
+    }
+
+    actor_system.shutdown();
+    Ok(())
+}
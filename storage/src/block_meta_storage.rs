@@ -1,6 +1,7 @@
 // Copyright (c) SimpleStaking and Tezedge Contributors
 // SPDX-License-Identifier: MIT
 
+use std::borrow::Cow;
 use std::sync::Arc;
 
 use getset::{CopyGetters, Getters, Setters};
@@ -8,26 +9,32 @@ use rocksdb::{ColumnFamilyDescriptor, MergeOperands, Options};
 
 use crypto::hash::{BlockHash, ChainId, HashType};
 
-use crate::{BlockHeaderWithHash, StorageError};
+use crate::{BlockHeaderWithHash, StorageError, SystemStorage};
 use crate::num_from_slice;
-use crate::persistent::{Decoder, Encoder, KeyValueSchema, KeyValueStoreWithSchema, PersistentStorage, SchemaError};
+use crate::persistent::{DBError, Decoder, Encoder, KeyValueSchema, KeyValueStoreWithSchema, PersistentStorage, SchemaError};
 use crate::persistent::database::{IteratorMode, IteratorWithSchema};
 
 pub type BlockMetaStorageKV = dyn KeyValueStoreWithSchema<BlockMetaStorage> + Sync + Send;
 
 pub trait BlockMetaStorageReader: Sync + Send {
-    /// Load local head (block with highest level) from dedicated storage
+    /// Load local head (applied block with the highest fitness) from dedicated storage.
+    ///
+    /// If no head was stored yet, then applied block with the highest level is returned.
     fn load_current_head(&self) -> Result<Option<BlockHash>, StorageError>;
 }
 
 #[derive(Clone)]
 pub struct BlockMetaStorage {
-    kv: Arc<BlockMetaStorageKV>
+    kv: Arc<BlockMetaStorageKV>,
+    system: SystemStorage,
 }
 
 impl BlockMetaStorage {
     pub fn new(persistent_storage: &PersistentStorage) -> Self {
-        BlockMetaStorage { kv: persistent_storage.kv() }
+        BlockMetaStorage {
+            kv: persistent_storage.kv(),
+            system: SystemStorage::new(persistent_storage.kv()),
+        }
     }
 
     /// Create new metadata record in storage from given block header
@@ -42,7 +49,7 @@ impl BlockMetaStorage {
                 let meta = Meta {
                     is_applied: false,
                     predecessor: Some(block_header.header.predecessor().clone()),
-                    successor: None,
+                    successors: vec![],
                     level: block_header.header.level(),
                     chain_id: chain_id.clone(),
                 };
@@ -50,27 +57,34 @@ impl BlockMetaStorage {
             }
         }
 
-        // create/update record for block predecessor
-        match self.get(&block_header.header.predecessor())?.as_mut() {
-            Some(meta) => {
-                meta.successor = Some(block_header.hash.clone());
-                self.put(block_header.header.predecessor(), &meta)?;
-            },
-            None => {
-                let meta = Meta {
-                    is_applied: false,
-                    predecessor: None,
-                    successor: Some(block_header.hash.clone()),
-                    level: block_header.header.level() - 1,
-                    chain_id: chain_id.clone(),
-                };
-                self.put(block_header.header.predecessor(), &meta)?;
-            }
+        // genesis is predecessor of itself, but we do not want it to be its own successor
+        if block_header.header.predecessor() == &block_header.hash {
+            return Ok(());
         }
 
+        // create/update record for block predecessor, successors are merged by the merge operator
+        let predecessor_level = match self.get(&block_header.header.predecessor())? {
+            Some(meta) => meta.level,
+            None => block_header.header.level() - 1,
+        };
+        let meta = Meta {
+            is_applied: false,
+            predecessor: None,
+            successors: vec![block_header.hash.clone()],
+            level: predecessor_level,
+            chain_id: chain_id.clone(),
+        };
+        self.put(block_header.header.predecessor(), &meta)?;
+
         Ok(())
     }
 
+    /// Store hash of the block which was selected as the current head (applied block with the highest fitness)
+    #[inline]
+    pub fn store_current_head(&mut self, block_hash: &BlockHash) -> Result<(), StorageError> {
+        self.system.set_current_head(block_hash)
+    }
+
     #[inline]
     pub fn put(&mut self, block_hash: &BlockHash, meta: &Meta) -> Result<(), StorageError> {
         self.kv.merge(block_hash, meta)
@@ -83,6 +97,23 @@ impl BlockMetaStorage {
             .map_err(StorageError::from)
     }
 
+    #[inline]
+    pub fn delete(&mut self, block_hash: &BlockHash) -> Result<(), StorageError> {
+        self.kv.delete(block_hash)
+            .map_err(StorageError::from)
+    }
+
+    /// Remove the successor from the block metadata.
+    ///
+    /// Successors are merged by the merge operator, so the whole record has to be overwritten.
+    pub fn remove_successor(&mut self, block_hash: &BlockHash, successor: &BlockHash) -> Result<(), StorageError> {
+        if let Some(mut meta) = self.get(block_hash)? {
+            meta.successors.retain(|s| s != successor);
+            self.kv.put(block_hash, &meta)?;
+        }
+        Ok(())
+    }
+
     #[inline]
     pub fn iter(&self, mode: IteratorMode<Self>) -> Result<IteratorWithSchema<Self>, StorageError> {
         self.kv.iterator(mode)
@@ -92,6 +123,10 @@ impl BlockMetaStorage {
 
 impl BlockMetaStorageReader for BlockMetaStorage {
     fn load_current_head(&self) -> Result<Option<BlockHash>, StorageError> {
+        if let Some(current_head) = self.system.get_current_head()? {
+            return Ok(Some(current_head));
+        }
+
         self.iter(IteratorMode::End)
             .and_then(|meta_iterator|
                 Ok(
@@ -113,18 +148,23 @@ const LEN_BLOCK_HASH: usize = HashType::BlockHash.size();
 const LEN_CHAIN_ID: usize = HashType::ChainId.size();
 
 const MASK_IS_APPLIED: u8 = 0b0000_0001;
-const MASK_HAS_SUCCESSOR: u8 = 0b0000_0010;
 const MASK_HAS_PREDECESSOR: u8 = 0b0000_0100;
 
 const IDX_MASK: usize = 0;
 const IDX_PREDECESSOR: usize = IDX_MASK + 1;
-const IDX_SUCCESSOR: usize = IDX_PREDECESSOR + LEN_BLOCK_HASH;
-const IDX_LEVEL: usize = IDX_SUCCESSOR + LEN_BLOCK_HASH;
+const IDX_LEVEL: usize = IDX_PREDECESSOR + LEN_BLOCK_HASH;
 const IDX_CHAIN_ID: usize = IDX_LEVEL + std::mem::size_of::<i32>();
-const IDX_END: usize = IDX_CHAIN_ID + LEN_CHAIN_ID;
+const IDX_SUCCESSORS: usize = IDX_CHAIN_ID + LEN_CHAIN_ID;
 
 const BLANK_BLOCK_HASH: [u8; LEN_BLOCK_HASH] = [0; LEN_BLOCK_HASH];
-const LEN_META: usize = std::mem::size_of::<u8>() + LEN_BLOCK_HASH + LEN_BLOCK_HASH + std::mem::size_of::<i32>() + LEN_CHAIN_ID;
+/// Length of the fixed part of the meta, successors are appended after it
+const LEN_META_FIXED: usize = std::mem::size_of::<u8>() + LEN_BLOCK_HASH + std::mem::size_of::<i32>() + LEN_CHAIN_ID;
+
+/// Layout used before database version 13 had a single successor placed right after the predecessor
+const MASK_LEGACY_HAS_SUCCESSOR: u8 = 0b0000_0010;
+const IDX_LEGACY_SUCCESSOR: usize = IDX_PREDECESSOR + LEN_BLOCK_HASH;
+const IDX_LEGACY_LEVEL: usize = IDX_LEGACY_SUCCESSOR + LEN_BLOCK_HASH;
+const LEN_LEGACY_META: usize = LEN_META_FIXED + LEN_BLOCK_HASH;
 
 macro_rules! is_applied {
     ($mask:expr) => {{ ($mask & MASK_IS_APPLIED) != 0 }}
@@ -132,8 +172,8 @@ macro_rules! is_applied {
 macro_rules! has_predecessor {
     ($mask:expr) => {{ ($mask & MASK_HAS_PREDECESSOR) != 0 }}
 }
-macro_rules! has_successor {
-    ($mask:expr) => {{ ($mask & MASK_HAS_SUCCESSOR) != 0 }}
+macro_rules! is_valid_meta_len {
+    ($len:expr) => {{ ($len >= LEN_META_FIXED) && (($len - LEN_META_FIXED) % LEN_BLOCK_HASH == 0) }}
 }
 
 /// Meta information for the block
@@ -141,8 +181,9 @@ macro_rules! has_successor {
 pub struct Meta {
     #[get = "pub"]
     predecessor: Option<BlockHash>,
+    /// All known successors of the block. There is more than one successor in case of a fork.
     #[get = "pub"]
-    successor: Option<BlockHash>,
+    successors: Vec<BlockHash>,
     #[get_copy = "pub"]
     #[set = "pub"]
     is_applied: bool,
@@ -158,7 +199,7 @@ impl Meta {
         Meta {
             is_applied,
             predecessor: Some(genesis_hash.clone()), // this is what we want
-            successor: None, // we do not know (yet) successor of the genesis
+            successors: vec![], // we do not know (yet) successors of the genesis
             level: 0,
             chain_id: genesis_chain_id.clone(),
         }
@@ -167,35 +208,32 @@ impl Meta {
 
 /// Codec for `Meta`
 ///
-/// * bytes layout: `[mask(1)][predecessor(32)][successor(32)][level(4)][chain_id(4)]`
+/// * bytes layout: `[mask(1)][predecessor(32)][level(4)][chain_id(4)][successors(n * 32)]`
 impl Decoder for Meta {
     fn decode(bytes: &[u8]) -> Result<Self, SchemaError> {
-        if LEN_META == bytes.len() {
+        if is_valid_meta_len!(bytes.len()) {
             // mask
             let mask = bytes[IDX_MASK];
             let is_processed = is_applied!(mask);
             // predecessor
             let predecessor = if has_predecessor!(mask) {
-                let block_hash = bytes[IDX_PREDECESSOR..IDX_SUCCESSOR].to_vec();
+                let block_hash = bytes[IDX_PREDECESSOR..IDX_LEVEL].to_vec();
                 assert_eq!(LEN_BLOCK_HASH, block_hash.len(), "Predecessor expected length is {} but found {}", LEN_BLOCK_HASH, block_hash.len());
                 Some(block_hash)
             } else {
                 None
             };
-            // successor
-            let successor = if has_successor!(mask) {
-                let block_hash = bytes[IDX_SUCCESSOR..IDX_LEVEL].to_vec();
-                assert_eq!(LEN_BLOCK_HASH, block_hash.len(), "Successor expected length is {} but found {}", LEN_BLOCK_HASH, block_hash.len());
-                Some(block_hash)
-            } else {
-                None
-            };
             // level
             let level = num_from_slice!(bytes, IDX_LEVEL, i32);
             // chain_id
-            let chain_id = bytes[IDX_CHAIN_ID..IDX_END].to_vec();
+            let chain_id = bytes[IDX_CHAIN_ID..IDX_SUCCESSORS].to_vec();
             assert_eq!(LEN_CHAIN_ID, chain_id.len(), "Chain ID expected length is {} but found {}", LEN_CHAIN_ID, chain_id.len());
-            Ok(Meta { predecessor, successor, is_applied: is_processed, level, chain_id })
+            // successors
+            let successors = bytes[IDX_SUCCESSORS..]
+                .chunks(LEN_BLOCK_HASH)
+                .map(|block_hash| block_hash.to_vec())
+                .collect();
+            Ok(Meta { predecessor, successors, is_applied: is_processed, level, chain_id })
         } else {
             Err(SchemaError::DecodeError)
         }
@@ -211,23 +249,19 @@ impl Encoder for Meta {
         if self.predecessor.is_some() {
             mask |= MASK_HAS_PREDECESSOR;
         }
-        if self.successor.is_some() {
-            mask |= MASK_HAS_SUCCESSOR;
-        }
 
-        let mut value = Vec::with_capacity(LEN_META);
+        let mut value = Vec::with_capacity(LEN_META_FIXED + self.successors.len() * LEN_BLOCK_HASH);
         value.push(mask);
         match &self.predecessor {
             Some(predecessor) => value.extend(predecessor),
             None => value.extend(&BLANK_BLOCK_HASH)
         }
-        match &self.successor {
-            Some(successor) => value.extend(successor),
-            None => value.extend(&BLANK_BLOCK_HASH)
-        }
         value.extend(&self.level.to_be_bytes());
         value.extend(&self.chain_id);
-        assert_eq!(LEN_META, value.len(), "Invalid size. predecessor={:?}, successor={:?}, level={:?}, data={:?}", &self.predecessor, &self.successor, self.level, &value);
+        for successor in &self.successors {
+            value.extend(successor);
+        }
+        assert!(is_valid_meta_len!(value.len()), "Invalid size. predecessor={:?}, successors={:?}, level={:?}, data={:?}", &self.predecessor, &self.successors, self.level, &value);
 
         Ok(value)
     }
@@ -250,12 +284,15 @@ impl KeyValueSchema for BlockMetaStorage {
 }
 
 fn merge_meta_value(_new_key: &[u8], existing_val: Option<&[u8]>, operands: &mut MergeOperands) -> Option<Vec<u8>> {
-    let mut result = existing_val.map(|v| v.to_vec());
+    // values and operands written before database version 13 are converted, so they can be merged
+    let mut result = existing_val.map(|v| upgrade_legacy_meta(v).unwrap_or_else(|| v.to_vec()));
 
     for op in operands {
+        let op = upgrade_legacy_meta(op).map(Cow::Owned).unwrap_or(Cow::Borrowed(op));
         match result {
             Some(ref mut val) => {
-                assert_eq!(LEN_META, val.len(), "Value length is incorrect. Was expecting {} but instead found {}", LEN_META, val.len());
+                assert!(is_valid_meta_len!(val.len()), "Value length is incorrect. Was expecting at least {} but instead found {}", LEN_META_FIXED, val.len());
+                assert!(is_valid_meta_len!(op.len()), "Operand length is incorrect. Was expecting at least {} but instead found {}", LEN_META_FIXED, op.len());
 
                 let mask_val = val[IDX_MASK];
                 let mask_op = op[IDX_MASK];
@@ -265,13 +302,19 @@ fn merge_meta_value(_new_key: &[u8], existing_val: Option<&[u8]>, operands: &mut
 
                 // if op has predecessor and val has not, copy it from op to val
                 if has_predecessor!(mask_op) && !has_predecessor!(mask_val) {
-                    val.splice(IDX_PREDECESSOR..IDX_SUCCESSOR, op[IDX_PREDECESSOR..IDX_SUCCESSOR].iter().cloned());
+                    val.splice(IDX_PREDECESSOR..IDX_LEVEL, op[IDX_PREDECESSOR..IDX_LEVEL].iter().cloned());
                 }
-                // if op has successor and val has not, copy it from op to val
-                if has_successor!(mask_op) && !has_successor!(mask_val) {
-                    val.splice(IDX_SUCCESSOR..IDX_LEVEL, op[IDX_SUCCESSOR..IDX_LEVEL].iter().cloned());
+
+                // append all successors from op, which are not yet present in val
+                for op_successor in op[IDX_SUCCESSORS..].chunks(LEN_BLOCK_HASH) {
+                    let is_known_successor = val[IDX_SUCCESSORS..]
+                        .chunks(LEN_BLOCK_HASH)
+                        .any(|val_successor| val_successor == op_successor);
+                    if !is_known_successor {
+                        val.extend(op_successor);
+                    }
                 }
-                assert_eq!(LEN_META, val.len(), "Invalid length after merge operator was applied. Was expecting {} but found {}.", LEN_META, val.len());
+                assert!(is_valid_meta_len!(val.len()), "Invalid length after merge operator was applied. Was expecting at least {} but found {}.", LEN_META_FIXED, val.len());
             },
             None => result = Some(op.to_vec())
         }
@@ -280,6 +323,51 @@ fn merge_meta_value(_new_key: &[u8], existing_val: Option<&[u8]>, operands: &mut
     result
 }
 
+/// Convert meta from the layout used before database version 13
+/// `[mask(1)][predecessor(32)][successor(32)][level(4)][chain_id(4)]` into the current layout.
+///
+/// Returns `None` if the value is already in the current layout. Current layout with a single successor
+/// has the same length, but it never sets the legacy successor flag and its level, chain id and successor
+/// are never all zeros, which is how the legacy layout stores a missing successor.
+fn upgrade_legacy_meta(bytes: &[u8]) -> Option<Vec<u8>> {
+    if bytes.len() != LEN_LEGACY_META {
+        return None;
+    }
+    let mask = bytes[IDX_MASK];
+    let has_successor = (mask & MASK_LEGACY_HAS_SUCCESSOR) != 0;
+    let successor = &bytes[IDX_LEGACY_SUCCESSOR..IDX_LEGACY_LEVEL];
+    if !has_successor && successor != &BLANK_BLOCK_HASH[..] {
+        return None;
+    }
+
+    let mut value = Vec::with_capacity(LEN_LEGACY_META);
+    value.push(mask & !MASK_LEGACY_HAS_SUCCESSOR);
+    value.extend(&bytes[IDX_PREDECESSOR..IDX_LEGACY_SUCCESSOR]);
+    // level and chain_id
+    value.extend(&bytes[IDX_LEGACY_LEVEL..]);
+    if has_successor {
+        value.extend(successor);
+    }
+    Some(value)
+}
+
+/// Migration to the database version 13, which converts block metadata with a single successor
+/// into the layout with all known successors.
+pub fn upgrade_legacy_metas(persistent_storage: &PersistentStorage) -> Result<(), StorageError> {
+    let db = persistent_storage.kv();
+    let cf = db.cf_handle(BlockMetaStorage::name())
+        .ok_or(DBError::MissingColumnFamily { name: BlockMetaStorage::name() })?;
+
+    // iterator reads from a snapshot, so records can be rewritten while iterating
+    for (key, value) in db.iterator_cf(cf, rocksdb::IteratorMode::Start).map_err(DBError::from)? {
+        if let Some(value) = upgrade_legacy_meta(&value) {
+            db.put_cf(cf, &key, &value).map_err(DBError::from)?;
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::Path;
@@ -298,7 +386,7 @@ mod tests {
         let expected = Meta {
             is_applied: false,
             predecessor: Some(vec![98; 32]),
-            successor: Some(vec![21; 32]),
+            successors: vec![vec![21; 32], vec![22; 32]],
             level: 34,
             chain_id: vec![44; 4],
         };
@@ -321,7 +409,7 @@ mod tests {
                 let expected = Meta {
                     is_applied: true,
                     predecessor: Some(k.clone()),
-                    successor: None,
+                    successors: vec![],
                     level: 0,
                     chain_id: chain_id.clone(),
                 };
@@ -341,7 +429,7 @@ mod tests {
         let mut v = Meta {
             is_applied: false,
             predecessor: None,
-            successor: None,
+            successors: vec![],
             level: 1_245_762,
             chain_id: vec![44; 4],
         };
@@ -350,11 +438,11 @@ mod tests {
         let p = storage.get(&k)?;
         assert!(p.is_some());
         v.is_applied = true;
-        v.successor = Some(vec![21; 32]);
+        v.successors = vec![vec![21; 32]];
         storage.put(&k, &v)?;
         v.is_applied = false;
         v.predecessor = Some(vec![98; 32]);
-        v.successor = None;
+        v.successors = vec![vec![22; 32], vec![21; 32]];
         storage.put(&k, &v)?;
         v.predecessor = None;
         storage.put(&k, &v)?;
@@ -363,7 +451,7 @@ mod tests {
                 let expected = Meta {
                     is_applied: true,
                     predecessor: Some(vec![98; 32]),
-                    successor: Some(vec![21; 32]),
+                    successors: vec![vec![21; 32], vec![22; 32]],
                     level: 1_245_762,
                     chain_id: vec![44; 4],
                 };
@@ -375,6 +463,110 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn block_meta_storage_fork_test() -> Result<(), Error> {
+        use tezos_messages::p2p::encoding::prelude::BlockHeaderBuilder;
+
+        let tmp_storage = TmpStorage::create("__blockmeta_forktest")?;
+        let mut storage = BlockMetaStorage::new(tmp_storage.storage());
+        let chain_id = vec![44; 4];
+        let predecessor = vec![98; 32];
+
+        let make_block = |timestamp: i64| -> Result<BlockHeaderWithHash, Error> {
+            let header = BlockHeaderBuilder::default()
+                .level(5)
+                .proto(1)
+                .predecessor(predecessor.clone())
+                .timestamp(timestamp)
+                .validation_pass(4)
+                .operations_hash(vec![0; 32])
+                .fitness(vec![])
+                .context(vec![0; 32])
+                .protocol_data(vec![])
+                .build().unwrap();
+            Ok(BlockHeaderWithHash::new(header)?)
+        };
+        let block_1 = make_block(1)?;
+        let block_2 = make_block(2)?;
+
+        storage.put_block_header(&block_1, &chain_id)?;
+        storage.put_block_header(&block_2, &chain_id)?;
+        // storing the same block twice should not duplicate successor
+        storage.put_block_header(&block_1, &chain_id)?;
+
+        let predecessor_meta = storage.get(&predecessor)?.expect("Predecessor meta was not stored");
+        assert_eq!(&vec![block_1.hash.clone(), block_2.hash.clone()], predecessor_meta.successors());
+        assert_eq!(4, predecessor_meta.level());
+        assert!(predecessor_meta.predecessor().is_none());
+
+        let block_meta = storage.get(&block_2.hash)?.expect("Block meta was not stored");
+        assert_eq!(&Some(predecessor.clone()), block_meta.predecessor());
+        assert!(block_meta.successors().is_empty());
+
+        // explicitly stored current head takes precedence
+        assert!(storage.load_current_head()?.is_none());
+        storage.store_current_head(&block_2.hash)?;
+        assert_eq!(Some(block_2.hash.clone()), storage.load_current_head()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn upgrade_legacy_metas_test() -> Result<(), Error> {
+        let legacy_meta = |mask: u8, predecessor: Option<Vec<u8>>, successor: Option<Vec<u8>>, level: i32| {
+            let mut value = vec![mask];
+            value.extend(predecessor.unwrap_or_else(|| BLANK_BLOCK_HASH.to_vec()));
+            value.extend(successor.unwrap_or_else(|| BLANK_BLOCK_HASH.to_vec()));
+            value.extend(&level.to_be_bytes());
+            value.extend(&[44; 4]);
+            value
+        };
+
+        let tmp_storage = TmpStorage::create("__blockmeta_upgradetest")?;
+        let db = tmp_storage.storage().kv();
+        let cf = db.cf_handle(BlockMetaStorage::name()).unwrap();
+        let (k1, k2, k3) = (vec![1; 32], vec![2; 32], vec![3; 32]);
+        // legacy value with successor
+        db.put_cf(cf, &k1, legacy_meta(MASK_IS_APPLIED | MASK_HAS_PREDECESSOR | MASK_LEGACY_HAS_SUCCESSOR, Some(vec![98; 32]), Some(vec![21; 32]), 5))?;
+        // legacy value without successor merged with legacy operand, which has the successor
+        db.merge_cf(cf, &k2, legacy_meta(MASK_HAS_PREDECESSOR, Some(vec![98; 32]), None, 6))?;
+        db.merge_cf(cf, &k2, legacy_meta(MASK_LEGACY_HAS_SUCCESSOR, None, Some(vec![22; 32]), 6))?;
+        // value in the current layout with a single successor has the same length as legacy value
+        let current = Meta {
+            is_applied: false,
+            predecessor: Some(vec![98; 32]),
+            successors: vec![vec![23; 32]],
+            level: 7,
+            chain_id: vec![44; 4],
+        };
+        db.put_cf(cf, &k3, current.encode()?)?;
+
+        upgrade_legacy_metas(tmp_storage.storage())?;
+        // migration can be run again
+        upgrade_legacy_metas(tmp_storage.storage())?;
+
+        let storage = BlockMetaStorage::new(tmp_storage.storage());
+        let expected = Meta {
+            is_applied: true,
+            predecessor: Some(vec![98; 32]),
+            successors: vec![vec![21; 32]],
+            level: 5,
+            chain_id: vec![44; 4],
+        };
+        assert_eq!(Some(expected), storage.get(&k1)?);
+        let expected = Meta {
+            is_applied: false,
+            predecessor: Some(vec![98; 32]),
+            successors: vec![vec![22; 32]],
+            level: 6,
+            chain_id: vec![44; 4],
+        };
+        assert_eq!(Some(expected), storage.get(&k2)?);
+        assert_eq!(Some(current), storage.get(&k3)?);
+
+        Ok(())
+    }
+
     #[test]
     fn merge_meta_value_test() -> Result<(), Error> {
         use rocksdb::{Options, DB};
@@ -390,18 +582,18 @@ mod tests {
             let mut v = Meta {
                 is_applied: false,
                 predecessor: None,
-                successor: None,
+                successors: vec![],
                 level: 2,
                 chain_id: vec![44; 4],
             };
             let p = BlockMetaStorageKV::merge(&db, &k, &v);
             assert!(p.is_ok(), "p: {:?}", p.unwrap_err());
             v.is_applied = true;
-            v.successor = Some(vec![21; 32]);
+            v.successors = vec![vec![21; 32]];
             let _ = BlockMetaStorageKV::merge(&db, &k, &v);
             v.is_applied = false;
             v.predecessor = Some(vec![98; 32]);
-            v.successor = None;
+            v.successors = vec![vec![22; 32], vec![21; 32]];
             let _ = BlockMetaStorageKV::merge(&db, &k, &v);
             v.predecessor = None;
             let m = BlockMetaStorageKV::merge(&db, &k, &v);
@@ -411,7 +603,7 @@ mod tests {
                     let expected = Meta {
                         is_applied: true,
                         predecessor: Some(vec![98; 32]),
-                        successor: Some(vec![21; 32]),
+                        successors: vec![vec![21; 32], vec![22; 32]],
                         level: 2,
                         chain_id: vec![44; 4],
                     };
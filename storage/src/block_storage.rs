@@ -1,6 +1,7 @@
 // Copyright (c) SimpleStaking and Tezedge Contributors
 // SPDX-License-Identifier: MIT
 
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use derive_builder::Builder;
@@ -84,7 +85,8 @@ impl BlockStorage {
                     block_json_data: None,
                     block_additional_data: None,
                 };
-                self.primary_index.put(&block_header.hash, &location).and(self.by_level_index.put(block_header.header.level(), &location))
+                self.primary_index.put(&block_header.hash, &location)
+                    .and(self.update_level_index(block_header, &location))
             })
     }
 
@@ -98,7 +100,7 @@ impl BlockStorage {
         let block_header = self.get_block_header_by_location(&updated_column_location)?;
         // update indexes
         self.primary_index.put(&block_header.hash, &updated_column_location)
-            .and(self.by_level_index.put(block_header.header.level(), &updated_column_location))
+            .and(self.update_level_index(&block_header, &updated_column_location))
     }
 
     pub fn put_block_additional_data(&mut self, block_hash: &BlockHash, additional_data: BlockAdditionalData) -> Result<(), StorageError> {
@@ -111,7 +113,135 @@ impl BlockStorage {
         let block_header = self.get_block_header_by_location(&updated_column_location)?;
         // update indexes
         self.primary_index.put(&block_header.hash, &updated_column_location)
-            .and(self.by_level_index.put(block_header.header.level(), &updated_column_location))
+            .and(self.update_level_index(&block_header, &updated_column_location))
+    }
+
+    /// Remove json data of the block, block header and additional data are kept
+    pub fn remove_block_json_data(&mut self, block_hash: &BlockHash) -> Result<(), StorageError> {
+        let mut updated_column_location = self.primary_index.get(block_hash)?.ok_or(StorageError::MissingKey)?;
+        updated_column_location.block_json_data = None;
+        let block_header = self.get_block_header_by_location(&updated_column_location)?;
+        // update indexes
+        self.primary_index.put(&block_header.hash, &updated_column_location)
+            .and(self.update_level_index(&block_header, &updated_column_location))
+    }
+
+    /// Remove the block from all indexes
+    pub fn delete(&mut self, block_hash: &BlockHash) -> Result<(), StorageError> {
+        if let Some(location) = self.primary_index.get(block_hash)? {
+            let block_header = self.get_block_header_by_location(&location)?;
+            if self.by_level_index.get(&block_header.header.level())?.filter(|l| l.block_header == location.block_header).is_some() {
+                self.by_level_index.delete(block_header.header.level())?;
+            }
+            if self.by_context_hash_index.get(block_header.header.context())?.filter(|l| l.block_header == location.block_header).is_some() {
+                self.by_context_hash_index.delete(block_header.header.context())?;
+            }
+            self.primary_index.delete(block_hash)?;
+        }
+        Ok(())
+    }
+
+    /// Make the block the one, which is returned for its level
+    pub fn index_by_level(&mut self, block_hash: &BlockHash) -> Result<(), StorageError> {
+        let location = self.primary_index.get(block_hash)?.ok_or(StorageError::MissingKey)?;
+        let block_header = self.get_block_header_by_location(&location)?;
+        self.by_level_index.put(block_header.header.level(), &location)
+    }
+
+    /// Remove the block from the level index, if it is the one, which is returned for its level (e.g. block is no longer in the main chain)
+    pub fn unindex_by_level(&mut self, block_hash: &BlockHash) -> Result<(), StorageError> {
+        if let Some(location) = self.primary_index.get(block_hash)? {
+            let block_header = self.get_block_header_by_location(&location)?;
+            if self.level_indexed_by(&block_header)? == Some(true) {
+                self.by_level_index.delete(block_header.header.level())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Update location of the block in the level index, if the level is not indexed yet or it is indexed by the same block.
+    ///
+    /// Block of a fork does not replace the block of the main chain, see [index_by_level](BlockStorage::index_by_level).
+    fn update_level_index(&self, block_header: &BlockHeaderWithHash, location: &BlockStorageColumnsLocation) -> Result<(), StorageError> {
+        match self.level_indexed_by(block_header)? {
+            None | Some(true) => self.by_level_index.put(block_header.header.level(), location),
+            Some(false) => Ok(()),
+        }
+    }
+
+    /// Returns `None` if the level of the block is not indexed, otherwise `true` if it is indexed by the block
+    fn level_indexed_by(&self, block_header: &BlockHeaderWithHash) -> Result<Option<bool>, StorageError> {
+        match self.by_level_index.get(&block_header.header.level())? {
+            Some(location) => Ok(Some(self.get_block_header_by_location(&location)?.hash == block_header.hash)),
+            None => Ok(None),
+        }
+    }
+
+    /// All blocks with assigned context by their context hash
+    pub fn iter_by_context_hash(&self) -> Result<Vec<(ContextHash, BlockHeaderWithHash)>, StorageError> {
+        self.by_context_hash_index.iter()?
+            .into_iter()
+            .map(|(context_hash, location)| self.get_block_header_by_location(&location).map(|block_header| (context_hash, block_header)))
+            .collect()
+    }
+
+    /// Rewrite the commit log, so it contains only records of the blocks present in the primary index.
+    ///
+    /// Locations in all indexes are updated, entries of the secondary indexes pointing to deleted blocks are removed.
+    /// Must not be called while the storage is used by anyone else, because old locations become invalid.
+    pub fn compact_commit_log(&mut self) -> Result<(), StorageError> {
+        let blocks = self.primary_index.iter()?;
+        let block_hash_by_header: HashMap<Location, BlockHash> = blocks.iter()
+            .map(|(block_hash, location)| (location.block_header, block_hash.clone()))
+            .collect();
+        // secondary indexes can refer to an older record of the same block, resolve them by block hash
+        let resolve_block_hash = |location: &BlockStorageColumnsLocation| -> Result<Option<BlockHash>, StorageError> {
+            match block_hash_by_header.get(&location.block_header) {
+                Some(block_hash) => Ok(Some(block_hash.clone())),
+                None => Ok(self.get_block_header_by_location(location).ok().map(|block_header| block_header.hash)),
+            }
+        };
+        let by_level = self.by_level_index.iter()?.into_iter()
+            .map(|(level, location)| resolve_block_hash(&location).map(|block_hash| (level, block_hash)))
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        let by_context_hash = self.by_context_hash_index.iter()?.into_iter()
+            .map(|(context_hash, location)| resolve_block_hash(&location).map(|block_hash| (context_hash, block_hash)))
+            .collect::<Result<Vec<_>, StorageError>>()?;
+
+        let mut locations: Vec<Location> = blocks.iter()
+            .flat_map(|(_, location)| std::iter::once(location.block_header).chain(location.block_json_data).chain(location.block_additional_data))
+            .collect();
+        locations.sort();
+        locations.dedup();
+        let relocated: HashMap<Location, Location> = locations.iter().cloned()
+            .zip(self.clog.rewrite(&locations)?)
+            .collect();
+        let relocate = |location: &Location| relocated.get(location).cloned().ok_or(StorageError::MissingKey);
+
+        let mut new_locations = HashMap::with_capacity(blocks.len());
+        for (block_hash, location) in blocks {
+            let location = BlockStorageColumnsLocation {
+                block_header: relocate(&location.block_header)?,
+                block_json_data: location.block_json_data.as_ref().map(&relocate).transpose()?,
+                block_additional_data: location.block_additional_data.as_ref().map(&relocate).transpose()?,
+            };
+            self.primary_index.put(&block_hash, &location)?;
+            new_locations.insert(block_hash, location);
+        }
+        for (level, block_hash) in by_level {
+            match block_hash.and_then(|block_hash| new_locations.get(&block_hash)) {
+                Some(location) => self.by_level_index.put(level, location)?,
+                None => self.by_level_index.delete(level)?,
+            }
+        }
+        for (context_hash, block_hash) in by_context_hash {
+            match block_hash.and_then(|block_hash| new_locations.get(&block_hash)) {
+                Some(location) => self.by_context_hash_index.put(&context_hash, location)?,
+                None => self.by_context_hash_index.delete(&context_hash)?,
+            }
+        }
+
+        Ok(())
     }
 
     pub fn assign_to_context(&mut self, block_hash: &BlockHash, context_hash: &ContextHash) -> Result<(), StorageError> {
@@ -308,6 +438,18 @@ impl BlockPrimaryIndex {
         self.kv.contains(block_hash)
             .map_err(StorageError::from)
     }
+
+    #[inline]
+    fn delete(&mut self, block_hash: &BlockHash) -> Result<(), StorageError> {
+        self.kv.delete(block_hash)
+            .map_err(StorageError::from)
+    }
+
+    fn iter(&self) -> Result<Vec<(BlockHash, BlockStorageColumnsLocation)>, StorageError> {
+        self.kv.iterator(IteratorMode::Start)?
+            .map(|(block_hash, location)| block_hash.and_then(|block_hash| location.map(|location| (block_hash, location))).map_err(StorageError::from))
+            .collect()
+    }
 }
 
 impl KeyValueSchema for BlockPrimaryIndex {
@@ -343,6 +485,16 @@ impl BlockByLevelIndex {
         self.kv.get(level).map_err(StorageError::from)
     }
 
+    fn delete(&self, level: BlockLevel) -> Result<(), StorageError> {
+        self.kv.delete(&level).map_err(StorageError::from)
+    }
+
+    fn iter(&self) -> Result<Vec<(BlockLevel, BlockStorageColumnsLocation)>, StorageError> {
+        self.kv.iterator(IteratorMode::Start)?
+            .map(|(level, location)| level.and_then(|level| location.map(|location| (level, location))).map_err(StorageError::from))
+            .collect()
+    }
+
     fn get_blocks(&self, from_level: BlockLevel, limit: usize) -> Result<Vec<BlockStorageColumnsLocation>, StorageError> {
         self.kv.iterator(IteratorMode::From(&from_level, Direction::Reverse))?
             .take(limit)
@@ -398,6 +550,16 @@ impl BlockByContextHashIndex {
     fn get(&self, context_hash: &ContextHash) -> Result<Option<BlockStorageColumnsLocation>, StorageError> {
         self.kv.get(context_hash).map_err(StorageError::from)
     }
+
+    fn delete(&self, context_hash: &ContextHash) -> Result<(), StorageError> {
+        self.kv.delete(context_hash).map_err(StorageError::from)
+    }
+
+    fn iter(&self) -> Result<Vec<(ContextHash, BlockStorageColumnsLocation)>, StorageError> {
+        self.kv.iterator(IteratorMode::Start)?
+            .map(|(context_hash, location)| context_hash.and_then(|context_hash| location.map(|location| (context_hash, location))).map_err(StorageError::from))
+            .collect()
+    }
 }
 
 impl KeyValueSchema for BlockByContextHashIndex {
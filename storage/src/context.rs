@@ -1,14 +1,26 @@
 // Copyright (c) SimpleStaking and Tezedge Contributors
 // SPDX-License-Identifier: MIT
 
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
 use failure::Fail;
 
 use crypto::hash::{BlockHash, ContextHash, HashType};
 
-use crate::{BlockStorage, BlockStorageReader, StorageError};
-use crate::persistent::{ContextList, ContextMap};
+use crate::{BlockStorage, StorageError};
+use crate::merkle_hash::{CommitInfo, EntryHash, hash_commit, MerkleError, MerkleTree};
+use crate::persistent::{ContextList, ContextMap, KeyValueSchema, KeyValueStoreWithSchema, PersistentStorage};
 use crate::skip_list::{Bucket, SkipListError};
 
+/// How many merkle trees of the recent commits are kept in memory, tree of an older commit is built again from its whole context
+const MAX_RETAINED_MERKLE_TREES: usize = 64;
+/// How many commits (or commit infos) can wait for their counterpart, older ones are never verified
+const MAX_PENDING_VERIFICATIONS: usize = 256;
+/// How many commits are kept by the in-memory context, older ones cannot be checkouted anymore
+const MAX_IN_MEMORY_COMMITS: usize = 256;
+
 /// Possible errors for context
 #[derive(Debug, Fail)]
 pub enum ContextError {
@@ -35,11 +47,31 @@ pub enum ContextError {
     UnknownContextHashError {
         context_hash: String,
     },
-    #[fail(display = "Failed to read block for context_hash: {:?}, error: {}", context_hash, error)]
-    ReadBlockError {
+    #[fail(display = "Failed to read or write index of the context_hash: {:?}, error: {}", context_hash, error)]
+    IndexError {
         context_hash: String,
         error: StorageError,
     },
+    #[fail(display = "Context hash does not match the committed context, context_hash: {}, calculated_context_hash: {}", context_hash, calculated_context_hash)]
+    ContextHashMismatchError {
+        context_hash: String,
+        calculated_context_hash: String,
+    },
+    #[fail(display = "Failed to calculate hash of the committed context, context_hash: {}, error: {}", context_hash, error)]
+    MerkleHashError {
+        context_hash: String,
+        error: MerkleError,
+    },
+}
+
+impl ContextError {
+    /// Returns `true` if committed context could not be verified against the context hash calculated by protocol
+    pub fn is_verification_error(&self) -> bool {
+        match self {
+            ContextError::ContextHashMismatchError { .. } | ContextError::MerkleHashError { .. } => true,
+            _ => false,
+        }
+    }
 }
 
 impl From<SkipListError> for ContextError {
@@ -63,27 +95,142 @@ macro_rules! ensure_eq_context_hash {
     }}
 }
 
+/// Thread safe reference to the context, which is shared by the context listener and RPC
+pub type ContextApiRef = Arc<RwLock<dyn ContextApi + Send + Sync>>;
+
 /// Abstraction on context manipulation
 pub trait ContextApi {
-    fn init_from_start(&self) -> ContextDiff;
+    fn init_from_start(&self) -> ContextDiff {
+        ContextDiff::new(None, Default::default())
+    }
 
     /// Checkout context for hash and return ContextDiff which is prepared for applying new successor block
     fn checkout(&self, context_hash: &ContextHash) -> Result<ContextDiff, ContextError>;
 
     /// Commit new generated context diff to storage
     /// if parent_context_hash is empty, it means that its a commit_genesis a we dont assign context_hash to header
+    ///
+    /// Diff is committed even if the verification of the context hash fails,
+    /// see [is_verification_error](ContextError::is_verification_error).
     fn commit(&mut self, block_hash: &BlockHash, parent_context_hash: &Option<ContextHash>, new_context_hash: &ContextHash, context_diff: &ContextDiff) -> Result<(), ContextError>;
 
     /// Checks context and resolves keys to be delete a place them to diff, and also deletes keys from diff
-    fn delete_to_diff(&self, context_hash: &Option<ContextHash>, key_prefix_to_delete: &Vec<String>, context_diff: &mut ContextDiff) -> Result<(), ContextError>;
+    fn delete_to_diff(&self, context_hash: &Option<ContextHash>, key_prefix_to_delete: &Vec<String>, context_diff: &mut ContextDiff) -> Result<(), ContextError> {
+        ensure_eq_context_hash!(context_hash, &context_diff);
+        self.remove_recursively_to_diff(context_hash, key_prefix_to_delete, context_diff)
+    }
 
     /// Checks context and resolves keys to be delete a place them to diff, and also deletes keys from diff
-    fn remove_recursively_to_diff(&self, context_hash: &Option<ContextHash>, key_prefix_to_remove: &Vec<String>, context_diff: &mut ContextDiff) -> Result<(), ContextError>;
+    fn remove_recursively_to_diff(&self, context_hash: &Option<ContextHash>, key_prefix_to_remove: &Vec<String>, context_diff: &mut ContextDiff) -> Result<(), ContextError> {
+        ensure_eq_context_hash!(context_hash, &context_diff);
+
+        // at first remove keys from temp diff
+        let context_map_diff = &mut context_diff.diff;
+        context_map_diff.retain(|k, v| {
+            if key_starts_with(k, key_prefix_to_remove) == true {
+                match v {
+                    Bucket::Deleted => true, // deleted stays in diff, because of previous delete from parent context, see bellow
+                    _ => false
+                }
+            } else {
+                // else keep in diff
+                true
+            }
+        });
+
+        // remove all keys with prefix from actual/parent context
+        let context = self.get_by_key_prefix(&context_diff.predecessor_index, key_prefix_to_remove)?;
+        if context.is_some() {
+            let context = context.unwrap();
+            for key in context.keys() {
+                context_map_diff.insert(key.clone(), Bucket::Deleted);
+            }
+        }
+
+        Ok(())
+    }
 
     /// Checks context and copies subtree under 'from_key' to new subtree under 'to_key'
-    fn copy_to_diff(&self, context_hash: &Option<ContextHash>, from_key: &Vec<String>, to_key: &Vec<String>, context_diff: &mut ContextDiff) -> Result<(), ContextError>;
+    fn copy_to_diff(&self, context_hash: &Option<ContextHash>, from_key: &Vec<String>, to_key: &Vec<String>, context_diff: &mut ContextDiff) -> Result<(), ContextError> {
+        ensure_eq_context_hash!(context_hash, &context_diff);
 
+        // get keys from actual/parent context
+        let mut final_context_to_copy = self.get_by_key_prefix(&context_diff.predecessor_index, from_key)?.unwrap_or(ContextMap::default());
+
+        // merge the same keys from diff to final context
+        for (key, bucket) in &context_diff.diff {
+            if key_starts_with(key, from_key) == true {
+                match bucket {
+                    Bucket::Exists(_) => final_context_to_copy.insert(key.clone(), bucket.clone()),
+                    | Bucket::Deleted => final_context_to_copy.remove(key),
+                };
+            }
+        }
+
+        // now we can copy to_key destination
+        for (key, bucket) in final_context_to_copy {
+            match bucket {
+                Bucket::Exists(_) => {
+                    let destination_key = replace_key(&key, from_key, to_key);
+                    context_diff.diff.insert(destination_key, bucket.clone());
+                    ()
+                }
+                _ => ()
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Get value of the key from the checkouted context
     fn get_key(&self, context_index: &ContextIndex, key: &Vec<String>) -> Result<Option<Bucket<Vec<u8>>>, ContextError>;
+
+    /// Get all keys (and their values) with the prefix from the checkouted context
+    fn get_by_key_prefix(&self, context_index: &ContextIndex, key: &Vec<String>) -> Result<Option<ContextMap>, ContextError>;
+
+    /// Get the whole checkouted context
+    fn get_context(&self, context_index: &ContextIndex) -> Result<Option<ContextMap>, ContextError>;
+
+    /// Check context hash calculated by protocol against the hash of the committed context.
+    ///
+    /// Result of the block application and the commit of the context are received independently,
+    /// so the check is done as soon as both of them are available.
+    fn verify_commit(&mut self, context_hash: &ContextHash, commit_info: CommitInfo) -> Result<(), ContextError>;
+
+    /// Context hash of the first commit, which failed the verification, no matter if it was detected by
+    /// the [commit](ContextApi::commit) or by the [verify_commit](ContextApi::verify_commit).
+    fn verification_failure(&self) -> Option<ContextHash>;
+}
+
+/// Context storage backends, which can be selected at startup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextBackend {
+    /// Context diffs are stored in the database backed skip list
+    SkipList,
+    /// Context of every commit is kept in memory, nothing survives restart of the node
+    InMemory,
+}
+
+impl ContextBackend {
+    /// Create new context stored in this backend
+    pub fn create(&self, persistent_storage: &PersistentStorage) -> ContextApiRef {
+        match self {
+            ContextBackend::SkipList => Arc::new(RwLock::new(TezedgeContext::new(BlockStorage::new(persistent_storage), persistent_storage.context_storage(), ContextListIndex::new(persistent_storage)))),
+            ContextBackend::InMemory => Arc::new(RwLock::new(InMemoryContext::new(BlockStorage::new(persistent_storage)))),
+        }
+    }
+}
+
+impl FromStr for ContextBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "skip-list" => Ok(ContextBackend::SkipList),
+            "in-memory" => Ok(ContextBackend::InMemory),
+            _ => Err(format!("Unsupported variant: {}", s))
+        }
+    }
 }
 
 fn to_key(key: &Vec<String>) -> String {
@@ -98,15 +245,16 @@ fn replace_key(key: &String, matched: &Vec<String>, replacer: &Vec<String>) -> S
     key.replace(&to_key(matched), &to_key(replacer))
 }
 
-/// Struct points to context commmit hash or index, which is checkouted
+/// Struct points to context commmit hash, which is checkouted.
+///
+/// Commits are identified only by their context hash, because blocks of different branches can have the same level.
 pub struct ContextIndex {
-    level: Option<usize>,
     pub context_hash: Option<ContextHash>,
 }
 
 impl ContextIndex {
-    pub fn new(level: Option<usize>, context_hash: Option<ContextHash>) -> Self {
-        ContextIndex { level, context_hash }
+    pub fn new(context_hash: Option<ContextHash>) -> Self {
+        ContextIndex { context_hash }
     }
 }
 
@@ -117,9 +265,9 @@ pub struct ContextDiff {
 }
 
 impl ContextDiff {
-    pub fn new(predecessor_level: Option<usize>, predecessor_context_hash: Option<ContextHash>, diff: ContextMap) -> Self {
+    pub fn new(predecessor_context_hash: Option<ContextHash>, diff: ContextMap) -> Self {
         ContextDiff {
-            predecessor_index: ContextIndex::new(predecessor_level, predecessor_context_hash),
+            predecessor_index: ContextIndex::new(predecessor_context_hash),
             diff,
         }
     }
@@ -133,61 +281,239 @@ impl ContextDiff {
     }
 }
 
-/// Actual context implementation with context skip list
+/// Actual context implementation with context skip list.
+///
+/// Skip list grows only at its end, but blocks of a fork are applied on top of an older commit.
+/// So every commit is pushed as a diff against the last commit in the list and its position is indexed by the context hash.
 pub struct TezedgeContext {
     block_storage: BlockStorage,
     storage: ContextList,
+    index: ContextListIndex,
+    verifier: CommitVerifier,
 }
 
 impl TezedgeContext {
-    pub fn new(block_storage: BlockStorage, storage: ContextList) -> Self {
-        TezedgeContext { block_storage, storage }
+    pub fn new(block_storage: BlockStorage, storage: ContextList, index: ContextListIndex) -> Self {
+        TezedgeContext { block_storage, storage, index, verifier: CommitVerifier::default() }
     }
 
-    fn level_by_context_hash(&self, context_hash: &ContextHash) -> Result<usize, ContextError> {
-        // find block header by context_hash, we need block level
-        let block = self.block_storage
-            .get_by_context_hash(context_hash)
-            .map_err(|e| ContextError::ReadBlockError { context_hash: HashType::ContextHash.bytes_to_string(context_hash), error: e })?;
-        if block.is_none() {
-            return Err(ContextError::UnknownContextHashError { context_hash: HashType::ContextHash.bytes_to_string(context_hash) });
+    /// Resolve position of the commit in the skip list by its context hash
+    fn list_index(&self, context_hash: &ContextHash) -> Result<usize, ContextError> {
+        self.index.get(context_hash)
+            .map_err(|error| ContextError::IndexError { context_hash: HashType::ContextHash.bytes_to_string(context_hash), error })?
+            .ok_or_else(|| ContextError::UnknownContextHashError { context_hash: HashType::ContextHash.bytes_to_string(context_hash) })
+    }
+
+    /// Resolve position of the checkouted context in the skip list
+    fn checkouted_index(&self, context_index: &ContextIndex) -> Result<Option<usize>, ContextError> {
+        context_index.context_hash.as_ref()
+            .map(|context_hash| self.list_index(context_hash))
+            .transpose()
+    }
+}
+
+impl ContextApi for TezedgeContext {
+    fn checkout(&self, context_hash: &ContextHash) -> Result<ContextDiff, ContextError> {
+        // fail early, if the context is not known
+        self.list_index(context_hash)?;
+
+        Ok(
+            ContextDiff::new(
+                Some(context_hash.clone()),
+                Default::default(),
+            )
+        )
+    }
+
+    fn commit(&mut self, block_hash: &BlockHash, parent_context_hash: &Option<ContextHash>, new_context_hash: &ContextHash, context_diff: &ContextDiff) -> Result<(), ContextError> {
+        ensure_eq_context_hash!(parent_context_hash, &context_diff);
+
+        let parent_index = self.checkouted_index(&context_diff.predecessor_index)?;
+        let known_commit = self.index.get(new_context_hash)
+            .map_err(|error| ContextError::IndexError { context_hash: HashType::ContextHash.bytes_to_string(new_context_hash), error })?
+            .is_some();
+
+        // whole context of the parent is needed only if its merkle tree is not retained anymore (e.g. after restart)
+        let parent_context = if self.verifier.has_tree(parent_context_hash) {
+            None
+        } else {
+            self.get_context(&context_diff.predecessor_index)?
+        };
+
+        // the same context hash means the same context (e.g. block applied again), so it is not stored twice
+        if !known_commit {
+            let mut writer = self.storage.write().expect("lock poisoning");
+            let last_index = writer.len().checked_sub(1);
+            if parent_index == last_index {
+                writer.push(&context_diff.diff)?;
+            } else {
+                // parent is not the last commit, so the diff is rebased on top of the last commit (e.g. block of a fork)
+                let last_context = match last_index {
+                    Some(last_index) => writer.get(last_index).map_err(|error| ContextError::ContextReadError { error })?.unwrap_or_default(),
+                    None => ContextMap::default(),
+                };
+                let parent_context = match parent_index {
+                    Some(parent_index) => writer.get(parent_index).map_err(|error| ContextError::ContextReadError { error })?.unwrap_or_default(),
+                    None => ContextMap::default(),
+                };
+                writer.push(&rebase_diff(&last_context, parent_context, &context_diff.diff))?;
+            }
+            self.index.put(new_context_hash, writer.len() - 1)
+                .map_err(|error| ContextError::IndexError { context_hash: HashType::ContextHash.bytes_to_string(new_context_hash), error })?;
         }
-        let block = block.unwrap();
-        Ok(block.header.level() as usize)
+
+        // associate block and context_hash
+        assign_to_context(&mut self.block_storage, block_hash, parent_context_hash, new_context_hash)?;
+
+        self.verifier.committed(new_context_hash, parent_context_hash, &context_diff.diff, parent_context)
+    }
+
+    fn get_key(&self, context_index: &ContextIndex, key: &Vec<String>) -> Result<Option<Bucket<Vec<u8>>>, ContextError> {
+        let index = match self.checkouted_index(context_index)? {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        let list = self.storage.read().expect("lock poisoning");
+        list
+            .get_key(index, &to_key(key))
+            .map_err(|se| ContextError::ContextReadError { error: se })
     }
 
     fn get_by_key_prefix(&self, context_index: &ContextIndex, key: &Vec<String>) -> Result<Option<ContextMap>, ContextError> {
-        if context_index.context_hash.is_none() && context_index.level.is_none() {
-            return Ok(None);
-        }
+        let index = match self.checkouted_index(context_index)? {
+            Some(index) => index,
+            None => return Ok(None),
+        };
 
-        // TODO: should be based just on context hash
-        let level = if let Some(context_index_level) = context_index.level {
-            context_index_level
-        } else {
-            self.level_by_context_hash(context_index.context_hash.as_ref().unwrap())?
+        let list = self.storage.read().expect("lock poisoning");
+        list
+            .get_prefix(index, &to_key(key))
+            .map_err(|se| ContextError::ContextReadError { error: se })
+    }
+
+    fn get_context(&self, context_index: &ContextIndex) -> Result<Option<ContextMap>, ContextError> {
+        let index = match self.checkouted_index(context_index)? {
+            Some(index) => index,
+            None => return Ok(None),
         };
 
         let list = self.storage.read().expect("lock poisoning");
         list
-            .get_prefix(level, &to_key(key))
+            .get(index)
             .map_err(|se| ContextError::ContextReadError { error: se })
     }
+
+    fn verify_commit(&mut self, context_hash: &ContextHash, commit_info: CommitInfo) -> Result<(), ContextError> {
+        self.verifier.expect(context_hash, commit_info)
+    }
+
+    fn verification_failure(&self) -> Option<ContextHash> {
+        self.verifier.failure.clone()
+    }
 }
 
-impl ContextApi for TezedgeContext {
-    fn init_from_start(&self) -> ContextDiff {
-        ContextDiff::new(None, None, Default::default())
+/// Diff, which turns the `last_context` into the `parent_context` with the applied `diff`.
+///
+/// Keys of the `last_context`, which are missing in the result, are marked as deleted.
+fn rebase_diff(last_context: &ContextMap, parent_context: ContextMap, diff: &ContextMap) -> ContextMap {
+    let mut context = parent_context;
+    context.extend(diff.iter().map(|(key, bucket)| (key.clone(), bucket.clone())));
+
+    let mut rebased: ContextMap = last_context.keys()
+        .filter(|key| !context.contains_key(*key))
+        .map(|key| (key.clone(), Bucket::Deleted))
+        .collect();
+    rebased.extend(context.into_iter().filter(|(key, bucket)| last_context.get(key) != Some(bucket)));
+    rebased
+}
+
+pub type ContextListIndexKV = dyn KeyValueStoreWithSchema<ContextListIndex> + Sync + Send;
+
+/// Index of the commits in the context skip list by their context hash
+#[derive(Clone)]
+pub struct ContextListIndex {
+    kv: Arc<ContextListIndexKV>,
+}
+
+impl ContextListIndex {
+    pub fn new(persistent_storage: &PersistentStorage) -> Self {
+        ContextListIndex { kv: persistent_storage.kv() }
+    }
+
+    pub fn put(&self, context_hash: &ContextHash, index: usize) -> Result<(), StorageError> {
+        self.kv.put(context_hash, &index)
+            .map_err(StorageError::from)
+    }
+
+    pub fn get(&self, context_hash: &ContextHash) -> Result<Option<usize>, StorageError> {
+        self.kv.get(context_hash)
+            .map_err(StorageError::from)
     }
+}
+
+impl KeyValueSchema for ContextListIndex {
+    type Key = ContextHash;
+    type Value = usize;
 
+    #[inline]
+    fn name() -> &'static str {
+        "context_list_index_storage"
+    }
+}
+
+/// Index commits of the database created before the [ContextListIndex] existed.
+///
+/// Commits were pushed to the skip list at the index equal to the level of their block.
+pub fn index_stored_commits(persistent_storage: &PersistentStorage) -> Result<(), StorageError> {
+    let block_storage = BlockStorage::new(persistent_storage);
+    let index = ContextListIndex::new(persistent_storage);
+    let len = persistent_storage.context_storage().read().expect("lock poisoning").len();
+    for (context_hash, block) in block_storage.iter_by_context_hash()? {
+        let level = block.header.level() as usize;
+        if level < len {
+            index.put(&context_hash, level)?;
+        }
+    }
+    Ok(())
+}
+
+/// In-memory context implementation, which holds the whole context for every recent commit.
+///
+/// Useful for tests and for comparison with other implementations, but it is not suitable for long running nodes,
+/// only the last [MAX_IN_MEMORY_COMMITS] commits are kept.
+pub struct InMemoryContext {
+    block_storage: BlockStorage,
+    /// Context of recent commits by their context hash
+    commits: BoundedMap<ContextMap>,
+    verifier: CommitVerifier,
+}
+
+impl InMemoryContext {
+    pub fn new(block_storage: BlockStorage) -> Self {
+        InMemoryContext { block_storage, commits: BoundedMap::new(MAX_IN_MEMORY_COMMITS), verifier: CommitVerifier::default() }
+    }
+
+    /// Find context of the checkouted commit
+    fn commit_context(&self, context_index: &ContextIndex) -> Result<Option<&ContextMap>, ContextError> {
+        match &context_index.context_hash {
+            Some(context_hash) => self.commits.get(context_hash)
+                .map(Some)
+                .ok_or_else(|| ContextError::UnknownContextHashError { context_hash: HashType::ContextHash.bytes_to_string(context_hash) }),
+            None => Ok(None),
+        }
+    }
+}
+
+impl ContextApi for InMemoryContext {
     fn checkout(&self, context_hash: &ContextHash) -> Result<ContextDiff, ContextError> {
-        // TODO: should be based just on context hash
-        let level = self.level_by_context_hash(&context_hash)?;
+        let context_index = ContextIndex::new(Some(context_hash.clone()));
+        // fail early, if the context is not known
+        self.commit_context(&context_index)?;
 
         Ok(
             ContextDiff::new(
-                Some(level),
-                Some(context_hash.clone()),
+                context_index.context_hash,
                 Default::default(),
             )
         )
@@ -196,121 +522,206 @@ impl ContextApi for TezedgeContext {
     fn commit(&mut self, block_hash: &BlockHash, parent_context_hash: &Option<ContextHash>, new_context_hash: &ContextHash, context_diff: &ContextDiff) -> Result<(), ContextError> {
         ensure_eq_context_hash!(parent_context_hash, &context_diff);
 
-        // add to context
-        let mut writer = self.storage.write().expect("lock poisoning");
-        // TODO: push to correct index by context_hash found by block_hash
-        writer.push(&context_diff.diff)?;
+        // apply diff to the context of the predecessor, deleted keys are kept as in the skip list
+        let parent_context = self.commit_context(&context_diff.predecessor_index)?.cloned();
+        let mut context = parent_context.clone().unwrap_or_default();
+        context.extend(context_diff.diff.iter().map(|(key, bucket)| (key.clone(), bucket.clone())));
+        self.commits.insert(new_context_hash.clone(), context);
 
         // associate block and context_hash
-        if let Err(e) = self.block_storage.assign_to_context(block_hash, new_context_hash) {
-            match e {
-                StorageError::MissingKey => {
-                    if parent_context_hash.is_some() {
-                        return Err(
-                            ContextError::ContextHashAssignError {
-                                block_hash: HashType::BlockHash.bytes_to_string(block_hash),
-                                context_hash: HashType::ContextHash.bytes_to_string(new_context_hash),
-                                error: e,
-                            }
-                        );
-                    } else {
-                        // if parent_context_hash is empty, means it is commit_genesis, and block is not already stored, thats ok
-                        ()
-                    }
-                }
-                _ => return Err(
-                    ContextError::ContextHashAssignError {
-                        block_hash: HashType::BlockHash.bytes_to_string(block_hash),
-                        context_hash: HashType::ContextHash.bytes_to_string(new_context_hash),
-                        error: e,
-                    }
-                )
-            };
-        }
+        assign_to_context(&mut self.block_storage, block_hash, parent_context_hash, new_context_hash)?;
 
-        Ok(())
+        let parent_context = if self.verifier.has_tree(parent_context_hash) { None } else { parent_context };
+        self.verifier.committed(new_context_hash, parent_context_hash, &context_diff.diff, parent_context)
     }
 
-    fn delete_to_diff(&self, context_hash: &Option<ContextHash>, key_prefix_to_delete: &Vec<String>, context_diff: &mut ContextDiff) -> Result<(), ContextError> {
-        ensure_eq_context_hash!(context_hash, &context_diff);
-        self.remove_recursively_to_diff(context_hash, key_prefix_to_delete, context_diff)
+    fn get_key(&self, context_index: &ContextIndex, key: &Vec<String>) -> Result<Option<Bucket<Vec<u8>>>, ContextError> {
+        Ok(
+            self.commit_context(context_index)?
+                .and_then(|context| context.get(&to_key(key)).cloned())
+        )
     }
 
-    fn remove_recursively_to_diff(&self, context_hash: &Option<ContextHash>, key_prefix_to_remove: &Vec<String>, context_diff: &mut ContextDiff) -> Result<(), ContextError> {
-        ensure_eq_context_hash!(context_hash, &context_diff);
+    fn get_by_key_prefix(&self, context_index: &ContextIndex, key: &Vec<String>) -> Result<Option<ContextMap>, ContextError> {
+        Ok(
+            self.commit_context(context_index)?
+                .map(|context| context.iter()
+                    .filter(|(k, _)| key_starts_with(k, key))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect())
+        )
+    }
 
-        // at first remove keys from temp diff
-        let context_map_diff = &mut context_diff.diff;
-        context_map_diff.retain(|k, v| {
-            if key_starts_with(k, key_prefix_to_remove) == true {
-                match v {
-                    Bucket::Deleted => true, // deleted stays in diff, because of previous delete from parent context, see bellow
-                    _ => false
-                }
-            } else {
-                // else keep in diff
-                true
-            }
-        });
+    fn get_context(&self, context_index: &ContextIndex) -> Result<Option<ContextMap>, ContextError> {
+        Ok(self.commit_context(context_index)?.cloned())
+    }
 
-        // remove all keys with prefix from actual/parent context
-        let context = self.get_by_key_prefix(&context_diff.predecessor_index, key_prefix_to_remove)?;
-        if context.is_some() {
-            let context = context.unwrap();
-            for key in context.keys() {
-                context_map_diff.insert(key.clone(), Bucket::Deleted);
-            }
+    fn verify_commit(&mut self, context_hash: &ContextHash, commit_info: CommitInfo) -> Result<(), ContextError> {
+        self.verifier.expect(context_hash, commit_info)
+    }
+
+    fn verification_failure(&self) -> Option<ContextHash> {
+        self.verifier.failure.clone()
+    }
+}
+
+/// Pairs commits of the context with commit infos of applied blocks and checks their context hashes.
+///
+/// Context hash is calculated incrementally, merkle tree of the commit is built from the tree of its parent and the diff.
+/// Genesis commit (without parent) is not verified, because it is not a result of the block application.
+struct CommitVerifier {
+    /// Merkle trees of recent commits
+    trees: BoundedMap<MerkleTree>,
+    /// Root hashes (and parents) of committed contexts, which are waiting for the commit info
+    committed: BoundedMap<(Option<ContextHash>, Result<EntryHash, MerkleError>)>,
+    /// Commit infos of applied blocks, which are waiting for the commit of the context
+    expected: BoundedMap<CommitInfo>,
+    /// First commit, which failed the verification
+    failure: Option<ContextHash>,
+}
+
+impl Default for CommitVerifier {
+    fn default() -> Self {
+        CommitVerifier {
+            trees: BoundedMap::new(MAX_RETAINED_MERKLE_TREES),
+            committed: BoundedMap::new(MAX_PENDING_VERIFICATIONS),
+            expected: BoundedMap::new(MAX_PENDING_VERIFICATIONS),
+            failure: None,
         }
+    }
+}
 
-        Ok(())
+impl CommitVerifier {
+    /// Returns `true` if the merkle tree of the parent commit is available, so its whole context is not needed
+    fn has_tree(&self, parent_context_hash: &Option<ContextHash>) -> bool {
+        match parent_context_hash {
+            Some(parent_context_hash) => self.trees.get(parent_context_hash).is_some(),
+            None => true,
+        }
     }
 
-    fn copy_to_diff(&self, context_hash: &Option<ContextHash>, from_key: &Vec<String>, to_key: &Vec<String>, context_diff: &mut ContextDiff) -> Result<(), ContextError> {
-        ensure_eq_context_hash!(context_hash, &context_diff);
+    /// Hash new commit, `parent_context` is required only if the parent merkle tree is not available, see [has_tree](CommitVerifier::has_tree)
+    fn committed(&mut self, context_hash: &ContextHash, parent_context_hash: &Option<ContextHash>, diff: &ContextMap, parent_context: Option<ContextMap>) -> Result<(), ContextError> {
+        let parent_tree = match parent_context_hash.as_ref().and_then(|parent_context_hash| self.trees.get(parent_context_hash)) {
+            Some(parent_tree) => Ok(parent_tree.clone()),
+            None => MerkleTree::from_context(&parent_context.unwrap_or_default()),
+        };
+        let tree = parent_tree.and_then(|parent_tree| parent_tree.apply(diff));
+        if let Ok(tree) = &tree {
+            self.trees.insert(context_hash.clone(), tree.clone());
+        }
 
-        // get keys from actual/parent context
-        let mut final_context_to_copy = self.get_by_key_prefix(&context_diff.predecessor_index, from_key)?.unwrap_or(ContextMap::default());
+        if parent_context_hash.is_none() {
+            return Ok(());
+        }
 
-        // merge the same keys from diff to final context
-        for (key, bucket) in &context_diff.diff {
-            if key_starts_with(key, from_key) == true {
-                match bucket {
-                    Bucket::Exists(_) => final_context_to_copy.insert(key.clone(), bucket.clone()),
-                    | Bucket::Deleted => final_context_to_copy.remove(key),
-                };
+        let root_hash = tree.map(|tree| tree.hash());
+        match self.expected.remove(context_hash) {
+            Some(commit_info) => self.verify(context_hash, parent_context_hash, root_hash, &commit_info),
+            None => {
+                self.committed.insert(context_hash.clone(), (parent_context_hash.clone(), root_hash));
+                Ok(())
             }
         }
+    }
 
-        // now we can copy to_key destination
-        for (key, bucket) in final_context_to_copy {
-            match bucket {
-                Bucket::Exists(_) => {
-                    let destination_key = replace_key(&key, from_key, to_key);
-                    context_diff.diff.insert(destination_key, bucket.clone());
-                    ()
-                }
-                _ => ()
-            };
+    fn expect(&mut self, context_hash: &ContextHash, commit_info: CommitInfo) -> Result<(), ContextError> {
+        match self.committed.remove(context_hash) {
+            Some((parent_context_hash, root_hash)) => self.verify(context_hash, &parent_context_hash, root_hash, &commit_info),
+            None => {
+                self.expected.insert(context_hash.clone(), commit_info);
+                Ok(())
+            }
         }
+    }
 
-        Ok(())
+    fn verify(&mut self, context_hash: &ContextHash, parent_context_hash: &Option<ContextHash>, root_hash: Result<EntryHash, MerkleError>, commit_info: &CommitInfo) -> Result<(), ContextError> {
+        let result = verify_context_hash(context_hash, parent_context_hash, root_hash, commit_info);
+        if result.is_err() && self.failure.is_none() {
+            self.failure = Some(context_hash.clone());
+        }
+        result
     }
+}
 
-    fn get_key(&self, context_index: &ContextIndex, key: &Vec<String>) -> Result<Option<Bucket<Vec<u8>>>, ContextError> {
-        if context_index.context_hash.is_none() && context_index.level.is_none() {
-            return Ok(None);
+/// Map with a limited capacity, the oldest entry is evicted, when the capacity is exceeded
+struct BoundedMap<V> {
+    capacity: usize,
+    entries: HashMap<ContextHash, V>,
+    order: VecDeque<ContextHash>,
+}
+
+impl<V> BoundedMap<V> {
+    fn new(capacity: usize) -> Self {
+        BoundedMap { capacity, entries: HashMap::new(), order: VecDeque::new() }
+    }
+
+    fn get(&self, key: &ContextHash) -> Option<&V> {
+        self.entries.get(key)
+    }
+
+    fn insert(&mut self, key: ContextHash, value: V) {
+        if self.entries.insert(key.clone(), value).is_none() {
+            self.order.push_back(key);
+            if self.order.len() > self.capacity {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.entries.remove(&oldest);
+                }
+            }
         }
+    }
 
-        // TODO: should be based just on context hash
-        let level = if let Some(context_index_level) = context_index.level {
-            context_index_level
-        } else {
-            self.level_by_context_hash(context_index.context_hash.as_ref().unwrap())?
-        };
+    fn remove(&mut self, key: &ContextHash) -> Option<V> {
+        let value = self.entries.remove(key)?;
+        self.order.retain(|k| k != key);
+        Some(value)
+    }
+}
 
-        let list = self.storage.read().expect("lock poisoning");
-        list
-            .get_key(level, &to_key(key))
-            .map_err(|se| ContextError::ContextReadError { error: se })
+fn verify_context_hash(context_hash: &ContextHash, parent_context_hash: &Option<ContextHash>, root_hash: Result<EntryHash, MerkleError>, commit_info: &CommitInfo) -> Result<(), ContextError> {
+    let root_hash = root_hash.map_err(|error| ContextError::MerkleHashError {
+        context_hash: HashType::ContextHash.bytes_to_string(context_hash),
+        error,
+    })?;
+
+    let calculated_context_hash = hash_commit(&root_hash, parent_context_hash, commit_info);
+    if &calculated_context_hash == context_hash {
+        Ok(())
+    } else {
+        Err(ContextError::ContextHashMismatchError {
+            context_hash: HashType::ContextHash.bytes_to_string(context_hash),
+            calculated_context_hash: HashType::ContextHash.bytes_to_string(&calculated_context_hash),
+        })
     }
-}
\ No newline at end of file
+}
+
+/// Associate block with the new context hash
+fn assign_to_context(block_storage: &mut BlockStorage, block_hash: &BlockHash, parent_context_hash: &Option<ContextHash>, new_context_hash: &ContextHash) -> Result<(), ContextError> {
+    if let Err(e) = block_storage.assign_to_context(block_hash, new_context_hash) {
+        match e {
+            StorageError::MissingKey => {
+                if parent_context_hash.is_some() {
+                    return Err(
+                        ContextError::ContextHashAssignError {
+                            block_hash: HashType::BlockHash.bytes_to_string(block_hash),
+                            context_hash: HashType::ContextHash.bytes_to_string(new_context_hash),
+                            error: e,
+                        }
+                    );
+                } else {
+                    // if parent_context_hash is empty, means it is commit_genesis, and block is not already stored, thats ok
+                    ()
+                }
+            }
+            _ => return Err(
+                ContextError::ContextHashAssignError {
+                    block_hash: HashType::BlockHash.bytes_to_string(block_hash),
+                    context_hash: HashType::ContextHash.bytes_to_string(new_context_hash),
+                    error: e,
+                }
+            )
+        };
+    }
+
+    Ok(())
+}
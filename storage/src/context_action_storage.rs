@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: MIT
 
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::mem;
 use std::ops::Range;
 use std::sync::Arc;
@@ -18,6 +19,7 @@ use crate::num_from_slice;
 use crate::persistent::{CommitLogSchema, CommitLogWithSchema, Decoder, Encoder, KeyValueSchema, KeyValueStoreWithSchema, Location, PersistentStorage, SchemaError};
 use crate::persistent::codec::{range_from_idx_len, vec_from_slice};
 use crate::persistent::commit_log::fold_consecutive_locations;
+use crate::persistent::database::IteratorMode;
 use crate::persistent::sequence::{SequenceGenerator, SequenceNumber};
 use crate::StorageError;
 
@@ -63,6 +65,42 @@ impl ContextActionStorage {
             .and_then(|locations| self.get_records_by_locations(&locations))
     }
 
+    /// Delete all actions of the block from indexes, so they cannot be retrieved anymore.
+    pub fn delete_by_block_hash(&mut self, block_hash: &BlockHash) -> Result<(), StorageError> {
+        for record in self.get_by_block_hash(block_hash)? {
+            for contract_address in extract_contract_addresses(&record) {
+                self.context_by_contract_index.delete(&ContextActionByContractIndexKey::new(&contract_address, record.id()))?;
+            }
+            self.context_primary_index.delete(&ContextActionPrimaryIndexKey::new(block_hash, record.id()))?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the commit log, so it contains only actions present in the primary index.
+    ///
+    /// Must not be called while the storage is used by anyone else, because old locations become invalid.
+    pub fn compact_commit_log(&mut self) -> Result<(), StorageError> {
+        let actions = self.context_primary_index.iter()?;
+        let mut locations: Vec<Location> = actions.iter().map(|(_, location)| *location).collect();
+        locations.sort();
+        locations.dedup();
+        let relocated: HashMap<Location, Location> = locations.iter().cloned()
+            .zip(self.clog.rewrite(&locations)?)
+            .collect();
+
+        for (key, location) in actions {
+            self.context_primary_index.put(&key, relocated.get(&location).ok_or(StorageError::MissingKey)?)?;
+        }
+        for (key, location) in self.context_by_contract_index.iter()? {
+            match relocated.get(&location) {
+                Some(location) => self.context_by_contract_index.put(&key, location)?,
+                None => self.context_by_contract_index.delete(&key)?,
+            }
+        }
+
+        Ok(())
+    }
+
     #[inline]
     pub fn get_by_contract_address(&self, contract_address: &ContractAddress, from_id: Option<SequenceNumber>, limit: usize) -> Result<Vec<ContextActionRecordValue>, StorageError> {
         self.context_by_contract_index.get_by_contract_address(contract_address, from_id, limit)
@@ -200,6 +238,12 @@ impl ContextActionPrimaryIndex {
             .map_err(StorageError::from)
     }
 
+    #[inline]
+    fn delete(&mut self, key: &ContextActionPrimaryIndexKey) -> Result<(), StorageError> {
+        self.kv.delete(key)
+            .map_err(StorageError::from)
+    }
+
     #[inline]
     fn get_by_block_hash(&self, block_hash: &BlockHash) -> Result<Vec<Location>, StorageError> {
         let key = ContextActionPrimaryIndexKey::from_block_hash_prefix(block_hash);
@@ -207,6 +251,12 @@ impl ContextActionPrimaryIndex {
             .map(|(_, value)| value.map_err(StorageError::from))
             .collect()
     }
+
+    fn iter(&self) -> Result<Vec<(ContextActionPrimaryIndexKey, Location)>, StorageError> {
+        self.kv.iterator(IteratorMode::Start)?
+            .map(|(key, location)| key.and_then(|key| location.map(|location| (key, location))).map_err(StorageError::from))
+            .collect()
+    }
 }
 
 impl KeyValueSchema for ContextActionPrimaryIndex {
@@ -310,6 +360,17 @@ impl ContextActionByContractIndex {
         self.kv.put(key, value).map_err(StorageError::from)
     }
 
+    #[inline]
+    fn delete(&mut self, key: &ContextActionByContractIndexKey) -> Result<(), StorageError> {
+        self.kv.delete(key).map_err(StorageError::from)
+    }
+
+    fn iter(&self) -> Result<Vec<(ContextActionByContractIndexKey, Location)>, StorageError> {
+        self.kv.iterator(IteratorMode::Start)?
+            .map(|(key, location)| key.and_then(|key| location.map(|location| (key, location))).map_err(StorageError::from))
+            .collect()
+    }
+
     #[inline]
     fn get_by_contract_address(&self, contract_address: &ContractAddress, from_id: Option<SequenceNumber>, limit: usize) -> Result<Vec<Location>, StorageError> {
         let iterate_from_key = from_id
@@ -21,9 +21,11 @@ pub use crate::block_storage::{BlockAdditionalData, BlockAdditionalDataBuilder,
 pub use crate::context_action_storage::{ContextActionPrimaryIndexKey, ContextActionRecordValue, ContextActionStorage};
 pub use crate::operations_meta_storage::{OperationsMetaStorage, OperationsMetaStorageKV};
 pub use crate::operations_storage::{OperationKey, OperationsStorage, OperationsStorageKV, OperationsStorageReader};
+pub use crate::protocol_storage::ProtocolStorage;
 use crate::persistent::{CommitLogError, DBError, Decoder, Encoder, SchemaError};
 pub use crate::persistent::database::{Direction, IteratorMode};
 use crate::persistent::sequence::SequenceError;
+pub use crate::peer_blacklist_storage::{PeerBan, PeerBlacklistStorage};
 pub use crate::system_storage::SystemStorage;
 use std::path::PathBuf;
 
@@ -34,9 +36,15 @@ pub mod block_storage;
 pub mod block_meta_storage;
 pub mod context_action_storage;
 pub mod p2p_message_storage;
+pub mod peer_blacklist_storage;
+pub mod protocol_storage;
 pub mod system_storage;
 pub mod skip_list;
 pub mod context;
+pub mod merkle_hash;
+pub mod snapshot;
+pub mod pruning;
+pub mod migration;
 
 /// Extension of block header with block hash
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
@@ -97,6 +105,15 @@ pub enum StorageError {
     TezosEnvironmentError {
         error: TezosEnvironmentError
     },
+    #[fail(display = "Data of the block at level {} were pruned, only blocks above level {} are available", level, pruned_level)]
+    PrunedError {
+        level: i32,
+        pruned_level: i32,
+    },
+    #[fail(display = "Message hash error: {}", error)]
+    MessageHashError {
+        error: MessageHashError
+    },
 }
 
 impl From<DBError> for StorageError {
@@ -117,6 +134,12 @@ impl From<SchemaError> for StorageError {
     }
 }
 
+impl From<MessageHashError> for StorageError {
+    fn from(error: MessageHashError) -> Self {
+        StorageError::MessageHashError { error }
+    }
+}
+
 impl From<SequenceError> for StorageError {
     fn from(error: SequenceError) -> Self {
         StorageError::SequenceError { error }
@@ -195,7 +218,7 @@ pub fn store_applied_block_result(
         .build().unwrap();
     block_storage.put_block_additional_data(&block_hash, block_additional_data.clone())?;
 
-    // TODO: check context checksum or context_hash
+    // context hash is verified against the committed context, see `ContextApi::verify_commit`
 
     // if everything is stored and ok, we can considere this block as applied
     // mark current head as applied
@@ -287,6 +310,8 @@ pub mod tests_common {
     use failure::Error;
 
     use crate::block_storage;
+    use crate::context::ContextListIndex;
+    use crate::p2p_message_storage::{P2PMessageSecondaryIndex, P2PMessageStorage};
     use crate::persistent::*;
     use crate::persistent::sequence::Sequences;
     use crate::skip_list::{DatabaseBackedSkipList, Lane, ListValue};
@@ -312,14 +337,20 @@ pub mod tests_common {
                 block_storage::BlockByContextHashIndex::descriptor(),
                 BlockMetaStorage::descriptor(),
                 OperationsStorage::descriptor(),
+                operations_storage::OperationsByHashIndex::descriptor(),
                 OperationsMetaStorage::descriptor(),
                 context_action_storage::ContextActionPrimaryIndex::descriptor(),
                 context_action_storage::ContextActionByContractIndex::descriptor(),
                 SystemStorage::descriptor(),
+                PeerBlacklistStorage::descriptor(),
+                ProtocolStorage::descriptor(),
                 Sequences::descriptor(),
                 DatabaseBackedSkipList::descriptor(),
+                ContextListIndex::descriptor(),
                 Lane::descriptor(),
                 ListValue::descriptor(),
+                P2PMessageStorage::descriptor(),
+                P2PMessageSecondaryIndex::descriptor(),
             ])?;
             let clog = open_cl(&path, vec![
                 BlockStorage::descriptor(),
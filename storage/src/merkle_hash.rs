@@ -0,0 +1,377 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Irmin compatible hashing of the context.
+//!
+//! Context is hashed as a tree, where values are blobs (leafs) and every key segment is a node.
+//! Context hash is the hash of the commit, which is calculated from the hash of the root node,
+//! the hash of the parent commit and the commit info (date, author and message),
+//! so it can be compared with the context hash calculated by the OCaml node.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use failure::Fail;
+
+use crypto::blake2b;
+use crypto::hash::ContextHash;
+
+use crate::persistent::ContextMap;
+use crate::skip_list::Bucket;
+
+/// Hash of the blob, node or commit
+pub type EntryHash = Vec<u8>;
+
+/// Length of all hashes (blake2b 256)
+const HASH_LEN: usize = 32;
+
+/// Author of every commit of the Tezos context
+pub const COMMIT_AUTHOR: &str = "Tezos";
+
+/// Possible errors for merkle hashing
+#[derive(Debug, Fail)]
+pub enum MerkleError {
+    #[fail(display = "Key is used for a value and for a subtree at the same time, key: {}", key)]
+    KeyConflictError {
+        key: String,
+    },
+}
+
+/// Info of the commit, which is part of the commit hash
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommitInfo {
+    /// Commit date, Tezos uses the timestamp of the block
+    pub date: i64,
+    pub author: String,
+    pub message: String,
+}
+
+impl CommitInfo {
+    /// Info of the commit created by Tezos node
+    pub fn new(date: i64, message: String) -> Self {
+        CommitInfo { date, author: COMMIT_AUTHOR.to_string(), message }
+    }
+}
+
+/// Persistent tree of the context hashes, every commit produces a new tree, which shares unchanged subtrees with its parent.
+///
+/// Hash of every node is cached, so only nodes on the paths of changed keys are hashed again.
+#[derive(Clone)]
+pub struct MerkleTree {
+    root: Arc<Node>,
+}
+
+/// Node of the context tree, entries are sorted by their names as in Irmin
+struct Node {
+    entries: BTreeMap<String, Entry>,
+    hash: EntryHash,
+}
+
+#[derive(Clone)]
+enum Entry {
+    Blob(EntryHash),
+    Tree(Arc<Node>),
+}
+
+/// Changes of the diff grouped by the key segments
+#[derive(Default)]
+struct Change<'a> {
+    /// Change of the value stored under the key: `Some(None)` if the value was deleted, `None` if it was not changed
+    value: Option<Option<&'a [u8]>>,
+    /// Changes of the subtree under the key
+    subtree: BTreeMap<&'a str, Change<'a>>,
+}
+
+impl Default for MerkleTree {
+    fn default() -> Self {
+        MerkleTree { root: Arc::new(Node::new(BTreeMap::new())) }
+    }
+}
+
+impl MerkleTree {
+    /// Build tree of the whole context, deleted keys are not part of the tree
+    pub fn from_context(context: &ContextMap) -> Result<Self, MerkleError> {
+        MerkleTree::default().apply(context)
+    }
+
+    /// Hash of the root node
+    pub fn hash(&self) -> EntryHash {
+        self.root.hash.clone()
+    }
+
+    /// Create new tree by applying the diff of the commit, only changed nodes are hashed again
+    pub fn apply(&self, diff: &ContextMap) -> Result<Self, MerkleError> {
+        let mut changes = Change::default();
+        for (key, bucket) in diff {
+            let change = key.split('/').fold(&mut changes, |change, segment| change.subtree.entry(segment).or_default());
+            change.value = match bucket {
+                Bucket::Exists(value) => Some(Some(value.as_slice())),
+                _ => Some(None),
+            };
+        }
+
+        let root = update_node(Some(&self.root), &changes.subtree)?.unwrap_or_else(|| Arc::new(Node::new(BTreeMap::new())));
+        Ok(MerkleTree { root })
+    }
+}
+
+impl Node {
+    fn new(entries: BTreeMap<String, Entry>) -> Self {
+        let hash = hash_node(&entries);
+        Node { entries, hash }
+    }
+}
+
+/// Apply changes to the node, `None` is returned if the node is empty after the changes, because Irmin does not store empty nodes
+fn update_node(node: Option<&Arc<Node>>, changes: &BTreeMap<&str, Change>) -> Result<Option<Arc<Node>>, MerkleError> {
+    let mut entries = node.map(|node| node.entries.clone()).unwrap_or_default();
+    for (&segment, change) in changes {
+        let (blob, subtree) = match entries.remove(segment) {
+            Some(Entry::Blob(hash)) => (Some(hash), None),
+            Some(Entry::Tree(subtree)) => (None, Some(subtree)),
+            None => (None, None),
+        };
+
+        let blob = match change.value {
+            Some(value) => value.map(hash_blob),
+            None => blob,
+        };
+        let subtree = if change.subtree.is_empty() {
+            subtree
+        } else {
+            update_node(subtree.as_ref(), &change.subtree)?
+        };
+
+        match (blob, subtree) {
+            (Some(_), Some(_)) => return Err(MerkleError::KeyConflictError { key: segment.to_string() }),
+            (Some(hash), None) => {
+                entries.insert(segment.to_string(), Entry::Blob(hash));
+            }
+            (None, Some(subtree)) => {
+                entries.insert(segment.to_string(), Entry::Tree(subtree));
+            }
+            (None, None) => (),
+        }
+    }
+
+    if entries.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(Arc::new(Node::new(entries))))
+    }
+}
+
+/// Hash of the node is calculated as:
+/// `<number of entries (8 bytes)>` followed by every entry encoded as
+/// `<kind (8 bytes)><name length (leb128)><name><hash length (8 bytes)><hash>`
+fn hash_node(entries: &BTreeMap<String, Entry>) -> EntryHash {
+    let mut data = Vec::new();
+    data.extend_from_slice(&(entries.len() as u64).to_be_bytes());
+    for (name, entry) in entries {
+        let entry_hash = match entry {
+            Entry::Blob(hash) => {
+                data.extend_from_slice(&[0xff, 0, 0, 0, 0, 0, 0, 0]);
+                hash
+            }
+            Entry::Tree(node) => {
+                data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+                &node.hash
+            }
+        };
+        write_leb128(&mut data, name.len() as u64);
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(&(HASH_LEN as u64).to_be_bytes());
+        data.extend_from_slice(entry_hash);
+    }
+    blake2b::digest_256(&data)
+}
+
+/// Hash of the blob is calculated as `<value length (8 bytes)><value>`
+pub fn hash_blob(value: &[u8]) -> EntryHash {
+    let mut data = Vec::with_capacity(8 + value.len());
+    data.extend_from_slice(&(value.len() as u64).to_be_bytes());
+    data.extend_from_slice(value);
+    blake2b::digest_256(&data)
+}
+
+/// Calculate hash of the root node of the whole context, deleted keys are not part of the tree
+pub fn hash_context(context: &ContextMap) -> Result<EntryHash, MerkleError> {
+    MerkleTree::from_context(context).map(|tree| tree.hash())
+}
+
+/// Hash of the commit is calculated as:
+/// `<hash length (8 bytes)><root hash>`
+/// `<number of parents (8 bytes)>` followed by `<hash length (8 bytes)><parent hash>` for the parent
+/// `<date (8 bytes)><author length (8 bytes)><author><message length (8 bytes)><message>`
+pub fn hash_commit(root_hash: &EntryHash, parent_context_hash: &Option<ContextHash>, info: &CommitInfo) -> ContextHash {
+    let mut data = Vec::new();
+    data.extend_from_slice(&(root_hash.len() as u64).to_be_bytes());
+    data.extend_from_slice(root_hash);
+
+    match parent_context_hash {
+        Some(parent_context_hash) => {
+            data.extend_from_slice(&1u64.to_be_bytes());
+            data.extend_from_slice(&(parent_context_hash.len() as u64).to_be_bytes());
+            data.extend_from_slice(parent_context_hash);
+        }
+        None => data.extend_from_slice(&0u64.to_be_bytes()),
+    }
+
+    data.extend_from_slice(&(info.date as u64).to_be_bytes());
+    data.extend_from_slice(&(info.author.len() as u64).to_be_bytes());
+    data.extend_from_slice(info.author.as_bytes());
+    data.extend_from_slice(&(info.message.len() as u64).to_be_bytes());
+    data.extend_from_slice(info.message.as_bytes());
+    blake2b::digest_256(&data)
+}
+
+fn write_leb128(data: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            data.push(byte);
+            break;
+        }
+        data.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(entries: Vec<(&str, Bucket<Vec<u8>>)>) -> ContextMap {
+        entries.into_iter()
+            .map(|(key, bucket)| (key.to_string(), bucket))
+            .collect()
+    }
+
+    #[test]
+    fn test_hash_blob() {
+        let expected = hex::decode("69e2c536c5a64cd7c282a38033956f3d65ef936f5880353643e8e6349f9b182e").unwrap();
+        assert_eq!(expected, hash_blob(&[1, 2, 3]));
+    }
+
+    #[test]
+    fn test_hash_context() {
+        let ctx = context(vec![
+            ("data/a", Bucket::Exists(vec![1])),
+            ("data/b/c", Bucket::Exists(vec![2, 3])),
+            ("protocol", Bucket::Exists(vec![4])),
+            ("data/deleted", Bucket::Deleted),
+        ]);
+        let expected = hex::decode("b597e7b10a062a99b84dff9bd364b71b085c884216f6b7e4e864360490ab80aa").unwrap();
+        assert_eq!(expected, hash_context(&ctx).unwrap());
+
+        // deleted keys and order of insertion do not change the hash
+        let same_ctx = context(vec![
+            ("protocol", Bucket::Exists(vec![4])),
+            ("data/b/c", Bucket::Exists(vec![2, 3])),
+            ("data/a", Bucket::Exists(vec![1])),
+        ]);
+        assert_eq!(expected, hash_context(&same_ctx).unwrap());
+    }
+
+    #[test]
+    fn test_hash_empty_context() {
+        let expected = hex::decode("81e47a19e6b29b0a65b9591762ce5143ed30d0261e5d24a3201752506b20f15c").unwrap();
+        assert_eq!(expected, hash_context(&ContextMap::default()).unwrap());
+    }
+
+    #[test]
+    fn test_hash_context_with_key_conflict() {
+        let ctx = context(vec![
+            ("data/a", Bucket::Exists(vec![1])),
+            ("data/a/b", Bucket::Exists(vec![2])),
+        ]);
+        assert!(hash_context(&ctx).is_err());
+    }
+
+    #[test]
+    fn test_apply_diff_to_merkle_tree() {
+        let parent = context(vec![
+            ("data/a", Bucket::Exists(vec![1])),
+            ("data/b/c", Bucket::Exists(vec![2, 3])),
+            ("data/b/d", Bucket::Exists(vec![5])),
+            ("protocol", Bucket::Exists(vec![4])),
+        ]);
+        let parent_tree = MerkleTree::from_context(&parent).unwrap();
+
+        let diff = context(vec![
+            ("data/a", Bucket::Deleted),
+            ("data/b/c", Bucket::Exists(vec![9])),
+            ("data/b/d", Bucket::Deleted),
+            ("data/e/f", Bucket::Exists(vec![6])),
+            ("data/unknown", Bucket::Deleted),
+        ]);
+        let expected = context(vec![
+            ("data/b/c", Bucket::Exists(vec![9])),
+            ("data/e/f", Bucket::Exists(vec![6])),
+            ("protocol", Bucket::Exists(vec![4])),
+        ]);
+        assert_eq!(hash_context(&expected).unwrap(), parent_tree.apply(&diff).unwrap().hash());
+
+        // parent tree is not changed by the diff
+        assert_eq!(hash_context(&parent).unwrap(), parent_tree.hash());
+
+        // empty subtree is removed and its key can be used for a value
+        let diff = context(vec![
+            ("data/b/c", Bucket::Deleted),
+            ("data/b/d", Bucket::Deleted),
+            ("data/b", Bucket::Exists(vec![7])),
+        ]);
+        let expected = context(vec![
+            ("data/a", Bucket::Exists(vec![1])),
+            ("data/b", Bucket::Exists(vec![7])),
+            ("protocol", Bucket::Exists(vec![4])),
+        ]);
+        assert_eq!(hash_context(&expected).unwrap(), parent_tree.apply(&diff).unwrap().hash());
+
+        // deleted value can be replaced by a subtree
+        let diff = context(vec![
+            ("data/a", Bucket::Deleted),
+            ("data/a/x", Bucket::Exists(vec![8])),
+        ]);
+        let tree = parent_tree.apply(&diff).unwrap();
+        let expected = context(vec![
+            ("data/a/x", Bucket::Exists(vec![8])),
+            ("data/b/c", Bucket::Exists(vec![2, 3])),
+            ("data/b/d", Bucket::Exists(vec![5])),
+            ("protocol", Bucket::Exists(vec![4])),
+        ]);
+        assert_eq!(hash_context(&expected).unwrap(), tree.hash());
+
+        // deleting everything results in the empty tree
+        let diff = expected.keys().map(|key| (key.as_str(), Bucket::Deleted)).collect();
+        assert_eq!(hash_context(&ContextMap::default()).unwrap(), tree.apply(&context(diff)).unwrap().hash());
+    }
+
+    #[test]
+    fn test_apply_diff_with_key_conflict() {
+        let parent_tree = MerkleTree::from_context(&context(vec![("data/a", Bucket::Exists(vec![1]))])).unwrap();
+        assert!(parent_tree.apply(&context(vec![("data/a/b", Bucket::Exists(vec![2]))])).is_err());
+        assert!(parent_tree.apply(&context(vec![("data", Bucket::Exists(vec![2]))])).is_err());
+    }
+
+    #[test]
+    fn test_hash_commit() {
+        let root_hash = vec![1; HASH_LEN];
+        let parent_context_hash = Some(vec![2; HASH_LEN]);
+        let info = CommitInfo::new(1_588_000_000, "lvl 1, fit 1:1, prio 0, 0 ops".to_string());
+
+        let expected = hex::decode("de37c9a74060bf921758ae2ab4c9484a5a1f5858753d9bea50ea32fbb7738f4b").unwrap();
+        assert_eq!(expected, hash_commit(&root_hash, &parent_context_hash, &info));
+
+        let expected_genesis = hex::decode("401fb4fce587e696d8c8c0710d4b60d8ef8be7c51dc324730c32112f3ec7afbb").unwrap();
+        assert_eq!(expected_genesis, hash_commit(&root_hash, &None, &CommitInfo::new(0, "Genesis".to_string())));
+    }
+
+    #[test]
+    fn test_write_leb128() {
+        let mut data = Vec::new();
+        write_leb128(&mut data, 5);
+        write_leb128(&mut data, 300);
+        assert_eq!(vec![0x05, 0xac, 0x02], data);
+    }
+}
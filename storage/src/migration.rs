@@ -0,0 +1,202 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Migrations of the database schema between database versions.
+//!
+//! Every migration step upgrades the database from one version to the next one.
+//! Version of the database is stored in the [SystemStorage] after every finished step,
+//! so the migration can be resumed after a crash. Step which was interrupted is run again,
+//! so every step has to be idempotent.
+
+use failure::Fail;
+use slog::{info, Logger, warn};
+
+use crate::persistent::PersistentStorage;
+use crate::StorageError;
+use crate::system_storage::{DbVersion, SystemStorage};
+
+/// Function, which migrates the database by a single version
+pub type MigrationFn = fn(&PersistentStorage) -> Result<(), StorageError>;
+
+/// Possible errors for migrations
+#[derive(Debug, Fail)]
+pub enum MigrationError {
+    #[fail(display = "Storage error: {}", error)]
+    StorageError {
+        error: StorageError
+    },
+    #[fail(display = "Migration {} -> {} does not follow the previous migration to version {}", from_version, to_version, previous_version)]
+    InvalidOrder {
+        from_version: DbVersion,
+        to_version: DbVersion,
+        previous_version: DbVersion,
+    },
+    #[fail(display = "Database version {} is newer than the supported version {}", db_version, supported_version)]
+    NewerDatabase {
+        db_version: DbVersion,
+        supported_version: DbVersion,
+    },
+    #[fail(display = "No migration from database version {} to version {} is available", from_version, to_version)]
+    MissingMigration {
+        from_version: DbVersion,
+        to_version: DbVersion,
+    },
+    #[fail(display = "Migration {} -> {} ({}) failed: {}", from_version, to_version, name, error)]
+    MigrationFailed {
+        from_version: DbVersion,
+        to_version: DbVersion,
+        name: &'static str,
+        error: StorageError,
+    },
+}
+
+impl From<StorageError> for MigrationError {
+    fn from(error: StorageError) -> Self {
+        MigrationError::StorageError { error }
+    }
+}
+
+impl slog::Value for MigrationError {
+    fn serialize(&self, _record: &slog::Record, key: slog::Key, serializer: &mut dyn slog::Serializer) -> slog::Result {
+        serializer.emit_arguments(key, &format_args!("{}", self))
+    }
+}
+
+/// Single migration step
+#[derive(Clone)]
+pub struct Migration {
+    pub from_version: DbVersion,
+    pub to_version: DbVersion,
+    /// Short description of the migration used in logs
+    pub name: &'static str,
+    migrate: MigrationFn,
+}
+
+/// Ordered list of all migration steps
+#[derive(Clone, Default)]
+pub struct MigrationRegistry {
+    migrations: Vec<Migration>,
+}
+
+impl MigrationRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the next migration step, steps has to be registered in order of database versions
+    pub fn register(mut self, from_version: DbVersion, to_version: DbVersion, name: &'static str, migrate: MigrationFn) -> Result<Self, MigrationError> {
+        if let Some(previous) = self.migrations.last() {
+            if previous.to_version != from_version {
+                return Err(MigrationError::InvalidOrder { from_version, to_version, previous_version: previous.to_version });
+            }
+        }
+        if to_version <= from_version {
+            return Err(MigrationError::InvalidOrder { from_version, to_version, previous_version: from_version });
+        }
+
+        self.migrations.push(Migration { from_version, to_version, name, migrate });
+        Ok(self)
+    }
+
+    /// Resolve steps, which are needed to migrate database from `from_version` to `to_version`
+    pub fn plan(&self, from_version: DbVersion, to_version: DbVersion) -> Result<Vec<&Migration>, MigrationError> {
+        let mut current_version = from_version;
+        let steps: Vec<&Migration> = self.migrations.iter()
+            .skip_while(|migration| migration.from_version != from_version)
+            .take_while(|migration| migration.to_version <= to_version)
+            .inspect(|migration| current_version = migration.to_version)
+            .collect();
+
+        if current_version == to_version {
+            Ok(steps)
+        } else {
+            Err(MigrationError::MissingMigration { from_version: current_version, to_version })
+        }
+    }
+
+    /// Migrate database to the `to_version`, returns number of applied steps.
+    ///
+    /// In the `dry_run` mode steps are only logged and the database is not touched.
+    pub fn migrate(&self, persistent_storage: &PersistentStorage, to_version: DbVersion, dry_run: bool, log: &Logger) -> Result<usize, MigrationError> {
+        let mut system_storage = SystemStorage::new(persistent_storage.kv());
+        let from_version = match system_storage.get_db_version()? {
+            Some(db_version) => db_version,
+            // new database does not need any migration
+            None => return Ok(0),
+        };
+        if from_version > to_version {
+            return Err(MigrationError::NewerDatabase { db_version: from_version, supported_version: to_version });
+        }
+        if let Some(interrupted_version) = system_storage.get_migration_in_progress()? {
+            warn!(log, "Previous migration was interrupted, it will be run again"; "from_version" => from_version, "to_version" => interrupted_version);
+        }
+
+        let steps = self.plan(from_version, to_version)?;
+        for (idx, step) in steps.iter().enumerate() {
+            let progress = format!("{}/{}", idx + 1, steps.len());
+            if dry_run {
+                info!(log, "Migration would run"; "progress" => progress, "from_version" => step.from_version, "to_version" => step.to_version, "name" => step.name);
+                continue;
+            }
+
+            info!(log, "Running migration"; "progress" => &progress, "from_version" => step.from_version, "to_version" => step.to_version, "name" => step.name);
+            system_storage.set_migration_in_progress(Some(step.to_version))?;
+            (step.migrate)(persistent_storage)
+                .map_err(|error| MigrationError::MigrationFailed { from_version: step.from_version, to_version: step.to_version, name: step.name, error })?;
+            system_storage.set_db_version(step.to_version)?;
+            system_storage.set_migration_in_progress(None)?;
+            info!(log, "Migration finished"; "progress" => progress, "db_version" => step.to_version);
+        }
+
+        Ok(steps.len())
+    }
+}
+
+/// All migrations of the database, new migrations has to be registered here, when `DATABASE_VERSION` is increased
+pub fn migrations() -> MigrationRegistry {
+    MigrationRegistry::new()
+        .register(12, 13, "store all successors of blocks", crate::block_meta_storage::upgrade_legacy_metas)
+        .and_then(|registry| registry.register(13, 14, "index operations by hash", crate::operations_storage::index_stored_operations))
+        .and_then(|registry| registry.register(14, 15, "index context commits by context hash", crate::context::index_stored_commits))
+        .expect("Migrations are not registered in order")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noop(_: &PersistentStorage) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_in_order() {
+        assert!(MigrationRegistry::new()
+            .register(1, 2, "first", noop)
+            .and_then(|registry| registry.register(2, 3, "second", noop))
+            .is_ok());
+        assert!(MigrationRegistry::new()
+            .register(1, 2, "first", noop)
+            .and_then(|registry| registry.register(3, 4, "gap", noop))
+            .is_err());
+        assert!(MigrationRegistry::new().register(2, 1, "downgrade", noop).is_err());
+    }
+
+    #[test]
+    fn test_plan() -> Result<(), MigrationError> {
+        let registry = MigrationRegistry::new()
+            .register(1, 2, "first", noop)?
+            .register(2, 3, "second", noop)?
+            .register(3, 5, "third", noop)?;
+
+        let names = |steps: Vec<&Migration>| steps.iter().map(|step| step.name).collect::<Vec<_>>();
+        assert_eq!(vec!["first", "second", "third"], names(registry.plan(1, 5)?));
+        assert_eq!(vec!["second"], names(registry.plan(2, 3)?));
+        assert!(registry.plan(5, 5)?.is_empty());
+        assert!(registry.plan(1, 4).is_err());
+        assert!(registry.plan(0, 5).is_err());
+        assert!(registry.plan(5, 6).is_err());
+
+        Ok(())
+    }
+}
@@ -79,6 +79,12 @@ impl OperationsMetaStorage {
             .map_err(StorageError::from)
     }
 
+    #[inline]
+    pub fn delete(&mut self, block_hash: &BlockHash) -> Result<(), StorageError> {
+        self.kv.delete(block_hash)
+            .map_err(StorageError::from)
+    }
+
     #[inline]
     pub fn contains(&self, block_hash: &BlockHash) -> Result<bool, StorageError> {
         self.kv.contains(block_hash)
@@ -5,12 +5,12 @@ use std::sync::Arc;
 
 use rocksdb::{ColumnFamilyDescriptor, Options, SliceTransform};
 
-use crypto::hash::{BlockHash, HashType};
-use tezos_messages::p2p::binary_message::BinaryMessage;
+use crypto::hash::{BlockHash, HashType, OperationHash};
+use tezos_messages::p2p::binary_message::{BinaryMessage, MessageHash};
 use tezos_messages::p2p::encoding::prelude::*;
 
 use crate::persistent::{Decoder, Encoder, KeyValueSchema, KeyValueStoreWithSchema, PersistentStorage, SchemaError};
-use crate::StorageError;
+use crate::{IteratorMode, StorageError};
 
 pub type OperationsStorageKV = dyn KeyValueStoreWithSchema<OperationsStorage> + Sync + Send;
 
@@ -18,16 +18,23 @@ pub trait OperationsStorageReader: Sync + Send {
     fn get(&self, key: &OperationKey) -> Result<Option<OperationsForBlocksMessage>, StorageError>;
 
     fn get_operations(&self, block_hash: &BlockHash) -> Result<Vec<OperationsForBlocksMessage>, StorageError>;
+
+    /// Find stored operation by its hash
+    fn find_operation(&self, operation_hash: &OperationHash) -> Result<Option<Operation>, StorageError>;
 }
 
 #[derive(Clone)]
 pub struct OperationsStorage {
-    kv: Arc<OperationsStorageKV>
+    kv: Arc<OperationsStorageKV>,
+    hash_index: OperationsByHashIndex,
 }
 
 impl OperationsStorage {
     pub fn new(persistent_storage: &PersistentStorage) -> Self {
-        Self { kv: persistent_storage.kv() }
+        Self {
+            kv: persistent_storage.kv(),
+            hash_index: OperationsByHashIndex::new(persistent_storage.kv()),
+        }
     }
 
     #[inline]
@@ -41,9 +48,38 @@ impl OperationsStorage {
 
     #[inline]
     pub fn put(&mut self, key: &OperationKey, value: &OperationsForBlocksMessage) -> Result<(), StorageError> {
+        self.index_operations(key, value)?;
         self.kv.put(key, value)
             .map_err(StorageError::from)
     }
+
+    /// Index all operations of the validation pass by their hash
+    pub fn index_operations(&mut self, key: &OperationKey, value: &OperationsForBlocksMessage) -> Result<(), StorageError> {
+        for operation in value.operations() {
+            self.hash_index.put(&operation.message_hash()?, key)?;
+        }
+        Ok(())
+    }
+
+    /// Delete operations of all validation passes of the block
+    pub fn delete_operations(&mut self, block_hash: &BlockHash) -> Result<(), StorageError> {
+        let key = OperationKey {
+            block_hash: block_hash.clone(),
+            validation_pass: 0
+        };
+
+        let operations = self.kv.prefix_iterator(&key)?
+            .map(|(key, value)| Ok((key?, value?)))
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        for (key, value) in operations {
+            for operation in value.operations() {
+                self.hash_index.delete(&operation.message_hash()?)?;
+            }
+            self.kv.delete(&key)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl OperationsStorageReader for OperationsStorage {
@@ -70,6 +106,25 @@ impl OperationsStorageReader for OperationsStorage {
 
         Ok(operations)
     }
+
+    fn find_operation(&self, operation_hash: &OperationHash) -> Result<Option<Operation>, StorageError> {
+        let key = match self.hash_index.get(operation_hash)? {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+
+        match self.get(&key)? {
+            Some(operations) => {
+                for operation in operations.operations() {
+                    if &operation.message_hash()? == operation_hash {
+                        return Ok(Some(operation.clone()));
+                    }
+                }
+                Ok(None)
+            }
+            None => Ok(None)
+        }
+    }
 }
 
 impl KeyValueSchema for OperationsStorage {
@@ -89,6 +144,61 @@ impl KeyValueSchema for OperationsStorage {
     }
 }
 
+pub type OperationsByHashIndexKV = dyn KeyValueStoreWithSchema<OperationsByHashIndex> + Sync + Send;
+
+/// Index of the stored operations by the operation hash
+#[derive(Clone)]
+pub struct OperationsByHashIndex {
+    kv: Arc<OperationsByHashIndexKV>
+}
+
+impl OperationsByHashIndex {
+    fn new(kv: Arc<OperationsByHashIndexKV>) -> Self {
+        Self { kv }
+    }
+
+    #[inline]
+    fn put(&mut self, operation_hash: &OperationHash, key: &OperationKey) -> Result<(), StorageError> {
+        self.kv.put(operation_hash, key)
+            .map_err(StorageError::from)
+    }
+
+    #[inline]
+    fn get(&self, operation_hash: &OperationHash) -> Result<Option<OperationKey>, StorageError> {
+        self.kv.get(operation_hash)
+            .map_err(StorageError::from)
+    }
+
+    #[inline]
+    fn delete(&mut self, operation_hash: &OperationHash) -> Result<(), StorageError> {
+        self.kv.delete(operation_hash)
+            .map_err(StorageError::from)
+    }
+}
+
+impl KeyValueSchema for OperationsByHashIndex {
+    type Key = OperationHash;
+    type Value = OperationKey;
+
+    #[inline]
+    fn name() -> &'static str {
+        "operations_by_hash_index"
+    }
+}
+
+/// Build index of the operations by their hash for the database, which was created before the index existed
+pub fn index_stored_operations(persistent_storage: &PersistentStorage) -> Result<(), StorageError> {
+    let kv: Arc<OperationsStorageKV> = persistent_storage.kv();
+    let mut hash_index = OperationsByHashIndex::new(persistent_storage.kv());
+    for (key, value) in kv.iterator(IteratorMode::Start)? {
+        let key = key?;
+        for operation in value?.operations() {
+            hash_index.put(&operation.message_hash()?, &key)?;
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, PartialEq)]
 pub struct OperationKey {
     block_hash: BlockHash,
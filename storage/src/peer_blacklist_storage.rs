@@ -0,0 +1,118 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use getset::{CopyGetters, Getters};
+use serde::{Deserialize, Serialize};
+
+use crate::{IteratorMode, StorageError};
+use crate::persistent::{BincodeEncoded, KeyValueSchema, KeyValueStoreWithSchema, PersistentStorage};
+
+pub type PeerBlacklistStorageKV = dyn KeyValueStoreWithSchema<PeerBlacklistStorage> + Sync + Send;
+
+/// Stores banned IP addresses, so the bans survive restarts of the node.
+#[derive(Clone)]
+pub struct PeerBlacklistStorage {
+    kv: Arc<PeerBlacklistStorageKV>
+}
+
+impl PeerBlacklistStorage {
+    pub fn new(persistent_storage: &PersistentStorage) -> Self {
+        PeerBlacklistStorage { kv: persistent_storage.kv() }
+    }
+
+    #[inline]
+    pub fn put(&mut self, address: &IpAddr, ban: &PeerBan) -> Result<(), StorageError> {
+        self.kv.put(address, ban)
+            .map_err(StorageError::from)
+    }
+
+    #[inline]
+    pub fn get(&self, address: &IpAddr) -> Result<Option<PeerBan>, StorageError> {
+        self.kv.get(address)
+            .map_err(StorageError::from)
+    }
+
+    #[inline]
+    pub fn delete(&mut self, address: &IpAddr) -> Result<(), StorageError> {
+        self.kv.delete(address)
+            .map_err(StorageError::from)
+    }
+
+    /// Returns all banned addresses, including the expired bans
+    pub fn get_all(&self) -> Result<Vec<(IpAddr, PeerBan)>, StorageError> {
+        self.kv.iterator(IteratorMode::Start)?
+            .map(|(address, ban)| Ok((address?, ban?)))
+            .collect()
+    }
+}
+
+impl KeyValueSchema for PeerBlacklistStorage {
+    type Key = IpAddr;
+    type Value = PeerBan;
+
+    #[inline]
+    fn name() -> &'static str {
+        "peer_blacklist_storage"
+    }
+}
+
+/// Ban of the peer address
+#[derive(Clone, Getters, CopyGetters, Serialize, Deserialize, PartialEq, Debug)]
+pub struct PeerBan {
+    /// Unix timestamp (in seconds) until which is the address banned
+    #[get_copy = "pub"]
+    banned_until: u64,
+    /// Human readable reason of the ban
+    #[get = "pub"]
+    reason: String,
+}
+
+impl PeerBan {
+    pub fn new(banned_until: u64, reason: String) -> Self {
+        PeerBan { banned_until, reason }
+    }
+
+    /// Check if the ban is still active at the unix timestamp `now` (in seconds)
+    #[inline]
+    pub fn is_active(&self, now: u64) -> bool {
+        self.banned_until > now
+    }
+}
+
+impl BincodeEncoded for IpAddr {}
+
+impl BincodeEncoded for PeerBan {}
+
+#[cfg(test)]
+mod tests {
+    use failure::Error;
+
+    use crate::tests_common::TmpStorage;
+
+    use super::*;
+
+    #[test]
+    fn test_put_get_delete() -> Result<(), Error> {
+        let tmp_storage = TmpStorage::create("__peer_blacklist_storage")?;
+        let mut storage = PeerBlacklistStorage::new(tmp_storage.storage());
+
+        let address: IpAddr = "192.168.1.10".parse()?;
+        let ban = PeerBan::new(1_000, "Invalid messages".to_string());
+        storage.put(&address, &ban)?;
+        storage.put(&"::1".parse()?, &PeerBan::new(2_000, "Timeouts".to_string()))?;
+
+        assert_eq!(Some(ban.clone()), storage.get(&address)?);
+        assert_eq!(2, storage.get_all()?.len());
+        assert!(ban.is_active(999));
+        assert!(!ban.is_active(1_000));
+
+        storage.delete(&address)?;
+        assert_eq!(None, storage.get(&address)?);
+        assert_eq!(1, storage.get_all()?.len());
+
+        Ok(())
+    }
+}
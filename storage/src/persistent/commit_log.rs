@@ -65,7 +65,7 @@ type ByteLimit = usize;
 type ItemCount = u32;
 
 /// Precisely identifies location of a record in a commit log.
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Location(Offset, ByteLimit);
 
 impl Location {
@@ -97,6 +97,12 @@ pub trait CommitLogWithSchema<S: CommitLogSchema> {
 
     /// Retrieve stored records stored in a single range.
     fn get_range(&self, range: &Range) -> Result<Vec<S::Value>, CommitLogError>;
+
+    /// Copy records at the given locations into a new commit log, which then replaces the current one.
+    ///
+    /// All records, which are not listed, are dropped. Returns new locations of the records in the same order.
+    /// Locations returned before the rewrite are invalid afterwards, so the caller has to update all references.
+    fn rewrite(&self, locations: &[Location]) -> Result<Vec<Location>, CommitLogError>;
 }
 
 
@@ -136,6 +142,52 @@ impl<S: CommitLogSchema> CommitLogWithSchema<S> for CommitLogs {
                 map_err(|_| CommitLogError::ReadError { error: ReadError::CorruptLog, location: Location(message.offset(), message.size() as usize) }))
             .collect()
     }
+
+    fn rewrite(&self, locations: &[Location]) -> Result<Vec<Location>, CommitLogError> {
+        let cl = self.cl_handle(S::name())
+            .ok_or(CommitLogError::MissingCommitLog { name: S::name() })?;
+        let mut cl = cl.write().expect("Write lock failed");
+
+        let path = self.base_path.join(S::name());
+        let rewrite_path = self.base_path.join(format!("{}.rewrite", S::name()));
+        let replaced_path = self.base_path.join(format!("{}.replaced", S::name()));
+        if rewrite_path.exists() {
+            std::fs::remove_dir_all(&rewrite_path)?;
+        }
+
+        let mut new_locations = Vec::with_capacity(locations.len());
+        {
+            let mut rewritten = open_commit_log(&rewrite_path)?;
+            for location in locations {
+                let msg_buf = cl.read(location.0, fit_read_limit(location.1))
+                    .map_err(|error| CommitLogError::ReadError { error, location: *location })?;
+                let message = msg_buf.iter().next().ok_or(CommitLogError::ReadError { error: ReadError::CorruptLog, location: *location })?;
+                let offset = rewritten.append_msg(message.payload())
+                    .map_err(|error| CommitLogError::AppendError { error })?;
+                new_locations.push(Location(offset, message.payload().len()));
+            }
+            rewritten.flush()?;
+        }
+
+        // swap directories and reopen the commit log from the new location
+        cl.flush()?;
+        std::fs::rename(&path, &replaced_path)?;
+        std::fs::rename(&rewrite_path, &path)?;
+        *cl = open_commit_log(&path)?;
+        std::fs::remove_dir_all(&replaced_path)?;
+
+        Ok(new_locations)
+    }
+}
+
+fn open_commit_log(path: &Path) -> Result<CommitLog, CommitLogError> {
+    if !path.exists() {
+        std::fs::create_dir_all(path)?;
+    }
+
+    let mut opts = LogOptions::new(path);
+    opts.message_max_bytes(10_000_000);
+    Ok(CommitLog::new(opts)?)
 }
 
 #[inline]
@@ -199,13 +251,13 @@ impl CommitLogs {
     /// Register a new commit log.
     fn register(&self, name: &str) -> Result<(), CommitLogError> {
         let path = self.base_path.join(name);
-        if !Path::new(&path).exists() {
-            std::fs::create_dir_all(&path)?;
+        // rewrite was interrupted before the new commit log took place of the old one
+        let replaced_path = self.base_path.join(format!("{}.replaced", name));
+        if !path.exists() && replaced_path.exists() {
+            std::fs::rename(&replaced_path, &path)?;
         }
 
-        let mut opts = LogOptions::new(&path);
-        opts.message_max_bytes(10_000_000);
-        let log = CommitLog::new(opts)?;
+        let log = open_commit_log(&path)?;
 
         let mut commit_log_map = self.commit_log_map.write().unwrap();
         commit_log_map.insert(name.into(), Arc::new(RwLock::new(log)));
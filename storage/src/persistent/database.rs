@@ -84,6 +84,9 @@ pub trait KeyValueStoreWithSchema<S: KeyValueSchema> {
     /// # Arguments
     /// * `key` - Key (specified by schema), to be checked for existence
     fn contains(&self, key: &S::Key) -> Result<bool, DBError>;
+
+    /// Compact whole column family, so the space occupied by deleted entries is reclaimed
+    fn compact(&self) -> Result<(), DBError>;
 }
 
 impl<S: KeyValueSchema> KeyValueStoreWithSchema<S> for DB {
@@ -167,6 +170,14 @@ impl<S: KeyValueSchema> KeyValueStoreWithSchema<S> for DB {
 
         Ok(contains)
     }
+
+    fn compact(&self) -> Result<(), DBError> {
+        let cf = self.cf_handle(S::name())
+            .ok_or(DBError::MissingColumnFamily { name: S::name() })?;
+
+        self.compact_range_cf(cf, None::<&[u8]>, None::<&[u8]>);
+        Ok(())
+    }
 }
 
 fn default_write_options() -> WriteOptions {
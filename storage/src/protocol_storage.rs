@@ -0,0 +1,166 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use failure::Fail;
+use serde::Deserialize;
+
+use crypto::hash::{HashType, ProtocolHash};
+use tezos_messages::p2p::binary_message::{BinaryMessage, MessageHash};
+use tezos_messages::p2p::encoding::prelude::*;
+
+use crate::persistent::{Decoder, Encoder, KeyValueSchema, KeyValueStoreWithSchema, PersistentStorage, SchemaError};
+use crate::StorageError;
+
+pub type ProtocolStorageKV = dyn KeyValueStoreWithSchema<ProtocolStorage> + Sync + Send;
+
+/// Name of the file, which describes the protocol in the directory with protocol sources
+const PROTOCOL_MANIFEST_FILE: &str = "TEZOS_PROTOCOL";
+
+/// Possible errors for loading of the protocol sources
+#[derive(Debug, Fail)]
+pub enum ProtocolSourceError {
+    #[fail(display = "Storage error: {}", error)]
+    StorageError {
+        error: StorageError
+    },
+    #[fail(display = "Failed to read protocol sources: {}", error)]
+    IOError {
+        error: std::io::Error
+    },
+    #[fail(display = "Invalid {} file: {}", PROTOCOL_MANIFEST_FILE, error)]
+    InvalidManifest {
+        error: serde_json::Error
+    },
+    #[fail(display = "Protocol hash mismatch, expected: {}, computed: {}", expected, computed)]
+    HashMismatch {
+        expected: String,
+        computed: String,
+    },
+}
+
+impl From<StorageError> for ProtocolSourceError {
+    fn from(error: StorageError) -> Self {
+        ProtocolSourceError::StorageError { error }
+    }
+}
+
+impl From<std::io::Error> for ProtocolSourceError {
+    fn from(error: std::io::Error) -> Self {
+        ProtocolSourceError::IOError { error }
+    }
+}
+
+impl From<serde_json::Error> for ProtocolSourceError {
+    fn from(error: serde_json::Error) -> Self {
+        ProtocolSourceError::InvalidManifest { error }
+    }
+}
+
+/// Content of the `TEZOS_PROTOCOL` file
+#[derive(Deserialize)]
+struct ProtocolManifest {
+    hash: String,
+    #[serde(default)]
+    expected_env_version: i16,
+    modules: Vec<String>,
+}
+
+/// Stores sources of the protocols, so they can be served to the peers requesting them with `GetProtocols`.
+#[derive(Clone)]
+pub struct ProtocolStorage {
+    kv: Arc<ProtocolStorageKV>
+}
+
+impl ProtocolStorage {
+    pub fn new(persistent_storage: &PersistentStorage) -> Self {
+        ProtocolStorage { kv: persistent_storage.kv() }
+    }
+
+    /// Store protocol under its hash, returns the hash of the protocol
+    pub fn put(&mut self, protocol: &Protocol) -> Result<ProtocolHash, StorageError> {
+        let protocol_hash = protocol.message_hash()?;
+        self.kv.put(&protocol_hash, protocol)?;
+        Ok(protocol_hash)
+    }
+
+    #[inline]
+    pub fn get(&self, protocol_hash: &ProtocolHash) -> Result<Option<Protocol>, StorageError> {
+        self.kv.get(protocol_hash)
+            .map_err(StorageError::from)
+    }
+
+    #[inline]
+    pub fn contains(&self, protocol_hash: &ProtocolHash) -> Result<bool, StorageError> {
+        self.kv.contains(protocol_hash)
+            .map_err(StorageError::from)
+    }
+
+    /// Store protocol from the directory with its sources, e.g. `src/proto_006_PsCARTHA/lib_protocol` of the Tezos repository.
+    ///
+    /// Directory contains the `TEZOS_PROTOCOL` file with the hash and the list of modules of the protocol.
+    /// Protocol is stored only if the hash computed from the sources matches the declared hash.
+    pub fn put_sources<P: AsRef<Path>>(&mut self, protocol_dir: P) -> Result<ProtocolHash, ProtocolSourceError> {
+        let protocol_dir = protocol_dir.as_ref();
+        let manifest: ProtocolManifest = serde_json::from_str(&fs::read_to_string(protocol_dir.join(PROTOCOL_MANIFEST_FILE))?)?;
+
+        let mut components = Vec::with_capacity(manifest.modules.len());
+        for module in manifest.modules {
+            let file_name = uncapitalize(&module);
+            let interface_path = protocol_dir.join(format!("{}.mli", file_name));
+            let interface = if interface_path.exists() {
+                Some(fs::read_to_string(interface_path)?)
+            } else {
+                None
+            };
+            let implementation = fs::read_to_string(protocol_dir.join(format!("{}.ml", file_name)))?;
+            components.push(Component::new(module, interface, implementation));
+        }
+
+        let protocol = Protocol::new(manifest.expected_env_version, components);
+        let computed = HashType::ProtocolHash.bytes_to_string(&protocol.message_hash().map_err(StorageError::from)?);
+        if computed != manifest.hash {
+            return Err(ProtocolSourceError::HashMismatch { expected: manifest.hash, computed });
+        }
+
+        Ok(self.put(&protocol)?)
+    }
+}
+
+/// Name of the source file of the OCaml module, e.g. `Apply_results` is in `apply_results.ml`
+fn uncapitalize(module: &str) -> String {
+    let mut chars = module.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+impl KeyValueSchema for ProtocolStorage {
+    type Key = ProtocolHash;
+    type Value = Protocol;
+
+    #[inline]
+    fn name() -> &'static str {
+        "protocol_storage"
+    }
+}
+
+impl Decoder for Protocol {
+    #[inline]
+    fn decode(bytes: &[u8]) -> Result<Self, SchemaError> {
+        Protocol::from_bytes(bytes.to_vec())
+            .map_err(|_| SchemaError::DecodeError)
+    }
+}
+
+impl Encoder for Protocol {
+    #[inline]
+    fn encode(&self) -> Result<Vec<u8>, SchemaError> {
+        self.as_bytes()
+            .map_err(|_| SchemaError::EncodeError)
+    }
+}
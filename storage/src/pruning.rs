@@ -0,0 +1,227 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Pruning of the historical block data.
+//!
+//! In the rolling history mode, operations, json data and context actions of old blocks are deleted.
+//! Block headers and metadata are kept for the blocks of the main chain, so the chain can still be traversed.
+//! Blocks of the abandoned branches, which forked from the pruned part of the chain, are deleted completely.
+//!
+//! Only levels below the last allowed fork level of the current head are pruned, so the main chain
+//! cannot change there anymore.
+//!
+//! Commit logs can only be appended, so pruned records are removed just from the indexes while the node runs.
+//! Their space is reclaimed by [StoragePruner::compact_commit_logs], which has to be run before the storage
+//! is used by other components, e.g. on the node startup.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use rocksdb::DB;
+
+use crypto::hash::BlockHash;
+
+use crate::{BlockMetaStorage, BlockMetaStorageReader, BlockStorage, BlockStorageReader, ContextActionStorage, OperationsMetaStorage, OperationsStorage, StorageError, SystemStorage};
+use crate::block_storage::{BlockByContextHashIndex, BlockByLevelIndex, BlockPrimaryIndex};
+use crate::context_action_storage::{ContextActionByContractIndex, ContextActionPrimaryIndex};
+use crate::operations_storage::OperationsByHashIndex;
+use crate::persistent::{KeyValueStoreWithSchema, PersistentStorage};
+
+/// How much of the block history is kept in the storage
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HistoryMode {
+    /// All block data are kept forever
+    Full,
+    /// Data of blocks older than given number of cycles are pruned
+    Rolling { cycles: u32 },
+}
+
+impl FromStr for HistoryMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_ascii_lowercase();
+        match s.as_str() {
+            "full" => Ok(HistoryMode::Full),
+            _ if s.starts_with("rolling:") => match s["rolling:".len()..].parse::<u32>() {
+                Ok(cycles) if cycles > 0 => Ok(HistoryMode::Rolling { cycles }),
+                _ => Err(format!("Invalid number of cycles: {}", s)),
+            }
+            _ => Err(format!("Unsupported variant: {}", s))
+        }
+    }
+}
+
+impl fmt::Display for HistoryMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryMode::Full => write!(f, "full"),
+            HistoryMode::Rolling { cycles } => write!(f, "rolling:{}", cycles),
+        }
+    }
+}
+
+/// Returns [StorageError::PrunedError] if data of the block at the `level` were already pruned
+pub fn ensure_not_pruned(system_storage: &SystemStorage, level: i32) -> Result<(), StorageError> {
+    match system_storage.get_pruned_level()? {
+        Some(pruned_level) if level <= pruned_level => Err(StorageError::PrunedError { level, pruned_level }),
+        _ => Ok(())
+    }
+}
+
+/// Deletes data of the blocks level by level, starting from the genesis.
+pub struct StoragePruner {
+    block_storage: BlockStorage,
+    block_meta_storage: BlockMetaStorage,
+    operations_storage: OperationsStorage,
+    operations_meta_storage: OperationsMetaStorage,
+    context_action_storage: ContextActionStorage,
+    system_storage: SystemStorage,
+    kv: Arc<DB>,
+    /// Blocks of the main chain by level, resolved from the current head
+    main_chain: BTreeMap<i32, BlockHash>,
+}
+
+impl StoragePruner {
+    pub fn new(persistent_storage: &PersistentStorage) -> Self {
+        Self {
+            block_storage: BlockStorage::new(persistent_storage),
+            block_meta_storage: BlockMetaStorage::new(persistent_storage),
+            operations_storage: OperationsStorage::new(persistent_storage),
+            operations_meta_storage: OperationsMetaStorage::new(persistent_storage),
+            context_action_storage: ContextActionStorage::new(persistent_storage),
+            system_storage: SystemStorage::new(persistent_storage.kv()),
+            kv: persistent_storage.kv(),
+            main_chain: BTreeMap::new(),
+        }
+    }
+
+    /// Highest level, which was already pruned
+    #[inline]
+    pub fn pruned_level(&self) -> Result<Option<i32>, StorageError> {
+        self.system_storage.get_pruned_level()
+    }
+
+    /// Last allowed fork level of the current head, levels below it can be pruned
+    pub fn last_allowed_fork_level(&self) -> Result<i32, StorageError> {
+        match self.block_meta_storage.load_current_head()? {
+            Some(current_head) => Ok(self.block_storage.get_with_additional_data(&current_head)?
+                .map(|(_, additional_data)| additional_data.last_allowed_fork_level())
+                .unwrap_or(0)),
+            None => Ok(0),
+        }
+    }
+
+    /// Prune operations, json data and context actions of the main chain block at the `level`
+    /// and delete branches forking from it.
+    ///
+    /// Levels has to be pruned in ascending order, because all levels up to the `level` are considered pruned afterwards.
+    /// The `level` has to be below the [last allowed fork level](StoragePruner::last_allowed_fork_level).
+    pub fn prune_level(&mut self, level: i32) -> Result<(), StorageError> {
+        if let Some(block_hash) = self.main_chain_block(level)? {
+            self.prune_block_data(&block_hash)?;
+
+            // successors, which are not on the main chain, are abandoned
+            let next_block_hash = self.main_chain_block(level + 1)?;
+            let successors = self.block_meta_storage.get(&block_hash)?
+                .map(|meta| meta.successors().clone())
+                .unwrap_or_default();
+            for successor in successors.iter().filter(|successor| Some(*successor) != next_block_hash.as_ref()) {
+                self.delete_branch(successor)?;
+                self.block_meta_storage.remove_successor(&block_hash, successor)?;
+            }
+        }
+        self.main_chain = self.main_chain.split_off(&(level + 1));
+        self.system_storage.set_pruned_level(level)
+    }
+
+    /// Reclaim space of the deleted records
+    pub fn compact(&self) -> Result<(), StorageError> {
+        KeyValueStoreWithSchema::<BlockPrimaryIndex>::compact(&*self.kv)?;
+        KeyValueStoreWithSchema::<BlockByLevelIndex>::compact(&*self.kv)?;
+        KeyValueStoreWithSchema::<BlockByContextHashIndex>::compact(&*self.kv)?;
+        KeyValueStoreWithSchema::<BlockMetaStorage>::compact(&*self.kv)?;
+        KeyValueStoreWithSchema::<OperationsStorage>::compact(&*self.kv)?;
+        KeyValueStoreWithSchema::<OperationsByHashIndex>::compact(&*self.kv)?;
+        KeyValueStoreWithSchema::<OperationsMetaStorage>::compact(&*self.kv)?;
+        KeyValueStoreWithSchema::<ContextActionPrimaryIndex>::compact(&*self.kv)?;
+        KeyValueStoreWithSchema::<ContextActionByContractIndex>::compact(&*self.kv)?;
+        Ok(())
+    }
+
+    /// Rewrite commit logs, so the space of the pruned records is reclaimed.
+    /// Returns `false`, if nothing was pruned since the last compaction.
+    ///
+    /// Locations of the records change, so this must not run while the storage is used by anyone else.
+    /// If the rewrite is interrupted after the new commit log replaced the old one, but before all indexes
+    /// were updated, the storage has to be synchronized again.
+    pub fn compact_commit_logs(&mut self) -> Result<bool, StorageError> {
+        let pruned_level = match self.system_storage.get_pruned_level()? {
+            Some(pruned_level) => pruned_level,
+            None => return Ok(false),
+        };
+        if self.system_storage.get_compacted_level()?.filter(|compacted_level| *compacted_level >= pruned_level).is_some() {
+            return Ok(false);
+        }
+
+        self.block_storage.compact_commit_log()?;
+        self.context_action_storage.compact_commit_log()?;
+        self.system_storage.set_compacted_level(pruned_level)?;
+        Ok(true)
+    }
+
+    /// Delete operations, operations metadata, json data and context actions of the block
+    fn prune_block_data(&mut self, block_hash: &BlockHash) -> Result<(), StorageError> {
+        self.operations_storage.delete_operations(block_hash)?;
+        self.operations_meta_storage.delete(block_hash)?;
+        self.context_action_storage.delete_by_block_hash(block_hash)?;
+        if self.block_storage.contains(block_hash)? {
+            self.block_storage.remove_block_json_data(block_hash)?;
+        }
+        Ok(())
+    }
+
+    /// Delete the block and all its descendants
+    fn delete_branch(&mut self, branch_block_hash: &BlockHash) -> Result<(), StorageError> {
+        let mut blocks = vec![branch_block_hash.clone()];
+        while let Some(block_hash) = blocks.pop() {
+            let meta = self.block_meta_storage.get(&block_hash)?;
+            if let Some(meta) = &meta {
+                blocks.extend(meta.successors().iter().cloned());
+            }
+
+            self.prune_block_data(&block_hash)?;
+            self.block_storage.delete(&block_hash)?;
+            self.block_meta_storage.delete(&block_hash)?;
+
+            // level index could have pointed to the deleted block
+            if let Some(level) = meta.map(|meta| meta.level()) {
+                if let Some(main_chain_block_hash) = self.main_chain.get(&level).cloned() {
+                    if self.block_storage.get_by_block_level(level)?.is_none() {
+                        self.block_storage.index_by_level(&main_chain_block_hash)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve block of the main chain at the `level` by walking from the current head
+    fn main_chain_block(&mut self, level: i32) -> Result<Option<BlockHash>, StorageError> {
+        if !self.main_chain.contains_key(&level) {
+            self.main_chain.clear();
+            let mut block_hash = self.block_meta_storage.load_current_head()?;
+            while let Some(meta) = block_hash.as_ref().map(|block_hash| self.block_meta_storage.get(block_hash)).transpose()?.flatten() {
+                if meta.level() < level {
+                    break;
+                }
+                self.main_chain.insert(meta.level(), block_hash.take().unwrap());
+                // genesis is the predecessor of itself
+                block_hash = meta.predecessor().clone().filter(|_| meta.level() > 0);
+            }
+        }
+        Ok(self.main_chain.get(&level).cloned())
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Snapshot of the storage used for fast bootstrap of a new node.
+//!
+//! Snapshot is a single versioned file, which contains:
+//! * snapshot header (chain id, hash and level of the snapshot block),
+//! * recent history of the snapshot block (`max_operations_ttl` predecessors) with their operations, json data and metadata,
+//! * the Rust context at the snapshot block.
+//!
+//! Blocks, operations and metadata are stored in the same binary format, which is used by the storage.
+//!
+//! Snapshot does not contain the context of the OCaml protocol runner (`tezos-data-dir`), so there is no import yet,
+//! blocks could not be applied on top of the imported head without it.
+
+use std::io::{Read, Write};
+
+use failure::Fail;
+use serde::{Deserialize, Serialize};
+
+use crypto::hash::{BlockHash, ChainId, HashType};
+
+use crate::{BlockAdditionalData, BlockJsonData, BlockMetaStorage, BlockStorage, BlockStorageReader, OperationsStorage, OperationsStorageReader, StorageError};
+use crate::block_meta_storage::BlockMetaStorageReader;
+use crate::context::{ContextApiRef, ContextError, ContextIndex};
+use crate::persistent::{ContextMap, Encoder, PersistentStorage, SchemaError};
+use crate::skip_list::Bucket;
+
+/// Every snapshot file starts with these bytes
+const SNAPSHOT_MAGIC: [u8; 16] = *b"tezedge-snapshot";
+/// Version of the snapshot format, must be increased on every incompatible change
+pub const SNAPSHOT_VERSION: u16 = 2;
+
+/// Possible errors for snapshot export
+#[derive(Debug, Fail)]
+pub enum SnapshotError {
+    #[fail(display = "Storage error: {}", error)]
+    StorageError {
+        error: StorageError
+    },
+    #[fail(display = "Context storage error: {}", error)]
+    ContextError {
+        error: ContextError
+    },
+    #[fail(display = "Failed to read/write snapshot: {}", error)]
+    IOError {
+        error: std::io::Error
+    },
+    #[fail(display = "Failed to encode/decode snapshot: {}", error)]
+    EncodingError {
+        error: bincode::Error
+    },
+    #[fail(display = "Unsupported snapshot version: {}, supported version: {}", version, SNAPSHOT_VERSION)]
+    UnsupportedVersion {
+        version: u16
+    },
+    #[fail(display = "Invalid snapshot: {}", reason)]
+    InvalidSnapshot {
+        reason: String
+    },
+    #[fail(display = "Block {} cannot be exported, reason: {}", block_hash, reason)]
+    InvalidBlock {
+        block_hash: String,
+        reason: String,
+    },
+    #[fail(display = "Snapshot was created for another chain, chain_id: {}, expected_chain_id: {}", chain_id, expected_chain_id)]
+    ChainMismatch {
+        chain_id: String,
+        expected_chain_id: String,
+    },
+}
+
+impl From<StorageError> for SnapshotError {
+    fn from(error: StorageError) -> Self {
+        SnapshotError::StorageError { error }
+    }
+}
+
+impl From<SchemaError> for SnapshotError {
+    fn from(error: SchemaError) -> Self {
+        SnapshotError::StorageError { error: error.into() }
+    }
+}
+
+impl From<ContextError> for SnapshotError {
+    fn from(error: ContextError) -> Self {
+        SnapshotError::ContextError { error }
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(error: std::io::Error) -> Self {
+        SnapshotError::IOError { error }
+    }
+}
+
+impl From<bincode::Error> for SnapshotError {
+    fn from(error: bincode::Error) -> Self {
+        SnapshotError::EncodingError { error }
+    }
+}
+
+impl slog::Value for SnapshotError {
+    fn serialize(&self, _record: &slog::Record, key: slog::Key, serializer: &mut dyn slog::Serializer) -> slog::Result {
+        serializer.emit_arguments(key, &format_args!("{}", self))
+    }
+}
+
+/// Header of the snapshot, describes the block at which the snapshot was created
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SnapshotHeader {
+    pub chain_id: ChainId,
+    pub block_hash: BlockHash,
+    pub level: i32,
+    /// Number of exported blocks (the snapshot block and its recent predecessors)
+    pub block_count: usize,
+}
+
+/// Block with all its data, which are needed to continue syncing from the snapshot
+#[derive(Serialize, Deserialize)]
+struct SnapshotBlock {
+    /// Encoded [BlockHeaderWithHash](crate::BlockHeaderWithHash)
+    header: Vec<u8>,
+    json_data: Option<BlockJsonData>,
+    additional_data: Option<BlockAdditionalData>,
+    /// Encoded block [Meta](crate::block_meta_storage::Meta)
+    meta: Vec<u8>,
+    /// Encoded [OperationsForBlocksMessage](tezos_messages::p2p::encoding::prelude::OperationsForBlocksMessage) for every validation pass
+    operations: Vec<Vec<u8>>,
+}
+
+/// Export snapshot of the storage at the applied block.
+///
+/// Only the history needed to validate operations of the following blocks (`max_operations_ttl` predecessors) is exported.
+/// Context is read from the `context` by the context hash of the block.
+pub fn export_snapshot<W: Write>(persistent_storage: &PersistentStorage, context: &ContextApiRef, block_hash: &BlockHash, mut writer: W) -> Result<SnapshotHeader, SnapshotError> {
+    let block_storage = BlockStorage::new(persistent_storage);
+    let block_meta_storage = BlockMetaStorage::new(persistent_storage);
+    let operations_storage = OperationsStorage::new(persistent_storage);
+
+    let invalid_block = |reason: &str| SnapshotError::InvalidBlock {
+        block_hash: HashType::BlockHash.bytes_to_string(block_hash),
+        reason: reason.to_string(),
+    };
+
+    let meta = block_meta_storage.get(block_hash)?.ok_or_else(|| invalid_block("block metadata were not found"))?;
+    if !meta.is_applied() {
+        return Err(invalid_block("block is not applied"));
+    }
+    let (block, additional_data) = block_storage.get_with_additional_data(block_hash)?.ok_or_else(|| invalid_block("block additional data were not found"))?;
+
+    // context is read before anything is written, deleted keys are not needed anymore
+    let exported_context: ContextMap = context
+        .read().expect("lock poisoning")
+        .get_context(&ContextIndex::new(Some(block.header.context().clone())))?
+        .ok_or_else(|| invalid_block("context was not found"))?
+        .into_iter()
+        .filter(|(_, bucket)| match bucket {
+            Bucket::Exists(_) => true,
+            _ => false,
+        })
+        .collect();
+
+    // collect the history from the snapshot block back to the oldest block, which can be referenced by operations, or to the genesis
+    let mut chain = vec![block_hash.clone()];
+    let mut current = block;
+    while chain.len() <= usize::from(additional_data.max_operations_ttl()) && current.header.predecessor() != &current.hash {
+        current = block_storage.get(current.header.predecessor())?.ok_or_else(|| invalid_block("predecessor was not found"))?;
+        chain.push(current.hash.clone());
+    }
+
+    let header = SnapshotHeader {
+        chain_id: meta.chain_id().clone(),
+        block_hash: block_hash.clone(),
+        level: meta.level(),
+        block_count: chain.len(),
+    };
+    writer.write_all(&SNAPSHOT_MAGIC)?;
+    bincode::serialize_into(&mut writer, &SNAPSHOT_VERSION)?;
+    bincode::serialize_into(&mut writer, &header)?;
+
+    // blocks are exported from the oldest one, so they can be stored in the same order
+    for block_hash in chain.iter().rev() {
+        let (block, json_data) = match block_storage.get_with_json_data(block_hash)? {
+            Some((block, json_data)) => (block, Some(json_data)),
+            None => (block_storage.get(block_hash)?.ok_or_else(|| invalid_block("chain block was not found"))?, None),
+        };
+        let additional_data = block_storage.get_with_additional_data(block_hash)?.map(|(_, additional_data)| additional_data);
+        let meta = block_meta_storage.get(block_hash)?.ok_or_else(|| invalid_block("chain block metadata were not found"))?;
+        let operations = operations_storage.get_operations(block_hash)?
+            .iter()
+            .map(|operations| operations.encode())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let snapshot_block = SnapshotBlock {
+            header: block.encode()?,
+            json_data,
+            additional_data,
+            meta: meta.encode()?,
+            operations,
+        };
+        bincode::serialize_into(&mut writer, &snapshot_block)?;
+    }
+
+    bincode::serialize_into(&mut writer, &exported_context)?;
+    writer.flush()?;
+
+    Ok(header)
+}
+
+/// Read and validate the header of the snapshot.
+pub fn read_snapshot_header<R: Read>(chain_id: &ChainId, mut reader: R) -> Result<SnapshotHeader, SnapshotError> {
+    let mut magic = SNAPSHOT_MAGIC;
+    reader.read_exact(&mut magic)?;
+    if magic != SNAPSHOT_MAGIC {
+        return Err(SnapshotError::InvalidSnapshot { reason: "not a snapshot file".to_string() });
+    }
+    let version: u16 = bincode::deserialize_from(&mut reader)?;
+    if version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion { version });
+    }
+
+    let header: SnapshotHeader = bincode::deserialize_from(&mut reader)?;
+    if &header.chain_id != chain_id {
+        return Err(SnapshotError::ChainMismatch {
+            chain_id: HashType::ChainId.bytes_to_string(&header.chain_id),
+            expected_chain_id: HashType::ChainId.bytes_to_string(chain_id),
+        });
+    }
+
+    Ok(header)
+}
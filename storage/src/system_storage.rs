@@ -5,7 +5,7 @@ use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 
-use crypto::hash::ChainId;
+use crypto::hash::{BlockHash, ChainId};
 
 use crate::persistent::{BincodeEncoded, KeyValueSchema, KeyValueStoreWithSchema};
 use crate::StorageError;
@@ -25,6 +25,10 @@ pub struct SystemStorage {
 impl SystemStorage {
     const CHAIN_ID: &'static str = "chain_id";
     const DB_VERSION: &'static str = "db_version";
+    const CURRENT_HEAD: &'static str = "current_head";
+    const PRUNED_LEVEL: &'static str = "pruned_level";
+    const COMPACTED_LEVEL: &'static str = "compacted_level";
+    const MIGRATION_IN_PROGRESS: &'static str = "migration_in_progress";
 
     pub fn new(kv: Arc<SystemStorageKv>) -> Self {
         SystemStorage { kv }
@@ -61,6 +65,75 @@ impl SystemStorage {
         self.kv.put(&Self::DB_VERSION.to_string(), &SystemValue::Integer(db_version))
             .map_err(StorageError::from)
     }
+
+    #[inline]
+    pub fn get_current_head(&self) -> Result<Option<BlockHash>, StorageError> {
+        self.kv.get(&Self::CURRENT_HEAD.to_string())
+            .map(|result| match result {
+                Some(SystemValue::Hash(value)) => Some(value),
+                _ => None
+            })
+            .map_err(StorageError::from)
+    }
+
+    #[inline]
+    pub fn set_current_head(&mut self, block_hash: &BlockHash) -> Result<(), StorageError> {
+        self.kv.put(&Self::CURRENT_HEAD.to_string(), &SystemValue::Hash(block_hash.clone()))
+            .map_err(StorageError::from)
+    }
+
+    /// Highest level of the block, whose data were pruned
+    #[inline]
+    pub fn get_pruned_level(&self) -> Result<Option<i32>, StorageError> {
+        self.kv.get(&Self::PRUNED_LEVEL.to_string())
+            .map(|result| match result {
+                Some(SystemValue::Integer(value)) => Some(value as i32),
+                _ => None
+            })
+            .map_err(StorageError::from)
+    }
+
+    #[inline]
+    pub fn set_pruned_level(&mut self, level: i32) -> Result<(), StorageError> {
+        self.kv.put(&Self::PRUNED_LEVEL.to_string(), &SystemValue::Integer(level as i64))
+            .map_err(StorageError::from)
+    }
+
+    /// Highest pruned level, whose records were already removed from commit logs
+    #[inline]
+    pub fn get_compacted_level(&self) -> Result<Option<i32>, StorageError> {
+        self.kv.get(&Self::COMPACTED_LEVEL.to_string())
+            .map(|result| match result {
+                Some(SystemValue::Integer(value)) => Some(value as i32),
+                _ => None
+            })
+            .map_err(StorageError::from)
+    }
+
+    #[inline]
+    pub fn set_compacted_level(&mut self, level: i32) -> Result<(), StorageError> {
+        self.kv.put(&Self::COMPACTED_LEVEL.to_string(), &SystemValue::Integer(level as i64))
+            .map_err(StorageError::from)
+    }
+
+    /// Target database version of the migration, which was started but not finished yet
+    #[inline]
+    pub fn get_migration_in_progress(&self) -> Result<Option<DbVersion>, StorageError> {
+        self.kv.get(&Self::MIGRATION_IN_PROGRESS.to_string())
+            .map(|result| match result {
+                Some(SystemValue::Integer(value)) => Some(value),
+                _ => None
+            })
+            .map_err(StorageError::from)
+    }
+
+    #[inline]
+    pub fn set_migration_in_progress(&mut self, db_version: Option<DbVersion>) -> Result<(), StorageError> {
+        match db_version {
+            Some(db_version) => self.kv.put(&Self::MIGRATION_IN_PROGRESS.to_string(), &SystemValue::Integer(db_version)),
+            None => self.kv.delete(&Self::MIGRATION_IN_PROGRESS.to_string()),
+        }.map_err(StorageError::from)
+    }
 }
 
 
@@ -39,6 +39,47 @@ fn block_storage_assign_context() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn block_storage_level_index() -> Result<(), Error> {
+    let tmp_storage = TmpStorage::create("__block_level_index")?;
+    let mut storage = BlockStorage::new(tmp_storage.storage());
+
+    let block_header = make_test_block_header_at_level(5, 1)?;
+    let fork_block_header = make_test_block_header_at_level(5, 2)?;
+
+    // block stored first is returned for the level, another block of the same level does not replace it
+    storage.put_block_header(&block_header)?;
+    storage.put_block_header(&fork_block_header)?;
+    assert_eq!(block_header, storage.get_by_block_level(5)?.unwrap());
+
+    // block of the fork becomes part of the main chain
+    storage.unindex_by_level(&block_header.hash)?;
+    assert!(storage.get_by_block_level(5)?.is_none());
+    storage.index_by_level(&fork_block_header.hash)?;
+    assert_eq!(fork_block_header, storage.get_by_block_level(5)?.unwrap());
+
+    // block out of the main chain is not unindexed from the level of another block
+    storage.unindex_by_level(&block_header.hash)?;
+    assert_eq!(fork_block_header, storage.get_by_block_level(5)?.unwrap());
+
+    Ok(())
+}
+
+fn make_test_block_header_at_level(level: i32, timestamp: i64) -> Result<BlockHeaderWithHash, Error> {
+    let block_header = BlockHeaderBuilder::default()
+        .level(level)
+        .proto(0)
+        .predecessor(vec![0; HashType::BlockHash.size()])
+        .timestamp(timestamp)
+        .validation_pass(0)
+        .operations_hash(vec![0; HashType::OperationListListHash.size()])
+        .fitness(vec![])
+        .context(vec![0; HashType::ContextHash.size()])
+        .protocol_data(vec![])
+        .build().unwrap();
+    Ok(BlockHeaderWithHash::new(block_header)?)
+}
+
 fn make_test_block_header() -> Result<BlockHeaderWithHash, Error> {
     let message_bytes = hex::decode("00006d6e0102dd00defaf70c53e180ea148b349a6feb4795610b2abc7b07fe91ce50a90814000000005c1276780432bc1d3a28df9a67b363aa1638f807214bb8987e5f9c0abcbd69531facffd1c80000001100000001000000000800000000000c15ef15a6f54021cb353780e2847fb9c546f1d72c1dc17c3db510f45553ce501ce1de000000000003c762c7df00a856b8bfcaf0676f069f825ca75f37f2bee9fe55ba109cec3d1d041d8c03519626c0c0faa557e778cb09d2e0c729e8556ed6a7a518c84982d1f2682bc6aa753f")?;
     let block_header = BlockHeaderWithHash::new(BlockHeader::from_bytes(message_bytes)?)?;
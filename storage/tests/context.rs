@@ -7,15 +7,26 @@ use std::sync::Arc;
 
 use crypto::hash::{ContextHash, HashType};
 use storage::{BlockHeaderWithHash, BlockStorage};
-use storage::context::{ContextApi, ContextIndex, TezedgeContext};
+use storage::context::{ContextApi, ContextBackend, ContextError, ContextIndex};
+use storage::merkle_hash::{CommitInfo, hash_commit, hash_context};
+use storage::persistent::ContextMap;
 use storage::skip_list::Bucket;
 use storage::tests_common::TmpStorage;
 use tezos_messages::p2p::encoding::prelude::BlockHeaderBuilder;
 
 #[test]
 pub fn test_context_set_get_commit() -> Result<(), failure::Error> {
+    context_set_get_commit(ContextBackend::SkipList, "__context:test_context_set_get_commit")
+}
+
+#[test]
+pub fn test_in_memory_context_set_get_commit() -> Result<(), failure::Error> {
+    context_set_get_commit(ContextBackend::InMemory, "__context:in_memory_test_context_set_get_commit")
+}
+
+fn context_set_get_commit(backend: ContextBackend, storage_dir: &str) -> Result<(), failure::Error> {
     // prepare temp storage
-    let tmp_storage = TmpStorage::create(test_storage_dir_path("__context:test_context_set_get_commit")).expect("Storage error");
+    let tmp_storage = TmpStorage::create(test_storage_dir_path(storage_dir)).expect("Storage error");
     let persistent_storage = tmp_storage.storage();
 
     // init block storage (because of commit)
@@ -24,10 +35,8 @@ pub fn test_context_set_get_commit() -> Result<(), failure::Error> {
     block_storage.put_block_header(&block)?;
 
     // context
-    let mut context = TezedgeContext::new(
-        BlockStorage::new(&persistent_storage),
-        persistent_storage.context_storage(),
-    );
+    let context = backend.create(&persistent_storage);
+    let mut context = context.write().expect("lock poisoning");
 
     // add to context
     let mut diff = context.init_from_start();
@@ -51,8 +60,17 @@ pub fn test_context_set_get_commit() -> Result<(), failure::Error> {
 
 #[test]
 pub fn test_context_delete_and_remove() -> Result<(), failure::Error> {
+    context_delete_and_remove(ContextBackend::SkipList, "__context:test_context_delete_and_remove")
+}
+
+#[test]
+pub fn test_in_memory_context_delete_and_remove() -> Result<(), failure::Error> {
+    context_delete_and_remove(ContextBackend::InMemory, "__context:in_memory_test_context_delete_and_remove")
+}
+
+fn context_delete_and_remove(backend: ContextBackend, storage_dir: &str) -> Result<(), failure::Error> {
     // prepare temp storage
-    let tmp_storage = TmpStorage::create(test_storage_dir_path("__context:test_context_delete_and_remove")).expect("Storage error");
+    let tmp_storage = TmpStorage::create(test_storage_dir_path(storage_dir)).expect("Storage error");
     let persistent_storage = tmp_storage.storage();
 
     // init block with level 0 (because of commit)
@@ -61,10 +79,8 @@ pub fn test_context_delete_and_remove() -> Result<(), failure::Error> {
     block_storage.put_block_header(&block)?;
 
     // context
-    let mut context = TezedgeContext::new(
-        BlockStorage::new(&persistent_storage),
-        persistent_storage.context_storage(),
-    );
+    let context = backend.create(&persistent_storage);
+    let mut context = context.write().expect("lock poisoning");
 
     // add to context
     let mut context_diff = context.init_from_start();
@@ -145,8 +161,17 @@ pub fn test_context_delete_and_remove() -> Result<(), failure::Error> {
 
 #[test]
 pub fn test_context_copy() -> Result<(), failure::Error> {
+    context_copy(ContextBackend::SkipList, "__context:context_copy")
+}
+
+#[test]
+pub fn test_in_memory_context_copy() -> Result<(), failure::Error> {
+    context_copy(ContextBackend::InMemory, "__context:in_memory_context_copy")
+}
+
+fn context_copy(backend: ContextBackend, storage_dir: &str) -> Result<(), failure::Error> {
     // prepare temp storage
-    let tmp_storage = TmpStorage::create(test_storage_dir_path("__context:context_copy")).expect("Storage error");
+    let tmp_storage = TmpStorage::create(test_storage_dir_path(storage_dir)).expect("Storage error");
     let persistent_storage = tmp_storage.storage();
 
     // init block with level 0 (because of commit)
@@ -155,10 +180,8 @@ pub fn test_context_copy() -> Result<(), failure::Error> {
     block_storage.put_block_header(&block)?;
 
     // context
-    let mut context = TezedgeContext::new(
-        BlockStorage::new(&persistent_storage),
-        persistent_storage.context_storage(),
-    );
+    let context = backend.create(&persistent_storage);
+    let mut context = context.write().expect("lock poisoning");
 
     // add to context
     let mut context_diff = context.init_from_start();
@@ -236,6 +259,177 @@ pub fn test_context_copy() -> Result<(), failure::Error> {
     Ok(())
 }
 
+#[test]
+pub fn test_context_verify_commit() -> Result<(), failure::Error> {
+    context_verify_commit(ContextBackend::SkipList, "__context:context_verify_commit")
+}
+
+#[test]
+pub fn test_in_memory_context_verify_commit() -> Result<(), failure::Error> {
+    context_verify_commit(ContextBackend::InMemory, "__context:in_memory_context_verify_commit")
+}
+
+fn context_verify_commit(backend: ContextBackend, storage_dir: &str) -> Result<(), failure::Error> {
+    // prepare temp storage
+    let tmp_storage = TmpStorage::create(test_storage_dir_path(storage_dir)).expect("Storage error");
+    let persistent_storage = tmp_storage.storage();
+
+    // init block with level 0 (because of commit)
+    let block = dummy_block("BLockGenesisGenesisGenesisGenesisGenesisb83baZgbyZe", 0)?;
+    let mut block_storage = BlockStorage::new(&persistent_storage);
+    block_storage.put_block_header(&block)?;
+
+    // context
+    let context = backend.create(&persistent_storage);
+    let mut context = context.write().expect("lock poisoning");
+
+    // genesis commit is not verified
+    let mut context_diff = context.init_from_start();
+    context_diff.set(&None, &to_key(["protocol"].to_vec()), &vec![1, 2, 3])?;
+    context_diff.set(&None, &to_key(["data", "v1", "constants"].to_vec()), &vec![4, 5, 6])?;
+    let context_hash_1: ContextHash = HashType::ContextHash.string_to_bytes("CoVmAcMV64uAQo8XvfLr9VDuz7HVZLT4cgK1w1qYmTjQNbGwQwDd")?;
+    context.commit(&block.hash, &None, &context_hash_1, &context_diff)?;
+
+    // insert another block with level 1
+    let block = dummy_block("BKyQ9EofHrgaZKENioHyP4FZNsTmiSEcVmcghgzCC9cGhE7oCET", 1)?;
+    block_storage.put_block_header(&block)?;
+
+    // commit context with the correct context hash
+    let mut context_diff = context.checkout(&context_hash_1)?;
+    context_diff.set(&Some(context_hash_1.clone()), &to_key(["data", "v1", "constants"].to_vec()), &vec![7, 8, 9])?;
+    let mut expected_context = ContextMap::new();
+    expected_context.insert("protocol".to_string(), Bucket::Exists(vec![1, 2, 3]));
+    expected_context.insert("data/v1/constants".to_string(), Bucket::Exists(vec![7, 8, 9]));
+    let commit_info = CommitInfo::new(block.header.timestamp(), "lvl 1, fit 0, prio 0, 0 ops".to_string());
+    let context_hash_2 = hash_commit(&hash_context(&expected_context)?, &Some(context_hash_1.clone()), &commit_info);
+
+    context.commit(&block.hash, &Some(context_hash_1), &context_hash_2, &context_diff)?;
+    context.verify_commit(&context_hash_2, commit_info)?;
+    assert!(context.verification_failure().is_none());
+
+    // insert another block with level 2
+    let block = dummy_block("BKjcGSmAbSoBvxvXkrzG7XZ722cW8sfQu6tyykT1e7tDcNJvj9m", 2)?;
+    block_storage.put_block_header(&block)?;
+
+    // commit info can be received before the commit of the context
+    let context_hash_3: ContextHash = HashType::ContextHash.string_to_bytes("CoUfdnduWrSwV7bGLvrtEu3gMXU2BLYEq2eUTGnx66EPByKDAxPY")?;
+    context.verify_commit(&context_hash_3, CommitInfo::new(block.header.timestamp(), "lvl 2, fit 0, prio 0, 0 ops".to_string()))?;
+
+    // context hash does not match the committed context
+    let context_diff = context.checkout(&context_hash_2)?;
+    match context.commit(&block.hash, &Some(context_hash_2.clone()), &context_hash_3, &context_diff) {
+        Err(ContextError::ContextHashMismatchError { .. }) => (),
+        result => panic!("Context hash verification should fail, but result was: {:?}", result),
+    }
+
+    // context is committed anyway, but it is flagged as not verified
+    assert_eq!(Some(context_hash_3.clone()), context.verification_failure());
+    assert_data_eq!(context, ["data", "v1", "constants"], context_hash_3, Bucket::Exists(vec![7, 8, 9]));
+
+    Ok(())
+}
+
+#[test]
+pub fn test_context_fork() -> Result<(), failure::Error> {
+    context_fork(ContextBackend::SkipList, "__context:context_fork")
+}
+
+#[test]
+pub fn test_in_memory_context_fork() -> Result<(), failure::Error> {
+    context_fork(ContextBackend::InMemory, "__context:in_memory_context_fork")
+}
+
+fn context_fork(backend: ContextBackend, storage_dir: &str) -> Result<(), failure::Error> {
+    // prepare temp storage
+    let tmp_storage = TmpStorage::create(test_storage_dir_path(storage_dir)).expect("Storage error");
+    let persistent_storage = tmp_storage.storage();
+    let mut block_storage = BlockStorage::new(&persistent_storage);
+
+    // context
+    let context = backend.create(&persistent_storage);
+    let mut context = context.write().expect("lock poisoning");
+
+    // main branch: genesis <- 1 <- 2
+    let context_hash_0: ContextHash = vec![10; 32];
+    let context_hash_1: ContextHash = vec![11; 32];
+    let context_hash_2: ContextHash = vec![12; 32];
+    let block = dummy_block(&HashType::BlockHash.bytes_to_string(&vec![0; 32]), 0)?;
+    block_storage.put_block_header(&block)?;
+    let mut context_diff = context.init_from_start();
+    context_diff.set(&None, &to_key(["a"].to_vec()), &vec![1])?;
+    context_diff.set(&None, &to_key(["b"].to_vec()), &vec![1])?;
+    context.commit(&block.hash, &None, &context_hash_0, &context_diff)?;
+
+    let block = dummy_block(&HashType::BlockHash.bytes_to_string(&vec![1; 32]), 1)?;
+    block_storage.put_block_header(&block)?;
+    let mut context_diff = context.checkout(&context_hash_0)?;
+    context_diff.set(&Some(context_hash_0.clone()), &to_key(["a"].to_vec()), &vec![2])?;
+    context_diff.set(&Some(context_hash_0.clone()), &to_key(["c"].to_vec()), &vec![1])?;
+    context.commit(&block.hash, &Some(context_hash_0.clone()), &context_hash_1, &context_diff)?;
+
+    let block = dummy_block(&HashType::BlockHash.bytes_to_string(&vec![2; 32]), 2)?;
+    block_storage.put_block_header(&block)?;
+    let mut context_diff = context.checkout(&context_hash_1)?;
+    context.delete_to_diff(&Some(context_hash_1.clone()), &to_key(["b"].to_vec()), &mut context_diff)?;
+    context_diff.set(&Some(context_hash_1.clone()), &to_key(["d"].to_vec()), &vec![1])?;
+    context.commit(&block.hash, &Some(context_hash_1.clone()), &context_hash_2, &context_diff)?;
+
+    // fork: 1 <- 2' <- 3', blocks of the fork have the same levels as the blocks of the main branch
+    let fork_context_hash_2: ContextHash = vec![22; 32];
+    let fork_context_hash_3: ContextHash = vec![23; 32];
+    let fork_block_2 = dummy_block(&HashType::BlockHash.bytes_to_string(&vec![12; 32]), 2)?;
+    block_storage.put_block_header(&fork_block_2)?;
+    let mut fork_context_diff_2 = context.checkout(&context_hash_1)?;
+    fork_context_diff_2.set(&Some(context_hash_1.clone()), &to_key(["b"].to_vec()), &vec![3])?;
+    fork_context_diff_2.set(&Some(context_hash_1.clone()), &to_key(["e"].to_vec()), &vec![1])?;
+    context.commit(&fork_block_2.hash, &Some(context_hash_1.clone()), &fork_context_hash_2, &fork_context_diff_2)?;
+
+    let block = dummy_block(&HashType::BlockHash.bytes_to_string(&vec![13; 32]), 3)?;
+    block_storage.put_block_header(&block)?;
+    let mut context_diff = context.checkout(&fork_context_hash_2)?;
+    context.delete_to_diff(&Some(fork_context_hash_2.clone()), &to_key(["a"].to_vec()), &mut context_diff)?;
+    context.commit(&block.hash, &Some(fork_context_hash_2.clone()), &fork_context_hash_3, &context_diff)?;
+
+    // back on the main branch: 2 <- 3
+    let context_hash_3: ContextHash = vec![13; 32];
+    let block = dummy_block(&HashType::BlockHash.bytes_to_string(&vec![3; 32]), 3)?;
+    block_storage.put_block_header(&block)?;
+    let mut context_diff = context.checkout(&context_hash_2)?;
+    context_diff.set(&Some(context_hash_2.clone()), &to_key(["f"].to_vec()), &vec![1])?;
+    context.commit(&block.hash, &Some(context_hash_2.clone()), &context_hash_3, &context_diff)?;
+
+    // the same block of the fork is applied again
+    context.commit(&fork_block_2.hash, &Some(context_hash_1.clone()), &fork_context_hash_2, &fork_context_diff_2)?;
+
+    // every commit sees only the changes of its own branch
+    assert_context_eq(&*context, &context_hash_0, &[("a", Some(1)), ("b", Some(1)), ("c", None), ("d", None), ("e", None), ("f", None)])?;
+    assert_context_eq(&*context, &context_hash_1, &[("a", Some(2)), ("b", Some(1)), ("c", Some(1)), ("d", None), ("e", None), ("f", None)])?;
+    assert_context_eq(&*context, &context_hash_2, &[("a", Some(2)), ("b", None), ("c", Some(1)), ("d", Some(1)), ("e", None), ("f", None)])?;
+    assert_context_eq(&*context, &context_hash_3, &[("a", Some(2)), ("b", None), ("c", Some(1)), ("d", Some(1)), ("e", None), ("f", Some(1))])?;
+    assert_context_eq(&*context, &fork_context_hash_2, &[("a", Some(2)), ("b", Some(3)), ("c", Some(1)), ("d", None), ("e", Some(1)), ("f", None)])?;
+    assert_context_eq(&*context, &fork_context_hash_3, &[("a", None), ("b", Some(3)), ("c", Some(1)), ("d", None), ("e", Some(1)), ("f", None)])?;
+
+    // unknown context is not mistaken for another commit
+    match context.checkout(&vec![99; 32]) {
+        Err(ContextError::UnknownContextHashError { .. }) => (),
+        result => panic!("Checkout of unknown context should fail, but result was: {:?}", result.map(|_| ())),
+    }
+
+    Ok(())
+}
+
+/// Assert values of the keys in the committed context, deleted key is the same as the missing key
+fn assert_context_eq(context: &dyn ContextApi, context_hash: &ContextHash, expected: &[(&str, Option<u8>)]) -> Result<(), failure::Error> {
+    for (key, expected_value) in expected {
+        let value = match context.get_key(&ContextIndex::new(Some(context_hash.clone())), &to_key(vec![key]))? {
+            Some(Bucket::Exists(value)) => Some(value),
+            Some(Bucket::Deleted) | None => None,
+        };
+        assert_eq!(expected_value.map(|value| vec![value]), value, "key: {}, context_hash: {}", key, HashType::ContextHash.bytes_to_string(context_hash));
+    }
+    Ok(())
+}
+
 fn to_key(key: Vec<&str>) -> Vec<String> {
     key
         .into_iter()
@@ -267,7 +461,7 @@ fn dummy_block(block_hash: &str, level: i32) -> Result<BlockHeaderWithHash, fail
 #[macro_export]
 macro_rules! assert_data_eq {
     ($ctx:expr, $key:expr, $context_hash:expr, $data:expr) => {{
-        let data = $ctx.get_key(&ContextIndex::new(None, Some($context_hash)), &to_key($key.to_vec()))?;
+        let data = $ctx.get_key(&ContextIndex::new(Some($context_hash)), &to_key($key.to_vec()))?;
         assert!(data.is_some());
         assert_eq!(data.unwrap(), $data);
     }}
@@ -7,6 +7,7 @@ use crypto::hash::HashType;
 use storage::*;
 use storage::tests_common::TmpStorage;
 use tezos_context::channel::ContextAction;
+use tezos_encoding::micheline::Micheline;
 
 #[test]
 fn context_get_values_by_block_hash() -> Result<(), Error> {
@@ -101,4 +102,63 @@ fn context_get_values_by_contract_address() -> Result<(), Error> {
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn context_contract_script_values_round_trip_through_micheline() -> Result<(), Error> {
+    let tmp_storage = TmpStorage::create("__ctx_storage_contract_script_values")?;
+
+    let str_block_hash = "BKyQ9EofHrgaZKENioHyP4FZNsTmiSEcVmcghgzCC9cGhE7oCET";
+    let block_hash = HashType::BlockHash.string_to_bytes(str_block_hash)?;
+    let contract_id = "01e5f7bc6f2c8c7bac3d9e8ab6c7f2c3a6d4e5f6a700";
+    let contract_key = |name: &str| vec![
+        "data".to_string(),
+        "contracts".to_string(),
+        "index".to_string(),
+        "6b".to_string(),
+        "1e".to_string(),
+        "40".to_string(),
+        "2d".to_string(),
+        "2b".to_string(),
+        "be".to_string(),
+        contract_id.to_string(),
+        "data".to_string(),
+        name.to_string()
+    ];
+    let code = hex::decode("000000bd02000000b805000764045b0000000a2564656372656d656e740764045b0000000a25696e6372656d656e74046c0000000625726573657405010865045b0000000825636f756e746572046e000000062561646d696e000000083a73746f726167650502020000005503210317034c0316072e020000000f051f020000000403210316034c034b0200000021072e020000000d051f0200000004032103160312020000000803200743035b0000051f020000000203170342053d036d0342")?;
+    let storage_value = hex::decode("0000002d0707006a0100000024747a314b715470455a37596f62375162504534487934576f38664847384c684b785a5378")?;
+
+    let mut storage = ContextActionStorage::new(tmp_storage.storage());
+    for (key, value) in vec![(contract_key("code"), code.clone()), (contract_key("storage"), storage_value.clone())] {
+        storage.put_action(&block_hash, ContextAction::Set {
+            key,
+            value,
+            operation_hash: None,
+            block_hash: Some(str_block_hash.into()),
+            context_hash: None,
+            value_as_json: None,
+            start_time: 0.0,
+            end_time: 0.0,
+            ignored: false,
+        })?;
+    }
+
+    let values = storage.get_by_contract_address(&hex::decode(contract_id)?, None, 10)?;
+    assert_eq!(2, values.len(), "Was expecting vector of {} elements but instead found {}", 2, values.len());
+    let expected_json = vec![
+        r#"[{"prim":"parameter","args":[{"prim":"or","args":[{"prim":"int","annots":["%decrement"]},{"prim":"or","args":[{"prim":"int","annots":["%increment"]},{"prim":"unit","annots":["%reset"]}]}]}]},{"prim":"storage","args":[{"prim":"pair","args":[{"prim":"int","annots":["%counter"]},{"prim":"address","annots":["%admin"]}],"annots":[":storage"]}]},{"prim":"code","args":[[{"prim":"DUP"},{"prim":"CDR"},{"prim":"SWAP"},{"prim":"CAR"},{"prim":"IF_LEFT","args":[[{"prim":"DIP","args":[[{"prim":"DUP"},{"prim":"CAR"}]]},{"prim":"SWAP"},{"prim":"SUB"}],[{"prim":"IF_LEFT","args":[[{"prim":"DIP","args":[[{"prim":"DUP"},{"prim":"CAR"}]]},{"prim":"ADD"}],[{"prim":"DROP"},{"prim":"PUSH","args":[{"prim":"int"},{"int":"0"}]}]]}]]},{"prim":"DIP","args":[[{"prim":"CDR"}]]},{"prim":"PAIR"},{"prim":"NIL","args":[{"prim":"operation"}]},{"prim":"PAIR"}]]}]"#,
+        r#"{"prim":"Pair","args":[{"int":"-42"},{"string":"tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx"}]}"#,
+    ];
+    for (value, expected_json) in values.iter().zip(expected_json) {
+        if let ContextAction::Set { value, .. } = value.action() {
+            let expr = Micheline::from_lazy_expr_bytes(value)?;
+            assert_eq!(expected_json, serde_json::to_string(&expr)?);
+            let expr: Micheline = serde_json::from_str(expected_json)?;
+            assert_eq!(value, &expr.to_lazy_expr_bytes()?);
+        } else {
+            panic!("Was expecting ContextAction::Set");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use failure::Error;
+use slog::{Drain, Level, Logger};
+
+use storage::{StorageError, SystemStorage};
+use storage::migration::{MigrationError, MigrationRegistry};
+use storage::persistent::PersistentStorage;
+use storage::tests_common::TmpStorage;
+
+#[test]
+fn test_migrate() -> Result<(), Error> {
+    let log = create_logger();
+    let tmp_storage = TmpStorage::create("__storage_migration")?;
+    let mut system_storage = SystemStorage::new(tmp_storage.storage().kv());
+    let registry = MigrationRegistry::new()
+        .register(1, 2, "first", set_chain_id)?
+        .register(2, 3, "second", set_chain_id)?;
+
+    // new database is not migrated
+    assert_eq!(0, registry.migrate(tmp_storage.storage(), 3, false, &log)?);
+    assert_eq!(None, system_storage.get_db_version()?);
+
+    // dry run does not touch the database
+    system_storage.set_db_version(1)?;
+    assert_eq!(2, registry.migrate(tmp_storage.storage(), 3, true, &log)?);
+    assert_eq!(Some(1), system_storage.get_db_version()?);
+    assert_eq!(None, system_storage.get_chain_id()?);
+
+    assert_eq!(2, registry.migrate(tmp_storage.storage(), 3, false, &log)?);
+    assert_eq!(Some(3), system_storage.get_db_version()?);
+    assert_eq!(None, system_storage.get_migration_in_progress()?);
+    assert_eq!(Some(vec![1, 2, 3]), system_storage.get_chain_id()?);
+
+    // nothing to migrate
+    assert_eq!(0, registry.migrate(tmp_storage.storage(), 3, false, &log)?);
+
+    // database from the future
+    match registry.migrate(tmp_storage.storage(), 2, false, &log) {
+        Err(MigrationError::NewerDatabase { db_version: 3, supported_version: 2 }) => (),
+        result => panic!("Newer database should not be migrated, but result was: {:?}", result),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_migrate_failed_step_is_resumed() -> Result<(), Error> {
+    let log = create_logger();
+    let tmp_storage = TmpStorage::create("__storage_migration_resume")?;
+    let mut system_storage = SystemStorage::new(tmp_storage.storage().kv());
+    system_storage.set_db_version(1)?;
+
+    let failing_registry = MigrationRegistry::new()
+        .register(1, 2, "first", set_chain_id)?
+        .register(2, 3, "failing", fail)?;
+    match failing_registry.migrate(tmp_storage.storage(), 3, false, &log) {
+        Err(MigrationError::MigrationFailed { from_version: 2, to_version: 3, .. }) => (),
+        result => panic!("Migration should fail, but result was: {:?}", result),
+    }
+    // finished step is checkpointed, failed step is marked as in progress
+    assert_eq!(Some(2), system_storage.get_db_version()?);
+    assert_eq!(Some(3), system_storage.get_migration_in_progress()?);
+
+    let registry = MigrationRegistry::new()
+        .register(1, 2, "first", set_chain_id)?
+        .register(2, 3, "second", set_chain_id)?;
+    assert_eq!(1, registry.migrate(tmp_storage.storage(), 3, false, &log)?);
+    assert_eq!(Some(3), system_storage.get_db_version()?);
+    assert_eq!(None, system_storage.get_migration_in_progress()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_migrate_missing_migration() -> Result<(), Error> {
+    let log = create_logger();
+    let tmp_storage = TmpStorage::create("__storage_migration_missing")?;
+    SystemStorage::new(tmp_storage.storage().kv()).set_db_version(1)?;
+
+    let registry = MigrationRegistry::new()
+        .register(2, 3, "second", set_chain_id)?;
+    match registry.migrate(tmp_storage.storage(), 3, false, &log) {
+        Err(MigrationError::MissingMigration { from_version: 1, to_version: 3 }) => (),
+        result => panic!("Migration should be missing, but result was: {:?}", result),
+    }
+
+    Ok(())
+}
+
+fn set_chain_id(persistent_storage: &PersistentStorage) -> Result<(), StorageError> {
+    SystemStorage::new(persistent_storage.kv()).set_chain_id(&vec![1, 2, 3])
+}
+
+fn fail(_: &PersistentStorage) -> Result<(), StorageError> {
+    Err(StorageError::MissingKey)
+}
+
+fn create_logger() -> Logger {
+    let drain = slog_async::Async::new(slog_term::FullFormat::new(slog_term::TermDecorator::new().build()).build().fuse()).build().filter_level(Level::Info).fuse();
+
+    Logger::root(drain, slog::o!())
+}
@@ -6,6 +6,7 @@ use failure::Error;
 use crypto::hash::HashType;
 use storage::*;
 use storage::tests_common::TmpStorage;
+use tezos_messages::p2p::binary_message::{BinaryMessage, MessageHash};
 use tezos_messages::p2p::encoding::prelude::*;
 
 #[test]
@@ -42,4 +43,26 @@ fn test_get_operations() -> Result<(), Error> {
     assert_eq!(1, operations.len(), "Was expecting vector of {} elements but instead found {}", 1, operations.len());
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_find_operation() -> Result<(), Error> {
+    let tmp_storage = TmpStorage::create("__op_storage_find_operation")?;
+
+    let block_hash = HashType::BlockHash.string_to_bytes("BKyQ9EofHrgaZKENioHyP4FZNsTmiSEcVmcghgzCC9cGhE7oCET")?;
+    let operation = Operation::from_bytes(hex::decode("10490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e000008c387fa065a181d45d47a9b78ddc77e92a881779ff2cbabbf9646eade4bf1405a08e00b725ed849eea46953b10b5cdebc518e6fd47e69b82d2ca18c4cf6d2f312dd08")?)?;
+    let operation_hash = operation.message_hash()?;
+
+    let mut storage = OperationsStorage::new(tmp_storage.storage());
+    assert!(storage.find_operation(&operation_hash)?.is_none());
+
+    storage.put_operations(&OperationsForBlocksMessage::new(OperationsForBlock::new(block_hash.clone(), 3), Path::Op, vec![operation.clone()]))?;
+    let found = storage.find_operation(&operation_hash)?.expect("Operation should be found");
+    assert_eq!(operation.as_bytes()?, found.as_bytes()?);
+
+    // index is cleaned together with the operations
+    storage.delete_operations(&block_hash)?;
+    assert!(storage.find_operation(&operation_hash)?.is_none());
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::fs;
+use std::path::PathBuf;
+
+use failure::Error;
+
+use crypto::hash::HashType;
+use storage::ProtocolStorage;
+use storage::protocol_storage::ProtocolSourceError;
+use storage::tests_common::TmpStorage;
+use tezos_messages::p2p::binary_message::MessageHash;
+use tezos_messages::p2p::encoding::prelude::*;
+
+#[test]
+fn test_put_get_protocol() -> Result<(), Error> {
+    let tmp_storage = TmpStorage::create("__protocol_storage")?;
+    let mut storage = ProtocolStorage::new(tmp_storage.storage());
+
+    let protocol = Protocol::new(0, vec![
+        Component::new("Main".to_string(), Some("module type S = sig end".to_string()), "let x = 1".to_string()),
+        Component::new("Apply".to_string(), None, "let y = 2".to_string()),
+    ]);
+    let protocol_hash = storage.put(&protocol)?;
+    assert!(storage.contains(&protocol_hash)?);
+
+    let stored = storage.get(&protocol_hash)?.expect("Protocol should be stored");
+    assert_eq!(0, stored.expected_env_version());
+    assert_eq!(2, stored.components().len());
+    assert_eq!("Main", stored.components()[0].name());
+    assert_eq!(&Some("module type S = sig end".to_string()), stored.components()[0].interface());
+    assert_eq!("let y = 2", stored.components()[1].implementation());
+
+    assert!(!storage.contains(&vec![0; 32])?);
+    assert!(storage.get(&vec![0; 32])?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_put_protocol_sources() -> Result<(), Error> {
+    let tmp_storage = TmpStorage::create("__protocol_storage_sources")?;
+    let mut storage = ProtocolStorage::new(tmp_storage.storage());
+
+    let protocol = Protocol::new(0, vec![
+        Component::new("Main".to_string(), Some("module type S = sig end".to_string()), "let x = 1".to_string()),
+        Component::new("Apply_results".to_string(), None, "let y = 2".to_string()),
+    ]);
+    let protocol_hash = protocol.message_hash()?;
+    let protocol_dir = protocol_sources("__protocol_storage_sources_dir", &HashType::ProtocolHash.bytes_to_string(&protocol_hash))?;
+
+    assert_eq!(protocol_hash, storage.put_sources(&protocol_dir)?);
+    let stored = storage.get(&protocol_hash)?.expect("Protocol should be stored");
+    assert_eq!(2, stored.components().len());
+    assert_eq!("Main", stored.components()[0].name());
+    assert_eq!(&Some("module type S = sig end".to_string()), stored.components()[0].interface());
+    assert_eq!("Apply_results", stored.components()[1].name());
+    assert_eq!(&None, stored.components()[1].interface());
+    assert_eq!("let y = 2", stored.components()[1].implementation());
+
+    fs::remove_dir_all(protocol_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_put_protocol_sources_hash_mismatch() -> Result<(), Error> {
+    let tmp_storage = TmpStorage::create("__protocol_storage_sources_mismatch")?;
+    let mut storage = ProtocolStorage::new(tmp_storage.storage());
+    let protocol_dir = protocol_sources("__protocol_storage_sources_mismatch_dir", "PsCARTHAGazKbHtnKfLzQg3kms52kSRpgnDY982a9oYsSXRLQEb")?;
+
+    match storage.put_sources(&protocol_dir) {
+        Err(ProtocolSourceError::HashMismatch { expected, .. }) => assert_eq!("PsCARTHAGazKbHtnKfLzQg3kms52kSRpgnDY982a9oYsSXRLQEb", expected),
+        result => panic!("Protocol with invalid hash should be rejected, but result was: {:?}", result),
+    }
+    assert!(!storage.contains(&HashType::ProtocolHash.string_to_bytes("PsCARTHAGazKbHtnKfLzQg3kms52kSRpgnDY982a9oYsSXRLQEb")?)?);
+
+    fs::remove_dir_all(protocol_dir)?;
+    Ok(())
+}
+
+/// Create directory with sources of the protocol in the layout of the Tezos repository
+fn protocol_sources(dir: &str, protocol_hash: &str) -> Result<PathBuf, Error> {
+    let protocol_dir = std::env::temp_dir().join(dir);
+    if protocol_dir.exists() {
+        fs::remove_dir_all(&protocol_dir)?;
+    }
+    fs::create_dir_all(&protocol_dir)?;
+    fs::write(protocol_dir.join("TEZOS_PROTOCOL"), format!(r#"{{"hash": "{}", "modules": ["Main", "Apply_results"]}}"#, protocol_hash))?;
+    fs::write(protocol_dir.join("main.mli"), "module type S = sig end")?;
+    fs::write(protocol_dir.join("main.ml"), "let x = 1")?;
+    fs::write(protocol_dir.join("apply_results.ml"), "let y = 2")?;
+    Ok(protocol_dir)
+}
@@ -0,0 +1,209 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::sync::Arc;
+
+use failure::Error;
+
+use crypto::hash::{BlockHash, HashType};
+use storage::*;
+use storage::persistent::PersistentStorage;
+use storage::pruning::{ensure_not_pruned, HistoryMode, StoragePruner};
+use storage::tests_common::TmpStorage;
+use tezos_context::channel::ContextAction;
+use tezos_messages::p2p::encoding::prelude::*;
+
+#[test]
+fn test_prune_level() -> Result<(), Error> {
+    let tmp_storage = TmpStorage::create("__storage_pruning")?;
+    let mut block_storage = BlockStorage::new(tmp_storage.storage());
+    let mut block_meta_storage = BlockMetaStorage::new(tmp_storage.storage());
+    let operations_storage = OperationsStorage::new(tmp_storage.storage());
+    let operations_meta_storage = OperationsMetaStorage::new(tmp_storage.storage());
+    let context_action_storage = ContextActionStorage::new(tmp_storage.storage());
+    let system_storage = SystemStorage::new(tmp_storage.storage().kv());
+
+    // main chain 1 <- 2 <- 3 and abandoned branch 1 <- 2b <- 3b
+    let contract_address = hex::decode("000003cb7d7842406496fc07288635562bfd17e176c4")?;
+    let block_1 = block(vec![1; 32], vec![0; 32], 1)?;
+    let block_2 = block(vec![2; 32], block_1.hash.clone(), 2)?;
+    let block_2b = block(vec![22; 32], block_1.hash.clone(), 2)?;
+    let block_3 = block(vec![3; 32], block_2.hash.clone(), 3)?;
+    let block_3b = block(vec![33; 32], block_2b.hash.clone(), 3)?;
+    for block in &[&block_1, &block_2, &block_3, &block_2b, &block_3b] {
+        store_block(tmp_storage.storage(), block)?;
+    }
+    block_meta_storage.store_current_head(&block_3.hash)?;
+    block_storage.put_block_additional_data(&block_3.hash, BlockAdditionalDataBuilder::default()
+        .max_operations_ttl(60)
+        .last_allowed_fork_level(3)
+        .build().unwrap())?;
+
+    let mut pruner = StoragePruner::new(tmp_storage.storage());
+    assert_eq!(None, pruner.pruned_level()?);
+    assert_eq!(3, pruner.last_allowed_fork_level()?);
+    pruner.prune_level(1)?;
+    pruner.compact()?;
+    assert_eq!(Some(1), pruner.pruned_level()?);
+
+    // data of the pruned block are removed, header and metadata are kept
+    assert_eq!(Some(block_1.clone()), block_storage.get(&block_1.hash)?);
+    assert!(block_storage.get_with_json_data(&block_1.hash)?.is_none());
+    assert!(operations_storage.get_operations(&block_1.hash)?.is_empty());
+    assert!(operations_meta_storage.get(&block_1.hash)?.is_none());
+    assert!(context_action_storage.get_by_block_hash(&block_1.hash)?.is_empty());
+    assert_eq!(&vec![block_2.hash.clone()], block_meta_storage.get(&block_1.hash)?.unwrap().successors());
+    match ensure_not_pruned(&system_storage, 1) {
+        Err(StorageError::PrunedError { level: 1, pruned_level: 1 }) => (),
+        result => panic!("Block should be pruned, but result was: {:?}", result),
+    }
+
+    // abandoned branch is deleted completely
+    for block in &[&block_2b, &block_3b] {
+        assert!(block_storage.get(&block.hash)?.is_none());
+        assert!(block_meta_storage.get(&block.hash)?.is_none());
+        assert!(operations_storage.get_operations(&block.hash)?.is_empty());
+        assert!(operations_meta_storage.get(&block.hash)?.is_none());
+        assert!(context_action_storage.get_by_block_hash(&block.hash)?.is_empty());
+        assert!(block_storage.get_by_context_hash(block.header.context())?.is_none());
+    }
+    assert_eq!(Some(block_3.clone()), block_storage.get_by_block_level(3)?);
+
+    // data of the next block are untouched
+    assert!(block_storage.get_with_json_data(&block_2.hash)?.is_some());
+    assert_eq!(Some(block_2.clone()), block_storage.get_by_block_level(2)?);
+    assert_eq!(1, operations_storage.get_operations(&block_2.hash)?.len());
+    assert!(operations_meta_storage.get(&block_2.hash)?.is_some());
+    assert_eq!(1, context_action_storage.get_by_block_hash(&block_2.hash)?.len());
+    assert_eq!(2, context_action_storage.get_by_contract_address(&contract_address, None, 10)?.len());
+    assert!(ensure_not_pruned(&system_storage, 2).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_compact_commit_logs() -> Result<(), Error> {
+    let tmp_storage = TmpStorage::create("__storage_pruning_compact_commit_logs")?;
+    let mut block_storage = BlockStorage::new(tmp_storage.storage());
+    let mut block_meta_storage = BlockMetaStorage::new(tmp_storage.storage());
+    let context_action_storage = ContextActionStorage::new(tmp_storage.storage());
+
+    let contract_address = hex::decode("000003cb7d7842406496fc07288635562bfd17e176c4")?;
+    let block_1 = block(vec![1; 32], vec![0; 32], 1)?;
+    let block_2 = block(vec![2; 32], block_1.hash.clone(), 2)?;
+    let block_3 = block(vec![3; 32], block_2.hash.clone(), 3)?;
+    for block in &[&block_1, &block_2, &block_3] {
+        store_block(tmp_storage.storage(), block)?;
+        block_storage.assign_to_context(&block.hash, block.header.context())?;
+    }
+    block_meta_storage.store_current_head(&block_3.hash)?;
+    block_storage.put_block_additional_data(&block_3.hash, BlockAdditionalDataBuilder::default()
+        .max_operations_ttl(60)
+        .last_allowed_fork_level(3)
+        .build().unwrap())?;
+
+    let mut pruner = StoragePruner::new(tmp_storage.storage());
+    // nothing was pruned yet
+    assert!(!pruner.compact_commit_logs()?);
+
+    pruner.prune_level(1)?;
+    assert!(pruner.compact_commit_logs()?);
+    assert!(!pruner.compact_commit_logs()?);
+
+    // all indexes point to the rewritten records
+    assert_eq!(Some(block_1.clone()), block_storage.get(&block_1.hash)?);
+    assert!(block_storage.get_with_json_data(&block_1.hash)?.is_none());
+    assert_eq!(Some(block_1.clone()), block_storage.get_by_context_hash(block_1.header.context())?);
+    for block in &[&block_2, &block_3] {
+        assert_eq!(Some((*block).clone()), block_storage.get(&block.hash)?);
+        assert!(block_storage.get_with_json_data(&block.hash)?.is_some());
+        assert_eq!(Some((*block).clone()), block_storage.get_by_block_level(block.header.level())?);
+        assert_eq!(Some((*block).clone()), block_storage.get_by_context_hash(block.header.context())?);
+        assert_eq!(1, context_action_storage.get_by_block_hash(&block.hash)?.len());
+    }
+    assert_eq!(3, block_storage.get_with_additional_data(&block_3.hash)?.unwrap().1.last_allowed_fork_level());
+    assert_eq!(2, context_action_storage.get_by_contract_address(&contract_address, None, 10)?.len());
+
+    // new records are appended after the rewritten ones
+    let block_4 = block(vec![4; 32], block_3.hash.clone(), 4)?;
+    store_block(tmp_storage.storage(), &block_4)?;
+    assert_eq!(Some(block_4.clone()), block_storage.get(&block_4.hash)?);
+    assert_eq!(Some(block_3.clone()), block_storage.get(&block_3.hash)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_history_mode() {
+    assert_eq!(Ok(HistoryMode::Full), "full".parse::<HistoryMode>());
+    assert_eq!(Ok(HistoryMode::Rolling { cycles: 5 }), "rolling:5".parse::<HistoryMode>());
+    assert_eq!("rolling:5", HistoryMode::Rolling { cycles: 5 }.to_string());
+    assert!("rolling:0".parse::<HistoryMode>().is_err());
+    assert!("rolling".parse::<HistoryMode>().is_err());
+    assert!("archive".parse::<HistoryMode>().is_err());
+}
+
+/// Store header, metadata, json data, operations and context action of the block
+fn store_block(persistent_storage: &PersistentStorage, block: &BlockHeaderWithHash) -> Result<(), Error> {
+    let chain_id = vec![1, 2, 3, 4];
+    BlockStorage::new(persistent_storage).put_block_header(block)?;
+    BlockMetaStorage::new(persistent_storage).put_block_header(block, &chain_id)?;
+    BlockStorage::new(persistent_storage).put_block_json_data(&block.hash, BlockJsonDataBuilder::default()
+        .block_header_proto_json("{}".to_string())
+        .block_header_proto_metadata_json("{}".to_string())
+        .operations_proto_metadata_json("[]".to_string())
+        .build().unwrap())?;
+    OperationsMetaStorage::new(persistent_storage).put_block_header(block, &chain_id)?;
+    let operations = OperationsForBlocksMessage::new(OperationsForBlock::new(block.hash.clone(), 0), Path::Op, vec![]);
+    OperationsStorage::new(persistent_storage).put_operations(&operations)?;
+    OperationsMetaStorage::new(persistent_storage).put_operations(&operations)?;
+    ContextActionStorage::new(persistent_storage).put_action(&block.hash, contract_action(&block.hash))?;
+    Ok(())
+}
+
+fn block(block_hash: BlockHash, predecessor: BlockHash, level: i32) -> Result<BlockHeaderWithHash, Error> {
+    Ok(
+        BlockHeaderWithHash {
+            hash: block_hash.clone(),
+            header: Arc::new(
+                BlockHeaderBuilder::default()
+                    .level(level)
+                    .proto(0)
+                    .predecessor(predecessor)
+                    .timestamp(5_635_634)
+                    .validation_pass(1)
+                    .operations_hash(HashType::OperationListListHash.string_to_bytes("LLoaGLRPRx3Zf8kB4ACtgku8F4feeBiskeb41J1ciwfcXB3KzHKXc")?)
+                    .fitness(vec![])
+                    .context(block_hash)
+                    .protocol_data(vec![])
+                    .build().unwrap()
+            ),
+        }
+    )
+}
+
+fn contract_action(block_hash: &[u8]) -> ContextAction {
+    ContextAction::Set {
+        key: vec![
+            "data".to_string(),
+            "contracts".to_string(),
+            "index".to_string(),
+            "ad".to_string(),
+            "af".to_string(),
+            "43".to_string(),
+            "23".to_string(),
+            "f9".to_string(),
+            "3e".to_string(),
+            "000003cb7d7842406496fc07288635562bfd17e176c4".to_string(),
+            "balance".to_string()
+        ],
+        value: vec![10, 200],
+        operation_hash: None,
+        block_hash: Some(HashType::BlockHash.bytes_to_string(block_hash)),
+        context_hash: None,
+        value_as_json: None,
+        start_time: 0.0,
+        end_time: 0.0,
+        ignored: false,
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::sync::Arc;
+
+use failure::Error;
+
+use crypto::hash::{chain_id_from_block_hash, ChainId, HashType};
+use storage::*;
+use storage::block_meta_storage::Meta;
+use storage::context::{ContextApiRef, ContextBackend};
+use storage::snapshot::{export_snapshot, read_snapshot_header, SnapshotError};
+use storage::tests_common::TmpStorage;
+use tezos_messages::p2p::encoding::prelude::*;
+
+#[test]
+fn test_snapshot_export() -> Result<(), Error> {
+    let genesis = block("BLockGenesisGenesisGenesisGenesisGenesisb83baZgbyZe", "BLockGenesisGenesisGenesisGenesisGenesisb83baZgbyZe", 0, "CoVmAcMV64uAQo8XvfLr9VDuz7HVZLT4cgK1w1qYmTjQNbGwQwDd")?;
+    let block_1 = block("BKyQ9EofHrgaZKENioHyP4FZNsTmiSEcVmcghgzCC9cGhE7oCET", "BLockGenesisGenesisGenesisGenesisGenesisb83baZgbyZe", 1, "CoV16kW8WgL51SpcftQKdeqc94D6ekghMgPMmEn7TSZzFA697PeE")?;
+    let block_2 = block("BKjcGSmAbSoBvxvXkrzG7XZ722cW8sfQu6tyykT1e7tDcNJvj9m", "BKyQ9EofHrgaZKENioHyP4FZNsTmiSEcVmcghgzCC9cGhE7oCET", 2, "CoUfdnduWrSwV7bGLvrtEu3gMXU2BLYEq2eUTGnx66EPByKDAxPY")?;
+    let chain_id = chain_id_from_block_hash(&genesis.hash);
+
+    // prepare storage with applied genesis and blocks
+    let source_storage = TmpStorage::create("__snapshot_export")?;
+    let mut block_storage = BlockStorage::new(source_storage.storage());
+    let mut block_meta_storage = BlockMetaStorage::new(source_storage.storage());
+    let mut operations_storage = OperationsStorage::new(source_storage.storage());
+    let mut operations_meta_storage = OperationsMetaStorage::new(source_storage.storage());
+
+    block_storage.put_block_header(&genesis)?;
+    block_meta_storage.put(&genesis.hash, &Meta::genesis_meta(&genesis.hash, &chain_id, true))?;
+    operations_meta_storage.put_block_header(&genesis, &chain_id)?;
+
+    for block in &[&block_1, &block_2] {
+        block_storage.put_block_header(block)?;
+        block_meta_storage.put_block_header(block, &chain_id)?;
+        let mut meta = block_meta_storage.get(&block.hash)?.expect("No metadata was saved");
+        meta.set_is_applied(true);
+        block_meta_storage.put(&block.hash, &meta)?;
+        block_storage.put_block_json_data(&block.hash, BlockJsonDataBuilder::default()
+            .block_header_proto_json("{block_header_proto_json}".to_string())
+            .block_header_proto_metadata_json("{block_header_proto_metadata_json}".to_string())
+            .operations_proto_metadata_json("{operations_proto_metadata_json}".to_string())
+            .build().unwrap())?;
+        // only one predecessor is needed to validate operations
+        block_storage.put_block_additional_data(&block.hash, BlockAdditionalDataBuilder::default()
+            .max_operations_ttl(1)
+            .last_allowed_fork_level(0)
+            .build().unwrap())?;
+        operations_meta_storage.put_block_header(block, &chain_id)?;
+        let operations = OperationsForBlocksMessage::new(OperationsForBlock::new(block.hash.clone(), 0), Path::Op, vec![]);
+        operations_storage.put_operations(&operations)?;
+        operations_meta_storage.put_operations(&operations)?;
+    }
+
+    let context = ContextBackend::SkipList.create(source_storage.storage());
+    {
+        let mut context = context.write().expect("lock poisoning");
+        let mut context_diff = context.init_from_start();
+        context_diff.set(&None, &key("protocol"), &vec![1])?;
+        context_diff.set(&None, &key("data/removed"), &vec![2])?;
+        context.commit(&genesis.hash, &None, genesis.header.context(), &context_diff)?;
+
+        let mut context_diff = context.checkout(genesis.header.context())?;
+        context_diff.set(&Some(genesis.header.context().clone()), &key("data/rolls"), &vec![3])?;
+        context.delete_to_diff(&Some(genesis.header.context().clone()), &key("data/removed"), &mut context_diff)?;
+        context.commit(&block_1.hash, &Some(genesis.header.context().clone()), block_1.header.context(), &context_diff)?;
+    }
+
+    // context of the snapshot block has to be committed
+    match export_snapshot(source_storage.storage(), &context, &block_2.hash, Vec::new()) {
+        Err(SnapshotError::ContextError { .. }) => (),
+        result => panic!("Export should fail, but result was: {:?}", result),
+    }
+    {
+        let mut context = context.write().expect("lock poisoning");
+        let mut context_diff = context.checkout(block_1.header.context())?;
+        context_diff.set(&Some(block_1.header.context().clone()), &key("data/votes"), &vec![4])?;
+        context.commit(&block_2.hash, &Some(block_1.header.context().clone()), block_2.header.context(), &context_diff)?;
+    }
+
+    // export contains only the block and its recent history
+    let mut snapshot = Vec::new();
+    let exported = export_snapshot(source_storage.storage(), &context, &block_2.hash, &mut snapshot)?;
+    assert_eq!(block_2.hash, exported.block_hash);
+    assert_eq!(2, exported.level);
+    assert_eq!(2, exported.block_count);
+    assert_eq!(exported, read_snapshot_header(&chain_id, snapshot.as_slice())?);
+
+    // snapshot of another chain is not accepted
+    let other_chain_id: ChainId = HashType::ChainId.string_to_bytes("NetXgtSLGNJvNye")?;
+    match read_snapshot_header(&other_chain_id, snapshot.as_slice()) {
+        Err(SnapshotError::ChainMismatch { .. }) => (),
+        result => panic!("Reading of the header should fail, but result was: {:?}", result),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_export_not_applied_block() -> Result<(), Error> {
+    let genesis = block("BLockGenesisGenesisGenesisGenesisGenesisb83baZgbyZe", "BLockGenesisGenesisGenesisGenesisGenesisb83baZgbyZe", 0, "CoVmAcMV64uAQo8XvfLr9VDuz7HVZLT4cgK1w1qYmTjQNbGwQwDd")?;
+    let chain_id = chain_id_from_block_hash(&genesis.hash);
+
+    let tmp_storage = TmpStorage::create("__snapshot_export_not_applied")?;
+    let mut block_storage = BlockStorage::new(tmp_storage.storage());
+    let mut block_meta_storage = BlockMetaStorage::new(tmp_storage.storage());
+    block_storage.put_block_header(&genesis)?;
+    block_meta_storage.put(&genesis.hash, &Meta::genesis_meta(&genesis.hash, &chain_id, false))?;
+
+    let context: ContextApiRef = ContextBackend::SkipList.create(tmp_storage.storage());
+    match export_snapshot(tmp_storage.storage(), &context, &genesis.hash, Vec::new()) {
+        Err(SnapshotError::InvalidBlock { .. }) => (),
+        result => panic!("Export should fail, but result was: {:?}", result),
+    }
+
+    Ok(())
+}
+
+fn block(block_hash: &str, predecessor: &str, level: i32, context_hash: &str) -> Result<BlockHeaderWithHash, Error> {
+    Ok(
+        BlockHeaderWithHash {
+            hash: HashType::BlockHash.string_to_bytes(block_hash)?,
+            header: Arc::new(
+                BlockHeaderBuilder::default()
+                    .level(level)
+                    .proto(0)
+                    .predecessor(HashType::BlockHash.string_to_bytes(predecessor)?)
+                    .timestamp(5_635_634)
+                    .validation_pass(if level == 0 { 0 } else { 1 })
+                    .operations_hash(HashType::OperationListListHash.string_to_bytes("LLoaGLRPRx3Zf8kB4ACtgku8F4feeBiskeb41J1ciwfcXB3KzHKXc")?)
+                    .fitness(vec![])
+                    .context(HashType::ContextHash.string_to_bytes(context_hash)?)
+                    .protocol_data(vec![])
+                    .build().unwrap()
+            ),
+        }
+    )
+}
+
+fn key(key: &str) -> Vec<String> {
+    key.split('/').map(|k| k.to_string()).collect()
+}
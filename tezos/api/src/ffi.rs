@@ -21,6 +21,12 @@ lazy_static! {
             Field::new("max_operations_ttl", Encoding::Int31),
             Field::new("operations", Encoding::dynamic(Encoding::list(Encoding::dynamic(Encoding::list(Encoding::dynamic(Operation::encoding())))))),
     ]);
+
+    pub static ref VALIDATE_OPERATION_REQUEST_ENCODING: Encoding = Encoding::Obj(vec![
+            Field::new("chain_id", Encoding::Hash(HashType::ChainId)),
+            Field::new("block_header", Encoding::dynamic(BlockHeader::encoding())),
+            Field::new("operation", Encoding::dynamic(Operation::encoding())),
+    ]);
 }
 
 pub type RustBytes = Vec<u8>;
@@ -95,6 +101,65 @@ impl CachedData for ApplyBlockRequest {
     }
 }
 
+/// Request for prevalidation of the mempool operation on top of the `block_header` (current head)
+#[derive(Serialize, Deserialize, Debug, Clone, Builder)]
+pub struct ValidateOperationRequest {
+    pub chain_id: ChainId,
+    pub block_header: BlockHeader,
+    pub operation: Operation,
+    #[serde(skip_serializing)]
+    #[builder(default)]
+    body: BinaryDataCache,
+}
+
+impl CachedData for ValidateOperationRequest {
+    #[inline]
+    fn cache_reader(&self) -> &dyn CacheReader {
+        &self.body
+    }
+
+    #[inline]
+    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
+        Some(&mut self.body)
+    }
+}
+
+/// Classification of the operation by the prevalidator, same as in the tezos mempool
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
+pub enum OperationClassification {
+    /// Operation was successfully applied on top of the current head
+    Applied,
+    /// Operation is invalid, and will be never valid
+    Refused,
+    /// Operation is invalid on top of the current branch, but can be valid on another branch
+    BranchRefused,
+    /// Operation is not valid yet on the current branch, but it can be valid later
+    BranchDelayed,
+}
+
+impl OperationClassification {
+    /// Converts tag returned by OCaml prevalidator
+    pub fn from_tag(tag: usize) -> Option<OperationClassification> {
+        match tag {
+            0 => Some(OperationClassification::Applied),
+            1 => Some(OperationClassification::Refused),
+            2 => Some(OperationClassification::BranchRefused),
+            3 => Some(OperationClassification::BranchDelayed),
+            _ => None
+        }
+    }
+}
+
+/// Operation prevalidation result
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ValidateOperationResult {
+    pub classification: OperationClassification,
+    /// Protocol data of the operation (contents and signature) decoded by protocol as json
+    pub protocol_data_json: String,
+    /// Errors returned by protocol as json, empty for applied operations
+    pub error_json: String,
+}
+
 /// Application block result
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct ApplyBlockResult {
@@ -279,6 +344,37 @@ impl From<ocaml::Error> for ApplyBlockError {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Fail, PartialEq)]
+pub enum ValidateOperationError {
+    #[fail(display = "Failed to validate operation - message: {}!", message)]
+    FailedToValidateOperation {
+        message: String,
+    },
+    #[fail(display = "Invalid validate operation request data - message: {}!", message)]
+    InvalidValidateOperationRequestData {
+        message: String,
+    },
+    #[fail(display = "Operation validation is not supported by the tezos library - message: {}!", message)]
+    OperationValidationNotSupported {
+        message: String,
+    },
+}
+
+impl From<ocaml::Error> for ValidateOperationError {
+    fn from(error: ocaml::Error) -> Self {
+        match error {
+            ocaml::Error::Exception(ffi_error) => {
+                ValidateOperationError::FailedToValidateOperation {
+                    message: parse_error_message(ffi_error).unwrap_or_else(|| "unknown".to_string())
+                }
+            }
+            _ => ValidateOperationError::FailedToValidateOperation {
+                message: format!("Unhandled ocaml error occurred for validate operation! Error: {:?}", error)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Fail)]
 pub enum BlockHeaderError {
     #[fail(display = "BlockHeader cannot be read from storage: {}!", message)]
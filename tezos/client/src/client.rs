@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: MIT
 
 use crypto::hash::{ChainId, ContextHash, ProtocolHash};
-use tezos_api::ffi::{APPLY_BLOCK_REQUEST_ENCODING, ApplyBlockError, ApplyBlockRequest, ApplyBlockRequestBuilder, ApplyBlockResult, CommitGenesisResult, ContextDataError, GenesisChain, GetDataError, InitProtocolContextResult, ProtocolOverrides, TezosGenerateIdentityError, TezosRuntimeConfiguration, TezosRuntimeConfigurationError, TezosStorageInitError};
+use tezos_api::ffi::{APPLY_BLOCK_REQUEST_ENCODING, ApplyBlockError, ApplyBlockRequest, ApplyBlockRequestBuilder, ApplyBlockResult, CommitGenesisResult, ContextDataError, GenesisChain, GetDataError, InitProtocolContextResult, ProtocolOverrides, TezosGenerateIdentityError, TezosRuntimeConfiguration, TezosRuntimeConfigurationError, TezosStorageInitError, VALIDATE_OPERATION_REQUEST_ENCODING, ValidateOperationError, ValidateOperationRequest, ValidateOperationRequestBuilder, ValidateOperationResult};
 use tezos_api::identity::Identity;
 use tezos_encoding::binary_writer;
 use tezos_interop::ffi;
@@ -99,6 +99,38 @@ pub fn apply_block(
     }
 }
 
+/// Prevalidates operation for the mempool, means:
+/// - operation is decoded by the protocol
+/// - operation is applied on top of the `block_header` (current head) in the temporary context
+/// - returns classification of the operation (applied, refused, branch_refused, branch_delayed)
+pub fn validate_operation(
+    chain_id: &ChainId,
+    block_header: &BlockHeader,
+    operation: &Operation) -> Result<ValidateOperationResult, ValidateOperationError> {
+
+    // request
+    let request: ValidateOperationRequest = ValidateOperationRequestBuilder::default()
+        .chain_id(chain_id.clone())
+        .block_header(block_header.clone())
+        .operation(operation.clone())
+        .build().unwrap();
+
+    // write to bytes
+    let request = match binary_writer::write(&request, &VALIDATE_OPERATION_REQUEST_ENCODING) {
+        Ok(data) => data,
+        Err(e) => return Err(ValidateOperationError::InvalidValidateOperationRequestData { message: format!("{:?}", e) })
+    };
+
+    match ffi::validate_operation(request) {
+        Ok(result) => result,
+        Err(e) => {
+            Err(ValidateOperationError::FailedToValidateOperation {
+                message: format!("Unknown OcamlError: {:?}", e)
+            })
+        }
+    }
+}
+
 /// Generate tezos identity
 pub fn generate_identity(expected_pow: f64) -> Result<Identity, TezosGenerateIdentityError> {
     match ffi::generate_identity(expected_pow) {
@@ -3,6 +3,8 @@
 
 //! Tezos binary data reader.
 
+use std::collections::HashMap;
+
 use bitvec::{Bits, BitVec};
 use bytes::Buf;
 use bytes::buf::ext::BufExt;
@@ -38,6 +40,13 @@ pub enum BinaryReaderError {
     UnsupportedTag {
         tag: u16
     },
+    /// Value received by a [StreamReader] is larger than allowed by its [SizeLimit].
+    #[fail(display = "Message of {} bytes exceeds the limit of {} bytes", size, max_size)]
+    MessageTooLarge {
+        size: usize,
+        max_size: usize,
+        tag: Option<u16>,
+    },
 }
 
 impl From<crate::de::Error> for BinaryReaderError {
@@ -181,6 +190,11 @@ impl BinaryReader {
                 let mut buf_slice = safe!(buf, bytes_sz, buf.take(bytes_sz));
                 self.decode_value(&mut buf_slice, dynamic_encoding)
             }
+            Encoding::ShortDynamic(dynamic_encoding) => {
+                let bytes_sz = safe!(buf, get_u8, u8) as usize;
+                let mut buf_slice = safe!(buf, bytes_sz, buf.take(bytes_sz));
+                self.decode_value(&mut buf_slice, dynamic_encoding)
+            }
             Encoding::Sized(sized_size, sized_encoding) => {
                 let mut buf_slice = safe!(buf, *sized_size, buf.take(*sized_size));
                 self.decode_value(&mut buf_slice, sized_encoding)
@@ -334,6 +348,282 @@ impl BinaryReader {
             | Encoding::RangedFloat => Err(de::Error::custom(format!("Unsupported encoding {:?}", encoding)).into())
         }
     }
+
+    /// Advance input buffer past the value without constructing it. Consumes the same number of bytes as [decode_value](BinaryReader::decode_value).
+    ///
+    /// Outermost tagged values found in the input are stored to `tagged`.
+    fn skip_value(&self, buf: &mut dyn Buf, encoding: &Encoding, tagged: &mut TaggedValues) -> Result<(), BinaryReaderError> {
+        match encoding {
+            Encoding::Unit => Ok(()),
+            Encoding::Int8
+            | Encoding::Uint8
+            | Encoding::Bool
+            | Encoding::Enum => skip_bytes(buf, 1),
+            Encoding::Int16
+            | Encoding::Uint16 => skip_bytes(buf, 2),
+            Encoding::Int31
+            | Encoding::Int32 => skip_bytes(buf, 4),
+            Encoding::Int64
+            | Encoding::Timestamp
+            | Encoding::Float => skip_bytes(buf, 8),
+            Encoding::String => {
+                let bytes_sz = safe!(buf, get_u32, u32) as usize;
+                skip_bytes(buf, bytes_sz)
+            }
+            Encoding::Dynamic(dynamic_encoding) => {
+                let bytes_sz = safe!(buf, get_u32, u32) as usize;
+                self.skip_dynamic(buf, bytes_sz, dynamic_encoding, tagged)
+            }
+            Encoding::ShortDynamic(dynamic_encoding) => {
+                let bytes_sz = safe!(buf, get_u8, u8) as usize;
+                self.skip_dynamic(buf, bytes_sz, dynamic_encoding, tagged)
+            }
+            Encoding::Sized(sized_size, sized_encoding) => {
+                let mut buf_slice = safe!(buf, *sized_size, buf.take(*sized_size));
+                self.skip_value(&mut buf_slice, sized_encoding, tagged)
+            }
+            Encoding::Greedy(un_sized_encoding) => {
+                let bytes_sz = buf.remaining();
+                let mut buf_slice = buf.take(bytes_sz);
+                self.skip_value(&mut buf_slice, un_sized_encoding, tagged)
+            }
+            Encoding::Tags(tag_sz, ref tag_map) => {
+                let remaining = buf.remaining();
+                let tag_id = match tag_sz {
+                    /*u8*/  1 => Ok(u16::from(safe!(buf, get_u8, u8))),
+                    /*u16*/ 2 => Ok(safe!(buf, get_u16, u16)),
+                    _ => Err(de::Error::custom(format!("Unsupported tag size {}", tag_sz)))
+                }?;
+
+                // only the outermost tagged values (e.g. p2p messages) are recorded
+                let outermost = tagged.depth == 0;
+                if outermost {
+                    tagged.values.push(TaggedValue { tag: tag_id, remaining, size: None });
+                }
+                tagged.depth += 1;
+                let result = match tag_map.find_by_id(tag_id) {
+                    Some(tag) => self.skip_value(buf, tag.get_encoding(), tagged),
+                    None => Err(BinaryReaderError::UnsupportedTag { tag: tag_id })
+                };
+                tagged.depth -= 1;
+                if outermost && result.is_ok() {
+                    if let Some(value) = tagged.values.last_mut() {
+                        value.size = Some(remaining - buf.remaining());
+                    }
+                }
+                result
+            }
+            Encoding::List(encoding_inner) => {
+                let bytes_sz = buf.remaining();
+                let mut buf_slice = buf.take(bytes_sz);
+                while buf_slice.remaining() > 0 {
+                    self.skip_value(&mut buf_slice, encoding_inner, tagged)?;
+                }
+                Ok(())
+            }
+            Encoding::Option(_) => {
+                let is_present_byte = safe!(buf, get_u8, u8);
+                match is_present_byte {
+                    types::BYTE_VAL_SOME => self.skip_value(buf, encoding.try_unwrap_option_encoding(), tagged),
+                    types::BYTE_VAL_NONE => Ok(()),
+                    _ => Err(de::Error::custom(format!("Unexpected option value {:X}", is_present_byte)).into())
+                }
+            }
+            Encoding::Obj(schema_inner) => {
+                for field in schema_inner {
+                    self.skip_value(buf, field.get_encoding(), tagged)?;
+                }
+                Ok(())
+            }
+            Encoding::Tup(encodings_inner) => {
+                for encoding_inner in encodings_inner {
+                    self.skip_value(buf, encoding_inner, tagged)?;
+                }
+                Ok(())
+            }
+            Encoding::Z
+            | Encoding::Mutez => {
+                // the highest bit of every byte but the last one is set
+                while safe!(buf, get_u8, u8) & 0x80 != 0 {}
+                Ok(())
+            }
+            Encoding::Bytes => {
+                let bytes_sz = buf.remaining();
+                skip_bytes(buf, bytes_sz)
+            }
+            Encoding::Hash(hash_type) => skip_bytes(buf, hash_type.size()),
+            Encoding::Split(inner_encoding) => {
+                let inner_encoding = inner_encoding(SchemaType::Binary);
+                self.skip_value(buf, &inner_encoding, tagged)
+            }
+            Encoding::Lazy(fn_encoding) => {
+                let inner_encoding = fn_encoding();
+                self.skip_value(buf, &inner_encoding, tagged)
+            }
+            Encoding::Uint32
+            | Encoding::RangedInt
+            | Encoding::RangedFloat => Err(de::Error::custom(format!("Unsupported encoding {:?}", encoding)).into())
+        }
+    }
+
+    /// Skip length prefixed value. Available part of incomplete value is scanned too, so that its tags are known
+    /// and malformed input is reported before the rest of the value is received.
+    fn skip_dynamic(&self, buf: &mut dyn Buf, bytes_sz: usize, encoding: &Encoding, tagged: &mut TaggedValues) -> Result<(), BinaryReaderError> {
+        let available = buf.remaining();
+        if available >= bytes_sz {
+            let mut buf_slice = buf.take(bytes_sz);
+            return self.skip_value(&mut buf_slice, encoding, tagged);
+        }
+
+        let mut buf_slice = buf.take(available);
+        match self.skip_value(&mut buf_slice, encoding, tagged) {
+            // unsupported tag is reported only after the whole value is received, so that reader can continue with the next value
+            Ok(_)
+            | Err(BinaryReaderError::Underflow { .. })
+            | Err(BinaryReaderError::UnsupportedTag { .. }) => Err(BinaryReaderError::Underflow { bytes: bytes_sz - available }),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Advance input buffer by `bytes_sz` bytes.
+#[inline]
+fn skip_bytes(buf: &mut dyn Buf, bytes_sz: usize) -> Result<(), BinaryReaderError> {
+    safe!(buf, bytes_sz, buf.advance(bytes_sz));
+    Ok(())
+}
+
+/// Maximal size in bytes of the value accepted by the [StreamReader].
+///
+/// Every outermost tagged value (e.g. every p2p message of the peer message response) has to fit the limit of its own tag
+/// and the whole value has to fit the highest limit of the tags it contains. While the value is incomplete,
+/// only the tags received so far are considered.
+#[derive(Clone, Debug)]
+pub enum SizeLimit {
+    /// Same limit applies to every value
+    Fixed(usize),
+    /// Limit depends on the tag of the value (e.g. on the kind of the p2p message), `default` applies to tags without a limit
+    ByTag {
+        default: usize,
+        limits: HashMap<u16, usize>,
+    },
+}
+
+impl SizeLimit {
+    /// Get maximal size of the value with the `tag`. Until the tag is known, the highest of the limits applies.
+    pub fn max_size(&self, tag: Option<u16>) -> usize {
+        match self {
+            SizeLimit::Fixed(max_size) => *max_size,
+            SizeLimit::ByTag { default, limits } => match tag {
+                Some(tag) => limits.get(&tag).cloned().unwrap_or(*default),
+                None => limits.values().cloned().fold(*default, usize::max),
+            }
+        }
+    }
+}
+
+/// Outermost tagged value found by [BinaryReader::skip_value].
+struct TaggedValue {
+    tag: u16,
+    /// Number of input bytes starting with the tag
+    remaining: usize,
+    /// Size of the tagged value, `None` if the value is incomplete
+    size: Option<usize>,
+}
+
+/// Tagged values found by [BinaryReader::skip_value], nested tagged values are not recorded.
+#[derive(Default)]
+struct TaggedValues {
+    /// Number of tagged values being skipped
+    depth: usize,
+    values: Vec<TaggedValue>,
+}
+
+/// Resumable reader of the binary data received in chunks (e.g. p2p message split into multiple encrypted chunks).
+///
+/// Received chunks are buffered and the reader remembers how many bytes are missing to complete the value,
+/// so the input is scanned again only after at least that many bytes are received. Value is never decoded
+/// from incomplete input, binary form of the value is returned as soon as all its bytes are buffered.
+pub struct StreamReader {
+    encoding: Encoding,
+    size_limit: SizeLimit,
+    /// Received bytes of the incomplete value
+    buffer: Vec<u8>,
+    /// Buffer has to contain at least this many bytes to complete the value
+    required: usize,
+}
+
+impl StreamReader {
+    /// Construct new instance of the [StreamReader], which reads values of the `encoding`.
+    pub fn new(encoding: Encoding, size_limit: SizeLimit) -> Self {
+        StreamReader {
+            encoding,
+            size_limit,
+            buffer: Vec::new(),
+            required: 0,
+        }
+    }
+
+    /// Append received chunk to the buffered input.
+    ///
+    /// Returns binary form of the value when all its bytes were received and resets the reader so it can read the next value.
+    /// Value larger than allowed by the [SizeLimit] is reported as [BinaryReaderError::MessageTooLarge] without waiting for the rest of it.
+    /// Reader is reset after an error too.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Option<Vec<u8>>, BinaryReaderError> {
+        self.buffer.extend_from_slice(chunk);
+        if self.buffer.len() < self.required {
+            return Ok(None);
+        }
+
+        let result = self.read_buffered();
+        if result.is_err() {
+            self.buffer.clear();
+            self.required = 0;
+        }
+        result
+    }
+
+    fn read_buffered(&mut self) -> Result<Option<Vec<u8>>, BinaryReaderError> {
+        let mut tagged = TaggedValues::default();
+        let mut buf = &self.buffer[..];
+        let result = BinaryReader::new().skip_value(&mut buf, &self.encoding, &mut tagged);
+
+        // every tagged value has to fit the limit of its tag, received part is checked for the incomplete one
+        for value in &tagged.values {
+            let max_size = self.size_limit.max_size(Some(value.tag));
+            let size = value.size.unwrap_or(value.remaining);
+            if size > max_size {
+                return Err(BinaryReaderError::MessageTooLarge { size, max_size, tag: Some(value.tag) });
+            }
+        }
+        let (max_size, tag) = tagged.values.iter()
+            .map(|value| (self.size_limit.max_size(Some(value.tag)), Some(value.tag)))
+            .max_by_key(|(max_size, _)| *max_size)
+            .unwrap_or_else(|| (self.size_limit.max_size(None), None));
+
+        match result {
+            Ok(()) => {
+                let size = self.buffer.len() - buf.remaining();
+                if size > max_size {
+                    return Err(BinaryReaderError::MessageTooLarge { size, max_size, tag });
+                }
+                if buf.remaining() > 0 {
+                    return Err(BinaryReaderError::Overflow { bytes: buf.remaining() });
+                }
+                self.required = 0;
+                Ok(Some(std::mem::take(&mut self.buffer)))
+            }
+            Err(BinaryReaderError::Underflow { bytes }) => {
+                self.required = self.buffer.len() + bytes;
+                if self.required > max_size {
+                    Err(BinaryReaderError::MessageTooLarge { size: self.required, max_size, tag })
+                } else {
+                    Ok(None)
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -423,6 +713,31 @@ mod tests {
         assert_eq!(expected_value, value)
     }
 
+    #[test]
+    fn can_deserialize_short_dynamic_from_binary() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Record {
+            name: Vec<u8>,
+            level: i32,
+        }
+        let record_encoding = Encoding::Obj(vec![
+            Field::new("name", Encoding::short_dynamic(Encoding::Bytes)),
+            Field::new("level", Encoding::Int32),
+        ]);
+
+        let record_buf = hex::decode("087472616e73666572000008c3").unwrap();
+        let reader = BinaryReader::new();
+        let value = reader.read(record_buf.clone(), &record_encoding).unwrap();
+        let record: Record = de::from_value(&value).unwrap();
+        assert_eq!(Record { name: b"transfer".to_vec(), level: 2243 }, record);
+
+        // serialize back
+        assert_eq!(record_buf, binary_writer::write(&record, &record_encoding).unwrap());
+
+        // size exceeds remaining data
+        assert!(reader.read(hex::decode("09747261").unwrap(), &record_encoding).is_err());
+    }
+
     #[test]
     fn can_deserialize_z_range() {
         #[derive(Serialize, Deserialize, Debug)]
@@ -500,4 +815,99 @@ mod tests {
         let connection_message_deserialized: ConnectionMessage = de::from_value(&value).unwrap();
         assert_eq!(connection_message, connection_message_deserialized);
     }
+
+    fn stream_message_encoding() -> Encoding {
+        Encoding::Obj(vec![
+            Field::new("messages", Encoding::dynamic(Encoding::list(
+                Encoding::Tags(
+                    size_of::<u16>(),
+                    TagMap::new(&[
+                        Tag::new(0x10, "GetHead", Encoding::Obj(vec![Field::new("chain_id", Encoding::sized(4, Encoding::Bytes))])),
+                        Tag::new(0x11, "Head", Encoding::Obj(vec![Field::new("data", Encoding::dynamic(Encoding::Bytes))])),
+                    ]),
+                )
+            )))
+        ])
+    }
+
+    #[test]
+    fn stream_reader_returns_message_when_all_chunks_are_received() {
+        let message = hex::decode("0000000600108eceda2f").unwrap();
+        let mut reader = StreamReader::new(stream_message_encoding(), SizeLimit::Fixed(1024));
+
+        assert_eq!(None, reader.feed(&message[0..2]).unwrap());
+        assert_eq!(None, reader.feed(&message[2..5]).unwrap());
+        // length of the message is known now, input is not scanned again until all bytes are received
+        assert_eq!(10, reader.required);
+        assert_eq!(None, reader.feed(&message[5..7]).unwrap());
+        assert_eq!(Some(message.clone()), reader.feed(&message[7..]).unwrap());
+
+        // reader continues with the next message
+        assert_eq!(None, reader.feed(&message[0..6]).unwrap());
+        assert_eq!(Some(message.clone()), reader.feed(&message[6..]).unwrap());
+        assert!(BinaryReader::new().read(message, &stream_message_encoding()).is_ok());
+    }
+
+    #[test]
+    fn stream_reader_rejects_message_exceeding_limit_of_its_tag() {
+        let mut limits = HashMap::new();
+        limits.insert(0x10, 16);
+        let size_limit = SizeLimit::ByTag { default: 12, limits };
+
+        // Head message with 20 bytes of data is rejected after the first chunk
+        let mut message = hex::decode("0000001a001100000014").unwrap();
+        message.extend(vec![0xAB; 20]);
+        let mut reader = StreamReader::new(stream_message_encoding(), size_limit.clone());
+        match reader.feed(&message[0..8]) {
+            Err(BinaryReaderError::MessageTooLarge { size, max_size, tag }) => {
+                assert_eq!(30, size);
+                assert_eq!(12, max_size);
+                assert_eq!(Some(0x11), tag);
+            }
+            other => panic!("Expected MessageTooLarge error, but got: {:?}", other)
+        }
+
+        // GetHead message fits into the limit of its tag
+        let message = hex::decode("0000000600108eceda2f").unwrap();
+        let mut reader = StreamReader::new(stream_message_encoding(), size_limit);
+        assert_eq!(None, reader.feed(&message[0..6]).unwrap());
+        assert_eq!(Some(message.clone()), reader.feed(&message[6..]).unwrap());
+    }
+
+    #[test]
+    fn stream_reader_rejects_every_message_exceeding_limit_of_its_tag() {
+        let mut limits = HashMap::new();
+        limits.insert(0x10, 4);
+        limits.insert(0x11, 64);
+        let size_limit = SizeLimit::ByTag { default: 12, limits };
+
+        // GetHead message following the Head message is checked against its own limit
+        let mut message = hex::decode("00000020001100000014").unwrap();
+        message.extend(vec![0xAB; 20]);
+        message.extend(hex::decode("00108eceda2f").unwrap());
+        let mut reader = StreamReader::new(stream_message_encoding(), size_limit);
+        match reader.feed(&message) {
+            Err(BinaryReaderError::MessageTooLarge { size, max_size, tag }) => {
+                assert_eq!(6, size);
+                assert_eq!(4, max_size);
+                assert_eq!(Some(0x10), tag);
+            }
+            other => panic!("Expected MessageTooLarge error, but got: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn stream_reader_reports_unsupported_tag_when_message_is_received() {
+        let mut reader = StreamReader::new(stream_message_encoding(), SizeLimit::Fixed(1024));
+
+        // message with unknown tag 0x99 is consumed completely before the error is reported
+        assert_eq!(None, reader.feed(&hex::decode("0000000500").unwrap()).unwrap());
+        match reader.feed(&hex::decode("99aabbcc").unwrap()) {
+            Err(BinaryReaderError::UnsupportedTag { tag }) => assert_eq!(0x99, tag),
+            other => panic!("Expected UnsupportedTag error, but got: {:?}", other)
+        }
+
+        let message = hex::decode("0000000600108eceda2f").unwrap();
+        assert_eq!(Some(message.clone()), reader.feed(&message).unwrap());
+    }
 }
\ No newline at end of file
@@ -326,6 +326,22 @@ fn encode_value(data: &mut Vec<u8>, value: &Value, encoding: &Encoding) -> Resul
 
             Ok(data.len() - data_len_before_write)
         }
+        Encoding::ShortDynamic(dynamic_encoding) => {
+            let data_len_before_write = data.len();
+            // put 0 as a placeholder
+            data.put_u8(0);
+
+            // write data
+            let bytes_sz = encode_value(data, value, dynamic_encoding)?;
+            if bytes_sz > usize::from(u8::max_value()) {
+                return Err(Error::custom(format!("Was expecting at most {} bytes but got {}", u8::max_value(), bytes_sz)));
+            }
+
+            // update size
+            data[data_len_before_write] = bytes_sz as u8;
+
+            Ok(data.len() - data_len_before_write)
+        }
         Encoding::Sized(sized_size, sized_encoding) => {
             // write data
             let bytes_sz = encode_value(data, value, sized_encoding)?;
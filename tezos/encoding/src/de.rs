@@ -460,6 +460,10 @@ impl<'de, 'a> de::EnumAccess<'de> for EnumDeserializer<'a, 'de> {
                 let val = variant.as_str().into_deserializer();
                 seed.deserialize(val).map(|s| (s, self))
             },
+            Value::Enum(Some(variant), _) => {
+                let val = variant.as_str().into_deserializer();
+                seed.deserialize(val).map(|s| (s, self))
+            },
             _ => Err(Error::custom(format!("variant_seed: not an enum but a {:?}", self.de.input))),
         }
     }
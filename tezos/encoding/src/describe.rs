@@ -0,0 +1,699 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Human readable descriptions of an [Encoding].
+//!
+//! Output follows `tezos-codec describe`, so our encodings can be compared with the OCaml definitions:
+//! * [describe_binary] renders the binary layout as tables of fields with their size and contents,
+//! * [describe_json] renders a JSON schema (draft 04) of the json form.
+//!
+//! [Encoding::Split] is expanded for the described form and every [Encoding::Tags] case is described separately.
+//! [Encoding::Lazy] is described only once and referenced by name, so recursive encodings have a finite description.
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::{json, Map, Value as JsonValue};
+
+use crate::encoding::{Encoding, Field, SchemaType, TagMap};
+
+const UNNAMED_FIELD: &str = "Unnamed field 0";
+const BOOLEAN: &str = "boolean (0 for false, 255 for true)";
+
+/// Describe binary layout of the encoding in the same form as `tezos-codec describe <id> binary schema`.
+///
+/// # Examples:
+///
+/// ```
+/// use tezos_encoding::describe::describe_binary;
+/// use tezos_encoding::encoding::{Field, Encoding};
+///
+/// let version_schema = Encoding::Obj(vec![
+///     Field::new("major", Encoding::Uint16),
+///     Field::new("minor", Encoding::Uint16)
+/// ]);
+///
+/// let expected = "\
+/// +-------+---------+-------------------------+
+/// | Name  | Size    | Contents                |
+/// +=======+=========+=========================+
+/// | major | 2 bytes | unsigned 16-bit integer |
+/// +-------+---------+-------------------------+
+/// | minor | 2 bytes | unsigned 16-bit integer |
+/// +-------+---------+-------------------------+
+/// ";
+/// assert_eq!(expected, describe_binary(&version_schema));
+/// ```
+pub fn describe_binary(encoding: &Encoding) -> String {
+    let mut describer = BinaryDescriber::default();
+    describer.describe_top(encoding);
+    describer.render()
+}
+
+/// Describe json form of the encoding as a JSON schema.
+pub fn describe_json(encoding: &Encoding) -> JsonValue {
+    let mut describer = JsonDescriber::default();
+    let mut schema = match describer.schema("root", encoding) {
+        JsonValue::Object(schema) => schema,
+        _ => unreachable!("JSON schema is always an object"),
+    };
+    schema.insert("$schema".to_string(), json!("http://json-schema.org/draft-04/schema#"));
+    if !describer.definitions.is_empty() {
+        schema.insert("definitions".to_string(), JsonValue::Object(describer.definitions));
+    }
+    JsonValue::Object(schema)
+}
+
+/// Described encodings are identified by their structure, nested lazy encodings are not expanded.
+fn structure_key(encoding: &Encoding) -> String {
+    match encoding {
+        Encoding::Obj(fields) => {
+            let fields: Vec<String> = fields.iter().map(|field| format!("{}: {}", field.get_name(), structure_key(field.get_encoding()))).collect();
+            format!("Obj({})", fields.join(", "))
+        }
+        Encoding::Tup(encodings) => {
+            let encodings: Vec<String> = encodings.iter().map(structure_key).collect();
+            format!("Tup({})", encodings.join(", "))
+        }
+        Encoding::Tags(tag_sz, tag_map) => {
+            // tag map is not formatted directly, because order of its hash maps is not stable
+            let tags: Vec<String> = tag_map.tags().iter().map(|tag| format!("{} {}: {}", tag.get_id(), tag.get_variant(), structure_key(tag.get_encoding()))).collect();
+            format!("Tags({}, {})", tag_sz, tags.join(", "))
+        }
+        Encoding::List(inner_encoding) => format!("List({})", structure_key(inner_encoding)),
+        Encoding::Option(inner_encoding) => format!("Option({})", structure_key(inner_encoding)),
+        Encoding::Dynamic(inner_encoding) => format!("Dynamic({})", structure_key(inner_encoding)),
+        Encoding::ShortDynamic(inner_encoding) => format!("ShortDynamic({})", structure_key(inner_encoding)),
+        Encoding::Greedy(inner_encoding) => format!("Greedy({})", structure_key(inner_encoding)),
+        Encoding::Sized(sized_size, inner_encoding) => format!("Sized({}, {})", sized_size, structure_key(inner_encoding)),
+        Encoding::Split(fn_encoding) => format!("Split({}, {})", structure_key(&fn_encoding(SchemaType::Binary)), structure_key(&fn_encoding(SchemaType::Json))),
+        _ => format!("{:?}", encoding)
+    }
+}
+
+/// Names are unique, repeated names are suffixed by a counter.
+fn unique_name(name: &str, used: &mut HashSet<String>) -> String {
+    let mut unique = name.to_string();
+    let mut counter = 0;
+    while used.contains(&unique) {
+        counter += 1;
+        unique = format!("{}_{}", name, counter);
+    }
+    used.insert(unique.clone());
+    unique
+}
+
+struct Row {
+    name: String,
+    size: String,
+    contents: String,
+}
+
+impl Row {
+    fn new<N: Into<String>, S: Into<String>, C: Into<String>>(name: N, size: S, contents: C) -> Self {
+        Row { name: name.into(), size: size.into(), contents: contents.into() }
+    }
+}
+
+struct Section {
+    title: Option<String>,
+    underline: char,
+    rows: Vec<Row>,
+}
+
+#[derive(Default)]
+struct BinaryDescriber {
+    sections: Vec<Section>,
+    references: HashSet<String>,
+    described: HashMap<String, String>,
+}
+
+impl BinaryDescriber {
+    fn describe_top(&mut self, encoding: &Encoding) {
+        match encoding {
+            Encoding::Tags(tag_sz, tag_map) => self.describe_tags(None, *tag_sz, tag_map),
+            Encoding::Split(fn_encoding) => self.describe_top(&fn_encoding(SchemaType::Binary)),
+            _ => {
+                let index = self.reserve_section(None, '*');
+                let rows = self.section_rows(encoding);
+                self.sections[index].rows = rows;
+            }
+        }
+    }
+
+    /// Rows of a standalone section describing the encoding.
+    fn section_rows(&mut self, encoding: &Encoding) -> Vec<Row> {
+        match encoding {
+            Encoding::Obj(fields) => self.object_rows(fields),
+            Encoding::Tup(encodings) => encodings.iter().enumerate()
+                .flat_map(|(idx, encoding)| self.field_rows(&format!("Unnamed field {}", idx), encoding))
+                .collect(),
+            Encoding::Split(fn_encoding) => self.section_rows(&fn_encoding(SchemaType::Binary)),
+            _ => self.field_rows(UNNAMED_FIELD, encoding)
+        }
+    }
+
+    fn object_rows(&mut self, fields: &[Field]) -> Vec<Row> {
+        fields.iter()
+            .flat_map(|field| self.field_rows(field.get_name(), field.get_encoding()))
+            .collect()
+    }
+
+    fn field_rows(&mut self, name: &str, encoding: &Encoding) -> Vec<Row> {
+        match encoding {
+            Encoding::Unit => vec![],
+            Encoding::String => vec![
+                Row::new("# bytes in next field", "4 bytes", "unsigned 30-bit integer"),
+                Row::new(name, "Variable", "bytes"),
+            ],
+            Encoding::Dynamic(dynamic_encoding) => {
+                let mut rows = vec![Row::new("# bytes in next field", "4 bytes", "unsigned 30-bit integer")];
+                rows.extend(self.field_rows(name, dynamic_encoding));
+                rows
+            }
+            Encoding::ShortDynamic(dynamic_encoding) => {
+                let mut rows = vec![Row::new("# bytes in next field", "1 byte", "unsigned 8-bit integer")];
+                rows.extend(self.field_rows(name, dynamic_encoding));
+                rows
+            }
+            Encoding::Greedy(un_sized_encoding) => self.field_rows(name, un_sized_encoding),
+            Encoding::Sized(sized_size, sized_encoding) => {
+                let contents = self.contents(name, sized_encoding);
+                vec![Row::new(name, bytes(*sized_size), contents)]
+            }
+            Encoding::Option(option_encoding) => {
+                let contents = self.contents(name, option_encoding);
+                vec![
+                    Row::new(format!("? presence of field \"{}\"", name), "1 byte", BOOLEAN),
+                    Row::new(name, size(option_encoding), contents),
+                ]
+            }
+            Encoding::Split(fn_encoding) => self.field_rows(name, &fn_encoding(SchemaType::Binary)),
+            _ => {
+                let contents = self.contents(name, encoding);
+                vec![Row::new(name, size(encoding), contents)]
+            }
+        }
+    }
+
+    /// Contents column of the encoding, compound encodings are described in a separate section and referenced by name.
+    fn contents(&mut self, name: &str, encoding: &Encoding) -> String {
+        match encoding {
+            Encoding::Unit => "placeholder (not actually present)".to_string(),
+            Encoding::Int8 => "signed 8-bit integer".to_string(),
+            Encoding::Uint8 | Encoding::Enum => "unsigned 8-bit integer".to_string(),
+            Encoding::Int16 => "signed 16-bit integer".to_string(),
+            Encoding::Uint16 => "unsigned 16-bit integer".to_string(),
+            Encoding::Int31 => "signed 31-bit integer".to_string(),
+            Encoding::Int32 => "signed 32-bit integer".to_string(),
+            Encoding::Uint32 => "unsigned 32-bit integer".to_string(),
+            Encoding::Int64 | Encoding::Timestamp => "signed 64-bit integer".to_string(),
+            Encoding::RangedInt => "ranged integer".to_string(),
+            Encoding::Z => "Z.t".to_string(),
+            Encoding::Mutez => "N.t".to_string(),
+            Encoding::Float => "double-precision floating-point number".to_string(),
+            Encoding::RangedFloat => "ranged double-precision floating-point number".to_string(),
+            Encoding::Bool => BOOLEAN.to_string(),
+            Encoding::Bytes
+            | Encoding::Hash(_) => "bytes".to_string(),
+            Encoding::List(list_inner_encoding) => format!("sequence of {}", self.contents(name, list_inner_encoding)),
+            Encoding::Greedy(un_sized_encoding)
+            | Encoding::Sized(_, un_sized_encoding) => self.contents(name, un_sized_encoding),
+            Encoding::Split(fn_encoding) => self.contents(name, &fn_encoding(SchemaType::Binary)),
+            Encoding::Lazy(fn_encoding) => self.reference(name, &fn_encoding()),
+            Encoding::String
+            | Encoding::Tags(_, _)
+            | Encoding::Obj(_)
+            | Encoding::Tup(_)
+            | Encoding::Option(_)
+            | Encoding::Dynamic(_)
+            | Encoding::ShortDynamic(_) => self.reference(name, encoding),
+        }
+    }
+
+    /// Describe the encoding in a separate section, structurally equal encodings are described only once.
+    fn reference(&mut self, name: &str, encoding: &Encoding) -> String {
+        let key = structure_key(encoding);
+        if let Some(reference) = self.described.get(&key) {
+            return format!("${}", reference);
+        }
+        // register before the expansion, so recursive occurrences refer to the section being described
+        let reference = self.reference_name(name);
+        self.described.insert(key, reference.clone());
+        self.describe_section(&reference, encoding);
+        format!("${}", reference)
+    }
+
+    fn describe_section(&mut self, reference: &str, encoding: &Encoding) {
+        match encoding {
+            Encoding::Tags(tag_sz, tag_map) => self.describe_tags(Some(reference), *tag_sz, tag_map),
+            _ => {
+                let index = self.reserve_section(Some(reference.to_string()), '*');
+                let rows = self.section_rows(encoding);
+                self.sections[index].rows = rows;
+            }
+        }
+    }
+
+    fn describe_tags(&mut self, name: Option<&str>, tag_sz: usize, tag_map: &TagMap) {
+        if let Some(name) = name {
+            self.reserve_section(Some(format!("{} (Determined from data, {}-bit tag)", name, tag_sz * 8)), '*');
+        }
+
+        for tag in tag_map.tags() {
+            let index = self.reserve_section(Some(format!("{} (tag {})", tag.get_variant(), tag.get_id())), '=');
+            let mut rows = vec![Row::new("Tag", bytes(tag_sz), format!("unsigned {}-bit integer", tag_sz * 8))];
+            match tag.get_encoding() {
+                Encoding::Lazy(_) => {
+                    let contents = self.contents(tag.get_variant(), tag.get_encoding());
+                    rows.push(Row::new(UNNAMED_FIELD, size(tag.get_encoding()), contents));
+                }
+                tag_encoding => rows.extend(self.section_rows(tag_encoding)),
+            }
+            self.sections[index].rows = rows;
+        }
+    }
+
+    fn reserve_section(&mut self, title: Option<String>, underline: char) -> usize {
+        self.sections.push(Section { title, underline, rows: vec![] });
+        self.sections.len() - 1
+    }
+
+    fn reference_name(&mut self, name: &str) -> String {
+        if name == UNNAMED_FIELD {
+            // anonymous sections are named X_<n> as in OCaml
+            let mut idx = 0;
+            while self.references.contains(&format!("X_{}", idx)) {
+                idx += 1;
+            }
+            let reference = format!("X_{}", idx);
+            self.references.insert(reference.clone());
+            reference
+        } else {
+            unique_name(name, &mut self.references)
+        }
+    }
+
+    fn render(&self) -> String {
+        self.sections.iter()
+            .map(|section| {
+                let mut out = String::new();
+                if let Some(title) = &section.title {
+                    out.push_str(title);
+                    out.push('\n');
+                    out.push_str(&section.underline.to_string().repeat(title.chars().count()));
+                    out.push('\n');
+                    if !section.rows.is_empty() {
+                        out.push('\n');
+                    }
+                }
+                if !section.rows.is_empty() || section.title.is_none() {
+                    out.push_str(&render_table(&section.rows));
+                }
+                out
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn render_table(rows: &[Row]) -> String {
+    let header = Row::new("Name", "Size", "Contents");
+    let widths = rows.iter().fold(
+        [header.name.len(), header.size.len(), header.contents.len()],
+        |[name, size, contents], row| [name.max(row.name.len()), size.max(row.size.len()), contents.max(row.contents.len())],
+    );
+
+    let separator = |fill: char| {
+        let mut line = String::from("+");
+        for width in &widths {
+            line.push_str(&fill.to_string().repeat(width + 2));
+            line.push('+');
+        }
+        line.push('\n');
+        line
+    };
+    let line = |row: &Row| {
+        format!("| {:w0$} | {:w1$} | {:w2$} |\n", row.name, row.size, row.contents, w0 = widths[0], w1 = widths[1], w2 = widths[2])
+    };
+
+    let mut table = separator('-');
+    table.push_str(&line(&header));
+    table.push_str(&separator('='));
+    for row in rows {
+        table.push_str(&line(row));
+        table.push_str(&separator('-'));
+    }
+    table
+}
+
+fn bytes(size: usize) -> String {
+    match size {
+        1 => "1 byte".to_string(),
+        _ => format!("{} bytes", size)
+    }
+}
+
+fn size(encoding: &Encoding) -> String {
+    match fixed_size(encoding) {
+        Some(size) => bytes(size),
+        None if is_variable(encoding) => "Variable".to_string(),
+        None => "Determined from data".to_string()
+    }
+}
+
+/// Size of the encoding in bytes, if it does not depend on the data.
+fn fixed_size(encoding: &Encoding) -> Option<usize> {
+    match encoding {
+        Encoding::Unit => Some(0),
+        Encoding::Int8
+        | Encoding::Uint8
+        | Encoding::Bool
+        | Encoding::Enum => Some(1),
+        Encoding::Int16
+        | Encoding::Uint16 => Some(2),
+        Encoding::Int31
+        | Encoding::Int32
+        | Encoding::Uint32 => Some(4),
+        Encoding::Int64
+        | Encoding::Timestamp
+        | Encoding::Float => Some(8),
+        Encoding::Hash(hash_type) => Some(hash_type.size()),
+        Encoding::Sized(sized_size, _) => Some(*sized_size),
+        Encoding::Obj(fields) => fields.iter().map(|field| fixed_size(field.get_encoding())).sum(),
+        Encoding::Tup(encodings) => encodings.iter().map(fixed_size).sum(),
+        Encoding::Split(fn_encoding) => fixed_size(&fn_encoding(SchemaType::Binary)),
+        _ => None
+    }
+}
+
+/// Variable encodings consume all of the remaining input.
+fn is_variable(encoding: &Encoding) -> bool {
+    match encoding {
+        Encoding::Bytes
+        | Encoding::List(_)
+        | Encoding::Greedy(_) => true,
+        Encoding::Obj(fields) => fields.iter().any(|field| is_variable(field.get_encoding())),
+        Encoding::Tup(encodings) => encodings.iter().any(is_variable),
+        Encoding::Split(fn_encoding) => is_variable(&fn_encoding(SchemaType::Binary)),
+        _ => false
+    }
+}
+
+#[derive(Default)]
+struct JsonDescriber {
+    definitions: Map<String, JsonValue>,
+    names: HashSet<String>,
+    lazy_references: HashMap<String, String>,
+}
+
+impl JsonDescriber {
+    fn schema(&mut self, name: &str, encoding: &Encoding) -> JsonValue {
+        match encoding {
+            Encoding::Unit => json!({ "type": "null" }),
+            Encoding::Int8 => integer_schema(i64::from(i8::min_value()), i64::from(i8::max_value())),
+            Encoding::Uint8 => integer_schema(0, i64::from(u8::max_value())),
+            Encoding::Int16 => integer_schema(i64::from(i16::min_value()), i64::from(i16::max_value())),
+            Encoding::Uint16 => integer_schema(0, i64::from(u16::max_value())),
+            Encoding::Int31 => integer_schema(-(1 << 30), (1 << 30) - 1),
+            Encoding::Int32 => integer_schema(i64::from(i32::min_value()), i64::from(i32::max_value())),
+            Encoding::Uint32 => integer_schema(0, i64::from(u32::max_value())),
+            Encoding::RangedInt => json!({ "type": "integer" }),
+            Encoding::Int64 => json!({ "oneOf": [ { "type": "integer" }, { "type": "string", "pattern": "^-?[0-9]+$" } ] }),
+            Encoding::Z => json!({ "title": "Z.t", "type": "string" }),
+            Encoding::Mutez => json!({ "title": "N.t", "type": "string" }),
+            Encoding::Float
+            | Encoding::RangedFloat => json!({ "type": "number" }),
+            Encoding::Bool => json!({ "type": "boolean" }),
+            Encoding::String
+            | Encoding::Enum => json!({ "type": "string" }),
+            Encoding::Bytes => json!({ "type": "string", "pattern": "^([a-zA-Z0-9][a-zA-Z0-9])*$" }),
+            Encoding::Hash(hash_type) => json!({ "title": format!("{:?} (Base58Check-encoded)", hash_type), "type": "string" }),
+            Encoding::Timestamp => json!({ "type": "string", "format": "date-time" }),
+            Encoding::List(list_inner_encoding) => json!({ "type": "array", "items": self.schema(name, list_inner_encoding) }),
+            Encoding::Option(option_encoding) => json!({ "oneOf": [ self.schema(name, option_encoding), { "type": "null" } ] }),
+            Encoding::Obj(fields) => {
+                let mut properties = Map::new();
+                let mut required = vec![];
+                for field in fields {
+                    properties.insert(field.get_name().clone(), self.schema(field.get_name(), field.get_encoding()));
+                    if !is_json_optional(field.get_encoding()) {
+                        required.push(field.get_name().clone());
+                    }
+                }
+                json!({ "type": "object", "properties": properties, "required": required, "additionalProperties": false })
+            }
+            Encoding::Tup(encodings) => {
+                let mut items = vec![];
+                for encoding in encodings {
+                    items.push(self.schema(name, encoding));
+                }
+                json!({ "type": "array", "items": items, "additionalItems": false })
+            }
+            Encoding::Tags(_, tag_map) => {
+                let mut cases = vec![];
+                for tag in tag_map.tags() {
+                    cases.push(match tag.get_encoding() {
+                        Encoding::Unit => json!({ "title": tag.get_variant(), "type": "string", "enum": [tag.get_variant()] }),
+                        tag_encoding => json!({
+                            "title": tag.get_variant(),
+                            "type": "object",
+                            "properties": { tag.get_variant().clone(): self.schema(tag.get_variant(), tag_encoding) },
+                            "required": [tag.get_variant()],
+                            "additionalProperties": false
+                        })
+                    });
+                }
+                json!({ "oneOf": cases })
+            }
+            Encoding::Dynamic(inner_encoding)
+            | Encoding::ShortDynamic(inner_encoding)
+            | Encoding::Sized(_, inner_encoding)
+            | Encoding::Greedy(inner_encoding) => self.schema(name, inner_encoding),
+            Encoding::Split(fn_encoding) => self.schema(name, &fn_encoding(SchemaType::Json)),
+            Encoding::Lazy(fn_encoding) => {
+                let lazy_encoding = fn_encoding();
+                let key = structure_key(&lazy_encoding);
+                let definition = match self.lazy_references.get(&key) {
+                    Some(definition) => definition.clone(),
+                    None => {
+                        // register before the expansion, so recursive occurrences refer to the definition being described
+                        let definition = unique_name(name, &mut self.names);
+                        self.lazy_references.insert(key, definition.clone());
+                        let schema = self.schema(name, &lazy_encoding);
+                        self.definitions.insert(definition.clone(), schema);
+                        definition
+                    }
+                };
+                json!({ "$ref": format!("#/definitions/{}", definition) })
+            }
+        }
+    }
+}
+
+fn integer_schema(minimum: i64, maximum: i64) -> JsonValue {
+    json!({ "type": "integer", "minimum": minimum, "maximum": maximum })
+}
+
+/// Optional fields do not have to be present in json object.
+fn is_json_optional(encoding: &Encoding) -> bool {
+    match encoding {
+        Encoding::Option(_) => true,
+        Encoding::Dynamic(inner_encoding)
+        | Encoding::ShortDynamic(inner_encoding)
+        | Encoding::Sized(_, inner_encoding)
+        | Encoding::Greedy(inner_encoding) => is_json_optional(inner_encoding),
+        Encoding::Split(fn_encoding) => is_json_optional(&fn_encoding(SchemaType::Json)),
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crypto::hash::HashType;
+
+    use crate::encoding::Tag;
+
+    use super::*;
+
+    #[test]
+    fn can_describe_binary_layout() {
+        let version_schema = Encoding::Obj(vec![
+            Field::new("name", Encoding::String),
+            Field::new("major", Encoding::Uint16),
+        ]);
+        let schema = Encoding::Obj(vec![
+            Field::new("chain_id", Encoding::Hash(HashType::ChainId)),
+            Field::new("timestamp", Encoding::Timestamp),
+            Field::new("fitness", Encoding::Split(Arc::new(|schema_type|
+                match schema_type {
+                    SchemaType::Json => Encoding::Unit,
+                    SchemaType::Binary => Encoding::dynamic(Encoding::list(Encoding::dynamic(Encoding::list(Encoding::Uint8))))
+                }
+            ))),
+            Field::new("versions", Encoding::dynamic(Encoding::list(version_schema))),
+            Field::new("balance", Encoding::option(Encoding::Mutez)),
+            Field::new("kind", Encoding::Tags(1, TagMap::new(&[
+                Tag::new(0x00, "Nop", Encoding::Unit),
+                Tag::new(0x01, "Transfer", Encoding::Obj(vec![Field::new("amount", Encoding::Z)])),
+            ]))),
+        ]);
+
+        let expected = "\
++-------------------------------+----------------------+-------------------------------------+
+| Name                          | Size                 | Contents                            |
++===============================+======================+=====================================+
+| chain_id                      | 4 bytes              | bytes                               |
++-------------------------------+----------------------+-------------------------------------+
+| timestamp                     | 8 bytes              | signed 64-bit integer               |
++-------------------------------+----------------------+-------------------------------------+
+| # bytes in next field         | 4 bytes              | unsigned 30-bit integer             |
++-------------------------------+----------------------+-------------------------------------+
+| fitness                       | Variable             | sequence of $fitness                |
++-------------------------------+----------------------+-------------------------------------+
+| # bytes in next field         | 4 bytes              | unsigned 30-bit integer             |
++-------------------------------+----------------------+-------------------------------------+
+| versions                      | Variable             | sequence of $versions               |
++-------------------------------+----------------------+-------------------------------------+
+| ? presence of field \"balance\" | 1 byte               | boolean (0 for false, 255 for true) |
++-------------------------------+----------------------+-------------------------------------+
+| balance                       | Determined from data | N.t                                 |
++-------------------------------+----------------------+-------------------------------------+
+| kind                          | Determined from data | $kind                               |
++-------------------------------+----------------------+-------------------------------------+
+
+fitness
+*******
+
++-----------------------+----------+------------------------------------+
+| Name                  | Size     | Contents                           |
++=======================+==========+====================================+
+| # bytes in next field | 4 bytes  | unsigned 30-bit integer            |
++-----------------------+----------+------------------------------------+
+| Unnamed field 0       | Variable | sequence of unsigned 8-bit integer |
++-----------------------+----------+------------------------------------+
+
+versions
+********
+
++-----------------------+----------+-------------------------+
+| Name                  | Size     | Contents                |
++=======================+==========+=========================+
+| # bytes in next field | 4 bytes  | unsigned 30-bit integer |
++-----------------------+----------+-------------------------+
+| name                  | Variable | bytes                   |
++-----------------------+----------+-------------------------+
+| major                 | 2 bytes  | unsigned 16-bit integer |
++-----------------------+----------+-------------------------+
+
+kind (Determined from data, 8-bit tag)
+**************************************
+
+Nop (tag 0)
+===========
+
++------+--------+------------------------+
+| Name | Size   | Contents               |
++======+========+========================+
+| Tag  | 1 byte | unsigned 8-bit integer |
++------+--------+------------------------+
+
+Transfer (tag 1)
+================
+
++--------+----------------------+------------------------+
+| Name   | Size                 | Contents               |
++========+======================+========================+
+| Tag    | 1 byte               | unsigned 8-bit integer |
++--------+----------------------+------------------------+
+| amount | Determined from data | Z.t                    |
++--------+----------------------+------------------------+
+";
+        assert_eq!(expected, describe_binary(&schema));
+    }
+
+    #[test]
+    fn can_describe_json_schema() {
+        let schema = Encoding::Obj(vec![
+            Field::new("level", Encoding::Int32),
+            Field::new("fitness", Encoding::Split(Arc::new(|schema_type|
+                match schema_type {
+                    SchemaType::Json => Encoding::list(Encoding::Bytes),
+                    SchemaType::Binary => Encoding::Unit
+                }
+            ))),
+            Field::new("predecessor", Encoding::option(Encoding::Hash(HashType::BlockHash))),
+            Field::new("kind", Encoding::Tags(1, TagMap::new(&[
+                Tag::new(0x00, "Nop", Encoding::Unit),
+                Tag::new(0x01, "Transfer", Encoding::Z),
+            ]))),
+        ]);
+
+        let expected = json!({
+            "$schema": "http://json-schema.org/draft-04/schema#",
+            "type": "object",
+            "properties": {
+                "level": { "type": "integer", "minimum": -2_147_483_648i64, "maximum": 2_147_483_647 },
+                "fitness": { "type": "array", "items": { "type": "string", "pattern": "^([a-zA-Z0-9][a-zA-Z0-9])*$" } },
+                "predecessor": { "oneOf": [ { "title": "BlockHash (Base58Check-encoded)", "type": "string" }, { "type": "null" } ] },
+                "kind": { "oneOf": [
+                    { "title": "Nop", "type": "string", "enum": ["Nop"] },
+                    { "title": "Transfer", "type": "object", "properties": { "Transfer": { "title": "Z.t", "type": "string" } }, "required": ["Transfer"], "additionalProperties": false }
+                ] }
+            },
+            "required": ["level", "fitness", "kind"],
+            "additionalProperties": false
+        });
+        assert_eq!(expected, describe_json(&schema));
+    }
+
+    #[test]
+    fn can_describe_recursive_encoding() {
+        fn tree_encoding() -> Encoding {
+            Encoding::Obj(vec![
+                Field::new("value", Encoding::Int32),
+                Field::new("children", Encoding::dynamic(Encoding::list(Encoding::Lazy(Arc::new(tree_encoding))))),
+            ])
+        }
+
+        let expected = "\
++-----------------------+----------+-------------------------+
+| Name                  | Size     | Contents                |
++=======================+==========+=========================+
+| value                 | 4 bytes  | signed 32-bit integer   |
++-----------------------+----------+-------------------------+
+| # bytes in next field | 4 bytes  | unsigned 30-bit integer |
++-----------------------+----------+-------------------------+
+| children              | Variable | sequence of $children   |
++-----------------------+----------+-------------------------+
+
+children
+********
+
++-----------------------+----------+-------------------------+
+| Name                  | Size     | Contents                |
++=======================+==========+=========================+
+| value                 | 4 bytes  | signed 32-bit integer   |
++-----------------------+----------+-------------------------+
+| # bytes in next field | 4 bytes  | unsigned 30-bit integer |
++-----------------------+----------+-------------------------+
+| children              | Variable | sequence of $children   |
++-----------------------+----------+-------------------------+
+";
+        assert_eq!(expected, describe_binary(&tree_encoding()));
+
+        let tree_schema = json!({
+            "type": "object",
+            "properties": {
+                "value": { "type": "integer", "minimum": -2_147_483_648i64, "maximum": 2_147_483_647 },
+                "children": { "type": "array", "items": { "$ref": "#/definitions/children" } }
+            },
+            "required": ["value", "children"],
+            "additionalProperties": false
+        });
+        let described = describe_json(&tree_encoding());
+        assert_eq!(tree_schema, described["definitions"]["children"]);
+        assert_eq!(tree_schema["properties"], described["properties"]);
+    }
+}
@@ -83,6 +83,13 @@ impl TagMap {
     pub fn find_by_variant(&self, variant: &str) -> Option<&Tag> {
         self.variant_to_tag.get(variant)
     }
+
+    /// Returns all tags ordered by their id.
+    pub fn tags(&self) -> Vec<&Tag> {
+        let mut tags: Vec<&Tag> = self.id_to_tag.values().collect();
+        tags.sort_by_key(|tag| tag.get_id());
+        tags
+    }
 }
 
 pub enum SchemaType {
@@ -187,6 +194,8 @@ pub enum Encoding {
     /// Is the collection of fields.
     /// prefixed its length in bytes (4 Bytes), encoded as the concatenation of all the element in binary
     Dynamic(Box<Encoding>),
+    /// Same as [Encoding::Dynamic], but the length is prefixed by a single byte (e.g. bounded strings up to 255 bytes).
+    ShortDynamic(Box<Encoding>),
     /// Represents fixed size block in binary encoding.
     Sized(usize, Box<Encoding>),
     /// Almost same as [Encoding::Dynamic] but without bytes size information prefix.
@@ -243,6 +252,13 @@ impl Encoding {
         Encoding::Dynamic(Box::new(encoding))
     }
 
+    /// Utility function to construct [Encoding::ShortDynamic] without the need
+    /// to manually create new [Box].
+    #[inline]
+    pub fn short_dynamic(encoding: Encoding) -> Encoding {
+        Encoding::ShortDynamic(Box::new(encoding))
+    }
+
     /// Utility function to construct [Encoding::Option] without the need
     /// to manually create new [Box].
     #[inline]
@@ -0,0 +1,555 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Tezos json data reader.
+
+use std::convert::TryFrom;
+
+use chrono::DateTime;
+use failure::Fail;
+use serde_json::Value as JsonValue;
+
+use crypto::base58::FromBase58Check;
+use crypto::hash::HashType;
+
+use crate::encoding::{Encoding, Field, SchemaType};
+use crate::types::Value;
+
+/// Error produced by a [JsonReader].
+#[derive(Debug, Fail)]
+pub enum JsonReaderError {
+    /// Input is not a valid json document.
+    #[fail(display = "Invalid json input: {}", reason)]
+    InvalidJson {
+        reason: String
+    },
+    /// Json value does not have the shape required by the encoding.
+    #[fail(display = "Json value {} does not match encoding {}", value, encoding)]
+    EncodingMismatch {
+        encoding: String,
+        value: String,
+    },
+    /// Json value has the right shape but its content is invalid.
+    #[fail(display = "Invalid value {}: {}", value, reason)]
+    InvalidValue {
+        value: String,
+        reason: String,
+    },
+    /// Json object is missing a field required by the encoding.
+    #[fail(display = "Missing field: {}", name)]
+    MissingField {
+        name: String
+    },
+    /// No tag with the corresponding variant name was found.
+    #[fail(display = "No tag found for variant: {}", variant)]
+    UnsupportedTag {
+        variant: String
+    },
+    /// Encoding has no json representation.
+    #[fail(display = "Unsupported encoding {}", encoding)]
+    UnsupportedEncoding {
+        encoding: String
+    },
+}
+
+impl JsonReaderError {
+    fn encoding_mismatch(encoding: &Encoding, value: &JsonValue) -> Self {
+        JsonReaderError::EncodingMismatch { encoding: format!("{:?}", encoding), value: value.to_string() }
+    }
+
+    fn invalid_value<T: ToString>(value: &JsonValue, reason: T) -> Self {
+        JsonReaderError::InvalidValue { value: value.to_string(), reason: reason.to_string() }
+    }
+}
+
+impl From<serde_json::Error> for JsonReaderError {
+    fn from(from: serde_json::Error) -> Self {
+        JsonReaderError::InvalidJson { reason: from.to_string() }
+    }
+}
+
+/// Converts Tezos json form into rust types.
+///
+/// Json is read in the form produced by [JsonWriter](crate::json_writer::JsonWriter) and by the Tezos node:
+/// * `Int64` is accepted both as a number and as a decimal string,
+/// * `Z` and `Mutez` are decimal strings (json numbers are accepted as well), they are converted to the hexadecimal form the [BinaryReader](crate::binary_reader::BinaryReader) produces,
+/// * `Bytes` are hex strings and `Hash` values are base58check strings,
+/// * `Timestamp` is an RFC3339 string or a number of seconds since epoch,
+/// * `Tags` are read from `{ "<variant>": <value> }`, from an object with a `"kind": "<variant>"` field or from a bare `"<variant>"` string.
+pub struct JsonReader;
+
+impl JsonReader {
+    /// Construct new instance of the [JsonReader].
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Convert Tezos json data to intermediate [Value] format.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use serde::Deserialize;
+    /// use tezos_encoding::json_reader::JsonReader;
+    /// use tezos_encoding::de;
+    /// use tezos_encoding::encoding::{Field, Encoding};
+    ///
+    /// #[derive(Deserialize, Debug, PartialEq)]
+    /// struct Version {
+    ///    name: String,
+    ///    major: u16,
+    ///    minor: u16,
+    /// }
+    ///
+    /// let version_schema = Encoding::Obj(vec![
+    ///     Field::new("name", Encoding::String),
+    ///     Field::new("major", Encoding::Uint16),
+    ///     Field::new("minor", Encoding::Uint16)
+    /// ]);
+    ///
+    /// let reader = JsonReader::new();
+    /// // create intermediate form
+    /// let intermediate = reader.read(r#"{ "name": "v1.0", "major": 1, "minor": 0 }"#, &version_schema).unwrap();
+    /// // deserialize from intermediate form
+    /// let version = de::from_value::<Version>(&intermediate).unwrap();
+    ///
+    /// let version_expected = Version { name: "v1.0".into(), major: 1, minor: 0 };
+    ///
+    /// assert_eq!(version, version_expected);
+    /// ```
+    pub fn read(&self, json: &str, encoding: &Encoding) -> Result<Value, JsonReaderError> {
+        let json: JsonValue = serde_json::from_str(json)?;
+        self.decode_value(&json, encoding)
+    }
+
+    fn decode_record(&self, json: &JsonValue, schema: &[Field]) -> Result<Value, JsonReaderError> {
+        let object = match json {
+            JsonValue::Object(object) => object,
+            _ => return Err(JsonReaderError::encoding_mismatch(&Encoding::Obj(schema.to_vec()), json))
+        };
+
+        let mut values = Vec::with_capacity(schema.len());
+        for field in schema {
+            let name = field.get_name();
+            let encoding = field.get_encoding();
+            let value = match object.get(name) {
+                Some(field_json) => self.decode_value(field_json, encoding)?,
+                None if is_option(encoding) => Value::Option(None),
+                None => return Err(JsonReaderError::MissingField { name: name.clone() })
+            };
+            values.push((name.clone(), value));
+        }
+        Ok(Value::Record(values))
+    }
+
+    fn decode_tuple(&self, json: &JsonValue, encodings: &[Encoding]) -> Result<Value, JsonReaderError> {
+        match json {
+            JsonValue::Array(items) if items.len() == encodings.len() => {
+                let mut values = Vec::with_capacity(encodings.len());
+                for (item, encoding) in items.iter().zip(encodings) {
+                    values.push(self.decode_value(item, encoding)?)
+                }
+                Ok(Value::Tuple(values))
+            }
+            _ => Err(JsonReaderError::encoding_mismatch(&Encoding::Tup(encodings.to_vec()), json))
+        }
+    }
+
+    fn decode_value(&self, json: &JsonValue, encoding: &Encoding) -> Result<Value, JsonReaderError> {
+        match encoding {
+            Encoding::Unit => Ok(Value::Unit),
+            Encoding::Int8 => Ok(Value::Int8(decode_int(json, encoding)?)),
+            Encoding::Uint8 => Ok(Value::Uint8(decode_int(json, encoding)?)),
+            Encoding::Int16 => Ok(Value::Int16(decode_int(json, encoding)?)),
+            Encoding::Uint16 => Ok(Value::Uint16(decode_int(json, encoding)?)),
+            Encoding::Int31 => Ok(Value::Int31(decode_int(json, encoding)?)),
+            Encoding::Int32 => Ok(Value::Int32(decode_int(json, encoding)?)),
+            Encoding::Int64 => {
+                match json {
+                    JsonValue::String(v) => v.parse().map(Value::Int64).map_err(|e| JsonReaderError::invalid_value(json, e)),
+                    _ => Ok(Value::Int64(decode_int(json, encoding)?))
+                }
+            }
+            Encoding::Timestamp => {
+                match json {
+                    JsonValue::String(v) => DateTime::parse_from_rfc3339(v)
+                        .map(|timestamp| Value::Int64(timestamp.timestamp()))
+                        .map_err(|e| JsonReaderError::invalid_value(json, e)),
+                    _ => Ok(Value::Int64(decode_int(json, encoding)?))
+                }
+            }
+            Encoding::Float => {
+                match json.as_f64() {
+                    Some(v) => Ok(Value::Float(v)),
+                    None => Err(JsonReaderError::encoding_mismatch(encoding, json))
+                }
+            }
+            Encoding::Bool => {
+                match json {
+                    JsonValue::Bool(v) => Ok(Value::Bool(*v)),
+                    _ => Err(JsonReaderError::encoding_mismatch(encoding, json))
+                }
+            }
+            Encoding::String => {
+                match json {
+                    JsonValue::String(v) => Ok(Value::String(v.clone())),
+                    _ => Err(JsonReaderError::encoding_mismatch(encoding, json))
+                }
+            }
+            Encoding::Z | Encoding::Mutez => {
+                let num = match json {
+                    JsonValue::String(v) => num_bigint::BigInt::parse_bytes(v.as_bytes(), 10),
+                    JsonValue::Number(v) => num_bigint::BigInt::parse_bytes(v.to_string().as_bytes(), 10),
+                    _ => return Err(JsonReaderError::encoding_mismatch(encoding, json))
+                };
+                let num = num.ok_or_else(|| JsonReaderError::invalid_value(json, "Not a valid number"))?;
+                if let (Encoding::Mutez, num_bigint::Sign::Minus) = (encoding, num.sign()) {
+                    return Err(JsonReaderError::invalid_value(json, "Mutez cannot be negative"));
+                }
+                Ok(Value::String(num.to_str_radix(16)))
+            }
+            Encoding::Enum => {
+                match json {
+                    JsonValue::String(v) => Ok(Value::Enum(Some(v.clone()), None)),
+                    _ => Err(JsonReaderError::encoding_mismatch(encoding, json))
+                }
+            }
+            Encoding::List(list_inner_encoding) => {
+                match json {
+                    JsonValue::Array(items) => {
+                        let mut values = Vec::with_capacity(items.len());
+                        for item in items {
+                            values.push(self.decode_value(item, list_inner_encoding)?);
+                        }
+                        Ok(Value::List(values))
+                    }
+                    _ => Err(JsonReaderError::encoding_mismatch(encoding, json))
+                }
+            }
+            Encoding::Bytes => {
+                match json {
+                    JsonValue::String(v) => {
+                        let bytes = hex::decode(v).map_err(|e| JsonReaderError::invalid_value(json, e))?;
+                        Ok(Value::List(bytes.into_iter().map(Value::Uint8).collect()))
+                    }
+                    _ => Err(JsonReaderError::encoding_mismatch(encoding, json))
+                }
+            }
+            Encoding::Hash(hash_type) => {
+                match json {
+                    JsonValue::String(v) => {
+                        let bytes = decode_hash(*hash_type, v).map_err(|reason| JsonReaderError::invalid_value(json, reason))?;
+                        Ok(Value::List(bytes.into_iter().map(Value::Uint8).collect()))
+                    }
+                    _ => Err(JsonReaderError::encoding_mismatch(encoding, json))
+                }
+            }
+            Encoding::Option(option_encoding) => {
+                match json {
+                    JsonValue::Null => Ok(Value::Option(None)),
+                    _ => Ok(Value::Option(Some(Box::new(self.decode_value(json, option_encoding)?))))
+                }
+            }
+            Encoding::Obj(obj_schema) => {
+                self.decode_record(json, obj_schema)
+            }
+            Encoding::Tup(tup_encodings) => {
+                self.decode_tuple(json, tup_encodings)
+            }
+            Encoding::Dynamic(dynamic_encoding)
+            | Encoding::ShortDynamic(dynamic_encoding) => {
+                self.decode_value(json, dynamic_encoding)
+            }
+            Encoding::Sized(_, sized_encoding) => {
+                self.decode_value(json, sized_encoding)
+            }
+            Encoding::Greedy(un_sized_encoding) => {
+                self.decode_value(json, un_sized_encoding)
+            }
+            Encoding::Tags(_, ref tag_map) => {
+                let (variant, tag_json) = match json {
+                    JsonValue::String(variant) => (variant.as_str(), &JsonValue::Null),
+                    JsonValue::Object(object) => match object.get("kind") {
+                        Some(JsonValue::String(variant)) => (variant.as_str(), json),
+                        _ if object.len() == 1 => {
+                            let (variant, tag_json) = object.iter().next().unwrap();
+                            (variant.as_str(), tag_json)
+                        }
+                        _ => return Err(JsonReaderError::encoding_mismatch(encoding, json))
+                    }
+                    _ => return Err(JsonReaderError::encoding_mismatch(encoding, json))
+                };
+
+                match tag_map.find_by_variant(variant) {
+                    Some(tag) => {
+                        let tag_value = self.decode_value(tag_json, tag.get_encoding())?;
+                        Ok(Value::Tag(tag.get_variant().to_string(), Box::new(tag_value)))
+                    }
+                    None => Err(JsonReaderError::UnsupportedTag { variant: variant.to_string() })
+                }
+            }
+            Encoding::Split(fn_encoding) => {
+                let inner_encoding = fn_encoding(SchemaType::Json);
+                self.decode_value(json, &inner_encoding)
+            }
+            Encoding::Lazy(fn_encoding) => {
+                let inner_encoding = fn_encoding();
+                self.decode_value(json, &inner_encoding)
+            }
+            Encoding::Uint32
+            | Encoding::RangedInt
+            | Encoding::RangedFloat => Err(JsonReaderError::UnsupportedEncoding { encoding: format!("{:?}", encoding) })
+        }
+    }
+}
+
+impl Default for JsonReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_option(encoding: &Encoding) -> bool {
+    match encoding {
+        Encoding::Option(_) => true,
+        Encoding::Dynamic(inner)
+        | Encoding::ShortDynamic(inner)
+        | Encoding::Sized(_, inner)
+        | Encoding::Greedy(inner) => is_option(inner),
+        _ => false
+    }
+}
+
+/// Read an integer number encoded as a json number and check it fits the target type.
+fn decode_int<T: TryFrom<i64>>(json: &JsonValue, encoding: &Encoding) -> Result<T, JsonReaderError> {
+    match json.as_i64() {
+        Some(v) => T::try_from(v).map_err(|_| JsonReaderError::invalid_value(json, format!("Value is outside of {:?} range", encoding))),
+        None => Err(JsonReaderError::encoding_mismatch(encoding, json))
+    }
+}
+
+/// Inverse of [HashType::bytes_to_string].
+fn decode_hash(hash_type: HashType, data: &str) -> Result<Vec<u8>, String> {
+    if let HashType::CryptoboxPublicKeyHash = hash_type {
+        return Err(format!("{:?} cannot be converted back to binary form", hash_type));
+    }
+
+    let mut hash = data.from_base58check().map_err(|e| format!("{:?}", e))?;
+    let expected_len = hash_type.size() + hash_type.prefix().len();
+    if hash.len() != expected_len || !hash.starts_with(hash_type.prefix()) {
+        return Err(format!("Not a valid {:?}", hash_type));
+    }
+    hash.drain(0..hash_type.prefix().len());
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::binary_reader::BinaryReader;
+    use crate::de;
+    use crate::encoding::{Tag, TagMap};
+    use crate::json_writer::JsonWriter;
+    use crate::types::BigInt;
+
+    use super::*;
+
+    #[test]
+    fn can_deserialize_complex_schema_written_by_json_writer() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        enum EnumType {
+            Accepted,
+            Running,
+            Disconnected,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Version {
+            name: String,
+            major: u16,
+            minor: u16,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct SubRecord {
+            x: i32,
+            y: i32,
+            v: Vec<i32>,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Record {
+            a: i32,
+            b: bool,
+            c: Option<BigInt>,
+            d: f64,
+            e: EnumType,
+            f: Vec<Version>,
+            s: SubRecord,
+            h: Vec<u8>,
+            p: Vec<u8>,
+            t: i64,
+            l: i64,
+            n: Option<BigInt>,
+        }
+
+        let record = Record {
+            a: 32,
+            b: true,
+            c: Some(num_bigint::BigInt::from(1_548_569_249).into()),
+            d: 12.34,
+            e: EnumType::Disconnected,
+            f: vec![Version { name: "A".to_string(), major: 1, minor: 1 }, Version { name: "B".to_string(), major: 2, minor: 0 }],
+            s: SubRecord {
+                x: 5,
+                y: 32,
+                v: vec![12, 34],
+            },
+            h: hex::decode("8eceda2f").unwrap(),
+            p: hex::decode("6cf20139cedef0ed52395a327ad13390d9e8c1e999339a24f8513fe513ed689a").unwrap(),
+            t: 1_553_127_011,
+            l: -9_007_199_254_740_993,
+            n: None,
+        };
+
+        let version_schema = vec![
+            Field::new("name", Encoding::String),
+            Field::new("major", Encoding::Uint16),
+            Field::new("minor", Encoding::Uint16)
+        ];
+
+        let sub_record_schema = vec![
+            Field::new("x", Encoding::Int31),
+            Field::new("y", Encoding::Int31),
+            Field::new("v", Encoding::dynamic(Encoding::list(Encoding::Int31)))
+        ];
+
+        let record_schema = Encoding::Obj(vec![
+            Field::new("a", Encoding::Int31),
+            Field::new("b", Encoding::Bool),
+            Field::new("t", Encoding::Timestamp),
+            Field::new("s", Encoding::Obj(sub_record_schema)),
+            Field::new("p", Encoding::sized(32, Encoding::Bytes)),
+            Field::new("c", Encoding::Option(Box::new(Encoding::Z))),
+            Field::new("d", Encoding::Float),
+            Field::new("e", Encoding::Enum),
+            Field::new("f", Encoding::dynamic(Encoding::list(Encoding::Obj(version_schema)))),
+            Field::new("h", Encoding::Hash(HashType::ChainId)),
+            Field::new("l", Encoding::Int64),
+            Field::new("n", Encoding::Option(Box::new(Encoding::Mutez)))
+        ]);
+
+        let json = JsonWriter::new().write(&record, &record_schema).unwrap();
+        assert!(json.contains(r#""c": "1548569249""#));
+
+        let value = JsonReader::new().read(&json, &record_schema).unwrap();
+        let record_deserialized: Record = de::from_value(&value).unwrap();
+        assert_eq!(record, record_deserialized);
+
+        // written again it must produce the same json
+        let json_again = JsonWriter::new().write(&record_deserialized, &record_schema).unwrap();
+        assert_eq!(json, json_again);
+    }
+
+    #[test]
+    fn can_read_tezos_json_specifics() {
+        let reader = JsonReader::new();
+
+        assert_eq!(Value::Int64(-9_007_199_254_740_993), reader.read(r#""-9007199254740993""#, &Encoding::Int64).unwrap());
+        assert_eq!(Value::Int64(42), reader.read("42", &Encoding::Int64).unwrap());
+        assert_eq!(Value::String("5c4d4aa1".to_string()), reader.read(r#""1548569249""#, &Encoding::Z).unwrap());
+        assert_eq!(Value::String("-ff".to_string()), reader.read("-255", &Encoding::Z).unwrap());
+        assert_eq!(Value::String("-ff".to_string()), reader.read(r#""-255""#, &Encoding::Z).unwrap());
+        assert!(reader.read(r#""5c4d4aa1""#, &Encoding::Z).is_err());
+        assert!(reader.read("-255", &Encoding::Mutez).is_err());
+        assert_eq!(Value::Int64(1_553_127_011), reader.read(r#""2019-03-21T00:10:11Z""#, &Encoding::Timestamp).unwrap());
+        assert_eq!(Value::Int64(1_553_127_011), reader.read(r#""2019-03-21T02:10:11+02:00""#, &Encoding::Timestamp).unwrap());
+        assert_eq!(
+            Value::List(vec![Value::Uint8(0x8e), Value::Uint8(0xce), Value::Uint8(0xda), Value::Uint8(0x2f)]),
+            reader.read(r#""NetXgtSLGNJvNye""#, &Encoding::Hash(HashType::ChainId)).unwrap()
+        );
+        assert!(reader.read(r#""NetXgtSLGNJvNye""#, &Encoding::Hash(HashType::BlockHash)).is_err());
+        assert!(reader.read(r#""not hex""#, &Encoding::Bytes).is_err());
+        assert!(reader.read("256", &Encoding::Uint8).is_err());
+        assert!(reader.read(r#"{ "a": 1 }"#, &Encoding::Obj(vec![Field::new("b", Encoding::Int8)])).is_err());
+    }
+
+    #[test]
+    fn can_read_numbers_of_tezos_node_operation() {
+        #[derive(Serialize, Deserialize, Debug)]
+        struct ManagerNumbers {
+            fee: BigInt,
+            counter: BigInt,
+            gas_limit: BigInt,
+            storage_limit: BigInt,
+            amount: BigInt,
+        }
+
+        // transaction contents as served by the Tezos node, numbers are decimal strings
+        let json = r#"{
+            "kind": "transaction",
+            "source": "tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx",
+            "fee": "2000",
+            "counter": "8",
+            "gas_limit": "15385",
+            "storage_limit": "257",
+            "amount": "1000000",
+            "destination": "KT1NrjjM791v7cyo6VGy7rrzB3Dg3p1mQki3",
+            "parameters": { "entrypoint": "transfer", "value": { "string": "abc" } }
+        }"#;
+        let encoding = Encoding::Obj(vec![
+            Field::new("fee", Encoding::Mutez),
+            Field::new("counter", Encoding::Mutez),
+            Field::new("gas_limit", Encoding::Mutez),
+            Field::new("storage_limit", Encoding::Mutez),
+            Field::new("amount", Encoding::Mutez),
+        ]);
+
+        let value = JsonReader::new().read(json, &encoding).unwrap();
+        let numbers: ManagerNumbers = de::from_value(&value).unwrap();
+        assert_eq!(num_bigint::BigInt::from(2000), numbers.fee.0);
+        assert_eq!(num_bigint::BigInt::from(8), numbers.counter.0);
+        assert_eq!(num_bigint::BigInt::from(15385), numbers.gas_limit.0);
+        assert_eq!(num_bigint::BigInt::from(257), numbers.storage_limit.0);
+        assert_eq!(num_bigint::BigInt::from(1_000_000), numbers.amount.0);
+
+        // same value as decoded from the binary form of the operation
+        let binary_value = BinaryReader::new().read(hex::decode("d00f0899788102c0843d").unwrap(), &encoding).unwrap();
+        assert_eq!(binary_value, value);
+    }
+
+    #[test]
+    fn can_deserialize_tags() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Transfer {
+            amount: i32,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        enum Operation {
+            Nop,
+            Transfer(Transfer),
+        }
+
+        let tags = Encoding::Tags(1, TagMap::new(&[
+            Tag::new(0x00, "Nop", Encoding::Unit),
+            Tag::new(0x01, "Transfer", Encoding::Obj(vec![Field::new("amount", Encoding::Int32)])),
+        ]));
+        let operations = Encoding::list(tags);
+        let reader = JsonReader::new();
+
+        let value = reader.read(r#"["Nop", { "Transfer": { "amount": 5 } }, { "kind": "Transfer", "amount": 7 }]"#, &operations).unwrap();
+        let operations_deserialized: Vec<Operation> = de::from_value(&value).unwrap();
+        assert_eq!(vec![Operation::Nop, Operation::Transfer(Transfer { amount: 5 }), Operation::Transfer(Transfer { amount: 7 })], operations_deserialized);
+
+        match reader.read(r#"{ "kind": "Burn" }"#, &operations) {
+            Err(JsonReaderError::EncodingMismatch { .. }) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        match reader.read(r#"[{ "kind": "Burn" }]"#, &operations) {
+            Err(JsonReaderError::UnsupportedTag { variant }) => assert_eq!("Burn", variant),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+}
@@ -203,13 +203,22 @@ impl JsonWriter {
                     _ => Err(Error::encoding_mismatch(encoding, value))
                 }
             }
-            Encoding::String |
-            Encoding::Z | Encoding::Mutez => {
+            Encoding::String => {
                 match value {
                     Value::String(v) => Ok(self.push_str(v)),
                     _ => Err(Error::encoding_mismatch(encoding, value))
                 }
             }
+            Encoding::Z | Encoding::Mutez => {
+                // numbers are kept in hexadecimal form, but Tezos json contains decimal numbers
+                match value {
+                    Value::String(v) => match num_bigint::BigInt::parse_bytes(v.as_bytes(), 16) {
+                        Some(num) => Ok(self.push_str(&num.to_string())),
+                        None => Err(Error::encoding_mismatch(encoding, value))
+                    },
+                    _ => Err(Error::encoding_mismatch(encoding, value))
+                }
+            }
             Encoding::Enum => {
                 match value {
                     Value::Enum(name, _) => {
@@ -285,7 +294,8 @@ impl JsonWriter {
             Encoding::Tup(tup_encodings) => {
                 self.encode_tuple(value, tup_encodings)
             }
-            Encoding::Dynamic(dynamic_encoding) => {
+            Encoding::Dynamic(dynamic_encoding)
+            | Encoding::ShortDynamic(dynamic_encoding) => {
                 self.encode_value(value, dynamic_encoding)
             }
             Encoding::Sized(_, sized_encoding) => {
@@ -409,7 +419,7 @@ mod tests {
         let writer_result = writer.write(&record, &Encoding::Obj(record_schema));
         assert!(writer_result.is_ok());
 
-        let expected_writer_result = r#"{ "a": 32, "b": true, "t": "2019-03-21T00:10:11+00:00", "s": { "x": 5, "y": 32, "v": [12, 34] }, "p": "6cf20139cedef0ed52395a327ad13390d9e8c1e999339a24f8513fe513ed689a", "c": "5c4d4aa1", "d": 12.34, "e": "Disconnected", "f": [{ "name": "A", "major": 1, "minor": 1 }, { "name": "B", "major": 2, "minor": 0 }], "h": "NetXgtSLGNJvNye" }"#;
+        let expected_writer_result = r#"{ "a": 32, "b": true, "t": "2019-03-21T00:10:11+00:00", "s": { "x": 5, "y": 32, "v": [12, 34] }, "p": "6cf20139cedef0ed52395a327ad13390d9e8c1e999339a24f8513fe513ed689a", "c": "1548569249", "d": 12.34, "e": "Disconnected", "f": [{ "name": "A", "major": 1, "minor": 1 }, { "name": "B", "major": 2, "minor": 0 }], "h": "NetXgtSLGNJvNye" }"#;
         assert_eq!(expected_writer_result, writer_result.unwrap());
     }
 }
@@ -9,7 +9,10 @@ mod bit_utils;
 
 pub mod encoding;
 pub mod de;
+pub mod describe;
 pub mod ser;
 pub mod binary_reader;
 pub mod binary_writer;
-pub mod json_writer;
\ No newline at end of file
+pub mod json_reader;
+pub mod json_writer;
+pub mod micheline;
\ No newline at end of file
@@ -0,0 +1,511 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Micheline is a generic tree format used by Michelson scripts and data (contract code, storage,
+//! transaction parameters, ...). This module converts micheline expressions between Tezos binary
+//! form and the canonical JSON form produced by OCaml RPC.
+
+use failure::Fail;
+use num_bigint::{BigInt, BigUint, Sign};
+use num_traits::{ToPrimitive, Zero};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::Error as DeError;
+use serde::ser::SerializeMap;
+
+/// Michelson primitives of the protocols 005 and 006. Binary code of the primitive is its index.
+const PRIMITIVES: [&str; 118] = [
+    "parameter", "storage", "code", "False", "Elt", "Left", "None", "Pair", "Right", "Some", "True", "Unit",
+    "PACK", "UNPACK", "BLAKE2B", "SHA256", "SHA512", "ABS", "ADD", "AMOUNT", "AND", "BALANCE", "CAR", "CDR",
+    "CHECK_SIGNATURE", "COMPARE", "CONCAT", "CONS", "CREATE_ACCOUNT", "CREATE_CONTRACT", "IMPLICIT_ACCOUNT",
+    "DIP", "DROP", "DUP", "EDIV", "EMPTY_MAP", "EMPTY_SET", "EQ", "EXEC", "FAILWITH", "GE", "GET", "GT",
+    "HASH_KEY", "IF", "IF_CONS", "IF_LEFT", "IF_NONE", "INT", "LAMBDA", "LE", "LEFT", "LOOP", "LSL", "LSR",
+    "LT", "MAP", "MEM", "MUL", "NEG", "NEQ", "NIL", "NONE", "NOT", "NOW", "OR", "PAIR", "PUSH", "RIGHT",
+    "SIZE", "SOME", "SOURCE", "SENDER", "SELF", "STEPS_TO_QUOTA", "SUB", "SWAP", "TRANSFER_TOKENS",
+    "SET_DELEGATE", "UNIT", "UPDATE", "XOR", "ITER", "LOOP_LEFT", "ADDRESS", "CONTRACT", "ISNAT", "CAST",
+    "RENAME", "bool", "contract", "int", "key", "key_hash", "lambda", "list", "map", "big_map", "nat",
+    "option", "or", "pair", "set", "signature", "string", "bytes", "mutez", "timestamp", "unit", "operation",
+    "address", "SLICE", "DIG", "DUG", "EMPTY_BIG_MAP", "APPLY", "chain_id", "CHAIN_ID",
+];
+
+const TAG_INT: u8 = 0x00;
+const TAG_STRING: u8 = 0x01;
+const TAG_SEQ: u8 = 0x02;
+/// Primitives with up to two arguments have dedicated tags `0x03..=0x08`, odd tags are without annotations.
+const TAG_PRIM_NO_ARGS: u8 = 0x03;
+const TAG_PRIM_TWO_ARGS_ANNOTS: u8 = 0x08;
+const TAG_PRIM_GENERIC: u8 = 0x09;
+const TAG_BYTES: u8 = 0x0A;
+
+/// Size of the length prefix of dynamically sized values.
+const DYNAMIC_SIZE_BYTES: usize = 4;
+
+/// Maximal nesting of the decoded nodes, deeper expressions are rejected instead of overflowing the stack.
+/// Decoding is recursive, so the limit has to fit into the 2MB stack of the spawned threads also in debug builds.
+pub const MAX_DEPTH: usize = 256;
+
+/// Error produced by micheline decoding or encoding.
+#[derive(Debug, Fail)]
+pub enum MichelineError {
+    /// More bytes were expected than there were available in input buffer.
+    #[fail(display = "Input underflow, missing {} bytes", bytes)]
+    Underflow {
+        bytes: usize
+    },
+    /// Input buffer contains more bytes than the expression.
+    #[fail(display = "Input overflow, excess of {} bytes", bytes)]
+    Overflow {
+        bytes: usize
+    },
+    /// Node tag is not known.
+    #[fail(display = "No micheline node found for tag: 0x{:X}", tag)]
+    UnsupportedTag {
+        tag: u8
+    },
+    /// Primitive code is not known.
+    #[fail(display = "No primitive found for code: 0x{:X}", code)]
+    UnsupportedPrimitiveCode {
+        code: u8
+    },
+    /// Primitive name is not known, so it cannot be encoded into binary form.
+    #[fail(display = "No primitive found for name: {}", prim)]
+    UnsupportedPrimitive {
+        prim: String
+    },
+    /// Node contents are not valid.
+    #[fail(display = "Invalid micheline node, reason: {}", reason)]
+    InvalidNode {
+        reason: String
+    },
+    /// Nodes are nested deeper than allowed.
+    #[fail(display = "Micheline expression is nested deeper than {} nodes", max_depth)]
+    TooDeep {
+        max_depth: usize
+    },
+}
+
+/// Micheline expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Micheline {
+    Int(BigInt),
+    String(String),
+    Bytes(Vec<u8>),
+    Prim {
+        prim: String,
+        args: Vec<Micheline>,
+        annots: Vec<String>,
+    },
+    Seq(Vec<Micheline>),
+}
+
+impl Micheline {
+    /// Decode micheline expression from its binary form.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Micheline, MichelineError> {
+        let mut buf = bytes;
+        let node = read_node(&mut buf, 1)?;
+        if buf.is_empty() {
+            Ok(node)
+        } else {
+            Err(MichelineError::Overflow { bytes: buf.len() })
+        }
+    }
+
+    /// Decode micheline expression prefixed by its length. This is how scripts and storage of contracts
+    /// are stored in the context and carried by operations.
+    pub fn from_lazy_expr_bytes(bytes: &[u8]) -> Result<Micheline, MichelineError> {
+        let mut buf = bytes;
+        let expr = read_dynamic(&mut buf)?;
+        if buf.is_empty() {
+            Micheline::from_bytes(expr)
+        } else {
+            Err(MichelineError::Overflow { bytes: buf.len() })
+        }
+    }
+
+    /// Encode micheline expression into its binary form.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MichelineError> {
+        let mut out = Vec::new();
+        write_node(self, &mut out)?;
+        Ok(out)
+    }
+
+    /// Encode micheline expression into its binary form prefixed by its length.
+    pub fn to_lazy_expr_bytes(&self) -> Result<Vec<u8>, MichelineError> {
+        let mut out = Vec::new();
+        write_dynamic(&self.to_bytes()?, &mut out);
+        Ok(out)
+    }
+}
+
+fn read_u8(buf: &mut &[u8]) -> Result<u8, MichelineError> {
+    match buf.split_first() {
+        Some((byte, rest)) => {
+            *buf = rest;
+            Ok(*byte)
+        }
+        None => Err(MichelineError::Underflow { bytes: 1 })
+    }
+}
+
+fn read_dynamic<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8], MichelineError> {
+    if buf.len() < DYNAMIC_SIZE_BYTES {
+        return Err(MichelineError::Underflow { bytes: DYNAMIC_SIZE_BYTES - buf.len() });
+    }
+    let (size, rest) = buf.split_at(DYNAMIC_SIZE_BYTES);
+    let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as usize;
+    if rest.len() < size {
+        return Err(MichelineError::Underflow { bytes: size - rest.len() });
+    }
+    let (data, rest) = rest.split_at(size);
+    *buf = rest;
+    Ok(data)
+}
+
+fn read_string(buf: &mut &[u8]) -> Result<String, MichelineError> {
+    String::from_utf8(read_dynamic(buf)?.to_vec())
+        .map_err(|e| MichelineError::InvalidNode { reason: format!("Error decoding UTF-8 string. Reason: {:?}", e) })
+}
+
+fn read_z(buf: &mut &[u8]) -> Result<BigInt, MichelineError> {
+    let mut byte = read_u8(buf)?;
+    let sign = if byte & 0x40 != 0 { Sign::Minus } else { Sign::Plus };
+    let mut value = BigUint::from(byte & 0x3F);
+    let mut shift = 6;
+    while byte & 0x80 != 0 {
+        byte = read_u8(buf)?;
+        value |= BigUint::from(byte & 0x7F) << shift;
+        shift += 7;
+    }
+    Ok(BigInt::from_biguint(sign, value))
+}
+
+fn read_prim(buf: &mut &[u8]) -> Result<String, MichelineError> {
+    let code = read_u8(buf)?;
+    PRIMITIVES.get(code as usize)
+        .map(|prim| prim.to_string())
+        .ok_or(MichelineError::UnsupportedPrimitiveCode { code })
+}
+
+fn read_annots(buf: &mut &[u8]) -> Result<Vec<String>, MichelineError> {
+    Ok(read_string(buf)?
+        .split(' ')
+        .filter(|annot| !annot.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+fn read_nodes(buf: &mut &[u8], depth: usize) -> Result<Vec<Micheline>, MichelineError> {
+    let mut data = read_dynamic(buf)?;
+    let mut nodes = Vec::new();
+    while !data.is_empty() {
+        nodes.push(read_node(&mut data, depth)?);
+    }
+    Ok(nodes)
+}
+
+/// Read node at the `depth` of the expression, top level node is at depth 1
+fn read_node(buf: &mut &[u8], depth: usize) -> Result<Micheline, MichelineError> {
+    if depth > MAX_DEPTH {
+        return Err(MichelineError::TooDeep { max_depth: MAX_DEPTH });
+    }
+    match read_u8(buf)? {
+        TAG_INT => Ok(Micheline::Int(read_z(buf)?)),
+        TAG_STRING => Ok(Micheline::String(read_string(buf)?)),
+        TAG_SEQ => Ok(Micheline::Seq(read_nodes(buf, depth + 1)?)),
+        tag @ TAG_PRIM_NO_ARGS..=TAG_PRIM_TWO_ARGS_ANNOTS => {
+            let prim = read_prim(buf)?;
+            let args_count = (tag - TAG_PRIM_NO_ARGS) / 2;
+            let mut args = Vec::with_capacity(args_count as usize);
+            for _ in 0..args_count {
+                args.push(read_node(buf, depth + 1)?);
+            }
+            let annots = if tag % 2 == 0 { read_annots(buf)? } else { Vec::new() };
+            Ok(Micheline::Prim { prim, args, annots })
+        }
+        TAG_PRIM_GENERIC => {
+            let prim = read_prim(buf)?;
+            let args = read_nodes(buf, depth + 1)?;
+            let annots = read_annots(buf)?;
+            Ok(Micheline::Prim { prim, args, annots })
+        }
+        TAG_BYTES => Ok(Micheline::Bytes(read_dynamic(buf)?.to_vec())),
+        tag => Err(MichelineError::UnsupportedTag { tag })
+    }
+}
+
+fn write_dynamic(data: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+fn write_z(value: &BigInt, out: &mut Vec<u8>) {
+    let (sign, magnitude) = value.to_bytes_le();
+    let mut magnitude = BigUint::from_bytes_le(&magnitude);
+    let mut byte = (&magnitude & BigUint::from(0x3Fu8)).to_u8().unwrap();
+    if sign == Sign::Minus {
+        byte |= 0x40;
+    }
+    magnitude >>= 6;
+    while !magnitude.is_zero() {
+        out.push(byte | 0x80);
+        byte = (&magnitude & BigUint::from(0x7Fu8)).to_u8().unwrap();
+        magnitude >>= 7;
+    }
+    out.push(byte);
+}
+
+fn write_nodes(nodes: &[Micheline], out: &mut Vec<u8>) -> Result<(), MichelineError> {
+    let mut data = Vec::new();
+    for node in nodes {
+        write_node(node, &mut data)?;
+    }
+    write_dynamic(&data, out);
+    Ok(())
+}
+
+fn write_node(node: &Micheline, out: &mut Vec<u8>) -> Result<(), MichelineError> {
+    match node {
+        Micheline::Int(value) => {
+            out.push(TAG_INT);
+            write_z(value, out);
+        }
+        Micheline::String(value) => {
+            out.push(TAG_STRING);
+            write_dynamic(value.as_bytes(), out);
+        }
+        Micheline::Bytes(value) => {
+            out.push(TAG_BYTES);
+            write_dynamic(value, out);
+        }
+        Micheline::Seq(nodes) => {
+            out.push(TAG_SEQ);
+            write_nodes(nodes, out)?;
+        }
+        Micheline::Prim { prim, args, annots } => {
+            let code = PRIMITIVES.iter()
+                .position(|p| p == prim)
+                .ok_or_else(|| MichelineError::UnsupportedPrimitive { prim: prim.clone() })?;
+            if args.len() <= 2 {
+                let has_annots = if annots.is_empty() { 0 } else { 1 };
+                out.push(TAG_PRIM_NO_ARGS + 2 * args.len() as u8 + has_annots);
+                out.push(code as u8);
+                for arg in args {
+                    write_node(arg, out)?;
+                }
+                if !annots.is_empty() {
+                    write_dynamic(annots.join(" ").as_bytes(), out);
+                }
+            } else {
+                out.push(TAG_PRIM_GENERIC);
+                out.push(code as u8);
+                write_nodes(args, out)?;
+                write_dynamic(annots.join(" ").as_bytes(), out);
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Serialize for Micheline {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        match self {
+            Micheline::Int(value) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("int", &value.to_string())?;
+                map.end()
+            }
+            Micheline::String(value) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("string", value)?;
+                map.end()
+            }
+            Micheline::Bytes(value) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("bytes", &hex::encode(value))?;
+                map.end()
+            }
+            Micheline::Seq(nodes) => nodes.serialize(serializer),
+            Micheline::Prim { prim, args, annots } => {
+                let len = 1 + (!args.is_empty() as usize) + (!annots.is_empty() as usize);
+                let mut map = serializer.serialize_map(Some(len))?;
+                map.serialize_entry("prim", prim)?;
+                if !args.is_empty() {
+                    map.serialize_entry("args", args)?;
+                }
+                if !annots.is_empty() {
+                    map.serialize_entry("annots", annots)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+/// JSON form of the micheline node, see [Micheline] serde implementations.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MichelineJson {
+    Seq(Vec<Micheline>),
+    Int {
+        int: String
+    },
+    String {
+        string: String
+    },
+    Bytes {
+        bytes: String
+    },
+    Prim {
+        prim: String,
+        #[serde(default)]
+        args: Vec<Micheline>,
+        #[serde(default)]
+        annots: Vec<String>,
+    },
+}
+
+impl<'de> Deserialize<'de> for Micheline {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        match MichelineJson::deserialize(deserializer)? {
+            MichelineJson::Seq(nodes) => Ok(Micheline::Seq(nodes)),
+            MichelineJson::Int { int } => int.parse::<BigInt>()
+                .map(Micheline::Int)
+                .map_err(|e| D::Error::custom(format!("Invalid micheline int {}: {}", int, e))),
+            MichelineJson::String { string } => Ok(Micheline::String(string)),
+            MichelineJson::Bytes { bytes } => hex::decode(&bytes)
+                .map(Micheline::Bytes)
+                .map_err(|e| D::Error::custom(format!("Invalid micheline bytes {}: {}", bytes, e))),
+            MichelineJson::Prim { prim, args, annots } => Ok(Micheline::Prim { prim, args, annots }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prim(prim: &str, args: Vec<Micheline>, annots: Vec<&str>) -> Micheline {
+        Micheline::Prim { prim: prim.to_string(), args, annots: annots.into_iter().map(String::from).collect() }
+    }
+
+    #[test]
+    fn can_decode_and_encode_nodes() -> Result<(), failure::Error> {
+        let expr = Micheline::from_bytes(&hex::decode("0707006a0100000024747a314b715470455a37596f62375162504534487934576f38664847384c684b785a5378")?)?;
+        assert_eq!(prim("Pair", vec![Micheline::Int(BigInt::from(-42)), Micheline::String("tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx".to_string())], vec![]), expr);
+
+        let expr = Micheline::from_bytes(&hex::decode("02000000110743035b0000053d036d0a000000020102")?)?;
+        let expected = Micheline::Seq(vec![
+            prim("PUSH", vec![prim("int", vec![], vec![]), Micheline::Int(BigInt::from(0))], vec![]),
+            prim("NIL", vec![prim("operation", vec![], vec![])], vec![]),
+            Micheline::Bytes(vec![1, 2]),
+        ]);
+        assert_eq!(expected, expr);
+        assert_eq!("02000000110743035b0000053d036d0a000000020102", hex::encode(expr.to_bytes()?));
+        Ok(())
+    }
+
+    #[test]
+    fn can_decode_and_encode_annotations_and_generic_prim() -> Result<(), failure::Error> {
+        let bytes = hex::decode("091d00000006000100410000000000054061202562")?;
+        let expr = Micheline::from_bytes(&bytes)?;
+        let expected = prim("CREATE_CONTRACT", vec![Micheline::Int(BigInt::from(1)), Micheline::Int(BigInt::from(-1)), Micheline::Int(BigInt::from(0))], vec!["@a", "%b"]);
+        assert_eq!(expected, expr);
+        assert_eq!(bytes, expr.to_bytes()?);
+
+        let bytes = hex::decode("0865045b0000000825636f756e746572046e000000062561646d696e000000083a73746f72616765")?;
+        let expr = Micheline::from_bytes(&bytes)?;
+        let expected = prim("pair", vec![prim("int", vec![], vec!["%counter"]), prim("address", vec![], vec!["%admin"])], vec![":storage"]);
+        assert_eq!(expected, expr);
+        assert_eq!(bytes, expr.to_bytes()?);
+        Ok(())
+    }
+
+    #[test]
+    fn can_encode_big_int() -> Result<(), failure::Error> {
+        let expr = Micheline::Int("1000000000000000000000".parse()?);
+        assert_eq!("00808080eabbf1d6c9ebd801", hex::encode(expr.to_bytes()?));
+        assert_eq!("008001", hex::encode(Micheline::Int(BigInt::from(64)).to_bytes()?));
+        assert_eq!(expr, Micheline::from_bytes(&expr.to_bytes()?)?);
+        Ok(())
+    }
+
+    #[test]
+    fn can_serialize_to_json_and_back() -> Result<(), failure::Error> {
+        let json = r#"[{"prim":"PUSH","args":[{"prim":"int"},{"int":"-42"}],"annots":["@x"]},{"string":"a"},{"bytes":"0102"},[]]"#;
+        let expr: Micheline = serde_json::from_str(json)?;
+        assert_eq!(Micheline::Seq(vec![
+            prim("PUSH", vec![prim("int", vec![], vec![]), Micheline::Int(BigInt::from(-42))], vec!["@x"]),
+            Micheline::String("a".to_string()),
+            Micheline::Bytes(vec![1, 2]),
+            Micheline::Seq(vec![]),
+        ]), expr);
+        assert_eq!(json, serde_json::to_string(&expr)?);
+        Ok(())
+    }
+
+    #[test]
+    fn can_round_trip_contract_code_and_storage() -> Result<(), failure::Error> {
+        // values of the `data/contracts/index/../data/code` and `data/contracts/index/../data/storage` context keys
+        let contexts_values = vec![
+            ("000000bd02000000b805000764045b0000000a2564656372656d656e740764045b0000000a25696e6372656d656e74046c0000000625726573657405010865045b0000000825636f756e746572046e000000062561646d696e000000083a73746f726167650502020000005503210317034c0316072e020000000f051f020000000403210316034c034b0200000021072e020000000d051f0200000004032103160312020000000803200743035b0000051f020000000203170342053d036d0342", r#"[{"prim":"parameter","args":[{"prim":"or","args":[{"prim":"int","annots":["%decrement"]},{"prim":"or","args":[{"prim":"int","annots":["%increment"]},{"prim":"unit","annots":["%reset"]}]}]}]},{"prim":"storage","args":[{"prim":"pair","args":[{"prim":"int","annots":["%counter"]},{"prim":"address","annots":["%admin"]}],"annots":[":storage"]}]},{"prim":"code","args":[[{"prim":"DUP"},{"prim":"CDR"},{"prim":"SWAP"},{"prim":"CAR"},{"prim":"IF_LEFT","args":[[{"prim":"DIP","args":[[{"prim":"DUP"},{"prim":"CAR"}]]},{"prim":"SWAP"},{"prim":"SUB"}],[{"prim":"IF_LEFT","args":[[{"prim":"DIP","args":[[{"prim":"DUP"},{"prim":"CAR"}]]},{"prim":"ADD"}],[{"prim":"DROP"},{"prim":"PUSH","args":[{"prim":"int"},{"int":"0"}]}]]}]]},{"prim":"DIP","args":[[{"prim":"CDR"}]]},{"prim":"PAIR"},{"prim":"NIL","args":[{"prim":"operation"}]},{"prim":"PAIR"}]]}]"#),
+            ("0000008f0707020000008007040a00000016000002298c03ed7d454a101eb7022bc95f7e5f41ac78070700808080eabbf1d6c9ebd801020000000007040a000000160000e7670f32038107a59a2b9cfefae36ea21f5aa63c0707008001020000002901000000244b54314245717a6e35577838754a725a4e767553394456486d4c76473974643366444c69070703060509030a", r#"{"prim":"Pair","args":[[{"prim":"Elt","args":[{"bytes":"000002298c03ed7d454a101eb7022bc95f7e5f41ac78"},{"prim":"Pair","args":[{"int":"1000000000000000000000"},[]]}]},{"prim":"Elt","args":[{"bytes":"0000e7670f32038107a59a2b9cfefae36ea21f5aa63c"},{"prim":"Pair","args":[{"int":"64"},[{"string":"KT1BEqzn5Wx8uJrZNvuS9DVHmLvG9td3fDLi"}]]}]}],{"prim":"Pair","args":[{"prim":"None"},{"prim":"Some","args":[{"prim":"True"}]}]}]}"#),
+        ];
+
+        for (value, expected_json) in contexts_values {
+            let value = hex::decode(value)?;
+            let expr = Micheline::from_lazy_expr_bytes(&value)?;
+            assert_eq!(expected_json, serde_json::to_string(&expr)?);
+
+            let expr: Micheline = serde_json::from_str(expected_json)?;
+            assert_eq!(value, expr.to_lazy_expr_bytes()?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn can_not_decode_invalid_expressions() -> Result<(), failure::Error> {
+        // unknown node tag
+        assert!(Micheline::from_bytes(&hex::decode("0b")?).is_err());
+        // unknown primitive code
+        assert!(Micheline::from_bytes(&hex::decode("03ff")?).is_err());
+        // missing second argument of the pair
+        assert!(Micheline::from_bytes(&hex::decode("07070001")?).is_err());
+        // trailing bytes
+        assert!(Micheline::from_bytes(&hex::decode("030b00")?).is_err());
+        // unknown primitive name
+        assert!(prim("UNKNOWN", vec![], vec![]).to_bytes().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn can_not_decode_too_deep_expressions() -> Result<(), failure::Error> {
+        // `Some (Some (... Unit))` nested to the depth
+        let nested = |depth: usize| hex::decode("0509".repeat(depth - 1) + "030b");
+
+        let expr = Micheline::from_bytes(&nested(MAX_DEPTH)?)?;
+        assert_eq!(nested(MAX_DEPTH)?, expr.to_bytes()?);
+
+        match Micheline::from_bytes(&nested(MAX_DEPTH + 1)?) {
+            Err(MichelineError::TooDeep { max_depth }) => assert_eq!(MAX_DEPTH, max_depth),
+            result => panic!("expected too deep expression, but was: {:?}", result),
+        }
+
+        // deeply nested sequences do not overflow the stack, every sequence adds 5 bytes
+        let depth: u32 = 100_000;
+        let mut bytes = Vec::new();
+        for level in 0..depth {
+            bytes.push(TAG_SEQ);
+            bytes.extend_from_slice(&(5 * (depth - level - 1)).to_be_bytes());
+        }
+        match Micheline::from_bytes(&bytes) {
+            Err(MichelineError::TooDeep { .. }) => (),
+            result => panic!("expected too deep expression, but was: {:?}", result),
+        }
+        Ok(())
+    }
+}
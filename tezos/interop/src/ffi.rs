@@ -213,6 +213,46 @@ pub fn apply_block(apply_block_request: RustBytes)
     })
 }
 
+/// Prevalidates operation on top of the current head, result is used by mempool
+/// - validate_operation_request see [tezos_api::ffi:ValidateOperationRequest]
+pub fn validate_operation(validate_operation_request: RustBytes)
+    -> Result<Result<ValidateOperationResult, ValidateOperationError>, OcamlError> {
+    runtime::execute(move || {
+        // older tezos ffi libraries do not register the prevalidation callback
+        let ocaml_function = match ocaml::named_value("validate_operation") {
+            Some(ocaml_function) => ocaml_function,
+            None => return Err(ValidateOperationError::OperationValidationNotSupported {
+                message: "function 'validate_operation' is not registered".to_string()
+            }),
+        };
+
+        // call ffi
+        match ocaml_function.call_exn::<OcamlBytes>(validate_operation_request.convert_to()) {
+            Ok(validation_result) => {
+                let validation_result: Tuple = validation_result.into();
+
+                let classification_tag = validation_result.get(0).unwrap().usize_val();
+                let protocol_data_json: Str = validation_result.get(1).unwrap().into();
+                let error_json: Str = validation_result.get(2).unwrap().into();
+
+                match OperationClassification::from_tag(classification_tag) {
+                    Some(classification) => Ok(ValidateOperationResult {
+                        classification,
+                        protocol_data_json: protocol_data_json.as_str().to_string(),
+                        error_json: error_json.as_str().to_string(),
+                    }),
+                    None => Err(ValidateOperationError::FailedToValidateOperation {
+                        message: format!("Unknown operation classification tag: {}", classification_tag)
+                    })
+                }
+            }
+            Err(e) => {
+                Err(ValidateOperationError::from(e))
+            }
+        }
+    })
+}
+
 pub fn generate_identity(expected_pow: f64) -> Result<Result<Identity, TezosGenerateIdentityError>, OcamlError> {
     runtime::execute(move || {
         let ocaml_function = ocaml::named_value("generate_identity").expect("function 'generate_identity' is not registered");
@@ -0,0 +1,58 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Comparison of block fitness, which is used for choosing the best branch.
+//! Follows `Fitness.compare` from the tezos base library.
+
+use std::cmp::Ordering;
+
+/// Fitness of the block as it is stored in the [`BlockHeader`](crate::p2p::encoding::block_header::BlockHeader)
+pub type Fitness = Vec<Vec<u8>>;
+
+/// Compares two fitness values.
+///
+/// Fitness with more elements is always greater. When element count is the same, elements are compared
+/// one by one, where longer element is greater and elements of the same length are compared byte by byte.
+pub fn fitness_compare(fitness1: &Fitness, fitness2: &Fitness) -> Ordering {
+    fitness1.len().cmp(&fitness2.len())
+        .then_with(|| {
+            fitness1.iter()
+                .zip(fitness2.iter())
+                .map(|(a, b)| a.len().cmp(&b.len()).then_with(|| a.cmp(b)))
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        })
+}
+
+/// Returns true if `new_fitness` is strictly greater than `current_fitness`
+#[inline]
+pub fn fitness_increases(current_fitness: &Fitness, new_fitness: &Fitness) -> bool {
+    fitness_compare(new_fitness, current_fitness) == Ordering::Greater
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fitness_compare() {
+        let f1: Fitness = vec![vec![0], vec![0, 0, 0, 0, 0, 0, 0, 1]];
+        let f2: Fitness = vec![vec![0], vec![0, 0, 0, 0, 0, 0, 0, 2]];
+        assert_eq!(Ordering::Less, fitness_compare(&f1, &f2));
+        assert_eq!(Ordering::Greater, fitness_compare(&f2, &f1));
+        assert_eq!(Ordering::Equal, fitness_compare(&f1, &f1.clone()));
+
+        // longer element wins
+        let f3: Fitness = vec![vec![0], vec![0, 0, 0, 0, 0, 0, 0, 0, 0]];
+        assert_eq!(Ordering::Greater, fitness_compare(&f3, &f2));
+
+        // more elements wins
+        let f4: Fitness = vec![vec![0], vec![0], vec![0]];
+        assert_eq!(Ordering::Greater, fitness_compare(&f4, &f3));
+        assert_eq!(Ordering::Less, fitness_compare(&vec![], &f1));
+
+        assert!(fitness_increases(&f1, &f2));
+        assert!(!fitness_increases(&f2, &f1));
+        assert!(!fitness_increases(&f1, &f1));
+    }
+}
@@ -11,6 +11,8 @@ use serde::{Deserialize, Serialize};
 use crypto::base58::FromBase58CheckError;
 use crypto::blake2b;
 use crypto::hash::{ContractTz1Hash, ContractTz2Hash, ContractTz3Hash, HashType};
+use crypto::signature::PublicKey;
+use tezos_encoding::encoding::{Encoding, HasEncoding, Tag, TagMap};
 
 #[derive(Debug, Fail, PartialEq)]
 pub enum ConversionError {
@@ -134,9 +136,35 @@ impl SignaturePublicKeyHash {
     }
 }
 
+/// Binary representation is the curve tag (0 - ed25519, 1 - secp256k1, 2 - p256) followed by the 20 bytes of the hash
+impl HasEncoding for SignaturePublicKeyHash {
+    fn encoding() -> Encoding {
+        Encoding::Tags(
+            std::mem::size_of::<u8>(),
+            TagMap::new(&[
+                Tag::new(0x00, "Ed25519", Encoding::Hash(HashType::ContractTz1Hash)),
+                Tag::new(0x01, "Secp256k1", Encoding::Hash(HashType::ContractTz2Hash)),
+                Tag::new(0x02, "P256", Encoding::Hash(HashType::ContractTz3Hash)),
+            ]),
+        )
+    }
+}
+
+impl From<&PublicKey> for SignaturePublicKeyHash {
+    fn from(public_key: &PublicKey) -> Self {
+        let hash = public_key.public_key_hash();
+        match public_key {
+            PublicKey::Ed25519(_) => SignaturePublicKeyHash::Ed25519(hash),
+            PublicKey::Secp256k1(_) => SignaturePublicKeyHash::Secp256k1(hash),
+            PublicKey::P256(_) => SignaturePublicKeyHash::P256(hash),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crypto::hash::HashType;
+    use crypto::signature::PublicKey;
 
     use crate::base::signature_public_key_hash::{ConversionError, SignaturePublicKeyHash};
 
@@ -202,4 +230,18 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_from_public_key() -> Result<(), failure::Error> {
+        let result = SignaturePublicKeyHash::from(&PublicKey::from_b58("edpkuBknW28nW72KG6RoHtYW7p12T6GKc7nAbwYX5m8Wd9sDVC9yav")?);
+        assert_eq!(result.to_string().as_str(), "tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx");
+
+        let result = SignaturePublicKeyHash::from(&PublicKey::from_b58("sppk7a5cv7qynbR4nABzLjgRyjt1gT8cVwsbESH7xbPbMZnWHX2eN5k")?);
+        assert_eq!(result.to_string().as_str(), "tz2EUM2Fh6w3PiMJ1iEqvWKyksT9Hjd8NQcs");
+
+        let result = SignaturePublicKeyHash::from(&PublicKey::from_b58("p2pk67HTySHGGdwtgp5sMYATzRs9dqmoCi9ZvMD6ki5mGp6CHC2CMax")?);
+        assert_eq!(result.to_string().as_str(), "tz3V9Ept7k2qpwUz5DJm7WEi2PWfdcrZN1zb");
+
+        Ok(())
+    }
+}
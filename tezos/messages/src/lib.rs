@@ -7,9 +7,13 @@
 
 use chrono::prelude::*;
 
+// allows derived implementations to refer to this crate by its name
+extern crate self as tezos_messages;
+
 pub mod base;
 pub mod p2p;
 pub mod protocol;
+pub mod schema;
 
 /// Helper function to format UNIX (integral) timestamp to RFC3339 string timestamp
 pub fn ts_to_rfc3339(ts: i64) -> String {
@@ -9,7 +9,7 @@ use serde::Serialize;
 
 use crypto::blake2b;
 use crypto::hash::Hash;
-use tezos_encoding::binary_reader::{BinaryReader, BinaryReaderError};
+use tezos_encoding::binary_reader::{BinaryReader, BinaryReaderError, SizeLimit};
 use tezos_encoding::binary_writer;
 use tezos_encoding::de::from_value as deserialize_from_value;
 use tezos_encoding::encoding::HasEncoding;
@@ -22,6 +22,8 @@ use crate::p2p::binary_message::MessageHashError::SerializationError;
 pub const CONTENT_LENGTH_FIELD_BYTES: usize = 2;
 /// Max allowed message length in bytes
 pub const CONTENT_LENGTH_MAX: usize = u16::max_value() as usize;
+/// Default max allowed size of the whole message (possibly split into multiple chunks) in bytes
+pub const MESSAGE_SIZE_MAX_DEFAULT: usize = 64 * 1024;
 
 pub mod cache {
     use std::fmt;
@@ -157,6 +159,17 @@ impl<T> BinaryMessage for T
     }
 }
 
+/// Trait for limiting the size of the binary message received from peer.
+///
+/// Limit is enforced by the [`StreamReader`](tezos_encoding::binary_reader::StreamReader) while the message chunks are received.
+pub trait HasSizeLimit {
+
+    /// Max allowed size of the message in bytes.
+    fn size_limit() -> SizeLimit {
+        SizeLimit::Fixed(MESSAGE_SIZE_MAX_DEFAULT)
+    }
+}
+
 
 /// Represents binary raw encoding received from peer node.
 ///
@@ -2,39 +2,48 @@
 // SPDX-License-Identifier: MIT
 
 use std::fmt;
-use std::mem::size_of;
 
 use getset::Getters;
 use serde::{Deserialize, Serialize};
 
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding, Tag, TagMap};
+use tezos_messages_derive::{CachedData, HasEncoding};
 
-use crate::p2p::binary_message::cache::{CachedData, CacheReader, CacheWriter, NeverCache};
+use crate::p2p::binary_message::HasSizeLimit;
 
-static DUMMY_BODY_CACHE: NeverCache = NeverCache;
-
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, HasEncoding, CachedData)]
+#[encoding(tags = "u8")]
 pub enum AckMessage {
+    #[encoding(tag = 0x00)]
     Ack,
+    #[encoding(tag = 0xFF)]
     NackV0,
+    #[encoding(tag = 0x01)]
     Nack(NackInfo),
 }
 
-#[derive(Serialize, Deserialize, PartialEq)]
+#[derive(Serialize, Deserialize, PartialEq, HasEncoding)]
+#[encoding(tags = "u16")]
 pub enum NackMotive {
+    #[encoding(tag = 0)]
     NoMotive,
+    #[encoding(tag = 1)]
     TooManyConnections,
+    #[encoding(tag = 2)]
     UnknownChainName,
+    #[encoding(tag = 3)]
     DeprecatedP2pVersion,
+    #[encoding(tag = 4)]
     DeprecatedDistributedDbVersion,
+    #[encoding(tag = 5)]
     AlreadyConnected
 }
 
-#[derive(Serialize, Deserialize, Getters, PartialEq)]
+#[derive(Serialize, Deserialize, Getters, PartialEq, HasEncoding)]
 pub struct NackInfo {
     #[get = "pub"]
     motive: NackMotive,
     #[get = "pub"]
+    #[encoding(dynamic, list)]
     potential_peers_to_connect: Vec<String>,
 }
 
@@ -62,47 +71,4 @@ impl fmt::Debug for NackInfo {
     }
 }
 
-impl NackInfo {
-    fn encoding() -> Encoding {
-        Encoding::Obj(
-            vec![
-                Field::new("motive", Encoding::Tags(
-                    size_of::<u16>(),
-                    TagMap::new(&[
-                        Tag::new(0, "NoMotive", Encoding::Unit),
-                        Tag::new(1, "TooManyConnections", Encoding::Unit),
-                        Tag::new(2, "UnknownChainName", Encoding::Unit),
-                        Tag::new(3, "DeprecatedP2pVersion", Encoding::Unit),
-                        Tag::new(4, "DeprecatedDistributedDbVersion", Encoding::Unit),
-                        Tag::new(5, "AlreadyConnected", Encoding::Unit),
-                    ]),
-                )),
-                Field::new("potential_peers_to_connect", Encoding::dynamic(Encoding::list(Encoding::String))),
-            ]
-        )
-    }
-}
-
-
-impl HasEncoding for AckMessage {
-    fn encoding() -> Encoding {
-        Encoding::Tags(
-            size_of::<u8>(),
-            TagMap::new(&[
-                Tag::new(0x00, "Ack", Encoding::Unit),
-                Tag::new(0x01, "Nack", NackInfo::encoding()),
-                Tag::new(0xFF, "NackV0", Encoding::Unit),
-            ]),
-        )
-    }
-}
-
-impl CachedData for AckMessage {
-    fn cache_reader(&self) -> &dyn CacheReader {
-        &DUMMY_BODY_CACHE
-    }
-
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        None
-    }
-}
\ No newline at end of file
+impl HasSizeLimit for AckMessage {}
\ No newline at end of file
@@ -6,13 +6,14 @@ use std::net::SocketAddr;
 use getset::Getters;
 use serde::{Deserialize, Serialize};
 
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding};
+use tezos_messages_derive::{CachedData, HasEncoding};
 
-use crate::p2p::binary_message::cache::{BinaryDataCache, CachedData, CacheReader, CacheWriter};
+use crate::p2p::binary_message::cache::BinaryDataCache;
 
-#[derive(Serialize, Deserialize, Debug, Getters, Clone)]
+#[derive(Serialize, Deserialize, Debug, Getters, Clone, HasEncoding, CachedData)]
 pub struct AdvertiseMessage {
     #[get = "pub"]
+    #[encoding(list)]
     id: Vec<String>,
 
     #[serde(skip_serializing)]
@@ -27,23 +28,3 @@ impl AdvertiseMessage {
         }
     }
 }
-
-impl HasEncoding for AdvertiseMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("id", Encoding::list(Encoding::String)),
-        ])
-    }
-}
-
-impl CachedData for AdvertiseMessage {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
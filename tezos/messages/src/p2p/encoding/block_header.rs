@@ -7,12 +7,13 @@ use derive_builder::Builder;
 use getset::{CopyGetters, Getters};
 use serde::{Deserialize, Serialize};
 
-use crypto::hash::{BlockHash, ContextHash, HashType, OperationListListHash};
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding, SchemaType};
+use crypto::hash::{BlockHash, ContextHash, OperationListListHash};
+use tezos_encoding::encoding::{Encoding, SchemaType};
+use tezos_messages_derive::{CachedData, HasEncoding};
 
-use crate::p2p::binary_message::cache::{BinaryDataCache, CachedData, CacheReader, CacheWriter};
+use crate::p2p::binary_message::cache::BinaryDataCache;
 
-#[derive(Serialize, Deserialize, Debug, Getters, Clone)]
+#[derive(Serialize, Deserialize, Debug, Getters, Clone, HasEncoding, CachedData)]
 pub struct BlockHeaderMessage {
     #[get = "pub"]
     block_header: BlockHeader,
@@ -21,26 +22,6 @@ pub struct BlockHeaderMessage {
     body: BinaryDataCache,
 }
 
-impl HasEncoding for BlockHeaderMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("block_header", BlockHeader::encoding()),
-        ])
-    }
-}
-
-impl CachedData for BlockHeaderMessage {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
-
 impl From<BlockHeader> for BlockHeaderMessage {
     fn from(block_header: BlockHeader) -> Self {
         BlockHeaderMessage { block_header, body: Default::default() }
@@ -48,9 +29,10 @@ impl From<BlockHeader> for BlockHeaderMessage {
 }
 
 // -----------------------------------------------------------------------------------------------
-#[derive(Serialize, Deserialize, Debug, Getters, Clone)]
+#[derive(Serialize, Deserialize, Debug, Getters, Clone, HasEncoding, CachedData)]
 pub struct GetBlockHeadersMessage {
     #[get = "pub"]
+    #[encoding(dynamic, list, hash = "BlockHash")]
     get_block_headers: Vec<BlockHash>,
 
     #[serde(skip_serializing)]
@@ -66,46 +48,32 @@ impl GetBlockHeadersMessage {
     }
 }
 
-impl HasEncoding for GetBlockHeadersMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("get_block_headers", Encoding::dynamic(Encoding::list(Encoding::Hash(HashType::BlockHash)))),
-        ])
-    }
-}
-
-impl CachedData for GetBlockHeadersMessage {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
-
 // -----------------------------------------------------------------------------------------------
-#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Builder, Getters, CopyGetters)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Builder, Getters, CopyGetters, HasEncoding, CachedData)]
 pub struct BlockHeader {
     #[get_copy = "pub"]
     level: i32,
     #[get_copy = "pub"]
     proto: u8,
     #[get = "pub"]
+    #[encoding(hash = "BlockHash")]
     predecessor: BlockHash,
     #[get_copy = "pub"]
+    #[encoding(timestamp)]
     timestamp: i64,
     #[get_copy = "pub"]
     validation_pass: u8,
     #[get = "pub"]
+    #[encoding(hash = "OperationListListHash")]
     operations_hash: OperationListListHash,
     #[get = "pub"]
+    #[encoding(custom = "fitness_encoding")]
     fitness: Vec<Vec<u8>>,
     #[get = "pub"]
+    #[encoding(hash = "ContextHash")]
     context: ContextHash,
     #[get = "pub"]
+    #[encoding(custom = "protocol_data_encoding")]
     protocol_data: Vec<u8>,
 
     #[serde(skip_serializing)]
@@ -113,42 +81,22 @@ pub struct BlockHeader {
     body: BinaryDataCache,
 }
 
-impl HasEncoding for BlockHeader {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("level", Encoding::Int32),
-            Field::new("proto", Encoding::Uint8),
-            Field::new("predecessor", Encoding::Hash(HashType::BlockHash)),
-            Field::new("timestamp", Encoding::Timestamp),
-            Field::new("validation_pass", Encoding::Uint8),
-            Field::new("operations_hash", Encoding::Hash(HashType::OperationListListHash)),
-            Field::new("fitness", Encoding::Split(Arc::new(|schema_type|
-                match schema_type {
-                    SchemaType::Json => Encoding::dynamic(Encoding::list(Encoding::Bytes)),
-                    SchemaType::Binary => Encoding::dynamic(Encoding::list(
-                        Encoding::dynamic(Encoding::list(Encoding::Uint8))
-                    ))
-                }
-            ))),
-            Field::new("context", Encoding::Hash(HashType::ContextHash)),
-            Field::new("protocol_data", Encoding::Split(Arc::new(|schema_type|
-                match schema_type {
-                    SchemaType::Json => Encoding::Bytes,
-                    SchemaType::Binary => Encoding::list(Encoding::Uint8)
-                }
-            )))
-        ])
-    }
+fn fitness_encoding() -> Encoding {
+    Encoding::Split(Arc::new(|schema_type|
+        match schema_type {
+            SchemaType::Json => Encoding::dynamic(Encoding::list(Encoding::Bytes)),
+            SchemaType::Binary => Encoding::dynamic(Encoding::list(
+                Encoding::dynamic(Encoding::list(Encoding::Uint8))
+            ))
+        }
+    ))
 }
 
-impl CachedData for BlockHeader {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
+fn protocol_data_encoding() -> Encoding {
+    Encoding::Split(Arc::new(|schema_type|
+        match schema_type {
+            SchemaType::Json => Encoding::Bytes,
+            SchemaType::Binary => Encoding::list(Encoding::Uint8)
+        }
+    ))
 }
\ No newline at end of file
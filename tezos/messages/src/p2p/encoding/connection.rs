@@ -8,21 +8,26 @@ use getset::Getters;
 use serde::{Deserialize, Serialize};
 
 use tezos_encoding::binary_reader::BinaryReaderError;
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding};
+use tezos_messages_derive::{CachedData, HasEncoding};
 
 use crate::p2p::binary_message::{BinaryChunk, BinaryMessage};
-use crate::p2p::binary_message::cache::{BinaryDataCache, CachedData, CacheReader, CacheWriter};
+use crate::p2p::binary_message::cache::BinaryDataCache;
 use crate::p2p::encoding::version::Version;
 
-#[derive(Serialize, Deserialize, Debug, Getters, Clone)]
+#[derive(Serialize, Deserialize, Debug, Getters, Clone, HasEncoding, CachedData)]
 pub struct ConnectionMessage {
     pub port: u16,
     #[get = "pub"]
-    pub versions: Vec<Version>,
-    #[get = "pub"]
+    #[encoding(sized = 32, bytes)]
     pub public_key: Vec<u8>,
+    #[get = "pub"]
+    #[encoding(sized = 24, bytes)]
     pub proof_of_work_stamp: Vec<u8>,
+    #[encoding(sized = 24, bytes)]
     pub message_nonce: Vec<u8>,
+    #[get = "pub"]
+    #[encoding(list)]
+    pub versions: Vec<Version>,
     #[serde(skip_serializing)]
     body: BinaryDataCache
 }
@@ -51,28 +56,4 @@ impl TryFrom<BinaryChunk> for ConnectionMessage {
         let cursor = Cursor::new(value.content());
         ConnectionMessage::from_bytes(cursor.into_inner().to_vec())
     }
-}
-
-impl HasEncoding for ConnectionMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("port", Encoding::Uint16),
-            Field::new("public_key", Encoding::sized(32, Encoding::Bytes)),
-            Field::new("proof_of_work_stamp", Encoding::sized(24, Encoding::Bytes)),
-            Field::new("message_nonce", Encoding::sized(24, Encoding::Bytes)),
-            Field::new("versions", Encoding::list(Version::encoding()))
-        ])
-    }
-}
-
-impl CachedData for ConnectionMessage {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
 }
\ No newline at end of file
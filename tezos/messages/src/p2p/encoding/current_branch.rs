@@ -7,14 +7,16 @@ use getset::Getters;
 use serde::{Deserialize, Serialize};
 
 use crypto::hash::{BlockHash, ChainId, HashType};
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding, SchemaType};
+use tezos_encoding::encoding::{Encoding, SchemaType};
+use tezos_messages_derive::{CachedData, HasEncoding};
 
-use crate::p2p::binary_message::cache::{BinaryDataCache, CachedData, CacheReader, CacheWriter};
+use crate::p2p::binary_message::cache::BinaryDataCache;
 use crate::p2p::encoding::block_header::BlockHeader;
 
-#[derive(Clone, Serialize, Deserialize, Debug, Getters)]
+#[derive(Clone, Serialize, Deserialize, Debug, Getters, HasEncoding, CachedData)]
 pub struct CurrentBranchMessage {
     #[get = "pub"]
+    #[encoding(hash = "ChainId")]
     chain_id: ChainId,
     #[get = "pub"]
     current_branch: CurrentBranch,
@@ -32,33 +34,14 @@ impl CurrentBranchMessage {
     }
 }
 
-impl HasEncoding for CurrentBranchMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("chain_id", Encoding::Hash(HashType::ChainId)),
-            Field::new("current_branch", CurrentBranch::encoding())
-        ])
-    }
-}
-
-impl CachedData for CurrentBranchMessage {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
-
 // -----------------------------------------------------------------------------------------------
-#[derive(Clone, Serialize, Deserialize, Debug, Getters)]
+#[derive(Clone, Serialize, Deserialize, Debug, Getters, HasEncoding, CachedData)]
 pub struct CurrentBranch {
     #[get = "pub"]
+    #[encoding(dynamic)]
     current_head: BlockHeader,
     #[get = "pub"]
+    #[encoding(custom = "history_encoding")]
     history: Vec<BlockHash>,
     #[serde(skip_serializing)]
     body: BinaryDataCache,
@@ -74,35 +57,10 @@ impl CurrentBranch {
     }
 }
 
-impl HasEncoding for CurrentBranch {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("current_head", Encoding::dynamic(BlockHeader::encoding())),
-            Field::new("history", Encoding::Split(Arc::new(|schema_type|
-                match schema_type {
-                    SchemaType::Json => Encoding::Unit, // TODO: decode as list of hashes when history is needed
-                    SchemaType::Binary => Encoding::list(Encoding::Hash(HashType::BlockHash))
-                }
-            )))
-        ])
-    }
-}
-
-impl CachedData for CurrentBranch {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
-
 // -----------------------------------------------------------------------------------------------
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, HasEncoding, CachedData)]
 pub struct GetCurrentBranchMessage {
+    #[encoding(hash = "ChainId")]
     pub chain_id: ChainId,
 
     #[serde(skip_serializing)]
@@ -115,22 +73,11 @@ impl GetCurrentBranchMessage {
     }
 }
 
-impl HasEncoding for GetCurrentBranchMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("chain_id", Encoding::Hash(HashType::ChainId))
-        ])
-    }
-}
-
-impl CachedData for GetCurrentBranchMessage {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
+fn history_encoding() -> Encoding {
+    Encoding::Split(Arc::new(|schema_type|
+        match schema_type {
+            SchemaType::Json => Encoding::Unit, // TODO: decode as list of hashes when history is needed
+            SchemaType::Binary => Encoding::list(Encoding::Hash(HashType::BlockHash))
+        }
+    ))
 }
\ No newline at end of file
@@ -4,19 +4,21 @@
 use getset::Getters;
 use serde::{Deserialize, Serialize};
 
-use crypto::hash::{ChainId, HashType};
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding};
+use crypto::hash::ChainId;
+use tezos_messages_derive::{CachedData, HasEncoding};
 
-use crate::p2p::binary_message::cache::{BinaryDataCache, CachedData, CacheReader, CacheWriter};
+use crate::p2p::binary_message::cache::BinaryDataCache;
 
 use super::block_header::BlockHeader;
 use super::mempool::Mempool;
 
-#[derive(Serialize, Deserialize, Debug, Getters, Clone)]
+#[derive(Serialize, Deserialize, Debug, Getters, Clone, HasEncoding, CachedData)]
 pub struct CurrentHeadMessage {
     #[get = "pub"]
+    #[encoding(hash = "ChainId")]
     chain_id: ChainId,
     #[get = "pub"]
+    #[encoding(dynamic)]
     current_block_header: BlockHeader,
     #[get = "pub"]
     current_mempool: Mempool,
@@ -25,42 +27,21 @@ pub struct CurrentHeadMessage {
 }
 
 impl CurrentHeadMessage {
-    pub fn new(chain_id: ChainId, current_block_header: BlockHeader) -> Self {
+    pub fn new(chain_id: ChainId, current_block_header: BlockHeader, current_mempool: Mempool) -> Self {
         CurrentHeadMessage {
             chain_id,
             current_block_header,
-            current_mempool: Default::default(),
+            current_mempool,
             body: Default::default()
         }
     }
 }
 
-impl HasEncoding for CurrentHeadMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("chain_id", Encoding::Hash(HashType::ChainId)),
-            Field::new("current_block_header", Encoding::dynamic(BlockHeader::encoding())),
-            Field::new("current_mempool", Mempool::encoding())
-        ])
-    }
-}
-
-impl CachedData for CurrentHeadMessage {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
-
 // -----------------------------------------------------------------------------------------------
-#[derive(Serialize, Deserialize, Debug, Getters, Clone)]
+#[derive(Serialize, Deserialize, Debug, Getters, Clone, HasEncoding, CachedData)]
 pub struct GetCurrentHeadMessage {
     #[get = "pub"]
+    #[encoding(hash = "ChainId")]
     chain_id: ChainId,
 
     #[serde(skip_serializing)]
@@ -74,24 +55,4 @@ impl GetCurrentHeadMessage {
             body: Default::default()
         }
     }
-}
-
-impl HasEncoding for GetCurrentHeadMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("chain_id", Encoding::Hash(HashType::ChainId))
-        ])
-    }
-}
-
-impl CachedData for GetCurrentHeadMessage {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
 }
\ No newline at end of file
@@ -4,14 +4,15 @@
 use getset::Getters;
 use serde::{Serialize, Deserialize};
 
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding};
+use tezos_messages_derive::{CachedData, HasEncoding};
 
-use crate::p2p::binary_message::cache::{BinaryDataCache, CachedData, CacheReader, CacheWriter};
-use crypto::hash::{ChainId, HashType};
+use crate::p2p::binary_message::cache::BinaryDataCache;
+use crypto::hash::ChainId;
 
-#[derive(Serialize, Deserialize, Debug, Getters, Clone)]
+#[derive(Serialize, Deserialize, Debug, Getters, Clone, HasEncoding, CachedData)]
 pub struct DeactivateMessage {
     #[get = "pub"]
+    #[encoding(hash = "ChainId")]
     deactivate: ChainId,
 
     #[serde(skip_serializing)]
@@ -25,24 +26,4 @@ impl DeactivateMessage {
             body: Default::default(),
         }
     }
-}
-
-impl HasEncoding for DeactivateMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("deactivate", Encoding::Hash(HashType::ChainId)),
-        ])
-    }
-}
-
-impl CachedData for DeactivateMessage {
-    #[inline]
-    fn cache_reader(&self) -> &dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
 }
\ No newline at end of file
@@ -4,38 +4,29 @@
 use getset::Getters;
 use serde::{Deserialize, Serialize};
 
-use crypto::hash::{HashType, OperationHash};
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding};
+use crypto::hash::OperationHash;
+use tezos_messages_derive::{CachedData, HasEncoding};
 
-use crate::p2p::binary_message::cache::{BinaryDataCache, CachedData, CacheReader, CacheWriter};
+use crate::p2p::binary_message::cache::BinaryDataCache;
 
-#[derive(Clone, Serialize, Deserialize, Debug, Default, Getters)]
+#[derive(Clone, Serialize, Deserialize, Debug, Default, Getters, HasEncoding, CachedData)]
 pub struct Mempool {
     #[get = "pub"]
+    #[encoding(dynamic, list, hash = "OperationHash")]
     known_valid: Vec<OperationHash>,
     #[get = "pub"]
+    #[encoding(dynamic, dynamic, list, hash = "OperationHash")]
     pending: Vec<OperationHash>,
     #[serde(skip_serializing)]
     body: BinaryDataCache,
 }
 
-impl HasEncoding for Mempool {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("known_valid", Encoding::dynamic(Encoding::list(Encoding::Hash(HashType::OperationHash)))),
-            Field::new("pending", Encoding::dynamic(Encoding::dynamic(Encoding::list(Encoding::Hash(HashType::OperationHash))))),
-        ])
-    }
-}
-
-impl CachedData for Mempool {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
+impl Mempool {
+    pub fn new(known_valid: Vec<OperationHash>, pending: Vec<OperationHash>) -> Self {
+        Mempool {
+            known_valid,
+            pending,
+            body: Default::default(),
+        }
     }
 }
@@ -4,11 +4,12 @@
 use getset::CopyGetters;
 use serde::{Deserialize, Serialize};
 
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding};
+use tezos_messages_derive::{CachedData, HasEncoding};
 
-use crate::p2p::binary_message::cache::{BinaryDataCache, CachedData, CacheReader, CacheWriter};
+use crate::p2p::binary_message::HasSizeLimit;
+use crate::p2p::binary_message::cache::BinaryDataCache;
 
-#[derive(Serialize, Deserialize, Debug, CopyGetters, Clone)]
+#[derive(Serialize, Deserialize, Debug, CopyGetters, Clone, HasEncoding, CachedData)]
 pub struct MetadataMessage {
     #[get_copy = "pub"]
     disable_mempool: bool,
@@ -28,23 +29,4 @@ impl MetadataMessage {
     }
 }
 
-impl HasEncoding for MetadataMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("disable_mempool", Encoding::Bool),
-            Field::new("private_node", Encoding::Bool)
-        ])
-    }
-}
-
-impl CachedData for MetadataMessage {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
+impl HasSizeLimit for MetadataMessage {}
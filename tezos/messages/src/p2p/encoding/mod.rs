@@ -36,5 +36,5 @@ pub mod prelude {
     pub use super::version::Version;
     pub use super::swap::SwapMessage;
     pub use super::deactivate::DeactivateMessage;
-    pub use super::operation_hashes_for_blocks::{GetOperationHashesForBlocksMessage, OperationHashesForBlocksMessage};
+    pub use super::operation_hashes_for_blocks::{GetOperationHashesForBlocksMessage, OperationHashesForBlock, OperationHashesForBlocksMessage};
 }
\ No newline at end of file
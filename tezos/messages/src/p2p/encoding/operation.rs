@@ -5,12 +5,13 @@ use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 
-use crypto::hash::{BlockHash, HashType, OperationHash};
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding, SchemaType};
+use crypto::hash::{BlockHash, OperationHash};
+use tezos_encoding::encoding::{Encoding, SchemaType};
+use tezos_messages_derive::{CachedData, HasEncoding};
 
-use crate::p2p::binary_message::cache::{BinaryDataCache, CachedData, CacheReader, CacheWriter};
+use crate::p2p::binary_message::cache::BinaryDataCache;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, HasEncoding, CachedData)]
 pub struct OperationMessage {
     operation: Operation,
 
@@ -18,30 +19,27 @@ pub struct OperationMessage {
     body: BinaryDataCache,
 }
 
-impl HasEncoding for OperationMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("operation", Operation::encoding())
-        ])
+impl OperationMessage {
+    pub fn operation(&self) -> &Operation {
+        &self.operation
     }
 }
 
-impl CachedData for OperationMessage {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
+impl From<Operation> for OperationMessage {
+    fn from(operation: Operation) -> Self {
+        OperationMessage {
+            operation,
+            body: Default::default(),
+        }
     }
 }
 
 // -----------------------------------------------------------------------------------------------
-#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, HasEncoding, CachedData)]
 pub struct Operation {
+    #[encoding(hash = "BlockHash")]
     branch: BlockHash,
+    #[encoding(custom = "data_encoding")]
     data: Vec<u8>,
 
     #[serde(skip_serializing)]
@@ -58,57 +56,34 @@ impl Operation {
     }
 }
 
-impl HasEncoding for Operation {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("branch", Encoding::Hash(HashType::BlockHash)),
-            Field::new("data", Encoding::Split(Arc::new(|schema_type|
-                match schema_type {
-                    SchemaType::Json => Encoding::Bytes,
-                    SchemaType::Binary => Encoding::list(Encoding::Uint8)
-                }
-            )))
-        ])
-    }
-}
-
-impl CachedData for Operation {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
-
 // -----------------------------------------------------------------------------------------------
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, HasEncoding, CachedData)]
 pub struct GetOperationsMessage {
+    #[encoding(dynamic, list, hash = "OperationHash")]
     get_operations: Vec<OperationHash>,
 
     #[serde(skip_serializing)]
     body: BinaryDataCache,
 }
 
-impl HasEncoding for GetOperationsMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("get_operations", Encoding::dynamic(Encoding::list(Encoding::Hash(HashType::OperationHash)))),
-        ])
+impl GetOperationsMessage {
+    pub fn new(operations: Vec<OperationHash>) -> Self {
+        GetOperationsMessage {
+            get_operations: operations,
+            body: Default::default(),
+        }
     }
-}
 
-impl CachedData for GetOperationsMessage {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
+    pub fn get_operations(&self) -> &Vec<OperationHash> {
+        &self.get_operations
     }
+}
 
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
+fn data_encoding() -> Encoding {
+    Encoding::Split(Arc::new(|schema_type|
+        match schema_type {
+            SchemaType::Json => Encoding::Bytes,
+            SchemaType::Binary => Encoding::list(Encoding::Uint8)
+        }
+    ))
 }
\ No newline at end of file
@@ -4,16 +4,16 @@
 use getset::{CopyGetters, Getters};
 use serde::{Serialize, Deserialize};
 
-use crypto::hash::{BlockHash, HashType, OperationHash};
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding};
+use crypto::hash::{BlockHash, OperationHash};
+use tezos_messages_derive::{CachedData, HasEncoding};
 
-use crate::p2p::binary_message::cache::{BinaryDataCache, CachedData, CacheReader, CacheWriter};
+use crate::p2p::binary_message::cache::BinaryDataCache;
 use crate::p2p::encoding::prelude::Path;
-use super::operations_for_blocks::path_encoding;
 
-#[derive(Serialize, Deserialize, Debug, Getters, Clone)]
+#[derive(Serialize, Deserialize, Debug, Getters, Clone, HasEncoding, CachedData)]
 pub struct GetOperationHashesForBlocksMessage {
     #[get = "pub"]
+    #[encoding(dynamic, list)]
     get_operation_hashes_for_blocks: Vec<OperationHashesForBlock>,
 
     #[serde(skip_serializing)]
@@ -29,34 +29,15 @@ impl GetOperationHashesForBlocksMessage {
     }
 }
 
-impl HasEncoding for GetOperationHashesForBlocksMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("get_operation_hashes_for_blocks", Encoding::dynamic(Encoding::list(OperationHashesForBlock::encoding()))),
-        ])
-    }
-}
-
-impl CachedData for GetOperationHashesForBlocksMessage {
-    #[inline]
-    fn cache_reader(&self) -> &dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
-
 // ------------------ Response ------------------ //
-#[derive(Serialize, Deserialize, Debug, Getters, Clone)]
+#[derive(Serialize, Deserialize, Debug, Getters, Clone, HasEncoding, CachedData)]
 pub struct OperationHashesForBlocksMessage {
     #[get = "pub"]
     operation_hashes_for_block: OperationHashesForBlock,
     #[get = "pub"]
     operation_hashes_path: Path,
     #[get = "pub"]
+    #[encoding(list, dynamic, list, uint8)]
     operation_hashes: Vec<OperationHash>,
 
     #[serde(skip_serializing)]
@@ -74,32 +55,11 @@ impl OperationHashesForBlocksMessage {
     }
 }
 
-impl HasEncoding for OperationHashesForBlocksMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("operation_hashes_for_block", OperationHashesForBlock::encoding()),
-            Field::new("operation_hashes_path", path_encoding()),
-            Field::new("operation_hashes", Encoding::list(Encoding::dynamic(Encoding::list(Encoding::Uint8)))),
-        ])
-    }
-}
-
-impl CachedData for OperationHashesForBlocksMessage {
-    #[inline]
-    fn cache_reader(&self) -> &dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
-
 // ------------------ Inner message for operation hashes message ------------------ //
-#[derive(Serialize, Deserialize, Debug, Getters, CopyGetters, Clone)]
+#[derive(Serialize, Deserialize, Debug, Getters, CopyGetters, Clone, HasEncoding, CachedData)]
 pub struct OperationHashesForBlock {
     #[get = "pub"]
+    #[encoding(hash = "BlockHash")]
     hash: BlockHash,
     #[get_copy = "pub"]
     validation_pass: i8,
@@ -116,25 +76,4 @@ impl OperationHashesForBlock {
             body: Default::default(),
         }
     }
-}
-
-impl HasEncoding for OperationHashesForBlock {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("hash", Encoding::Hash(HashType::BlockHash)),
-            Field::new("validation_pass", Encoding::Int8),
-        ])
-    }
-}
-
-impl CachedData for OperationHashesForBlock {
-    #[inline]
-    fn cache_reader(&self) -> &dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
 }
\ No newline at end of file
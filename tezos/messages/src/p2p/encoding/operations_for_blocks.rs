@@ -1,23 +1,19 @@
 // Copyright (c) SimpleStaking and Tezedge Contributors
 // SPDX-License-Identifier: MIT
 
-use std::mem::size_of;
-use std::sync::Arc;
-
 use getset::{CopyGetters, Getters};
 use serde::{Deserialize, Serialize};
 
-use crypto::hash::{BlockHash, Hash, HashType};
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding, Tag, TagMap};
+use crypto::hash::{BlockHash, Hash};
+use tezos_messages_derive::{CachedData, HasEncoding};
 
-use crate::p2p::binary_message::cache::{BinaryDataCache, CachedData, CacheReader, CacheWriter, NeverCache};
+use crate::p2p::binary_message::cache::BinaryDataCache;
 use crate::p2p::encoding::operation::Operation;
 
-static DUMMY_BODY_CACHE: NeverCache = NeverCache;
-
-#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, CopyGetters, Getters)]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, CopyGetters, Getters, HasEncoding, CachedData)]
 pub struct OperationsForBlock {
     #[get = "pub"]
+    #[encoding(hash = "BlockHash")]
     hash: BlockHash,
     #[get_copy = "pub"]
     validation_pass: i8,
@@ -35,35 +31,15 @@ impl OperationsForBlock {
     }
 }
 
-impl HasEncoding for OperationsForBlock {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("hash", Encoding::Hash(HashType::BlockHash)),
-            Field::new("validation_pass", Encoding::Int8),
-        ])
-    }
-}
-
-impl CachedData for OperationsForBlock {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
-
 // -----------------------------------------------------------------------------------------------
-#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Getters)]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Getters, HasEncoding, CachedData)]
 pub struct OperationsForBlocksMessage {
     #[get = "pub"]
     operations_for_block: OperationsForBlock,
     #[get = "pub"]
     operation_hashes_path: Path,
     #[get = "pub"]
+    #[encoding(list, dynamic)]
     operations: Vec<Operation>,
     #[serde(skip_serializing)]
     body: BinaryDataCache
@@ -80,32 +56,11 @@ impl OperationsForBlocksMessage {
     }
 }
 
-impl HasEncoding for OperationsForBlocksMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("operations_for_block", OperationsForBlock::encoding()),
-            Field::new("operation_hashes_path", path_encoding()),
-            Field::new("operations", Encoding::list(Encoding::dynamic(Operation::encoding()))),
-        ])
-    }
-}
-
-impl CachedData for OperationsForBlocksMessage {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
-
 // -----------------------------------------------------------------------------------------------
-#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Getters)]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Getters, HasEncoding, CachedData)]
 pub struct PathRight {
     #[get = "pub"]
+    #[encoding(hash = "OperationListListHash")]
     left: Hash,
     #[get = "pub"]
     path: Path,
@@ -113,92 +68,35 @@ pub struct PathRight {
     body: BinaryDataCache,
 }
 
-impl HasEncoding for PathRight {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("left", Encoding::Hash(HashType::OperationListListHash)),
-            Field::new("path", path_encoding()),
-        ])
-    }
-}
-
-impl CachedData for PathRight {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
-
 // -----------------------------------------------------------------------------------------------
-#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Getters)]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Getters, HasEncoding, CachedData)]
 pub struct PathLeft {
     #[get = "pub"]
     path: Path,
     #[get = "pub"]
+    #[encoding(hash = "OperationListListHash")]
     right: Hash,
     #[serde(skip_serializing)]
     body: BinaryDataCache,
 }
 
-impl HasEncoding for PathLeft {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("path", path_encoding()),
-            Field::new("right", Encoding::Hash(HashType::OperationListListHash)),
-        ])
-    }
-}
-
-impl CachedData for PathLeft {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
-
 // -----------------------------------------------------------------------------------------------
-#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, HasEncoding, CachedData)]
+#[encoding(tags = "u8")]
 pub enum Path {
+    #[encoding(tag = 0xF0, lazy)]
     Left(Box<PathLeft>),
+    #[encoding(tag = 0x0F, lazy)]
     Right(Box<PathRight>),
+    #[encoding(tag = 0x00)]
     Op
 }
 
-pub fn path_encoding() -> Encoding {
-    Encoding::Tags(
-        size_of::<u8>(),
-        TagMap::new(&[
-            Tag::new(0xF0, "Left", Encoding::Lazy(Arc::new(PathLeft::encoding))),
-            Tag::new(0x0F, "Right", Encoding::Lazy(Arc::new(PathRight::encoding))),
-            Tag::new(0x00, "Op", Encoding::Unit),
-        ])
-    )
-}
-
-impl CachedData for Path {
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &DUMMY_BODY_CACHE
-    }
-
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        None
-    }
-}
-
 // -----------------------------------------------------------------------------------------------
-#[derive(Serialize, Deserialize, Debug, Getters, Clone)]
+#[derive(Serialize, Deserialize, Debug, Getters, Clone, HasEncoding, CachedData)]
 pub struct GetOperationsForBlocksMessage {
     #[get = "pub"]
+    #[encoding(dynamic, list)]
     get_operations_for_blocks: Vec<OperationsForBlock>,
     #[serde(skip_serializing)]
     body: BinaryDataCache,
@@ -212,23 +110,3 @@ impl GetOperationsForBlocksMessage {
         }
     }
 }
-
-impl HasEncoding for GetOperationsForBlocksMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("get_operations_for_blocks", Encoding::dynamic(Encoding::list(OperationsForBlock::encoding()))),
-        ])
-    }
-}
-
-impl CachedData for GetOperationsForBlocksMessage {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
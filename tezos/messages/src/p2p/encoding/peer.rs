@@ -1,43 +1,67 @@
 // Copyright (c) SimpleStaking and Tezedge Contributors
 // SPDX-License-Identifier: MIT
 
-use std::mem::size_of;
+use std::collections::HashMap;
 
 use getset::Getters;
 use serde::{Deserialize, Serialize};
 use lazy_static::lazy_static;
 
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding, Tag, TagMap};
+use tezos_encoding::binary_reader::SizeLimit;
+use tezos_encoding::encoding::{Encoding, Field, HasEncoding};
+use tezos_messages_derive::{CachedData, HasEncoding};
 
-use crate::p2p::binary_message::cache::{BinaryDataCache, CachedData, CacheReader, CacheWriter};
+use crate::p2p::binary_message::{HasSizeLimit, MESSAGE_SIZE_MAX_DEFAULT};
+use crate::p2p::binary_message::cache::BinaryDataCache;
 use crate::p2p::encoding::prelude::*;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, HasEncoding)]
+#[encoding(tags = "u16")]
 pub enum PeerMessage {
+    #[encoding(tag = 0x01)]
     Disconnect,
+    #[encoding(tag = 0x03)]
     Advertise(AdvertiseMessage),
+    #[encoding(tag = 0x04)]
     SwapRequest(SwapMessage),
+    #[encoding(tag = 0x05)]
     SwapAck(SwapMessage),
+    #[encoding(tag = 0x02)]
     Bootstrap,
+    #[encoding(tag = 0x10)]
     GetCurrentBranch(GetCurrentBranchMessage),
+    #[encoding(tag = 0x11)]
     CurrentBranch(CurrentBranchMessage),
+    #[encoding(tag = 0x12)]
     Deactivate(DeactivateMessage),
+    #[encoding(tag = 0x13)]
     GetCurrentHead(GetCurrentHeadMessage),
+    #[encoding(tag = 0x14)]
     CurrentHead(CurrentHeadMessage),
+    #[encoding(tag = 0x20)]
     GetBlockHeaders(GetBlockHeadersMessage),
+    #[encoding(tag = 0x21)]
     BlockHeader(BlockHeaderMessage),
+    #[encoding(tag = 0x30)]
     GetOperations(GetOperationsMessage),
+    #[encoding(tag = 0x31)]
     Operation(OperationMessage),
+    #[encoding(tag = 0x40)]
     GetProtocols(GetProtocolsMessage),
+    #[encoding(tag = 0x41)]
     Protocol(ProtocolMessage),
+    #[encoding(tag = 0x50)]
     GetOperationHashesForBlocks(GetOperationHashesForBlocksMessage),
+    #[encoding(tag = 0x51)]
     OperationHashesForBlock(OperationHashesForBlocksMessage),
+    #[encoding(tag = 0x60)]
     GetOperationsForBlocks(GetOperationsForBlocksMessage),
+    #[encoding(tag = 0x61)]
     OperationsForBlocks(OperationsForBlocksMessage),
 }
 
 
-#[derive(Serialize, Deserialize, Debug, Getters)]
+#[derive(Serialize, Deserialize, Debug, Getters, CachedData)]
 pub struct PeerMessageResponse {
     #[get = "pub"]
     messages: Vec<PeerMessage>,
@@ -45,48 +69,11 @@ pub struct PeerMessageResponse {
     body: BinaryDataCache,
 }
 
-impl CachedData for PeerMessageResponse {
-    #[inline]
-    fn cache_reader(&self) -> &dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
-
 lazy_static! {
+    /// Encoding is shared by all received messages
     static ref ENCODING: Encoding = Encoding::Obj(vec![
-                Field::new("messages", Encoding::dynamic(Encoding::list(
-                    Encoding::Tags(
-                        size_of::<u16>(),
-                        TagMap::new(&[
-                            Tag::new(0x01, "Disconnect", Encoding::Unit),
-                            Tag::new(0x02, "Bootstrap", Encoding::Unit),
-                            Tag::new(0x03, "Advertise", AdvertiseMessage::encoding()),
-                            Tag::new(0x04, "SwapRequest", SwapMessage::encoding()),
-                            Tag::new(0x05, "SwapAck", SwapMessage::encoding()),
-                            Tag::new(0x10, "GetCurrentBranch", GetCurrentBranchMessage::encoding()),
-                            Tag::new(0x11, "CurrentBranch", CurrentBranchMessage::encoding()),
-                            Tag::new(0x12, "Deactivate", DeactivateMessage::encoding()),
-                            Tag::new(0x13, "GetCurrentHead", GetCurrentHeadMessage::encoding()),
-                            Tag::new(0x14, "CurrentHead", CurrentHeadMessage::encoding()),
-                            Tag::new(0x20, "GetBlockHeaders", GetBlockHeadersMessage::encoding()),
-                            Tag::new(0x21, "BlockHeader", BlockHeaderMessage::encoding()),
-                            Tag::new(0x30, "GetOperations", GetOperationsMessage::encoding()),
-                            Tag::new(0x31, "Operation", OperationMessage::encoding()),
-                            Tag::new(0x40, "GetProtocols", GetProtocolsMessage::encoding()),
-                            Tag::new(0x41, "Protocol", ProtocolMessage::encoding()),
-                            Tag::new(0x50, "GetOperationHashesForBlocks", GetOperationHashesForBlocksMessage::encoding()),
-                            Tag::new(0x51, "OperationHashesForBlocks", OperationHashesForBlocksMessage::encoding()),
-                            Tag::new(0x60, "GetOperationsForBlocks", GetOperationsForBlocksMessage::encoding()),
-                            Tag::new(0x61, "OperationsForBlocks", OperationsForBlocksMessage::encoding()),
-                        ])
-                    )
-                )))
-            ]);
+        Field::new("messages", Encoding::dynamic(Encoding::list(PeerMessage::encoding())))
+    ]);
 }
 
 impl HasEncoding for PeerMessageResponse {
@@ -95,6 +82,30 @@ impl HasEncoding for PeerMessageResponse {
     }
 }
 
+/// Max allowed size in bytes of messages carrying block hashes (e.g. `CurrentBranch` with its history)
+pub const MESSAGE_SIZE_MAX_HASHES: usize = 1024 * 1024;
+/// Max allowed size in bytes of messages carrying whole mempool, protocol sources or block operations
+pub const MESSAGE_SIZE_MAX_CONTENT: usize = 8 * 1024 * 1024;
+
+lazy_static! {
+    static ref SIZE_LIMIT: SizeLimit = SizeLimit::ByTag {
+        default: MESSAGE_SIZE_MAX_DEFAULT,
+        limits: vec![
+            (0x11, MESSAGE_SIZE_MAX_HASHES),
+            (0x14, MESSAGE_SIZE_MAX_CONTENT),
+            (0x41, MESSAGE_SIZE_MAX_CONTENT),
+            (0x51, MESSAGE_SIZE_MAX_HASHES),
+            (0x61, MESSAGE_SIZE_MAX_CONTENT),
+        ].into_iter().collect::<HashMap<_, _>>(),
+    };
+}
+
+impl HasSizeLimit for PeerMessageResponse {
+    fn size_limit() -> SizeLimit {
+        SIZE_LIMIT.clone()
+    }
+}
+
 impl From<PeerMessage> for PeerMessageResponse {
     fn from(peer_message: PeerMessage) -> Self {
         PeerMessageResponse { messages: vec![peer_message], body: Default::default() }
@@ -124,5 +135,11 @@ into_peer_message!(GetBlockHeadersMessage, GetBlockHeaders);
 into_peer_message!(BlockHeaderMessage, BlockHeader);
 into_peer_message!(GetCurrentHeadMessage, GetCurrentHead);
 into_peer_message!(CurrentHeadMessage, CurrentHead);
+into_peer_message!(GetOperationsMessage, GetOperations);
+into_peer_message!(OperationMessage, Operation);
+into_peer_message!(GetProtocolsMessage, GetProtocols);
+into_peer_message!(ProtocolMessage, Protocol);
+into_peer_message!(GetOperationHashesForBlocksMessage, GetOperationHashesForBlocks);
+into_peer_message!(OperationHashesForBlocksMessage, OperationHashesForBlock);
 into_peer_message!(GetOperationsForBlocksMessage, GetOperationsForBlocks);
 into_peer_message!(OperationsForBlocksMessage, OperationsForBlocks);
@@ -3,12 +3,12 @@
 
 use serde::{Deserialize, Serialize};
 
-use crypto::hash::{HashType, ProtocolHash};
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding};
+use crypto::hash::ProtocolHash;
+use tezos_messages_derive::{CachedData, HasEncoding};
 
-use crate::p2p::binary_message::cache::{BinaryDataCache, CachedData, CacheReader, CacheWriter};
+use crate::p2p::binary_message::cache::BinaryDataCache;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, HasEncoding, CachedData)]
 pub struct ProtocolMessage {
     protocol: Protocol,
 
@@ -16,30 +16,24 @@ pub struct ProtocolMessage {
     body: BinaryDataCache,
 }
 
-impl HasEncoding for ProtocolMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("protocol", Protocol::encoding())
-        ])
-    }
-}
-
-impl CachedData for ProtocolMessage {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
+impl ProtocolMessage {
+    pub fn new(protocol: Protocol) -> Self {
+        ProtocolMessage {
+            protocol,
+            body: Default::default(),
+        }
     }
 
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
+    pub fn protocol(&self) -> &Protocol {
+        &self.protocol
     }
 }
 
 // -----------------------------------------------------------------------------------------------
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, HasEncoding, CachedData)]
 pub struct Component {
     name: String,
+    #[encoding(option)]
     interface: Option<String>,
     implementation: String,
 
@@ -47,32 +41,34 @@ pub struct Component {
     body: BinaryDataCache,
 }
 
-impl HasEncoding for Component {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("name", Encoding::String),
-            Field::new("interface", Encoding::option(Encoding::String)),
-            Field::new("implementation", Encoding::String),
-        ])
+impl Component {
+    pub fn new(name: String, interface: Option<String>, implementation: String) -> Self {
+        Component {
+            name,
+            interface,
+            implementation,
+            body: Default::default(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
     }
-}
 
-impl CachedData for Component {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
+    pub fn interface(&self) -> &Option<String> {
+        &self.interface
     }
 
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
+    pub fn implementation(&self) -> &str {
+        &self.implementation
     }
 }
 
 // -----------------------------------------------------------------------------------------------
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, HasEncoding, CachedData)]
 pub struct Protocol {
     expected_env_version: i16,
+    #[encoding(dynamic, list)]
     components: Vec<Component>,
 
     #[serde(skip_serializing)]
@@ -80,6 +76,14 @@ pub struct Protocol {
 }
 
 impl Protocol {
+    pub fn new(expected_env_version: i16, components: Vec<Component>) -> Self {
+        Protocol {
+            expected_env_version,
+            components,
+            body: Default::default(),
+        }
+    }
+
     pub fn expected_env_version(&self) -> i16 {
         self.expected_env_version
     }
@@ -89,52 +93,25 @@ impl Protocol {
     }
 }
 
-impl HasEncoding for Protocol {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("expected_env_version", Encoding::Int16),
-            Field::new("components", Encoding::dynamic(Encoding::list(Component::encoding())))
-        ])
-    }
-}
-
-impl CachedData for Protocol {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
-
 // -----------------------------------------------------------------------------------------------
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, HasEncoding, CachedData)]
 pub struct GetProtocolsMessage {
+    #[encoding(dynamic, list, hash = "ProtocolHash")]
     get_protocols: Vec<ProtocolHash>,
 
     #[serde(skip_serializing)]
     body: BinaryDataCache,
 }
 
-impl HasEncoding for GetProtocolsMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("get_protocols", Encoding::dynamic(Encoding::list(Encoding::Hash(HashType::ProtocolHash)))),
-        ])
-    }
-}
-
-impl CachedData for GetProtocolsMessage {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
+impl GetProtocolsMessage {
+    pub fn new(get_protocols: Vec<ProtocolHash>) -> Self {
+        GetProtocolsMessage {
+            get_protocols,
+            body: Default::default(),
+        }
     }
 
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
+    pub fn get_protocols(&self) -> &Vec<ProtocolHash> {
+        &self.get_protocols
     }
 }
\ No newline at end of file
@@ -1,41 +1,34 @@
 // Copyright (c) SimpleStaking and Tezedge Contributors
 // SPDX-License-Identifier: MIT
 
+use std::net::SocketAddr;
+
 use getset::Getters;
 use serde::{Serialize, Deserialize};
 
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding};
+use crypto::hash::CryptoboxPublicKeyHash;
+use tezos_messages_derive::{CachedData, HasEncoding};
 
-use crate::p2p::binary_message::cache::{BinaryDataCache, CachedData, CacheReader, CacheWriter};
+use crate::p2p::binary_message::cache::BinaryDataCache;
 
-#[derive(Serialize, Deserialize, Debug, Getters, Clone)]
+#[derive(Serialize, Deserialize, Debug, Getters, Clone, HasEncoding, CachedData)]
 pub struct SwapMessage {
     #[get = "pub"]
     point: String,
     #[get = "pub"]
-    peer_id: String,
+    #[encoding(hash = "CryptoboxPublicKeyHash")]
+    peer_id: CryptoboxPublicKeyHash,
 
     #[serde(skip_serializing)]
     body: BinaryDataCache,
 }
 
-impl HasEncoding for SwapMessage {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("point", Encoding::String),
-            Field::new("peer_id", Encoding::String),
-        ])
-    }
-}
-
-impl CachedData for SwapMessage {
-    #[inline]
-    fn cache_reader(&self) -> &dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
+impl SwapMessage {
+    pub fn new(point: &SocketAddr, peer_id: CryptoboxPublicKeyHash) -> Self {
+        Self {
+            point: format!("{}", point),
+            peer_id,
+            body: Default::default(),
+        }
     }
 }
\ No newline at end of file
@@ -3,12 +3,12 @@
 
 use serde::{Deserialize, Serialize};
 
-use tezos_encoding::encoding::{Encoding, Field, HasEncoding};
+use tezos_messages_derive::{CachedData, HasEncoding};
 
-use crate::p2p::binary_message::cache::{BinaryDataCache, CachedData, CacheReader, CacheWriter};
+use crate::p2p::binary_message::cache::BinaryDataCache;
 use std::fmt;
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, HasEncoding, CachedData)]
 pub struct Version {
     chain_name: String,
     distributed_db_version: u16,
@@ -41,28 +41,6 @@ impl Version {
     }
 }
 
-impl HasEncoding for Version {
-    fn encoding() -> Encoding {
-        Encoding::Obj(vec![
-            Field::new("chain_name", Encoding::String),
-            Field::new("distributed_db_version", Encoding::Uint16),
-            Field::new("p2p_version", Encoding::Uint16)
-        ])
-    }
-}
-
-impl CachedData for Version {
-    #[inline]
-    fn cache_reader(&self) -> & dyn CacheReader {
-        &self.body
-    }
-
-    #[inline]
-    fn cache_writer(&mut self) -> Option<&mut dyn CacheWriter> {
-        Some(&mut self.body)
-    }
-}
-
 impl Eq for Version { }
 
 impl PartialEq for Version {
@@ -0,0 +1,487 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Binary encoding of the operation contents of the protocol.
+//!
+//! Shell [Operation] carries only the branch and opaque `data`, which consists of the list of
+//! operation contents followed by the signature (64 bytes).
+
+use std::mem::size_of;
+
+use getset::{CopyGetters, Getters};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crypto::hash::{BlockHash, HashType, ProtocolHash};
+use crypto::signature::PublicKey;
+use tezos_encoding::binary_reader::{BinaryReader, BinaryReaderError};
+use tezos_encoding::de::from_value as deserialize_from_value;
+use tezos_encoding::encoding::{Encoding, Field, HasEncoding, Tag, TagMap};
+use tezos_encoding::types::BigInt;
+
+use crate::base::signature_public_key_hash::SignaturePublicKeyHash;
+use crate::p2p::encoding::prelude::{BlockHeader, Operation};
+
+pub const SIGNATURE_SIZE: usize = 64;
+/// Contract address `[tag(1)][hash(21)]`, implicit contract is tagged by 0, originated by 1
+pub const CONTRACT_ADDRESS_SIZE: usize = 22;
+pub const NONCE_SIZE: usize = 32;
+pub const ACTIVATION_SECRET_SIZE: usize = 20;
+
+/// Contents and signature of the shell operation
+#[derive(Serialize, Deserialize, Debug, Clone, Getters)]
+pub struct OperationProtocolData {
+    #[get = "pub"]
+    contents: Vec<Contents>,
+    #[get = "pub"]
+    signature: Vec<u8>,
+}
+
+impl OperationProtocolData {
+    /// Decode the `data` of the shell operation
+    pub fn from_operation(operation: &Operation) -> Result<Self, BinaryReaderError> {
+        Self::from_bytes(operation.data())
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, BinaryReaderError> {
+        if data.len() < SIGNATURE_SIZE {
+            return Err(BinaryReaderError::Underflow { bytes: SIGNATURE_SIZE - data.len() });
+        }
+        let (contents, signature) = data.split_at(data.len() - SIGNATURE_SIZE);
+        let contents = BinaryReader::new().read(contents, &Encoding::list(Contents::encoding()))?;
+
+        Ok(OperationProtocolData {
+            contents: deserialize_from_value(&contents)?,
+            signature: signature.to_vec(),
+        })
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Contents {
+    Endorsement(EndorsementOperation),
+    SeedNonceRevelation(SeedNonceRevelationOperation),
+    DoubleEndorsementEvidence(DoubleEndorsementEvidenceOperation),
+    DoubleBakingEvidence(DoubleBakingEvidenceOperation),
+    ActivateAccount(ActivateAccountOperation),
+    Proposals(ProposalsOperation),
+    Ballot(BallotOperation),
+    Reveal(RevealOperation),
+    Transaction(TransactionOperation),
+    Origination(OriginationOperation),
+    Delegation(DelegationOperation),
+}
+
+lazy_static! {
+    static ref CONTENTS_ENCODING: Encoding = Encoding::Tags(
+        size_of::<u8>(),
+        TagMap::new(&[
+            Tag::new(0x00, "Endorsement", EndorsementOperation::encoding()),
+            Tag::new(0x01, "SeedNonceRevelation", SeedNonceRevelationOperation::encoding()),
+            Tag::new(0x02, "DoubleEndorsementEvidence", DoubleEndorsementEvidenceOperation::encoding()),
+            Tag::new(0x03, "DoubleBakingEvidence", DoubleBakingEvidenceOperation::encoding()),
+            Tag::new(0x04, "ActivateAccount", ActivateAccountOperation::encoding()),
+            Tag::new(0x05, "Proposals", ProposalsOperation::encoding()),
+            Tag::new(0x06, "Ballot", BallotOperation::encoding()),
+            Tag::new(0x6b, "Reveal", RevealOperation::encoding()),
+            Tag::new(0x6c, "Transaction", TransactionOperation::encoding()),
+            Tag::new(0x6d, "Origination", OriginationOperation::encoding()),
+            Tag::new(0x6e, "Delegation", DelegationOperation::encoding()),
+        ]),
+    );
+}
+
+impl HasEncoding for Contents {
+    fn encoding() -> Encoding {
+        CONTENTS_ENCODING.clone()
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone, CopyGetters)]
+pub struct EndorsementOperation {
+    #[get_copy = "pub"]
+    level: i32,
+}
+
+impl HasEncoding for EndorsementOperation {
+    fn encoding() -> Encoding {
+        Encoding::Obj(vec![
+            Field::new("level", Encoding::Int32),
+        ])
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone, Getters, CopyGetters)]
+pub struct SeedNonceRevelationOperation {
+    #[get_copy = "pub"]
+    level: i32,
+    #[get = "pub"]
+    nonce: Vec<u8>,
+}
+
+impl HasEncoding for SeedNonceRevelationOperation {
+    fn encoding() -> Encoding {
+        Encoding::Obj(vec![
+            Field::new("level", Encoding::Int32),
+            Field::new("nonce", Encoding::sized(NONCE_SIZE, Encoding::Bytes)),
+        ])
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone, Getters)]
+pub struct DoubleEndorsementEvidenceOperation {
+    #[get = "pub"]
+    op1: InlinedEndorsement,
+    #[get = "pub"]
+    op2: InlinedEndorsement,
+}
+
+impl HasEncoding for DoubleEndorsementEvidenceOperation {
+    fn encoding() -> Encoding {
+        Encoding::Obj(vec![
+            Field::new("op1", Encoding::dynamic(InlinedEndorsement::encoding())),
+            Field::new("op2", Encoding::dynamic(InlinedEndorsement::encoding())),
+        ])
+    }
+}
+
+/// Endorsement operation including its branch and signature, as used by the double endorsement evidence
+#[derive(Serialize, Deserialize, Debug, Clone, Getters)]
+pub struct InlinedEndorsement {
+    #[get = "pub"]
+    branch: BlockHash,
+    #[get = "pub"]
+    operations: InlinedEndorsementContents,
+    #[get = "pub"]
+    signature: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum InlinedEndorsementContents {
+    Endorsement(EndorsementOperation),
+}
+
+impl HasEncoding for InlinedEndorsement {
+    fn encoding() -> Encoding {
+        Encoding::Obj(vec![
+            Field::new("branch", Encoding::Hash(HashType::BlockHash)),
+            Field::new("operations", Encoding::Tags(
+                size_of::<u8>(),
+                TagMap::new(&[
+                    Tag::new(0x00, "Endorsement", EndorsementOperation::encoding()),
+                ]),
+            )),
+            Field::new("signature", Encoding::sized(SIGNATURE_SIZE, Encoding::Bytes)),
+        ])
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone, Getters)]
+pub struct DoubleBakingEvidenceOperation {
+    #[get = "pub"]
+    bh1: BlockHeader,
+    #[get = "pub"]
+    bh2: BlockHeader,
+}
+
+impl HasEncoding for DoubleBakingEvidenceOperation {
+    fn encoding() -> Encoding {
+        Encoding::Obj(vec![
+            Field::new("bh1", Encoding::dynamic(BlockHeader::encoding())),
+            Field::new("bh2", Encoding::dynamic(BlockHeader::encoding())),
+        ])
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone, Getters)]
+pub struct ActivateAccountOperation {
+    /// Ed25519 public key hash (tz1) of the activated account
+    #[get = "pub"]
+    pkh: Vec<u8>,
+    #[get = "pub"]
+    secret: Vec<u8>,
+}
+
+impl HasEncoding for ActivateAccountOperation {
+    fn encoding() -> Encoding {
+        Encoding::Obj(vec![
+            Field::new("pkh", Encoding::Hash(HashType::ContractTz1Hash)),
+            Field::new("secret", Encoding::sized(ACTIVATION_SECRET_SIZE, Encoding::Bytes)),
+        ])
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone, Getters, CopyGetters)]
+pub struct ProposalsOperation {
+    #[get = "pub"]
+    source: SignaturePublicKeyHash,
+    #[get_copy = "pub"]
+    period: i32,
+    #[get = "pub"]
+    proposals: Vec<ProtocolHash>,
+}
+
+impl HasEncoding for ProposalsOperation {
+    fn encoding() -> Encoding {
+        Encoding::Obj(vec![
+            Field::new("source", SignaturePublicKeyHash::encoding()),
+            Field::new("period", Encoding::Int32),
+            Field::new("proposals", Encoding::dynamic(Encoding::list(Encoding::Hash(HashType::ProtocolHash)))),
+        ])
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone, Getters, CopyGetters)]
+pub struct BallotOperation {
+    #[get = "pub"]
+    source: SignaturePublicKeyHash,
+    #[get_copy = "pub"]
+    period: i32,
+    #[get = "pub"]
+    proposal: ProtocolHash,
+    #[get = "pub"]
+    ballot: Ballot,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Ballot {
+    Yay,
+    Nay,
+    Pass,
+}
+
+impl HasEncoding for BallotOperation {
+    fn encoding() -> Encoding {
+        Encoding::Obj(vec![
+            Field::new("source", SignaturePublicKeyHash::encoding()),
+            Field::new("period", Encoding::Int32),
+            Field::new("proposal", Encoding::Hash(HashType::ProtocolHash)),
+            Field::new("ballot", Encoding::Tags(
+                size_of::<u8>(),
+                TagMap::new(&[
+                    Tag::new(0x00, "Yay", Encoding::Unit),
+                    Tag::new(0x01, "Nay", Encoding::Unit),
+                    Tag::new(0x02, "Pass", Encoding::Unit),
+                ]),
+            )),
+        ])
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone, Getters)]
+pub struct RevealOperation {
+    #[get = "pub"]
+    source: SignaturePublicKeyHash,
+    #[get = "pub"]
+    fee: BigInt,
+    #[get = "pub"]
+    counter: BigInt,
+    #[get = "pub"]
+    gas_limit: BigInt,
+    #[get = "pub"]
+    storage_limit: BigInt,
+    #[get = "pub"]
+    public_key: PublicKey,
+}
+
+impl HasEncoding for RevealOperation {
+    fn encoding() -> Encoding {
+        Encoding::Obj(manager_fields(vec![
+            Field::new("public_key", public_key_encoding()),
+        ]))
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone, Getters)]
+pub struct TransactionOperation {
+    #[get = "pub"]
+    source: SignaturePublicKeyHash,
+    #[get = "pub"]
+    fee: BigInt,
+    #[get = "pub"]
+    counter: BigInt,
+    #[get = "pub"]
+    gas_limit: BigInt,
+    #[get = "pub"]
+    storage_limit: BigInt,
+    #[get = "pub"]
+    amount: BigInt,
+    /// Contract address, see [CONTRACT_ADDRESS_SIZE]
+    #[get = "pub"]
+    destination: Vec<u8>,
+    #[get = "pub"]
+    parameters: Option<Parameters>,
+}
+
+impl HasEncoding for TransactionOperation {
+    fn encoding() -> Encoding {
+        Encoding::Obj(manager_fields(vec![
+            Field::new("amount", Encoding::Mutez),
+            Field::new("destination", Encoding::sized(CONTRACT_ADDRESS_SIZE, Encoding::Bytes)),
+            Field::new("parameters", Encoding::option(Parameters::encoding())),
+        ]))
+    }
+}
+
+/// Parameters of the smart contract call
+#[derive(Serialize, Deserialize, Debug, Clone, Getters)]
+pub struct Parameters {
+    #[get = "pub"]
+    entrypoint: Entrypoint,
+    /// Binary encoded Micheline expression
+    #[get = "pub"]
+    value: Vec<u8>,
+}
+
+impl HasEncoding for Parameters {
+    fn encoding() -> Encoding {
+        Encoding::Obj(vec![
+            Field::new("entrypoint", Encoding::Tags(
+                size_of::<u8>(),
+                TagMap::new(&[
+                    Tag::new(0x00, "Default", Encoding::Unit),
+                    Tag::new(0x01, "Root", Encoding::Unit),
+                    Tag::new(0x02, "Do", Encoding::Unit),
+                    Tag::new(0x03, "SetDelegate", Encoding::Unit),
+                    Tag::new(0x04, "RemoveDelegate", Encoding::Unit),
+                    Tag::new(0xff, "Named", Encoding::short_dynamic(Encoding::Bytes)),
+                ]),
+            )),
+            Field::new("value", Encoding::dynamic(Encoding::Bytes)),
+        ])
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Entrypoint {
+    Default,
+    Root,
+    Do,
+    SetDelegate,
+    RemoveDelegate,
+    /// Name of the entrypoint (at most 31 bytes)
+    Named(Vec<u8>),
+}
+
+impl Entrypoint {
+    /// Entrypoint name, as used by the RPC
+    pub fn name(&self) -> String {
+        match self {
+            Entrypoint::Default => "default".to_string(),
+            Entrypoint::Root => "root".to_string(),
+            Entrypoint::Do => "do".to_string(),
+            Entrypoint::SetDelegate => "set_delegate".to_string(),
+            Entrypoint::RemoveDelegate => "remove_delegate".to_string(),
+            Entrypoint::Named(name) => String::from_utf8_lossy(name).into_owned(),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone, Getters)]
+pub struct OriginationOperation {
+    #[get = "pub"]
+    source: SignaturePublicKeyHash,
+    #[get = "pub"]
+    fee: BigInt,
+    #[get = "pub"]
+    counter: BigInt,
+    #[get = "pub"]
+    gas_limit: BigInt,
+    #[get = "pub"]
+    storage_limit: BigInt,
+    #[get = "pub"]
+    balance: BigInt,
+    #[get = "pub"]
+    delegate: Option<SignaturePublicKeyHash>,
+    #[get = "pub"]
+    script: Script,
+}
+
+impl HasEncoding for OriginationOperation {
+    fn encoding() -> Encoding {
+        Encoding::Obj(manager_fields(vec![
+            Field::new("balance", Encoding::Mutez),
+            Field::new("delegate", Encoding::option(SignaturePublicKeyHash::encoding())),
+            Field::new("script", Script::encoding()),
+        ]))
+    }
+}
+
+/// Code and initial storage of the originated contract, both are binary encoded Micheline expressions
+#[derive(Serialize, Deserialize, Debug, Clone, Getters)]
+pub struct Script {
+    #[get = "pub"]
+    code: Vec<u8>,
+    #[get = "pub"]
+    storage: Vec<u8>,
+}
+
+impl HasEncoding for Script {
+    fn encoding() -> Encoding {
+        Encoding::Obj(vec![
+            Field::new("code", Encoding::dynamic(Encoding::Bytes)),
+            Field::new("storage", Encoding::dynamic(Encoding::Bytes)),
+        ])
+    }
+}
+
+// -----------------------------------------------------------------------------------------------
+#[derive(Serialize, Deserialize, Debug, Clone, Getters)]
+pub struct DelegationOperation {
+    #[get = "pub"]
+    source: SignaturePublicKeyHash,
+    #[get = "pub"]
+    fee: BigInt,
+    #[get = "pub"]
+    counter: BigInt,
+    #[get = "pub"]
+    gas_limit: BigInt,
+    #[get = "pub"]
+    storage_limit: BigInt,
+    #[get = "pub"]
+    delegate: Option<SignaturePublicKeyHash>,
+}
+
+impl HasEncoding for DelegationOperation {
+    fn encoding() -> Encoding {
+        Encoding::Obj(manager_fields(vec![
+            Field::new("delegate", Encoding::option(SignaturePublicKeyHash::encoding())),
+        ]))
+    }
+}
+
+/// Fields common to all manager operations followed by the operation specific fields
+fn manager_fields(fields: Vec<Field>) -> Vec<Field> {
+    let mut manager_fields = vec![
+        Field::new("source", SignaturePublicKeyHash::encoding()),
+        Field::new("fee", Encoding::Mutez),
+        Field::new("counter", Encoding::Mutez),
+        Field::new("gas_limit", Encoding::Mutez),
+        Field::new("storage_limit", Encoding::Mutez),
+    ];
+    manager_fields.extend(fields);
+    manager_fields
+}
+
+/// Public key prefixed by the curve tag
+fn public_key_encoding() -> Encoding {
+    Encoding::Tags(
+        size_of::<u8>(),
+        TagMap::new(&[
+            Tag::new(0x00, "Ed25519", Encoding::sized(32, Encoding::Bytes)),
+            Tag::new(0x01, "Secp256k1", Encoding::sized(33, Encoding::Bytes)),
+            Tag::new(0x02, "P256", Encoding::sized(33, Encoding::Bytes)),
+        ]),
+    )
+}
@@ -0,0 +1,6 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Binary encoding of the operation contents of the protocol, which did not change since the protocol 005.
+
+pub use crate::protocol::proto_005_2::operation::*;
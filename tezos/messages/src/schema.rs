@@ -0,0 +1,53 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Registry of encodings, which can be inspected by [tezos_encoding::describe].
+
+use tezos_encoding::encoding::{Encoding, HasEncoding};
+
+use crate::p2p::encoding::prelude::*;
+use crate::protocol::{proto_001, proto_002, proto_003, proto_004, proto_005, proto_005_2, proto_006};
+
+/// Returns name and encoding of every p2p message and protocol constants type.
+///
+/// Names are stable, they are used by the `/describe` RPC and as names of golden files.
+pub fn described_encodings() -> Vec<(&'static str, Encoding)> {
+    vec![
+        ("p2p.connection", ConnectionMessage::encoding()),
+        ("p2p.metadata", MetadataMessage::encoding()),
+        ("p2p.ack", AckMessage::encoding()),
+        ("p2p.peer_message_response", PeerMessageResponse::encoding()),
+        ("p2p.peer_message", PeerMessage::encoding()),
+        ("p2p.advertise", AdvertiseMessage::encoding()),
+        ("p2p.swap", SwapMessage::encoding()),
+        ("p2p.get_current_branch", GetCurrentBranchMessage::encoding()),
+        ("p2p.current_branch", CurrentBranchMessage::encoding()),
+        ("p2p.deactivate", DeactivateMessage::encoding()),
+        ("p2p.get_current_head", GetCurrentHeadMessage::encoding()),
+        ("p2p.current_head", CurrentHeadMessage::encoding()),
+        ("p2p.get_block_headers", GetBlockHeadersMessage::encoding()),
+        ("p2p.block_header", BlockHeaderMessage::encoding()),
+        ("p2p.get_operations", GetOperationsMessage::encoding()),
+        ("p2p.operation", OperationMessage::encoding()),
+        ("p2p.get_protocols", GetProtocolsMessage::encoding()),
+        ("p2p.protocol", ProtocolMessage::encoding()),
+        ("p2p.get_operation_hashes_for_blocks", GetOperationHashesForBlocksMessage::encoding()),
+        ("p2p.operation_hashes_for_blocks", OperationHashesForBlocksMessage::encoding()),
+        ("p2p.get_operations_for_blocks", GetOperationsForBlocksMessage::encoding()),
+        ("p2p.operations_for_blocks", OperationsForBlocksMessage::encoding()),
+        ("proto_001.constants", proto_001::constants::ParametricConstants::encoding()),
+        ("proto_002.constants", proto_002::constants::ParametricConstants::encoding()),
+        ("proto_003.constants", proto_003::constants::ParametricConstants::encoding()),
+        ("proto_004.constants", proto_004::constants::ParametricConstants::encoding()),
+        ("proto_005.constants", proto_005::constants::ParametricConstants::encoding()),
+        ("proto_005_2.constants", proto_005_2::constants::ParametricConstants::encoding()),
+        ("proto_006.constants", proto_006::constants::ParametricConstants::encoding()),
+    ]
+}
+
+/// Find encoding by its name from [described_encodings].
+pub fn find_encoding(name: &str) -> Option<Encoding> {
+    described_encodings().into_iter()
+        .find(|(encoding_name, _)| *encoding_name == name)
+        .map(|(_, encoding)| encoding)
+}
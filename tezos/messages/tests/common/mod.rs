@@ -0,0 +1,44 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Set this environment variable to rewrite golden files with the actual output.
+pub const UPDATE_GOLDEN_ENV: &str = "UPDATE_GOLDEN";
+
+/// Compare `actual` with the content of the golden file `tests/resources/<name>`.
+///
+/// Returns a line by line diff, if they differ. When [UPDATE_GOLDEN_ENV] is set, golden file is overwritten instead.
+pub fn diff_golden(name: &str, actual: &str) -> Result<(), String> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("resources").join(name);
+
+    if env::var_os(UPDATE_GOLDEN_ENV).is_some() {
+        fs::create_dir_all(path.parent().unwrap()).map_err(|e| format!("{}: {}", path.display(), e))?;
+        return fs::write(&path, actual).map_err(|e| format!("{}: {}", path.display(), e));
+    }
+
+    let expected = fs::read_to_string(&path)
+        .map_err(|e| format!("{}: {} (run with {}=1 to create it)", path.display(), e, UPDATE_GOLDEN_ENV))?;
+    if expected == actual {
+        return Ok(());
+    }
+
+    let expected_lines: Vec<&str> = expected.lines().collect();
+    let actual_lines: Vec<&str> = actual.lines().collect();
+    let mut diff = format!("{} differs (run with {}=1 to update it):\n", path.display(), UPDATE_GOLDEN_ENV);
+    for idx in 0..expected_lines.len().max(actual_lines.len()) {
+        let expected_line = expected_lines.get(idx);
+        let actual_line = actual_lines.get(idx);
+        if expected_line != actual_line {
+            if let Some(line) = expected_line {
+                diff.push_str(&format!("{:>5} - {}\n", idx + 1, line));
+            }
+            if let Some(line) = actual_line {
+                diff.push_str(&format!("{:>5} + {}\n", idx + 1, line));
+            }
+        }
+    }
+    Err(diff)
+}
@@ -0,0 +1,28 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use tezos_encoding::describe::{describe_binary, describe_json};
+use tezos_messages::schema::{described_encodings, find_encoding};
+
+mod common;
+
+#[test]
+fn described_encodings_match_golden_files() {
+    let mut failures = vec![];
+    for (name, encoding) in described_encodings() {
+        let binary = describe_binary(&encoding);
+        let json = serde_json::to_string_pretty(&describe_json(&encoding)).unwrap() + "\n";
+
+        failures.extend(common::diff_golden(&format!("describe/{}.binary.txt", name), &binary).err());
+        failures.extend(common::diff_golden(&format!("describe/{}.json", name), &json).err());
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn can_find_described_encoding() {
+    assert!(find_encoding("p2p.peer_message").is_some());
+    assert!(find_encoding("proto_006.constants").is_some());
+    assert!(find_encoding("p2p.unknown").is_none());
+}
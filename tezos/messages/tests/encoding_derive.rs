@@ -0,0 +1,404 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Derived encodings compared with the encodings previously written by hand.
+
+use std::mem::size_of;
+use std::sync::Arc;
+
+use crypto::hash::HashType;
+use tezos_encoding::encoding::{Encoding, Field, HasEncoding, SchemaType, Tag, TagMap};
+use tezos_messages::p2p::encoding::ack::NackInfo;
+use tezos_messages::p2p::encoding::prelude::*;
+
+/// Describe encoding in a form, which can be compared. Lazy encodings are expanded up to `depth` levels.
+fn describe(encoding: &Encoding, depth: usize) -> String {
+    match encoding {
+        Encoding::Tags(tag_sz, tag_map) => {
+            let tags: Vec<String> = tag_map.tags().iter()
+                .map(|tag| format!("0x{:X} {}: {}", tag.get_id(), tag.get_variant(), describe(tag.get_encoding(), depth)))
+                .collect();
+            format!("Tags({}, [{}])", tag_sz, tags.join(", "))
+        }
+        Encoding::Obj(schema) => {
+            let fields: Vec<String> = schema.iter()
+                .map(|field| format!("{}: {}", field.get_name(), describe(field.get_encoding(), depth)))
+                .collect();
+            format!("Obj({})", fields.join(", "))
+        }
+        Encoding::Tup(encodings) => {
+            let encodings: Vec<String> = encodings.iter().map(|encoding| describe(encoding, depth)).collect();
+            format!("Tup({})", encodings.join(", "))
+        }
+        Encoding::List(inner) => format!("List({})", describe(inner, depth)),
+        Encoding::Option(inner) => format!("Option({})", describe(inner, depth)),
+        Encoding::Dynamic(inner) => format!("Dynamic({})", describe(inner, depth)),
+        Encoding::ShortDynamic(inner) => format!("ShortDynamic({})", describe(inner, depth)),
+        Encoding::Greedy(inner) => format!("Greedy({})", describe(inner, depth)),
+        Encoding::Sized(size, inner) => format!("Sized({}, {})", size, describe(inner, depth)),
+        Encoding::Split(fn_encoding) => format!(
+            "Split(json: {}, binary: {})",
+            describe(&fn_encoding(SchemaType::Json), depth),
+            describe(&fn_encoding(SchemaType::Binary), depth),
+        ),
+        Encoding::Lazy(fn_encoding) => match depth {
+            0 => "Lazy".to_string(),
+            _ => format!("Lazy({})", describe(&fn_encoding(), depth - 1)),
+        },
+        encoding => format!("{:?}", encoding),
+    }
+}
+
+fn assert_same_encoding(expected: Encoding, derived: Encoding) {
+    assert_eq!(describe(&expected, 3), describe(&derived, 3));
+}
+
+fn path_encoding() -> Encoding {
+    Encoding::Tags(
+        size_of::<u8>(),
+        TagMap::new(&[
+            Tag::new(0xF0, "Left", Encoding::Lazy(Arc::new(PathLeft::encoding))),
+            Tag::new(0x0F, "Right", Encoding::Lazy(Arc::new(PathRight::encoding))),
+            Tag::new(0x00, "Op", Encoding::Unit),
+        ])
+    )
+}
+
+#[test]
+fn can_derive_ack_encoding() {
+    assert_same_encoding(
+        Encoding::Tags(
+            size_of::<u8>(),
+            TagMap::new(&[
+                Tag::new(0x00, "Ack", Encoding::Unit),
+                Tag::new(0x01, "Nack", NackInfo::encoding()),
+                Tag::new(0xFF, "NackV0", Encoding::Unit),
+            ]),
+        ),
+        AckMessage::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("motive", Encoding::Tags(
+                size_of::<u16>(),
+                TagMap::new(&[
+                    Tag::new(0, "NoMotive", Encoding::Unit),
+                    Tag::new(1, "TooManyConnections", Encoding::Unit),
+                    Tag::new(2, "UnknownChainName", Encoding::Unit),
+                    Tag::new(3, "DeprecatedP2pVersion", Encoding::Unit),
+                    Tag::new(4, "DeprecatedDistributedDbVersion", Encoding::Unit),
+                    Tag::new(5, "AlreadyConnected", Encoding::Unit),
+                ]),
+            )),
+            Field::new("potential_peers_to_connect", Encoding::dynamic(Encoding::list(Encoding::String))),
+        ]),
+        NackInfo::encoding(),
+    );
+}
+
+#[test]
+fn can_derive_connection_encoding() {
+    // fields are encoded in the order of the wire format, not in the order of the struct fields
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("port", Encoding::Uint16),
+            Field::new("public_key", Encoding::sized(32, Encoding::Bytes)),
+            Field::new("proof_of_work_stamp", Encoding::sized(24, Encoding::Bytes)),
+            Field::new("message_nonce", Encoding::sized(24, Encoding::Bytes)),
+            Field::new("versions", Encoding::list(Version::encoding()))
+        ]),
+        ConnectionMessage::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("chain_name", Encoding::String),
+            Field::new("distributed_db_version", Encoding::Uint16),
+            Field::new("p2p_version", Encoding::Uint16)
+        ]),
+        Version::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("disable_mempool", Encoding::Bool),
+            Field::new("private_node", Encoding::Bool)
+        ]),
+        MetadataMessage::encoding(),
+    );
+}
+
+#[test]
+fn can_derive_peer_messages_encoding() {
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("id", Encoding::list(Encoding::String)),
+        ]),
+        AdvertiseMessage::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("point", Encoding::String),
+            Field::new("peer_id", Encoding::Hash(HashType::CryptoboxPublicKeyHash)),
+        ]),
+        SwapMessage::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("deactivate", Encoding::Hash(HashType::ChainId)),
+        ]),
+        DeactivateMessage::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("messages", Encoding::dynamic(Encoding::list(
+                Encoding::Tags(
+                    size_of::<u16>(),
+                    TagMap::new(&[
+                        Tag::new(0x01, "Disconnect", Encoding::Unit),
+                        Tag::new(0x02, "Bootstrap", Encoding::Unit),
+                        Tag::new(0x03, "Advertise", AdvertiseMessage::encoding()),
+                        Tag::new(0x04, "SwapRequest", SwapMessage::encoding()),
+                        Tag::new(0x05, "SwapAck", SwapMessage::encoding()),
+                        Tag::new(0x10, "GetCurrentBranch", GetCurrentBranchMessage::encoding()),
+                        Tag::new(0x11, "CurrentBranch", CurrentBranchMessage::encoding()),
+                        Tag::new(0x12, "Deactivate", DeactivateMessage::encoding()),
+                        Tag::new(0x13, "GetCurrentHead", GetCurrentHeadMessage::encoding()),
+                        Tag::new(0x14, "CurrentHead", CurrentHeadMessage::encoding()),
+                        Tag::new(0x20, "GetBlockHeaders", GetBlockHeadersMessage::encoding()),
+                        Tag::new(0x21, "BlockHeader", BlockHeaderMessage::encoding()),
+                        Tag::new(0x30, "GetOperations", GetOperationsMessage::encoding()),
+                        Tag::new(0x31, "Operation", OperationMessage::encoding()),
+                        Tag::new(0x40, "GetProtocols", GetProtocolsMessage::encoding()),
+                        Tag::new(0x41, "Protocol", ProtocolMessage::encoding()),
+                        Tag::new(0x50, "GetOperationHashesForBlocks", GetOperationHashesForBlocksMessage::encoding()),
+                        // variant was named "OperationHashesForBlocks" by hand, which did not match the variant of the PeerMessage
+                        Tag::new(0x51, "OperationHashesForBlock", OperationHashesForBlocksMessage::encoding()),
+                        Tag::new(0x60, "GetOperationsForBlocks", GetOperationsForBlocksMessage::encoding()),
+                        Tag::new(0x61, "OperationsForBlocks", OperationsForBlocksMessage::encoding()),
+                    ])
+                )
+            )))
+        ]),
+        PeerMessageResponse::encoding(),
+    );
+}
+
+#[test]
+fn can_derive_block_header_encoding() {
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("level", Encoding::Int32),
+            Field::new("proto", Encoding::Uint8),
+            Field::new("predecessor", Encoding::Hash(HashType::BlockHash)),
+            Field::new("timestamp", Encoding::Timestamp),
+            Field::new("validation_pass", Encoding::Uint8),
+            Field::new("operations_hash", Encoding::Hash(HashType::OperationListListHash)),
+            Field::new("fitness", Encoding::Split(Arc::new(|schema_type|
+                match schema_type {
+                    SchemaType::Json => Encoding::dynamic(Encoding::list(Encoding::Bytes)),
+                    SchemaType::Binary => Encoding::dynamic(Encoding::list(
+                        Encoding::dynamic(Encoding::list(Encoding::Uint8))
+                    ))
+                }
+            ))),
+            Field::new("context", Encoding::Hash(HashType::ContextHash)),
+            Field::new("protocol_data", Encoding::Split(Arc::new(|schema_type|
+                match schema_type {
+                    SchemaType::Json => Encoding::Bytes,
+                    SchemaType::Binary => Encoding::list(Encoding::Uint8)
+                }
+            )))
+        ]),
+        BlockHeader::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("block_header", BlockHeader::encoding()),
+        ]),
+        BlockHeaderMessage::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("get_block_headers", Encoding::dynamic(Encoding::list(Encoding::Hash(HashType::BlockHash)))),
+        ]),
+        GetBlockHeadersMessage::encoding(),
+    );
+}
+
+#[test]
+fn can_derive_current_branch_and_head_encoding() {
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("chain_id", Encoding::Hash(HashType::ChainId)),
+            Field::new("current_branch", CurrentBranch::encoding())
+        ]),
+        CurrentBranchMessage::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("current_head", Encoding::dynamic(BlockHeader::encoding())),
+            Field::new("history", Encoding::Split(Arc::new(|schema_type|
+                match schema_type {
+                    SchemaType::Json => Encoding::Unit,
+                    SchemaType::Binary => Encoding::list(Encoding::Hash(HashType::BlockHash))
+                }
+            )))
+        ]),
+        CurrentBranch::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("chain_id", Encoding::Hash(HashType::ChainId))
+        ]),
+        GetCurrentBranchMessage::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("chain_id", Encoding::Hash(HashType::ChainId)),
+            Field::new("current_block_header", Encoding::dynamic(BlockHeader::encoding())),
+            Field::new("current_mempool", Mempool::encoding())
+        ]),
+        CurrentHeadMessage::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("chain_id", Encoding::Hash(HashType::ChainId))
+        ]),
+        GetCurrentHeadMessage::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("known_valid", Encoding::dynamic(Encoding::list(Encoding::Hash(HashType::OperationHash)))),
+            Field::new("pending", Encoding::dynamic(Encoding::dynamic(Encoding::list(Encoding::Hash(HashType::OperationHash))))),
+        ]),
+        Mempool::encoding(),
+    );
+}
+
+#[test]
+fn can_derive_operation_encoding() {
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("branch", Encoding::Hash(HashType::BlockHash)),
+            Field::new("data", Encoding::Split(Arc::new(|schema_type|
+                match schema_type {
+                    SchemaType::Json => Encoding::Bytes,
+                    SchemaType::Binary => Encoding::list(Encoding::Uint8)
+                }
+            )))
+        ]),
+        Operation::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("operation", Operation::encoding())
+        ]),
+        OperationMessage::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("get_operations", Encoding::dynamic(Encoding::list(Encoding::Hash(HashType::OperationHash)))),
+        ]),
+        GetOperationsMessage::encoding(),
+    );
+}
+
+#[test]
+fn can_derive_path_encoding() {
+    // recursive path is expanded to the depth of 3 levels
+    assert_same_encoding(path_encoding(), Path::encoding());
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("left", Encoding::Hash(HashType::OperationListListHash)),
+            Field::new("path", path_encoding()),
+        ]),
+        PathRight::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("path", path_encoding()),
+            Field::new("right", Encoding::Hash(HashType::OperationListListHash)),
+        ]),
+        PathLeft::encoding(),
+    );
+}
+
+#[test]
+fn can_derive_operations_for_blocks_encoding() {
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("hash", Encoding::Hash(HashType::BlockHash)),
+            Field::new("validation_pass", Encoding::Int8),
+        ]),
+        OperationsForBlock::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("operations_for_block", OperationsForBlock::encoding()),
+            Field::new("operation_hashes_path", path_encoding()),
+            Field::new("operations", Encoding::list(Encoding::dynamic(Operation::encoding()))),
+        ]),
+        OperationsForBlocksMessage::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("get_operations_for_blocks", Encoding::dynamic(Encoding::list(OperationsForBlock::encoding()))),
+        ]),
+        GetOperationsForBlocksMessage::encoding(),
+    );
+}
+
+#[test]
+fn can_derive_operation_hashes_for_blocks_encoding() {
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("hash", Encoding::Hash(HashType::BlockHash)),
+            Field::new("validation_pass", Encoding::Int8),
+        ]),
+        OperationHashesForBlock::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("operation_hashes_for_block", OperationHashesForBlock::encoding()),
+            Field::new("operation_hashes_path", path_encoding()),
+            Field::new("operation_hashes", Encoding::list(Encoding::dynamic(Encoding::list(Encoding::Uint8)))),
+        ]),
+        OperationHashesForBlocksMessage::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("get_operation_hashes_for_blocks", Encoding::dynamic(Encoding::list(OperationHashesForBlock::encoding()))),
+        ]),
+        GetOperationHashesForBlocksMessage::encoding(),
+    );
+}
+
+#[test]
+fn can_derive_protocol_encoding() {
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("protocol", Protocol::encoding())
+        ]),
+        ProtocolMessage::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("name", Encoding::String),
+            Field::new("interface", Encoding::option(Encoding::String)),
+            Field::new("implementation", Encoding::String),
+        ]),
+        Component::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("expected_env_version", Encoding::Int16),
+            Field::new("components", Encoding::dynamic(Encoding::list(Component::encoding())))
+        ]),
+        Protocol::encoding(),
+    );
+    assert_same_encoding(
+        Encoding::Obj(vec![
+            Field::new("get_protocols", Encoding::dynamic(Encoding::list(Encoding::Hash(HashType::ProtocolHash)))),
+        ]),
+        GetProtocolsMessage::encoding(),
+    );
+}
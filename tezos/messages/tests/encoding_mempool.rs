@@ -11,4 +11,11 @@ fn can_serialize_mempool() -> Result<(), Error> {
     let serialized = hex::encode(message.as_bytes()?);
     let expected = "000000000000000400000000";
     Ok(assert_eq!(expected, &serialized))
-}
\ No newline at end of file
+}
+#[test]
+fn can_serialize_mempool_with_known_valid() -> Result<(), Error> {
+    let message = Mempool::new(vec![vec![1; 32]], vec![]);
+    let serialized = hex::encode(message.as_bytes()?);
+    let expected = format!("00000020{}0000000400000000", "01".repeat(32));
+    Ok(assert_eq!(expected, serialized))
+}
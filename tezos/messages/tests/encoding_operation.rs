@@ -12,4 +12,11 @@ fn can_deserialize() -> Result<(), Error> {
     let operation = Operation::from_bytes(message_bytes)?;
     assert_eq!("BKqTKfGwK3zHnVXX33X5PPHy1FDTnbkajj3eFtCXGFyfimQhT1H", HashType::BlockHash.bytes_to_string(&operation.branch()));
     Ok(assert_eq!("000008c387fa065a181d45d47a9b78ddc77e92a881779ff2cbabbf9646eade4bf1405a08e00b725ed849eea46953b10b5cdebc518e6fd47e69b82d2ca18c4cf6d2f312dd08", &hex::encode(&operation.data())))
-}
\ No newline at end of file
+}
+#[test]
+fn can_serialize_get_operations() -> Result<(), Error> {
+    let operation_hash = vec![2; HashType::OperationHash.size()];
+    let message = GetOperationsMessage::new(vec![operation_hash.clone()]);
+    let deserialized = GetOperationsMessage::from_bytes(message.as_bytes()?)?;
+    Ok(assert_eq!(&vec![operation_hash], deserialized.get_operations()))
+}
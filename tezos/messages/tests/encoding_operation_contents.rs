@@ -0,0 +1,227 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use failure::Error;
+
+use crypto::hash::HashType;
+use crypto::signature::Watermark;
+use tezos_messages::base::signature_public_key_hash::SignaturePublicKeyHash;
+use tezos_messages::p2p::binary_message::BinaryMessage;
+use tezos_messages::p2p::encoding::prelude::*;
+use tezos_messages::protocol::{proto_005_2, proto_006};
+use tezos_messages::protocol::proto_006::operation::*;
+
+/// Reveal and transaction with parameters, signed by the sandbox account bootstrap1
+const REVEAL_AND_TRANSACTION: &str = "10490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e6b0002298c03ed7d454a101eb7022bc95f7e5f41ac78f50907904e00004798d2cc98473d7e250c898885718afd2e4efbcb1a1595ab9730761ed830de0f6c0002298c03ed7d454a101eb7022bc95f7e5f41ac78d00f0899788102c0843d019c96e27f418b5db7c301147b3e941b41bd224fe400ffff087472616e736665720000000801000000036162636151cafdabd5fb476fd189ca6f04b54bca4e5c7b1d7b73473cf0de545bc12c5b7dfffcca8670705c8ac5e8d887f30e8a36b431889c977e5832c9d87cac98370f";
+const DOUBLE_BAKING_EVIDENCE: &str = "10490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e03000000ce00006d6e0102dd00defaf70c53e180ea148b349a6feb4795610b2abc7b07fe91ce50a90814000000005c1276780432bc1d3a28df9a67b363aa1638f807214bb8987e5f9c0abcbd69531facffd1c80000001100000001000000000800000000000c15ef15a6f54021cb353780e2847fb9c546f1d72c1dc17c3db510f45553ce501ce1de000000000003c762c7df00a856b8bfcaf0676f069f825ca75f37f2bee9fe55ba109cec3d1d041d8c03519626c0c0faa557e778cb09d2e0c729e8556ed6a7a518c84982d1f2682bc6aa753f000000ce00006d6e0102dd00defaf70c53e180ea148b349a6feb4795610b2abc7b07fe91ce50a90814000000005c1276780432bc1d3a28df9a67b363aa1638f807214bb8987e5f9c0abcbd69531facffd1c80000001100000001000000000800000000000c15ef15a6f54021cb353780e2847fb9c546f1d72c1dc17c3db510f45553ce501ce1de000000000003c762c7df00a856b8bfcaf0676f069f825ca75f37f2bee9fe55ba109cec3d1d041d8c03519626c0c0faa557e778cb09d2e0c729e8556ed6a7a518c84982d1f2682bc6aa753f00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000";
+
+fn decode(operation: &str) -> Result<OperationProtocolData, Error> {
+    let operation = Operation::from_bytes(hex::decode(operation)?)?;
+    Ok(OperationProtocolData::from_operation(&operation)?)
+}
+
+#[test]
+fn can_deserialize_endorsement() -> Result<(), Error> {
+    let protocol_data = decode("10490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e000008c387fa065a181d45d47a9b78ddc77e92a881779ff2cbabbf9646eade4bf1405a08e00b725ed849eea46953b10b5cdebc518e6fd47e69b82d2ca18c4cf6d2f312dd08")?;
+    assert_eq!(1, protocol_data.contents().len());
+    match &protocol_data.contents()[0] {
+        Contents::Endorsement(endorsement) => assert_eq!(574343, endorsement.level()),
+        contents => panic!("expected endorsement, but was: {:?}", contents),
+    }
+    assert_eq!("fa065a181d45d47a9b78ddc77e92a881779ff2cbabbf9646eade4bf1405a08e00b725ed849eea46953b10b5cdebc518e6fd47e69b82d2ca18c4cf6d2f312dd08", hex::encode(protocol_data.signature()));
+    Ok(())
+}
+
+#[test]
+fn can_deserialize_reveal_and_transaction() -> Result<(), Error> {
+    let operation = Operation::from_bytes(hex::decode(REVEAL_AND_TRANSACTION)?)?;
+    let protocol_data = OperationProtocolData::from_operation(&operation)?;
+    assert_eq!(2, protocol_data.contents().len());
+
+    let public_key = match &protocol_data.contents()[0] {
+        Contents::Reveal(reveal) => {
+            assert_eq!("tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx", reveal.source().to_string());
+            assert_eq!("1269", reveal.fee().0.to_string());
+            assert_eq!("7", reveal.counter().0.to_string());
+            assert_eq!("10000", reveal.gas_limit().0.to_string());
+            assert_eq!("0", reveal.storage_limit().0.to_string());
+            assert_eq!("edpkuBknW28nW72KG6RoHtYW7p12T6GKc7nAbwYX5m8Wd9sDVC9yav", reveal.public_key().to_b58());
+            reveal.public_key().clone()
+        }
+        contents => panic!("expected reveal, but was: {:?}", contents),
+    };
+    match &protocol_data.contents()[1] {
+        Contents::Transaction(transaction) => {
+            assert_eq!("tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx", transaction.source().to_string());
+            assert_eq!("2000", transaction.fee().0.to_string());
+            assert_eq!("8", transaction.counter().0.to_string());
+            assert_eq!("15385", transaction.gas_limit().0.to_string());
+            assert_eq!("257", transaction.storage_limit().0.to_string());
+            assert_eq!("1000000", transaction.amount().0.to_string());
+            assert_eq!(CONTRACT_ADDRESS_SIZE, transaction.destination().len());
+            assert_eq!("KT1NrjjM791v7cyo6VGy7rrzB3Dg3p1mQki3", HashType::ContractKt1Hash.bytes_to_string(&transaction.destination()[1..21]));
+            let parameters = transaction.parameters().as_ref().expect("expected parameters");
+            assert_eq!(&Entrypoint::Named(b"transfer".to_vec()), parameters.entrypoint());
+            assert_eq!("transfer", parameters.entrypoint().name());
+            assert_eq!("0100000003616263", hex::encode(parameters.value()));
+        }
+        contents => panic!("expected transaction, but was: {:?}", contents),
+    }
+
+    // decoded contents are enough to check the signature
+    let data = operation.data();
+    let mut signed = operation.branch().clone();
+    signed.extend_from_slice(&data[..data.len() - SIGNATURE_SIZE]);
+    assert!(public_key.verify(Some(&Watermark::GenericOperation), &signed, protocol_data.signature())?);
+    assert_eq!(SignaturePublicKeyHash::from(&public_key).to_string(), "tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx");
+
+    Ok(())
+}
+
+#[test]
+fn can_deserialize_delegation_origination_and_transaction() -> Result<(), Error> {
+    let protocol_data = decode("10490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e6e014389ced6a4dcd006af220de35f248ba350b26fcde90901904e00ff0002298c03ed7d454a101eb7022bc95f7e5f41ac786d014389ced6a4dcd006af220de35f248ba350b26fcddc0b02e05dac02c096b102000000000f020000000a0500036c0501036c050200000002030b6c014389ced6a4dcd006af220de35f248ba350b26fcd8c0b03c35000ac02000002298c03ed7d454a101eb7022bc95f7e5f41ac7800000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f")?;
+    assert_eq!(3, protocol_data.contents().len());
+
+    match &protocol_data.contents()[0] {
+        Contents::Delegation(delegation) => {
+            assert_eq!("tz2EUM2Fh6w3PiMJ1iEqvWKyksT9Hjd8NQcs", delegation.source().to_string());
+            assert_eq!("1257", delegation.fee().0.to_string());
+            assert_eq!(Some("tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx".to_string()), delegation.delegate().as_ref().map(|delegate| delegate.to_string()));
+        }
+        contents => panic!("expected delegation, but was: {:?}", contents),
+    }
+    match &protocol_data.contents()[1] {
+        Contents::Origination(origination) => {
+            assert_eq!("5000000", origination.balance().0.to_string());
+            assert_eq!("300", origination.storage_limit().0.to_string());
+            assert!(origination.delegate().is_none());
+            assert_eq!("020000000a0500036c0501036c0502", hex::encode(origination.script().code()));
+            assert_eq!("030b", hex::encode(origination.script().storage()));
+        }
+        contents => panic!("expected origination, but was: {:?}", contents),
+    }
+    match &protocol_data.contents()[2] {
+        Contents::Transaction(transaction) => {
+            assert_eq!("300", transaction.amount().0.to_string());
+            assert_eq!("000002298c03ed7d454a101eb7022bc95f7e5f41ac78", hex::encode(transaction.destination()));
+            assert!(transaction.parameters().is_none());
+        }
+        contents => panic!("expected transaction, but was: {:?}", contents),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn can_deserialize_proposals_and_ballot() -> Result<(), Error> {
+    let protocol_data = decode("10490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e050260adea28e9ae58909a5d1a4b1f51dc7f8d7731310000000a000000403e5e3a606afab74a59ca09e333633e2770b6492c5e594455b71e9a2f0ea92afb3d0b4bacb5c3e152a167da26fefc266bd3a0e14fc4e41e6c53623bf482833da200000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000")?;
+    match &protocol_data.contents()[0] {
+        Contents::Proposals(proposals) => {
+            assert_eq!("tz3V9Ept7k2qpwUz5DJm7WEi2PWfdcrZN1zb", proposals.source().to_string());
+            assert_eq!(10, proposals.period());
+            let proposals: Vec<String> = proposals.proposals().iter().map(|proposal| HashType::ProtocolHash.bytes_to_string(proposal)).collect();
+            assert_eq!(vec![proto_006::PROTOCOL_HASH.to_string(), proto_005_2::PROTOCOL_HASH.to_string()], proposals);
+        }
+        contents => panic!("expected proposals, but was: {:?}", contents),
+    }
+
+    let protocol_data = decode("10490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e060260adea28e9ae58909a5d1a4b1f51dc7f8d7731310000000b3e5e3a606afab74a59ca09e333633e2770b6492c5e594455b71e9a2f0ea92afb0200000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000")?;
+    match &protocol_data.contents()[0] {
+        Contents::Ballot(ballot) => {
+            assert_eq!(11, ballot.period());
+            assert_eq!(proto_006::PROTOCOL_HASH, HashType::ProtocolHash.bytes_to_string(ballot.proposal()));
+            assert_eq!(&Ballot::Pass, ballot.ballot());
+        }
+        contents => panic!("expected ballot, but was: {:?}", contents),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn can_deserialize_seed_nonce_revelation_and_activation() -> Result<(), Error> {
+    let protocol_data = decode("10490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e010000040007070707070707070707070707070707070707070707070707070707070707070402298c03ed7d454a101eb7022bc95f7e5f41ac7841f98b15efc63fa893d61d7d6eee4a2ce9427ac400000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000")?;
+    assert_eq!(2, protocol_data.contents().len());
+    match &protocol_data.contents()[0] {
+        Contents::SeedNonceRevelation(revelation) => {
+            assert_eq!(1024, revelation.level());
+            assert_eq!(&vec![7; 32], revelation.nonce());
+        }
+        contents => panic!("expected seed nonce revelation, but was: {:?}", contents),
+    }
+    match &protocol_data.contents()[1] {
+        Contents::ActivateAccount(activation) => {
+            assert_eq!("tz1KqTpEZ7Yob7QbPE4Hy4Wo8fHG8LhKxZSx", HashType::ContractTz1Hash.bytes_to_string(activation.pkh()));
+            assert_eq!("41f98b15efc63fa893d61d7d6eee4a2ce9427ac4", hex::encode(activation.secret()));
+        }
+        contents => panic!("expected activation, but was: {:?}", contents),
+    }
+    Ok(())
+}
+
+#[test]
+fn can_deserialize_double_endorsement_evidence() -> Result<(), Error> {
+    let protocol_data = decode("10490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e020000006510490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e000008c387010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010101010000006510490b79070cf19175cd7e3b9c1ee66f6e85799980404b119132ea7e58a4a97e000008c3870202020202020202020202020202020202020202020202020202020202020202020202020202020202020202020202020202020202020202020202020202020200000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000")?;
+    match &protocol_data.contents()[0] {
+        Contents::DoubleEndorsementEvidence(evidence) => {
+            for (op, signature) in &[(evidence.op1(), vec![1; 64]), (evidence.op2(), vec![2; 64])] {
+                assert_eq!("BKqTKfGwK3zHnVXX33X5PPHy1FDTnbkajj3eFtCXGFyfimQhT1H", HashType::BlockHash.bytes_to_string(op.branch()));
+                match op.operations() {
+                    InlinedEndorsementContents::Endorsement(endorsement) => assert_eq!(574343, endorsement.level()),
+                }
+                assert_eq!(signature, op.signature());
+            }
+        }
+        contents => panic!("expected double endorsement evidence, but was: {:?}", contents),
+    }
+    Ok(())
+}
+
+#[test]
+fn can_deserialize_double_baking_evidence() -> Result<(), Error> {
+    let protocol_data = decode(DOUBLE_BAKING_EVIDENCE)?;
+    match &protocol_data.contents()[0] {
+        Contents::DoubleBakingEvidence(evidence) => {
+            assert_eq!(28014, evidence.bh1().level());
+            assert_eq!(evidence.bh1().as_bytes()?, evidence.bh2().as_bytes()?);
+        }
+        contents => panic!("expected double baking evidence, but was: {:?}", contents),
+    }
+    Ok(())
+}
+
+#[test]
+fn can_deserialize_operation_contents_005_2() -> Result<(), Error> {
+    use proto_005_2::operation::{Contents, OperationProtocolData};
+
+    let operation = Operation::from_bytes(hex::decode(REVEAL_AND_TRANSACTION)?)?;
+    let protocol_data = OperationProtocolData::from_operation(&operation)?;
+    match protocol_data.contents().as_slice() {
+        [Contents::Reveal(_), Contents::Transaction(transaction)] => assert_eq!("1000000", transaction.amount().0.to_string()),
+        contents => panic!("expected reveal and transaction, but was: {:?}", contents),
+    }
+
+    let operation = Operation::from_bytes(hex::decode(DOUBLE_BAKING_EVIDENCE)?)?;
+    let protocol_data = OperationProtocolData::from_operation(&operation)?;
+    match protocol_data.contents().as_slice() {
+        [Contents::DoubleBakingEvidence(evidence)] => assert_eq!(28014, evidence.bh2().level()),
+        contents => panic!("expected double baking evidence, but was: {:?}", contents),
+    }
+    Ok(())
+}
+
+#[test]
+fn can_not_deserialize_invalid_operation_contents() -> Result<(), Error> {
+    // data shorter than the signature
+    assert!(OperationProtocolData::from_bytes(&[0; 10]).is_err());
+    // unknown tag
+    let mut data = vec![0x0f, 0, 0, 0, 1];
+    data.extend_from_slice(&[0; SIGNATURE_SIZE]);
+    assert!(OperationProtocolData::from_bytes(&data).is_err());
+    // truncated contents
+    let mut data = vec![0x00, 0, 0];
+    data.extend_from_slice(&[0; SIGNATURE_SIZE]);
+    assert!(OperationProtocolData::from_bytes(&data).is_err());
+    Ok(())
+}
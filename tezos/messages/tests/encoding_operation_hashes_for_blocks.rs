@@ -1 +1,30 @@
-// TODO: Ask Brano for specific test scenarios and data in march.
\ No newline at end of file
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+// TODO: Ask Brano for specific test scenarios and data in march.
+
+use tezos_messages::p2p::binary_message::BinaryMessage;
+use tezos_messages::p2p::encoding::prelude::*;
+
+#[test]
+fn deserialized_equals_serialized_message() {
+    let block_hash = hex::decode("46a6aefde9243ae18b191a8d010b7237d5130b3530ce5d1f60457411b2fa632d").unwrap();
+    let operation_hash = hex::decode("9aeb8e663111c3e5d3406bbf263a2d5869475ea8552bf16b28ef26a3ffac590a").unwrap();
+    let original_message = PeerMessage::OperationHashesForBlock(OperationHashesForBlocksMessage::new(
+        OperationHashesForBlock::new(block_hash.clone(), 3),
+        Path::Op,
+        vec![operation_hash.clone()],
+    ));
+    let resp: PeerMessageResponse = original_message.into();
+    let msg_bytes = resp.as_bytes().unwrap();
+    let deserialized = PeerMessageResponse::from_bytes(msg_bytes).expect("expected valid message");
+
+    match deserialized.messages().get(0).expect("expected message in response") {
+        PeerMessage::OperationHashesForBlock(message) => {
+            assert_eq!(&block_hash, message.operation_hashes_for_block().hash());
+            assert_eq!(3, message.operation_hashes_for_block().validation_pass());
+            assert_eq!(&vec![operation_hash], message.operation_hashes());
+        }
+        message => panic!("expected operation hashes for block message, but got: {:?}", message)
+    }
+}
\ No newline at end of file
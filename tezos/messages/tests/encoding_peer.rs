@@ -2,7 +2,9 @@
 // SPDX-License-Identifier: MIT
 
 use failure::Error;
-use tezos_messages::p2p::binary_message::BinaryMessage;
+use tezos_encoding::binary_reader::{BinaryReaderError, StreamReader};
+use tezos_encoding::encoding::HasEncoding;
+use tezos_messages::p2p::binary_message::{BinaryMessage, HasSizeLimit};
 use tezos_messages::p2p::encoding::prelude::*;
 
 #[test]
@@ -26,3 +28,37 @@ fn can_serialize_bootstrap() -> Result<(), Error> {
     Ok(assert_eq!(expected, &serialized))
 }
 
+
+#[test]
+fn can_read_peer_message_received_in_chunks() -> Result<(), Error> {
+    let message_bytes = hex::decode("0000000600108eceda2f")?;
+    let mut reader = StreamReader::new(PeerMessageResponse::encoding(), PeerMessageResponse::size_limit());
+
+    let mut chunks = message_bytes.chunks(3).peekable();
+    let mut received = None;
+    while let Some(chunk) = chunks.next() {
+        received = reader.feed(chunk)?;
+        assert_eq!(chunks.peek().is_none(), received.is_some());
+    }
+
+    let messages = PeerMessageResponse::from_bytes(received.unwrap())?;
+    match messages.messages().get(0).unwrap() {
+        PeerMessage::GetCurrentBranch(message) => Ok(assert_eq!(hex::decode("8eceda2f")?, message.chain_id)),
+        message => panic!("Unsupported encoding: {:?}", message)
+    }
+}
+
+#[test]
+fn can_reject_peer_message_exceeding_size_limit() -> Result<(), Error> {
+    // GetCurrentHead with declared size of 100 000 bytes
+    let first_chunk = hex::decode("000186a000138eceda2f")?;
+    let mut reader = StreamReader::new(PeerMessageResponse::encoding(), PeerMessageResponse::size_limit());
+
+    match reader.feed(&first_chunk) {
+        Err(BinaryReaderError::MessageTooLarge { size, tag, .. }) => {
+            assert_eq!(100_004, size);
+            Ok(assert_eq!(Some(0x13), tag))
+        }
+        result => panic!("Expected message to be rejected, but got: {:?}", result)
+    }
+}
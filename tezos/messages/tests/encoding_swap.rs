@@ -0,0 +1,33 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+use std::net::SocketAddr;
+
+use failure::Error;
+
+use tezos_messages::p2p::binary_message::BinaryMessage;
+use tezos_messages::p2p::encoding::prelude::*;
+
+#[test]
+fn can_serialize_swap_request_message() -> Result<(), Error> {
+    let point: SocketAddr = "192.168.1.10:9732".parse()?;
+    let swap_message = PeerMessage::SwapRequest(SwapMessage::new(&point, hex::decode("b6930ab826e51e601901725838b76721")?));
+    let resp: PeerMessageResponse = swap_message.into();
+    let msg_bytes = resp.as_bytes()?;
+    let expected = hex::decode("000000270004000000113139322e3136382e312e31303a39373332b6930ab826e51e601901725838b76721")?;
+    Ok(assert_eq!(expected, msg_bytes))
+}
+
+#[test]
+fn can_deserialize_swap_ack_message() -> Result<(), Error> {
+    let msg_bytes = hex::decode("000000270005000000113139322e3136382e312e31303a39373332b6930ab826e51e601901725838b76721")?;
+    let resp = PeerMessageResponse::from_bytes(msg_bytes)?;
+    match resp.messages().get(0) {
+        Some(PeerMessage::SwapAck(message)) => {
+            assert_eq!("192.168.1.10:9732", message.point());
+            assert_eq!(&hex::decode("b6930ab826e51e601901725838b76721")?, message.peer_id());
+        }
+        message => panic!("expected swap ack message, but was: {:?}", message),
+    }
+    Ok(())
+}
@@ -0,0 +1,364 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! This crate provides derive macros for the `HasEncoding` and `CachedData` traits of the tezos messages.
+//!
+//! Encoding of the struct is an `Encoding::Obj` with the fields in the order of declaration. Fields of
+//! the `BinaryDataCache` type hold the cached binary form of the message and are not part of the encoding.
+//! Encoding of the field is inferred from its type (primitive types or `HasEncoding` implementation)
+//! and can be adjusted by the `#[encoding(...)]` attribute. Attribute lists wrappers from the outermost one,
+//! optionally followed by the encoding of the innermost value:
+//!
+//! ```ignore
+//! #[derive(HasEncoding, CachedData)]
+//! pub struct GetBlockHeadersMessage {
+//!     #[encoding(dynamic, list, hash = "BlockHash")]
+//!     get_block_headers: Vec<BlockHash>,
+//!     body: BinaryDataCache,
+//! }
+//! ```
+//!
+//! - wrappers: `dynamic`, `list`, `option`, `sized = <bytes>`
+//! - value: `hash = "<HashType variant>"`, primitive encoding (`uint8`, `int32`, `timestamp`, `bytes`, ...),
+//!   `lazy` for recursive types or `custom = "<fn returning Encoding>"`
+//!
+//! Encoding of the enum is an `Encoding::Tags` with the tag size set by the `#[encoding(tags = "u8")]` attribute
+//! of the enum and the tag id set by the `#[encoding(tag = 0x01)]` attribute of each variant. Unit variants are
+//! encoded as `Encoding::Unit`, the single value of the tuple variant is encoded as a field described above
+//! (e.g. `#[encoding(tag = 0xF0, lazy)]`).
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{Attribute, Data, DeriveInput, Error, Fields, GenericArgument, Ident, Lit, LitInt, Meta, NestedMeta, parse_macro_input, Path, PathArguments, Result, Type, Variant};
+use syn::spanned::Spanned;
+
+const ATTRIBUTE: &str = "encoding";
+const CACHE_TYPE: &str = "BinaryDataCache";
+
+/// Derive `tezos_encoding::encoding::HasEncoding` for the struct or the enum.
+#[proc_macro_derive(HasEncoding, attributes(encoding))]
+pub fn derive_has_encoding(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    has_encoding(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// Derive `CachedData` backed by the `BinaryDataCache` field of the struct. Types without such field never cache data.
+#[proc_macro_derive(CachedData)]
+pub fn derive_cached_data(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    cached_data(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn has_encoding(input: &DeriveInput) -> Result<TokenStream2> {
+    let encoding = match &input.data {
+        Data::Struct(data) => obj_encoding(&data.fields)?,
+        Data::Enum(data) => {
+            let tag_type = tag_type(&input.attrs, input)?;
+            let mut tags = vec![];
+            for variant in &data.variants {
+                let mut items = encoding_items(&variant.attrs)?;
+                let tag_id = take_tag_id(&mut items, variant)?;
+                let variant_name = variant.ident.to_string();
+                let tag_encoding = match &variant.fields {
+                    Fields::Unit if items.is_empty() => quote!(::tezos_encoding::encoding::Encoding::Unit),
+                    Fields::Unnamed(fields) if fields.unnamed.len() == 1 => field_encoding(&items, &fields.unnamed[0].ty)?,
+                    Fields::Unit => return Err(Error::new(items[0].span(), "unit variant cannot have an encoding")),
+                    _ => return Err(Error::new(variant.span(), "only unit variants and variants with a single value are supported")),
+                };
+                tags.push(quote!(::tezos_encoding::encoding::Tag::new(#tag_id, #variant_name, #tag_encoding)));
+            }
+            quote! {
+                ::tezos_encoding::encoding::Encoding::Tags(
+                    ::std::mem::size_of::<#tag_type>(),
+                    ::tezos_encoding::encoding::TagMap::new(&[#(#tags),*]),
+                )
+            }
+        }
+        Data::Union(_) => return Err(Error::new(input.span(), "unions are not supported")),
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::tezos_encoding::encoding::HasEncoding for #name #ty_generics #where_clause {
+            fn encoding() -> ::tezos_encoding::encoding::Encoding {
+                #encoding
+            }
+        }
+    })
+}
+
+fn obj_encoding(fields: &Fields) -> Result<TokenStream2> {
+    let fields = match fields {
+        Fields::Named(fields) => &fields.named,
+        _ => return Err(Error::new(fields.span(), "only structs with named fields are supported")),
+    };
+
+    let mut obj_fields = vec![];
+    for field in fields.iter().filter(|field| !is_cache(&field.ty)) {
+        let field_name = field.ident.as_ref().map(Ident::to_string);
+        let encoding = field_encoding(&encoding_items(&field.attrs)?, &field.ty)?;
+        obj_fields.push(quote!(::tezos_encoding::encoding::Field::new(#field_name, #encoding)));
+    }
+    Ok(quote!(::tezos_encoding::encoding::Encoding::Obj(vec![#(#obj_fields),*])))
+}
+
+/// Wrappers of the value encoding listed by the attribute.
+enum Wrapper {
+    Dynamic,
+    List,
+    Option,
+    Sized(LitInt),
+}
+
+/// Build encoding of the value of type `ty` described by the attribute `items`.
+fn field_encoding(items: &[NestedMeta], ty: &Type) -> Result<TokenStream2> {
+    let mut wrappers = vec![];
+    let mut value = None;
+
+    for item in items {
+        if value.is_some() {
+            return Err(Error::new(item.span(), "encoding of the value has to be the last item"));
+        }
+        match item {
+            NestedMeta::Meta(Meta::Path(path)) => {
+                let word = path_word(path)?;
+                match word.as_str() {
+                    "dynamic" => wrappers.push((Wrapper::Dynamic, item)),
+                    "list" => wrappers.push((Wrapper::List, item)),
+                    "option" => wrappers.push((Wrapper::Option, item)),
+                    "lazy" => {
+                        let value_ty = unboxed(&wrapped_type(ty, &wrappers)?);
+                        value = Some(quote!(::tezos_encoding::encoding::Encoding::Lazy(::std::sync::Arc::new(<#value_ty as ::tezos_encoding::encoding::HasEncoding>::encoding))));
+                    }
+                    _ => match primitive_encoding(&word) {
+                        Some(variant) => {
+                            let variant = Ident::new(variant, path.span());
+                            value = Some(quote!(::tezos_encoding::encoding::Encoding::#variant));
+                        }
+                        None => return Err(Error::new(item.span(), format!("unknown encoding `{}`", word))),
+                    }
+                }
+            }
+            NestedMeta::Meta(Meta::NameValue(name_value)) => {
+                let word = path_word(&name_value.path)?;
+                match (word.as_str(), &name_value.lit) {
+                    ("sized", Lit::Int(bytes_sz)) => wrappers.push((Wrapper::Sized(bytes_sz.clone()), item)),
+                    ("hash", Lit::Str(hash_type)) => {
+                        let hash_type = hash_type.parse::<Ident>()?;
+                        value = Some(quote!(::tezos_encoding::encoding::Encoding::Hash(::crypto::hash::HashType::#hash_type)));
+                    }
+                    ("custom", Lit::Str(encoding_fn)) => {
+                        let encoding_fn = encoding_fn.parse::<Path>()?;
+                        value = Some(quote!(#encoding_fn()));
+                    }
+                    _ => return Err(Error::new(item.span(), format!("unknown encoding `{}` or invalid value", word))),
+                }
+            }
+            _ => return Err(Error::new(item.span(), "unsupported encoding attribute")),
+        }
+    }
+
+    let mut encoding = match value {
+        Some(value) => value,
+        None => inferred_encoding(&wrapped_type(ty, &wrappers)?),
+    };
+    for (wrapper, _) in wrappers.iter().rev() {
+        encoding = match wrapper {
+            Wrapper::Dynamic => quote!(::tezos_encoding::encoding::Encoding::dynamic(#encoding)),
+            Wrapper::List => quote!(::tezos_encoding::encoding::Encoding::list(#encoding)),
+            Wrapper::Option => quote!(::tezos_encoding::encoding::Encoding::option(#encoding)),
+            Wrapper::Sized(bytes_sz) => quote!(::tezos_encoding::encoding::Encoding::sized(#bytes_sz, #encoding)),
+        };
+    }
+    Ok(encoding)
+}
+
+/// Get type of the value inside of the `list` and `option` wrappers.
+fn wrapped_type(ty: &Type, wrappers: &[(Wrapper, &NestedMeta)]) -> Result<Type> {
+    let mut ty = ty.clone();
+    for (wrapper, item) in wrappers {
+        ty = match wrapper {
+            Wrapper::List => generic_argument(&ty, "Vec", item)?,
+            Wrapper::Option => generic_argument(&ty, "Option", item)?,
+            Wrapper::Dynamic | Wrapper::Sized(_) => ty,
+        };
+    }
+    Ok(ty)
+}
+
+/// Encoding of the primitive types, other types have to implement `HasEncoding`.
+fn inferred_encoding(ty: &Type) -> TokenStream2 {
+    let ty = unboxed(ty);
+    let variant = match &ty {
+        Type::Path(type_path) if type_path.qself.is_none() && type_path.path.segments.len() == 1 => {
+            match type_path.path.segments[0].ident.to_string().as_str() {
+                "i8" => Some("Int8"),
+                "u8" => Some("Uint8"),
+                "i16" => Some("Int16"),
+                "u16" => Some("Uint16"),
+                "i32" => Some("Int32"),
+                "u32" => Some("Uint32"),
+                "i64" => Some("Int64"),
+                "f64" => Some("Float"),
+                "bool" => Some("Bool"),
+                "String" => Some("String"),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    match variant {
+        Some(variant) => {
+            let variant = Ident::new(variant, ty.span());
+            quote!(::tezos_encoding::encoding::Encoding::#variant)
+        }
+        None => quote!(<#ty as ::tezos_encoding::encoding::HasEncoding>::encoding()),
+    }
+}
+
+fn primitive_encoding(word: &str) -> Option<&'static str> {
+    match word {
+        "unit" => Some("Unit"),
+        "int8" => Some("Int8"),
+        "uint8" => Some("Uint8"),
+        "int16" => Some("Int16"),
+        "uint16" => Some("Uint16"),
+        "int31" => Some("Int31"),
+        "int32" => Some("Int32"),
+        "uint32" => Some("Uint32"),
+        "int64" => Some("Int64"),
+        "z" => Some("Z"),
+        "mutez" => Some("Mutez"),
+        "float" => Some("Float"),
+        "bool" => Some("Bool"),
+        "string" => Some("String"),
+        "bytes" => Some("Bytes"),
+        "timestamp" => Some("Timestamp"),
+        _ => None,
+    }
+}
+
+fn cached_data(input: &DeriveInput) -> Result<TokenStream2> {
+    let cache_field = match &input.data {
+        Data::Struct(data) => data.fields.iter()
+            .find(|field| is_cache(&field.ty))
+            .map(|field| field.ident.clone().ok_or_else(|| Error::new(field.span(), "cache has to be a named field")))
+            .transpose()?,
+        _ => None,
+    };
+
+    let (cache_reader, cache_writer) = match cache_field {
+        Some(field) => (quote!(&self.#field), quote!(Some(&mut self.#field))),
+        None => (quote!(&::tezos_messages::p2p::binary_message::cache::NeverCache), quote!(None)),
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::tezos_messages::p2p::binary_message::cache::CachedData for #name #ty_generics #where_clause {
+            #[inline]
+            fn cache_reader(&self) -> &dyn ::tezos_messages::p2p::binary_message::cache::CacheReader {
+                #cache_reader
+            }
+
+            #[inline]
+            fn cache_writer(&mut self) -> Option<&mut dyn ::tezos_messages::p2p::binary_message::cache::CacheWriter> {
+                #cache_writer
+            }
+        }
+    })
+}
+
+/// Collect items of all `#[encoding(...)]` attributes in order of declaration.
+fn encoding_items(attrs: &[Attribute]) -> Result<Vec<NestedMeta>> {
+    let mut items = vec![];
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident(ATTRIBUTE)) {
+        match attr.parse_meta()? {
+            Meta::List(list) => items.extend(list.nested),
+            meta => return Err(Error::new(meta.span(), "expected #[encoding(...)]")),
+        }
+    }
+    Ok(items)
+}
+
+fn tag_type(attrs: &[Attribute], input: &DeriveInput) -> Result<Ident> {
+    for item in encoding_items(attrs)? {
+        if let NestedMeta::Meta(Meta::NameValue(name_value)) = &item {
+            if name_value.path.is_ident("tags") {
+                return match &name_value.lit {
+                    Lit::Str(tag_type) if tag_type.value() == "u8" || tag_type.value() == "u16" => tag_type.parse::<Ident>(),
+                    _ => Err(Error::new(item.span(), "expected #[encoding(tags = \"u8\")] or #[encoding(tags = \"u16\")]")),
+                };
+            }
+        }
+    }
+    Err(Error::new(input.span(), "enum requires #[encoding(tags = \"u8\")] or #[encoding(tags = \"u16\")]"))
+}
+
+/// Remove `tag = <id>` from the variant attribute items and return the tag id.
+fn take_tag_id(items: &mut Vec<NestedMeta>, variant: &Variant) -> Result<LitInt> {
+    let position = items.iter().position(|item| match item {
+        NestedMeta::Meta(Meta::NameValue(name_value)) => name_value.path.is_ident("tag"),
+        _ => false,
+    });
+    match position.map(|position| items.remove(position)) {
+        Some(NestedMeta::Meta(Meta::NameValue(name_value))) => match name_value.lit {
+            Lit::Int(tag_id) => Ok(tag_id),
+            lit => Err(Error::new(lit.span(), "tag id has to be an integer")),
+        },
+        _ => Err(Error::new(variant.span(), "variant requires #[encoding(tag = <id>)]")),
+    }
+}
+
+fn path_word(path: &Path) -> Result<String> {
+    path.get_ident()
+        .map(Ident::to_string)
+        .ok_or_else(|| Error::new(path.span(), "expected identifier"))
+}
+
+fn is_cache(ty: &Type) -> bool {
+    match ty {
+        Type::Path(type_path) => type_path.path.segments.last().map_or(false, |segment| segment.ident == CACHE_TYPE),
+        _ => false,
+    }
+}
+
+/// Get `T` of the `container<T>` type.
+fn generic_argument(ty: &Type, container: &str, item: &NestedMeta) -> Result<Type> {
+    if let Type::Path(type_path) = ty {
+        if let Some(segment) = type_path.path.segments.last() {
+            if segment.ident == container {
+                if let PathArguments::AngleBracketed(arguments) = &segment.arguments {
+                    if let Some(GenericArgument::Type(argument)) = arguments.args.first() {
+                        return Ok(argument.clone());
+                    }
+                }
+            }
+        }
+    }
+    Err(Error::new(item.span(), format!("expected `{}<_>` type, otherwise encoding of the value has to be set explicitly", container)))
+}
+
+fn unboxed(ty: &Type) -> Type {
+    if let Type::Path(type_path) = ty {
+        if let Some(segment) = type_path.path.segments.last() {
+            if segment.ident == "Box" {
+                if let PathArguments::AngleBracketed(arguments) = &segment.arguments {
+                    if let Some(GenericArgument::Type(argument)) = arguments.args.first() {
+                        return argument.clone();
+                    }
+                }
+            }
+        }
+    }
+    ty.clone()
+}
@@ -3,5 +3,6 @@
 
 //! This crate provides core implementation for a protocol runner (both IPC server and client parts).
 
+pub mod pool;
 pub mod protocol;
 pub mod service;
\ No newline at end of file
@@ -0,0 +1,396 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Pool of protocol runner sub-processes.
+//!
+//! Single [`ProtocolController`] serializes every protocol call through one OCaml process.
+//! The pool spawns one writer runner, which applies blocks and whose context events are consumed by the node,
+//! and a configured number of readonly runners, which serve protocol calls in parallel (e.g. for RPC).
+//! Readonly runners never touch the context of the writer, each of them works with its own context
+//! in a separate data dir (see [`ProtocolRunnerPool::READONLY_DATA_DIR`]), which is recreated with a fresh genesis
+//! on every (re)start, and their context events are discarded.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::process::Child;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use failure::Fail;
+use getset::CopyGetters;
+use slog::{info, warn, Logger};
+
+use tezos_api::ffi::InitProtocolContextResult;
+
+use crate::service::{IpcCmdServer, IpcEvtServer, ProtocolController, ProtocolEndpointConfiguration, ProtocolRunner, ProtocolRunnerEndpoint, ProtocolServiceError};
+
+/// Errors generated by the [`ProtocolRunnerPool`].
+#[derive(Fail, Debug)]
+pub enum ProtocolRunnerPoolError {
+    /// All runners of the requested kind were checked out for the whole timeout.
+    #[fail(display = "No {:?} protocol runner was returned to the pool within {:?}", kind, timeout)]
+    CheckoutTimeout {
+        kind: ProtocolRunnerKind,
+        timeout: Duration,
+    },
+    /// Protocol runner sub-process failed to start or to initialize the protocol.
+    #[fail(display = "Failed to start {:?} protocol runner: {}", kind, reason)]
+    StartError {
+        kind: ProtocolRunnerKind,
+        reason: ProtocolServiceError,
+    },
+}
+
+impl slog::Value for ProtocolRunnerPoolError {
+    fn serialize(&self, _record: &slog::Record, key: slog::Key, serializer: &mut dyn slog::Serializer) -> slog::Result {
+        serializer.emit_arguments(key, &format_args!("{}", self))
+    }
+}
+
+/// Role of the protocol runner in the pool.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProtocolRunnerKind {
+    /// Runner applying blocks, its context events are consumed by the node.
+    Writer,
+    /// Runner for protocol calls, it works with its own throw-away context, never with the context of the writer.
+    Readonly,
+}
+
+/// Pool configuration
+#[derive(Clone, Debug, CopyGetters)]
+pub struct ProtocolRunnerPoolConfiguration {
+    /// Number of readonly runners spawned next to the writer runner
+    #[get_copy = "pub"]
+    readonly_runners: usize,
+    /// How long to wait for a runner to be returned to the pool
+    #[get_copy = "pub"]
+    checkout_timeout: Duration,
+    /// IO timeout of a single protocol call
+    #[get_copy = "pub"]
+    call_timeout: Duration,
+}
+
+impl ProtocolRunnerPoolConfiguration {
+    pub fn new(readonly_runners: usize, checkout_timeout: Duration, call_timeout: Duration) -> Self {
+        ProtocolRunnerPoolConfiguration {
+            readonly_runners,
+            checkout_timeout,
+            call_timeout,
+        }
+    }
+}
+
+/// Protocol runner sub-process together with its command channel.
+struct PooledRunner {
+    id: usize,
+    kind: ProtocolRunnerKind,
+    runner: ProtocolRunner,
+    commands: IpcCmdServer,
+    /// Own data dir of readonly runner, it is recreated on every start
+    data_dir: Option<PathBuf>,
+    process: Option<Child>,
+    controller: Option<ProtocolController>,
+    initialized: bool,
+}
+
+impl PooledRunner {
+    /// How many times to wait for the spawned sub-process to connect
+    const CONNECT_ATTEMPTS: usize = 5;
+
+    /// Runner is healthy if the sub-process is running and responds to ping.
+    fn is_healthy(&mut self) -> bool {
+        match (&mut self.process, &self.controller) {
+            (Some(process), Some(controller)) => ProtocolRunner::is_running(process) && controller.ping().is_ok(),
+            _ => false,
+        }
+    }
+
+    /// Spawn a new sub-process (terminating the previous one) and wait for it to connect.
+    ///
+    /// Readonly runners are also initialized in a fresh data dir, writer is initialized by its user, because
+    /// whether to commit genesis depends on the state of the storage.
+    fn start(&mut self, call_timeout: Duration) -> Result<(), ProtocolServiceError> {
+        self.stop();
+
+        if let Some(data_dir) = &self.data_dir {
+            // context of the previous readonly sub-process is not needed anymore
+            if data_dir.exists() {
+                fs::remove_dir_all(data_dir).map_err(|err| ProtocolServiceError::SpawnError { reason: err })?;
+            }
+            fs::create_dir_all(data_dir).map_err(|err| ProtocolServiceError::SpawnError { reason: err })?;
+        }
+
+        let mut process = self.runner.spawn()?;
+        let mut attempt = 0;
+        let controller = loop {
+            attempt += 1;
+            match self.commands.accept_with_io_timeout(call_timeout) {
+                Ok(controller) => break controller,
+                Err(err) => if attempt >= Self::CONNECT_ATTEMPTS || !ProtocolRunner::is_running(&mut process) {
+                    ProtocolRunner::terminate(process);
+                    return Err(err.into());
+                }
+            }
+        };
+
+        if self.kind == ProtocolRunnerKind::Readonly {
+            if let Err(err) = controller.init_protocol(true) {
+                drop(controller);
+                ProtocolRunner::terminate(process);
+                return Err(err);
+            }
+            self.initialized = true;
+        }
+
+        self.process = Some(process);
+        self.controller = Some(controller);
+        Ok(())
+    }
+
+    /// Gracefully shutdown the sub-process, it is killed if it does not exit in time.
+    fn stop(&mut self) {
+        // dropped controller sends shutdown command
+        self.controller.take();
+        self.initialized = false;
+        if let Some(process) = self.process.take() {
+            ProtocolRunner::terminate(process);
+        }
+    }
+}
+
+/// Idle runners of one kind
+struct RunnerQueue {
+    kind: ProtocolRunnerKind,
+    idle: Mutex<VecDeque<PooledRunner>>,
+    returned: Condvar,
+}
+
+impl RunnerQueue {
+    fn new(kind: ProtocolRunnerKind, runners: Vec<PooledRunner>) -> Self {
+        RunnerQueue {
+            kind,
+            idle: Mutex::new(runners.into_iter().collect()),
+            returned: Condvar::new(),
+        }
+    }
+
+    /// Wait until some runner is idle, returns `None` after the `timeout`.
+    fn take(&self, timeout: Duration) -> Option<PooledRunner> {
+        let deadline = Instant::now() + timeout;
+        let mut idle = self.idle.lock().unwrap();
+        loop {
+            if let Some(runner) = idle.pop_front() {
+                return Some(runner);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            idle = self.returned.wait_timeout(idle, deadline - now).unwrap().0;
+        }
+    }
+
+    fn take_all(&self) -> Vec<PooledRunner> {
+        self.idle.lock().unwrap().drain(..).collect()
+    }
+
+    fn give_back(&self, runner: PooledRunner) {
+        self.idle.lock().unwrap().push_back(runner);
+        self.returned.notify_one();
+    }
+}
+
+/// Pool of one writer and several readonly protocol runners.
+///
+/// Runners are handed out by [`writer`](ProtocolRunnerPool::writer) and [`readonly`](ProtocolRunnerPool::readonly)
+/// and are returned to the pool when the [`PooledProtocolController`] is dropped.
+/// Sub-processes are started on the first checkout (or by [`check_health`](ProtocolRunnerPool::check_health)).
+/// Every checkout verifies that the runner responds, crashed or stuck runners are restarted transparently.
+pub struct ProtocolRunnerPool {
+    configuration: ProtocolRunnerPoolConfiguration,
+    writer: RunnerQueue,
+    readonly: RunnerQueue,
+    writer_events: Mutex<Option<IpcEvtServer>>,
+    events_run: Arc<AtomicBool>,
+    events_threads: Vec<JoinHandle<()>>,
+    log: Logger,
+}
+
+impl ProtocolRunnerPool {
+    /// Directory (inside the data dir of the writer) with data dirs of readonly runners
+    pub const READONLY_DATA_DIR: &'static str = "readonly_runners";
+
+    /// Create pool, IPC endpoints of all runners are bound, but no sub-process is spawned yet.
+    pub fn new(configuration: ProtocolRunnerPoolConfiguration, endpoint_configuration: ProtocolEndpointConfiguration, log: Logger) -> Self {
+        let events_run = Arc::new(AtomicBool::new(true));
+        let mut events_threads = Vec::with_capacity(configuration.readonly_runners);
+
+        let ProtocolRunnerEndpoint { runner, commands, events } = ProtocolRunnerEndpoint::new(endpoint_configuration.clone());
+        let writer = PooledRunner { id: 0, kind: ProtocolRunnerKind::Writer, runner, commands, data_dir: None, process: None, controller: None, initialized: false };
+
+        let readonly = (1..=configuration.readonly_runners)
+            .map(|id| {
+                let data_dir = endpoint_configuration.data_dir().join(Self::READONLY_DATA_DIR).join(id.to_string());
+                let ProtocolRunnerEndpoint { runner, commands, events } = ProtocolRunnerEndpoint::new(ProtocolEndpointConfiguration::new(
+                    endpoint_configuration.runtime_configuration().clone(),
+                    endpoint_configuration.environment().clone(),
+                    endpoint_configuration.enable_testchain(),
+                    data_dir.clone(),
+                    endpoint_configuration.executable_path().clone(),
+                ));
+                events_threads.push(discard_events(events, events_run.clone()));
+                PooledRunner { id, kind: ProtocolRunnerKind::Readonly, runner, commands, data_dir: Some(data_dir), process: None, controller: None, initialized: false }
+            })
+            .collect();
+
+        ProtocolRunnerPool {
+            configuration,
+            writer: RunnerQueue::new(ProtocolRunnerKind::Writer, vec![writer]),
+            readonly: RunnerQueue::new(ProtocolRunnerKind::Readonly, readonly),
+            writer_events: Mutex::new(Some(events)),
+            events_run,
+            events_threads,
+            log,
+        }
+    }
+
+    /// Event server of the writer runner, it is handed out only once.
+    ///
+    /// Context events of the writer are sent to it, also after the writer is restarted.
+    pub fn take_writer_events(&self) -> Option<IpcEvtServer> {
+        self.writer_events.lock().unwrap().take()
+    }
+
+    /// Checkout the writer runner, waits until it is returned by the previous user.
+    pub fn writer(&self) -> Result<PooledProtocolController<'_>, ProtocolRunnerPoolError> {
+        self.checkout(&self.writer)
+    }
+
+    /// Checkout any idle readonly runner.
+    pub fn readonly(&self) -> Result<PooledProtocolController<'_>, ProtocolRunnerPoolError> {
+        self.checkout(&self.readonly)
+    }
+
+    /// Check all idle runners and (re)start those which do not respond.
+    ///
+    /// Returns number of restarted runners.
+    pub fn check_health(&self) -> Result<usize, ProtocolRunnerPoolError> {
+        let mut restarted = 0;
+        for queue in &[&self.writer, &self.readonly] {
+            let mut result = Ok(());
+            for mut runner in queue.take_all() {
+                if result.is_ok() && !runner.is_healthy() {
+                    result = self.restart(&mut runner);
+                    if result.is_ok() {
+                        restarted += 1;
+                    }
+                }
+                queue.give_back(runner);
+            }
+            result?;
+        }
+        Ok(restarted)
+    }
+
+    fn checkout<'a>(&'a self, queue: &'a RunnerQueue) -> Result<PooledProtocolController<'a>, ProtocolRunnerPoolError> {
+        let timeout = self.configuration.checkout_timeout;
+        let mut runner = queue.take(timeout)
+            .ok_or(ProtocolRunnerPoolError::CheckoutTimeout { kind: queue.kind, timeout })?;
+
+        if !runner.is_healthy() {
+            if let Err(err) = self.restart(&mut runner) {
+                // runner is returned broken, so the next checkout will try to start it again
+                queue.give_back(runner);
+                return Err(err);
+            }
+        }
+
+        Ok(PooledProtocolController { runner: Some(runner), queue })
+    }
+
+    fn restart(&self, runner: &mut PooledRunner) -> Result<(), ProtocolRunnerPoolError> {
+        info!(self.log, "Starting protocol runner"; "id" => runner.id, "kind" => format!("{:?}", runner.kind));
+        runner.start(self.configuration.call_timeout)
+            .map_err(|reason| {
+                warn!(self.log, "Failed to start protocol runner"; "id" => runner.id, "kind" => format!("{:?}", runner.kind), "reason" => &reason);
+                ProtocolRunnerPoolError::StartError { kind: runner.kind, reason }
+            })
+    }
+}
+
+impl Drop for ProtocolRunnerPool {
+    fn drop(&mut self) {
+        // checked out runners borrow the pool, so all runners are idle now
+        self.writer.take_all().iter_mut()
+            .chain(self.readonly.take_all().iter_mut())
+            .for_each(PooledRunner::stop);
+
+        self.events_run.store(false, Ordering::Release);
+        for events_thread in self.events_threads.drain(..) {
+            let _ = events_thread.join();
+        }
+    }
+}
+
+/// Accept context events of readonly runners and throw them away.
+///
+/// Events have to be consumed, otherwise the runner blocks once the socket buffer is full.
+fn discard_events(mut events: IpcEvtServer, run: Arc<AtomicBool>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        while run.load(Ordering::Acquire) {
+            if let Ok(mut rx) = events.accept() {
+                // connection is closed when the runner exits
+                while rx.receive().is_ok() {}
+            }
+        }
+    })
+}
+
+/// Protocol runner checked out from the [`ProtocolRunnerPool`], it is returned to the pool on drop.
+pub struct PooledProtocolController<'a> {
+    runner: Option<PooledRunner>,
+    queue: &'a RunnerQueue,
+}
+
+impl PooledProtocolController<'_> {
+    pub fn kind(&self) -> ProtocolRunnerKind {
+        self.runner().kind
+    }
+
+    /// Readonly runners are always initialized, writer has to be initialized after every restart.
+    pub fn is_initialized(&self) -> bool {
+        self.runner().initialized
+    }
+
+    /// Initialize protocol environment of the runner, see [`ProtocolController::init_protocol`].
+    pub fn init_protocol(&mut self, commit_genesis: bool) -> Result<InitProtocolContextResult, ProtocolServiceError> {
+        let runner = self.runner.as_mut().unwrap();
+        let result = runner.controller.as_ref().unwrap().init_protocol(commit_genesis)?;
+        runner.initialized = true;
+        Ok(result)
+    }
+
+    fn runner(&self) -> &PooledRunner {
+        self.runner.as_ref().unwrap()
+    }
+}
+
+impl Deref for PooledProtocolController<'_> {
+    type Target = ProtocolController;
+
+    fn deref(&self) -> &Self::Target {
+        self.runner().controller.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledProtocolController<'_> {
+    fn drop(&mut self) {
+        if let Some(runner) = self.runner.take() {
+            self.queue.give_back(runner);
+        }
+    }
+}
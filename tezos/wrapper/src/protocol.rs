@@ -16,6 +16,12 @@ pub trait ProtocolApi {
         operations: &Vec<Option<OperationsForBlocksMessage>>,
         max_operations_ttl: u16) -> Result<ApplyBlockResult, ApplyBlockError>;
 
+    /// Prevalidate operation on top of the current head
+    fn validate_operation(
+        chain_id: &ChainId,
+        block_header: &BlockHeader,
+        operation: &Operation) -> Result<ValidateOperationResult, ValidateOperationError>;
+
     /// Change tezos runtime configuration
     fn change_runtime_configuration(settings: TezosRuntimeConfiguration) -> Result<(), TezosRuntimeConfigurationError>;
 
@@ -28,10 +28,12 @@ use crate::protocol::*;
 #[derive(Serialize, Deserialize, Debug, IntoStaticStr)]
 enum ProtocolMessage {
     ApplyBlockCall(ApplyBlockParams),
+    ValidateOperationCall(ValidateOperationParams),
     ChangeRuntimeConfigurationCall(TezosRuntimeConfiguration),
     InitProtocolContextCall(InitProtocolContextParams),
     GenesisResultDataCall(GenesisResultDataParams),
     GenerateIdentity(GenerateIdentityParams),
+    PingCall,
     ShutdownCall,
 }
 
@@ -44,6 +46,13 @@ struct ApplyBlockParams {
     max_operations_ttl: u16,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+struct ValidateOperationParams {
+    chain_id: ChainId,
+    block_header: BlockHeader,
+    operation: Operation,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct InitProtocolContextParams {
     storage_data_dir: String,
@@ -71,10 +80,12 @@ struct GenerateIdentityParams {
 #[derive(Serialize, Deserialize, Debug, IntoStaticStr)]
 enum NodeMessage {
     ApplyBlockResult(Result<ApplyBlockResult, ApplyBlockError>),
+    ValidateOperationResult(Result<ValidateOperationResult, ValidateOperationError>),
     ChangeRuntimeConfigurationResult(Result<(), TezosRuntimeConfigurationError>),
     InitProtocolContextResult(Result<InitProtocolContextResult, TezosStorageInitError>),
     CommitGenesisResultData(Result<CommitGenesisResult, GetDataError>),
     GenerateIdentityResult(Result<Identity, TezosGenerateIdentityError>),
+    PingResult,
     ShutdownResult,
 }
 
@@ -112,6 +123,14 @@ pub fn process_protocol_commands<Proto: ProtocolApi, P: AsRef<Path>>(socket_path
                 );
                 tx.send(&NodeMessage::ApplyBlockResult(res))?;
             }
+            ProtocolMessage::ValidateOperationCall(params) => {
+                let res = Proto::validate_operation(
+                    &params.chain_id,
+                    &params.block_header,
+                    &params.operation,
+                );
+                tx.send(&NodeMessage::ValidateOperationResult(res))?;
+            }
             ProtocolMessage::ChangeRuntimeConfigurationCall(params) => {
                 let res = Proto::change_runtime_configuration(params);
                 tx.send(&NodeMessage::ChangeRuntimeConfigurationResult(res))?;
@@ -139,6 +158,9 @@ pub fn process_protocol_commands<Proto: ProtocolApi, P: AsRef<Path>>(socket_path
                 let res = Proto::generate_identity(params.expected_pow);
                 tx.send(&NodeMessage::GenerateIdentityResult(res))?;
             }
+            ProtocolMessage::PingCall => {
+                tx.send(&NodeMessage::PingResult)?;
+            }
             ProtocolMessage::ShutdownCall => {
                 context_send(ContextAction::Shutdown).expect("Failed to send shutdown command to context channel");
                 tx.send(&NodeMessage::ShutdownResult)?;
@@ -158,6 +180,11 @@ pub enum ProtocolError {
     ApplyBlockError {
         reason: ApplyBlockError
     },
+    /// Protocol rejected to validate an operation.
+    #[fail(display = "Validate operation error: {}", reason)]
+    ValidateOperationError {
+        reason: ValidateOperationError
+    },
     /// Error in configuration.
     #[fail(display = "OCaml runtime configuration error: {}", reason)]
     TezosRuntimeConfigurationError {
@@ -274,15 +301,23 @@ impl IpcCmdServer {
     /// Returns a [`protocol controller`](ProtocolController) if new IPC channel is successfully created.
     /// This is a blocking operation.
     pub fn accept(&mut self) -> Result<ProtocolController, IpcError> {
+        self.accept_with_io_timeout(Self::IO_TIMEOUT)
+    }
+
+    /// Start accepting incoming IPC connection, calls of the returned controller will time out after `io_timeout`.
+    ///
+    /// Calls which are known to take long (block application, identity generation) keep their own timeouts.
+    pub fn accept_with_io_timeout(&mut self, io_timeout: Duration) -> Result<ProtocolController, IpcError> {
         let (rx, tx) = self.0.accept()?;
         // configure IO timeouts
-        rx.set_read_timeout(Some(Self::IO_TIMEOUT))
-            .and(tx.set_write_timeout(Some(Self::IO_TIMEOUT)))
+        rx.set_read_timeout(Some(io_timeout))
+            .and(tx.set_write_timeout(Some(io_timeout)))
             .map_err(|err| IpcError::SocketConfigurationError { reason: err })?;
 
         Ok(ProtocolController {
             io: RefCell::new(IpcIO { rx, tx }),
-            configuration: &self.1,
+            io_timeout,
+            configuration: self.1.clone(),
         })
     }
 }
@@ -335,16 +370,17 @@ struct IpcIO {
 }
 
 /// Encapsulate IPC communication.
-pub struct ProtocolController<'a> {
+pub struct ProtocolController {
     io: RefCell<IpcIO>,
-    configuration: &'a ProtocolEndpointConfiguration,
+    io_timeout: Duration,
+    configuration: ProtocolEndpointConfiguration,
 }
 
 /// Provides convenience methods for IPC communication.
 ///
 /// Instead of manually sending and receiving messages over IPC channel use provided methods.
 /// Methods also handle things such as timeouts and also checks is correct response type is received.
-impl<'a> ProtocolController<'a> {
+impl ProtocolController {
     const GENERATE_IDENTITY_TIMEOUT: Duration = Duration::from_secs(600);
     const APPLY_BLOCK_TIMEOUT: Duration = Duration::from_secs(180);
 
@@ -362,13 +398,27 @@ impl<'a> ProtocolController<'a> {
         io.rx.set_read_timeout(Some(Self::APPLY_BLOCK_TIMEOUT)).map_err(|err| IpcError::SocketConfigurationError { reason: err })?;
         let receive_result = io.rx.receive();
         // restore default timeout setting
-        io.rx.set_read_timeout(Some(IpcCmdServer::IO_TIMEOUT)).map_err(|err| IpcError::SocketConfigurationError { reason: err })?;
+        io.rx.set_read_timeout(Some(self.io_timeout)).map_err(|err| IpcError::SocketConfigurationError { reason: err })?;
         match receive_result? {
             NodeMessage::ApplyBlockResult(result) => result.map_err(|err| ProtocolError::ApplyBlockError { reason: err }.into()),
             message => Err(ProtocolServiceError::UnexpectedMessage { message: message.into() })
         }
     }
 
+    /// Prevalidate operation on top of the current head
+    pub fn validate_operation(&self, chain_id: &Vec<u8>, block_header: &BlockHeader, operation: &Operation) -> Result<ValidateOperationResult, ProtocolServiceError> {
+        let mut io = self.io.borrow_mut();
+        io.tx.send(&ProtocolMessage::ValidateOperationCall(ValidateOperationParams {
+            chain_id: chain_id.clone(),
+            block_header: block_header.clone(),
+            operation: operation.clone(),
+        }))?;
+        match io.rx.receive()? {
+            NodeMessage::ValidateOperationResult(result) => result.map_err(|err| ProtocolError::ValidateOperationError { reason: err }.into()),
+            message => Err(ProtocolServiceError::UnexpectedMessage { message: message.into() })
+        }
+    }
+
     /// Change tezos runtime configuration
     pub fn change_runtime_configuration(&self, settings: TezosRuntimeConfiguration) -> Result<(), ProtocolServiceError> {
         let mut io = self.io.borrow_mut();
@@ -407,13 +457,23 @@ impl<'a> ProtocolController<'a> {
         io.rx.set_read_timeout(Some(Self::GENERATE_IDENTITY_TIMEOUT)).map_err(|err| IpcError::SocketConfigurationError { reason: err })?;
         let receive_result = io.rx.receive();
         // restore default timeout setting
-        io.rx.set_read_timeout(Some(IpcCmdServer::IO_TIMEOUT)).map_err(|err| IpcError::SocketConfigurationError { reason: err })?;
+        io.rx.set_read_timeout(Some(self.io_timeout)).map_err(|err| IpcError::SocketConfigurationError { reason: err })?;
         match receive_result? {
             NodeMessage::GenerateIdentityResult(result) => result.map_err(|err| ProtocolError::TezosGenerateIdentityError { reason: err }.into()),
             message => Err(ProtocolServiceError::UnexpectedMessage { message: message.into() })
         }
     }
 
+    /// Check that protocol runner is responding, the call does not reach OCaml runtime
+    pub fn ping(&self) -> Result<(), ProtocolServiceError> {
+        let mut io = self.io.borrow_mut();
+        io.tx.send(&ProtocolMessage::PingCall)?;
+        match io.rx.receive()? {
+            NodeMessage::PingResult => Ok(()),
+            message => Err(ProtocolServiceError::UnexpectedMessage { message: message.into() }),
+        }
+    }
+
     /// Gracefully shutdown protocol runner
     pub fn shutdown(&self) -> Result<(), ProtocolServiceError> {
         let mut io = self.io.borrow_mut();
@@ -455,7 +515,7 @@ impl<'a> ProtocolController<'a> {
     }
 }
 
-impl Drop for ProtocolController<'_> {
+impl Drop for ProtocolController {
     fn drop(&mut self) {
         // try to gracefully shutdown protocol runner
         let _ = self.shutdown();
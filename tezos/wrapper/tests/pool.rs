@@ -0,0 +1,289 @@
+// Copyright (c) SimpleStaking and Tezedge Contributors
+// SPDX-License-Identifier: MIT
+
+//! Tests of [`ProtocolRunnerPool`] with a fake protocol runner.
+//!
+//! The test binary is the fake protocol runner itself: the pool spawns it with `--sock-cmd` and `--sock-evt` arguments,
+//! which the test harness does not understand, so the binary is built without the harness and `main` decides what to run.
+
+use std::{env, fs, process, thread};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crypto::hash::{ChainId, ContextHash, ProtocolHash};
+use tezos_api::environment::{TEZOS_ENV, TezosEnvironment};
+use tezos_api::ffi::*;
+use tezos_api::identity::Identity;
+use tezos_messages::p2p::encoding::prelude::*;
+use tezos_wrapper::pool::{ProtocolRunnerKind, ProtocolRunnerPool, ProtocolRunnerPoolConfiguration, ProtocolRunnerPoolError};
+use tezos_wrapper::protocol::ProtocolApi;
+use tezos_wrapper::service::{self, ProtocolEndpointConfiguration};
+
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(2);
+const CALL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Name of the file created by the fake runner in its data dir, when the protocol context is initialized
+const CONTEXT_FILE: &str = "context";
+
+/// Values of `no_of_ffi_calls_treshold_for_gc` which make the fake runner misbehave
+const GC_TRESHOLD_STUCK: i32 = -1;
+const GC_TRESHOLD_CRASH: i32 = -2;
+
+/// Fake protocol, which does not call ocaml
+struct FakeProtocol;
+
+impl ProtocolApi for FakeProtocol {
+    fn apply_block(_: &ChainId, _: &BlockHeader, _: &BlockHeader, _: &Vec<Option<OperationsForBlocksMessage>>, _: u16) -> Result<ApplyBlockResult, ApplyBlockError> {
+        unimplemented!()
+    }
+
+    fn validate_operation(_: &ChainId, _: &BlockHeader, _: &Operation) -> Result<ValidateOperationResult, ValidateOperationError> {
+        unimplemented!()
+    }
+
+    fn change_runtime_configuration(settings: TezosRuntimeConfiguration) -> Result<(), TezosRuntimeConfigurationError> {
+        match settings.no_of_ffi_calls_treshold_for_gc {
+            GC_TRESHOLD_STUCK => thread::sleep(CALL_TIMEOUT * 3),
+            GC_TRESHOLD_CRASH => process::exit(1),
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn init_protocol_context(storage_data_dir: String, _: GenesisChain, _: ProtocolOverrides, commit_genesis: bool, _: bool) -> Result<InitProtocolContextResult, TezosStorageInitError> {
+        fs::write(Path::new(&storage_data_dir).join(CONTEXT_FILE), &[]).expect("Failed to create context");
+        Ok(InitProtocolContextResult {
+            supported_protocol_hashes: vec![],
+            genesis_commit_hash: if commit_genesis { Some(vec![1; 32]) } else { None },
+        })
+    }
+
+    fn genesis_result_data(_: &ContextHash, _: &ChainId, _: &ProtocolHash, _: u16) -> Result<CommitGenesisResult, GetDataError> {
+        unimplemented!()
+    }
+
+    fn generate_identity(_: f64) -> Result<Identity, TezosGenerateIdentityError> {
+        unimplemented!()
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    match args.iter().position(|arg| arg == "--sock-cmd") {
+        Some(idx) => run_fake_runner(&args[idx + 1], &args[idx + 3]),
+        None => run_tests(),
+    }
+}
+
+/// Serve commands like the real `protocol_runner` does
+fn run_fake_runner(sock_cmd_path: &str, sock_evt_path: &str) {
+    tezos_context::channel::enable_context_channel();
+    let sock_evt_path = sock_evt_path.to_string();
+    let events_thread = thread::spawn(move || service::process_protocol_events(&sock_evt_path));
+    let _ = service::process_protocol_commands::<FakeProtocol, _>(sock_cmd_path);
+    let _ = events_thread.join();
+}
+
+fn run_tests() {
+    let tests: Vec<(&str, fn())> = vec![
+        ("test_checkout_and_return", test_checkout_and_return),
+        ("test_checkout_timeout", test_checkout_timeout),
+        ("test_health_check", test_health_check),
+        ("test_restart_crashed_runner", test_restart_crashed_runner),
+        ("test_restart_stuck_runner", test_restart_stuck_runner),
+        ("test_writer_is_initialized_after_restart", test_writer_is_initialized_after_restart),
+        ("test_readonly_runners_have_own_data_dir", test_readonly_runners_have_own_data_dir),
+        ("test_parallel_checkouts", test_parallel_checkouts),
+    ];
+    println!("running {} tests", tests.len());
+    for (name, test) in tests {
+        test();
+        println!("test {} ... ok", name);
+    }
+}
+
+fn runtime_configuration(no_of_ffi_calls_treshold_for_gc: i32) -> TezosRuntimeConfiguration {
+    TezosRuntimeConfiguration {
+        log_enabled: false,
+        no_of_ffi_calls_treshold_for_gc,
+    }
+}
+
+fn create_data_dir(name: &str) -> PathBuf {
+    let data_dir = env::temp_dir().join(format!("tezedge_pool_{}_{}", name, process::id()));
+    if data_dir.exists() {
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+    fs::create_dir_all(&data_dir).unwrap();
+    data_dir
+}
+
+fn create_pool(readonly_runners: usize, data_dir: &Path) -> ProtocolRunnerPool {
+    let log = slog::Logger::root(slog::Discard, slog::o!());
+    ProtocolRunnerPool::new(
+        ProtocolRunnerPoolConfiguration::new(readonly_runners, CHECKOUT_TIMEOUT, CALL_TIMEOUT),
+        ProtocolEndpointConfiguration::new(
+            runtime_configuration(50),
+            TEZOS_ENV.get(&TezosEnvironment::Alphanet).unwrap().clone(),
+            false,
+            data_dir.to_path_buf(),
+            env::current_exe().unwrap(),
+        ),
+        log,
+    )
+}
+
+fn test_checkout_and_return() {
+    let data_dir = create_data_dir("checkout");
+    let pool = create_pool(2, &data_dir);
+    assert!(pool.take_writer_events().is_some());
+    assert!(pool.take_writer_events().is_none());
+
+    {
+        let first = pool.readonly().unwrap();
+        let second = pool.readonly().unwrap();
+        assert_eq!(ProtocolRunnerKind::Readonly, first.kind());
+        assert!(first.is_initialized());
+        first.ping().unwrap();
+        second.ping().unwrap();
+    }
+
+    // readonly runners were returned and are running, only the writer is started
+    assert_eq!(1, pool.check_health().unwrap());
+
+    let writer = pool.writer().unwrap();
+    assert_eq!(ProtocolRunnerKind::Writer, writer.kind());
+    writer.ping().unwrap();
+}
+
+fn test_checkout_timeout() {
+    let data_dir = create_data_dir("timeout");
+    let pool = create_pool(1, &data_dir);
+
+    let _readonly = pool.readonly().unwrap();
+    let started = Instant::now();
+    match pool.readonly() {
+        Err(ProtocolRunnerPoolError::CheckoutTimeout { kind, timeout }) => {
+            assert_eq!(ProtocolRunnerKind::Readonly, kind);
+            assert_eq!(CHECKOUT_TIMEOUT, timeout);
+        }
+        Err(err) => panic!("Unexpected error: {}", err),
+        Ok(_) => panic!("Readonly runner should not be available"),
+    }
+    assert!(started.elapsed() >= CHECKOUT_TIMEOUT);
+
+    let _writer = pool.writer().unwrap();
+    assert!(pool.writer().is_err());
+}
+
+fn test_health_check() {
+    let data_dir = create_data_dir("health");
+    let pool = create_pool(2, &data_dir);
+
+    // sub-processes are started lazily
+    assert_eq!(3, pool.check_health().unwrap());
+    assert_eq!(0, pool.check_health().unwrap());
+}
+
+fn test_restart_crashed_runner() {
+    let data_dir = create_data_dir("crash");
+    let pool = create_pool(1, &data_dir);
+
+    {
+        let readonly = pool.readonly().unwrap();
+        assert!(readonly.change_runtime_configuration(runtime_configuration(GC_TRESHOLD_CRASH)).is_err());
+    }
+
+    // crashed runner is restarted on checkout
+    let readonly = pool.readonly().unwrap();
+    assert!(readonly.is_initialized());
+    readonly.ping().unwrap();
+}
+
+fn test_restart_stuck_runner() {
+    let data_dir = create_data_dir("stuck");
+    let pool = create_pool(1, &data_dir);
+
+    {
+        let readonly = pool.readonly().unwrap();
+        let started = Instant::now();
+        assert!(readonly.change_runtime_configuration(runtime_configuration(GC_TRESHOLD_STUCK)).is_err());
+        assert!(started.elapsed() < CALL_TIMEOUT * 2);
+    }
+
+    // only the stuck runner is restarted, the writer was not started yet
+    assert_eq!(2, pool.check_health().unwrap());
+    assert_eq!(0, pool.check_health().unwrap());
+    pool.readonly().unwrap().ping().unwrap();
+}
+
+fn test_writer_is_initialized_after_restart() {
+    let data_dir = create_data_dir("writer");
+    let pool = create_pool(0, &data_dir);
+
+    {
+        let mut writer = pool.writer().unwrap();
+        assert!(!writer.is_initialized());
+        assert!(writer.init_protocol(true).unwrap().genesis_commit_hash.is_some());
+        assert!(writer.is_initialized());
+        assert!(writer.change_runtime_configuration(runtime_configuration(GC_TRESHOLD_CRASH)).is_err());
+    }
+
+    // restarted writer has to be initialized again by its user
+    let writer = pool.writer().unwrap();
+    assert!(!writer.is_initialized());
+}
+
+fn test_readonly_runners_have_own_data_dir() {
+    let data_dir = create_data_dir("data_dir");
+    let pool = create_pool(2, &data_dir);
+    let readonly_data_dir = |id: usize| data_dir.join(ProtocolRunnerPool::READONLY_DATA_DIR).join(id.to_string());
+
+    assert_eq!(3, pool.check_health().unwrap());
+    assert!(readonly_data_dir(1).join(CONTEXT_FILE).exists());
+    assert!(readonly_data_dir(2).join(CONTEXT_FILE).exists());
+    // context of the writer is not touched by readonly runners
+    assert!(!data_dir.join(CONTEXT_FILE).exists());
+
+    // data dir is recreated on restart
+    fs::write(readonly_data_dir(1).join("stale"), &[]).unwrap();
+    fs::write(readonly_data_dir(2).join("stale"), &[]).unwrap();
+    {
+        let first = pool.readonly().unwrap();
+        let second = pool.readonly().unwrap();
+        assert!(first.change_runtime_configuration(runtime_configuration(GC_TRESHOLD_CRASH)).is_err());
+        assert!(second.change_runtime_configuration(runtime_configuration(GC_TRESHOLD_CRASH)).is_err());
+    }
+    assert_eq!(2, pool.check_health().unwrap());
+    for id in 1..=2 {
+        assert!(!readonly_data_dir(id).join("stale").exists());
+        assert!(readonly_data_dir(id).join(CONTEXT_FILE).exists());
+    }
+
+    let mut writer = pool.writer().unwrap();
+    writer.init_protocol(false).unwrap();
+    assert!(data_dir.join(CONTEXT_FILE).exists());
+}
+
+fn test_parallel_checkouts() {
+    let data_dir = create_data_dir("parallel");
+    let pool = Arc::new(create_pool(2, &data_dir));
+
+    let threads: Vec<_> = (0..6)
+        .map(|_| {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                for _ in 0..20 {
+                    let readonly = pool.readonly().unwrap();
+                    readonly.change_runtime_configuration(runtime_configuration(50)).unwrap();
+                }
+            })
+        })
+        .collect();
+    threads.into_iter().for_each(|t| t.join().unwrap());
+
+    // all runners were returned, readonly runners are running, only the writer is started
+    let pool = Arc::try_unwrap(pool).ok().unwrap();
+    assert_eq!(1, pool.check_health().unwrap());
+}